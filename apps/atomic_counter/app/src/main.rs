@@ -17,32 +17,17 @@
 #![feature(alloc_error_handler)]
 
 extern crate alloc;
-extern crate tcp_proto;
 extern crate tcp_runtime;
 
-use alloc::boxed::Box;
-use oak_restricted_kernel_sdk::{
-    channel::{start_blocking_server, FileDescriptorChannel},
-    entrypoint,
-    utils::{log, samplestore::StaticSampleStore},
-};
+use oak_restricted_kernel_sdk::entrypoint;
 use tcp_atomic_counter_service::actor::CounterActor;
-use tcp_proto::runtime::endpoint::EndpointServiceServer;
-use tcp_runtime::service::ApplicationService;
+use tcp_runtime::{
+    server::run_blocking_server, service::ApplicationService, typed::TypedActorAdapter,
+};
 
 #[entrypoint]
 fn run_server() -> ! {
-    // Only log warnings and errors to reduce the risk of accidentally leaking execution
-    // information through debug logs.
-    log::set_max_level(log::LevelFilter::Warn);
-
-    let mut invocation_stats = StaticSampleStore::<1000>::new().unwrap();
-    let service: ApplicationService<CounterActor> = ApplicationService::new(CounterActor::new());
-    let server = EndpointServiceServer::new(service);
-    start_blocking_server(
-        Box::<FileDescriptorChannel>::default(),
-        server,
-        &mut invocation_stats,
-    )
-    .expect("Server encountered an unrecoverable error");
+    let service: ApplicationService<TypedActorAdapter<CounterActor>> =
+        ApplicationService::new(TypedActorAdapter::new(CounterActor::new()));
+    run_blocking_server(service)
 }
@@ -24,12 +24,11 @@ use alloc::{
     string::{String, ToString},
 };
 use hashbrown::HashMap;
+use micro_rpc::{Status, StatusCode};
 use prost::{bytes::Bytes, Message};
 use slog::{debug, warn};
-use tcp_runtime::model::{
-    Actor, ActorCommand, ActorContext, ActorError, ActorEvent, ActorEventContext, CommandOutcome,
-    EventOutcome,
-};
+use tcp_runtime::model::{ActorContext, ActorError, ActorEventContext};
+use tcp_runtime::typed::{RequestOutcome, TypedActor};
 
 pub struct CounterValue {
     value: i64,
@@ -99,7 +98,12 @@ impl CounterActor {
     }
 }
 
-impl Actor for CounterActor {
+impl TypedActor for CounterActor {
+    type Request = AtomicCounterInMessage;
+    type Response = AtomicCounterOutMessage;
+    type Event = CounterRequest;
+    type Snapshot = CounterSnapshot;
+
     fn on_init(&mut self, context: Box<dyn ActorContext>) -> Result<(), ActorError> {
         self.context = Some(context);
         self.values = HashMap::new();
@@ -120,9 +124,7 @@ impl Actor for CounterActor {
         Ok(())
     }
 
-    fn on_shutdown(&mut self) {}
-
-    fn on_save_snapshot(&mut self) -> Result<Bytes, ActorError> {
+    fn on_save_snapshot(&mut self) -> Result<CounterSnapshot, ActorError> {
         debug!(self.get_context().logger(), "Saving snapshot");
 
         let mut snapshot = CounterSnapshot {
@@ -139,15 +141,12 @@ impl Actor for CounterActor {
             );
         }
 
-        Ok(snapshot.encode_to_vec().into())
+        Ok(snapshot)
     }
 
-    fn on_load_snapshot(&mut self, snapshot: Bytes) -> Result<(), ActorError> {
+    fn on_load_snapshot(&mut self, snapshot: CounterSnapshot) -> Result<(), ActorError> {
         debug!(self.get_context().logger(), "Loading snapshot");
 
-        let snapshot =
-            CounterSnapshot::decode(snapshot).map_err(|_| ActorError::SnapshotLoading)?;
-
         for (name, value) in snapshot.values {
             self.values.insert(
                 name,
@@ -161,106 +160,88 @@ impl Actor for CounterActor {
         Ok(())
     }
 
-    fn on_process_command(
+    fn on_request(
         &mut self,
-        command: Option<ActorCommand>,
-    ) -> Result<CommandOutcome, ActorError> {
-        if command.is_none() {
-            return Ok(CommandOutcome::with_none());
-        }
-        let command = command.unwrap();
-        let mut response = CounterResponse {
-            ..Default::default()
+        in_message: AtomicCounterInMessage,
+    ) -> Result<RequestOutcome<AtomicCounterOutMessage, CounterRequest>, Status> {
+        let Some(atomic_counter_in_message::Msg::CounterRequest(mut request)) = in_message.msg
+        else {
+            warn!(
+                self.get_context().logger(),
+                "Rejecting command: unknown msg"
+            );
+            return Err(Status::new_with_message(
+                StatusCode::InvalidArgument,
+                "Unknown msg",
+            ));
         };
-        let mut status = CounterStatus::Success;
-
-        match AtomicCounterInMessage::decode(command.header.clone()) {
-            Ok(in_message) => match in_message.msg {
-                Some(msg) => {
-                    match msg {
-                        atomic_counter_in_message::Msg::CounterRequest(mut request) => {
-                            debug!(
-                                self.get_context().logger(),
-                                "Processing #{} command", request.name
-                            );
 
-                            if request.op.is_none() {
-                                status = CounterStatus::InvalidOperationError;
-
-                                warn!(
-                                    self.get_context().logger(),
-                                    "Rejecting #{} command: unknown op", request.name
-                                );
-                            }
-
-                            if !self.get_context().leader() {
-                                status = CounterStatus::Rejected;
+        debug!(
+            self.get_context().logger(),
+            "Processing #{} command", request.name
+        );
 
-                                warn!(
-                                    self.get_context().logger(),
-                                    "Rejecting #{} command: not a leader", request.name
-                                );
-                            }
+        if !self.get_context().leader() {
+            warn!(
+                self.get_context().logger(),
+                "Rejecting #{} command: not a leader", request.name
+            );
+            return Err(Status::new_with_message(
+                StatusCode::Aborted,
+                "Not a leader",
+            ));
+        }
 
-                            if let CounterStatus::Success = status {
-                                // Clear out context so that it is not replicated.
-                                request.context = Bytes::new();
-                                return Ok(CommandOutcome::with_event(ActorEvent::with_proto(
-                                    command.correlation_id,
-                                    &request,
-                                )));
-                            }
-                        }
-                    }
-                }
-                None => {
-                    status = CounterStatus::InvalidOperationError;
-                }
-            },
-            Err(e) => {
-                warn!(self.get_context().logger(), "Rejecting command: {}", e);
-                status = CounterStatus::InvalidOperationError;
-            }
+        if request.op.is_none() {
+            warn!(
+                self.get_context().logger(),
+                "Rejecting #{} command: unknown op", request.name
+            );
+            return Err(Status::new_with_message(
+                StatusCode::InvalidArgument,
+                "Unknown op",
+            ));
         }
 
-        response.status = status.into();
-        Ok(CommandOutcome::with_command(ActorCommand::with_header(
-            command.correlation_id,
-            &AtomicCounterOutMessage {
-                msg: Some(atomic_counter_out_message::Msg::CounterResponse(response)),
-            },
-        )))
+        // Clear out context so that it is not replicated.
+        request.context = Bytes::new();
+        Ok(RequestOutcome::Event(request))
     }
 
-    fn on_apply_event(
+    fn on_event(
         &mut self,
         context: ActorEventContext,
-        event: ActorEvent,
-    ) -> Result<EventOutcome, ActorError> {
-        let request =
-            CounterRequest::decode(event.contents.clone()).map_err(|_| ActorError::Internal)?;
-
-        let op = request.op.unwrap();
-
-        let response = match op {
-            counter_request::Op::CompareAndSwap(ref compare_and_swap_request) => self
+        request: CounterRequest,
+    ) -> Result<AtomicCounterOutMessage, ActorError> {
+        let response = match request.op {
+            Some(counter_request::Op::CompareAndSwap(ref compare_and_swap_request)) => self
                 .apply_compare_and_swap(
                     context.index,
                     &request.name,
                     compare_and_swap_request,
                     request.payload,
                 ),
+            None => return Err(ActorError::Internal),
+        };
+
+        Ok(AtomicCounterOutMessage {
+            msg: Some(atomic_counter_out_message::Msg::CounterResponse(response)),
+        })
+    }
+
+    fn on_status(&mut self, status: Status) -> AtomicCounterOutMessage {
+        let status = match status.code {
+            StatusCode::Aborted => CounterStatus::Rejected,
+            _ => CounterStatus::InvalidOperationError,
         };
 
-        if context.owned {
-            return Ok(EventOutcome::with_command(ActorCommand::with_header(
-                event.correlation_id,
-                &AtomicCounterOutMessage {
-                    msg: Some(atomic_counter_out_message::Msg::CounterResponse(response)),
+        AtomicCounterOutMessage {
+            msg: Some(atomic_counter_out_message::Msg::CounterResponse(
+                CounterResponse {
+                    status: status.into(),
+                    op: None,
                 },
-            )));
+            )),
         }
-
-        Ok(EventOutcome::with_none())
     }
 }
@@ -17,17 +17,12 @@
 
 extern crate alloc;
 extern crate hashbrown;
+extern crate micro_rpc;
 extern crate prost;
 extern crate slog;
 extern crate tcp_proto;
 extern crate tcp_runtime;
 
-pub mod apps {
-    pub mod atomic_counter {
-        pub mod service {
-            include!(concat!(env!("OUT_DIR"), "/apps.atomic_counter.service.rs"));
-        }
-    }
-}
+pub use tcp_proto::apps;
 
 pub mod actor;
@@ -26,10 +26,13 @@ mod test {
     use tcp_atomic_counter_service::actor::CounterActor;
     use tcp_atomic_counter_service::apps::atomic_counter::service::*;
     use tcp_integration::harness::*;
-    use tcp_proto::runtime::endpoint::out_message;
+    use tcp_proto::runtime::endpoint::{
+        out_message, ActorHealth, NotReadyReason, ReplicaRole, TransferLeadershipStatus,
+    };
+    use tcp_runtime::typed::TypedActorAdapter;
 
     fn send_cas_counter_request(
-        cluster: &mut FakeCluster<CounterActor>,
+        cluster: &mut FakeCluster<TypedActorAdapter<CounterActor>>,
         node_id: u64,
         correlation_id: u64,
         counter_name: &str,
@@ -60,7 +63,7 @@ mod test {
     }
 
     fn advance_until_counter_response(
-        cluster: &mut FakeCluster<CounterActor>,
+        cluster: &mut FakeCluster<TypedActorAdapter<CounterActor>>,
         correlation_id: u64,
     ) -> CounterResponse {
         let mut counter_response_opt: Option<CounterResponse> = None;
@@ -89,7 +92,7 @@ mod test {
     }
 
     fn advance_until_cas_counter_response(
-        cluster: &mut FakeCluster<CounterActor>,
+        cluster: &mut FakeCluster<TypedActorAdapter<CounterActor>>,
         correlation_id: u64,
         counter_response_status: CounterStatus,
         old_value: i64,
@@ -130,12 +133,12 @@ mod test {
 
         let mut cluster = FakeCluster::new(config.encode_to_vec().into());
 
-        cluster.start_node(1, true, CounterActor::new());
+        cluster.start_node(1, true, TypedActorAdapter::new(CounterActor::new()));
         cluster.advance_until_elected_leader(None);
         assert!(cluster.leader_id() == 1);
 
-        cluster.start_node(2, false, CounterActor::new());
-        cluster.start_node(3, false, CounterActor::new());
+        cluster.start_node(2, false, TypedActorAdapter::new(CounterActor::new()));
+        cluster.start_node(3, false, TypedActorAdapter::new(CounterActor::new()));
 
         cluster.add_node_to_cluster(2);
 
@@ -212,4 +215,402 @@ mod test {
             counter_value_2 + 2
         ));
     }
+
+    #[test]
+    fn restart_from_persisted_state() {
+        let counter_name = "counter";
+        let counter_value: i64 = 5;
+        let config = CounterConfig {
+            initial_values: BTreeMap::from([(counter_name.to_string(), counter_value)]),
+        };
+
+        let mut cluster = FakeCluster::new(config.encode_to_vec().into());
+
+        cluster.start_node(1, true, TypedActorAdapter::new(CounterActor::new()));
+        cluster.advance_until_elected_leader(None);
+
+        cluster.start_node(2, false, TypedActorAdapter::new(CounterActor::new()));
+        cluster.start_node(3, false, TypedActorAdapter::new(CounterActor::new()));
+
+        cluster.add_node_to_cluster(2);
+        cluster.add_node_to_cluster(3);
+
+        send_cas_counter_request(
+            &mut cluster,
+            1,
+            1,
+            counter_name,
+            counter_value,
+            counter_value + 1,
+        );
+        assert!(advance_until_cas_counter_response(
+            &mut cluster,
+            1,
+            CounterStatus::Success,
+            counter_value,
+            counter_value + 1
+        ));
+
+        // Restarted follower must recover its log and acknowledge appends so that
+        // the leader can commit without the other follower.
+        cluster.restart_node(3, TypedActorAdapter::new(CounterActor::new()));
+        cluster.stop_node(2);
+
+        send_cas_counter_request(
+            &mut cluster,
+            1,
+            2,
+            counter_name,
+            counter_value + 1,
+            counter_value + 2,
+        );
+        assert!(advance_until_cas_counter_response(
+            &mut cluster,
+            2,
+            CounterStatus::Success,
+            counter_value + 1,
+            counter_value + 2
+        ));
+    }
+
+    #[test]
+    fn learner_promotion_and_demotion() {
+        let counter_name = "counter";
+        let counter_value: i64 = 7;
+        let config = CounterConfig {
+            initial_values: BTreeMap::from([(counter_name.to_string(), counter_value)]),
+        };
+
+        let mut cluster = FakeCluster::new(config.encode_to_vec().into());
+
+        cluster.start_node(1, true, TypedActorAdapter::new(CounterActor::new()));
+        cluster.advance_until_elected_leader(None);
+
+        cluster.start_node(2, false, TypedActorAdapter::new(CounterActor::new()));
+        cluster.add_learner_to_cluster(2);
+
+        // Learner doesn't vote hence the leader commits on its own.
+        send_cas_counter_request(
+            &mut cluster,
+            1,
+            1,
+            counter_name,
+            counter_value,
+            counter_value + 1,
+        );
+        assert!(advance_until_cas_counter_response(
+            &mut cluster,
+            1,
+            CounterStatus::Success,
+            counter_value,
+            counter_value + 1
+        ));
+
+        cluster.promote_learner_in_cluster(2);
+        cluster.demote_node_in_cluster(2);
+
+        // Demoted replica is no longer needed to reach the quorum.
+        cluster.stop_node(2);
+        send_cas_counter_request(
+            &mut cluster,
+            1,
+            2,
+            counter_name,
+            counter_value + 1,
+            counter_value + 2,
+        );
+        assert!(advance_until_cas_counter_response(
+            &mut cluster,
+            2,
+            CounterStatus::Success,
+            counter_value + 1,
+            counter_value + 2
+        ));
+    }
+
+    #[test]
+    fn transfer_leadership() {
+        let counter_name = "counter";
+        let counter_value: i64 = 3;
+        let config = CounterConfig {
+            initial_values: BTreeMap::from([(counter_name.to_string(), counter_value)]),
+        };
+
+        let mut cluster = FakeCluster::new(config.encode_to_vec().into());
+
+        cluster.start_node(1, true, TypedActorAdapter::new(CounterActor::new()));
+        cluster.advance_until_elected_leader(None);
+
+        cluster.start_node(2, false, TypedActorAdapter::new(CounterActor::new()));
+        cluster.start_node(3, false, TypedActorAdapter::new(CounterActor::new()));
+
+        cluster.add_node_to_cluster(2);
+        cluster.add_node_to_cluster(3);
+
+        assert_eq!(
+            cluster.transfer_leadership(2),
+            TransferLeadershipStatus::TransferStatusCompleted
+        );
+        assert_eq!(cluster.leader_id(), 2);
+
+        // Drained replica can be stopped without interrupting the cluster.
+        cluster.stop_node(1);
+        send_cas_counter_request(
+            &mut cluster,
+            2,
+            1,
+            counter_name,
+            counter_value,
+            counter_value + 1,
+        );
+        assert!(advance_until_cas_counter_response(
+            &mut cluster,
+            1,
+            CounterStatus::Success,
+            counter_value,
+            counter_value + 1
+        ));
+    }
+
+    #[test]
+    fn prepare_shutdown() {
+        let counter_name = "counter";
+        let counter_value: i64 = 3;
+        let config = CounterConfig {
+            initial_values: BTreeMap::from([(counter_name.to_string(), counter_value)]),
+        };
+
+        let mut cluster = FakeCluster::new(config.encode_to_vec().into());
+
+        cluster.start_node(1, true, TypedActorAdapter::new(CounterActor::new()));
+        cluster.advance_until_elected_leader(None);
+
+        cluster.start_node(2, false, TypedActorAdapter::new(CounterActor::new()));
+        cluster.start_node(3, false, TypedActorAdapter::new(CounterActor::new()));
+
+        cluster.add_node_to_cluster(2);
+        cluster.add_node_to_cluster(3);
+
+        send_cas_counter_request(
+            &mut cluster,
+            1,
+            1,
+            counter_name,
+            counter_value,
+            counter_value + 1,
+        );
+        assert!(advance_until_cas_counter_response(
+            &mut cluster,
+            1,
+            CounterStatus::Success,
+            counter_value,
+            counter_value + 1
+        ));
+
+        // Leader in lame-duck mode hands over leadership before acknowledging.
+        assert!(cluster.prepare_shutdown(1) > 0);
+        assert_ne!(cluster.leader_id(), 1);
+
+        let leader_id = cluster.leader_id();
+        cluster.stop_node(1);
+        send_cas_counter_request(
+            &mut cluster,
+            leader_id,
+            2,
+            counter_name,
+            counter_value + 1,
+            counter_value + 2,
+        );
+        assert!(advance_until_cas_counter_response(
+            &mut cluster,
+            2,
+            CounterStatus::Success,
+            counter_value + 1,
+            counter_value + 2
+        ));
+    }
+
+    #[test]
+    fn replication_status() {
+        let counter_name = "counter";
+        let counter_value: i64 = 5;
+        let config = CounterConfig {
+            initial_values: BTreeMap::from([(counter_name.to_string(), counter_value)]),
+        };
+
+        let mut cluster = FakeCluster::new(config.encode_to_vec().into());
+
+        cluster.start_node(1, true, TypedActorAdapter::new(CounterActor::new()));
+        cluster.advance_until_elected_leader(None);
+
+        cluster.start_node(2, false, TypedActorAdapter::new(CounterActor::new()));
+        cluster.start_node(3, false, TypedActorAdapter::new(CounterActor::new()));
+
+        cluster.add_node_to_cluster(2);
+        cluster.add_node_to_cluster(3);
+
+        send_cas_counter_request(
+            &mut cluster,
+            1,
+            1,
+            counter_name,
+            counter_value,
+            counter_value + 1,
+        );
+        assert!(advance_until_cas_counter_response(
+            &mut cluster,
+            1,
+            CounterStatus::Success,
+            counter_value,
+            counter_value + 1
+        ));
+
+        let replication_status = cluster.replication_status();
+        let mut follower_ids: Vec<u64> = replication_status
+            .followers
+            .iter()
+            .map(|follower| follower.replica_id)
+            .collect();
+        follower_ids.sort();
+        assert_eq!(follower_ids, vec![2, 3]);
+        for follower in &replication_status.followers {
+            assert!(follower.matched_index > 0);
+            assert_eq!(follower.consecutive_rejections, 0);
+            assert_eq!(follower.backoff_remaining, 0);
+        }
+    }
+
+    #[test]
+    fn replica_status() {
+        let counter_name = "counter";
+        let counter_value: i64 = 7;
+        let config = CounterConfig {
+            initial_values: BTreeMap::from([(counter_name.to_string(), counter_value)]),
+        };
+
+        let mut cluster = FakeCluster::new(config.encode_to_vec().into());
+
+        cluster.start_node(1, true, TypedActorAdapter::new(CounterActor::new()));
+        cluster.advance_until_elected_leader(None);
+
+        cluster.start_node(2, false, TypedActorAdapter::new(CounterActor::new()));
+        cluster.start_node(3, false, TypedActorAdapter::new(CounterActor::new()));
+
+        cluster.add_node_to_cluster(2);
+        cluster.add_node_to_cluster(3);
+
+        send_cas_counter_request(
+            &mut cluster,
+            1,
+            1,
+            counter_name,
+            counter_value,
+            counter_value + 1,
+        );
+        assert!(advance_until_cas_counter_response(
+            &mut cluster,
+            1,
+            CounterStatus::Success,
+            counter_value,
+            counter_value + 1
+        ));
+
+        let leader_status = cluster.replica_status(1);
+        assert_eq!(leader_status.replica_id, 1);
+        assert_eq!(leader_status.role(), ReplicaRole::Leader);
+        assert_eq!(leader_status.leader_replica_id, 1);
+        assert!(leader_status.term > 0);
+        assert!(leader_status.applied_index > 0);
+        assert!(leader_status.applied_index <= leader_status.committed_index);
+        assert_eq!(leader_status.actor_health(), ActorHealth::Healthy);
+        let mut voter_ids = leader_status.voter_ids.clone();
+        voter_ids.sort();
+        assert_eq!(voter_ids, vec![1, 2, 3]);
+        assert!(leader_status.learner_ids.is_empty());
+
+        let follower_status = cluster.replica_status(2);
+        assert_eq!(follower_status.replica_id, 2);
+        assert_eq!(follower_status.role(), ReplicaRole::Follower);
+        assert_eq!(follower_status.leader_replica_id, 1);
+        assert_eq!(follower_status.term, leader_status.term);
+        assert_eq!(follower_status.actor_health(), ActorHealth::Healthy);
+        assert_eq!(follower_status.skipped_entry_count, 0);
+    }
+
+    #[test]
+    fn health_check() {
+        let counter_name = "counter";
+        let counter_value: i64 = 9;
+        let config = CounterConfig {
+            initial_values: BTreeMap::from([(counter_name.to_string(), counter_value)]),
+        };
+
+        let mut cluster = FakeCluster::new(config.encode_to_vec().into());
+
+        cluster.start_node(1, true, TypedActorAdapter::new(CounterActor::new()));
+        cluster.advance_until_elected_leader(None);
+
+        cluster.start_node(2, false, TypedActorAdapter::new(CounterActor::new()));
+        cluster.start_node(3, false, TypedActorAdapter::new(CounterActor::new()));
+
+        cluster.add_node_to_cluster(2);
+        cluster.add_node_to_cluster(3);
+
+        for node_id in [1, 2, 3] {
+            let health = cluster.health_check(node_id, 10);
+            assert!(health.live);
+            assert!(health.ready);
+            assert_eq!(health.not_ready_reason(), NotReadyReason::Unspecified);
+        }
+
+        // Replica preparing to shut down is live but no longer ready.
+        assert!(cluster.prepare_shutdown(1) > 0);
+        let health = cluster.health_check(1, 10);
+        assert!(health.live);
+        assert!(!health.ready);
+        assert_eq!(health.not_ready_reason(), NotReadyReason::ShuttingDown);
+    }
+
+    #[test]
+    fn replace_voters_through_joint_consensus() {
+        let counter_name = "counter";
+        let counter_value: i64 = 11;
+        let config = CounterConfig {
+            initial_values: BTreeMap::from([(counter_name.to_string(), counter_value)]),
+        };
+
+        let mut cluster = FakeCluster::new(config.encode_to_vec().into());
+
+        cluster.start_node(1, true, TypedActorAdapter::new(CounterActor::new()));
+        cluster.advance_until_elected_leader(None);
+
+        cluster.start_node(2, false, TypedActorAdapter::new(CounterActor::new()));
+        cluster.start_node(3, false, TypedActorAdapter::new(CounterActor::new()));
+        cluster.add_node_to_cluster(2);
+        cluster.add_node_to_cluster(3);
+
+        // Replace both followers at once.
+        cluster.start_node(4, false, TypedActorAdapter::new(CounterActor::new()));
+        cluster.start_node(5, false, TypedActorAdapter::new(CounterActor::new()));
+        cluster.reconfigure_cluster(vec![4, 5], vec![2, 3]);
+
+        cluster.stop_node(2);
+        cluster.stop_node(3);
+
+        send_cas_counter_request(
+            &mut cluster,
+            1,
+            1,
+            counter_name,
+            counter_value,
+            counter_value + 1,
+        );
+        assert!(advance_until_cas_counter_response(
+            &mut cluster,
+            1,
+            CounterStatus::Success,
+            counter_value,
+            counter_value + 1
+        ));
+    }
 }
@@ -17,39 +17,22 @@
 #![feature(alloc_error_handler)]
 
 extern crate alloc;
-extern crate tcp_proto;
 extern crate tcp_runtime;
 
 use alloc::boxed::Box;
 use oak_restricted_kernel_sdk::{
-    attestation::InstanceEvidenceProvider,
-    channel::{start_blocking_server, FileDescriptorChannel},
-    crypto::InstanceSigner,
-    entrypoint,
-    utils::{log, samplestore::StaticSampleStore},
+    attestation::InstanceEvidenceProvider, crypto::InstanceSigner, entrypoint,
 };
 use tcp_ledger_service::actor::LedgerActor;
-use tcp_proto::runtime::endpoint::EndpointServiceServer;
-use tcp_runtime::service::ApplicationService;
+use tcp_runtime::{server::run_blocking_server, service::ApplicationService};
 
 #[entrypoint]
 fn run_server() -> ! {
-    // Only log warnings and errors to reduce the risk of accidentally leaking execution
-    // information through debug logs.
-    log::set_max_level(log::LevelFilter::Warn);
-
-    let mut invocation_stats = StaticSampleStore::<1000>::new().unwrap();
     let actor = LedgerActor::create(
         Box::new(InstanceEvidenceProvider::create().unwrap()),
         Box::new(InstanceSigner::create().unwrap()),
     )
     .expect("LedgerActor failed to create");
     let service: ApplicationService<LedgerActor> = ApplicationService::new(actor);
-    let server = EndpointServiceServer::new(service);
-    start_blocking_server(
-        Box::<FileDescriptorChannel>::default(),
-        server,
-        &mut invocation_stats,
-    )
-    .expect("Server encountered an unrecoverable error");
+    run_blocking_server(service)
 }
@@ -28,8 +28,10 @@ use coset::{
     iana, Algorithm, CborSerializable, CoseKey, CoseSign1, KeyType, Label,
 };
 use hpke::{
-    aead::AesGcm128, kdf::HkdfSha256, kem::X25519HkdfSha256, Deserializable, Kem, OpModeR, OpModeS,
-    Serializable,
+    aead::{Aead as HpkeAead, AesGcm128, ChaCha20Poly1305},
+    kdf::HkdfSha256,
+    kem::X25519HkdfSha256,
+    Deserializable, Kem, OpModeR, OpModeS, Serializable,
 };
 
 pub type PrivateKey = <X25519HkdfSha256 as Kem>::PrivateKey;
@@ -52,6 +54,88 @@ pub const CONFIG_PROPERTIES_CLAIM: i64 = -65538;
 const HPKE_BASE_X25519_SHA256_AES128GCM: i64 = -65537;
 const AEAD_AES_128_GCM_SIV_FIXED_NONCE: i64 = -65538;
 
+// Private CoseKey algorithm that is neither listed in the document above nor
+// registered with IANA. It is taken from the range the COSE Algorithms registry
+// reserves for private use (below -65536), hence only the Trusted Ledger and its
+// clients agree on its meaning.
+const HPKE_BASE_X25519_SHA256_CHACHA20POLY1305: i64 = -65539;
+
+/// The HPKE cipher suites that can be used to wrap the symmetric keys. All
+/// suites share the KEM, so a `PrivateKey` can be used with any of them, but the
+/// suite a keypair has been created for must be used consistently.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CipherSuite {
+    #[default]
+    X25519HkdfSha256Aes128Gcm,
+    X25519HkdfSha256ChaCha20Poly1305,
+}
+
+impl CipherSuite {
+    /// Returns the CoseKey algorithm identifying the cipher suite.
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::PrivateUse(match self {
+            CipherSuite::X25519HkdfSha256Aes128Gcm => HPKE_BASE_X25519_SHA256_AES128GCM,
+            CipherSuite::X25519HkdfSha256ChaCha20Poly1305 => {
+                HPKE_BASE_X25519_SHA256_CHACHA20POLY1305
+            }
+        })
+    }
+
+    /// Determines the cipher suite of the public key, failing if the CoseKey
+    /// cannot be used for wrapping.
+    pub fn from_public_key(public_key: &CoseKey) -> anyhow::Result<CipherSuite> {
+        if public_key.kty != KeyType::Assigned(iana::KeyType::OKP)
+            || !public_key.params.iter().any(|(label, value)| {
+                label == &Label::Int(iana::OkpKeyParameter::Crv as i64)
+                    && value == &Value::from(iana::EllipticCurve::X25519 as u64)
+            })
+        {
+            return Err(anyhow!("unsupported CoseKey type"));
+        }
+        [
+            CipherSuite::X25519HkdfSha256Aes128Gcm,
+            CipherSuite::X25519HkdfSha256ChaCha20Poly1305,
+        ]
+        .into_iter()
+        .find(|cipher_suite| public_key.alg == Some(cipher_suite.algorithm()))
+        .ok_or_else(|| anyhow!("unsupported CoseKey type"))
+    }
+}
+
+/// Seals the plaintext for the public key using HPKE with the given AEAD.
+fn seal<A: HpkeAead>(
+    public_key: &<X25519HkdfSha256 as Kem>::PublicKey,
+    plaintext: &[u8],
+    associated_data: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), hpke::HpkeError> {
+    let (encapped_key, ciphertext) = hpke::single_shot_seal::<A, HkdfSha256, X25519HkdfSha256, _>(
+        &OpModeS::Base,
+        public_key,
+        &INFO,
+        plaintext,
+        associated_data,
+        &mut OsRng,
+    )?;
+    Ok((encapped_key.to_bytes().to_vec(), ciphertext))
+}
+
+/// Opens the ciphertext sealed by `seal` using the same AEAD.
+fn open<A: HpkeAead>(
+    private_key: &PrivateKey,
+    encapped_key: &<X25519HkdfSha256 as Kem>::EncappedKey,
+    ciphertext: &[u8],
+    associated_data: &[u8],
+) -> Result<Vec<u8>, hpke::HpkeError> {
+    hpke::single_shot_open::<A, HkdfSha256, X25519HkdfSha256>(
+        &OpModeR::Base,
+        private_key,
+        encapped_key,
+        &INFO,
+        ciphertext,
+        associated_data,
+    )
+}
+
 /// Wraps a symmetric encryption key using HPKE.
 ///
 /// # Return Value
@@ -63,16 +147,7 @@ fn wrap_symmetric_key(
     associated_data: &[u8],
 ) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
     // Check that the CoseKey can be used for rewrapping.
-    if recipient_public_key.kty != KeyType::Assigned(iana::KeyType::OKP)
-        || recipient_public_key.alg
-            != Some(Algorithm::PrivateUse(HPKE_BASE_X25519_SHA256_AES128GCM))
-        || !recipient_public_key.params.iter().any(|(label, value)| {
-            label == &Label::Int(iana::OkpKeyParameter::Crv as i64)
-                && value == &Value::from(iana::EllipticCurve::X25519 as u64)
-        })
-    {
-        return Err(anyhow!("unsupported CoseKey type"));
-    }
+    let cipher_suite = CipherSuite::from_public_key(recipient_public_key)?;
 
     // Extract the raw public key and convert it to a PublicKey.
     let raw_recipient_public_key = recipient_public_key
@@ -84,18 +159,16 @@ fn wrap_symmetric_key(
     let public_key = <X25519HkdfSha256 as Kem>::PublicKey::from_bytes(raw_recipient_public_key)
         .map_err(|err| anyhow!("failed to parse recipient public key: {:?}", err))?;
 
-    // Rewrap the symmetric key.
-    let (encapped_key, encrypted_symmetric_key) =
-        hpke::single_shot_seal::<AesGcm128, HkdfSha256, X25519HkdfSha256, _>(
-            &OpModeS::Base,
-            &public_key,
-            &INFO,
-            symmetric_key,
-            associated_data,
-            &mut OsRng,
-        )
-        .map_err(|err| anyhow!("failed to seal key: {:?}", err))?;
-    Ok((encapped_key.to_bytes().to_vec(), encrypted_symmetric_key))
+    // Rewrap the symmetric key using the cipher suite of the recipient.
+    match cipher_suite {
+        CipherSuite::X25519HkdfSha256Aes128Gcm => {
+            seal::<AesGcm128>(&public_key, symmetric_key, associated_data)
+        }
+        CipherSuite::X25519HkdfSha256ChaCha20Poly1305 => {
+            seal::<ChaCha20Poly1305>(&public_key, symmetric_key, associated_data)
+        }
+    }
+    .map_err(|err| anyhow!("failed to seal key: {:?}", err))
 }
 
 /// Unwraps a symmetric encryption that was wrapped using `wrap_symmetric_key`.
@@ -107,28 +180,83 @@ fn unwrap_symmetric_key(
     encrypted_symmetric_key: &[u8],
     serialized_encapped_key: &[u8],
     private_key: &PrivateKey,
+    cipher_suite: CipherSuite,
     associated_data: &[u8],
 ) -> anyhow::Result<Vec<u8>> {
     let encapped_key = <X25519HkdfSha256 as Kem>::EncappedKey::from_bytes(serialized_encapped_key)
         .map_err(|err| anyhow!("failed to load encapped key: {:?}", err))?;
-    hpke::single_shot_open::<AesGcm128, HkdfSha256, X25519HkdfSha256>(
-        &OpModeR::Base,
-        private_key,
-        &encapped_key,
-        &INFO,
-        encrypted_symmetric_key,
-        associated_data,
-    )
+    match cipher_suite {
+        CipherSuite::X25519HkdfSha256Aes128Gcm => open::<AesGcm128>(
+            private_key,
+            &encapped_key,
+            encrypted_symmetric_key,
+            associated_data,
+        ),
+        CipherSuite::X25519HkdfSha256ChaCha20Poly1305 => open::<ChaCha20Poly1305>(
+            private_key,
+            &encapped_key,
+            encrypted_symmetric_key,
+            associated_data,
+        ),
+    }
     .map_err(|err| anyhow!("failed to unwrap symmetric key: {:?}", err))
 }
 
-/// Generates a random keypair.
+/// Generates a random keypair for the default cipher suite.
 pub fn gen_keypair(key_id: &[u8]) -> (PrivateKey, CoseKey) {
+    gen_keypair_with_cipher_suite(key_id, CipherSuite::default())
+}
+
+/// Generates a random keypair for the given cipher suite.
+pub fn gen_keypair_with_cipher_suite(
+    key_id: &[u8],
+    cipher_suite: CipherSuite,
+) -> (PrivateKey, CoseKey) {
     let (private_key, raw_public_key) = <X25519HkdfSha256 as Kem>::gen_keypair(&mut OsRng);
-    let public_key = CoseKey {
+    (
+        private_key,
+        build_public_key(key_id, &raw_public_key, cipher_suite),
+    )
+}
+
+/// Deterministically derives a keypair for the given cipher suite from the
+/// input keying material, which must contain at least 32 bytes of entropy.
+pub fn derive_keypair(
+    key_id: &[u8],
+    ikm: &[u8],
+    cipher_suite: CipherSuite,
+) -> (PrivateKey, CoseKey) {
+    let (private_key, raw_public_key) = <X25519HkdfSha256 as Kem>::derive_keypair(ikm);
+    (
+        private_key,
+        build_public_key(key_id, &raw_public_key, cipher_suite),
+    )
+}
+
+/// Computes the public key for the given cipher suite that corresponds to the
+/// private key.
+pub fn get_public_key(
+    key_id: &[u8],
+    private_key: &PrivateKey,
+    cipher_suite: CipherSuite,
+) -> CoseKey {
+    build_public_key(
+        key_id,
+        &<X25519HkdfSha256 as Kem>::sk_to_pk(private_key),
+        cipher_suite,
+    )
+}
+
+/// Wraps the raw public key into a CoseKey.
+fn build_public_key(
+    key_id: &[u8],
+    raw_public_key: &<X25519HkdfSha256 as Kem>::PublicKey,
+    cipher_suite: CipherSuite,
+) -> CoseKey {
+    CoseKey {
         kty: KeyType::Assigned(iana::KeyType::OKP),
         key_id: key_id.to_vec(),
-        alg: Some(Algorithm::PrivateUse(HPKE_BASE_X25519_SHA256_AES128GCM)),
+        alg: Some(cipher_suite.algorithm()),
         params: vec![
             (
                 Label::Int(iana::OkpKeyParameter::Crv as i64),
@@ -140,8 +268,7 @@ pub fn gen_keypair(key_id: &[u8]) -> (PrivateKey, CoseKey) {
             ),
         ],
         ..Default::default()
-    };
-    (private_key, public_key)
+    }
 }
 
 /// Encrypts client data using a combination of HPKE and AEAD.
@@ -203,8 +330,10 @@ pub fn encrypt_message(
 /// * `encrypted_symmetric_key` - The encrypted symmetric key produced by `encrypt_message`.
 /// * `serialized_encapped_key` - The encapped public key returned by `encrypt_message`.
 /// * `private_key` - The corresponding private key for the public key passed to `encrypt_message`.
+/// * `cipher_suite` - The cipher suite the keypair has been created for.
 /// * `unwrap_associated_data` - The associated data passed to `encrypt_message`.
-/// * `recipient_public_key` - The public key of the recipient.
+/// * `recipient_public_key` - The public key of the recipient, which determines the cipher suite
+///   used for re-wrapping.
 /// * `wrap_associated_data` - Additional data to be verified along with the message. This replaces
 ///    `unwrap_associated_data`.
 ///
@@ -215,6 +344,7 @@ pub fn rewrap_symmetric_key(
     encrypted_symmetric_key: &[u8],
     serialized_encapped_key: &[u8],
     private_key: &PrivateKey,
+    cipher_suite: CipherSuite,
     unwrap_associated_data: &[u8],
     recipient_public_key: &CoseKey,
     wrap_associated_data: &[u8],
@@ -224,6 +354,7 @@ pub fn rewrap_symmetric_key(
         encrypted_symmetric_key,
         serialized_encapped_key,
         private_key,
+        cipher_suite,
         unwrap_associated_data,
     )?;
 
@@ -231,7 +362,8 @@ pub fn rewrap_symmetric_key(
     wrap_symmetric_key(&symmetric_key, recipient_public_key, wrap_associated_data)
 }
 
-/// Decrypts data produced using `encrypt_message`.
+/// Decrypts data produced using `encrypt_message`, where the symmetric key has been re-wrapped
+/// for a keypair of the default cipher suite.
 ///
 /// # Arguments
 ///
@@ -254,11 +386,33 @@ pub fn decrypt_message(
     encrypted_symmetric_key_associated_data: &[u8],
     serialized_encapped_key: &[u8],
     private_key: &PrivateKey,
+) -> anyhow::Result<Vec<u8>> {
+    decrypt_message_with_cipher_suite(
+        ciphertext,
+        ciphertext_associated_data,
+        encrypted_symmetric_key,
+        encrypted_symmetric_key_associated_data,
+        serialized_encapped_key,
+        private_key,
+        CipherSuite::default(),
+    )
+}
+
+/// Same as `decrypt_message`, but for a keypair of the given cipher suite.
+pub fn decrypt_message_with_cipher_suite(
+    ciphertext: &[u8],
+    ciphertext_associated_data: &[u8],
+    encrypted_symmetric_key: &[u8],
+    encrypted_symmetric_key_associated_data: &[u8],
+    serialized_encapped_key: &[u8],
+    private_key: &PrivateKey,
+    cipher_suite: CipherSuite,
 ) -> anyhow::Result<Vec<u8>> {
     let symmetric_key = unwrap_symmetric_key(
         encrypted_symmetric_key,
         serialized_encapped_key,
         private_key,
+        cipher_suite,
         encrypted_symmetric_key_associated_data,
     )?;
 
@@ -352,6 +506,32 @@ mod tests {
         assert_ne!(public_key1, public_key2);
     }
 
+    #[test]
+    fn test_derive_keypair_is_deterministic() {
+        let (private_key1, public_key1) =
+            derive_keypair(b"key-id", &[1; 32], CipherSuite::default());
+        let (private_key2, public_key2) =
+            derive_keypair(b"key-id", &[1; 32], CipherSuite::default());
+        let (private_key3, _) = derive_keypair(b"key-id", &[2; 32], CipherSuite::default());
+        assert_eq!(private_key1.to_bytes(), private_key2.to_bytes());
+        assert_eq!(public_key1, public_key2);
+        assert_ne!(private_key1.to_bytes(), private_key3.to_bytes());
+    }
+
+    #[test]
+    fn test_get_public_key() {
+        let (private_key, public_key) = gen_keypair(b"key-id");
+        assert_eq!(
+            get_public_key(b"key-id", &private_key, CipherSuite::default()),
+            public_key
+        );
+        let (other_private_key, _) = gen_keypair(b"key-id");
+        assert_ne!(
+            get_public_key(b"key-id", &other_private_key, CipherSuite::default()),
+            public_key
+        );
+    }
+
     #[test]
     fn test_encrypt_rewrap_decrypt() -> anyhow::Result<()> {
         // Encrypt the original message.
@@ -368,6 +548,7 @@ mod tests {
             &encrypted_symmetric_key1,
             &encapped_key1,
             &private_key1,
+            CipherSuite::default(),
             associated_data1,
             &public_key2,
             associated_data2,
@@ -386,6 +567,76 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_cipher_suite_from_public_key() {
+        for cipher_suite in [
+            CipherSuite::X25519HkdfSha256Aes128Gcm,
+            CipherSuite::X25519HkdfSha256ChaCha20Poly1305,
+        ] {
+            let (_, public_key) = gen_keypair_with_cipher_suite(b"key-id", cipher_suite);
+            assert_that!(
+                CipherSuite::from_public_key(&public_key),
+                ok(eq(cipher_suite))
+            );
+        }
+        let (_, public_key) = gen_keypair(b"key-id");
+        assert_eq!(
+            public_key.alg,
+            Some(Algorithm::PrivateUse(HPKE_BASE_X25519_SHA256_AES128GCM))
+        );
+    }
+
+    #[test]
+    fn test_encrypt_rewrap_decrypt_across_cipher_suites() -> anyhow::Result<()> {
+        // Encrypt the original message for a ChaCha20Poly1305 keypair.
+        let plaintext = b"plaintext";
+        let associated_data1 = b"associated data1";
+        let (private_key1, public_key1) =
+            gen_keypair_with_cipher_suite(b"key-id", CipherSuite::X25519HkdfSha256ChaCha20Poly1305);
+        let (ciphertext, encapped_key1, encrypted_symmetric_key1) =
+            encrypt_message(plaintext, &public_key1, associated_data1)?;
+
+        // The symmetric key can't be unwrapped using a different cipher suite.
+        let associated_data2 = b"associated data2";
+        let (private_key2, public_key2) = gen_keypair(b"key_id");
+        assert_that!(
+            rewrap_symmetric_key(
+                &encrypted_symmetric_key1,
+                &encapped_key1,
+                &private_key1,
+                CipherSuite::X25519HkdfSha256Aes128Gcm,
+                associated_data1,
+                &public_key2,
+                associated_data2,
+            ),
+            err(displays_as(contains_substring(
+                "failed to unwrap symmetric key"
+            )))
+        );
+
+        // Rewrap the symmetric key for an AES-128-GCM keypair.
+        let (encapped_key2, encrypted_symmetric_key2) = rewrap_symmetric_key(
+            &encrypted_symmetric_key1,
+            &encapped_key1,
+            &private_key1,
+            CipherSuite::X25519HkdfSha256ChaCha20Poly1305,
+            associated_data1,
+            &public_key2,
+            associated_data2,
+        )?;
+        let result = decrypt_message_with_cipher_suite(
+            &ciphertext,
+            associated_data1,
+            &encrypted_symmetric_key2,
+            associated_data2,
+            &encapped_key2,
+            &private_key2,
+            CipherSuite::X25519HkdfSha256Aes128Gcm,
+        )?;
+        assert_eq!(result, plaintext);
+        Ok(())
+    }
+
     #[test]
     fn test_encrypt_message_with_invalid_public_key() {
         let plaintext = b"plaintext";
@@ -420,6 +671,7 @@ mod tests {
                 b"invalid",
                 &encapped_key,
                 &private_key1,
+                CipherSuite::default(),
                 associated_data1,
                 &public_key2,
                 associated_data2,
@@ -445,6 +697,7 @@ mod tests {
                 &encrypted_symmetric_key,
                 b"invalid",
                 &private_key1,
+                CipherSuite::default(),
                 associated_data1,
                 &public_key2,
                 associated_data2,
@@ -470,6 +723,7 @@ mod tests {
                 &encrypted_symmetric_key,
                 &encapped_key,
                 &private_key2, // Should be private_key1.
+                CipherSuite::default(),
                 associated_data1,
                 &public_key2,
                 associated_data2,
@@ -495,6 +749,7 @@ mod tests {
                 &encrypted_symmetric_key,
                 &encapped_key,
                 &private_key1,
+                CipherSuite::default(),
                 b"invalid",
                 &public_key2,
                 associated_data2,
@@ -521,6 +776,7 @@ mod tests {
                 &encrypted_symmetric_key,
                 &encapped_key,
                 &private_key1,
+                CipherSuite::default(),
                 associated_data1,
                 &public_key2,
                 associated_data2,
@@ -545,6 +801,7 @@ mod tests {
                 &encrypted_symmetric_key,
                 &encapped_key,
                 &private_key1,
+                CipherSuite::default(),
                 associated_data1,
                 &public_key2,
                 associated_data2,
@@ -571,6 +828,7 @@ mod tests {
                 &encrypted_symmetric_key,
                 &encapped_key,
                 &private_key1,
+                CipherSuite::default(),
                 associated_data1,
                 &public_key2,
                 associated_data2,
@@ -600,6 +858,7 @@ mod tests {
                 &encrypted_symmetric_key,
                 &encapped_key,
                 &private_key1,
+                CipherSuite::default(),
                 associated_data1,
                 &public_key2,
                 associated_data2,
@@ -626,6 +885,7 @@ mod tests {
                 &encrypted_symmetric_key,
                 &encapped_key,
                 &private_key1,
+                CipherSuite::default(),
                 associated_data1,
                 &public_key2,
                 associated_data2,
@@ -657,6 +917,7 @@ mod tests {
                 &encrypted_symmetric_key,
                 &encapped_key,
                 &private_key1,
+                CipherSuite::default(),
                 associated_data1,
                 &public_key2,
                 associated_data2,
@@ -680,6 +941,7 @@ mod tests {
             &encrypted_symmetric_key1,
             &encapped_key1,
             &private_key1,
+            CipherSuite::default(),
             associated_data1,
             &public_key2,
             associated_data2,
@@ -712,6 +974,7 @@ mod tests {
             &encrypted_symmetric_key1,
             &encapped_key1,
             &private_key1,
+            CipherSuite::default(),
             associated_data1,
             &public_key2,
             associated_data2,
@@ -744,6 +1007,7 @@ mod tests {
             &encrypted_symmetric_key1,
             &encapped_key1,
             &private_key1,
+            CipherSuite::default(),
             associated_data1,
             &public_key2,
             associated_data2,
@@ -778,6 +1042,7 @@ mod tests {
             &encrypted_symmetric_key1,
             &encapped_key1,
             &private_key1,
+            CipherSuite::default(),
             associated_data1,
             &public_key2,
             associated_data2,
@@ -812,6 +1077,7 @@ mod tests {
             &encrypted_symmetric_key1,
             &encapped_key1,
             &private_key1,
+            CipherSuite::default(),
             associated_data1,
             &public_key2,
             associated_data2,
@@ -846,6 +1112,7 @@ mod tests {
             &encrypted_symmetric_key1,
             &encapped_key1,
             &private_key1,
+            CipherSuite::default(),
             associated_data1,
             &public_key2,
             associated_data2,
@@ -12,11 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::error::LedgerError;
+use crate::key_wrapping::KEY_WRAPPING_CONTEXT;
 use crate::ledger::service::*;
 use crate::ledger::service::{ledger_event::*, ledger_request::*, ledger_response::*};
 use crate::ledger::{Ledger, LedgerService};
 
 use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
 use oak_restricted_kernel_sdk::{attestation::EvidenceProvider, crypto::Signer};
 use prost::{bytes::Bytes, Message};
 use slog::{debug, error, warn};
@@ -25,9 +29,15 @@ use tcp_runtime::model::{
     EventOutcome,
 };
 
+/// Replicated ledger actor. The private keys are replicated wrapped under the key derived
+/// from the cluster secret, hence the actor requires `RaftConfig.share_cluster_secret`.
 pub struct LedgerActor {
     context: Option<Box<dyn ActorContext>>,
     ledger: LedgerService,
+    has_key_wrapping_key: bool,
+    // Whether the requests are handled at the wall clock time attested by the time
+    // authority rather than the time passed by the untrusted side.
+    use_trusted_time: bool,
 }
 
 impl LedgerActor {
@@ -38,9 +48,32 @@ impl LedgerActor {
         Ok(LedgerActor {
             context: None,
             ledger: LedgerService::create(evidence_provider, signer)?,
+            has_key_wrapping_key: false,
+            use_trusted_time: false,
         })
     }
 
+    // Hands the key wrapping key derived from the cluster secret to the ledger once the
+    // secret is available. Returns false if the secret has not been received yet.
+    fn sync_key_wrapping_key(&mut self) -> bool {
+        if !self.has_key_wrapping_key {
+            match self.get_context().cluster_key(KEY_WRAPPING_CONTEXT) {
+                Some(key_wrapping_key) => {
+                    self.ledger.set_key_wrapping_key(key_wrapping_key);
+                    self.has_key_wrapping_key = true;
+                }
+                None => {
+                    error!(
+                        self.get_context().logger(),
+                        "LedgerActor: cluster secret is not available"
+                    );
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
     fn get_context(&mut self) -> &mut dyn ActorContext {
         self.context
             .as_mut()
@@ -54,10 +87,7 @@ impl LedgerActor {
 
     // Handles the actor message and returns the message outcome or the status to be promptly
     // returned to the untrusted side.
-    fn handle_command(
-        &mut self,
-        command: ActorCommand,
-    ) -> Result<CommandOutcome, micro_rpc::Status> {
+    fn handle_command(&mut self, command: ActorCommand) -> Result<CommandOutcome, LedgerError> {
         let ledger_request = LedgerRequest::decode(command.header.clone()).map_err(|error| {
             warn!(
                 self.get_context().logger(),
@@ -75,6 +105,22 @@ impl LedgerActor {
             ledger_request.name()
         );
 
+        if !self.sync_key_wrapping_key() {
+            return Err(micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::Unavailable,
+                "Cluster secret is not available",
+            )
+            .into());
+        }
+
+        if self.use_trusted_time {
+            let trusted_time = self
+                .get_context()
+                .wall_time()
+                .map(core::time::Duration::from_millis);
+            self.mut_ledger().set_trusted_time(trusted_time);
+        }
+
         if !self.get_context().leader() {
             // Not a leader.
             warn!(
@@ -85,23 +131,41 @@ impl LedgerActor {
             return Err(micro_rpc::Status::new_with_message(
                 micro_rpc::StatusCode::Aborted,
                 "Command rejected",
-            ));
+            )
+            .into());
         }
 
         let event = match ledger_request.request {
             Some(Request::AuthorizeAccess(authorize_access_request)) => {
                 // Attest and produce the event that contains all the data necessary to
                 // update the budget and rewrap the symmetric key when the event is later applied.
-                let authorize_access_event = self
+                let authorize_access_event = match self
                     .mut_ledger()
-                    .attest_and_produce_authorize_access_event(authorize_access_request)?;
+                    .attest_and_produce_authorize_access_event(authorize_access_request)
+                {
+                    Ok(authorize_access_event) => authorize_access_event,
+                    Err(err) => return Ok(self.with_access_denials(command.correlation_id, err)),
+                };
                 Event::AuthorizeAccess(authorize_access_event)
             }
+            Some(Request::AuthorizeAccessBatch(authorize_access_batch_request)) => {
+                // Attest once and produce the event that contains all the data necessary to
+                // update the budgets and rewrap the symmetric keys of the whole batch.
+                let authorize_access_batch_event = self
+                    .mut_ledger()
+                    .attest_and_produce_authorize_access_batch_event(
+                        authorize_access_batch_request,
+                    )?;
+                Event::AuthorizeAccessBatch(authorize_access_batch_event)
+            }
             Some(Request::CreateKey(create_key_request)) => {
                 // Produce the event that contains the pregenerate public/private key pair.
+                let context = self.context.as_deref().expect("Context is initialized");
                 let create_key_event = self
-                    .mut_ledger()
-                    .produce_create_key_event(create_key_request)?;
+                    .ledger
+                    .produce_create_key_event(create_key_request, &|dest| {
+                        context.fill_random(dest)
+                    })?;
                 Event::CreateKey(create_key_event)
             }
             Some(Request::DeleteKey(delete_key_request)) => {
@@ -112,6 +176,79 @@ impl LedgerActor {
                 // In this case the original request is replicated as the event.
                 Event::RevokeAccess(revoke_access_request)
             }
+            Some(Request::RewrapKeys(rewrap_keys_request)) => {
+                // Produce the event that carries the request along with the time it is
+                // applied at, which followers may have no trusted time source for.
+                let rewrap_keys_event = self
+                    .mut_ledger()
+                    .produce_rewrap_keys_event(rewrap_keys_request)?;
+                Event::RewrapKeys(rewrap_keys_event)
+            }
+            Some(Request::ListKeys(list_keys_request)) => {
+                // Queries are answered without replication once the state is confirmed
+                // to be up to date.
+                if !self.get_context().read_confirmed() {
+                    return Ok(CommandOutcome::with_read(command));
+                }
+                let list_keys_response = self.mut_ledger().list_keys(list_keys_request)?;
+                return Ok(CommandOutcome::with_command(ActorCommand::with_header(
+                    command.correlation_id,
+                    &LedgerResponse {
+                        response: Some(Response::ListKeys(list_keys_response)),
+                    },
+                )));
+            }
+            Some(Request::GetKeyDetails(get_key_details_request)) => {
+                if !self.get_context().read_confirmed() {
+                    return Ok(CommandOutcome::with_read(command));
+                }
+                let get_key_details_response =
+                    self.mut_ledger().get_key_details(get_key_details_request)?;
+                return Ok(CommandOutcome::with_command(ActorCommand::with_header(
+                    command.correlation_id,
+                    &LedgerResponse {
+                        response: Some(Response::GetKeyDetails(get_key_details_response)),
+                    },
+                )));
+            }
+            Some(Request::QueryAuditLog(query_audit_log_request)) => {
+                if !self.get_context().read_confirmed() {
+                    return Ok(CommandOutcome::with_read(command));
+                }
+                let query_audit_log_response =
+                    self.mut_ledger().query_audit_log(query_audit_log_request)?;
+                return Ok(CommandOutcome::with_command(ActorCommand::with_header(
+                    command.correlation_id,
+                    &LedgerResponse {
+                        response: Some(Response::QueryAuditLog(query_audit_log_response)),
+                    },
+                )));
+            }
+            Some(Request::CheckAccess(check_access_request)) => {
+                if !self.get_context().read_confirmed() {
+                    return Ok(CommandOutcome::with_read(command));
+                }
+                let check_access_response = self.mut_ledger().check_access(check_access_request)?;
+                return Ok(CommandOutcome::with_command(ActorCommand::with_header(
+                    command.correlation_id,
+                    &LedgerResponse {
+                        response: Some(Response::CheckAccess(check_access_response)),
+                    },
+                )));
+            }
+            Some(Request::GetKeyStats(get_key_stats_request)) => {
+                if !self.get_context().read_confirmed() {
+                    return Ok(CommandOutcome::with_read(command));
+                }
+                let get_key_stats_response =
+                    self.mut_ledger().get_key_stats(get_key_stats_request)?;
+                return Ok(CommandOutcome::with_command(ActorCommand::with_header(
+                    command.correlation_id,
+                    &LedgerResponse {
+                        response: Some(Response::GetKeyStats(get_key_stats_response)),
+                    },
+                )));
+            }
             _ => {
                 warn!(
                     self.get_context().logger(),
@@ -120,21 +257,93 @@ impl LedgerActor {
                 return Err(micro_rpc::Status::new_with_message(
                     micro_rpc::StatusCode::InvalidArgument,
                     "LedgerActor: unexpected request type",
-                ));
+                )
+                .into());
             }
         };
 
-        Ok(CommandOutcome::with_event(ActorEvent::with_proto(
-            command.correlation_id,
-            &LedgerEvent { event: Some(event) },
-        )))
+        let event =
+            ActorEvent::with_proto(command.correlation_id, &LedgerEvent { event: Some(event) });
+
+        // Mint the successor key if the rotation is due, the rotation is replicated
+        // atomically along with the request that advanced the time.
+        let context = self.context.as_deref().expect("Context is initialized");
+        let rotate_key_event = self
+            .ledger
+            .produce_rotate_key_event(&|dest| context.fill_random(dest))?;
+        Ok(match rotate_key_event {
+            Some(rotate_key_event) => CommandOutcome::with_events(vec![
+                event,
+                ActorEvent::with_proto(
+                    0,
+                    &LedgerEvent {
+                        event: Some(Event::RotateKey(rotate_key_event)),
+                    },
+                ),
+            ]),
+            None => CommandOutcome::with_event(event),
+        })
+    }
+
+    // Creates the outcome that responds with the error and replicates the denials found while
+    // handling the request, so that every replica counts them against the keys.
+    fn with_access_denials(&mut self, correlation_id: u64, err: LedgerError) -> CommandOutcome {
+        let response = ActorCommand::with_header(correlation_id, &LedgerResponse::with_error(err));
+        let access_denied_events: Vec<ActorEvent> = self
+            .mut_ledger()
+            .take_access_denied_events()
+            .into_iter()
+            .map(|access_denied_event| {
+                // Denials are not correlated with any request.
+                ActorEvent::with_proto(
+                    0,
+                    &LedgerEvent {
+                        event: Some(Event::AccessDenied(access_denied_event)),
+                    },
+                )
+            })
+            .collect();
+        if access_denied_events.is_empty() {
+            return CommandOutcome::with_command(response);
+        }
+        CommandOutcome {
+            commands: vec![response],
+            events: access_denied_events,
+            ..Default::default()
+        }
+    }
+
+    // Creates the outcome that responds to the event and sends out the notifications about
+    // the authorized accesses. Notifications are taken on every replica but only sent out by
+    // the replica that owns the event to avoid duplicates.
+    fn with_access_notifications(
+        correlation_id: u64,
+        response: Response,
+        access_notifications: Vec<AccessNotification>,
+    ) -> EventOutcome {
+        let response = ActorCommand::with_header(
+            correlation_id,
+            &LedgerResponse {
+                response: Some(response),
+            },
+        );
+        if access_notifications.is_empty() {
+            return EventOutcome::with_command(response);
+        }
+        let mut commands = Vec::with_capacity(access_notifications.len() + 1);
+        commands.push(response);
+        // Notifications are not correlated with any request.
+        commands.extend(access_notifications.into_iter().map(|notification| {
+            ActorCommand::with_header(0, &LedgerResponse::with_access_notification(notification))
+        }));
+        EventOutcome::with_commands(commands)
     }
 
     fn handle_event(
         &mut self,
         context: ActorEventContext,
         event: ActorEvent,
-    ) -> Result<EventOutcome, micro_rpc::Status> {
+    ) -> Result<EventOutcome, LedgerError> {
         let ledger_event = LedgerEvent::decode(event.contents.clone()).map_err(|error| {
             warn!(
                 self.get_context().logger(),
@@ -158,10 +367,46 @@ impl LedgerActor {
                 let authorize_access_response = self
                     .mut_ledger()
                     .apply_authorize_access_event(authorize_access_event)?;
+                let access_notifications = self.mut_ledger().take_access_notifications();
                 if !context.owned {
                     return Ok(EventOutcome::with_none());
                 }
-                Response::AuthorizeAccess(authorize_access_response)
+                return Ok(Self::with_access_notifications(
+                    event.correlation_id,
+                    Response::AuthorizeAccess(authorize_access_response),
+                    access_notifications,
+                ));
+            }
+            Some(Event::AuthorizeAccessBatch(authorize_access_batch_event)) => {
+                let authorize_access_batch_response = self
+                    .mut_ledger()
+                    .apply_authorize_access_batch_event(authorize_access_batch_event)?;
+                let access_notifications = self.mut_ledger().take_access_notifications();
+                if !context.owned {
+                    return Ok(EventOutcome::with_none());
+                }
+                return Ok(Self::with_access_notifications(
+                    event.correlation_id,
+                    Response::AuthorizeAccessBatch(authorize_access_batch_response),
+                    access_notifications,
+                ));
+            }
+            Some(Event::RotateKey(rotate_key_event)) => {
+                // The rotation is not requested by any client, hence there is no one to
+                // respond to.
+                if let Err(err) = self.mut_ledger().apply_rotate_key_event(rotate_key_event) {
+                    warn!(
+                        self.get_context().logger(),
+                        "LedgerActor: failed to rotate key: {}", err
+                    );
+                }
+                return Ok(EventOutcome::with_none());
+            }
+            Some(Event::AccessDenied(access_denied_event)) => {
+                // The request has already been responded to by the replica that rejected it.
+                self.mut_ledger()
+                    .apply_access_denied_event(access_denied_event)?;
+                return Ok(EventOutcome::with_none());
             }
             Some(Event::CreateKey(create_key_event)) => {
                 let create_key_response =
@@ -179,13 +424,23 @@ impl LedgerActor {
                 Response::DeleteKey(delete_key_response)
             }
             Some(ledger_event::Event::RevokeAccess(revoke_access_request)) => {
-                let revoke_access_response =
-                    self.mut_ledger().revoke_access(revoke_access_request)?;
+                let revoke_access_response = self
+                    .mut_ledger()
+                    .apply_revoke_access(revoke_access_request)?;
                 if !context.owned {
                     return Ok(EventOutcome::with_none());
                 }
                 Response::RevokeAccess(revoke_access_response)
             }
+            Some(Event::RewrapKeys(rewrap_keys_event)) => {
+                let rewrap_keys_response = self
+                    .mut_ledger()
+                    .apply_rewrap_keys_event(rewrap_keys_event)?;
+                if !context.owned {
+                    return Ok(EventOutcome::with_none());
+                }
+                Response::RewrapKeys(rewrap_keys_response)
+            }
             _ => {
                 warn!(
                     self.get_context().logger(),
@@ -194,7 +449,8 @@ impl LedgerActor {
                 return Err(micro_rpc::Status::new_with_message(
                     micro_rpc::StatusCode::InvalidArgument,
                     "LedgerActor: unexpected event type",
-                ));
+                )
+                .into());
             }
         };
 
@@ -211,9 +467,16 @@ impl LedgerRequest {
     fn name(self: &Self) -> &'static str {
         match self.request {
             Some(Request::AuthorizeAccess(_)) => "AuthorizeAccess",
+            Some(Request::AuthorizeAccessBatch(_)) => "AuthorizeAccessBatch",
             Some(Request::CreateKey(_)) => "CreateKey",
             Some(Request::DeleteKey(_)) => "DeleteKey",
             Some(Request::RevokeAccess(_)) => "RevokeAccess",
+            Some(Request::ListKeys(_)) => "ListKeys",
+            Some(Request::GetKeyDetails(_)) => "GetKeyDetails",
+            Some(Request::QueryAuditLog(_)) => "QueryAuditLog",
+            Some(Request::CheckAccess(_)) => "CheckAccess",
+            Some(Request::GetKeyStats(_)) => "GetKeyStats",
+            Some(Request::RewrapKeys(_)) => "RewrapKeys",
             _ => "Unknown",
         }
     }
@@ -223,21 +486,28 @@ impl LedgerEvent {
     fn name(self: &Self) -> &'static str {
         match self.event {
             Some(Event::AuthorizeAccess(_)) => "AuthorizeAccess",
+            Some(Event::AuthorizeAccessBatch(_)) => "AuthorizeAccessBatch",
+            Some(Event::RotateKey(_)) => "RotateKey",
             Some(Event::CreateKey(_)) => "CreateKey",
             Some(Event::DeleteKey(_)) => "DeleteKey",
             Some(Event::RevokeAccess(_)) => "RevokeAccess",
+            Some(Event::RewrapKeys(_)) => "RewrapKeys",
+            Some(Event::AccessDenied(_)) => "AccessDenied",
             _ => "Unknown",
         }
     }
 }
 
 impl LedgerResponse {
-    fn with_error(error: micro_rpc::Status) -> LedgerResponse {
+    fn with_error(error: LedgerError) -> LedgerResponse {
+        LedgerResponse {
+            response: Some(Response::Error(error.into())),
+        }
+    }
+
+    fn with_access_notification(access_notification: AccessNotification) -> LedgerResponse {
         LedgerResponse {
-            response: Some(Response::Error(ledger_response::Status {
-                code: error.code as i32,
-                message: error.message.into(),
-            })),
+            response: Some(Response::AccessNotification(access_notification)),
         }
     }
 }
@@ -257,9 +527,45 @@ impl Actor for LedgerActor {
         self.context = Some(context);
         debug!(self.get_context().logger(), "LedgerActor: initializing");
 
-        let _ = LedgerConfig::decode(self.get_context().config().as_ref())
+        let config = LedgerConfig::decode(self.get_context().config().as_ref())
             .map_err(|_| ActorError::ConfigLoading)?;
-        // TODO: use the config.
+        if let Some(access_notification_config) = config.access_notification_config {
+            self.mut_ledger()
+                .set_access_notification_config(access_notification_config);
+        }
+        if let Some(key_rotation_config) = config.key_rotation_config {
+            self.mut_ledger()
+                .set_key_rotation_config(key_rotation_config)
+                .map_err(|_| ActorError::ConfigLoading)?;
+        }
+        if let Some(key_deletion_grace_period) = config.key_deletion_grace_period {
+            self.mut_ledger()
+                .set_key_deletion_grace_period(key_deletion_grace_period)
+                .map_err(|_| ActorError::ConfigLoading)?;
+        }
+        if let Some(rate_limit_config) = config.rate_limit_config {
+            self.mut_ledger()
+                .set_rate_limit_config(rate_limit_config)
+                .map_err(|_| ActorError::ConfigLoading)?;
+        }
+        if let Some(idempotency_token_ttl) = config.idempotency_token_ttl {
+            self.mut_ledger()
+                .set_idempotency_token_ttl(idempotency_token_ttl)
+                .map_err(|_| ActorError::ConfigLoading)?;
+        }
+        if config.audit_log_retained_entries != 0 {
+            self.mut_ledger()
+                .set_audit_log_retained_entries(config.audit_log_retained_entries);
+        }
+        if let Some(recipient_verification_config) = config.recipient_verification_config {
+            self.mut_ledger()
+                .set_recipient_verification_config(recipient_verification_config)
+                .map_err(|_| ActorError::ConfigLoading)?;
+        }
+        if config.require_trusted_time {
+            self.use_trusted_time = true;
+            self.mut_ledger().set_require_trusted_time(true);
+        }
 
         Ok(())
     }
@@ -272,6 +578,9 @@ impl Actor for LedgerActor {
     /// is considered is unknown state and is destroyed.
     fn on_save_snapshot(&mut self) -> Result<Bytes, ActorError> {
         debug!(self.get_context().logger(), "LedgerActor: saving snapshot");
+        if !self.sync_key_wrapping_key() {
+            return Err(ActorError::Internal);
+        }
         let snapshot = self.mut_ledger().save_snapshot().map_err(|error| {
             error!(
                 self.get_context().logger(),
@@ -286,6 +595,9 @@ impl Actor for LedgerActor {
     /// is considered is unknown state and is destroyed.
     fn on_load_snapshot(&mut self, snapshot: Bytes) -> Result<(), ActorError> {
         debug!(self.get_context().logger(), "LedgerActor: loading snapshot");
+        if !self.sync_key_wrapping_key() {
+            return Err(ActorError::SnapshotLoading);
+        }
         let snapshot = LedgerSnapshot::decode(snapshot).map_err(|error| {
             error!(
                 self.get_context().logger(),
@@ -333,6 +645,11 @@ impl Actor for LedgerActor {
         context: ActorEventContext,
         event: ActorEvent,
     ) -> Result<EventOutcome, ActorError> {
+        // The event can't be applied consistently with the other replicas without
+        // the private keys it may carry.
+        if !self.sync_key_wrapping_key() {
+            return Err(ActorError::Internal);
+        }
         let correlation_id: u64 = event.correlation_id;
         self.handle_event(context, event).or_else(|err| {
             Ok(EventOutcome::with_command(ActorCommand::with_header(
@@ -346,15 +663,19 @@ impl Actor for LedgerActor {
 #[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
+    use crate::ledger::{LEDGER_SNAPSHOT_MIN_COMPATIBLE_VERSION, LEDGER_SNAPSHOT_VERSION};
     use oak_restricted_kernel_sdk::testing::{MockEvidenceProvider, MockSigner};
     use tcp_runtime::logger::log::create_logger;
     use tcp_runtime::mock::MockActorContext;
 
     fn create_actor() -> LedgerActor {
-        let config = LedgerConfig {};
+        let config = LedgerConfig::default();
         let mut mock_context = Box::new(MockActorContext::new());
         mock_context.expect_logger().return_const(create_logger());
         mock_context.expect_id().return_const(0u64);
+        mock_context
+            .expect_cluster_key()
+            .returning(|_| Some(vec![1; 32]));
         mock_context
             .expect_config()
             .return_const::<Bytes>(config.encode_to_vec().into());
@@ -378,6 +699,8 @@ mod tests {
     fn test_save_snapshot() {
         let mut actor = create_actor();
         let snapshot = LedgerSnapshot {
+            version: LEDGER_SNAPSHOT_VERSION,
+            min_compatible_version: LEDGER_SNAPSHOT_MIN_COMPATIBLE_VERSION,
             current_time: Some(prost_types::Timestamp::default()),
             ..Default::default()
         };
@@ -387,6 +710,71 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_save_snapshot_without_cluster_secret() {
+        let mut mock_context = Box::new(MockActorContext::new());
+        mock_context.expect_logger().return_const(create_logger());
+        mock_context
+            .expect_config()
+            .return_const::<Bytes>(LedgerConfig::default().encode_to_vec().into());
+        mock_context.expect_cluster_key().returning(|_| None);
+
+        let mut actor = LedgerActor::create(
+            Box::new(MockEvidenceProvider::create().unwrap()),
+            Box::new(MockSigner::create().unwrap()),
+        )
+        .unwrap();
+        assert_eq!(actor.on_init(mock_context), Ok(()));
+        assert_eq!(actor.on_save_snapshot(), Err(ActorError::Internal));
+    }
+
+    #[test]
+    fn test_require_trusted_time() {
+        let config = LedgerConfig {
+            require_trusted_time: true,
+            ..Default::default()
+        };
+        let mut mock_context = Box::new(MockActorContext::new());
+        mock_context.expect_logger().return_const(create_logger());
+        mock_context.expect_leader().return_const(true);
+        mock_context
+            .expect_cluster_key()
+            .returning(|_| Some(vec![1; 32]));
+        mock_context
+            .expect_config()
+            .return_const::<Bytes>(config.encode_to_vec().into());
+        mock_context
+            .expect_fill_random()
+            .returning(|dest| dest.fill(1));
+        let mut wall_times = vec![Some(1_000_000), None];
+        mock_context
+            .expect_wall_time()
+            .returning(move || wall_times.pop().unwrap());
+
+        let mut actor = LedgerActor::create(
+            Box::new(MockEvidenceProvider::create().unwrap()),
+            Box::new(MockSigner::create().unwrap()),
+        )
+        .unwrap();
+        assert_eq!(actor.on_init(mock_context), Ok(()));
+
+        let command = ActorCommand::with_header(
+            1,
+            &LedgerRequest {
+                request: Some(Request::CreateKey(CreateKeyRequest {
+                    ttl: Some(prost_types::Duration {
+                        seconds: 100,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })),
+            },
+        );
+        // Rejected until the attested wall clock time is known.
+        assert!(actor.handle_command(command.clone()).is_err());
+        assert!(actor.handle_command(command).is_ok());
+    }
+
     #[test]
     fn test_load_snapshot() {
         let mut actor = create_actor();
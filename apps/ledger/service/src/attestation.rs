@@ -14,7 +14,7 @@
 
 extern crate alloc;
 
-use alloc::string::String;
+use alloc::{string::String, vec::Vec};
 use anyhow::Context;
 use cfc_crypto::CONFIG_PROPERTIES_CLAIM;
 use core::time::Duration;
@@ -24,7 +24,10 @@ use federated_compute::proto::{
     StructMatcher, ValueMatcher,
 };
 use oak_attestation_verification::verifier::{verify, verify_dice_chain};
-use oak_proto_rust::oak::attestation::v1::{Endorsements, Evidence, ReferenceValues};
+use oak_proto_rust::oak::attestation::v1::{
+    extracted_evidence::EvidenceValues, Endorsements, Evidence, ExtractedEvidence, ReferenceValues,
+    RootLayerData,
+};
 use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
 use prost::Message;
 use prost_types::{value::Kind as ValueKind, Struct, Value};
@@ -36,11 +39,25 @@ pub struct Application<'a> {
     pub evidence: Option<&'a Evidence>,
     pub endorsements: Option<&'a Endorsements>,
     pub config_properties: Option<Struct>,
+    /// SHA2-256 digest of the application binary (or container bundle), if established by the
+    /// verifier independently of any reference values.
+    pub binary_sha256: Option<Vec<u8>>,
+    /// The application signing key which signed the application's public key, if established by
+    /// the verifier independently of any reference values (e.g. a pinned signing key).
+    pub signing_public_key: Option<Vec<u8>>,
+    /// SEV-SNP firmware security version of the current TCB, if established by the verifier
+    /// independently of any reference values.
+    pub security_version: Option<u32>,
 }
 
 impl Application<'_> {
     /// Returns whether the application matches all conditions in the ApplicationMatcher.
     ///
+    /// The binary digest, signing key and security version recorded in the evidence are only
+    /// trusted once the evidence has been verified against the matcher's reference values and
+    /// endorsements. Without reference values, these conditions are matched against the values
+    /// established by the verifier, if any.
+    ///
     /// # Arguments
     ///
     /// * `matcher` - The matcher to match against. An empty or unset matcher always matches.
@@ -55,9 +72,49 @@ impl Application<'_> {
             Some(m) => m,
             None => return true, // An empty matcher matches everything.
         };
-        self.tag_matches(&matcher.tag)
-            && self.reference_values_match(&matcher.reference_values, now)
-            && self.config_properties_match(&matcher.config_properties)
+        if !self.tag_matches(&matcher.tag)
+            || !self.config_properties_match(&matcher.config_properties)
+        {
+            return false;
+        }
+        let (binary_sha256, signing_public_key, security_version) =
+            match matcher.reference_values.as_ref() {
+                Some(reference_values) => match self.verify_evidence(reference_values, now) {
+                    Some(extracted_evidence) => (
+                        extract_binary_sha256(&extracted_evidence),
+                        Some(extracted_evidence.signing_public_key.clone()),
+                        extract_security_version(&extracted_evidence),
+                    ),
+                    None => return false,
+                },
+                None => (
+                    self.binary_sha256.clone(),
+                    self.signing_public_key.clone(),
+                    self.security_version,
+                ),
+            };
+        Self::value_allowed(&binary_sha256, &matcher.binary_sha256_digests)
+            && Self::value_allowed(&signing_public_key, &matcher.signing_public_keys)
+            && Self::security_version_matches(security_version, matcher.min_security_version)
+    }
+
+    /// Returns whether the attested value is one of the allowed values. Any value, including
+    /// a missing one, is allowed if the list of allowed values is empty.
+    fn value_allowed(value: &Option<Vec<u8>>, allowed_values: &[Vec<u8>]) -> bool {
+        allowed_values.is_empty()
+            || value
+                .as_ref()
+                .is_some_and(|value| allowed_values.contains(value))
+    }
+
+    /// Returns whether the attested security version is at least the expected minimum.
+    fn security_version_matches(
+        security_version: Option<u32>,
+        min_security_version: Option<u32>,
+    ) -> bool {
+        min_security_version.map_or(true, |min| {
+            security_version.is_some_and(|version| version >= min)
+        })
     }
 
     /// Returns whether the Application's tag matches the expected value.
@@ -65,23 +122,19 @@ impl Application<'_> {
         tag.as_ref().map_or(true, |t| self.tag == t)
     }
 
-    /// Returns whether the Application's evidence and endorsements match the ReferenceValues.
-    fn reference_values_match(
+    /// Verifies the Application's evidence and endorsements against the ReferenceValues and
+    /// returns the values extracted from the verified evidence, or None if they don't match.
+    fn verify_evidence(
         &self,
-        reference_values: &Option<ReferenceValues>,
+        reference_values: &ReferenceValues,
         now: Duration,
-    ) -> bool {
-        reference_values.as_ref().map_or(true, |rv| {
-            let now_utc_millis = match now.as_millis().try_into() {
-                Ok(v) => v,
-                Err(_) => return false,
-            };
-            let (evidence, endorsements) = match (self.evidence, self.endorsements) {
-                (Some(evidence), Some(endorsements)) => (evidence, endorsements),
-                _ => return false,
-            };
-            verify(now_utc_millis, evidence, endorsements, rv).is_ok()
-        })
+    ) -> Option<ExtractedEvidence> {
+        let now_utc_millis = now.as_millis().try_into().ok()?;
+        let (evidence, endorsements) = (self.evidence?, self.endorsements?);
+        verify(now_utc_millis, evidence, endorsements, reference_values).ok()?;
+        // The DICE chain is part of the verified evidence, so the values it records can be
+        // trusted now.
+        verify_dice_chain(evidence).ok()
     }
 
     /// Returns whether the Application's config properties match the expected value.
@@ -153,7 +206,9 @@ impl Application<'_> {
 /// Verifies enclave attestation and returns an Application describing its properties.
 ///
 /// Note that even if the verification succeeds, the attestation evidence should not be trusted
-/// until it has been matched against reference values.
+/// until it has been matched against reference values. The values recorded in the evidence are
+/// therefore left unset in the Application and are only extracted by `Application::matches`
+/// once the evidence has been verified.
 pub fn verify_attestation<'a>(
     public_key: &[u8],
     evidence: Option<&'a Evidence>,
@@ -166,46 +221,10 @@ pub fn verify_attestation<'a>(
         // correct and that the public key is signed by its application signing key. This
         // duplicates validation that occurs during `Application::matches`, but ensures that
         // malformed/incomplete requests are rejected earlier and with clearer error messages.
-        let cwt = CoseSign1::from_slice(public_key)
-            .map_err(anyhow::Error::msg)
-            .context("invalid public key")?;
-        if cwt.protected.header.alg.is_some()
-            && cwt.protected.header.alg
-                != Some(coset::Algorithm::Assigned(coset::iana::Algorithm::ES256))
-        {
-            return Err(anyhow::anyhow!(
-                "unsupported public key algorithm: {:?}",
-                cwt.protected.header.alg.unwrap()
-            ));
-        }
+        let cwt = parse_public_key_cwt(public_key)?;
         let extracted_evidence = verify_dice_chain(evidence).context("invalid DICE chain")?;
-        let verifying_key =
-            VerifyingKey::from_sec1_bytes(&extracted_evidence.signing_public_key)
-                .map_err(|err| anyhow::anyhow!("invalid application signing key: {:?}", err))?;
-        cwt.verify_signature(b"", |signature, message| {
-            verifying_key.verify(message, &Signature::from_slice(signature)?)
-        })
-        .map_err(anyhow::Error::msg)
-        .context("invalid public key signature")?;
-
-        // Extract the config properties. A missing claim results in config_properties = None,
-        // which is not an error.
-        config_properties = ClaimsSet::from_slice(cwt.payload.as_deref().unwrap_or_default())
-            .map_err(anyhow::Error::msg)
-            .and_then(|claims| {
-                claims
-                    .rest
-                    .into_iter()
-                    .find(|(name, _)| name == &ClaimName::PrivateUse(CONFIG_PROPERTIES_CLAIM))
-                    .map(|(_, value)| {
-                        value
-                            .into_bytes()
-                            .map_err(|err| anyhow::anyhow!("{:?}", err))
-                            .and_then(|b| Struct::decode(b.as_slice()).map_err(anyhow::Error::msg))
-                    })
-                    .transpose()
-            })
-            .context("failed to decode config properties claim")?;
+        verify_public_key_signature(&cwt, &extracted_evidence.signing_public_key)?;
+        config_properties = extract_config_properties(&cwt)?;
     }
 
     Ok((
@@ -214,11 +233,171 @@ pub fn verify_attestation<'a>(
             evidence,
             endorsements,
             config_properties,
+            ..Default::default()
         },
         cfc_crypto::extract_key_from_cwt(public_key).context("invalid public key")?,
     ))
 }
 
+/// Parses the CWT containing the public key, rejecting unsupported signature algorithms.
+fn parse_public_key_cwt(public_key: &[u8]) -> anyhow::Result<CoseSign1> {
+    let cwt = CoseSign1::from_slice(public_key)
+        .map_err(anyhow::Error::msg)
+        .context("invalid public key")?;
+    if cwt.protected.header.alg.is_some()
+        && cwt.protected.header.alg
+            != Some(coset::Algorithm::Assigned(coset::iana::Algorithm::ES256))
+    {
+        return Err(anyhow::anyhow!(
+            "unsupported public key algorithm: {:?}",
+            cwt.protected.header.alg.unwrap()
+        ));
+    }
+    Ok(cwt)
+}
+
+/// Verifies that the CWT containing the public key is signed by the SEC1-encoded P-256 signing
+/// key.
+fn verify_public_key_signature(cwt: &CoseSign1, signing_public_key: &[u8]) -> anyhow::Result<()> {
+    let verifying_key = VerifyingKey::from_sec1_bytes(signing_public_key)
+        .map_err(|err| anyhow::anyhow!("invalid application signing key: {:?}", err))?;
+    cwt.verify_signature(b"", |signature, message| {
+        verifying_key.verify(message, &Signature::from_slice(signature)?)
+    })
+    .map_err(anyhow::Error::msg)
+    .context("invalid public key signature")
+}
+
+/// Extracts the config properties from the CWT containing the public key. A missing claim
+/// results in None, which is not an error.
+fn extract_config_properties(cwt: &CoseSign1) -> anyhow::Result<Option<Struct>> {
+    ClaimsSet::from_slice(cwt.payload.as_deref().unwrap_or_default())
+        .map_err(anyhow::Error::msg)
+        .and_then(|claims| {
+            claims
+                .rest
+                .into_iter()
+                .find(|(name, _)| name == &ClaimName::PrivateUse(CONFIG_PROPERTIES_CLAIM))
+                .map(|(_, value)| {
+                    value
+                        .into_bytes()
+                        .map_err(|err| anyhow::anyhow!("{:?}", err))
+                        .and_then(|b| Struct::decode(b.as_slice()).map_err(anyhow::Error::msg))
+                })
+                .transpose()
+        })
+        .context("failed to decode config properties claim")
+}
+
+/// A RecipientVerifier verifies the identity of the recipients requesting access to the blobs.
+/// The ledger uses a single verifier, selected by the deployment configuration, so that
+/// recipients running on different confidential computing stacks can be authorized.
+pub trait RecipientVerifier {
+    /// Verifies the recipient's public key and returns an Application describing the recipient
+    /// along with the key the blob should be re-encrypted with.
+    ///
+    /// Note that even if the verification succeeds, the Application still needs to be matched
+    /// against the access policy.
+    fn verify<'a>(
+        &self,
+        public_key: &[u8],
+        evidence: Option<&'a Evidence>,
+        endorsements: Option<&'a Endorsements>,
+        tag: &'a str,
+    ) -> anyhow::Result<(Application<'a>, CoseKey)>;
+}
+
+/// Verifies the Oak attestation evidence of the recipients, see `verify_attestation`.
+#[derive(Default)]
+pub struct OakRecipientVerifier;
+
+impl RecipientVerifier for OakRecipientVerifier {
+    fn verify<'a>(
+        &self,
+        public_key: &[u8],
+        evidence: Option<&'a Evidence>,
+        endorsements: Option<&'a Endorsements>,
+        tag: &'a str,
+    ) -> anyhow::Result<(Application<'a>, CoseKey)> {
+        verify_attestation(public_key, evidence, endorsements, tag)
+    }
+}
+
+/// Verifies that the recipients' public keys are signed by one of the pinned signing keys, for
+/// confidential workloads that don't produce Oak evidence. Any evidence and endorsements are
+/// ignored, so access policies that require reference values never match; policies should
+/// instead match the `signing_public_keys` of the recipients.
+pub struct PinnedKeyRecipientVerifier {
+    signing_public_keys: Vec<Vec<u8>>,
+}
+
+impl PinnedKeyRecipientVerifier {
+    /// Creates a verifier that trusts the SEC1-encoded P-256 signing keys.
+    pub fn new(signing_public_keys: Vec<Vec<u8>>) -> anyhow::Result<Self> {
+        if signing_public_keys.is_empty() {
+            return Err(anyhow::anyhow!("no signing keys are pinned"));
+        }
+        for signing_public_key in &signing_public_keys {
+            VerifyingKey::from_sec1_bytes(signing_public_key)
+                .map_err(|err| anyhow::anyhow!("invalid pinned signing key: {:?}", err))?;
+        }
+        Ok(Self {
+            signing_public_keys,
+        })
+    }
+}
+
+impl RecipientVerifier for PinnedKeyRecipientVerifier {
+    fn verify<'a>(
+        &self,
+        public_key: &[u8],
+        _evidence: Option<&'a Evidence>,
+        _endorsements: Option<&'a Endorsements>,
+        tag: &'a str,
+    ) -> anyhow::Result<(Application<'a>, CoseKey)> {
+        let cwt = parse_public_key_cwt(public_key)?;
+        let signing_public_key = self
+            .signing_public_keys
+            .iter()
+            .find(|key| verify_public_key_signature(&cwt, key).is_ok())
+            .ok_or_else(|| anyhow::anyhow!("public key is not signed by a pinned signing key"))?;
+        Ok((
+            Application {
+                tag,
+                config_properties: extract_config_properties(&cwt)?,
+                signing_public_key: Some(signing_public_key.clone()),
+                ..Default::default()
+            },
+            cfc_crypto::extract_key_from_cwt(public_key).context("invalid public key")?,
+        ))
+    }
+}
+
+/// Returns the SHA2-256 digest of the application binary, or of the container bundle for Oak
+/// Containers, recorded in the evidence.
+fn extract_binary_sha256(evidence: &ExtractedEvidence) -> Option<Vec<u8>> {
+    let digest = match evidence.evidence_values.as_ref()? {
+        EvidenceValues::OakRestrictedKernel(values) => {
+            values.application_layer.as_ref()?.binary.as_ref()?
+        }
+        EvidenceValues::OakContainers(values) => {
+            values.container_layer.as_ref()?.bundle.as_ref()?
+        }
+        _ => return None,
+    };
+    Some(digest.sha2_256.clone()).filter(|digest| !digest.is_empty())
+}
+
+/// Returns the SEV-SNP firmware security version of the current TCB recorded in the evidence.
+fn extract_security_version(evidence: &ExtractedEvidence) -> Option<u32> {
+    let root_layer: &RootLayerData = match evidence.evidence_values.as_ref()? {
+        EvidenceValues::OakRestrictedKernel(values) => values.root_layer.as_ref()?,
+        EvidenceValues::OakContainers(values) => values.root_layer.as_ref()?,
+        _ => return None,
+    };
+    Some(root_layer.amd_sev.as_ref()?.current_tcb.as_ref()?.snp)
+}
+
 /// Helper function that returns a test Evidence message.
 #[cfg(any(test, feature = "testing"))]
 pub fn get_test_evidence() -> Evidence {
@@ -388,6 +567,75 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_application_matches_measurements() {
+        let app = Application {
+            binary_sha256: Some(b"binary".to_vec()),
+            signing_public_key: Some(b"signer".to_vec()),
+            security_version: Some(8),
+            ..Default::default()
+        };
+        assert!(app.matches(
+            &Some(ApplicationMatcher {
+                binary_sha256_digests: vec![b"other".to_vec(), b"binary".to_vec()],
+                signing_public_keys: vec![b"signer".to_vec()],
+                min_security_version: Some(8),
+                ..Default::default()
+            }),
+            Duration::default()
+        ));
+        assert!(!app.matches(
+            &Some(ApplicationMatcher {
+                binary_sha256_digests: vec![b"other".to_vec()],
+                ..Default::default()
+            }),
+            Duration::default()
+        ));
+        assert!(!app.matches(
+            &Some(ApplicationMatcher {
+                signing_public_keys: vec![b"other".to_vec()],
+                ..Default::default()
+            }),
+            Duration::default()
+        ));
+        assert!(!app.matches(
+            &Some(ApplicationMatcher {
+                min_security_version: Some(9),
+                ..Default::default()
+            }),
+            Duration::default()
+        ));
+
+        // All matchers must match.
+        assert!(!app.matches(
+            &Some(ApplicationMatcher {
+                binary_sha256_digests: vec![b"binary".to_vec()],
+                signing_public_keys: vec![b"signer".to_vec()],
+                min_security_version: Some(9),
+                ..Default::default()
+            }),
+            Duration::default()
+        ));
+
+        // An application without attested values only matches if the checks are skipped.
+        let app = Application::default();
+        assert!(app.matches(&Some(ApplicationMatcher::default()), Duration::default()));
+        assert!(!app.matches(
+            &Some(ApplicationMatcher {
+                binary_sha256_digests: vec![b"binary".to_vec()],
+                ..Default::default()
+            }),
+            Duration::default()
+        ));
+        assert!(!app.matches(
+            &Some(ApplicationMatcher {
+                min_security_version: Some(0),
+                ..Default::default()
+            }),
+            Duration::default()
+        ));
+    }
+
     #[test]
     fn test_application_matches_attestation() {
         let evidence = get_test_evidence();
@@ -434,6 +682,56 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_application_matches_verified_evidence() -> anyhow::Result<()> {
+        let (cwt, _) = create_public_key(None);
+        let evidence = get_test_evidence();
+        let endorsements = get_test_endorsements();
+        let (app, _) = verify_attestation(&cwt, Some(&evidence), Some(&endorsements), "tag")?;
+        let signing_public_key = verify_dice_chain(&evidence)?.signing_public_key;
+
+        // The signing key recorded in the evidence is only trusted once the evidence has been
+        // verified against the reference values.
+        assert!(!app.matches(
+            &Some(ApplicationMatcher {
+                signing_public_keys: vec![signing_public_key.clone()],
+                ..Default::default()
+            }),
+            Duration::default()
+        ));
+        assert!(app.matches(
+            &Some(ApplicationMatcher {
+                reference_values: Some(get_test_reference_values()),
+                signing_public_keys: vec![signing_public_key.clone()],
+                ..Default::default()
+            }),
+            Duration::default()
+        ));
+        assert!(!app.matches(
+            &Some(ApplicationMatcher {
+                reference_values: Some(get_test_reference_values()),
+                signing_public_keys: vec![b"other".to_vec()],
+                ..Default::default()
+            }),
+            Duration::default()
+        ));
+
+        // Values established by the verifier can't substitute for the verified evidence.
+        let app = Application {
+            signing_public_key: Some(signing_public_key.clone()),
+            ..Default::default()
+        };
+        assert!(!app.matches(
+            &Some(ApplicationMatcher {
+                reference_values: Some(get_test_reference_values()),
+                signing_public_keys: vec![signing_public_key],
+                ..Default::default()
+            }),
+            Duration::default()
+        ));
+        anyhow::Ok(())
+    }
+
     #[test]
     fn test_application_matches_config_properties() {
         let app = Application {
@@ -521,6 +819,11 @@ mod tests {
         assert_eq!(app.evidence, Some(&evidence));
         assert_eq!(app.endorsements, Some(&endorsements));
         assert_eq!(app.config_properties, Some(config_properties));
+        // The values recorded in the evidence are only trusted once the evidence has been
+        // matched against reference values.
+        assert_eq!(app.binary_sha256, None);
+        assert_eq!(app.signing_public_key, None);
+        assert_eq!(app.security_version, None);
         assert_eq!(key, cose_key);
         anyhow::Ok(())
     }
@@ -533,6 +836,9 @@ mod tests {
         assert_eq!(app.tag, tag);
         assert_eq!(app.evidence, None);
         assert_eq!(app.endorsements, None);
+        assert_eq!(app.binary_sha256, None);
+        assert_eq!(app.signing_public_key, None);
+        assert_eq!(app.security_version, None);
         assert_eq!(key, cose_key);
         anyhow::Ok(())
     }
@@ -594,6 +900,89 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_oak_recipient_verifier() -> anyhow::Result<()> {
+        let (cwt, cose_key) = create_public_key(None);
+        let evidence = get_test_evidence();
+        let (app, key) = OakRecipientVerifier.verify(&cwt, Some(&evidence), None, "tag")?;
+        assert_eq!(app.evidence, Some(&evidence));
+        assert_eq!(app.signing_public_key, None);
+        assert_eq!(key, cose_key);
+        anyhow::Ok(())
+    }
+
+    #[test]
+    fn test_pinned_key_recipient_verifier() -> anyhow::Result<()> {
+        let config_properties = Struct {
+            fields: BTreeMap::from([(
+                "x".into(),
+                prost_types::Value {
+                    kind: Some(ValueKind::NumberValue(1.0)),
+                },
+            )]),
+        };
+        let (cwt, cose_key) = create_public_key(Some(&config_properties));
+        let signing_public_key = verify_dice_chain(&get_test_evidence())?.signing_public_key;
+        let other_signing_public_key = VerifyingKey::from_affine(p256::AffinePoint::GENERATOR)
+            .unwrap()
+            .to_encoded_point(false)
+            .as_bytes()
+            .to_vec();
+        let verifier = PinnedKeyRecipientVerifier::new(vec![
+            other_signing_public_key.clone(),
+            signing_public_key.clone(),
+        ])?;
+
+        // The evidence is ignored.
+        let evidence = get_test_evidence();
+        let (app, key) = verifier.verify(&cwt, Some(&evidence), None, "tag")?;
+        assert_eq!(app.tag, "tag");
+        assert_eq!(app.evidence, None);
+        assert_eq!(app.endorsements, None);
+        assert_eq!(app.config_properties, Some(config_properties));
+        assert_eq!(app.binary_sha256, None);
+        assert_eq!(app.signing_public_key, Some(signing_public_key));
+        assert_eq!(app.security_version, None);
+        assert_eq!(key, cose_key);
+
+        // Public keys signed by other keys are rejected.
+        let verifier = PinnedKeyRecipientVerifier::new(vec![other_signing_public_key])?;
+        assert_that!(
+            verifier.verify(&cwt, None, None, "tag"),
+            err(displays_as(contains_substring(
+                "public key is not signed by a pinned signing key"
+            )))
+        );
+        anyhow::Ok(())
+    }
+
+    #[test]
+    fn test_pinned_key_recipient_verifier_invalid_keys() {
+        assert_that!(
+            PinnedKeyRecipientVerifier::new(vec![]),
+            err(displays_as(contains_substring(
+                "no signing keys are pinned"
+            )))
+        );
+        assert_that!(
+            PinnedKeyRecipientVerifier::new(vec![b"invalid".to_vec()]),
+            err(displays_as(contains_substring(
+                "invalid pinned signing key"
+            )))
+        );
+
+        let verifier = PinnedKeyRecipientVerifier::new(vec![
+            verify_dice_chain(&get_test_evidence())
+                .unwrap()
+                .signing_public_key,
+        ])
+        .unwrap();
+        assert_that!(
+            verifier.verify(b"invalid", None, None, "tag"),
+            err(displays_as(contains_substring("invalid public key")))
+        );
+    }
+
     #[test]
     fn test_struct_value_matches() {
         let value = Struct {
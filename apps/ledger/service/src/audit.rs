@@ -0,0 +1,364 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate alloc;
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use prost::Message;
+use sha2::{Digest, Sha256};
+
+use crate::ledger::service::{
+    AuditLogCheckpoint, AuditLogEntry, QueryAuditLogRequest, QueryAuditLogResponse,
+};
+
+/// The number of entries returned when the request doesn't specify the page size.
+const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// The maximum number of entries returned in a single page.
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// The number of entries retained when the retention isn't configured.
+const DEFAULT_RETAINED_ENTRIES: usize = 100_000;
+
+/// An AuditLog is the append-only record of the accesses authorized by the ledger and of the
+/// revocations and key deletions. Entries are appended when the events are applied, so the log
+/// is replicated along with the rest of the ledger state. Each entry is chained to the previous
+/// one by its SHA-256 digest. Only the most recent entries are retained, the older ones are
+/// compacted into the checkpoint that carries the digest of the last compacted entry so that
+/// the chain of the retained entries can still be verified.
+pub struct AuditLog {
+    entries: VecDeque<AuditLogEntry>,
+    checkpoint: AuditLogCheckpoint,
+    retained_entries: usize,
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            checkpoint: AuditLogCheckpoint::default(),
+            retained_entries: DEFAULT_RETAINED_ENTRIES,
+        }
+    }
+}
+
+impl AuditLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of the most recent entries that are retained. Every replica must be
+    /// configured with the same retention so that the entries are compacted identically.
+    pub fn set_retained_entries(&mut self, retained_entries: usize) {
+        self.retained_entries = retained_entries.max(1);
+        self.compact();
+    }
+
+    /// Appends the entry, assigning its sequence number and digest, and compacts the entries
+    /// that are no longer retained.
+    pub fn append(&mut self, mut entry: AuditLogEntry) {
+        entry.sequence_number = self.next_sequence_number();
+        entry.entry_sha256 = Self::compute_entry_sha256(self.last_entry_sha256(), &entry);
+        self.entries.push_back(entry);
+        self.compact();
+    }
+
+    fn compact(&mut self) {
+        while self.entries.len() > self.retained_entries {
+            let entry = self.entries.pop_front().unwrap();
+            self.checkpoint = AuditLogCheckpoint {
+                sequence_number: entry.sequence_number + 1,
+                entry_sha256: entry.entry_sha256,
+            };
+        }
+    }
+
+    fn next_sequence_number(&self) -> u64 {
+        self.checkpoint.sequence_number + self.entries.len() as u64
+    }
+
+    /// Returns the digest of the last entry, the one of the last compacted entry if no entries
+    /// are retained, and empty if the log is empty.
+    fn last_entry_sha256(&self) -> &[u8] {
+        self.entries
+            .back()
+            .map_or(&self.checkpoint.entry_sha256, |entry| &entry.entry_sha256)
+    }
+
+    /// Computes the digest of the entry chained to the digest of the previous entry.
+    pub fn compute_entry_sha256(previous_entry_sha256: &[u8], entry: &AuditLogEntry) -> Vec<u8> {
+        let entry = AuditLogEntry {
+            entry_sha256: Vec::new(),
+            ..entry.clone()
+        };
+        Sha256::new()
+            .chain_update(previous_entry_sha256)
+            .chain_update(entry.encode_to_vec())
+            .finalize()
+            .to_vec()
+    }
+
+    /// Returns the page of entries starting at the requested sequence number, or at the oldest
+    /// retained entry if the requested entries have been compacted.
+    pub fn query(&self, request: &QueryAuditLogRequest) -> QueryAuditLogResponse {
+        let page_size = match request.page_size {
+            0 => DEFAULT_PAGE_SIZE,
+            page_size => (page_size as usize).min(MAX_PAGE_SIZE),
+        };
+        let start = usize::try_from(
+            request
+                .start_sequence_number
+                .saturating_sub(self.checkpoint.sequence_number),
+        )
+        .unwrap_or(usize::MAX)
+        .min(self.entries.len());
+        let end = start.saturating_add(page_size).min(self.entries.len());
+
+        QueryAuditLogResponse {
+            entries: self.entries.range(start..end).cloned().collect(),
+            previous_entry_sha256: match start {
+                0 => self.checkpoint.entry_sha256.clone(),
+                _ => self.entries[start - 1].entry_sha256.clone(),
+            },
+            next_sequence_number: (end < self.entries.len())
+                .then(|| self.checkpoint.sequence_number + end as u64),
+            first_sequence_number: self.checkpoint.sequence_number,
+        }
+    }
+
+    /// Returns the checkpoint of the compacted entries, if any, and the retained entries.
+    pub fn save_snapshot(&self) -> (Option<AuditLogCheckpoint>, Vec<AuditLogEntry>) {
+        let checkpoint =
+            (self.checkpoint != AuditLogCheckpoint::default()).then(|| self.checkpoint.clone());
+        (checkpoint, self.entries.iter().cloned().collect())
+    }
+
+    pub fn load_snapshot(
+        &mut self,
+        checkpoint: Option<AuditLogCheckpoint>,
+        entries: Vec<AuditLogEntry>,
+    ) -> Result<(), micro_rpc::Status> {
+        let mut audit_log = AuditLog {
+            checkpoint: checkpoint.unwrap_or_default(),
+            retained_entries: self.retained_entries,
+            ..Default::default()
+        };
+        for entry in entries {
+            if entry.sequence_number != audit_log.next_sequence_number()
+                || entry.entry_sha256
+                    != Self::compute_entry_sha256(audit_log.last_entry_sha256(), &entry)
+            {
+                return Err(micro_rpc::Status::new_with_message(
+                    micro_rpc::StatusCode::InvalidArgument,
+                    "Audit log in the snapshot is corrupted",
+                ));
+            }
+            audit_log.entries.push_back(entry);
+        }
+        audit_log.compact();
+        *self = audit_log;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::assert_err;
+    use alloc::vec;
+
+    fn create_entry(blob_id: &[u8]) -> AuditLogEntry {
+        AuditLogEntry {
+            blob_id: blob_id.to_vec(),
+            recipient_tag: "tag".into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_append() {
+        let mut audit_log = AuditLog::new();
+        audit_log.append(create_entry(b"blob1"));
+        audit_log.append(create_entry(b"blob2"));
+
+        let (_, entries) = audit_log.save_snapshot();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| entry.sequence_number)
+                .collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+        assert_eq!(
+            entries[0].entry_sha256,
+            AuditLog::compute_entry_sha256(&[], &entries[0])
+        );
+        assert_eq!(
+            entries[1].entry_sha256,
+            AuditLog::compute_entry_sha256(&entries[0].entry_sha256, &entries[1])
+        );
+        // Identical entries have different digests at different positions.
+        audit_log.append(create_entry(b"blob2"));
+        assert_ne!(
+            audit_log.save_snapshot().1[2].entry_sha256,
+            entries[1].entry_sha256
+        );
+    }
+
+    #[test]
+    fn test_query() {
+        let mut audit_log = AuditLog::new();
+        for i in 0..5u8 {
+            audit_log.append(create_entry(&[i]));
+        }
+        let (_, entries) = audit_log.save_snapshot();
+
+        assert_eq!(
+            audit_log.query(&QueryAuditLogRequest {
+                start_sequence_number: 0,
+                page_size: 2,
+            }),
+            QueryAuditLogResponse {
+                entries: entries[0..2].to_vec(),
+                previous_entry_sha256: vec![],
+                next_sequence_number: Some(2),
+                first_sequence_number: 0,
+            }
+        );
+        assert_eq!(
+            audit_log.query(&QueryAuditLogRequest {
+                start_sequence_number: 2,
+                page_size: 0,
+            }),
+            QueryAuditLogResponse {
+                entries: entries[2..5].to_vec(),
+                previous_entry_sha256: entries[1].entry_sha256.clone(),
+                next_sequence_number: None,
+                first_sequence_number: 0,
+            }
+        );
+        assert_eq!(
+            audit_log.query(&QueryAuditLogRequest {
+                start_sequence_number: 10,
+                page_size: 2,
+            }),
+            QueryAuditLogResponse {
+                entries: vec![],
+                previous_entry_sha256: entries[4].entry_sha256.clone(),
+                next_sequence_number: None,
+                first_sequence_number: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_load_snapshot() {
+        let mut audit_log = AuditLog::new();
+        audit_log.append(create_entry(b"blob1"));
+        audit_log.append(create_entry(b"blob2"));
+        let (_, entries) = audit_log.save_snapshot();
+
+        let mut loaded = AuditLog::new();
+        assert_eq!(loaded.load_snapshot(None, entries.clone()), Ok(()));
+        assert_eq!(loaded.save_snapshot(), (None, entries.clone()));
+
+        // Altered, removed or reordered entries are detected.
+        let mut altered = entries.clone();
+        altered[0].blob_id = b"blob3".to_vec();
+        assert_err!(
+            loaded.load_snapshot(None, altered),
+            micro_rpc::StatusCode::InvalidArgument,
+            "Audit log in the snapshot is corrupted"
+        );
+        assert_err!(
+            loaded.load_snapshot(None, entries[1..].to_vec()),
+            micro_rpc::StatusCode::InvalidArgument,
+            "Audit log in the snapshot is corrupted"
+        );
+        assert_err!(
+            loaded.load_snapshot(None, vec![entries[1].clone(), entries[0].clone()]),
+            micro_rpc::StatusCode::InvalidArgument,
+            "Audit log in the snapshot is corrupted"
+        );
+        // The state is left unchanged after a failure.
+        assert_eq!(loaded.save_snapshot(), (None, entries.clone()));
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut audit_log = AuditLog::new();
+        for i in 0..5u8 {
+            audit_log.append(create_entry(&[i]));
+        }
+        let (_, entries) = audit_log.save_snapshot();
+
+        // The oldest entries are compacted into the checkpoint.
+        audit_log.set_retained_entries(3);
+        let checkpoint = AuditLogCheckpoint {
+            sequence_number: 2,
+            entry_sha256: entries[1].entry_sha256.clone(),
+        };
+        assert_eq!(
+            audit_log.save_snapshot(),
+            (Some(checkpoint), entries[2..].to_vec())
+        );
+        audit_log.append(create_entry(&[5]));
+        let (_, compacted_entries) = audit_log.save_snapshot();
+        assert_eq!(
+            compacted_entries
+                .iter()
+                .map(|entry| entry.sequence_number)
+                .collect::<Vec<_>>(),
+            vec![3, 4, 5]
+        );
+        assert_eq!(
+            compacted_entries[2].entry_sha256,
+            AuditLog::compute_entry_sha256(&entries[4].entry_sha256, &compacted_entries[2])
+        );
+
+        // Queries of the compacted entries start at the oldest retained entry, chained to the
+        // digest of the last compacted entry.
+        assert_eq!(
+            audit_log.query(&QueryAuditLogRequest {
+                start_sequence_number: 0,
+                page_size: 2,
+            }),
+            QueryAuditLogResponse {
+                entries: compacted_entries[0..2].to_vec(),
+                previous_entry_sha256: entries[2].entry_sha256.clone(),
+                next_sequence_number: Some(5),
+                first_sequence_number: 3,
+            }
+        );
+
+        // The chain of the retained entries is verified from the checkpoint.
+        let (checkpoint, retained_entries) = audit_log.save_snapshot();
+        let mut loaded = AuditLog::new();
+        assert_eq!(
+            loaded.load_snapshot(checkpoint.clone(), retained_entries.clone()),
+            Ok(())
+        );
+        assert_eq!(
+            loaded.save_snapshot(),
+            (checkpoint, retained_entries.clone())
+        );
+        assert_err!(
+            loaded.load_snapshot(None, retained_entries),
+            micro_rpc::StatusCode::InvalidArgument,
+            "Audit log in the snapshot is corrupted"
+        );
+    }
+}
@@ -17,20 +17,46 @@ extern crate alloc;
 use crate::attestation::Application;
 use alloc::{
     collections::{BTreeMap, BTreeSet},
+    format,
     vec::Vec,
 };
-use core::time::Duration;
+use core::{ops::RangeInclusive, time::Duration};
 
-use crate::ledger::service::{BlobBudgetSnapshot, BudgetSnapshot, PerPolicyBudgetSnapshot};
+use crate::ledger::service::{
+    BlobBudgetSnapshot, BlobExpirationSnapshot, BudgetSnapshot, PerPolicyBudgetSnapshot,
+    RevokedTransformsSnapshot,
+};
 use federated_compute::proto::{
-    access_budget::Kind as AccessBudgetKind, AccessBudget, DataAccessPolicy,
+    access_budget::Kind as AccessBudgetKind, data_access_policy::Transform, AccessBudget,
+    DataAccessPolicy,
 };
 
+/// Version of the BudgetSnapshot format saved by the tracker. It must be incremented whenever
+/// the format changes, including when fields are added. Version 2 added the nodes of the
+/// budgets of derived blobs.
+pub const BUDGET_SNAPSHOT_VERSION: u32 = 2;
+
+/// The oldest version of the BudgetSnapshot format that can load the snapshots saved by the
+/// tracker. Older trackers ignore the fields unknown to them, so this only needs to be raised
+/// when a new field can't be ignored without misrepresenting the budgets, which prevents the
+/// older replicas from loading such snapshots during a rolling upgrade.
+pub const BUDGET_SNAPSHOT_MIN_COMPATIBLE_VERSION: u32 = 1;
+
+/// The oldest version of the BudgetSnapshot format that can load snapshots tracking the budgets
+/// of derived blobs. Older trackers would merge the budgets of a blob at different nodes.
+const NODE_BUDGETS_MIN_COMPATIBLE_VERSION: u32 = 2;
+
+/// Budgets of the blobs keyed by blob id and the node of the policy graph the blob is at, so
+/// that each edge of a pipeline has its own budget even if the blob ids of the stages collide.
+type BlobBudgets = BTreeMap<(Vec<u8>, u32), BlobBudget>;
+
 /// The remaining privacy budget for an individual blob.
 #[derive(Default)]
 struct BlobBudget {
     transform_access_budgets: Vec<u32>,
     shared_access_budgets: Vec<u32>,
+    /// Indices of the policy-wide budgets the blob has been charged against.
+    charged_policy_access_budgets: Vec<u32>,
 }
 
 impl BlobBudget {
@@ -42,7 +68,7 @@ impl BlobBudget {
                     kind: Some(AccessBudgetKind::Times(n)),
                     ..
                 }) => n,
-                Some(AccessBudget { kind: None }) => 0,
+                Some(AccessBudget { kind: None, .. }) => 0,
                 None => 0,
             })
         }
@@ -58,7 +84,24 @@ impl BlobBudget {
         Self {
             transform_access_budgets,
             shared_access_budgets,
+            charged_policy_access_budgets: Vec::new(),
+        }
+    }
+
+    /// Returns the indices of the policy-wide budgets the transform is subject to that
+    /// haven't been charged for this blob yet.
+    fn uncharged_policy_access_budgets(
+        &self,
+        transform_index: usize,
+        policy: &DataAccessPolicy,
+    ) -> Vec<u32> {
+        let mut indices = Vec::new();
+        for &index in &policy.transforms[transform_index].policy_access_budget_indices {
+            if !self.charged_policy_access_budgets.contains(&index) && !indices.contains(&index) {
+                indices.push(index);
+            }
         }
+        indices
     }
 
     /// Returns whether another access is allowed.
@@ -96,16 +139,21 @@ impl BlobBudget {
         }
     }
 
-    /// Updates the budget to record an access.
+    /// Updates the budget to record an access. The budget is left untouched if the access
+    /// isn't allowed by any of the budgets the transform is subject to.
     pub fn record_access(
         &mut self,
         transform_index: usize,
         policy: &DataAccessPolicy,
     ) -> Result<(), micro_rpc::Status> {
+        // Update copies of the budgets so that the access is recorded either in all of them or
+        // in none.
+        let mut transform_access_budgets = self.transform_access_budgets.clone();
+        let mut shared_access_budgets = self.shared_access_budgets.clone();
         let transform = &policy.transforms[transform_index];
         if let Some(ref access_budget) = &transform.access_budget {
             Self::update_remaining_budget(
-                &mut self.transform_access_budgets,
+                &mut transform_access_budgets,
                 transform_index,
                 access_budget,
             )?;
@@ -122,12 +170,10 @@ impl BlobBudget {
                             "AccessPolicy is invalid",
                         )
                     })?;
-            Self::update_remaining_budget(
-                &mut self.shared_access_budgets,
-                shared_index,
-                access_budget,
-            )?;
+            Self::update_remaining_budget(&mut shared_access_budgets, shared_index, access_budget)?;
         }
+        self.transform_access_budgets = transform_access_budgets;
+        self.shared_access_budgets = shared_access_budgets;
         Ok(())
     }
 
@@ -152,13 +198,27 @@ impl BlobBudget {
     }
 }
 
+/// Budget state of a blob moved out of a BudgetTracker, see `BudgetTracker::take_blob`.
+pub struct BlobBudgetTransfer {
+    budgets: Vec<(u32, BlobBudget)>,
+    policy_access_budgets: Option<Vec<u32>>,
+    revoked_transforms: Option<BTreeSet<u32>>,
+}
+
 /// A BudgetTracker keeps track of the remaining budgets for zero or more blobs.
 #[derive(Default)]
 pub struct BudgetTracker {
-    /// Budgets keyed by policy hash and blob id.
-    budgets: BTreeMap<Vec<u8>, BTreeMap<Vec<u8>, BlobBudget>>,
+    /// Budgets keyed by policy hash, then by blob id and node.
+    budgets: BTreeMap<Vec<u8>, BlobBudgets>,
     /// Blob ids whose budgets have been consumed.
     consumed_budgets: BTreeSet<Vec<u8>>,
+    /// Remaining policy-wide budgets keyed by policy hash.
+    policy_access_budgets: BTreeMap<Vec<u8>, Vec<u32>>,
+    /// Expiration times of blobs, ordered by time so that the expired blobs can be found
+    /// without visiting all budgets.
+    expirations: BTreeSet<(Duration, Vec<u8>)>,
+    /// Indices of the transforms through which access has been revoked, keyed by blob id.
+    revoked_transforms: BTreeMap<Vec<u8>, BTreeSet<u32>>,
 }
 
 impl BudgetTracker {
@@ -166,6 +226,66 @@ impl BudgetTracker {
         Self::default()
     }
 
+    /// Returns the range of the keys of the budgets of the blob at all nodes.
+    fn blob_budget_keys(blob_id: &[u8]) -> RangeInclusive<(Vec<u8>, u32)> {
+        (blob_id.to_vec(), 0)..=(blob_id.to_vec(), u32::MAX)
+    }
+
+    /// Removes the budgets of the blob at all nodes, returning them keyed by node.
+    fn remove_blob_budgets(budgets: &mut BlobBudgets, blob_id: &[u8]) -> Vec<(u32, BlobBudget)> {
+        let keys: Vec<_> = budgets
+            .range(Self::blob_budget_keys(blob_id))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.into_iter()
+            .filter_map(|key| {
+                let node_id = key.1;
+                budgets.remove(&key).map(|budget| (node_id, budget))
+            })
+            .collect()
+    }
+
+    /// Returns the initial policy-wide budgets for the policy.
+    fn new_policy_access_budgets(policy: &DataAccessPolicy) -> Vec<u32> {
+        policy
+            .policy_access_budgets
+            .iter()
+            .map(|access_budget| match access_budget.kind {
+                Some(AccessBudgetKind::Times(n)) => n,
+                None => 0,
+            })
+            .collect()
+    }
+
+    /// Returns whether the policy-wide budgets allow the blob with the given budget to be
+    /// accessed through the transform.
+    fn policy_allows_access(
+        &self,
+        budget: &BlobBudget,
+        transform_index: usize,
+        policy: &DataAccessPolicy,
+        policy_hash: &[u8],
+    ) -> bool {
+        let mut owned_budgets = None;
+        let policy_budgets = self
+            .policy_access_budgets
+            .get(policy_hash)
+            .unwrap_or_else(|| owned_budgets.insert(Self::new_policy_access_budgets(policy)));
+        budget
+            .uncharged_policy_access_budgets(transform_index, policy)
+            .into_iter()
+            .all(
+                |index| match policy.policy_access_budgets.get(index as usize) {
+                    Some(access_budget) => BlobBudget::has_remaining_budget(
+                        policy_budgets,
+                        index as usize,
+                        access_budget,
+                    ),
+                    None => false,
+                },
+            )
+    }
+
     /// Finds the first matching transform in the policy that has sufficient budget available.
     ///
     /// The `policy_hash` is used as a concise, stable identifier for the policy; it's the caller's
@@ -178,6 +298,23 @@ impl BudgetTracker {
         policy_hash: &[u8],
         app: &Application,
         now: Duration,
+    ) -> Result<usize, micro_rpc::Status> {
+        self.find_matching_transform_with(blob_id, node_id, policy, policy_hash, now, &|i| {
+            app.matches(&policy.transforms[i].application, now)
+        })
+    }
+
+    /// Same as `find_matching_transform`, but takes the predicate telling whether the
+    /// requesting application matches the transform with the given index. This allows the
+    /// application matchers to be evaluated once for many blobs subject to the same policy.
+    pub fn find_matching_transform_with(
+        &self,
+        blob_id: &[u8],
+        node_id: u32,
+        policy: &DataAccessPolicy,
+        policy_hash: &[u8],
+        now: Duration,
+        app_matches: &dyn Fn(usize) -> bool,
     ) -> Result<usize, micro_rpc::Status> {
         if self.consumed_budgets.contains(blob_id) {
             return Err(micro_rpc::Status::new_with_message(
@@ -187,35 +324,66 @@ impl BudgetTracker {
         }
 
         let mut match_found = false;
+        let mut outside_window = false;
         for (i, transform) in policy.transforms.iter().enumerate() {
-            if transform.src != node_id || !app.matches(&transform.application, now) {
+            if transform.src != node_id || !app_matches(i) {
+                continue;
+            }
+            if !Self::is_within_window(transform, now) {
+                outside_window = true;
                 continue;
             }
             match_found = true;
+            if self.is_revoked(blob_id, i) {
+                continue;
+            }
 
             let mut owned_budget = None;
             let budget = self
                 .budgets
                 .get(policy_hash)
-                .and_then(|map| map.get(blob_id))
+                .and_then(|map| map.get(&(blob_id.to_vec(), node_id)))
                 .unwrap_or_else(|| owned_budget.insert(BlobBudget::new(policy)));
-            if budget.allows_access(i, policy) {
+            if budget.allows_access(i, policy)
+                && self.policy_allows_access(budget, i, policy, policy_hash)
+            {
                 return Ok(i);
             }
         }
 
-        Err(match match_found {
-            true => micro_rpc::Status::new_with_message(
+        Err(if match_found {
+            micro_rpc::Status::new_with_message(
                 micro_rpc::StatusCode::ResourceExhausted,
                 "data access budget exhausted",
-            ),
-            false => micro_rpc::Status::new_with_message(
+            )
+        } else if outside_window {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::FailedPrecondition,
+                "access through the matching transforms is not authorized at the current time",
+            )
+        } else {
+            micro_rpc::Status::new_with_message(
                 micro_rpc::StatusCode::FailedPrecondition,
                 "requesting application does not match the access policy",
-            ),
+            )
         })
     }
 
+    /// Returns whether the current time falls within the validity window of the
+    /// transform, where `not_before` is inclusive and `not_after` is exclusive.
+    fn is_within_window(transform: &Transform, now: Duration) -> bool {
+        let now = (now.as_secs() as i64, now.subsec_nanos() as i32);
+        let after_start = transform
+            .not_before
+            .as_ref()
+            .map_or(true, |t| (t.seconds, t.nanos) <= now);
+        let before_end = transform
+            .not_after
+            .as_ref()
+            .map_or(true, |t| now < (t.seconds, t.nanos));
+        after_start && before_end
+    }
+
     /// Updates the budget for a blob to reflect a new access.
     pub fn update_budget(
         &mut self,
@@ -230,13 +398,66 @@ impl BudgetTracker {
                 "data access budget consumed",
             ));
         }
+        if self.is_revoked(blob_id, transform_index) {
+            return Err(micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::Internal,
+                "data access through the transform revoked",
+            ));
+        }
 
-        self.budgets
+        // The budgets are tracked for the node the transform reads from.
+        let node_id = policy
+            .transforms
+            .get(transform_index)
+            .ok_or_else(|| {
+                micro_rpc::Status::new_with_message(
+                    micro_rpc::StatusCode::InvalidArgument,
+                    "AccessPolicy is invalid",
+                )
+            })?
+            .src;
+        let budget = self
+            .budgets
             .entry(policy_hash.to_vec())
             .or_insert_with(BTreeMap::new)
-            .entry(blob_id.to_vec())
-            .or_insert_with(|| BlobBudget::new(policy))
-            .record_access(transform_index, policy)
+            .entry((blob_id.to_vec(), node_id))
+            .or_insert_with(|| BlobBudget::new(policy));
+        let policy_budgets = self
+            .policy_access_budgets
+            .entry(policy_hash.to_vec())
+            .or_insert_with(|| Self::new_policy_access_budgets(policy));
+
+        // Check the policy-wide budgets before recording the access so that a failure leaves
+        // all budgets untouched.
+        let uncharged_indices = budget.uncharged_policy_access_budgets(transform_index, policy);
+        for &index in &uncharged_indices {
+            let access_budget = policy
+                .policy_access_budgets
+                .get(index as usize)
+                .ok_or_else(|| {
+                    micro_rpc::Status::new_with_message(
+                        micro_rpc::StatusCode::InvalidArgument,
+                        "AccessPolicy is invalid",
+                    )
+                })?;
+            if !BlobBudget::has_remaining_budget(policy_budgets, index as usize, access_budget) {
+                return Err(micro_rpc::Status::new_with_message(
+                    micro_rpc::StatusCode::Internal,
+                    "no budget remaining or DataAccessPolicy invalid",
+                ));
+            }
+        }
+
+        budget.record_access(transform_index, policy)?;
+        for index in uncharged_indices {
+            BlobBudget::update_remaining_budget(
+                policy_budgets,
+                index as usize,
+                &policy.policy_access_budgets[index as usize],
+            )?;
+            budget.charged_policy_access_budgets.push(index);
+        }
+        Ok(())
 
         // TODO: To reduce memory overhead, consider moving the entry to `consumed_budgets` if the
         // budget has been entirely consumed.
@@ -248,24 +469,184 @@ impl BudgetTracker {
             // If the budget wasn't already consumed, remove any not-yet-consumed budgets since
             // they'll never be accessed.
             for (_, map) in self.budgets.iter_mut() {
-                map.remove(blob_id);
+                Self::remove_blob_budgets(map, blob_id);
+            }
+            self.revoked_transforms.remove(blob_id);
+        }
+    }
+
+    /// Revokes access to a blob through the transform with the given index, leaving the
+    /// budgets of the other transforms untouched.
+    pub fn revoke_transform(&mut self, blob_id: &[u8], transform_index: u32) {
+        if !self.consumed_budgets.contains(blob_id) {
+            self.revoked_transforms
+                .entry(blob_id.to_vec())
+                .or_default()
+                .insert(transform_index);
+        }
+    }
+
+    /// Returns whether access to a blob through the transform has been revoked.
+    fn is_revoked(&self, blob_id: &[u8], transform_index: usize) -> bool {
+        self.revoked_transforms.get(blob_id).is_some_and(|indices| {
+            u32::try_from(transform_index).is_ok_and(|index| indices.contains(&index))
+        })
+    }
+
+    /// Fails if access to the blob has been revoked, either entirely by consuming its budget
+    /// or through the transform with the given index. Unlike `update_budget`, this doesn't
+    /// require any budget to remain, so that previously granted accesses can be re-checked.
+    pub fn check_not_revoked(
+        &self,
+        blob_id: &[u8],
+        transform_index: usize,
+    ) -> Result<(), micro_rpc::Status> {
+        if self.consumed_budgets.contains(blob_id) {
+            return Err(micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::ResourceExhausted,
+                "data access budget consumed",
+            ));
+        }
+        if self.is_revoked(blob_id, transform_index) {
+            return Err(micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::FailedPrecondition,
+                "data access through the transform revoked",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns whether any budget state of the blob is tracked.
+    pub fn is_tracked(&self, blob_id: &[u8]) -> bool {
+        self.consumed_budgets.contains(blob_id)
+            || self.revoked_transforms.contains_key(blob_id)
+            || self
+                .budgets
+                .values()
+                .any(|map| map.range(Self::blob_budget_keys(blob_id)).next().is_some())
+    }
+
+    /// Returns whether budgets are tracked for blobs subject to the policy.
+    pub fn is_policy_tracked(&self, policy_hash: &[u8]) -> bool {
+        self.budgets.contains_key(policy_hash)
+    }
+
+    /// Moves the budget state of a blob out of this tracker so that it can be tracked by
+    /// another one with `put_blob`. The blob's budget is consumed in this tracker, so that the
+    /// remaining budget can't be spent twice. Fails if the budget has already been consumed.
+    pub fn take_blob(
+        &mut self,
+        blob_id: &[u8],
+        policy_hash: &[u8],
+    ) -> Result<BlobBudgetTransfer, micro_rpc::Status> {
+        if self.consumed_budgets.contains(blob_id) {
+            return Err(micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::ResourceExhausted,
+                "data access budget consumed",
+            ));
+        }
+        let transfer = BlobBudgetTransfer {
+            budgets: self
+                .budgets
+                .get_mut(policy_hash)
+                .map(|map| Self::remove_blob_budgets(map, blob_id))
+                .unwrap_or_default(),
+            policy_access_budgets: self.policy_access_budgets.get(policy_hash).cloned(),
+            revoked_transforms: self.revoked_transforms.remove(blob_id),
+        };
+        self.consume_budget(blob_id);
+        Ok(transfer)
+    }
+
+    /// Starts tracking the budget state of a blob moved from another tracker. The blob must not
+    /// be tracked yet. The remaining policy-wide budgets become the lower of the remaining
+    /// budgets of both trackers, so that moving blobs never increases them.
+    pub fn put_blob(&mut self, blob_id: &[u8], policy_hash: &[u8], transfer: BlobBudgetTransfer) {
+        if !transfer.budgets.is_empty() {
+            let map = self.budgets.entry(policy_hash.to_vec()).or_default();
+            for (node_id, budget) in transfer.budgets {
+                map.insert((blob_id.to_vec(), node_id), budget);
+            }
+        }
+        if let Some(transferred_budgets) = transfer.policy_access_budgets {
+            self.policy_access_budgets
+                .entry(policy_hash.to_vec())
+                .and_modify(|budgets| {
+                    for (budget, transferred) in budgets.iter_mut().zip(&transferred_budgets) {
+                        *budget = (*budget).min(*transferred);
+                    }
+                })
+                .or_insert(transferred_budgets);
+        }
+        if let Some(revoked_transforms) = transfer.revoked_transforms {
+            self.revoked_transforms
+                .insert(blob_id.to_vec(), revoked_transforms);
+        }
+    }
+
+    /// Returns the number of distinct blobs whose budgets are tracked, including the blobs
+    /// whose budgets have been consumed.
+    pub fn distinct_blob_count(&self) -> usize {
+        let mut blob_ids: BTreeSet<&[u8]> = self
+            .consumed_budgets
+            .iter()
+            .map(|blob_id| blob_id.as_slice())
+            .collect();
+        for map in self.budgets.values() {
+            blob_ids.extend(map.keys().map(|(blob_id, _)| blob_id.as_slice()));
+        }
+        blob_ids.len()
+    }
+
+    /// Records the time after which the blob may no longer be accessed. Once that time has
+    /// passed, `collect_garbage` discards the blob's budgets, so it's the caller's
+    /// responsibility to refuse access to expired blobs.
+    pub fn set_expiration(&mut self, blob_id: &[u8], expiration: Duration) {
+        self.expirations.insert((expiration, blob_id.to_vec()));
+    }
+
+    /// Discards the budgets of all blobs that have expired by `now`.
+    pub fn collect_garbage(&mut self, now: Duration) {
+        while let Some((expiration, _)) = self.expirations.first() {
+            if *expiration > now {
+                break;
+            }
+            let (_, blob_id) = self.expirations.pop_first().unwrap();
+            for (_, map) in self.budgets.iter_mut() {
+                Self::remove_blob_budgets(map, &blob_id);
             }
+            self.consumed_budgets.remove(&blob_id);
+            self.revoked_transforms.remove(&blob_id);
         }
     }
 
     pub fn save_snapshot(&self) -> BudgetSnapshot {
-        let mut snapshot = BudgetSnapshot::default();
+        let mut snapshot = BudgetSnapshot {
+            version: BUDGET_SNAPSHOT_VERSION,
+            min_compatible_version: BUDGET_SNAPSHOT_MIN_COMPATIBLE_VERSION,
+            ..Default::default()
+        };
 
         for (access_policy_sha256, budgets) in &self.budgets {
             let mut per_policy_snapshot = PerPolicyBudgetSnapshot::default();
             per_policy_snapshot.access_policy_sha256 = access_policy_sha256.clone();
+            if let Some(policy_budgets) = self.policy_access_budgets.get(access_policy_sha256) {
+                per_policy_snapshot.policy_access_budgets = policy_budgets.clone();
+            }
 
-            for (blob_id, blob_budget) in budgets {
+            for ((blob_id, node_id), blob_budget) in budgets {
                 per_policy_snapshot.budgets.push(BlobBudgetSnapshot {
                     blob_id: blob_id.clone(),
+                    node_id: *node_id,
                     transform_access_budgets: blob_budget.transform_access_budgets.clone(),
                     shared_access_budgets: blob_budget.shared_access_budgets.clone(),
+                    charged_policy_access_budgets: blob_budget
+                        .charged_policy_access_budgets
+                        .clone(),
                 });
+                if *node_id != 0 {
+                    snapshot.min_compatible_version = NODE_BUDGETS_MIN_COMPATIBLE_VERSION;
+                }
             }
 
             snapshot.per_policy_snapshots.push(per_policy_snapshot);
@@ -275,23 +656,58 @@ impl BudgetTracker {
             snapshot.consumed_budgets.push(blob_id.clone());
         }
 
+        for (blob_id, transform_indices) in &self.revoked_transforms {
+            snapshot.revoked_transforms.push(RevokedTransformsSnapshot {
+                blob_id: blob_id.clone(),
+                transform_indices: transform_indices.iter().copied().collect(),
+            });
+        }
+
+        for (expiration, blob_id) in &self.expirations {
+            snapshot.blob_expirations.push(BlobExpirationSnapshot {
+                blob_id: blob_id.clone(),
+                expiration: Some(prost_types::Timestamp {
+                    seconds: expiration.as_secs().try_into().unwrap_or(i64::MAX),
+                    nanos: expiration.subsec_nanos().try_into().unwrap(),
+                }),
+            });
+        }
+
         snapshot
     }
 
     pub fn load_snapshot(&mut self, snapshot: BudgetSnapshot) -> Result<(), micro_rpc::Status> {
+        // Snapshots of newer versions are loaded as long as they are declared compatible; the
+        // fields unknown to this version have already been dropped when the snapshot was
+        // decoded.
+        if snapshot.min_compatible_version > BUDGET_SNAPSHOT_VERSION {
+            return Err(micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                format!(
+                    "Unsupported budget snapshot version {} (compatible with {} and above)",
+                    snapshot.version, snapshot.min_compatible_version
+                ),
+            ));
+        }
+
         // Discard any previous state.
         self.budgets.clear();
         self.consumed_budgets.clear();
+        self.policy_access_budgets.clear();
+        self.expirations.clear();
+        self.revoked_transforms.clear();
 
         for per_policy_snapshot in snapshot.per_policy_snapshots {
-            let mut per_policy_budgets = BTreeMap::<Vec<u8>, BlobBudget>::new();
+            let mut per_policy_budgets = BlobBudgets::new();
             for blob_budget_snapshot in per_policy_snapshot.budgets {
                 if per_policy_budgets
                     .insert(
-                        blob_budget_snapshot.blob_id,
+                        (blob_budget_snapshot.blob_id, blob_budget_snapshot.node_id),
                         BlobBudget {
                             transform_access_budgets: blob_budget_snapshot.transform_access_budgets,
                             shared_access_budgets: blob_budget_snapshot.shared_access_budgets,
+                            charged_policy_access_budgets: blob_budget_snapshot
+                                .charged_policy_access_budgets,
                         },
                     )
                     .is_some()
@@ -302,6 +718,12 @@ impl BudgetTracker {
                     ));
                 }
             }
+            if !per_policy_snapshot.policy_access_budgets.is_empty() {
+                self.policy_access_budgets.insert(
+                    per_policy_snapshot.access_policy_sha256.clone(),
+                    per_policy_snapshot.policy_access_budgets,
+                );
+            }
             if self
                 .budgets
                 .insert(per_policy_snapshot.access_policy_sha256, per_policy_budgets)
@@ -323,6 +745,41 @@ impl BudgetTracker {
             }
         }
 
+        for revoked in snapshot.revoked_transforms {
+            if self
+                .revoked_transforms
+                .insert(
+                    revoked.blob_id,
+                    revoked.transform_indices.into_iter().collect(),
+                )
+                .is_some()
+            {
+                return Err(micro_rpc::Status::new_with_message(
+                    micro_rpc::StatusCode::InvalidArgument,
+                    "Duplicated `revoked_transforms` entries in the snapshot",
+                ));
+            }
+        }
+
+        for blob_expiration in snapshot.blob_expirations {
+            let expiration = blob_expiration
+                .expiration
+                .and_then(|ts| {
+                    Some(Duration::new(
+                        ts.seconds.try_into().ok()?,
+                        ts.nanos.try_into().ok()?,
+                    ))
+                })
+                .ok_or_else(|| {
+                    micro_rpc::Status::new_with_message(
+                        micro_rpc::StatusCode::InvalidArgument,
+                        "Invalid `blob_expirations` entry in the snapshot",
+                    )
+                })?;
+            self.expirations
+                .insert((expiration, blob_expiration.blob_id));
+        }
+
         Ok(())
     }
 }
@@ -334,9 +791,9 @@ mod tests {
     use crate::assert_err;
     use alloc::{borrow::ToOwned, vec};
     use federated_compute::proto::{
-        access_budget::Kind as AccessBudgetKind, data_access_policy::Transform, AccessBudget,
-        ApplicationMatcher,
+        access_budget::Kind as AccessBudgetKind, AccessBudget, ApplicationMatcher,
     };
+    use prost::Message;
 
     #[test]
     fn test_find_matching_transform_success() {
@@ -482,6 +939,58 @@ mod tests {
             .is_err());
     }
 
+    #[test]
+    fn test_find_matching_transform_validity_window() {
+        let tracker = BudgetTracker::default();
+        let policy = DataAccessPolicy {
+            transforms: vec![
+                Transform {
+                    src: 0,
+                    not_before: Some(prost_types::Timestamp {
+                        seconds: 100,
+                        ..Default::default()
+                    }),
+                    not_after: Some(prost_types::Timestamp {
+                        seconds: 200,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                Transform {
+                    src: 0,
+                    not_before: Some(prost_types::Timestamp {
+                        seconds: 200,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let find_at = |seconds| {
+            tracker.find_matching_transform(
+                b"blob-id",
+                /* node_id=*/ 0,
+                &policy,
+                b"policy-hash",
+                &Application::default(),
+                Duration::from_secs(seconds),
+            )
+        };
+
+        // No transform is valid before the first window opens.
+        assert_err!(
+            find_at(99),
+            micro_rpc::StatusCode::FailedPrecondition,
+            "not authorized at the current time"
+        );
+        // `not_before` is inclusive and `not_after` is exclusive.
+        assert_eq!(find_at(100), Ok(0));
+        assert_eq!(find_at(199), Ok(0));
+        assert_eq!(find_at(200), Ok(1));
+        assert_eq!(find_at(1000), Ok(1));
+    }
+
     #[test]
     fn test_update_budget() {
         let mut tracker = BudgetTracker::default();
@@ -490,6 +999,7 @@ mod tests {
                 src: 0,
                 access_budget: Some(AccessBudget {
                     kind: Some(AccessBudgetKind::Times(2)),
+                    ..Default::default()
                 }),
                 ..Default::default()
             }],
@@ -546,6 +1056,94 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_budgets_per_node() {
+        let mut tracker = BudgetTracker::default();
+        // Both stages of the pipeline share a budget, which is tracked separately for the blob
+        // at each node even though the blob ids of the stages collide.
+        let policy = DataAccessPolicy {
+            transforms: vec![
+                Transform {
+                    src: 0,
+                    dest: Some(1),
+                    shared_access_budget_indices: vec![0],
+                    ..Default::default()
+                },
+                Transform {
+                    src: 1,
+                    shared_access_budget_indices: vec![0],
+                    ..Default::default()
+                },
+            ],
+            shared_access_budgets: vec![AccessBudget {
+                kind: Some(AccessBudgetKind::Times(1)),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let policy_hash = b"hash";
+        let blob_id = b"blob-id";
+        let find_matching_transform = |tracker: &BudgetTracker, node_id: u32| {
+            tracker.find_matching_transform(
+                blob_id,
+                node_id,
+                &policy,
+                policy_hash,
+                &Application::default(),
+                Duration::default(),
+            )
+        };
+
+        assert_eq!(find_matching_transform(&tracker, 0), Ok(0));
+        assert_eq!(
+            tracker.update_budget(blob_id, /* transform_index= */ 0, &policy, policy_hash),
+            Ok(())
+        );
+        assert_err!(
+            find_matching_transform(&tracker, 0),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "data access budget exhausted"
+        );
+        assert_eq!(find_matching_transform(&tracker, 1), Ok(1));
+        assert_eq!(
+            tracker.update_budget(blob_id, /* transform_index= */ 1, &policy, policy_hash),
+            Ok(())
+        );
+        assert_err!(
+            find_matching_transform(&tracker, 1),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "data access budget exhausted"
+        );
+
+        // The budgets at each node are saved, and can't be loaded by older trackers.
+        let snapshot = tracker.save_snapshot();
+        assert_eq!(
+            snapshot.min_compatible_version,
+            NODE_BUDGETS_MIN_COMPATIBLE_VERSION
+        );
+        assert_eq!(
+            snapshot.per_policy_snapshots[0]
+                .budgets
+                .iter()
+                .map(|budget| (budget.blob_id.as_slice(), budget.node_id))
+                .collect::<Vec<_>>(),
+            vec![(blob_id.as_slice(), 0), (blob_id.as_slice(), 1)]
+        );
+        let mut new_tracker = BudgetTracker::default();
+        assert_eq!(new_tracker.load_snapshot(snapshot), Ok(()));
+        assert_err!(
+            find_matching_transform(&new_tracker, 1),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "data access budget exhausted"
+        );
+
+        // Consuming the budget of the blob discards its budgets at all nodes.
+        tracker.consume_budget(blob_id);
+        assert!(tracker.save_snapshot().per_policy_snapshots[0]
+            .budgets
+            .is_empty());
+    }
+
     #[test]
     fn test_update_budget_after_exhausted() {
         let mut tracker = BudgetTracker::default();
@@ -554,6 +1152,7 @@ mod tests {
                 src: 0,
                 access_budget: Some(AccessBudget {
                     kind: Some(AccessBudgetKind::Times(1)),
+                    ..Default::default()
                 }),
                 ..Default::default()
             }],
@@ -688,14 +1287,234 @@ mod tests {
     }
 
     #[test]
-    fn test_shared_budgets() {
+    fn test_distinct_blob_count() {
         let mut tracker = BudgetTracker::default();
         let policy = DataAccessPolicy {
-            transforms: vec![
+            transforms: vec![Transform {
+                src: 0,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(tracker.distinct_blob_count(), 0);
+
+        // Budgets of the same blob under different policies are counted once.
+        assert_eq!(
+            tracker.update_budget(b"blob1", 0, &policy, b"hash1"),
+            Ok(())
+        );
+        assert_eq!(
+            tracker.update_budget(b"blob1", 0, &policy, b"hash2"),
+            Ok(())
+        );
+        assert_eq!(
+            tracker.update_budget(b"blob2", 0, &policy, b"hash1"),
+            Ok(())
+        );
+        assert_eq!(tracker.distinct_blob_count(), 2);
+
+        tracker.consume_budget(b"blob2");
+        tracker.consume_budget(b"blob3");
+        assert_eq!(tracker.distinct_blob_count(), 3);
+    }
+
+    #[test]
+    fn test_take_and_put_blob() {
+        let mut old_tracker = BudgetTracker::default();
+        let mut new_tracker = BudgetTracker::default();
+        let policy = DataAccessPolicy {
+            transforms: vec![
+                Transform {
+                    src: 0,
+                    access_budget: Some(AccessBudget {
+                        kind: Some(AccessBudgetKind::Times(2)),
+                        ..Default::default()
+                    }),
+                    policy_access_budget_indices: vec![0],
+                    ..Default::default()
+                },
+                Transform {
+                    src: 0,
+                    ..Default::default()
+                },
+            ],
+            policy_access_budgets: vec![AccessBudget {
+                kind: Some(AccessBudgetKind::Times(2)),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let policy_hash = b"hash";
+        let find_matching_transform = |tracker: &BudgetTracker, blob_id: &[u8]| {
+            tracker.find_matching_transform(
+                blob_id,
+                /* node_id=*/ 0,
+                &policy,
+                policy_hash,
+                &Application::default(),
+                Duration::default(),
+            )
+        };
+
+        assert_eq!(
+            old_tracker.update_budget(
+                b"blob1",
+                /* transform_index= */ 0,
+                &policy,
+                policy_hash
+            ),
+            Ok(())
+        );
+        old_tracker.revoke_transform(b"blob1", 1);
+        let transfer = old_tracker.take_blob(b"blob1", policy_hash).unwrap();
+
+        // The blob can no longer be accessed through the old tracker.
+        assert!(old_tracker.is_tracked(b"blob1"));
+        assert_err!(
+            find_matching_transform(&old_tracker, b"blob1"),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "data access budget consumed"
+        );
+        assert_err!(
+            old_tracker.take_blob(b"blob1", policy_hash),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "data access budget consumed"
+        );
+
+        // The new tracker picks up the remaining budgets and the revoked transforms.
+        assert!(!new_tracker.is_tracked(b"blob1"));
+        new_tracker.put_blob(b"blob1", policy_hash, transfer);
+        assert!(new_tracker.is_tracked(b"blob1"));
+        assert_eq!(find_matching_transform(&new_tracker, b"blob1"), Ok(0));
+        assert_eq!(
+            new_tracker.update_budget(
+                b"blob1",
+                /* transform_index= */ 0,
+                &policy,
+                policy_hash
+            ),
+            Ok(())
+        );
+        assert_err!(
+            find_matching_transform(&new_tracker, b"blob1"),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "data access budget exhausted"
+        );
+
+        // The policy-wide budget already charged for the blob isn't restored.
+        assert_eq!(
+            new_tracker.update_budget(
+                b"blob2",
+                /* transform_index= */ 0,
+                &policy,
+                policy_hash
+            ),
+            Ok(())
+        );
+        assert_eq!(find_matching_transform(&new_tracker, b"blob3"), Ok(1));
+    }
+
+    #[test]
+    fn test_revoke_transform() {
+        let mut tracker = BudgetTracker::default();
+        let policy = DataAccessPolicy {
+            transforms: vec![
+                Transform {
+                    src: 0,
+                    ..Default::default()
+                },
+                Transform {
+                    src: 0,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let policy_hash = b"hash";
+        let blob_id = b"blob-id";
+
+        tracker.revoke_transform(blob_id, /* transform_index= */ 0);
+
+        // Access through the revoked transform fails, but other transforms are unaffected.
+        assert_eq!(
+            tracker.find_matching_transform(
+                blob_id,
+                /* node_id=*/ 0,
+                &policy,
+                policy_hash,
+                &Application::default(),
+                Duration::default()
+            ),
+            Ok(1)
+        );
+        assert_err!(
+            tracker.update_budget(blob_id, /* transform_index= */ 0, &policy, policy_hash),
+            micro_rpc::StatusCode::Internal,
+            "revoked"
+        );
+        assert_eq!(
+            tracker.update_budget(blob_id, /* transform_index= */ 1, &policy, policy_hash),
+            Ok(())
+        );
+
+        // Access to other blobs is unaffected.
+        assert_eq!(
+            tracker.find_matching_transform(
+                b"blob-id2",
+                /* node_id=*/ 0,
+                &policy,
+                policy_hash,
+                &Application::default(),
+                Duration::default()
+            ),
+            Ok(0)
+        );
+
+        // Once all matching transforms are revoked, access is denied.
+        tracker.revoke_transform(blob_id, /* transform_index= */ 1);
+        assert_eq!(
+            tracker.find_matching_transform(
+                blob_id,
+                /* node_id=*/ 0,
+                &policy,
+                policy_hash,
+                &Application::default(),
+                Duration::default()
+            ),
+            Err(micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::ResourceExhausted,
+                "data access budget exhausted",
+            ))
+        );
+
+        // The revocations survive a snapshot round trip.
+        let snapshot = tracker.save_snapshot();
+        assert_eq!(
+            snapshot.revoked_transforms,
+            vec![RevokedTransformsSnapshot {
+                blob_id: blob_id.to_vec(),
+                transform_indices: vec![0, 1],
+            }]
+        );
+        let mut tracker = BudgetTracker::default();
+        assert_eq!(tracker.load_snapshot(snapshot.clone()), Ok(()));
+        assert_eq!(tracker.save_snapshot(), snapshot);
+
+        // Consuming the budget supersedes the revocations.
+        tracker.consume_budget(blob_id);
+        assert!(tracker.save_snapshot().revoked_transforms.is_empty());
+    }
+
+    #[test]
+    fn test_shared_budgets() {
+        let mut tracker = BudgetTracker::default();
+        let policy = DataAccessPolicy {
+            transforms: vec![
                 Transform {
                     src: 0,
                     access_budget: Some(AccessBudget {
                         kind: Some(AccessBudgetKind::Times(1)),
+                        ..Default::default()
                     }),
                     shared_access_budget_indices: vec![0],
                     ..Default::default()
@@ -708,7 +1527,9 @@ mod tests {
             ],
             shared_access_budgets: vec![AccessBudget {
                 kind: Some(AccessBudgetKind::Times(2)),
+                ..Default::default()
             }],
+            charged_policy_access_budgets: vec![],
             ..Default::default()
         };
         let policy_hash = b"hash";
@@ -779,6 +1600,463 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_update_budget_failure_leaves_shared_budgets() {
+        let mut tracker = BudgetTracker::default();
+        let policy = DataAccessPolicy {
+            transforms: vec![
+                Transform {
+                    src: 0,
+                    shared_access_budget_indices: vec![0, 1],
+                    ..Default::default()
+                },
+                Transform {
+                    src: 0,
+                    shared_access_budget_indices: vec![0],
+                    ..Default::default()
+                },
+            ],
+            shared_access_budgets: vec![
+                AccessBudget {
+                    kind: Some(AccessBudgetKind::Times(2)),
+                    ..Default::default()
+                },
+                AccessBudget {
+                    kind: Some(AccessBudgetKind::Times(1)),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let policy_hash = b"hash";
+        let blob_id = b"blob-id";
+
+        assert_eq!(
+            tracker.update_budget(blob_id, /* transform_index= */ 0, &policy, policy_hash),
+            Ok(())
+        );
+        // The second shared budget is exhausted, so the access through the first transform
+        // fails without charging the first shared budget.
+        assert_err!(
+            tracker.update_budget(blob_id, /* transform_index= */ 0, &policy, policy_hash),
+            micro_rpc::StatusCode::Internal,
+            "no budget remaining"
+        );
+        assert_eq!(
+            tracker.update_budget(blob_id, /* transform_index= */ 1, &policy, policy_hash),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_named_shared_access_budgets() {
+        let mut tracker = BudgetTracker::default();
+        let transform = |tag: &str, shared_access_budget_indices: Vec<u32>| Transform {
+            src: 0,
+            application: Some(ApplicationMatcher {
+                tag: Some(tag.to_owned()),
+                ..Default::default()
+            }),
+            shared_access_budget_indices,
+            ..Default::default()
+        };
+        let policy = DataAccessPolicy {
+            transforms: vec![
+                transform("analytics", vec![0]),
+                transform("debugging", vec![0, 1]),
+            ],
+            shared_access_budgets: vec![
+                AccessBudget {
+                    kind: Some(AccessBudgetKind::Times(3)),
+                    name: Some("exploration".into()),
+                },
+                AccessBudget {
+                    kind: Some(AccessBudgetKind::Times(1)),
+                    name: Some("debugging".into()),
+                },
+            ],
+            ..Default::default()
+        };
+        let policy_hash = b"hash";
+        let blob_id = b"blob-id";
+        let analytics = Application {
+            tag: "analytics",
+            ..Default::default()
+        };
+        let debugging = Application {
+            tag: "debugging",
+            ..Default::default()
+        };
+
+        // Access through the debugging transform is also limited by its own budget.
+        assert_eq!(
+            tracker.find_matching_transform(
+                blob_id,
+                /* node_id=*/ 0,
+                &policy,
+                policy_hash,
+                &debugging,
+                Duration::default()
+            ),
+            Ok(1)
+        );
+        assert_eq!(
+            tracker.update_budget(blob_id, /* transform_index= */ 1, &policy, policy_hash),
+            Ok(())
+        );
+        assert_err!(
+            tracker.find_matching_transform(
+                blob_id,
+                /* node_id=*/ 0,
+                &policy,
+                policy_hash,
+                &debugging,
+                Duration::default()
+            ),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "data access budget exhausted"
+        );
+        assert_err!(
+            tracker.update_budget(blob_id, /* transform_index= */ 1, &policy, policy_hash),
+            micro_rpc::StatusCode::Internal,
+            "no budget remaining"
+        );
+
+        // The remaining accesses allowed by the exploration budget can be spent by the analytics
+        // transform, which isn't affected by the failed update above.
+        for _ in 0..2 {
+            assert_eq!(
+                tracker.find_matching_transform(
+                    blob_id,
+                    /* node_id=*/ 0,
+                    &policy,
+                    policy_hash,
+                    &analytics,
+                    Duration::default()
+                ),
+                Ok(0)
+            );
+            assert_eq!(
+                tracker.update_budget(blob_id, /* transform_index= */ 0, &policy, policy_hash),
+                Ok(())
+            );
+        }
+        assert_err!(
+            tracker.find_matching_transform(
+                blob_id,
+                /* node_id=*/ 0,
+                &policy,
+                policy_hash,
+                &analytics,
+                Duration::default()
+            ),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "data access budget exhausted"
+        );
+
+        // Shared budgets are tracked per blob id.
+        assert_eq!(
+            tracker.find_matching_transform(
+                b"blob-id2",
+                /* node_id=*/ 0,
+                &policy,
+                policy_hash,
+                &analytics,
+                Duration::default()
+            ),
+            Ok(0)
+        );
+
+        // Named budgets are saved like the other shared budgets, which older versions can
+        // load.
+        let snapshot = tracker.save_snapshot();
+        assert_eq!(
+            snapshot.min_compatible_version,
+            BUDGET_SNAPSHOT_MIN_COMPATIBLE_VERSION
+        );
+        assert_eq!(
+            snapshot.per_policy_snapshots[0].budgets[0].shared_access_budgets,
+            vec![0, 0]
+        );
+        let mut loaded_tracker = BudgetTracker::default();
+        assert_eq!(loaded_tracker.load_snapshot(snapshot.clone()), Ok(()));
+        assert_eq!(loaded_tracker.save_snapshot(), snapshot);
+    }
+
+    #[test]
+    fn test_policy_budgets() {
+        let mut tracker = BudgetTracker::default();
+        let policy = DataAccessPolicy {
+            transforms: vec![
+                Transform {
+                    src: 0,
+                    access_budget: Some(AccessBudget {
+                        kind: Some(AccessBudgetKind::Times(2)),
+                        ..Default::default()
+                    }),
+                    policy_access_budget_indices: vec![0],
+                    ..Default::default()
+                },
+                Transform {
+                    src: 0,
+                    policy_access_budget_indices: vec![0],
+                    ..Default::default()
+                },
+            ],
+            policy_access_budgets: vec![AccessBudget {
+                kind: Some(AccessBudgetKind::Times(2)),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let policy_hash = b"hash";
+
+        // Repeated accesses to the same blob are charged against the policy budget once.
+        for _ in 0..2 {
+            assert_eq!(
+                tracker.find_matching_transform(
+                    b"blob1",
+                    /* node_id=*/ 0,
+                    &policy,
+                    policy_hash,
+                    &Application::default(),
+                    Duration::default()
+                ),
+                Ok(0)
+            );
+            assert_eq!(
+                tracker.update_budget(
+                    b"blob1",
+                    /* transform_index= */ 0,
+                    &policy,
+                    policy_hash
+                ),
+                Ok(())
+            );
+        }
+        assert_eq!(
+            tracker.find_matching_transform(
+                b"blob2",
+                /* node_id=*/ 0,
+                &policy,
+                policy_hash,
+                &Application::default(),
+                Duration::default()
+            ),
+            Ok(0)
+        );
+        assert_eq!(
+            tracker.update_budget(
+                b"blob2",
+                /* transform_index= */ 0,
+                &policy,
+                policy_hash
+            ),
+            Ok(())
+        );
+
+        // The policy budget is now exhausted, so no other blob can be accessed.
+        assert_eq!(
+            tracker.find_matching_transform(
+                b"blob3",
+                /* node_id=*/ 0,
+                &policy,
+                policy_hash,
+                &Application::default(),
+                Duration::default()
+            ),
+            Err(micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::ResourceExhausted,
+                "data access budget exhausted",
+            ))
+        );
+        assert_err!(
+            tracker.update_budget(
+                b"blob3",
+                /* transform_index= */ 1,
+                &policy,
+                policy_hash
+            ),
+            micro_rpc::StatusCode::Internal,
+            "no budget remaining"
+        );
+
+        // Blobs that have already been charged can still be accessed through the other
+        // transform.
+        assert_eq!(
+            tracker.find_matching_transform(
+                b"blob1",
+                /* node_id=*/ 0,
+                &policy,
+                policy_hash,
+                &Application::default(),
+                Duration::default()
+            ),
+            Ok(1)
+        );
+
+        // Policy budgets are tracked per policy.
+        assert_eq!(
+            tracker.find_matching_transform(
+                b"blob3",
+                /* node_id=*/ 0,
+                &policy,
+                b"other-hash",
+                &Application::default(),
+                Duration::default()
+            ),
+            Ok(0)
+        );
+    }
+
+    #[test]
+    fn test_policy_budgets_snapshot() {
+        let mut tracker = BudgetTracker::default();
+        let policy = DataAccessPolicy {
+            transforms: vec![Transform {
+                src: 0,
+                policy_access_budget_indices: vec![0],
+                ..Default::default()
+            }],
+            policy_access_budgets: vec![AccessBudget {
+                kind: Some(AccessBudgetKind::Times(3)),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let policy_hash = b"hash";
+        assert_eq!(
+            tracker.update_budget(
+                b"blob1",
+                /* transform_index= */ 0,
+                &policy,
+                policy_hash
+            ),
+            Ok(())
+        );
+
+        let snapshot = tracker.save_snapshot();
+        assert_eq!(
+            snapshot,
+            BudgetSnapshot {
+                per_policy_snapshots: vec![PerPolicyBudgetSnapshot {
+                    access_policy_sha256: policy_hash.to_vec(),
+                    budgets: vec![BlobBudgetSnapshot {
+                        blob_id: b"blob1".to_vec(),
+                        transform_access_budgets: vec![0],
+                        shared_access_budgets: vec![],
+                        charged_policy_access_budgets: vec![0],
+                        node_id: 0,
+                    }],
+                    policy_access_budgets: vec![2],
+                }],
+                consumed_budgets: vec![],
+                blob_expirations: vec![],
+                revoked_transforms: vec![],
+                version: BUDGET_SNAPSHOT_VERSION,
+                min_compatible_version: BUDGET_SNAPSHOT_MIN_COMPATIBLE_VERSION,
+            }
+        );
+
+        // The loaded tracker still knows that the blob has been charged.
+        let mut tracker = BudgetTracker::default();
+        assert_eq!(tracker.load_snapshot(snapshot.clone()), Ok(()));
+        assert_eq!(
+            tracker.update_budget(
+                b"blob1",
+                /* transform_index= */ 0,
+                &policy,
+                policy_hash
+            ),
+            Ok(())
+        );
+        assert_eq!(tracker.save_snapshot(), snapshot);
+    }
+
+    #[test]
+    fn test_collect_garbage() {
+        let mut tracker = BudgetTracker::default();
+        let policy = DataAccessPolicy {
+            transforms: vec![Transform {
+                src: 0,
+                access_budget: Some(AccessBudget {
+                    kind: Some(AccessBudgetKind::Times(2)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let policy_hash = b"hash";
+
+        for blob_id in [b"blob1", b"blob2", b"blob3"] {
+            assert_eq!(
+                tracker.update_budget(blob_id, /* transform_index= */ 0, &policy, policy_hash),
+                Ok(())
+            );
+        }
+        tracker.set_expiration(b"blob1", Duration::from_secs(10));
+        tracker.set_expiration(b"blob2", Duration::from_secs(20));
+        tracker.consume_budget(b"blob2");
+
+        // Nothing has expired yet.
+        tracker.collect_garbage(Duration::from_secs(5));
+        let snapshot = tracker.save_snapshot();
+        assert_eq!(snapshot.per_policy_snapshots[0].budgets.len(), 2);
+        assert_eq!(snapshot.consumed_budgets, vec![b"blob2".to_vec()]);
+        assert_eq!(
+            snapshot.blob_expirations,
+            vec![
+                BlobExpirationSnapshot {
+                    blob_id: b"blob1".to_vec(),
+                    expiration: Some(prost_types::Timestamp {
+                        seconds: 10,
+                        ..Default::default()
+                    }),
+                },
+                BlobExpirationSnapshot {
+                    blob_id: b"blob2".to_vec(),
+                    expiration: Some(prost_types::Timestamp {
+                        seconds: 20,
+                        ..Default::default()
+                    }),
+                },
+            ]
+        );
+
+        // The budget of the first blob is discarded once it has expired.
+        tracker.collect_garbage(Duration::from_secs(10));
+        let snapshot = tracker.save_snapshot();
+        assert_eq!(
+            snapshot.per_policy_snapshots[0]
+                .budgets
+                .iter()
+                .map(|budget| budget.blob_id.as_slice())
+                .collect::<Vec<_>>(),
+            vec![b"blob3"]
+        );
+        assert_eq!(snapshot.consumed_budgets, vec![b"blob2".to_vec()]);
+        assert_eq!(snapshot.blob_expirations.len(), 1);
+
+        // The consumed budgets are discarded too.
+        tracker.collect_garbage(Duration::from_secs(20));
+        let snapshot = tracker.save_snapshot();
+        assert_eq!(snapshot.per_policy_snapshots[0].budgets.len(), 1);
+        assert!(snapshot.consumed_budgets.is_empty());
+        assert!(snapshot.blob_expirations.is_empty());
+
+        // The expirations survive a snapshot round trip.
+        tracker.set_expiration(b"blob3", Duration::from_secs(30));
+        let snapshot = tracker.save_snapshot();
+        let mut tracker = BudgetTracker::default();
+        assert_eq!(tracker.load_snapshot(snapshot.clone()), Ok(()));
+        assert_eq!(tracker.save_snapshot(), snapshot);
+        tracker.collect_garbage(Duration::from_secs(30));
+        assert!(tracker.save_snapshot().per_policy_snapshots[0]
+            .budgets
+            .is_empty());
+    }
+
     #[test]
     fn test_policy_isolation() {
         let mut tracker = BudgetTracker::default();
@@ -858,6 +2136,7 @@ mod tests {
                 src: 0,
                 access_budget: Some(AccessBudget {
                     kind: Some(AccessBudgetKind::Times(2)),
+                    ..Default::default()
                 }),
                 ..Default::default()
             }],
@@ -890,9 +2169,16 @@ mod tests {
                         blob_id: blob_id.to_vec(),
                         transform_access_budgets: vec![1],
                         shared_access_budgets: vec![],
+                        charged_policy_access_budgets: vec![],
+                        node_id: 0,
                     }],
+                    policy_access_budgets: vec![],
                 }],
                 consumed_budgets: vec![],
+                blob_expirations: vec![],
+                revoked_transforms: vec![],
+                version: BUDGET_SNAPSHOT_VERSION,
+                min_compatible_version: BUDGET_SNAPSHOT_MIN_COMPATIBLE_VERSION,
             }
         );
     }
@@ -905,6 +2191,7 @@ mod tests {
                 src: 0,
                 access_budget: Some(AccessBudget {
                     kind: Some(AccessBudgetKind::Times(1)),
+                    ..Default::default()
                 }),
                 ..Default::default()
             }],
@@ -935,8 +2222,13 @@ mod tests {
                 per_policy_snapshots: vec![PerPolicyBudgetSnapshot {
                     access_policy_sha256: policy_hash.to_vec(),
                     budgets: vec![],
+                    policy_access_budgets: vec![],
                 }],
                 consumed_budgets: vec![blob_id.to_vec()],
+                blob_expirations: vec![],
+                revoked_transforms: vec![],
+                version: BUDGET_SNAPSHOT_VERSION,
+                min_compatible_version: BUDGET_SNAPSHOT_MIN_COMPATIBLE_VERSION,
             }
         );
     }
@@ -953,7 +2245,10 @@ mod tests {
                         blob_id: b"blob1".to_vec(),
                         transform_access_budgets: vec![1],
                         shared_access_budgets: vec![],
+                        charged_policy_access_budgets: vec![],
+                        node_id: 0,
                     }],
+                    policy_access_budgets: vec![],
                 },
                 PerPolicyBudgetSnapshot {
                     access_policy_sha256: b"hash2".to_vec(),
@@ -962,16 +2257,25 @@ mod tests {
                             blob_id: b"blob2".to_vec(),
                             transform_access_budgets: vec![2, 3],
                             shared_access_budgets: vec![11],
+                            charged_policy_access_budgets: vec![],
+                            node_id: 0,
                         },
                         BlobBudgetSnapshot {
                             blob_id: b"blob3".to_vec(),
                             transform_access_budgets: vec![],
                             shared_access_budgets: vec![12, 13, 14],
+                            charged_policy_access_budgets: vec![],
+                            node_id: 0,
                         },
                     ],
+                    policy_access_budgets: vec![],
                 },
             ],
             consumed_budgets: vec![b"blob4".to_vec(), b"blob5".to_vec()],
+            blob_expirations: vec![],
+            revoked_transforms: vec![],
+            version: BUDGET_SNAPSHOT_VERSION,
+            min_compatible_version: BUDGET_SNAPSHOT_MIN_COMPATIBLE_VERSION,
         };
 
         // Load the snapshot.
@@ -988,6 +2292,7 @@ mod tests {
                 src: 0,
                 access_budget: Some(AccessBudget {
                     kind: Some(AccessBudgetKind::Times(2)),
+                    ..Default::default()
                 }),
                 ..Default::default()
             }],
@@ -1014,7 +2319,18 @@ mod tests {
 
         // Load an empty snapshot and verify that an empty snapshot is saved.
         assert_eq!(tracker.load_snapshot(BudgetSnapshot::default()), Ok(()));
-        assert_eq!(tracker.save_snapshot(), BudgetSnapshot::default());
+        assert_eq!(
+            tracker.save_snapshot(),
+            BudgetTracker::default().save_snapshot()
+        );
+        assert_eq!(
+            tracker.save_snapshot(),
+            BudgetSnapshot {
+                version: BUDGET_SNAPSHOT_VERSION,
+                min_compatible_version: BUDGET_SNAPSHOT_MIN_COMPATIBLE_VERSION,
+                ..Default::default()
+            }
+        );
     }
 
     #[test]
@@ -1026,13 +2342,18 @@ mod tests {
                     PerPolicyBudgetSnapshot {
                         access_policy_sha256: b"hash1".to_vec(),
                         budgets: vec![],
+                        policy_access_budgets: vec![],
                     },
                     PerPolicyBudgetSnapshot {
                         access_policy_sha256: b"hash1".to_vec(),
                         budgets: vec![],
+                        policy_access_budgets: vec![],
                     }
                 ],
-                consumed_budgets: vec![]
+                consumed_budgets: vec![],
+                blob_expirations: vec![],
+                revoked_transforms: vec![],
+                ..Default::default()
             }),
             micro_rpc::StatusCode::InvalidArgument,
             "Duplicated `access_policy_sha256` entries in the snapshot"
@@ -1056,8 +2377,12 @@ mod tests {
                             ..Default::default()
                         },
                     ],
+                    policy_access_budgets: vec![],
                 },],
-                consumed_budgets: vec![]
+                consumed_budgets: vec![],
+                blob_expirations: vec![],
+                revoked_transforms: vec![],
+                ..Default::default()
             }),
             micro_rpc::StatusCode::InvalidArgument,
             "Duplicated `blob_id` entries in the snapshot"
@@ -1076,4 +2401,68 @@ mod tests {
             "Duplicated `consumed_budgets` entries in the snapshot"
         );
     }
+
+    #[test]
+    fn test_load_snapshot_unversioned() {
+        // Snapshots taken before the versioning was introduced are loaded and saved back with
+        // the current version.
+        let mut tracker = BudgetTracker::default();
+        assert_eq!(
+            tracker.load_snapshot(BudgetSnapshot {
+                consumed_budgets: vec![b"blob1".to_vec()],
+                ..Default::default()
+            }),
+            Ok(())
+        );
+        assert_eq!(
+            tracker.save_snapshot(),
+            BudgetSnapshot {
+                consumed_budgets: vec![b"blob1".to_vec()],
+                version: BUDGET_SNAPSHOT_VERSION,
+                min_compatible_version: BUDGET_SNAPSHOT_MIN_COMPATIBLE_VERSION,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_load_snapshot_newer_compatible_version() {
+        let snapshot = BudgetSnapshot {
+            consumed_budgets: vec![b"blob1".to_vec()],
+            version: BUDGET_SNAPSHOT_VERSION + 1,
+            min_compatible_version: BUDGET_SNAPSHOT_VERSION,
+            ..Default::default()
+        };
+        // Append a field unknown to this version, as if it had been added by the newer one.
+        let mut encoded = snapshot.encode_to_vec();
+        encoded.extend_from_slice(&[0xa0, 0x06, 0x01]); // Field 100, varint 1.
+        let decoded = BudgetSnapshot::decode(encoded.as_slice()).unwrap();
+        assert_eq!(decoded, snapshot);
+
+        let mut tracker = BudgetTracker::default();
+        assert_eq!(tracker.load_snapshot(decoded), Ok(()));
+        assert!(tracker.is_tracked(b"blob1"));
+        assert_eq!(
+            tracker.save_snapshot(),
+            BudgetSnapshot {
+                version: BUDGET_SNAPSHOT_VERSION,
+                min_compatible_version: BUDGET_SNAPSHOT_MIN_COMPATIBLE_VERSION,
+                ..snapshot
+            }
+        );
+    }
+
+    #[test]
+    fn test_load_snapshot_incompatible_version() {
+        let mut tracker = BudgetTracker::default();
+        assert_err!(
+            tracker.load_snapshot(BudgetSnapshot {
+                version: BUDGET_SNAPSHOT_VERSION + 1,
+                min_compatible_version: BUDGET_SNAPSHOT_VERSION + 1,
+                ..Default::default()
+            }),
+            micro_rpc::StatusCode::InvalidArgument,
+            "Unsupported budget snapshot version"
+        );
+    }
 }
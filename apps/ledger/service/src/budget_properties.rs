@@ -0,0 +1,513 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Property tests replaying randomized interleavings of the budget operations against both
+// the BudgetTracker and a reference model of the budget accounting. Every run is seeded with
+// its index, so a failure is reproduced by replaying the seed reported with it.
+
+extern crate alloc;
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::String,
+    vec,
+    vec::Vec,
+};
+use core::time::Duration;
+
+use crate::attestation::Application;
+use crate::budget::BudgetTracker;
+use federated_compute::proto::{
+    access_budget::Kind as AccessBudgetKind, data_access_policy::Transform, AccessBudget,
+    DataAccessPolicy,
+};
+
+/// Number of runs, each seeded with its index.
+const RUNS: u64 = 200;
+/// Number of operations applied in each run.
+const OPERATIONS_PER_RUN: usize = 200;
+/// Number of blobs the operations are applied to. Blobs alternate between the policies.
+const BLOB_COUNT: u8 = 8;
+
+/// A deterministic SplitMix64 pseudo-random generator.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `0..n`.
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}
+
+fn times(n: u32) -> AccessBudget {
+    AccessBudget {
+        kind: Some(AccessBudgetKind::Times(n)),
+        ..Default::default()
+    }
+}
+
+/// Returns the policies exercising the per-transform, shared and policy-wide budgets. The
+/// policy-wide budgets are smaller than the number of blobs subject to the policy.
+fn policies() -> Vec<DataAccessPolicy> {
+    vec![
+        DataAccessPolicy {
+            transforms: vec![
+                Transform {
+                    access_budget: Some(times(2)),
+                    policy_access_budget_indices: vec![0],
+                    ..Default::default()
+                },
+                Transform {
+                    shared_access_budget_indices: vec![0],
+                    policy_access_budget_indices: vec![0],
+                    ..Default::default()
+                },
+                Transform {
+                    access_budget: Some(times(1)),
+                    shared_access_budget_indices: vec![0, 1],
+                    ..Default::default()
+                },
+                Transform {
+                    shared_access_budget_indices: vec![1],
+                    ..Default::default()
+                },
+            ],
+            shared_access_budgets: vec![times(3), times(2)],
+            policy_access_budgets: vec![times(3)],
+            ..Default::default()
+        },
+        DataAccessPolicy {
+            transforms: vec![
+                Transform {
+                    access_budget: Some(times(1)),
+                    shared_access_budget_indices: vec![0],
+                    ..Default::default()
+                },
+                Transform {
+                    access_budget: Some(times(2)),
+                    policy_access_budget_indices: vec![0, 1],
+                    ..Default::default()
+                },
+                Transform {
+                    policy_access_budget_indices: vec![1],
+                    shared_access_budget_indices: vec![0],
+                    ..Default::default()
+                },
+            ],
+            shared_access_budgets: vec![AccessBudget {
+                name: Some("pool".into()),
+                ..times(3)
+            }],
+            policy_access_budgets: vec![times(2), times(3)],
+            ..Default::default()
+        },
+    ]
+}
+
+/// Returns the number of accesses allowed by the budget, None if unlimited.
+fn limit(access_budget: &AccessBudget) -> Option<u32> {
+    match access_budget.kind {
+        Some(AccessBudgetKind::Times(n)) => Some(n),
+        None => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Operation {
+    /// Finds the first transform through which the blob can be accessed and records the
+    /// access, as the ledger does when the authorization is checked and applied at once.
+    Authorize {
+        blob: u8,
+    },
+    /// Records the access through the transform without finding it first, as the ledger does
+    /// when it applies an authorization that has been checked against an older state.
+    RecordAccess {
+        blob: u8,
+        transform_index: usize,
+    },
+    Revoke {
+        blob: u8,
+        transform_index: u32,
+    },
+    Consume {
+        blob: u8,
+    },
+    /// Advances the time, discarding the budgets of the blobs that have expired.
+    Advance {
+        seconds: u64,
+    },
+    /// Replaces the tracker with the one loaded from its snapshot.
+    Snapshot,
+}
+
+/// Budget state of a blob in the reference model.
+struct ModelBlob {
+    /// Number of accesses granted through each transform.
+    accesses: Vec<u32>,
+    /// Indices of the policy-wide budgets the blob has been charged against.
+    charged: BTreeSet<u32>,
+    revoked: BTreeSet<u32>,
+    consumed: bool,
+}
+
+/// Reference model of the budget accounting. Unlike the BudgetTracker, it counts the granted
+/// accesses rather than the remaining budgets and derives every limit from the policy.
+struct Model {
+    policies: Vec<DataAccessPolicy>,
+    blobs: BTreeMap<u8, ModelBlob>,
+    /// Number of distinct blobs charged against each policy-wide budget, per policy.
+    policy_charges: Vec<Vec<u32>>,
+}
+
+impl Model {
+    fn new(policies: Vec<DataAccessPolicy>) -> Self {
+        let policy_charges = policies
+            .iter()
+            .map(|policy| vec![0; policy.policy_access_budgets.len()])
+            .collect();
+        Self {
+            policies,
+            blobs: BTreeMap::new(),
+            policy_charges,
+        }
+    }
+
+    fn policy_index(&self, blob: u8) -> usize {
+        usize::from(blob) % self.policies.len()
+    }
+
+    fn blob_mut(&mut self, blob: u8) -> &mut ModelBlob {
+        let transform_count = self.policies[self.policy_index(blob)].transforms.len();
+        self.blobs.entry(blob).or_insert_with(|| ModelBlob {
+            accesses: vec![0; transform_count],
+            charged: BTreeSet::new(),
+            revoked: BTreeSet::new(),
+            consumed: false,
+        })
+    }
+
+    /// Returns whether the blob can be accessed through the transform.
+    fn allows(&self, blob: u8, transform_index: usize) -> bool {
+        let policy_index = self.policy_index(blob);
+        let policy = &self.policies[policy_index];
+        let transform = &policy.transforms[transform_index];
+        let state = self.blobs.get(&blob);
+        if state.is_some_and(|state| {
+            state.consumed || state.revoked.contains(&(transform_index as u32))
+        }) {
+            return false;
+        }
+        let accesses = |i: usize| state.map_or(0, |state| state.accesses[i]);
+
+        if let Some(access_budget) = &transform.access_budget {
+            if limit(access_budget).is_some_and(|n| accesses(transform_index) >= n) {
+                return false;
+            }
+        }
+        for shared_index in &transform.shared_access_budget_indices {
+            let used: u32 = policy
+                .transforms
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| t.shared_access_budget_indices.contains(shared_index))
+                .map(|(i, _)| accesses(i))
+                .sum();
+            if limit(&policy.shared_access_budgets[*shared_index as usize])
+                .is_some_and(|n| used >= n)
+            {
+                return false;
+            }
+        }
+        for index in &transform.policy_access_budget_indices {
+            let charged = state.is_some_and(|state| state.charged.contains(index));
+            if !charged
+                && limit(&policy.policy_access_budgets[*index as usize])
+                    .is_some_and(|n| self.policy_charges[policy_index][*index as usize] >= n)
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Returns the first transform through which the blob can be accessed.
+    fn first_allowed(&self, blob: u8) -> Option<usize> {
+        let transform_count = self.policies[self.policy_index(blob)].transforms.len();
+        (0..transform_count).find(|i| self.allows(blob, *i))
+    }
+
+    fn record_access(&mut self, blob: u8, transform_index: usize) {
+        let policy_index = self.policy_index(blob);
+        let policy_access_budget_indices = self.policies[policy_index].transforms[transform_index]
+            .policy_access_budget_indices
+            .clone();
+        let state = self.blob_mut(blob);
+        state.accesses[transform_index] += 1;
+        let newly_charged: Vec<u32> = policy_access_budget_indices
+            .into_iter()
+            .filter(|index| state.charged.insert(*index))
+            .collect();
+        for index in newly_charged {
+            self.policy_charges[policy_index][index as usize] += 1;
+        }
+    }
+
+    fn revoke(&mut self, blob: u8, transform_index: u32) {
+        let state = self.blob_mut(blob);
+        if !state.consumed {
+            state.revoked.insert(transform_index);
+        }
+    }
+
+    fn consume(&mut self, blob: u8) {
+        self.blob_mut(blob).consumed = true;
+    }
+
+    /// Discards the state of the expired blob. The policy-wide budgets it has been charged
+    /// against remain spent.
+    fn expire(&mut self, blob: u8) {
+        self.blobs.remove(&blob);
+    }
+}
+
+/// Applies the same operations to the tracker and the model, checking after each of them
+/// that both agree on the accesses that are allowed.
+struct Harness {
+    seed: u64,
+    rng: Rng,
+    tracker: BudgetTracker,
+    model: Model,
+    policy_hashes: Vec<Vec<u8>>,
+    /// Expiration of each blob. Like the ledger, the harness never accesses expired blobs.
+    expirations: Vec<Duration>,
+    now: Duration,
+    operations: Vec<Operation>,
+}
+
+impl Harness {
+    fn new(seed: u64) -> Self {
+        let mut rng = Rng(seed);
+        let policies = policies();
+        let policy_hashes = (0..policies.len())
+            .map(|i| format!("policy-{}", i).into_bytes())
+            .collect();
+        let expirations: Vec<Duration> = (0..BLOB_COUNT)
+            .map(|_| Duration::from_secs(10 + rng.below(150)))
+            .collect();
+        let mut tracker = BudgetTracker::new();
+        for (blob, expiration) in expirations.iter().enumerate() {
+            tracker.set_expiration(&[blob as u8], *expiration);
+        }
+        Self {
+            seed,
+            rng,
+            tracker,
+            model: Model::new(policies),
+            policy_hashes,
+            expirations,
+            now: Duration::ZERO,
+            operations: Vec::new(),
+        }
+    }
+
+    fn is_live(&self, blob: u8) -> bool {
+        self.expirations[usize::from(blob)] > self.now
+    }
+
+    fn next_operation(&mut self) -> Operation {
+        let live: Vec<u8> = (0..BLOB_COUNT).filter(|blob| self.is_live(*blob)).collect();
+        if live.is_empty() {
+            return Operation::Advance { seconds: 1 };
+        }
+        let blob = live[self.rng.below(live.len() as u64) as usize];
+        let transform_count = self.model.policies[self.model.policy_index(blob)]
+            .transforms
+            .len() as u64;
+        match self.rng.below(20) {
+            0..=7 => Operation::Authorize { blob },
+            8..=11 => Operation::RecordAccess {
+                blob,
+                transform_index: self.rng.below(transform_count) as usize,
+            },
+            12..=13 => Operation::Revoke {
+                blob,
+                transform_index: self.rng.below(transform_count) as u32,
+            },
+            14 => Operation::Consume { blob },
+            15..=17 => Operation::Advance {
+                seconds: 1 + self.rng.below(5),
+            },
+            _ => Operation::Snapshot,
+        }
+    }
+
+    fn apply(&mut self, operation: Operation) {
+        self.operations.push(operation);
+        match operation {
+            Operation::Authorize { blob } => {
+                let expected = self.model.first_allowed(blob);
+                let transform_index = self.find_matching_transform(blob).ok();
+                assert_eq!(transform_index, expected, "{}", self.context());
+                if let Some(transform_index) = transform_index {
+                    assert_eq!(
+                        self.update_budget(blob, transform_index),
+                        Ok(()),
+                        "{}",
+                        self.context()
+                    );
+                    self.model.record_access(blob, transform_index);
+                }
+            }
+            Operation::RecordAccess {
+                blob,
+                transform_index,
+            } => {
+                let expected = self.model.allows(blob, transform_index);
+                let result = self.update_budget(blob, transform_index);
+                assert_eq!(result.is_ok(), expected, "{}", self.context());
+                if expected {
+                    self.model.record_access(blob, transform_index);
+                }
+            }
+            Operation::Revoke {
+                blob,
+                transform_index,
+            } => {
+                self.tracker.revoke_transform(&[blob], transform_index);
+                self.model.revoke(blob, transform_index);
+            }
+            Operation::Consume { blob } => {
+                self.tracker.consume_budget(&[blob]);
+                self.model.consume(blob);
+            }
+            Operation::Advance { seconds } => {
+                self.now += Duration::from_secs(seconds);
+                self.tracker.collect_garbage(self.now);
+                for blob in 0..BLOB_COUNT {
+                    if !self.is_live(blob) {
+                        self.model.expire(blob);
+                    }
+                }
+            }
+            Operation::Snapshot => {
+                let snapshot = self.tracker.save_snapshot();
+                let mut tracker = BudgetTracker::new();
+                assert_eq!(
+                    tracker.load_snapshot(snapshot.clone()),
+                    Ok(()),
+                    "{}",
+                    self.context()
+                );
+                assert_eq!(tracker.save_snapshot(), snapshot, "{}", self.context());
+                self.tracker = tracker;
+            }
+        }
+    }
+
+    /// Checks that the tracker allows access to the live blobs exactly when the model does
+    /// and that nothing is tracked for the expired blobs anymore.
+    fn check(&self) {
+        for blob in 0..BLOB_COUNT {
+            if !self.is_live(blob) {
+                assert!(!self.tracker.is_tracked(&[blob]), "{}", self.context());
+                continue;
+            }
+            let result = self.find_matching_transform(blob);
+            assert_eq!(
+                result.as_ref().ok().copied(),
+                self.model.first_allowed(blob),
+                "blob {}: {}",
+                blob,
+                self.context()
+            );
+            if let Err(err) = result {
+                assert_eq!(
+                    err.code,
+                    micro_rpc::StatusCode::ResourceExhausted,
+                    "{}",
+                    self.context()
+                );
+            }
+        }
+    }
+
+    fn find_matching_transform(&self, blob: u8) -> Result<usize, micro_rpc::Status> {
+        let policy_index = self.model.policy_index(blob);
+        self.tracker.find_matching_transform(
+            &[blob],
+            /* node_id= */ 0,
+            &self.model.policies[policy_index],
+            &self.policy_hashes[policy_index],
+            &Application::default(),
+            self.now,
+        )
+    }
+
+    fn update_budget(&mut self, blob: u8, transform_index: usize) -> Result<(), micro_rpc::Status> {
+        let policy_index = self.model.policy_index(blob);
+        self.tracker.update_budget(
+            &[blob],
+            transform_index,
+            &self.model.policies[policy_index],
+            &self.policy_hashes[policy_index],
+        )
+    }
+
+    /// Describes the run for the failure messages.
+    fn context(&self) -> String {
+        format!(
+            "seed {} at {:?} after {:?}",
+            self.seed, self.now, self.operations
+        )
+    }
+}
+
+#[test]
+fn test_budget_tracker_matches_model() {
+    for seed in 0..RUNS {
+        let mut harness = Harness::new(seed);
+        for _ in 0..OPERATIONS_PER_RUN {
+            let operation = harness.next_operation();
+            harness.apply(operation);
+            harness.check();
+        }
+    }
+}
+
+#[test]
+fn test_harness_exhausts_budgets() {
+    // Guard against the runs never reaching the limits, which would make the model
+    // comparison vacuous.
+    let mut exhausted = 0;
+    for seed in 0..RUNS {
+        let mut harness = Harness::new(seed);
+        for _ in 0..OPERATIONS_PER_RUN {
+            let operation = harness.next_operation();
+            harness.apply(operation);
+        }
+        exhausted += (0..BLOB_COUNT)
+            .filter(|blob| harness.is_live(*blob) && harness.model.first_allowed(*blob).is_none())
+            .count();
+    }
+    assert!(exhausted > 0);
+}
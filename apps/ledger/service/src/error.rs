@@ -0,0 +1,167 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate alloc;
+
+use alloc::string::String;
+use core::{fmt, time::Duration};
+
+use crate::ledger::service::{ledger_error_details::Reason, ledger_response, LedgerErrorDetails};
+
+/// An error of a ledger operation. Unlike micro_rpc::Status, it may carry machine-readable
+/// details that are returned to the clients along with the status. LedgerError converts to
+/// and from micro_rpc::Status, so operations that don't report any details keep returning
+/// micro_rpc::Status.
+#[derive(Debug, PartialEq)]
+pub struct LedgerError {
+    pub code: micro_rpc::StatusCode,
+    pub message: String,
+    pub details: Option<LedgerErrorDetails>,
+}
+
+impl LedgerError {
+    pub fn new(code: micro_rpc::StatusCode, message: impl Into<String>, reason: Reason) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: None,
+        }
+        .with_reason(reason)
+    }
+
+    /// Creates an InvalidArgument error caused by the request field.
+    pub fn invalid_argument(field: &str, message: impl Into<String>) -> Self {
+        Self::new(
+            micro_rpc::StatusCode::InvalidArgument,
+            message,
+            Reason::InvalidArgument,
+        )
+        .with_field(field)
+    }
+
+    pub fn with_reason(mut self, reason: Reason) -> Self {
+        self.details_mut().reason = reason.into();
+        self
+    }
+
+    pub fn with_field(mut self, field: &str) -> Self {
+        self.details_mut().field = field.into();
+        self
+    }
+
+    pub fn with_remaining_budget(mut self, remaining_budget: u32) -> Self {
+        self.details_mut().remaining_budget = Some(remaining_budget);
+        self
+    }
+
+    pub fn with_key_expiration(mut self, key_expiration: Duration) -> Self {
+        self.details_mut().key_expiration = Some(prost_types::Timestamp {
+            seconds: key_expiration.as_secs().try_into().unwrap_or(i64::MAX),
+            nanos: key_expiration.subsec_nanos().try_into().unwrap(),
+        });
+        self
+    }
+
+    fn details_mut(&mut self) -> &mut LedgerErrorDetails {
+        self.details.get_or_insert_with(LedgerErrorDetails::default)
+    }
+}
+
+impl From<micro_rpc::Status> for LedgerError {
+    fn from(status: micro_rpc::Status) -> Self {
+        Self {
+            code: status.code,
+            message: status.message.into(),
+            details: None,
+        }
+    }
+}
+
+impl From<LedgerError> for micro_rpc::Status {
+    fn from(err: LedgerError) -> Self {
+        micro_rpc::Status::new_with_message(err.code, err.message)
+    }
+}
+
+impl From<LedgerError> for ledger_response::Status {
+    fn from(err: LedgerError) -> Self {
+        ledger_response::Status {
+            code: err.code as i32,
+            message: err.message,
+            details: err.details,
+        }
+    }
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_conversion() {
+        let err = LedgerError::from(micro_rpc::Status::new_with_message(
+            micro_rpc::StatusCode::NotFound,
+            "not found",
+        ));
+        assert_eq!(err.details, None);
+        assert_eq!(
+            micro_rpc::Status::from(err),
+            micro_rpc::Status::new_with_message(micro_rpc::StatusCode::NotFound, "not found")
+        );
+    }
+
+    #[test]
+    fn test_details() {
+        let err = LedgerError::invalid_argument("ttl", "`ttl` is invalid");
+        assert_eq!(err.code, micro_rpc::StatusCode::InvalidArgument);
+        assert_eq!(
+            ledger_response::Status::from(err),
+            ledger_response::Status {
+                code: micro_rpc::StatusCode::InvalidArgument as i32,
+                message: "`ttl` is invalid".into(),
+                details: Some(LedgerErrorDetails {
+                    reason: Reason::InvalidArgument.into(),
+                    field: "ttl".into(),
+                    ..Default::default()
+                }),
+            }
+        );
+
+        let err = LedgerError::new(
+            micro_rpc::StatusCode::ResourceExhausted,
+            "data access budget exhausted",
+            Reason::BudgetExhausted,
+        )
+        .with_remaining_budget(0)
+        .with_key_expiration(Duration::new(100, 5));
+        assert_eq!(
+            err.details,
+            Some(LedgerErrorDetails {
+                reason: Reason::BudgetExhausted.into(),
+                remaining_budget: Some(0),
+                key_expiration: Some(prost_types::Timestamp {
+                    seconds: 100,
+                    nanos: 5,
+                }),
+                ..Default::default()
+            })
+        );
+    }
+}
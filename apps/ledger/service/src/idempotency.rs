@@ -0,0 +1,349 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate alloc;
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    vec::Vec,
+};
+use core::time::Duration;
+use federated_compute::proto::AuthorizeAccessResponse;
+
+use crate::ledger::service::IdempotencyTokenSnapshot;
+
+/// The retention of the idempotency tokens when it isn't configured.
+const DEFAULT_TTL: Duration = Duration::from_secs(3600);
+
+/// An authorization identified by an idempotency token.
+struct Authorization {
+    request_sha256: Vec<u8>,
+    transform_index: u64,
+    response: AuthorizeAccessResponse,
+    expiration: Duration,
+}
+
+/// An IdempotencyCache retains the authorized accesses by their idempotency tokens so that
+/// the retries of an authorization get the same response without consuming the budget again.
+/// Authorizations are recorded when the events are applied, so the cache is replicated along
+/// with the rest of the ledger state.
+pub struct IdempotencyCache {
+    ttl: Duration,
+    /// Authorizations keyed by namespace and token.
+    authorizations: BTreeMap<(String, Vec<u8>), Authorization>,
+    /// Expiration times of the tokens, ordered by time so that the expired tokens can be
+    /// found without visiting all authorizations.
+    expirations: BTreeSet<(Duration, (String, Vec<u8>))>,
+}
+
+impl Default for IdempotencyCache {
+    fn default() -> Self {
+        Self {
+            ttl: DEFAULT_TTL,
+            authorizations: BTreeMap::new(),
+            expirations: BTreeSet::new(),
+        }
+    }
+}
+
+impl IdempotencyCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the retention of the tokens recorded from now on.
+    pub fn set_ttl(&mut self, ttl: Duration) {
+        self.ttl = ttl;
+    }
+
+    /// Returns the transform index and the response of the authorization identified by the
+    /// token within the namespace, if the token is retained. Fails if the token has been used
+    /// for a different request.
+    pub fn get(
+        &self,
+        namespace: &str,
+        token: &[u8],
+        request_sha256: &[u8],
+        now: Duration,
+    ) -> Result<Option<(u64, &AuthorizeAccessResponse)>, micro_rpc::Status> {
+        if token.is_empty() {
+            return Ok(None);
+        }
+        match self.authorizations.get(&(namespace.into(), token.to_vec())) {
+            Some(authorization) if authorization.expiration > now => {
+                if authorization.request_sha256 != request_sha256 {
+                    return Err(micro_rpc::Status::new_with_message(
+                        micro_rpc::StatusCode::InvalidArgument,
+                        "`idempotency_token` has been used for a different request",
+                    ));
+                }
+                Ok(Some((
+                    authorization.transform_index,
+                    &authorization.response,
+                )))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Records the authorization identified by the token within the namespace, discarding the
+    /// expired tokens. Requests without a token are not recorded.
+    pub fn insert(
+        &mut self,
+        namespace: String,
+        token: Vec<u8>,
+        request_sha256: Vec<u8>,
+        transform_index: u64,
+        response: AuthorizeAccessResponse,
+        now: Duration,
+    ) {
+        self.collect_garbage(now);
+        if token.is_empty() {
+            return;
+        }
+        let expiration = now.saturating_add(self.ttl);
+        let key = (namespace, token);
+        if let Some(previous) = self.authorizations.insert(
+            key.clone(),
+            Authorization {
+                request_sha256,
+                transform_index,
+                response,
+                expiration,
+            },
+        ) {
+            self.expirations.remove(&(previous.expiration, key.clone()));
+        }
+        self.expirations.insert((expiration, key));
+    }
+
+    /// Discards the tokens that have expired by `now`.
+    fn collect_garbage(&mut self, now: Duration) {
+        while let Some((expiration, _)) = self.expirations.first() {
+            if *expiration > now {
+                break;
+            }
+            let (_, key) = self.expirations.pop_first().unwrap();
+            self.authorizations.remove(&key);
+        }
+    }
+
+    pub fn save_snapshot(&self) -> Vec<IdempotencyTokenSnapshot> {
+        self.authorizations
+            .iter()
+            .map(
+                |((namespace, token), authorization)| IdempotencyTokenSnapshot {
+                    token: token.clone(),
+                    request_sha256: authorization.request_sha256.clone(),
+                    transform_index: authorization.transform_index,
+                    response: Some(authorization.response.clone()),
+                    expiration: Some(prost_types::Timestamp {
+                        seconds: authorization
+                            .expiration
+                            .as_secs()
+                            .try_into()
+                            .unwrap_or(i64::MAX),
+                        nanos: authorization.expiration.subsec_nanos().try_into().unwrap(),
+                    }),
+                    namespace: namespace.clone(),
+                },
+            )
+            .collect()
+    }
+
+    pub fn load_snapshot(
+        &mut self,
+        snapshot: Vec<IdempotencyTokenSnapshot>,
+    ) -> Result<(), micro_rpc::Status> {
+        // Discard any previous state, keeping the configured retention.
+        self.authorizations.clear();
+        self.expirations.clear();
+
+        for token_snapshot in snapshot {
+            let expiration = token_snapshot
+                .expiration
+                .and_then(|ts| {
+                    Some(Duration::new(
+                        ts.seconds.try_into().ok()?,
+                        ts.nanos.try_into().ok()?,
+                    ))
+                })
+                .ok_or_else(|| {
+                    micro_rpc::Status::new_with_message(
+                        micro_rpc::StatusCode::InvalidArgument,
+                        "Invalid `idempotency_tokens` entry in the snapshot",
+                    )
+                })?;
+            let key = (token_snapshot.namespace, token_snapshot.token);
+            if self.authorizations.contains_key(&key) {
+                return Err(micro_rpc::Status::new_with_message(
+                    micro_rpc::StatusCode::InvalidArgument,
+                    "Duplicated `idempotency_tokens` entry in the snapshot",
+                ));
+            }
+            self.expirations.insert((expiration, key.clone()));
+            self.authorizations.insert(
+                key,
+                Authorization {
+                    request_sha256: token_snapshot.request_sha256,
+                    transform_index: token_snapshot.transform_index,
+                    response: token_snapshot.response.unwrap_or_default(),
+                    expiration,
+                },
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::assert_err;
+    use alloc::vec;
+
+    fn response(encapsulated_key: &[u8]) -> AuthorizeAccessResponse {
+        AuthorizeAccessResponse {
+            encapsulated_key: encapsulated_key.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_get() {
+        let mut cache = IdempotencyCache::new();
+        cache.set_ttl(Duration::from_secs(10));
+        cache.insert(
+            String::new(),
+            b"token".to_vec(),
+            b"request".to_vec(),
+            1,
+            response(b"key"),
+            Duration::from_secs(100),
+        );
+
+        assert_eq!(
+            cache.get("", b"token", b"request", Duration::from_secs(105)),
+            Ok(Some((1, &response(b"key"))))
+        );
+        assert_eq!(
+            cache.get("", b"other", b"request", Duration::from_secs(105)),
+            Ok(None)
+        );
+        assert_err!(
+            cache.get("", b"token", b"other", Duration::from_secs(105)),
+            micro_rpc::StatusCode::InvalidArgument,
+            "`idempotency_token` has been used for a different request"
+        );
+        // Tokens of other namespaces are independent.
+        assert_eq!(
+            cache.get("other", b"token", b"other", Duration::from_secs(105)),
+            Ok(None)
+        );
+        // The token is no longer retained once it expires.
+        assert_eq!(
+            cache.get("", b"token", b"request", Duration::from_secs(110)),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_empty_token() {
+        let mut cache = IdempotencyCache::new();
+        cache.insert(
+            String::new(),
+            vec![],
+            b"request".to_vec(),
+            0,
+            response(b"key"),
+            Duration::ZERO,
+        );
+        assert_eq!(cache.get("", b"", b"request", Duration::ZERO), Ok(None));
+        assert_eq!(cache.save_snapshot(), vec![]);
+    }
+
+    #[test]
+    fn test_collect_garbage() {
+        let mut cache = IdempotencyCache::new();
+        cache.set_ttl(Duration::from_secs(10));
+        cache.insert(
+            String::new(),
+            b"token1".to_vec(),
+            b"request1".to_vec(),
+            0,
+            response(b"key1"),
+            Duration::from_secs(100),
+        );
+        cache.insert(
+            String::new(),
+            b"token2".to_vec(),
+            b"request2".to_vec(),
+            0,
+            response(b"key2"),
+            Duration::from_secs(105),
+        );
+        cache.insert(
+            String::new(),
+            b"token3".to_vec(),
+            b"request3".to_vec(),
+            0,
+            response(b"key3"),
+            Duration::from_secs(110),
+        );
+
+        let snapshot = cache.save_snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].token, b"token2".to_vec());
+        assert_eq!(snapshot[1].token, b"token3".to_vec());
+    }
+
+    #[test]
+    fn test_load_snapshot() {
+        let mut cache = IdempotencyCache::new();
+        cache.insert(
+            String::new(),
+            b"token".to_vec(),
+            b"request".to_vec(),
+            2,
+            response(b"key"),
+            Duration::from_secs(100),
+        );
+        let snapshot = cache.save_snapshot();
+
+        let mut loaded_cache = IdempotencyCache::new();
+        assert_eq!(loaded_cache.load_snapshot(snapshot.clone()), Ok(()));
+        assert_eq!(loaded_cache.save_snapshot(), snapshot);
+        assert_eq!(
+            loaded_cache.get("", b"token", b"request", Duration::from_secs(100)),
+            Ok(Some((2, &response(b"key"))))
+        );
+    }
+
+    #[test]
+    fn test_load_snapshot_duplicated_token() {
+        let token_snapshot = IdempotencyTokenSnapshot {
+            token: b"token".to_vec(),
+            expiration: Some(prost_types::Timestamp::default()),
+            ..Default::default()
+        };
+        let mut cache = IdempotencyCache::new();
+        assert_err!(
+            cache.load_snapshot(vec![token_snapshot.clone(), token_snapshot]),
+            micro_rpc::StatusCode::InvalidArgument,
+            "Duplicated `idempotency_tokens` entry"
+        );
+    }
+}
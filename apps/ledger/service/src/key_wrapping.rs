@@ -0,0 +1,104 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate alloc;
+
+use aes_gcm_siv::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256GcmSiv, Nonce,
+};
+use alloc::vec::Vec;
+use anyhow::anyhow;
+
+/// The context the key wrapping key is derived from the cluster secret with.
+pub const KEY_WRAPPING_CONTEXT: &[u8] = b"ledger private key wrapping";
+
+/// The nonce the private keys are wrapped with. AES-256-GCM-SIV tolerates nonce reuse, the fixed
+/// nonce only reveals that the same private key has been wrapped for the same key id again. The
+/// wrapping is hence deterministic, so that all replicas save identical snapshots.
+const NONCE: [u8; 12] = [0; 12];
+
+/// Wraps the private key with AES-256-GCM-SIV under the key wrapping key, so that the private
+/// keys carried by the replicated events and the snapshots are only readable by the replicas
+/// of the cluster. The key id is authenticated along with the key, so that a wrapped key can't
+/// be passed off as the private key of another key id.
+pub fn wrap_private_key(
+    key_wrapping_key: &[u8],
+    key_id: &[u8],
+    private_key: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    Aes256GcmSiv::new_from_slice(key_wrapping_key)
+        .map_err(|err| anyhow!("invalid key wrapping key: {}", err))?
+        .encrypt(
+            Nonce::from_slice(&NONCE),
+            Payload {
+                msg: private_key,
+                aad: key_id,
+            },
+        )
+        .map_err(|err| anyhow!("failed to wrap private key: {}", err))
+}
+
+/// Unwraps the private key wrapped by `wrap_private_key` for the same key id.
+pub fn unwrap_private_key(
+    key_wrapping_key: &[u8],
+    key_id: &[u8],
+    wrapped_key: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    Aes256GcmSiv::new_from_slice(key_wrapping_key)
+        .map_err(|err| anyhow!("invalid key wrapping key: {}", err))?
+        .decrypt(
+            Nonce::from_slice(&NONCE),
+            Payload {
+                msg: wrapped_key,
+                aad: key_id,
+            },
+        )
+        .map_err(|err| anyhow!("failed to unwrap private key: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_wrap_unwrap_private_key() {
+        let key_wrapping_key = vec![1; 32];
+        let wrapped_key = wrap_private_key(&key_wrapping_key, b"id", b"private key").unwrap();
+
+        assert_ne!(wrapped_key, b"private key");
+        assert_eq!(
+            wrap_private_key(&key_wrapping_key, b"id", b"private key").unwrap(),
+            wrapped_key
+        );
+        assert_eq!(
+            unwrap_private_key(&key_wrapping_key, b"id", &wrapped_key).unwrap(),
+            b"private key"
+        );
+    }
+
+    #[test]
+    fn test_unwrap_private_key_fails() {
+        let key_wrapping_key = vec![1; 32];
+        let wrapped_key = wrap_private_key(&key_wrapping_key, b"id", b"private key").unwrap();
+
+        // Another key wrapping key.
+        assert!(unwrap_private_key(&[2; 32], b"id", &wrapped_key).is_err());
+        // Another key id.
+        assert!(unwrap_private_key(&key_wrapping_key, b"other id", &wrapped_key).is_err());
+        // Truncated key.
+        assert!(unwrap_private_key(&key_wrapping_key, b"id", &wrapped_key[..4]).is_err());
+    }
+}
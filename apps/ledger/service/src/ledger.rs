@@ -12,7 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use alloc::{boxed::Box, collections::BTreeMap, format, vec, vec::Vec};
+use alloc::{
+    borrow::ToOwned,
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::String,
+    vec,
+    vec::Vec,
+};
 use anyhow::anyhow;
 use cfc_crypto::{extract_key_from_cwt, PUBLIC_KEY_CLAIM};
 use core::time::Duration;
@@ -21,12 +29,21 @@ use coset::{
     CoseSign1Builder, Header,
 };
 
-use cfc_crypto::PrivateKey;
+use cfc_crypto::{CipherSuite, PrivateKey};
 use hpke::{Deserializable, Serializable};
 
-use crate::attestation;
+use crate::attestation::{
+    self, OakRecipientVerifier, PinnedKeyRecipientVerifier, RecipientVerifier,
+};
+use crate::audit::AuditLog;
 use crate::budget::{self, BudgetTracker};
+use crate::error::LedgerError;
+use crate::idempotency::IdempotencyCache;
+use crate::key_wrapping::{unwrap_private_key, wrap_private_key};
+use crate::rate_limit::RateLimiter;
 
+use crate::ledger::service::authorize_access_batch_response::{blob_result, BlobResult};
+use crate::ledger::service::ledger_error_details::Reason;
 use crate::ledger::service::*;
 use federated_compute::proto::*;
 
@@ -38,8 +55,80 @@ use prost::Message;
 use rand::{rngs::OsRng, RngCore};
 use sha2::{Digest, Sha256};
 
-pub mod service {
-    include!(concat!(env!("OUT_DIR"), "/ledger.service.rs"));
+pub use tcp_proto::ledger::service;
+
+/// Version of the snapshot format produced by [LedgerService::save_snapshot]. It must be
+/// incremented whenever the format changes, including when fields are added. Version 2 added
+/// the time when the keys have been issued, which older ledgers can ignore. Version 3 added
+/// the time when the disabled keys are purged. Version 4 added the policy-wide access budgets.
+/// Version 5 added the expiration times of the blobs. Version 6 added the transforms through
+/// which access to blobs has been revoked. Version 7 added the audit log. Version 8 added the
+/// idempotency tokens of the authorized accesses. Version 9 added the namespaces of the keys
+/// and idempotency tokens. Version 10 wraps the private keys under the key derived from the
+/// cluster secret. Version 11 added the audit log checkpoint and the audit log entries of
+/// revocations and key deletions. Version 12 added the rate limit counters. Version 13 added
+/// the key usage counters.
+pub const LEDGER_SNAPSHOT_VERSION: u32 = 13;
+
+/// The oldest version of the snapshot format that can load the snapshots produced by
+/// [LedgerService::save_snapshot]. Older ledgers ignore the fields unknown to them, so this
+/// only needs to be raised when a new field can't be ignored without misrepresenting the
+/// ledger state, which prevents the older replicas from loading such snapshots during a
+/// rolling upgrade.
+pub const LEDGER_SNAPSHOT_MIN_COMPATIBLE_VERSION: u32 = 1;
+
+/// The oldest version of the snapshot format that can load snapshots with disabled keys. Older
+/// ledgers would drop the purge time and bring the disabled keys back to life.
+const PURGE_TIME_MIN_COMPATIBLE_VERSION: u32 = 3;
+
+/// The oldest version of the snapshot format that can load snapshots tracking policy-wide access
+/// budgets. Older ledgers would drop these budgets and allow further accesses.
+const POLICY_ACCESS_BUDGETS_MIN_COMPATIBLE_VERSION: u32 = 4;
+
+/// The oldest version of the snapshot format that can load snapshots with blob expiration times.
+/// Older ledgers would drop the expiration times and never discard the budgets of expired blobs.
+const BLOB_EXPIRATIONS_MIN_COMPATIBLE_VERSION: u32 = 5;
+
+/// The oldest version of the snapshot format that can load snapshots with revoked transforms.
+/// Older ledgers would drop the revocations and grant access through the revoked transforms.
+const REVOKED_TRANSFORMS_MIN_COMPATIBLE_VERSION: u32 = 6;
+
+/// The oldest version of the snapshot format that can load snapshots with audit log entries.
+/// Older ledgers would drop the audit log and restart the hash chain.
+const AUDIT_LOG_MIN_COMPATIBLE_VERSION: u32 = 7;
+
+/// The oldest version of the snapshot format that can load snapshots with idempotency tokens.
+/// Older ledgers would drop the tokens and authorize the retried requests again.
+const IDEMPOTENCY_TOKENS_MIN_COMPATIBLE_VERSION: u32 = 8;
+
+/// The oldest version of the snapshot format that can load snapshots with namespaced keys or
+/// idempotency tokens. Older ledgers would drop the namespaces and merge them into the default
+/// namespace.
+const NAMESPACES_MIN_COMPATIBLE_VERSION: u32 = 9;
+
+/// The oldest version of the snapshot format that can load snapshots with wrapped private keys.
+/// Older ledgers would find no private keys in the snapshot.
+const WRAPPED_PRIVATE_KEYS_MIN_COMPATIBLE_VERSION: u32 = 10;
+
+/// The oldest version of the snapshot format that can load snapshots with a compacted audit log
+/// or with audit log entries other than authorized accesses. Older ledgers would fail to verify
+/// the chain of the retained entries, or misrepresent revocations and key deletions as
+/// authorized accesses.
+const AUDIT_LOG_CHECKPOINT_MIN_COMPATIBLE_VERSION: u32 = 11;
+
+/// The oldest version of the snapshot format that can load snapshots with rate limit counters.
+/// Older ledgers would drop the counters and let the recipients exceed the limits.
+const RATE_LIMIT_COUNTERS_MIN_COMPATIBLE_VERSION: u32 = 12;
+
+/// The oldest version of the snapshot format that can load snapshots with key usage counters.
+/// Older ledgers would drop the counters and report the keys as never used.
+const KEY_USAGE_MIN_COMPATIBLE_VERSION: u32 = 13;
+
+// Draws random bytes local to this replica, never visible to the host. Used when
+// the ledger is not replicated and for the key material that is replicated
+// wrapped.
+fn fill_os_random(dest: &mut [u8]) {
+    OsRng.fill_bytes(dest)
 }
 
 pub trait Ledger {
@@ -62,13 +151,101 @@ pub trait Ledger {
         &mut self,
         request: RevokeAccessRequest,
     ) -> Result<RevokeAccessResponse, micro_rpc::Status>;
+
+    fn authorize_access_batch(
+        &mut self,
+        request: AuthorizeAccessBatchRequest,
+    ) -> Result<AuthorizeAccessBatchResponse, micro_rpc::Status>;
+
+    fn list_keys(
+        &mut self,
+        request: ListKeysRequest,
+    ) -> Result<ListKeysResponse, micro_rpc::Status>;
+
+    fn get_key_details(
+        &mut self,
+        request: GetKeyDetailsRequest,
+    ) -> Result<GetKeyDetailsResponse, micro_rpc::Status>;
+
+    fn query_audit_log(
+        &mut self,
+        request: QueryAuditLogRequest,
+    ) -> Result<QueryAuditLogResponse, micro_rpc::Status>;
+
+    fn check_access(
+        &mut self,
+        request: CheckAccessRequest,
+    ) -> Result<CheckAccessResponse, micro_rpc::Status>;
+
+    fn get_key_stats(
+        &mut self,
+        request: GetKeyStatsRequest,
+    ) -> Result<GetKeyStatsResponse, micro_rpc::Status>;
+
+    fn rewrap_keys(
+        &mut self,
+        request: RewrapKeysRequest,
+    ) -> Result<RewrapKeysResponse, micro_rpc::Status>;
+}
+
+/// Counters of the accesses to the blobs encrypted with a key.
+#[derive(Default, PartialEq)]
+struct KeyUsage {
+    granted: u64,
+    denied: u64,
+    budget_exhausted: u64,
 }
 
 struct PerKeyLedger {
     private_key: cfc_crypto::PrivateKey,
     public_key: Vec<u8>,
+    cipher_suite: CipherSuite,
+    namespace: String,
+    issued_at: Duration,
     expiration: Duration,
+    // Set once the key has been disabled pending its deletion.
+    purge_time: Option<Duration>,
     budget_tracker: budget::BudgetTracker,
+    usage: KeyUsage,
+}
+
+impl PerKeyLedger {
+    /// Checks whether the key is neither expired nor purged at the given time.
+    fn is_live(&self, now: Duration) -> bool {
+        self.expiration > now && self.purge_time.map_or(true, |purge_time| purge_time > now)
+    }
+
+    /// Fails if the key has been disabled pending its deletion. Disabled keys keep
+    /// authorizing access to the existing blobs until they are purged, but must not be
+    /// the key of new blobs.
+    fn check_enabled(&self) -> Result<(), LedgerError> {
+        match self.purge_time {
+            Some(_) => Err(LedgerError::new(
+                micro_rpc::StatusCode::FailedPrecondition,
+                "public key is disabled",
+                Reason::KeyDisabled,
+            )
+            .with_key_expiration(self.expiration)),
+            None => Ok(()),
+        }
+    }
+
+    /// Fails if the blob header declares a cipher suite other than the one the key
+    /// has been created for.
+    fn check_cipher_suite(&self, header: &BlobHeader) -> Result<(), LedgerError> {
+        match HpkeCipherSuite::from_i32(header.cipher_suite) {
+            Some(HpkeCipherSuite::Unspecified) => Ok(()),
+            Some(cipher_suite)
+                if LedgerService::parse_cipher_suite(cipher_suite) == self.cipher_suite =>
+            {
+                Ok(())
+            }
+            _ => Err(LedgerError::invalid_argument(
+                "blob_header.cipher_suite",
+                "blob cipher suite does not match the public key",
+            )),
+        }
+    }
 }
 
 pub struct LedgerService {
@@ -76,6 +253,40 @@ pub struct LedgerService {
     signer: Box<dyn Signer>,
     current_time: Duration,
     per_key_ledgers: BTreeMap<Vec<u8>, PerKeyLedger>,
+    access_notification_config: AccessNotificationConfig,
+    access_notifications: Vec<AccessNotification>,
+    // Denials of the authorization requests rejected before being replicated, which are
+    // yet to be replicated. This is not a part of the replicated state.
+    access_denied_events: Vec<AccessDeniedEvent>,
+    audit_log: AuditLog,
+    idempotency_cache: IdempotencyCache,
+    key_rotation: Option<KeyRotation>,
+    key_deletion_grace_period: Option<Duration>,
+    // Time when this replica proposed the rotation that hasn't been applied yet. This
+    // is not a part of the replicated state.
+    rotation_proposed_at: Option<Duration>,
+    // Rate limits enforced when the authorization requests are handled. This is not a
+    // part of the replicated state.
+    rate_limiter: RateLimiter,
+    // Verifies the recipients when the authorization requests are handled. This is not a
+    // part of the replicated state.
+    recipient_verifier: Box<dyn RecipientVerifier>,
+    // Wraps the private keys carried by the events and the snapshots. Random unless set
+    // to the key shared by the replicas, which is the case whenever the ledger is
+    // replicated. This is not a part of the replicated state.
+    key_wrapping_key: Vec<u8>,
+    // Wall clock time attested by the time authority that the requests are handled at.
+    // Takes precedence over the time passed by the untrusted side. This is not a part of
+    // the replicated state.
+    trusted_time: Option<Duration>,
+    // Whether the requests are rejected unless the attested wall clock time is known.
+    require_trusted_time: bool,
+}
+
+/// Parsed key rotation configuration.
+struct KeyRotation {
+    period: Duration,
+    overlap: Duration,
 }
 
 impl LedgerService {
@@ -86,24 +297,312 @@ impl LedgerService {
         // Pre-generate and convert the evidence so that we don't have to do it every time a key is
         // created.
         let evidence = evidence_to_proto(evidence_provider.get_evidence().clone())?;
+        let mut key_wrapping_key = vec![0u8; 32];
+        fill_os_random(&mut key_wrapping_key);
         Ok(Self {
             evidence,
             signer,
             current_time: Duration::default(),
             per_key_ledgers: BTreeMap::default(),
+            access_notification_config: AccessNotificationConfig::default(),
+            access_notifications: Vec::new(),
+            access_denied_events: Vec::new(),
+            audit_log: AuditLog::new(),
+            idempotency_cache: IdempotencyCache::new(),
+            key_rotation: None,
+            key_deletion_grace_period: None,
+            rotation_proposed_at: None,
+            rate_limiter: RateLimiter::new(),
+            recipient_verifier: Box::new(OakRecipientVerifier),
+            key_wrapping_key,
+            trusted_time: None,
+            require_trusted_time: false,
+        })
+    }
+
+    /// Sets the wall clock time attested by the time authority, which the subsequent
+    /// requests are handled at instead of the time passed by the untrusted side. The
+    /// expiration of the keys and the blobs and the key rotation then follow the time the
+    /// host can't move forward. Must be set before each request, as it isn't replicated.
+    pub fn set_trusted_time(&mut self, trusted_time: Option<Duration>) {
+        self.trusted_time = trusted_time;
+    }
+
+    /// Sets whether the requests are rejected unless the attested wall clock time is set,
+    /// see `set_trusted_time`.
+    pub fn set_require_trusted_time(&mut self, require_trusted_time: bool) {
+        self.require_trusted_time = require_trusted_time;
+    }
+
+    /// Sets the key the private keys are wrapped under in the events and the snapshots. All
+    /// replicas must use the same key, which the host must not be able to derive.
+    pub fn set_key_wrapping_key(&mut self, key_wrapping_key: Vec<u8>) {
+        self.key_wrapping_key = key_wrapping_key;
+    }
+
+    /// Unwraps the private key carried by an event or a snapshot. The plaintext private keys
+    /// produced by the ledgers preceding the wrapping are accepted as well.
+    fn unwrap_private_key(
+        &self,
+        key_id: &[u8],
+        wrapped_private_key: &[u8],
+        private_key: &[u8],
+    ) -> Result<PrivateKey, micro_rpc::Status> {
+        let private_key = if wrapped_private_key.is_empty() {
+            private_key.to_vec()
+        } else {
+            unwrap_private_key(&self.key_wrapping_key, key_id, wrapped_private_key).map_err(
+                |err| {
+                    micro_rpc::Status::new_with_message(
+                        micro_rpc::StatusCode::InvalidArgument,
+                        format!("failed to unwrap private_key: {:?}", err),
+                    )
+                },
+            )?
+        };
+        PrivateKey::from_bytes(&private_key).map_err(|err| {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                format!("failed to parse private_key: {:?}", err),
+            )
+        })
+    }
+
+    /// Wraps the private key to be carried by an event or a snapshot.
+    fn wrap_private_key(
+        &self,
+        key_id: &[u8],
+        private_key: &PrivateKey,
+    ) -> Result<Vec<u8>, micro_rpc::Status> {
+        wrap_private_key(&self.key_wrapping_key, key_id, &private_key.to_bytes()).map_err(|err| {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::Internal,
+                format!("failed to wrap private_key: {:?}", err),
+            )
         })
     }
 
-    /// Updates `self.current_time` and removes expired keys.
+    /// Finds the per-key ledger of the key within the namespace. The keys of other namespaces
+    /// are reported the same way as the keys that don't exist.
+    fn get_per_key_ledger(
+        &self,
+        namespace: &str,
+        key_id: &[u8],
+    ) -> Result<&PerKeyLedger, LedgerError> {
+        self.per_key_ledgers
+            .get(key_id)
+            .filter(|per_key_ledger| per_key_ledger.namespace == namespace)
+            .ok_or_else(|| {
+                LedgerError::new(
+                    micro_rpc::StatusCode::NotFound,
+                    "public key not found",
+                    Reason::KeyNotFound,
+                )
+            })
+    }
+
+    /// Mutable version of `get_per_key_ledger`.
+    fn get_per_key_ledger_mut(
+        &mut self,
+        namespace: &str,
+        key_id: &[u8],
+    ) -> Result<&mut PerKeyLedger, LedgerError> {
+        self.per_key_ledgers
+            .get_mut(key_id)
+            .filter(|per_key_ledger| per_key_ledger.namespace == namespace)
+            .ok_or_else(|| {
+                LedgerError::new(
+                    micro_rpc::StatusCode::NotFound,
+                    "public key not found",
+                    Reason::KeyNotFound,
+                )
+            })
+    }
+
+    /// Enables the per-recipient rate limits of the access authorizations.
+    pub fn set_rate_limit_config(
+        &mut self,
+        config: RateLimitConfig,
+    ) -> Result<(), micro_rpc::Status> {
+        self.rate_limiter.set_config(config)
+    }
+
+    /// Replaces the verifier of the recipients requesting access.
+    pub fn set_recipient_verifier(&mut self, recipient_verifier: Box<dyn RecipientVerifier>) {
+        self.recipient_verifier = recipient_verifier;
+    }
+
+    /// Selects the verifier of the recipients requesting access.
+    pub fn set_recipient_verification_config(
+        &mut self,
+        config: RecipientVerificationConfig,
+    ) -> Result<(), micro_rpc::Status> {
+        let recipient_verifier: Box<dyn RecipientVerifier> = match config.kind {
+            None | Some(recipient_verification_config::Kind::OakEvidence(_)) => {
+                Box::new(OakRecipientVerifier)
+            }
+            Some(recipient_verification_config::Kind::PinnedSigningKeys(pinned)) => Box::new(
+                PinnedKeyRecipientVerifier::new(pinned.signing_public_keys).map_err(|err| {
+                    micro_rpc::Status::new_with_message(
+                        micro_rpc::StatusCode::InvalidArgument,
+                        format!("`pinned_signing_keys` is invalid: {:?}", err),
+                    )
+                })?,
+            ),
+        };
+        self.set_recipient_verifier(recipient_verifier);
+        Ok(())
+    }
+
+    /// Enables the automatic key rotation.
+    pub fn set_key_rotation_config(
+        &mut self,
+        config: KeyRotationConfig,
+    ) -> Result<(), micro_rpc::Status> {
+        let period = Self::parse_duration(&config.period).map_err(|err| {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                format!("`period` is invalid: {:?}", err),
+            )
+        })?;
+        if period.is_zero() {
+            return Err(micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                "`period` must be positive",
+            ));
+        }
+        let overlap = Self::parse_duration(&config.overlap).map_err(|err| {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                format!("`overlap` is invalid: {:?}", err),
+            )
+        })?;
+        self.key_rotation = Some(KeyRotation { period, overlap });
+        Ok(())
+    }
+
+    /// Sets transforms whose authorized accesses must be notified about.
+    pub fn set_access_notification_config(&mut self, config: AccessNotificationConfig) {
+        self.access_notification_config = config;
+    }
+
+    /// Takes notifications about the accesses authorized since the last call.
+    pub fn take_access_notifications(&mut self) -> Vec<AccessNotification> {
+        core::mem::take(&mut self.access_notifications)
+    }
+
+    /// Takes the events replicating the denials of the authorization requests rejected
+    /// since the last call.
+    pub fn take_access_denied_events(&mut self) -> Vec<AccessDeniedEvent> {
+        core::mem::take(&mut self.access_denied_events)
+    }
+
+    /// Records a notification if the authorized access matches any of the configured
+    /// transforms.
+    fn maybe_notify_access(
+        &mut self,
+        header: &BlobHeader,
+        transform_index: u64,
+        recipient_public_key: &[u8],
+    ) -> Result<(), micro_rpc::Status> {
+        let matches = self
+            .access_notification_config
+            .transforms
+            .iter()
+            .any(|selector| {
+                selector.transform_index == transform_index
+                    && (selector.access_policy_sha256.is_empty()
+                        || selector.access_policy_sha256 == header.access_policy_sha256)
+            });
+        if matches {
+            self.access_notifications.push(AccessNotification {
+                event_time: Some(Self::format_timestamp(&self.current_time)?),
+                blob_id_sha256: Sha256::digest(&header.blob_id).to_vec(),
+                access_policy_sha256: header.access_policy_sha256.clone(),
+                transform_index,
+                recipient_sha256: Sha256::digest(recipient_public_key).to_vec(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Gets the time the request is handled at: the attested wall clock time if set,
+    /// otherwise the time passed by the untrusted side unless the attested time is required.
+    fn request_time(
+        &self,
+        now: &Option<prost_types::Timestamp>,
+    ) -> Result<Option<prost_types::Timestamp>, micro_rpc::Status> {
+        match self.trusted_time {
+            Some(trusted_time) => Ok(Some(Self::format_timestamp(&trusted_time)?)),
+            None if self.require_trusted_time => Err(micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::Unavailable,
+                "trusted wall clock time is not available",
+            )),
+            None => Ok(now.clone()),
+        }
+    }
+
+    /// Updates `self.current_time`, removes expired keys and discards the budgets of expired
+    /// blobs.
     fn update_current_time(&mut self, now: &Option<prost_types::Timestamp>) -> anyhow::Result<()> {
         let now = Self::parse_timestamp(now).map_err(|err| anyhow!("{:?}", err))?;
         if now > self.current_time {
             self.current_time = now;
-            self.per_key_ledgers.retain(|_, v| v.expiration > now);
+            self.per_key_ledgers.retain(|_, v| v.is_live(now));
+            for per_key_ledger in self.per_key_ledgers.values_mut() {
+                per_key_ledger.budget_tracker.collect_garbage(now);
+            }
         }
         Ok(())
     }
 
+    /// Returns the expiration time declared in the blob header, failing if the blob has
+    /// already expired. The budgets of expired blobs are discarded, so access to them must
+    /// never be granted.
+    fn check_blob_expiration(
+        header: &BlobHeader,
+        now: Duration,
+    ) -> Result<Option<Duration>, LedgerError> {
+        if header.expiration.is_none() {
+            return Ok(None);
+        }
+        let expiration = Self::parse_timestamp(&header.expiration).map_err(|err| {
+            LedgerError::invalid_argument(
+                "blob_header.expiration",
+                format!("blob expiration is invalid: {:?}", err),
+            )
+        })?;
+        if expiration <= now {
+            return Err(LedgerError::new(
+                micro_rpc::StatusCode::FailedPrecondition,
+                "blob has expired",
+                Reason::BlobExpired,
+            ));
+        }
+        Ok(Some(expiration))
+    }
+
+    /// Maps the cipher suite in the protos to the one implemented by cfc_crypto,
+    /// using the default suite if unspecified.
+    fn parse_cipher_suite(cipher_suite: HpkeCipherSuite) -> CipherSuite {
+        match cipher_suite {
+            HpkeCipherSuite::Unspecified => CipherSuite::default(),
+            HpkeCipherSuite::X25519HkdfSha256Aes128gcm => CipherSuite::X25519HkdfSha256Aes128Gcm,
+            HpkeCipherSuite::X25519HkdfSha256Chacha20poly1305 => {
+                CipherSuite::X25519HkdfSha256ChaCha20Poly1305
+            }
+        }
+    }
+
+    fn format_cipher_suite(cipher_suite: CipherSuite) -> HpkeCipherSuite {
+        match cipher_suite {
+            CipherSuite::X25519HkdfSha256Aes128Gcm => HpkeCipherSuite::X25519HkdfSha256Aes128gcm,
+            CipherSuite::X25519HkdfSha256ChaCha20Poly1305 => {
+                HpkeCipherSuite::X25519HkdfSha256Chacha20poly1305
+            }
+        }
+    }
+
     /// Parses a proto Timestamp as a Duration since the Unix epoch.
     fn parse_timestamp(
         timestamp: &Option<prost_types::Timestamp>,
@@ -160,24 +659,30 @@ impl LedgerService {
             .map_err(anyhow::Error::msg)
     }
 
+    /// Produces the event that creates a new key. Key id is drawn from the given
+    /// source of random bytes, the keypair from the randomness local to this
+    /// replica. The event carries the private key wrapped so that all replicas
+    /// apply the identical keypair without revealing it to the host.
     pub fn produce_create_key_event(
         &mut self,
         request: CreateKeyRequest,
-    ) -> Result<CreateKeyEvent, micro_rpc::Status> {
-        self.update_current_time(&request.now).map_err(|err| {
-            micro_rpc::Status::new_with_message(
-                micro_rpc::StatusCode::InvalidArgument,
-                format!("`now` is invalid: {:?}", err),
-            )
+        fill_random: &dyn Fn(&mut [u8]),
+    ) -> Result<CreateKeyEvent, LedgerError> {
+        let now = self.request_time(&request.now)?;
+        self.update_current_time(&now).map_err(|err| {
+            LedgerError::invalid_argument("now", format!("`now` is invalid: {:?}", err))
         })?;
 
         let ttl = Self::parse_duration(&request.ttl).map_err(|err| {
-            micro_rpc::Status::new_with_message(
-                micro_rpc::StatusCode::InvalidArgument,
-                format!("`ttl` is invalid: {:?}", err),
-            )
+            LedgerError::invalid_argument("ttl", format!("`ttl` is invalid: {:?}", err))
         })?;
 
+        let cipher_suite = HpkeCipherSuite::from_i32(request.cipher_suite)
+            .map(Self::parse_cipher_suite)
+            .ok_or_else(|| {
+                LedgerError::invalid_argument("cipher_suite", "`cipher_suite` is invalid")
+            })?;
+
         // The expiration time cannot overflow because proto Timestamps and Durations are signed
         // but Rust's Durations are unsigned.
         let expiration = self.current_time + ttl;
@@ -189,12 +694,15 @@ impl LedgerService {
         // The code that applies the event must ensure that there is no collision.
         let mut key_id = vec![0u8; 4];
         while {
-            OsRng.fill_bytes(key_id.as_mut_slice());
+            fill_random(key_id.as_mut_slice());
             self.per_key_ledgers.contains_key(&key_id)
         } {}
 
         // Construct a new keypair.
-        let (private_key, cose_public_key) = cfc_crypto::gen_keypair(&key_id);
+        let mut ikm = vec![0u8; 32];
+        fill_os_random(ikm.as_mut_slice());
+        let (private_key, cose_public_key) =
+            cfc_crypto::derive_keypair(&key_id, &ikm, cipher_suite);
         let public_key = self.build_cwt(cose_public_key, expiration).map_err(|err| {
             micro_rpc::Status::new_with_message(
                 micro_rpc::StatusCode::Internal,
@@ -206,11 +714,133 @@ impl LedgerService {
         Ok(CreateKeyEvent {
             event_time: Some(Self::format_timestamp(&self.current_time)?),
             public_key,
-            private_key: private_key.to_bytes().to_vec(),
+            wrapped_private_key: self.wrap_private_key(&key_id, &private_key)?,
             expiration: Some(Self::format_timestamp(&expiration)?),
+            namespace: request.namespace,
+            ..Default::default()
         })
     }
 
+    /// Enables the two-phase key deletion: deleted keys are disabled first, i.e. no longer
+    /// listed and only authorize access to the existing blobs, and purged once the grace
+    /// period elapses.
+    pub fn set_key_deletion_grace_period(
+        &mut self,
+        grace_period: prost_types::Duration,
+    ) -> Result<(), micro_rpc::Status> {
+        self.key_deletion_grace_period =
+            Some(Self::parse_duration(&Some(grace_period)).map_err(|err| {
+                micro_rpc::Status::new_with_message(
+                    micro_rpc::StatusCode::InvalidArgument,
+                    format!("`key_deletion_grace_period` is invalid: {:?}", err),
+                )
+            })?);
+        Ok(())
+    }
+
+    /// Sets the retention of the idempotency tokens of the authorized accesses.
+    pub fn set_idempotency_token_ttl(
+        &mut self,
+        ttl: prost_types::Duration,
+    ) -> Result<(), micro_rpc::Status> {
+        let ttl = Self::parse_duration(&Some(ttl)).map_err(|err| {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                format!("`idempotency_token_ttl` is invalid: {:?}", err),
+            )
+        })?;
+        self.idempotency_cache.set_ttl(ttl);
+        Ok(())
+    }
+
+    /// Sets the number of the most recent audit log entries that are retained.
+    pub fn set_audit_log_retained_entries(&mut self, retained_entries: u32) {
+        self.audit_log
+            .set_retained_entries(retained_entries.try_into().unwrap());
+    }
+
+    /// Produces the event that creates the successor key if the key rotation is enabled
+    /// and the latest expiring key expires within the overlap as of the current time.
+    /// Keys are rotated separately in the default namespace and in each namespace that has
+    /// keys that aren't disabled, one namespace at a time.
+    pub fn produce_rotate_key_event(
+        &mut self,
+        fill_random: &dyn Fn(&mut [u8]),
+    ) -> Result<Option<CreateKeyEvent>, micro_rpc::Status> {
+        let Some(key_rotation) = &self.key_rotation else {
+            return Ok(None);
+        };
+        let mut latest_keys: BTreeMap<&str, Option<&PerKeyLedger>> = BTreeMap::new();
+        latest_keys.insert("", None);
+        for per_key_ledger in self
+            .per_key_ledgers
+            .values()
+            .filter(|per_key_ledger| per_key_ledger.purge_time.is_none())
+        {
+            let latest_key = latest_keys
+                .entry(per_key_ledger.namespace.as_str())
+                .or_default();
+            if latest_key.map_or(true, |latest_key| {
+                latest_key.expiration < per_key_ledger.expiration
+            }) {
+                *latest_key = Some(per_key_ledger);
+            }
+        }
+        let Some((namespace, latest_key)) = latest_keys.into_iter().find(|(_, latest_key)| {
+            latest_key.map_or(true, |per_key_ledger| {
+                per_key_ledger.expiration <= self.current_time.saturating_add(key_rotation.overlap)
+            })
+        }) else {
+            return Ok(None);
+        };
+        let namespace = namespace.to_owned();
+        // The successor keeps the cipher suite of the key it succeeds.
+        let cipher_suite = latest_key.map_or(CipherSuite::default(), |per_key_ledger| {
+            per_key_ledger.cipher_suite
+        });
+        // Avoid minting several successors while the proposed rotation is being
+        // replicated. The rotation is proposed again if it hasn't been applied within
+        // half of the overlap, e.g. because the proposal has been lost.
+        if self.rotation_proposed_at.is_some_and(|proposed_at| {
+            proposed_at.saturating_add(key_rotation.overlap / 2) > self.current_time
+        }) {
+            return Ok(None);
+        }
+
+        let ttl = key_rotation.period.saturating_add(key_rotation.overlap);
+        let rotate_key_event = self.produce_create_key_event(
+            CreateKeyRequest {
+                now: None,
+                ttl: Some(prost_types::Duration {
+                    seconds: ttl.as_secs().try_into().unwrap_or(i64::MAX),
+                    nanos: ttl.subsec_nanos().try_into().unwrap(),
+                }),
+                cipher_suite: Self::format_cipher_suite(cipher_suite).into(),
+                namespace,
+            },
+            fill_random,
+        )?;
+        self.rotation_proposed_at = Some(self.current_time);
+        Ok(Some(rotate_key_event))
+    }
+
+    pub fn apply_rotate_key_event(
+        &mut self,
+        event: CreateKeyEvent,
+    ) -> Result<CreateKeyResponse, micro_rpc::Status> {
+        self.rotation_proposed_at = None;
+        self.apply_create_key_event(event)
+    }
+
+    // Rotates the key locally if the rotation is due, only used when the ledger is not
+    // replicated.
+    fn rotate_key_if_due(&mut self) -> Result<(), micro_rpc::Status> {
+        if let Some(rotate_key_event) = self.produce_rotate_key_event(&fill_os_random)? {
+            self.apply_rotate_key_event(rotate_key_event)?;
+        }
+        Ok(())
+    }
+
     pub fn apply_create_key_event(
         &mut self,
         event: CreateKeyEvent,
@@ -230,15 +860,20 @@ impl LedgerService {
             )
         })?;
 
-        // Extract the key id from the CoseKey inside the public key CWT.
-        let key_id = extract_key_from_cwt(&event.public_key)
-            .map(|key| key.key_id)
-            .map_err(|err| {
-                micro_rpc::Status::new_with_message(
-                    micro_rpc::StatusCode::InvalidArgument,
-                    format!("public_key is invalid: {:?}", err),
-                )
-            })?;
+        // Extract the CoseKey inside the public key CWT.
+        let cose_public_key = extract_key_from_cwt(&event.public_key).map_err(|err| {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                format!("public_key is invalid: {:?}", err),
+            )
+        })?;
+        let key_id = cose_public_key.key_id.clone();
+        let cipher_suite = CipherSuite::from_public_key(&cose_public_key).map_err(|err| {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                format!("public_key is invalid: {:?}", err),
+            )
+        })?;
 
         // Verify that there is no key_id collision
         if self.per_key_ledgers.contains_key(&key_id) {
@@ -249,12 +884,17 @@ impl LedgerService {
         }
 
         let public_key = event.public_key;
-        let private_key = PrivateKey::from_bytes(&event.private_key).map_err(|err| {
-            micro_rpc::Status::new_with_message(
+        let private_key =
+            self.unwrap_private_key(&key_id, &event.wrapped_private_key, &event.private_key)?;
+
+        // Verify that the keypair is consistent, otherwise the replicas would
+        // hand out a public key that they cannot decrypt with.
+        if cfc_crypto::get_public_key(&key_id, &private_key, cipher_suite) != cose_public_key {
+            return Err(micro_rpc::Status::new_with_message(
                 micro_rpc::StatusCode::InvalidArgument,
-                format!("failed to parse private_key: {:?}", err),
-            )
-        })?;
+                "private_key does not match public_key",
+            ));
+        }
 
         // Insert keys
         self.per_key_ledgers.insert(
@@ -262,13 +902,19 @@ impl LedgerService {
             PerKeyLedger {
                 private_key,
                 public_key: public_key.clone(),
+                cipher_suite,
+                namespace: event.namespace,
+                issued_at: self.current_time,
                 expiration,
+                purge_time: None,
                 budget_tracker: budget::BudgetTracker::new(),
+                usage: KeyUsage::default(),
             },
         );
 
         Ok(CreateKeyResponse {
             public_key,
+            cipher_suite: Self::format_cipher_suite(cipher_suite).into(),
             attestation_evidence: Some(self.evidence.clone()),
         })
     }
@@ -276,92 +922,580 @@ impl LedgerService {
     pub fn attest_and_produce_authorize_access_event(
         &mut self,
         request: AuthorizeAccessRequest,
-    ) -> Result<AuthorizeAccessEvent, micro_rpc::Status> {
-        self.update_current_time(&request.now).map_err(|err| {
-            micro_rpc::Status::new_with_message(
-                micro_rpc::StatusCode::InvalidArgument,
-                format!("`now` is invalid: {:?}", err),
-            )
+    ) -> Result<AuthorizeAccessEvent, LedgerError> {
+        let now = self.request_time(&request.now)?;
+        self.update_current_time(&now).map_err(|err| {
+            LedgerError::invalid_argument("now", format!("`now` is invalid: {:?}", err))
         })?;
 
         // Verify the attestation and compute the properties of the requesting application.
-        let (recipient_app, _) = attestation::verify_attestation(
-            &request.recipient_public_key,
-            request.recipient_attestation_evidence.as_ref(),
-            request.recipient_attestation_endorsements.as_ref(),
-            &request.recipient_tag,
-        )
-        .map_err(|err| {
-            micro_rpc::Status::new_with_message(
-                micro_rpc::StatusCode::InvalidArgument,
-                format!("attestation validation failed: {:?}", err),
+        let (recipient_app, _) = self
+            .recipient_verifier
+            .verify(
+                &request.recipient_public_key,
+                request.recipient_attestation_evidence.as_ref(),
+                request.recipient_attestation_endorsements.as_ref(),
+                &request.recipient_tag,
             )
-        })?;
+            .map_err(|err| {
+                LedgerError::new(
+                    micro_rpc::StatusCode::InvalidArgument,
+                    format!("attestation validation failed: {:?}", err),
+                    Reason::AttestationFailed,
+                )
+            })?;
+        let attested_recipient = RateLimiter::attested_recipient(&recipient_app);
+
+        let mut event = AuthorizeAccessEvent {
+            event_time: Some(Self::format_timestamp(&self.current_time)?),
+            access_policy: request.access_policy,
+            transform_index: 0,
+            blob_header: request.blob_header,
+            encapsulated_key: request.encapsulated_key,
+            encrypted_symmetric_key: request.encrypted_symmetric_key,
+            recipient_public_key: request.recipient_public_key,
+            recipient_nonce: request.recipient_nonce,
+            recipient_evidence_sha256: Self::evidence_sha256(
+                request.recipient_attestation_evidence.as_ref(),
+            ),
+            recipient_tag: request.recipient_tag,
+            idempotency_token: request.idempotency_token,
+            namespace: request.namespace,
+            attested_recipient: Some(attested_recipient.clone()),
+        };
+
+        // A retried authorization is answered from the idempotency cache when the event is
+        // applied, so it is neither rate limited nor checked against the budget again.
+        if let Some((transform_index, _)) = self
+            .idempotency_cache
+            .get(
+                &event.namespace,
+                &event.idempotency_token,
+                &Self::authorization_sha256(&event),
+                self.current_time,
+            )
+            .map_err(Self::idempotency_error)?
+        {
+            event.transform_index = transform_index;
+            return Ok(event);
+        }
+
+        // Rate limits are checked as soon as the recipient is identified, the access is
+        // counted once the event is applied.
+        self.rate_limiter
+            .check(&attested_recipient, &event.namespace, 1, self.current_time)
+            .map_err(|err| LedgerError::from(err).with_reason(Reason::RateLimited))?;
+
+        let transform_index = self
+            .find_authorized_transform(
+                &event.namespace,
+                &event.access_policy,
+                &event.blob_header,
+                &recipient_app,
+                self.current_time,
+            )
+            .map_err(|err| {
+                // The denial is counted once it is replicated.
+                self.access_denied_events.push(AccessDeniedEvent {
+                    event_time: event.event_time.clone(),
+                    namespace: event.namespace.clone(),
+                    blob_header: event.blob_header.clone(),
+                    budget_exhausted: err.code == micro_rpc::StatusCode::ResourceExhausted,
+                });
+                err
+            })?;
+        event.transform_index = transform_index.try_into().unwrap();
+        Ok(event)
+    }
+
+    /// Computes the digest identifying the authorization requested by the event regardless
+    /// of the time of the request and the transform authorizing the access.
+    fn authorization_sha256(event: &AuthorizeAccessEvent) -> Vec<u8> {
+        let event = AuthorizeAccessEvent {
+            event_time: None,
+            transform_index: 0,
+            idempotency_token: Vec::new(),
+            ..event.clone()
+        };
+        Sha256::digest(event.encode_to_vec()).to_vec()
+    }
+
+    /// Attaches the details to the error of a lookup in the idempotency cache, which only
+    /// fails if the token has been used for a different request.
+    fn idempotency_error(err: micro_rpc::Status) -> LedgerError {
+        LedgerError::from(err)
+            .with_reason(Reason::InvalidArgument)
+            .with_field("idempotency_token")
+    }
 
+    /// Attaches the details to the error of a budget check or update. The access is denied if
+    /// the recipient isn't authorized by the policy; otherwise no budget remains for it.
+    fn budget_error(err: micro_rpc::Status, key_expiration: Duration) -> LedgerError {
+        let err = LedgerError::from(err).with_key_expiration(key_expiration);
+        match err.code {
+            micro_rpc::StatusCode::InvalidArgument => err
+                .with_reason(Reason::InvalidArgument)
+                .with_field("access_policy"),
+            micro_rpc::StatusCode::FailedPrecondition => err.with_reason(Reason::AccessDenied),
+            _ => err
+                .with_reason(Reason::BudgetExhausted)
+                .with_remaining_budget(0),
+        }
+    }
+
+    /// Finds the transform of the access policy that authorizes the attested recipient to
+    /// access the blob at the given time, verifying that there is still budget remaining.
+    fn find_authorized_transform(
+        &self,
+        namespace: &str,
+        access_policy: &[u8],
+        blob_header: &[u8],
+        recipient_app: &attestation::Application,
+        now: Duration,
+    ) -> Result<usize, LedgerError> {
         // Decode the blob header and access policy. Since the access policy was provided by an
         // untrusted source, we need to verify it by checking the hash in the header. The header is
         // also unverified at this point, but will be authenticated later when it's used as the
         // associated data for re-wrapping the symmetric key. This ensures that any request that
         // uses a different header or access policy than what was approved by the client will fail.
-        let header = BlobHeader::decode(request.blob_header.as_ref()).map_err(|err| {
-            micro_rpc::Status::new_with_message(
-                micro_rpc::StatusCode::InvalidArgument,
+        let header = BlobHeader::decode(blob_header).map_err(|err| {
+            LedgerError::invalid_argument(
+                "blob_header",
                 format!("failed to parse blob header: {:?}", err),
             )
         })?;
 
-        if Sha256::digest(&request.access_policy).as_slice() != header.access_policy_sha256 {
-            return Err(micro_rpc::Status::new_with_message(
-                micro_rpc::StatusCode::InvalidArgument,
+        if Sha256::digest(access_policy).as_slice() != header.access_policy_sha256 {
+            return Err(LedgerError::invalid_argument(
+                "access_policy",
                 "access policy does not match blob header",
             ));
         }
+        Self::check_blob_expiration(&header, now)?;
 
-        let access_policy =
-            DataAccessPolicy::decode(request.access_policy.as_ref()).map_err(|err| {
-                micro_rpc::Status::new_with_message(
-                    micro_rpc::StatusCode::InvalidArgument,
+        let access_policy = DataAccessPolicy::decode(access_policy).map_err(|err| {
+            LedgerError::invalid_argument(
+                "access_policy",
+                format!("failed to parse access policy: {:?}", err),
+            )
+        })?;
+
+        // Find the right per-key ledger.
+        let per_key_ledger = self.get_per_key_ledger(namespace, &header.key_id)?;
+        per_key_ledger.check_cipher_suite(&header)?;
+        Self::check_new_access_policy(
+            per_key_ledger,
+            &access_policy,
+            &header.access_policy_sha256,
+        )?;
+
+        // Verify that the access is authorized and that there is still budget remaining.
+        per_key_ledger
+            .budget_tracker
+            .find_matching_transform(
+                &header.blob_id,
+                header.access_policy_node_id,
+                &access_policy,
+                &header.access_policy_sha256,
+                recipient_app,
+                now,
+            )
+            .map_err(|err| Self::budget_error(err, per_key_ledger.expiration))
+    }
+
+    /// Validates the access policy unless the per-key ledger already tracks budgets subject to
+    /// it. Such policies were accepted before they were validated, so they remain usable for
+    /// the blobs already encrypted under them.
+    fn check_new_access_policy(
+        per_key_ledger: &PerKeyLedger,
+        access_policy: &DataAccessPolicy,
+        access_policy_sha256: &[u8],
+    ) -> Result<(), LedgerError> {
+        if per_key_ledger
+            .budget_tracker
+            .is_policy_tracked(access_policy_sha256)
+        {
+            return Ok(());
+        }
+        Self::validate_access_policy(access_policy).map_err(|err| {
+            LedgerError::from(err)
+                .with_reason(Reason::InvalidArgument)
+                .with_field("access_policy")
+        })
+    }
+
+    /// Checks that the transforms of the access policy form a valid pipeline: derived nodes
+    /// are never 0, every derived node consumed by a transform is produced by another one and
+    /// there are no cycles, so that each pipeline ends after a finite number of stages. Named
+    /// shared budgets must have unique names.
+    fn validate_access_policy(policy: &DataAccessPolicy) -> Result<(), micro_rpc::Status> {
+        let invalid = |message: &str| {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                format!("access policy is invalid: {}", message),
+            )
+        };
+
+        let mut budget_names = BTreeSet::new();
+        if !policy
+            .shared_access_budgets
+            .iter()
+            .filter_map(|budget| budget.name.as_deref())
+            .all(|name| budget_names.insert(name))
+        {
+            return Err(invalid("`shared_access_budgets` names must be unique"));
+        }
+
+        let mut edges = BTreeMap::<u32, Vec<u32>>::new();
+        for transform in &policy.transforms {
+            match transform.dest {
+                Some(0) => return Err(invalid("`dest` must not be 0")),
+                Some(dest) => edges.entry(transform.src).or_default().push(dest),
+                None => {}
+            }
+        }
+        let produced: BTreeSet<u32> = edges.values().flatten().copied().collect();
+        if policy
+            .transforms
+            .iter()
+            .any(|transform| transform.src != 0 && !produced.contains(&transform.src))
+        {
+            return Err(invalid("`src` is not produced by any transform"));
+        }
+
+        // Visit the nodes in topological order; the edges that are never visited are part of
+        // a cycle.
+        let mut in_degrees = BTreeMap::<u32, usize>::new();
+        for dest in edges.values().flatten() {
+            *in_degrees.entry(*dest).or_default() += 1;
+        }
+        let mut ready: Vec<u32> = edges
+            .keys()
+            .filter(|node| !in_degrees.contains_key(node))
+            .copied()
+            .collect();
+        let mut unvisited_edges: usize = edges.values().map(Vec::len).sum();
+        while let Some(node) = ready.pop() {
+            for dest in edges.get(&node).into_iter().flatten() {
+                unvisited_edges -= 1;
+                let in_degree = in_degrees.get_mut(dest).unwrap();
+                *in_degree -= 1;
+                if *in_degree == 0 {
+                    ready.push(*dest);
+                }
+            }
+        }
+        if unvisited_edges > 0 {
+            return Err(invalid("transforms form a cycle"));
+        }
+        Ok(())
+    }
+
+    /// Returns the SHA-256 digest of the serialized attestation evidence, empty if no
+    /// evidence was provided.
+    fn evidence_sha256(evidence: Option<&Evidence>) -> Vec<u8> {
+        evidence.map_or_else(Vec::new, |evidence| {
+            Sha256::digest(evidence.encode_to_vec()).to_vec()
+        })
+    }
+
+    pub fn apply_authorize_access_event(
+        &mut self,
+        event: AuthorizeAccessEvent,
+    ) -> Result<AuthorizeAccessResponse, LedgerError> {
+        // Update the current time.
+        self.update_current_time(&event.event_time).map_err(|err| {
+            LedgerError::invalid_argument("now", format!("event_time is invalid: {:?}", err))
+        })?;
+
+        // Return the same response to the retries of an authorization without updating the
+        // budget again, unless access has been lost since it was granted.
+        let request_sha256 = Self::authorization_sha256(&event);
+        if let Some((transform_index, response)) = self
+            .idempotency_cache
+            .get(
+                &event.namespace,
+                &event.idempotency_token,
+                &request_sha256,
+                self.current_time,
+            )
+            .map_err(Self::idempotency_error)?
+        {
+            let response = response.clone();
+            self.check_retained_access(&event.namespace, &event.blob_header, transform_index)?;
+            return Ok(response);
+        }
+
+        // Accesses are counted at the time of the event so that all replicas count them
+        // identically.
+        let event_time = Self::parse_timestamp(&event.event_time).map_err(|err| {
+            LedgerError::invalid_argument("now", format!("event_time is invalid: {:?}", err))
+        })?;
+        self.rate_limiter
+            .check_and_count(
+                &event.attested_recipient.clone().unwrap_or_default(),
+                &event.namespace,
+                1,
+                event_time,
+            )
+            .map_err(|err| LedgerError::from(err).with_reason(Reason::RateLimited))?;
+
+        let recipient_public_key =
+            extract_key_from_cwt(&event.recipient_public_key).map_err(|err| {
+                LedgerError::invalid_argument(
+                    "recipient_public_key",
+                    format!("public_key is invalid: {:?}", err),
+                )
+            })?;
+
+        let access_policy =
+            DataAccessPolicy::decode(event.access_policy.as_ref()).map_err(|err| {
+                LedgerError::invalid_argument(
+                    "access_policy",
                     format!("failed to parse access policy: {:?}", err),
                 )
             })?;
 
-        // Find the right per-key ledger.
+        let blob_header = event.blob_header.clone();
+        let response = self
+            .apply_blob_access(
+                &event.namespace,
+                &access_policy,
+                &recipient_public_key,
+                &event.recipient_public_key,
+                &event.recipient_tag,
+                &event.recipient_evidence_sha256,
+                authorize_access_batch_event::BlobAccess {
+                    blob_header: event.blob_header,
+                    encapsulated_key: event.encapsulated_key,
+                    encrypted_symmetric_key: event.encrypted_symmetric_key,
+                    recipient_nonce: event.recipient_nonce,
+                    transform_index: event.transform_index,
+                    error: None,
+                },
+            )
+            .map_err(|err| {
+                self.record_denial(
+                    &event.namespace,
+                    &blob_header,
+                    err.code == micro_rpc::StatusCode::ResourceExhausted,
+                );
+                err
+            })?;
+
+        self.idempotency_cache.insert(
+            event.namespace,
+            event.idempotency_token,
+            request_sha256,
+            event.transform_index,
+            response.clone(),
+            self.current_time,
+        );
+        Ok(response)
+    }
+
+    /// Checks that access to the blob granted through the transform with the given index
+    /// still holds: the blob hasn't expired, its key hasn't been deleted, and access to it
+    /// hasn't been revoked. The budget isn't checked since it was spent on the access.
+    fn check_retained_access(
+        &self,
+        namespace: &str,
+        blob_header: &[u8],
+        transform_index: u64,
+    ) -> Result<(), LedgerError> {
+        let header = BlobHeader::decode(blob_header).map_err(|err| {
+            LedgerError::invalid_argument(
+                "blob_header",
+                format!("failed to parse blob header: {:?}", err),
+            )
+        })?;
+        Self::check_blob_expiration(&header, self.current_time)?;
+        let per_key_ledger = self.get_per_key_ledger(namespace, &header.key_id)?;
+        per_key_ledger
+            .budget_tracker
+            .check_not_revoked(&header.blob_id, transform_index.try_into().unwrap())
+            .map_err(|err| Self::budget_error(err, per_key_ledger.expiration))
+    }
+
+    /// Revokes access to the blob, either through a single transform or entirely by
+    /// consuming its budget.
+    pub fn apply_revoke_access(
+        &mut self,
+        request: RevokeAccessRequest,
+    ) -> Result<RevokeAccessResponse, LedgerError> {
         let per_key_ledger = self
-            .per_key_ledgers
-            .get_mut(&header.key_id)
-            .ok_or_else(|| {
+            .get_per_key_ledger_mut(&request.namespace, &request.key_id)
+            .map_err(|err| err.with_field("key_id"))?;
+
+        let action = match request.transform_index {
+            Some(transform_index) => {
+                per_key_ledger
+                    .budget_tracker
+                    .revoke_transform(&request.blob_id, transform_index);
+                audit_log_entry::Action::TransformRevoked
+            }
+            None => {
+                per_key_ledger
+                    .budget_tracker
+                    .consume_budget(&request.blob_id);
+                audit_log_entry::Action::BudgetConsumed
+            }
+        };
+
+        self.audit_log.append(AuditLogEntry {
+            event_time: Some(Self::format_timestamp(&self.current_time)?),
+            key_id: request.key_id,
+            blob_id: request.blob_id,
+            transform_index: request.transform_index.unwrap_or_default().into(),
+            namespace: request.namespace,
+            action: action.into(),
+            ..Default::default()
+        });
+        Ok(RevokeAccessResponse {})
+    }
+
+    /// Attests the recipient and produces the event that authorizes access to a batch of
+    /// blobs subject to the same access policy. The attestation is verified and the policy is
+    /// parsed once for the whole batch. Failures of individual blobs are recorded in the
+    /// event rather than failing the batch.
+    pub fn attest_and_produce_authorize_access_batch_event(
+        &mut self,
+        request: AuthorizeAccessBatchRequest,
+    ) -> Result<AuthorizeAccessBatchEvent, micro_rpc::Status> {
+        let now = self.request_time(&request.now)?;
+        self.update_current_time(&now).map_err(|err| {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                format!("`now` is invalid: {:?}", err),
+            )
+        })?;
+
+        // Verify the attestation and compute the properties of the requesting application.
+        let (recipient_app, _) = self
+            .recipient_verifier
+            .verify(
+                &request.recipient_public_key,
+                request.recipient_attestation_evidence.as_ref(),
+                request.recipient_attestation_endorsements.as_ref(),
+                &request.recipient_tag,
+            )
+            .map_err(|err| {
                 micro_rpc::Status::new_with_message(
-                    micro_rpc::StatusCode::NotFound,
-                    "public key not found",
+                    micro_rpc::StatusCode::InvalidArgument,
+                    format!("attestation validation failed: {:?}", err),
                 )
             })?;
-
-        // Verify that the access is authorized and that there is still budget remaining.
-        let transform_index = per_key_ledger.budget_tracker.find_matching_transform(
-            &header.blob_id,
-            header.access_policy_node_id,
-            &access_policy,
-            &header.access_policy_sha256,
-            &recipient_app,
+        let attested_recipient = RateLimiter::attested_recipient(&recipient_app);
+        self.rate_limiter.check(
+            &attested_recipient,
+            &request.namespace,
+            request.blobs.len().try_into().unwrap(),
             self.current_time,
         )?;
 
-        Ok(AuthorizeAccessEvent {
+        // The access policy is verified against the hash in each blob header.
+        let access_policy =
+            DataAccessPolicy::decode(request.access_policy.as_ref()).map_err(|err| {
+                micro_rpc::Status::new_with_message(
+                    micro_rpc::StatusCode::InvalidArgument,
+                    format!("failed to parse access policy: {:?}", err),
+                )
+            })?;
+        let access_policy_sha256 = Sha256::digest(&request.access_policy).to_vec();
+
+        // Evaluate the application matchers once for the whole batch.
+        let app_matches: Vec<bool> = access_policy
+            .transforms
+            .iter()
+            .map(|transform| recipient_app.matches(&transform.application, self.current_time))
+            .collect();
+
+        let blobs = request
+            .blobs
+            .into_iter()
+            .map(|blob| {
+                let (transform_index, error) = match self.find_blob_transform(
+                    &request.namespace,
+                    &blob.blob_header,
+                    &access_policy,
+                    &access_policy_sha256,
+                    &app_matches,
+                ) {
+                    Ok(transform_index) => (transform_index, None),
+                    Err(err) => (0, Some(Self::format_status(err))),
+                };
+                authorize_access_batch_event::BlobAccess {
+                    blob_header: blob.blob_header,
+                    encapsulated_key: blob.encapsulated_key,
+                    encrypted_symmetric_key: blob.encrypted_symmetric_key,
+                    recipient_nonce: blob.recipient_nonce,
+                    transform_index,
+                    error,
+                }
+            })
+            .collect();
+
+        Ok(AuthorizeAccessBatchEvent {
             event_time: Some(Self::format_timestamp(&self.current_time)?),
             access_policy: request.access_policy,
-            transform_index: transform_index.try_into().unwrap(),
-            blob_header: request.blob_header,
-            encapsulated_key: request.encapsulated_key,
-            encrypted_symmetric_key: request.encrypted_symmetric_key,
             recipient_public_key: request.recipient_public_key,
-            recipient_nonce: request.recipient_nonce,
+            blobs,
+            recipient_evidence_sha256: Self::evidence_sha256(
+                request.recipient_attestation_evidence.as_ref(),
+            ),
+            recipient_tag: request.recipient_tag,
+            namespace: request.namespace,
+            attested_recipient: Some(attested_recipient),
         })
     }
 
-    pub fn apply_authorize_access_event(
+    /// Finds the transform of the access policy that authorizes access to the blob from
+    /// a batch.
+    fn find_blob_transform(
+        &self,
+        namespace: &str,
+        blob_header: &[u8],
+        access_policy: &DataAccessPolicy,
+        access_policy_sha256: &[u8],
+        app_matches: &[bool],
+    ) -> Result<u64, LedgerError> {
+        let header = BlobHeader::decode(blob_header).map_err(|err| {
+            LedgerError::invalid_argument(
+                "blob_header",
+                format!("failed to parse blob header: {:?}", err),
+            )
+        })?;
+
+        if header.access_policy_sha256 != access_policy_sha256 {
+            return Err(LedgerError::invalid_argument(
+                "access_policy",
+                "access policy does not match blob header",
+            ));
+        }
+        Self::check_blob_expiration(&header, self.current_time)?;
+
+        let per_key_ledger = self.get_per_key_ledger(namespace, &header.key_id)?;
+        per_key_ledger.check_cipher_suite(&header)?;
+        Self::check_new_access_policy(per_key_ledger, access_policy, access_policy_sha256)?;
+
+        let transform_index = per_key_ledger
+            .budget_tracker
+            .find_matching_transform_with(
+                &header.blob_id,
+                header.access_policy_node_id,
+                access_policy,
+                access_policy_sha256,
+                self.current_time,
+                &|i| app_matches[i],
+            )
+            .map_err(|err| Self::budget_error(err, per_key_ledger.expiration))?;
+        Ok(transform_index.try_into().unwrap())
+    }
+
+    pub fn apply_authorize_access_batch_event(
         &mut self,
-        event: AuthorizeAccessEvent,
-    ) -> Result<AuthorizeAccessResponse, micro_rpc::Status> {
+        event: AuthorizeAccessBatchEvent,
+    ) -> Result<AuthorizeAccessBatchResponse, micro_rpc::Status> {
         // Update the current time.
         self.update_current_time(&event.event_time).map_err(|err| {
             micro_rpc::Status::new_with_message(
@@ -370,6 +1504,20 @@ impl LedgerService {
             )
         })?;
 
+        // Every blob of the batch is counted, as when the event was produced.
+        let event_time = Self::parse_timestamp(&event.event_time).map_err(|err| {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                format!("event_time is invalid: {:?}", err),
+            )
+        })?;
+        self.rate_limiter.check_and_count(
+            &event.attested_recipient.clone().unwrap_or_default(),
+            &event.namespace,
+            event.blobs.len().try_into().unwrap(),
+            event_time,
+        )?;
+
         let recipient_public_key =
             extract_key_from_cwt(&event.recipient_public_key).map_err(|err| {
                 micro_rpc::Status::new_with_message(
@@ -378,14 +1526,6 @@ impl LedgerService {
                 )
             })?;
 
-        // Decode the blob header and the access policy.
-        let header = BlobHeader::decode(event.blob_header.as_ref()).map_err(|err| {
-            micro_rpc::Status::new_with_message(
-                micro_rpc::StatusCode::InvalidArgument,
-                format!("failed to parse blob header: {:?}", err),
-            )
-        })?;
-
         let access_policy =
             DataAccessPolicy::decode(event.access_policy.as_ref()).map_err(|err| {
                 micro_rpc::Status::new_with_message(
@@ -394,32 +1534,152 @@ impl LedgerService {
                 )
             })?;
 
+        let mut results = Vec::with_capacity(event.blobs.len());
+        for mut blob in event.blobs {
+            let blob_header = blob.blob_header.clone();
+            let outcome = match blob.error.take() {
+                Some(error) => blob_result::Outcome::Error(error),
+                None => match self.apply_blob_access(
+                    &event.namespace,
+                    &access_policy,
+                    &recipient_public_key,
+                    &event.recipient_public_key,
+                    &event.recipient_tag,
+                    &event.recipient_evidence_sha256,
+                    blob,
+                ) {
+                    Ok(response) => blob_result::Outcome::Authorized(response),
+                    Err(err) => blob_result::Outcome::Error(Self::format_status(err)),
+                },
+            };
+            if let blob_result::Outcome::Error(error) = &outcome {
+                self.record_denial(
+                    &event.namespace,
+                    &blob_header,
+                    error.code == micro_rpc::StatusCode::ResourceExhausted as i32,
+                );
+            }
+            results.push(BlobResult {
+                outcome: Some(outcome),
+            });
+        }
+
+        Ok(AuthorizeAccessBatchResponse { results })
+    }
+
+    /// Gets the time as of which the keys are queried. Queries don't update the current
+    /// time, but the time they observe doesn't go back either.
+    fn query_time(
+        &self,
+        now: &Option<prost_types::Timestamp>,
+    ) -> Result<Duration, micro_rpc::Status> {
+        let now = Self::parse_timestamp(&self.request_time(now)?).map_err(|err| {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                format!("`now` is invalid: {:?}", err),
+            )
+        })?;
+        Ok(core::cmp::max(now, self.current_time))
+    }
+
+    /// Builds the public details of the key.
+    fn key_details(
+        key_id: &[u8],
+        per_key_ledger: &PerKeyLedger,
+    ) -> Result<KeyDetails, micro_rpc::Status> {
+        Ok(KeyDetails {
+            key_id: key_id.to_vec(),
+            public_key: per_key_ledger.public_key.clone(),
+            issued_at: Some(Self::format_timestamp(&per_key_ledger.issued_at)?),
+            expiration: Some(Self::format_timestamp(&per_key_ledger.expiration)?),
+            state: match per_key_ledger.purge_time {
+                Some(_) => KeyState::Disabled.into(),
+                None => KeyState::Active.into(),
+            },
+            purge_time: per_key_ledger
+                .purge_time
+                .as_ref()
+                .map(Self::format_timestamp)
+                .transpose()?,
+            cipher_suite: Self::format_cipher_suite(per_key_ledger.cipher_suite).into(),
+        })
+    }
+
+    /// Converts the error into its proto representation.
+    fn format_status(err: impl Into<LedgerError>) -> ledger_response::Status {
+        ledger_response::Status::from(err.into())
+    }
+
+    pub fn apply_access_denied_event(
+        &mut self,
+        event: AccessDeniedEvent,
+    ) -> Result<(), micro_rpc::Status> {
+        // Update the current time.
+        self.update_current_time(&event.event_time).map_err(|err| {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                format!("event_time is invalid: {:?}", err),
+            )
+        })?;
+        self.record_denial(&event.namespace, &event.blob_header, event.budget_exhausted);
+        Ok(())
+    }
+
+    /// Counts the denied access to the blob against the key the blob is encrypted with,
+    /// provided that the key is known within the namespace.
+    fn record_denial(&mut self, namespace: &str, blob_header: &[u8], budget_exhausted: bool) {
+        let Ok(header) = BlobHeader::decode(blob_header) else {
+            return;
+        };
+        if let Ok(per_key_ledger) = self.get_per_key_ledger_mut(namespace, &header.key_id) {
+            per_key_ledger.usage.denied += 1;
+            if budget_exhausted {
+                per_key_ledger.usage.budget_exhausted += 1;
+            }
+        }
+    }
+
+    /// Re-wraps the blob's symmetric key for the recipient and records the access in the
+    /// budget and the audit log.
+    fn apply_blob_access(
+        &mut self,
+        namespace: &str,
+        access_policy: &DataAccessPolicy,
+        recipient_public_key: &CoseKey,
+        recipient_public_key_cwt: &[u8],
+        recipient_tag: &str,
+        recipient_evidence_sha256: &[u8],
+        blob: authorize_access_batch_event::BlobAccess,
+    ) -> Result<AuthorizeAccessResponse, LedgerError> {
+        // Decode the blob header.
+        let header = BlobHeader::decode(blob.blob_header.as_ref()).map_err(|err| {
+            LedgerError::invalid_argument(
+                "blob_header",
+                format!("failed to parse blob header: {:?}", err),
+            )
+        })?;
+        let expiration = Self::check_blob_expiration(&header, self.current_time)?;
+
         // Find the right per-key ledger.
-        let per_key_ledger = self
-            .per_key_ledgers
-            .get_mut(&header.key_id)
-            .ok_or_else(|| {
-                micro_rpc::Status::new_with_message(
-                    micro_rpc::StatusCode::NotFound,
-                    "public key not found",
-                )
-            })?;
+        let per_key_ledger = self.get_per_key_ledger_mut(namespace, &header.key_id)?;
+        per_key_ledger.check_cipher_suite(&header)?;
 
         // Re-wrap the blob's symmetric key. This should be done before budgets are updated in case
         // there are decryption errors (e.g., due to invalid associated data).
         let wrap_associated_data =
-            [&per_key_ledger.public_key[..], &event.recipient_nonce[..]].concat();
+            [&per_key_ledger.public_key[..], &blob.recipient_nonce[..]].concat();
         let (encapsulated_key, encrypted_symmetric_key) = cfc_crypto::rewrap_symmetric_key(
-            &event.encrypted_symmetric_key,
-            &event.encapsulated_key,
+            &blob.encrypted_symmetric_key,
+            &blob.encapsulated_key,
             &per_key_ledger.private_key,
-            /* unwrap_associated_data= */ &event.blob_header,
-            &recipient_public_key,
+            per_key_ledger.cipher_suite,
+            /* unwrap_associated_data= */ &blob.blob_header,
+            recipient_public_key,
             &wrap_associated_data,
         )
         .map_err(|err| {
-            micro_rpc::Status::new_with_message(
-                micro_rpc::StatusCode::InvalidArgument,
+            LedgerError::invalid_argument(
+                "encrypted_symmetric_key",
                 format!("failed to re-wrap symmetric key: {:?}", err),
             )
         })?;
@@ -427,64 +1687,397 @@ impl LedgerService {
         // Update the budget. This can potentially fail if the budget is insufficient at
         // the time when the event is applied, which can be a short delay from from the
         // attestation and initially checking the budget.
-        per_key_ledger.budget_tracker.update_budget(
-            &header.blob_id,
-            event.transform_index.try_into().unwrap(),
-            &access_policy,
-            &header.access_policy_sha256,
-        )?;
+        let key_expiration = per_key_ledger.expiration;
+        per_key_ledger
+            .budget_tracker
+            .update_budget(
+                &header.blob_id,
+                blob.transform_index.try_into().unwrap(),
+                access_policy,
+                &header.access_policy_sha256,
+            )
+            .map_err(|err| Self::budget_error(err, key_expiration))?;
+        if let Some(expiration) = expiration {
+            per_key_ledger
+                .budget_tracker
+                .set_expiration(&header.blob_id, expiration);
+        }
+
+        per_key_ledger.usage.granted += 1;
 
-        Ok(AuthorizeAccessResponse {
+        let response = AuthorizeAccessResponse {
             encapsulated_key,
             encrypted_symmetric_key,
             reencryption_public_key: per_key_ledger.public_key.clone(),
-        })
-    }
-
-    pub fn save_snapshot(&self) -> Result<LedgerSnapshot, micro_rpc::Status> {
-        let mut snapshot = LedgerSnapshot::default();
+        };
 
-        snapshot.current_time = Some(Self::format_timestamp(&self.current_time)?);
+        self.audit_log.append(AuditLogEntry {
+            event_time: Some(Self::format_timestamp(&self.current_time)?),
+            key_id: header.key_id.clone(),
+            blob_id: header.blob_id.clone(),
+            access_policy_sha256: header.access_policy_sha256.clone(),
+            transform_index: blob.transform_index,
+            recipient_sha256: Sha256::digest(recipient_public_key_cwt).to_vec(),
+            recipient_tag: recipient_tag.into(),
+            recipient_evidence_sha256: recipient_evidence_sha256.to_vec(),
+            namespace: namespace.into(),
+            ..Default::default()
+        });
+        self.maybe_notify_access(&header, blob.transform_index, recipient_public_key_cwt)?;
 
-        for (key_id, per_key_ledger) in &self.per_key_ledgers {
-            snapshot.per_key_snapshots.push(PerKeySnapshot {
-                key_id: key_id.clone(),
-                public_key: per_key_ledger.public_key.clone(),
-                private_key: per_key_ledger.private_key.to_bytes().to_vec(),
-                expiration: Some(Self::format_timestamp(&per_key_ledger.expiration)?),
-                budgets: Some(per_key_ledger.budget_tracker.save_snapshot()),
-            });
-        }
-        Ok(snapshot)
+        Ok(response)
     }
 
-    pub fn load_snapshot(&mut self, snapshot: LedgerSnapshot) -> Result<(), micro_rpc::Status> {
-        self.current_time = Self::parse_timestamp(&snapshot.current_time).map_err(|err| {
+    /// Produces the event that rewraps the symmetric keys, stamped with the current time so
+    /// that all replicas apply it as of the same time regardless of their own clocks.
+    pub fn produce_rewrap_keys_event(
+        &mut self,
+        request: RewrapKeysRequest,
+    ) -> Result<RewrapKeysEvent, micro_rpc::Status> {
+        let now = self.request_time(&request.now)?;
+        self.update_current_time(&now).map_err(|err| {
             micro_rpc::Status::new_with_message(
                 micro_rpc::StatusCode::InvalidArgument,
-                format!("current_time is invalid: {:?}", err),
+                format!("`now` is invalid: {:?}", err),
             )
         })?;
-        self.per_key_ledgers.clear();
+        Ok(RewrapKeysEvent {
+            event_time: Some(Self::format_timestamp(&self.current_time)?),
+            request: Some(request),
+        })
+    }
 
-        for per_key_snapshot in snapshot.per_key_snapshots {
-            let mut per_key_ledger = PerKeyLedger {
-                private_key: PrivateKey::from_bytes(&per_key_snapshot.private_key).map_err(
-                    |err| {
-                        micro_rpc::Status::new_with_message(
-                            micro_rpc::StatusCode::InvalidArgument,
-                            format!("failed to parse private_key: {:?}", err),
-                        )
-                    },
-                )?,
-                public_key: per_key_snapshot.public_key,
-                expiration: Self::parse_timestamp(&per_key_snapshot.expiration).map_err(|err| {
+    pub fn apply_rewrap_keys_event(
+        &mut self,
+        event: RewrapKeysEvent,
+    ) -> Result<RewrapKeysResponse, micro_rpc::Status> {
+        // Update the current time.
+        self.update_current_time(&event.event_time).map_err(|err| {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                format!("event_time is invalid: {:?}", err),
+            )
+        })?;
+        let request = event.request.ok_or_else(|| {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                "request is missing",
+            )
+        })?;
+        if request.old_key_id == request.new_key_id {
+            return Err(micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                "`old_key_id` and `new_key_id` must differ",
+            ));
+        }
+
+        // The successor key must be able to authorize access for longer than the old key.
+        let old_expiration = self
+            .get_per_key_ledger(&request.namespace, &request.old_key_id)?
+            .expiration;
+        let new_per_key_ledger =
+            self.get_per_key_ledger(&request.namespace, &request.new_key_id)?;
+        new_per_key_ledger.check_enabled()?;
+        if new_per_key_ledger.expiration <= old_expiration {
+            return Err(micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::FailedPrecondition,
+                "`new_key_id` must expire after `old_key_id`",
+            ));
+        }
+        let new_public_key =
+            extract_key_from_cwt(&new_per_key_ledger.public_key).map_err(|err| {
+                micro_rpc::Status::new_with_message(
+                    micro_rpc::StatusCode::Internal,
+                    format!("public_key is invalid: {:?}", err),
+                )
+            })?;
+
+        let mut results = Vec::with_capacity(request.blobs.len());
+        for blob in request.blobs {
+            let outcome = match self.rewrap_blob_key(
+                &request.namespace,
+                &request.old_key_id,
+                &request.new_key_id,
+                &new_public_key,
+                blob,
+            ) {
+                Ok(rewrapped) => rewrap_keys_response::blob_result::Outcome::Rewrapped(rewrapped),
+                Err(err) => {
+                    rewrap_keys_response::blob_result::Outcome::Error(Self::format_status(err))
+                }
+            };
+            results.push(rewrap_keys_response::BlobResult {
+                outcome: Some(outcome),
+            });
+        }
+        Ok(RewrapKeysResponse { results })
+    }
+
+    /// Re-wraps the blob's symmetric key from the old key to the new key and moves the blob's
+    /// budget along with it.
+    fn rewrap_blob_key(
+        &mut self,
+        namespace: &str,
+        old_key_id: &[u8],
+        new_key_id: &[u8],
+        new_public_key: &CoseKey,
+        blob: rewrap_keys_request::Blob,
+    ) -> Result<rewrap_keys_request::Blob, micro_rpc::Status> {
+        let mut header = BlobHeader::decode(blob.blob_header.as_ref()).map_err(|err| {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                format!("failed to parse blob header: {:?}", err),
+            )
+        })?;
+        if header.key_id != old_key_id {
+            return Err(micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                "blob is not encrypted with `old_key_id`",
+            ));
+        }
+        let expiration = Self::check_blob_expiration(&header, self.current_time)?;
+
+        let old_per_key_ledger = self.get_per_key_ledger(namespace, old_key_id)?;
+        old_per_key_ledger.check_cipher_suite(&header)?;
+        let new_per_key_ledger = self.get_per_key_ledger(namespace, new_key_id)?;
+        if new_per_key_ledger
+            .budget_tracker
+            .is_tracked(&header.blob_id)
+        {
+            return Err(micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::FailedPrecondition,
+                "blob budget is already tracked by `new_key_id`",
+            ));
+        }
+
+        // Bind the symmetric key to the header that references the new key. The budget is
+        // moved only once the symmetric key has been re-wrapped successfully.
+        header.key_id = new_key_id.to_vec();
+        if header.cipher_suite != HpkeCipherSuite::Unspecified as i32 {
+            header.cipher_suite = Self::format_cipher_suite(new_per_key_ledger.cipher_suite) as i32;
+        }
+        let new_blob_header = header.encode_to_vec();
+        let (encapsulated_key, encrypted_symmetric_key) = cfc_crypto::rewrap_symmetric_key(
+            &blob.encrypted_symmetric_key,
+            &blob.encapsulated_key,
+            &old_per_key_ledger.private_key,
+            old_per_key_ledger.cipher_suite,
+            /* unwrap_associated_data= */ &blob.blob_header,
+            new_public_key,
+            /* wrap_associated_data= */ &new_blob_header,
+        )
+        .map_err(|err| {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                format!("failed to re-wrap symmetric key: {:?}", err),
+            )
+        })?;
+
+        let transfer = self
+            .get_per_key_ledger_mut(namespace, old_key_id)?
+            .budget_tracker
+            .take_blob(&header.blob_id, &header.access_policy_sha256)?;
+        let new_budget_tracker = &mut self
+            .get_per_key_ledger_mut(namespace, new_key_id)?
+            .budget_tracker;
+        new_budget_tracker.put_blob(&header.blob_id, &header.access_policy_sha256, transfer);
+        if let Some(expiration) = expiration {
+            new_budget_tracker.set_expiration(&header.blob_id, expiration);
+        }
+
+        Ok(rewrap_keys_request::Blob {
+            blob_header: new_blob_header,
+            encapsulated_key,
+            encrypted_symmetric_key,
+        })
+    }
+
+    pub fn save_snapshot(&self) -> Result<LedgerSnapshot, micro_rpc::Status> {
+        let mut snapshot = LedgerSnapshot {
+            version: LEDGER_SNAPSHOT_VERSION,
+            min_compatible_version: LEDGER_SNAPSHOT_MIN_COMPATIBLE_VERSION,
+            ..Default::default()
+        };
+
+        snapshot.current_time = Some(Self::format_timestamp(&self.current_time)?);
+
+        for (key_id, per_key_ledger) in &self.per_key_ledgers {
+            let budgets = per_key_ledger.budget_tracker.save_snapshot();
+            if budgets
+                .per_policy_snapshots
+                .iter()
+                .any(|per_policy_snapshot| !per_policy_snapshot.policy_access_budgets.is_empty())
+            {
+                snapshot.min_compatible_version = snapshot
+                    .min_compatible_version
+                    .max(POLICY_ACCESS_BUDGETS_MIN_COMPATIBLE_VERSION);
+            }
+            if !budgets.blob_expirations.is_empty() {
+                snapshot.min_compatible_version = snapshot
+                    .min_compatible_version
+                    .max(BLOB_EXPIRATIONS_MIN_COMPATIBLE_VERSION);
+            }
+            if !budgets.revoked_transforms.is_empty() {
+                snapshot.min_compatible_version = snapshot
+                    .min_compatible_version
+                    .max(REVOKED_TRANSFORMS_MIN_COMPATIBLE_VERSION);
+            }
+            snapshot.per_key_snapshots.push(PerKeySnapshot {
+                key_id: key_id.clone(),
+                public_key: per_key_ledger.public_key.clone(),
+                wrapped_private_key: self.wrap_private_key(key_id, &per_key_ledger.private_key)?,
+                expiration: Some(Self::format_timestamp(&per_key_ledger.expiration)?),
+                budgets: Some(budgets),
+                issued_at: Some(Self::format_timestamp(&per_key_ledger.issued_at)?),
+                purge_time: per_key_ledger
+                    .purge_time
+                    .as_ref()
+                    .map(Self::format_timestamp)
+                    .transpose()?,
+                namespace: per_key_ledger.namespace.clone(),
+                usage: (per_key_ledger.usage != KeyUsage::default()).then(|| KeyUsageSnapshot {
+                    granted: per_key_ledger.usage.granted,
+                    denied: per_key_ledger.usage.denied,
+                    budget_exhausted: per_key_ledger.usage.budget_exhausted,
+                }),
+                ..Default::default()
+            });
+            snapshot.min_compatible_version = snapshot
+                .min_compatible_version
+                .max(WRAPPED_PRIVATE_KEYS_MIN_COMPATIBLE_VERSION);
+            if per_key_ledger.usage != KeyUsage::default() {
+                snapshot.min_compatible_version = snapshot
+                    .min_compatible_version
+                    .max(KEY_USAGE_MIN_COMPATIBLE_VERSION);
+            }
+            if per_key_ledger.purge_time.is_some() {
+                snapshot.min_compatible_version = snapshot
+                    .min_compatible_version
+                    .max(PURGE_TIME_MIN_COMPATIBLE_VERSION);
+            }
+            if !per_key_ledger.namespace.is_empty() {
+                snapshot.min_compatible_version = snapshot
+                    .min_compatible_version
+                    .max(NAMESPACES_MIN_COMPATIBLE_VERSION);
+            }
+        }
+        (snapshot.audit_log_checkpoint, snapshot.audit_log) = self.audit_log.save_snapshot();
+        if !snapshot.audit_log.is_empty() {
+            snapshot.min_compatible_version = snapshot
+                .min_compatible_version
+                .max(AUDIT_LOG_MIN_COMPATIBLE_VERSION);
+        }
+        if snapshot.audit_log_checkpoint.is_some()
+            || snapshot
+                .audit_log
+                .iter()
+                .any(|entry| entry.action() != audit_log_entry::Action::AccessAuthorized)
+        {
+            snapshot.min_compatible_version = snapshot
+                .min_compatible_version
+                .max(AUDIT_LOG_CHECKPOINT_MIN_COMPATIBLE_VERSION);
+        }
+        snapshot.rate_limit_counters = self.rate_limiter.save_snapshot();
+        if !snapshot.rate_limit_counters.is_empty() {
+            snapshot.min_compatible_version = snapshot
+                .min_compatible_version
+                .max(RATE_LIMIT_COUNTERS_MIN_COMPATIBLE_VERSION);
+        }
+        snapshot.idempotency_tokens = self.idempotency_cache.save_snapshot();
+        if !snapshot.idempotency_tokens.is_empty() {
+            snapshot.min_compatible_version = snapshot
+                .min_compatible_version
+                .max(IDEMPOTENCY_TOKENS_MIN_COMPATIBLE_VERSION);
+        }
+        if snapshot
+            .idempotency_tokens
+            .iter()
+            .any(|token| !token.namespace.is_empty())
+        {
+            snapshot.min_compatible_version = snapshot
+                .min_compatible_version
+                .max(NAMESPACES_MIN_COMPATIBLE_VERSION);
+        }
+        Ok(snapshot)
+    }
+
+    pub fn load_snapshot(&mut self, snapshot: LedgerSnapshot) -> Result<(), micro_rpc::Status> {
+        // Snapshots of newer versions are loaded as long as they are declared compatible; the
+        // fields unknown to this version have already been dropped when the snapshot was
+        // decoded.
+        if snapshot.min_compatible_version > LEDGER_SNAPSHOT_VERSION {
+            return Err(micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                format!(
+                    "Unsupported snapshot version {} (compatible with {} and above)",
+                    snapshot.version, snapshot.min_compatible_version
+                ),
+            ));
+        }
+        self.current_time = Self::parse_timestamp(&snapshot.current_time).map_err(|err| {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                format!("current_time is invalid: {:?}", err),
+            )
+        })?;
+        self.per_key_ledgers.clear();
+        self.rotation_proposed_at = None;
+        self.audit_log
+            .load_snapshot(snapshot.audit_log_checkpoint, snapshot.audit_log)?;
+        self.idempotency_cache
+            .load_snapshot(snapshot.idempotency_tokens)?;
+        self.rate_limiter
+            .load_snapshot(snapshot.rate_limit_counters)?;
+
+        for per_key_snapshot in snapshot.per_key_snapshots {
+            let mut per_key_ledger = PerKeyLedger {
+                private_key: self.unwrap_private_key(
+                    &per_key_snapshot.key_id,
+                    &per_key_snapshot.wrapped_private_key,
+                    &per_key_snapshot.private_key,
+                )?,
+                cipher_suite: extract_key_from_cwt(&per_key_snapshot.public_key)
+                    .and_then(|cose_public_key| CipherSuite::from_public_key(&cose_public_key))
+                    .map_err(|err| {
+                        micro_rpc::Status::new_with_message(
+                            micro_rpc::StatusCode::InvalidArgument,
+                            format!("public_key is invalid: {:?}", err),
+                        )
+                    })?,
+                public_key: per_key_snapshot.public_key,
+                namespace: per_key_snapshot.namespace,
+                issued_at: Self::parse_timestamp(&per_key_snapshot.issued_at).map_err(|err| {
+                    micro_rpc::Status::new_with_message(
+                        micro_rpc::StatusCode::InvalidArgument,
+                        format!("issued_at is invalid: {:?}", err),
+                    )
+                })?,
+                purge_time: per_key_snapshot
+                    .purge_time
+                    .as_ref()
+                    .map(|purge_time| Self::parse_timestamp(&Some(purge_time.clone())))
+                    .transpose()
+                    .map_err(|err| {
+                        micro_rpc::Status::new_with_message(
+                            micro_rpc::StatusCode::InvalidArgument,
+                            format!("purge_time is invalid: {:?}", err),
+                        )
+                    })?,
+                expiration: Self::parse_timestamp(&per_key_snapshot.expiration).map_err(|err| {
                     micro_rpc::Status::new_with_message(
                         micro_rpc::StatusCode::InvalidArgument,
                         format!("expiration is invalid: {:?}", err),
                     )
                 })?,
                 budget_tracker: BudgetTracker::new(),
+                usage: per_key_snapshot
+                    .usage
+                    .map(|usage| KeyUsage {
+                        granted: usage.granted,
+                        denied: usage.denied,
+                        budget_exhausted: usage.budget_exhausted,
+                    })
+                    .unwrap_or_default(),
             };
             if per_key_snapshot.budgets.is_some() {
                 per_key_ledger
@@ -512,8 +2105,10 @@ impl Ledger for LedgerService {
         &mut self,
         request: CreateKeyRequest,
     ) -> Result<CreateKeyResponse, micro_rpc::Status> {
-        let create_key_event = self.produce_create_key_event(request)?;
-        self.apply_create_key_event(create_key_event)
+        let create_key_event = self.produce_create_key_event(request, &fill_os_random)?;
+        let create_key_response = self.apply_create_key_event(create_key_event)?;
+        self.rotate_key_if_due()?;
+        Ok(create_key_response)
     }
 
     fn delete_key(
@@ -529,41 +2124,172 @@ impl Ledger for LedgerService {
                     format!("public_key is invalid: {:?}", err),
                 )
             })?;
-        match self.per_key_ledgers.remove(&key_id) {
-            Some(_) => Ok(DeleteKeyResponse::default()),
-            None => Err(micro_rpc::Status::new_with_message(
-                micro_rpc::StatusCode::NotFound,
-                "public key not found",
-            )),
-        }
+        let grace_period = self.key_deletion_grace_period;
+        let current_time = self.current_time;
+        let per_key_ledger = self.get_per_key_ledger_mut(&request.namespace, &key_id)?;
+        let action = match grace_period {
+            None => {
+                self.per_key_ledgers.remove(&key_id);
+                audit_log_entry::Action::KeyDeleted
+            }
+            // Deleting the disabled key again doesn't extend the grace period.
+            Some(_) if per_key_ledger.purge_time.is_some() => {
+                return Ok(DeleteKeyResponse::default());
+            }
+            // Disable the key and purge it once the grace period elapses.
+            Some(grace_period) => {
+                per_key_ledger.purge_time = Some(current_time.saturating_add(grace_period));
+                audit_log_entry::Action::KeyDisabled
+            }
+        };
+
+        self.audit_log.append(AuditLogEntry {
+            event_time: Some(Self::format_timestamp(&self.current_time)?),
+            key_id,
+            namespace: request.namespace,
+            action: action.into(),
+            ..Default::default()
+        });
+        Ok(DeleteKeyResponse::default())
     }
 
     fn authorize_access(
         &mut self,
         request: AuthorizeAccessRequest,
     ) -> Result<AuthorizeAccessResponse, micro_rpc::Status> {
-        let authorize_access_event = self.attest_and_produce_authorize_access_event(request)?;
-        self.apply_authorize_access_event(authorize_access_event)
+        let authorize_access_event = match self.attest_and_produce_authorize_access_event(request) {
+            Ok(authorize_access_event) => authorize_access_event,
+            Err(err) => {
+                for access_denied_event in self.take_access_denied_events() {
+                    self.apply_access_denied_event(access_denied_event)?;
+                }
+                return Err(err.into());
+            }
+        };
+        let authorize_access_response =
+            self.apply_authorize_access_event(authorize_access_event)?;
+        self.rotate_key_if_due()?;
+        Ok(authorize_access_response)
     }
 
     fn revoke_access(
         &mut self,
         request: RevokeAccessRequest,
     ) -> Result<RevokeAccessResponse, micro_rpc::Status> {
-        let per_key_ledger = self
+        Ok(self.apply_revoke_access(request)?)
+    }
+
+    fn authorize_access_batch(
+        &mut self,
+        request: AuthorizeAccessBatchRequest,
+    ) -> Result<AuthorizeAccessBatchResponse, micro_rpc::Status> {
+        let authorize_access_batch_event =
+            self.attest_and_produce_authorize_access_batch_event(request)?;
+        let authorize_access_batch_response =
+            self.apply_authorize_access_batch_event(authorize_access_batch_event)?;
+        self.rotate_key_if_due()?;
+        Ok(authorize_access_batch_response)
+    }
+
+    fn list_keys(
+        &mut self,
+        request: ListKeysRequest,
+    ) -> Result<ListKeysResponse, micro_rpc::Status> {
+        let now = self.query_time(&request.now)?;
+        let keys = self
             .per_key_ledgers
-            .get_mut(&request.key_id)
+            .iter()
+            .filter(|(_, per_key_ledger)| {
+                per_key_ledger.namespace == request.namespace
+                    && per_key_ledger.is_live(now)
+                    && per_key_ledger.purge_time.is_none()
+            })
+            .map(|(key_id, per_key_ledger)| Self::key_details(key_id, per_key_ledger))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ListKeysResponse { keys })
+    }
+
+    fn get_key_details(
+        &mut self,
+        request: GetKeyDetailsRequest,
+    ) -> Result<GetKeyDetailsResponse, micro_rpc::Status> {
+        let now = self.query_time(&request.now)?;
+        let per_key_ledger = self
+            .get_per_key_ledger(&request.namespace, &request.key_id)
+            .ok()
+            .filter(|per_key_ledger| per_key_ledger.is_live(now))
             .ok_or_else(|| {
                 micro_rpc::Status::new_with_message(
                     micro_rpc::StatusCode::NotFound,
                     "public key not found",
                 )
             })?;
+        Ok(GetKeyDetailsResponse {
+            key: Some(Self::key_details(&request.key_id, per_key_ledger)?),
+        })
+    }
 
-        per_key_ledger
-            .budget_tracker
-            .consume_budget(&request.blob_id);
-        Ok(RevokeAccessResponse {})
+    fn query_audit_log(
+        &mut self,
+        request: QueryAuditLogRequest,
+    ) -> Result<QueryAuditLogResponse, micro_rpc::Status> {
+        Ok(self.audit_log.query(&request))
+    }
+
+    fn check_access(
+        &mut self,
+        request: CheckAccessRequest,
+    ) -> Result<CheckAccessResponse, micro_rpc::Status> {
+        let now = self.query_time(&request.now)?;
+        let (recipient_app, _) = self
+            .recipient_verifier
+            .verify(
+                &request.recipient_public_key,
+                request.recipient_attestation_evidence.as_ref(),
+                request.recipient_attestation_endorsements.as_ref(),
+                &request.recipient_tag,
+            )
+            .map_err(|err| {
+                micro_rpc::Status::new_with_message(
+                    micro_rpc::StatusCode::InvalidArgument,
+                    format!("attestation validation failed: {:?}", err),
+                )
+            })?;
+        let transform_index = self.find_authorized_transform(
+            &request.namespace,
+            &request.access_policy,
+            &request.blob_header,
+            &recipient_app,
+            now,
+        )?;
+        Ok(CheckAccessResponse {
+            transform_index: transform_index.try_into().unwrap(),
+        })
+    }
+
+    fn get_key_stats(
+        &mut self,
+        request: GetKeyStatsRequest,
+    ) -> Result<GetKeyStatsResponse, micro_rpc::Status> {
+        let per_key_ledger = self.get_per_key_ledger(&request.namespace, &request.key_id)?;
+        Ok(GetKeyStatsResponse {
+            authorizations_granted: per_key_ledger.usage.granted,
+            authorizations_denied: per_key_ledger.usage.denied,
+            budget_exhaustions: per_key_ledger.usage.budget_exhausted,
+            distinct_blobs: per_key_ledger
+                .budget_tracker
+                .distinct_blob_count()
+                .try_into()
+                .unwrap(),
+        })
+    }
+
+    fn rewrap_keys(
+        &mut self,
+        request: RewrapKeysRequest,
+    ) -> Result<RewrapKeysResponse, micro_rpc::Status> {
+        let rewrap_keys_event = self.produce_rewrap_keys_event(request)?;
+        self.apply_rewrap_keys_event(rewrap_keys_event)
     }
 }
 
@@ -640,6 +2366,7 @@ mod tests {
                     seconds: 100,
                     ..Default::default()
                 }),
+                ..Default::default()
             })
             .unwrap();
         assert!(response1.attestation_evidence.is_some());
@@ -674,12 +2401,57 @@ mod tests {
                     seconds: 100,
                     ..Default::default()
                 }),
+                ..Default::default()
             })
             .unwrap();
         let key2 = extract_key_from_cwt(&response2.public_key).unwrap();
         assert_ne!(key1, key2);
     }
 
+    #[test]
+    fn test_create_key_attestation() {
+        let mut ledger = LedgerService::create(
+            Box::new(MockEvidenceProvider::create().unwrap()),
+            Box::new(MockSigner::create().unwrap()),
+        )
+        .unwrap();
+        let response = ledger
+            .create_key(CreateKeyRequest {
+                ttl: Some(prost_types::Duration {
+                    seconds: 3600,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .unwrap();
+
+        // The public key is signed by the application key bound into the ledger
+        // evidence, hence clients can verify the key has been minted by the ledger
+        // before encrypting the uploads.
+        let (_, cose_key) = attestation::verify_attestation(
+            &response.public_key,
+            response.attestation_evidence.as_ref(),
+            None,
+            "",
+        )
+        .unwrap();
+        assert_eq!(
+            cose_key,
+            extract_key_from_cwt(&response.public_key).unwrap()
+        );
+
+        // The key that isn't signed by the application key is rejected.
+        let mut cwt = CoseSign1::from_slice(&response.public_key).unwrap();
+        cwt.signature = vec![0; cwt.signature.len()];
+        assert!(attestation::verify_attestation(
+            &cwt.to_vec().unwrap(),
+            response.attestation_evidence.as_ref(),
+            None,
+            "",
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_delete_key() {
         let (mut ledger, public_key) = create_ledger_service();
@@ -731,26 +2503,26 @@ mod tests {
     }
 
     #[test]
-    fn test_authorize_access() {
+    fn test_delete_key_grace_period() {
         let (mut ledger, public_key) = create_ledger_service();
+        ledger
+            .set_key_deletion_grace_period(prost_types::Duration {
+                seconds: 100,
+                ..Default::default()
+            })
+            .unwrap();
         let cose_key = extract_key_from_cwt(&public_key).unwrap();
+        let key_details_request = GetKeyDetailsRequest {
+            key_id: cose_key.key_id.clone(),
+            ..Default::default()
+        };
 
-        // Define an access policy that grants access.
-        let recipient_tag = "tag";
+        // Prepare the blob to authorize access to.
         let access_policy = DataAccessPolicy {
-            transforms: vec![Transform {
-                application: Some(ApplicationMatcher {
-                    tag: Some(recipient_tag.to_owned()),
-                    ..Default::default()
-                }),
-                ..Default::default()
-            }],
+            transforms: vec![Transform::default()],
             ..Default::default()
         }
         .encode_to_vec();
-
-        // Construct a client message.
-        let plaintext = b"plaintext";
         let blob_header = BlobHeader {
             blob_id: "blob-id".into(),
             key_id: cose_key.key_id.clone(),
@@ -758,32 +2530,156 @@ mod tests {
             ..Default::default()
         }
         .encode_to_vec();
-        let (ciphertext, encapsulated_key, encrypted_symmetric_key) =
-            cfc_crypto::encrypt_message(plaintext, &cose_key, &blob_header).unwrap();
+        let (_, encapsulated_key, encrypted_symmetric_key) =
+            cfc_crypto::encrypt_message(b"plaintext", &cose_key, &blob_header).unwrap();
+        let (_, recipient_public_key) = cfc_crypto::gen_keypair(b"key-id");
+        let authorize_access_request = AuthorizeAccessRequest {
+            now: Some(prost_types::Timestamp {
+                seconds: 50,
+                ..Default::default()
+            }),
+            access_policy,
+            blob_header,
+            encapsulated_key,
+            encrypted_symmetric_key,
+            recipient_public_key: create_recipient_cwt(recipient_public_key),
+            ..Default::default()
+        };
 
-        // Request access.
-        let (recipient_private_key, recipient_public_key) = cfc_crypto::gen_keypair(b"key-id");
-        let recipient_nonce: &[u8] = b"nonce";
-        let response = ledger
-            .authorize_access(AuthorizeAccessRequest {
-                access_policy,
-                blob_header: blob_header.clone(),
-                encapsulated_key,
-                encrypted_symmetric_key,
-                recipient_public_key: create_recipient_cwt(recipient_public_key),
-                recipient_tag: recipient_tag.to_owned(),
-                recipient_nonce: recipient_nonce.to_owned(),
+        // Deleting the key disables it, the disabled key is no longer listed
+        // for new blobs but its details carry the time when it's purged.
+        assert_eq!(
+            ledger.delete_key(DeleteKeyRequest {
+                public_key: public_key.clone(),
                 ..Default::default()
-            })
+            }),
+            Ok(DeleteKeyResponse::default())
+        );
+        let key = ledger
+            .get_key_details(key_details_request.clone())
+            .unwrap()
+            .key
             .unwrap();
-
-        // Verify that the response contains the right public key and allows the message to be read.
-        assert_eq!(response.reencryption_public_key, public_key);
+        assert_eq!(key.state(), KeyState::Disabled);
         assert_eq!(
-            cfc_crypto::decrypt_message(
-                &ciphertext,
-                &blob_header,
-                &response.encrypted_symmetric_key,
+            key.purge_time,
+            Some(prost_types::Timestamp {
+                seconds: 100,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            ledger.list_keys(ListKeysRequest::default()),
+            Ok(ListKeysResponse::default())
+        );
+        // Older replicas would drop the purge time and bring the key back to
+        // life, hence they aren't allowed to load the snapshot.
+        assert_eq!(
+            ledger.save_snapshot().unwrap().min_compatible_version,
+            PURGE_TIME_MIN_COMPATIBLE_VERSION
+        );
+
+        // The disabled key keeps authorizing access to the existing blobs.
+        assert!(ledger
+            .authorize_access(authorize_access_request.clone())
+            .is_ok());
+
+        // Deleting the key again doesn't extend the grace period.
+        assert_eq!(
+            ledger.delete_key(DeleteKeyRequest {
+                public_key: public_key.clone(),
+                ..Default::default()
+            }),
+            Ok(DeleteKeyResponse::default())
+        );
+
+        // The key is purged once the grace period elapses.
+        ledger
+            .update_current_time(&Some(prost_types::Timestamp {
+                seconds: 100,
+                ..Default::default()
+            }))
+            .unwrap();
+        assert_err!(
+            ledger.get_key_details(key_details_request),
+            micro_rpc::StatusCode::NotFound,
+            "public key not found"
+        );
+        assert_err!(
+            ledger.authorize_access(AuthorizeAccessRequest {
+                now: Some(prost_types::Timestamp {
+                    seconds: 100,
+                    ..Default::default()
+                }),
+                ..authorize_access_request
+            }),
+            micro_rpc::StatusCode::NotFound,
+            "public key not found"
+        );
+        assert_err!(
+            ledger.delete_key(DeleteKeyRequest {
+                public_key,
+                ..Default::default()
+            }),
+            micro_rpc::StatusCode::NotFound,
+            "public key not found"
+        );
+    }
+
+    #[test]
+    fn test_authorize_access() {
+        let (mut ledger, public_key) = create_ledger_service();
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+
+        // Define an access policy that grants access.
+        let recipient_tag = "tag";
+        let access_policy = DataAccessPolicy {
+            transforms: vec![Transform {
+                application: Some(ApplicationMatcher {
+                    tag: Some(recipient_tag.to_owned()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+        .encode_to_vec();
+
+        // Construct a client message.
+        let plaintext = b"plaintext";
+        let blob_header = BlobHeader {
+            blob_id: "blob-id".into(),
+            key_id: cose_key.key_id.clone(),
+            access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let (ciphertext, encapsulated_key, encrypted_symmetric_key) =
+            cfc_crypto::encrypt_message(plaintext, &cose_key, &blob_header).unwrap();
+
+        // Request access.
+        let (recipient_private_key, recipient_public_key) = cfc_crypto::gen_keypair(b"key-id");
+        let recipient_nonce: &[u8] = b"nonce";
+        let response = ledger
+            .authorize_access(AuthorizeAccessRequest {
+                access_policy,
+                blob_header: blob_header.clone(),
+                encapsulated_key,
+                encrypted_symmetric_key,
+                recipient_public_key: create_recipient_cwt(recipient_public_key),
+                recipient_tag: recipient_tag.to_owned(),
+                recipient_nonce: recipient_nonce.to_owned(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        // Verify that the response contains the right public key and allows the message to be read.
+        assert_eq!(response.reencryption_public_key, public_key);
+        assert_eq!(
+            cfc_crypto::decrypt_message(
+                &ciphertext,
+                &blob_header,
+                &response.encrypted_symmetric_key,
                 &[&response.reencryption_public_key, recipient_nonce].concat(),
                 &response.encapsulated_key,
                 &recipient_private_key
@@ -793,6 +2689,75 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_authorize_access_notification() {
+        let (mut ledger, public_key) = create_ledger_service();
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+
+        // Define an access policy that grants access.
+        let recipient_tag = "tag";
+        let access_policy = DataAccessPolicy {
+            transforms: vec![Transform {
+                application: Some(ApplicationMatcher {
+                    tag: Some(recipient_tag.to_owned()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let access_policy_sha256 = Sha256::digest(&access_policy).to_vec();
+
+        // Request notifications for the first transform of the access policy.
+        ledger.set_access_notification_config(AccessNotificationConfig {
+            transforms: vec![access_notification_config::TransformSelector {
+                access_policy_sha256: access_policy_sha256.clone(),
+                transform_index: 0,
+            }],
+        });
+
+        // Construct a client message.
+        let blob_header = BlobHeader {
+            blob_id: "blob-id".into(),
+            key_id: cose_key.key_id.clone(),
+            access_policy_sha256: access_policy_sha256.clone(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let (_, encapsulated_key, encrypted_symmetric_key) =
+            cfc_crypto::encrypt_message(b"plaintext", &cose_key, &blob_header).unwrap();
+
+        // Request access.
+        let (_, recipient_public_key) = cfc_crypto::gen_keypair(b"key-id");
+        let recipient_cwt = create_recipient_cwt(recipient_public_key);
+        assert!(ledger
+            .authorize_access(AuthorizeAccessRequest {
+                access_policy,
+                blob_header,
+                encapsulated_key,
+                encrypted_symmetric_key,
+                recipient_public_key: recipient_cwt.clone(),
+                recipient_tag: recipient_tag.to_owned(),
+                recipient_nonce: b"nonce".to_vec(),
+                ..Default::default()
+            })
+            .is_ok());
+
+        // Verify that the notification identifies the access only through digests.
+        assert_eq!(
+            ledger.take_access_notifications(),
+            vec![AccessNotification {
+                event_time: Some(prost_types::Timestamp::default()),
+                blob_id_sha256: Sha256::digest(b"blob-id").to_vec(),
+                access_policy_sha256,
+                transform_index: 0,
+                recipient_sha256: Sha256::digest(&recipient_cwt).to_vec(),
+            }]
+        );
+        assert!(ledger.take_access_notifications().is_empty());
+    }
+
     #[test]
     fn test_authorize_access_with_attestation() {
         let (mut ledger, public_key) = create_ledger_service();
@@ -879,6 +2844,113 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_authorize_access_with_pinned_signing_keys() {
+        let (mut ledger, public_key) = create_ledger_service();
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+        let signing_public_key =
+            oak_attestation_verification::verifier::verify_dice_chain(&get_test_evidence())
+                .unwrap()
+                .signing_public_key;
+        assert_eq!(
+            ledger.set_recipient_verification_config(RecipientVerificationConfig {
+                kind: Some(recipient_verification_config::Kind::PinnedSigningKeys(
+                    recipient_verification_config::PinnedSigningKeys {
+                        signing_public_keys: vec![signing_public_key.clone()],
+                    }
+                )),
+            }),
+            Ok(())
+        );
+
+        // Define an access policy that grants access to the recipients signed by the pinned key.
+        let access_policy = DataAccessPolicy {
+            transforms: vec![Transform {
+                application: Some(ApplicationMatcher {
+                    signing_public_keys: vec![signing_public_key],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let blob_header = BlobHeader {
+            blob_id: "blob-id".into(),
+            key_id: cose_key.key_id.clone(),
+            access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let (_, encapsulated_key, encrypted_symmetric_key) =
+            cfc_crypto::encrypt_message(b"plaintext", &cose_key, &blob_header).unwrap();
+        let (_, recipient_public_key) = cfc_crypto::gen_keypair(b"key-id");
+        let request = AuthorizeAccessRequest {
+            access_policy,
+            blob_header,
+            encapsulated_key,
+            encrypted_symmetric_key,
+            recipient_nonce: b"nonce".to_vec(),
+            ..Default::default()
+        };
+
+        // A recipient whose public key is signed by the pinned key is authorized without
+        // providing any evidence.
+        let signed_cwt = CoseSign1Builder::new()
+            .payload(
+                ClaimsSetBuilder::new()
+                    .private_claim(
+                        PUBLIC_KEY_CLAIM,
+                        Value::from(recipient_public_key.clone().to_vec().unwrap()),
+                    )
+                    .build()
+                    .to_vec()
+                    .unwrap(),
+            )
+            .create_signature(b"", |message| {
+                MockSigner::create()
+                    .unwrap()
+                    .sign(message)
+                    .unwrap()
+                    .signature
+            })
+            .build()
+            .to_vec()
+            .unwrap();
+        assert!(ledger
+            .authorize_access(AuthorizeAccessRequest {
+                recipient_public_key: signed_cwt,
+                ..request.clone()
+            })
+            .is_ok());
+
+        // An unsigned public key is rejected.
+        assert_err!(
+            ledger.authorize_access(AuthorizeAccessRequest {
+                recipient_public_key: create_recipient_cwt(recipient_public_key),
+                ..request
+            }),
+            micro_rpc::StatusCode::InvalidArgument,
+            "attestation validation failed"
+        );
+    }
+
+    #[test]
+    fn test_set_recipient_verification_config_invalid() {
+        let (mut ledger, _) = create_ledger_service();
+        assert_err!(
+            ledger.set_recipient_verification_config(RecipientVerificationConfig {
+                kind: Some(recipient_verification_config::Kind::PinnedSigningKeys(
+                    recipient_verification_config::PinnedSigningKeys {
+                        signing_public_keys: vec![b"invalid".to_vec()],
+                    }
+                )),
+            }),
+            micro_rpc::StatusCode::InvalidArgument,
+            "`pinned_signing_keys` is invalid"
+        );
+    }
+
     #[test]
     fn test_authorize_access_invalid_evidence() {
         let (mut ledger, public_key) = create_ledger_service();
@@ -1319,6 +3391,7 @@ mod tests {
             transforms: vec![Transform {
                 access_budget: Some(AccessBudget {
                     kind: Some(AccessBudgetKind::Times(1)),
+                    ..Default::default()
                 }),
                 ..Default::default()
             }],
@@ -1368,38 +3441,1556 @@ mod tests {
     }
 
     #[test]
-    fn test_revoke_access() {
+    fn test_error_details() {
         let (mut ledger, public_key) = create_ledger_service();
         let cose_key = extract_key_from_cwt(&public_key).unwrap();
-        let blob_id = b"blob-id";
-        assert_eq!(
-            ledger.revoke_access(RevokeAccessRequest {
-                key_id: cose_key.key_id.clone(),
-                blob_id: blob_id.to_vec(),
-                ..Default::default()
-            }),
-            Ok(RevokeAccessResponse::default())
-        );
-
-        // Subsequent access should not be granted.
         let access_policy = DataAccessPolicy {
-            transforms: vec![Transform::default()],
+            transforms: vec![Transform {
+                access_budget: Some(AccessBudget {
+                    kind: Some(AccessBudgetKind::Times(1)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
             ..Default::default()
         }
         .encode_to_vec();
-        let plaintext = b"plaintext";
         let blob_header = BlobHeader {
-            blob_id: blob_id.to_vec(),
+            blob_id: b"blob-id".to_vec(),
             key_id: cose_key.key_id.clone(),
             access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
             ..Default::default()
         }
         .encode_to_vec();
         let (_, encapsulated_key, encrypted_symmetric_key) =
-            cfc_crypto::encrypt_message(plaintext, &cose_key, &blob_header).unwrap();
+            cfc_crypto::encrypt_message(b"plaintext", &cose_key, &blob_header).unwrap();
+        let request = AuthorizeAccessRequest {
+            access_policy,
+            blob_header,
+            encapsulated_key,
+            encrypted_symmetric_key,
+            recipient_public_key: create_recipient_cwt(cfc_crypto::gen_keypair(b"key-id").1),
+            recipient_tag: "tag".to_owned(),
+            recipient_nonce: b"nonce".to_vec(),
+            ..Default::default()
+        };
+        assert!(ledger.authorize_access(request.clone()).is_ok());
 
-        assert_err!(
-            ledger.authorize_access(AuthorizeAccessRequest {
+        // The budget exhaustion reports the remaining budget and the key expiration.
+        let err = ledger
+            .attest_and_produce_authorize_access_event(request)
+            .unwrap_err();
+        assert_eq!(err.code, micro_rpc::StatusCode::ResourceExhausted);
+        assert_eq!(
+            err.details,
+            Some(LedgerErrorDetails {
+                reason: Reason::BudgetExhausted.into(),
+                remaining_budget: Some(0),
+                key_expiration: Some(prost_types::Timestamp {
+                    seconds: 3600,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+        );
+
+        // Invalid arguments report the offending field.
+        let err = ledger
+            .produce_create_key_event(
+                CreateKeyRequest {
+                    ttl: Some(prost_types::Duration {
+                        seconds: -1,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                &fill_os_random,
+            )
+            .unwrap_err();
+        assert_eq!(err.code, micro_rpc::StatusCode::InvalidArgument);
+        assert_eq!(
+            err.details,
+            Some(LedgerErrorDetails {
+                reason: Reason::InvalidArgument.into(),
+                field: "ttl".into(),
+                ..Default::default()
+            })
+        );
+
+        let err = ledger
+            .apply_revoke_access(RevokeAccessRequest {
+                key_id: b"unknown".to_vec(),
+                blob_id: b"blob-id".to_vec(),
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert_eq!(err.code, micro_rpc::StatusCode::NotFound);
+        assert_eq!(
+            err.details,
+            Some(LedgerErrorDetails {
+                reason: Reason::KeyNotFound.into(),
+                field: "key_id".into(),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_authorize_access_pipeline() {
+        let (mut ledger, public_key) = create_ledger_service();
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+
+        // Define a two-stage pipeline: the first stage reads the uploaded blobs and produces
+        // derived blobs, which can only be read by the second stage.
+        let stage = |src: u32, dest: Option<u32>, tag: &str| Transform {
+            src,
+            dest,
+            application: Some(ApplicationMatcher {
+                tag: Some(tag.to_owned()),
+                ..Default::default()
+            }),
+            access_budget: Some(AccessBudget {
+                kind: Some(AccessBudgetKind::Times(1)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let access_policy = DataAccessPolicy {
+            transforms: vec![stage(0, Some(1), "stage1"), stage(1, None, "stage2")],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let authorize = |ledger: &mut LedgerService,
+                         blob_header: &[u8],
+                         key: &CoseKey,
+                         tag: &str| {
+            let (_, encapsulated_key, encrypted_symmetric_key) =
+                cfc_crypto::encrypt_message(b"plaintext", key, blob_header).unwrap();
+            ledger.authorize_access(AuthorizeAccessRequest {
+                access_policy: access_policy.clone(),
+                blob_header: blob_header.to_vec(),
+                encapsulated_key,
+                encrypted_symmetric_key,
+                recipient_public_key: create_recipient_cwt(cfc_crypto::gen_keypair(b"key-id").1),
+                recipient_tag: tag.to_owned(),
+                recipient_nonce: b"nonce".to_vec(),
+                ..Default::default()
+            })
+        };
+
+        // The uploaded blob can only be read by the first stage.
+        let blob_header = BlobHeader {
+            blob_id: b"blob-id".to_vec(),
+            key_id: cose_key.key_id.clone(),
+            access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        assert_err!(
+            authorize(&mut ledger, &blob_header, &cose_key, "stage2"),
+            micro_rpc::StatusCode::FailedPrecondition,
+            "requesting application does not match the access policy"
+        );
+        let response = authorize(&mut ledger, &blob_header, &cose_key, "stage1").unwrap();
+
+        // The first stage re-encrypts its output with the key returned by the ledger, and the
+        // derived blob can only be read by the second stage.
+        let reencryption_key = extract_key_from_cwt(&response.reencryption_public_key).unwrap();
+        let derived_blob_header = BlobHeader {
+            blob_id: b"derived-blob-id".to_vec(),
+            key_id: reencryption_key.key_id.clone(),
+            access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+            access_policy_node_id: 1,
+            ..Default::default()
+        }
+        .encode_to_vec();
+        assert_err!(
+            authorize(
+                &mut ledger,
+                &derived_blob_header,
+                &reencryption_key,
+                "stage1"
+            ),
+            micro_rpc::StatusCode::FailedPrecondition,
+            "requesting application does not match the access policy"
+        );
+        assert!(authorize(
+            &mut ledger,
+            &derived_blob_header,
+            &reencryption_key,
+            "stage2"
+        )
+        .is_ok());
+
+        // Each edge of the pipeline has its own budget.
+        assert_err!(
+            authorize(&mut ledger, &blob_header, &cose_key, "stage1"),
+            micro_rpc::StatusCode::ResourceExhausted,
+            ""
+        );
+        assert_err!(
+            authorize(
+                &mut ledger,
+                &derived_blob_header,
+                &reencryption_key,
+                "stage2"
+            ),
+            micro_rpc::StatusCode::ResourceExhausted,
+            ""
+        );
+    }
+
+    #[test]
+    fn test_authorize_access_invalid_pipeline() {
+        let (mut ledger, public_key) = create_ledger_service();
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+        let transform = |src: u32, dest: Option<u32>| Transform {
+            src,
+            dest,
+            ..Default::default()
+        };
+        let policy = |transforms: Vec<Transform>| DataAccessPolicy {
+            transforms,
+            ..Default::default()
+        };
+        let named_budget = |name: &str| AccessBudget {
+            name: Some(name.into()),
+            ..Default::default()
+        };
+
+        for (access_policy, message) in [
+            (policy(vec![transform(0, Some(0))]), "`dest` must not be 0"),
+            (
+                policy(vec![transform(0, Some(1)), transform(2, None)]),
+                "`src` is not produced by any transform",
+            ),
+            (
+                policy(vec![
+                    transform(0, Some(1)),
+                    transform(1, Some(2)),
+                    transform(2, Some(1)),
+                ]),
+                "transforms form a cycle",
+            ),
+            (
+                DataAccessPolicy {
+                    shared_access_budgets: vec![
+                        named_budget("budget"),
+                        AccessBudget::default(),
+                        named_budget("budget"),
+                    ],
+                    ..policy(vec![transform(0, None)])
+                },
+                "`shared_access_budgets` names must be unique",
+            ),
+        ] {
+            let access_policy = access_policy.encode_to_vec();
+            let blob_header = BlobHeader {
+                blob_id: b"blob-id".to_vec(),
+                key_id: cose_key.key_id.clone(),
+                access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+                ..Default::default()
+            }
+            .encode_to_vec();
+            let (_, encapsulated_key, encrypted_symmetric_key) =
+                cfc_crypto::encrypt_message(b"plaintext", &cose_key, &blob_header).unwrap();
+            assert_err!(
+                ledger.authorize_access(AuthorizeAccessRequest {
+                    access_policy,
+                    blob_header,
+                    encapsulated_key,
+                    encrypted_symmetric_key,
+                    recipient_public_key: create_recipient_cwt(
+                        cfc_crypto::gen_keypair(b"key-id").1
+                    ),
+                    recipient_nonce: b"nonce".to_vec(),
+                    ..Default::default()
+                }),
+                micro_rpc::StatusCode::InvalidArgument,
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn test_authorize_access_grandfathered_policy() {
+        let (mut ledger, public_key) = create_ledger_service();
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+
+        // The transforms of the policy form a cycle, which is no longer accepted.
+        let transform = |src: u32, dest: Option<u32>| Transform {
+            src,
+            dest,
+            access_budget: Some(AccessBudget {
+                kind: Some(AccessBudgetKind::Times(1)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let policy = DataAccessPolicy {
+            transforms: vec![
+                transform(0, Some(1)),
+                transform(1, Some(2)),
+                transform(2, Some(1)),
+            ],
+            ..Default::default()
+        };
+        let access_policy = policy.encode_to_vec();
+        let access_policy_sha256 = Sha256::digest(&access_policy).to_vec();
+        let authorize = |ledger: &mut LedgerService, blob_id: &[u8]| {
+            let blob_header = BlobHeader {
+                blob_id: blob_id.to_vec(),
+                key_id: cose_key.key_id.clone(),
+                access_policy_sha256: access_policy_sha256.clone(),
+                ..Default::default()
+            }
+            .encode_to_vec();
+            let (_, encapsulated_key, encrypted_symmetric_key) =
+                cfc_crypto::encrypt_message(b"plaintext", &cose_key, &blob_header).unwrap();
+            ledger.authorize_access(AuthorizeAccessRequest {
+                access_policy: access_policy.clone(),
+                blob_header,
+                encapsulated_key,
+                encrypted_symmetric_key,
+                recipient_public_key: create_recipient_cwt(cfc_crypto::gen_keypair(b"key-id").1),
+                recipient_nonce: b"nonce".to_vec(),
+                ..Default::default()
+            })
+        };
+        assert_err!(
+            authorize(&mut ledger, b"blob-id"),
+            micro_rpc::StatusCode::InvalidArgument,
+            "transforms form a cycle"
+        );
+
+        // Once budgets are tracked for the policy, as they would be if the policy had been
+        // accepted before it was validated, the policy remains usable for all blobs.
+        ledger
+            .get_per_key_ledger_mut("", &cose_key.key_id)
+            .unwrap()
+            .budget_tracker
+            .update_budget(
+                b"old-blob-id",
+                /* transform_index= */ 0,
+                &policy,
+                &access_policy_sha256,
+            )
+            .unwrap();
+        assert!(authorize(&mut ledger, b"blob-id").is_ok());
+        assert_err!(
+            authorize(&mut ledger, b"old-blob-id"),
+            micro_rpc::StatusCode::ResourceExhausted,
+            ""
+        );
+    }
+
+    #[test]
+    fn test_authorize_access_expired_blob() {
+        let (mut ledger, public_key) = create_ledger_service();
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+        let access_policy = DataAccessPolicy {
+            transforms: vec![Transform {
+                access_budget: Some(AccessBudget {
+                    kind: Some(AccessBudgetKind::Times(2)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let blob_header = BlobHeader {
+            blob_id: b"blob-id".to_vec(),
+            key_id: cose_key.key_id.clone(),
+            access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+            expiration: Some(prost_types::Timestamp {
+                seconds: 100,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let (_, encapsulated_key, encrypted_symmetric_key) =
+            cfc_crypto::encrypt_message(b"plaintext", &cose_key, &blob_header).unwrap();
+        let request = AuthorizeAccessRequest {
+            access_policy,
+            blob_header,
+            encapsulated_key,
+            encrypted_symmetric_key,
+            recipient_public_key: create_recipient_cwt(cfc_crypto::gen_keypair(b"key-id").1),
+            recipient_nonce: b"nonce".to_vec(),
+            ..Default::default()
+        };
+
+        // Access before the blob's expiration should succeed and track the expiration.
+        assert!(ledger
+            .authorize_access(AuthorizeAccessRequest {
+                now: Some(prost_types::Timestamp {
+                    seconds: 10,
+                    ..Default::default()
+                }),
+                ..request.clone()
+            })
+            .is_ok());
+        let budgets = ledger.save_snapshot().unwrap().per_key_snapshots[0]
+            .budgets
+            .clone()
+            .unwrap();
+        assert_eq!(budgets.per_policy_snapshots[0].budgets.len(), 1);
+        assert_eq!(budgets.blob_expirations.len(), 1);
+
+        // Once the blob has expired, access should be denied even though budget remains, and
+        // the blob's budget should be discarded.
+        assert_err!(
+            ledger.authorize_access(AuthorizeAccessRequest {
+                now: Some(prost_types::Timestamp {
+                    seconds: 100,
+                    ..Default::default()
+                }),
+                ..request
+            }),
+            micro_rpc::StatusCode::FailedPrecondition,
+            "blob has expired"
+        );
+        let budgets = ledger.save_snapshot().unwrap().per_key_snapshots[0]
+            .budgets
+            .clone()
+            .unwrap();
+        assert!(budgets.per_policy_snapshots[0].budgets.is_empty());
+        assert!(budgets.blob_expirations.is_empty());
+    }
+
+    #[test]
+    fn test_authorize_access_cipher_suite() {
+        let mut ledger = LedgerService::create(
+            Box::new(MockEvidenceProvider::create().unwrap()),
+            Box::new(MockSigner::create().unwrap()),
+        )
+        .unwrap();
+        let response = ledger
+            .create_key(CreateKeyRequest {
+                ttl: Some(prost_types::Duration {
+                    seconds: 3600,
+                    ..Default::default()
+                }),
+                cipher_suite: HpkeCipherSuite::X25519HkdfSha256Chacha20poly1305.into(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            response.cipher_suite,
+            HpkeCipherSuite::X25519HkdfSha256Chacha20poly1305 as i32
+        );
+        let public_key = response.public_key;
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+        let access_policy = DataAccessPolicy {
+            transforms: vec![Transform::default()],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let encrypt = |blob_id: &[u8], cipher_suite: HpkeCipherSuite| {
+            let blob_header = BlobHeader {
+                blob_id: blob_id.to_vec(),
+                key_id: cose_key.key_id.clone(),
+                access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+                cipher_suite: cipher_suite.into(),
+                ..Default::default()
+            }
+            .encode_to_vec();
+            let (ciphertext, encapsulated_key, encrypted_symmetric_key) =
+                cfc_crypto::encrypt_message(b"plaintext", &cose_key, &blob_header).unwrap();
+            (
+                ciphertext,
+                AuthorizeAccessRequest {
+                    access_policy: access_policy.clone(),
+                    blob_header,
+                    encapsulated_key,
+                    encrypted_symmetric_key,
+                    recipient_nonce: b"nonce".to_vec(),
+                    ..Default::default()
+                },
+            )
+        };
+
+        // The symmetric key is re-wrapped for the recipient using the recipient's cipher suite.
+        let (recipient_private_key, recipient_public_key) = cfc_crypto::gen_keypair(b"key-id");
+        let (ciphertext, request) =
+            encrypt(b"blob1", HpkeCipherSuite::X25519HkdfSha256Chacha20poly1305);
+        let response = ledger
+            .authorize_access(AuthorizeAccessRequest {
+                recipient_public_key: create_recipient_cwt(recipient_public_key.clone()),
+                ..request.clone()
+            })
+            .unwrap();
+        assert_eq!(
+            cfc_crypto::decrypt_message(
+                &ciphertext,
+                &request.blob_header,
+                &response.encrypted_symmetric_key,
+                &[&response.reencryption_public_key[..], &b"nonce"[..]].concat(),
+                &response.encapsulated_key,
+                &recipient_private_key
+            )
+            .unwrap(),
+            b"plaintext"
+        );
+
+        // Blobs that don't declare their cipher suite are assumed to use the key's suite.
+        let (_, request) = encrypt(b"blob2", HpkeCipherSuite::Unspecified);
+        assert!(ledger
+            .authorize_access(AuthorizeAccessRequest {
+                recipient_public_key: create_recipient_cwt(recipient_public_key.clone()),
+                ..request
+            })
+            .is_ok());
+
+        // Blobs declaring a different cipher suite are rejected.
+        let (_, request) = encrypt(b"blob3", HpkeCipherSuite::X25519HkdfSha256Aes128gcm);
+        assert_err!(
+            ledger.authorize_access(AuthorizeAccessRequest {
+                recipient_public_key: create_recipient_cwt(recipient_public_key),
+                ..request
+            }),
+            micro_rpc::StatusCode::InvalidArgument,
+            "blob cipher suite does not match the public key"
+        );
+    }
+
+    #[test]
+    fn test_authorize_access_rate_limit() {
+        let (mut ledger, public_key) = create_ledger_service();
+        assert_eq!(
+            ledger.set_rate_limit_config(RateLimitConfig {
+                limits: vec![rate_limit_config::Limit {
+                    window: Some(prost_types::Duration {
+                        seconds: 100,
+                        ..Default::default()
+                    }),
+                    max_accesses: 2,
+                    ..Default::default()
+                }],
+            }),
+            Ok(())
+        );
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+        let access_policy = DataAccessPolicy {
+            transforms: vec![Transform::default()],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let request = |blob_id: &[u8], seconds: i64, recipient_tag: &str| {
+            let blob_header = BlobHeader {
+                blob_id: blob_id.to_vec(),
+                key_id: cose_key.key_id.clone(),
+                access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+                ..Default::default()
+            }
+            .encode_to_vec();
+            let (_, encapsulated_key, encrypted_symmetric_key) =
+                cfc_crypto::encrypt_message(b"plaintext", &cose_key, &blob_header).unwrap();
+            AuthorizeAccessRequest {
+                now: Some(prost_types::Timestamp {
+                    seconds,
+                    ..Default::default()
+                }),
+                access_policy: access_policy.clone(),
+                blob_header,
+                encapsulated_key,
+                encrypted_symmetric_key,
+                recipient_public_key: create_recipient_cwt(cfc_crypto::gen_keypair(b"key-id").1),
+                recipient_tag: recipient_tag.to_owned(),
+                recipient_nonce: b"nonce".to_vec(),
+                ..Default::default()
+            }
+        };
+
+        assert!(ledger
+            .authorize_access(request(b"blob1", 10, "tag"))
+            .is_ok());
+        assert!(ledger
+            .authorize_access(request(b"blob2", 20, "tag"))
+            .is_ok());
+        assert_err!(
+            ledger.authorize_access(request(b"blob3", 30, "tag")),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "rate limit exceeded for the recipient"
+        );
+        // The recipient can't escape the limit by changing its tag.
+        assert_err!(
+            ledger.authorize_access(request(b"blob3", 30, "other")),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "rate limit exceeded for the recipient"
+        );
+
+        // The limit is lifted once the window ends. The accesses are only counted when the
+        // events are applied, so the events produced concurrently are rejected once applied.
+        let events: Vec<_> = [b"blob4", b"blob5", b"blob6"]
+            .into_iter()
+            .map(|blob_id| {
+                ledger
+                    .attest_and_produce_authorize_access_event(request(blob_id, 110, "tag"))
+                    .unwrap()
+            })
+            .collect();
+        let results: Vec<_> = events
+            .into_iter()
+            .map(|event| ledger.apply_authorize_access_event(event))
+            .collect();
+        assert!(results[0].is_ok());
+        assert!(results[1].is_ok());
+        assert_err!(
+            results[2],
+            micro_rpc::StatusCode::ResourceExhausted,
+            "rate limit exceeded for the recipient"
+        );
+
+        // The counters are carried in the snapshot.
+        let snapshot = ledger.save_snapshot().unwrap();
+        assert_eq!(snapshot.rate_limit_counters.len(), 1);
+        assert_eq!(snapshot.rate_limit_counters[0].count, 2);
+        assert_eq!(
+            snapshot.min_compatible_version,
+            RATE_LIMIT_COUNTERS_MIN_COMPATIBLE_VERSION
+        );
+    }
+
+    #[test]
+    fn test_get_key_stats() {
+        let (mut ledger, public_key) = create_ledger_service();
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+        let access_policy = DataAccessPolicy {
+            transforms: vec![Transform {
+                access_budget: Some(AccessBudget {
+                    kind: Some(AccessBudgetKind::Times(1)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let request = |blob_id: &[u8], access_policy_sha256: Vec<u8>| {
+            let blob_header = BlobHeader {
+                blob_id: blob_id.to_vec(),
+                key_id: cose_key.key_id.clone(),
+                access_policy_sha256,
+                ..Default::default()
+            }
+            .encode_to_vec();
+            let (_, encapsulated_key, encrypted_symmetric_key) =
+                cfc_crypto::encrypt_message(b"plaintext", &cose_key, &blob_header).unwrap();
+            AuthorizeAccessRequest {
+                access_policy: access_policy.clone(),
+                blob_header,
+                encapsulated_key,
+                encrypted_symmetric_key,
+                recipient_public_key: create_recipient_cwt(cfc_crypto::gen_keypair(b"key-id").1),
+                recipient_nonce: b"nonce".to_vec(),
+                ..Default::default()
+            }
+        };
+        let access_policy_sha256 = Sha256::digest(&access_policy).to_vec();
+
+        assert_eq!(
+            ledger.get_key_stats(GetKeyStatsRequest {
+                key_id: cose_key.key_id.clone(),
+                ..Default::default()
+            }),
+            Ok(GetKeyStatsResponse::default())
+        );
+
+        assert!(ledger
+            .authorize_access(request(b"blob1", access_policy_sha256.clone()))
+            .is_ok());
+        assert!(ledger
+            .authorize_access(request(b"blob2", access_policy_sha256.clone()))
+            .is_ok());
+        // The budget of the blob is exhausted.
+        assert!(ledger
+            .authorize_access(request(b"blob1", access_policy_sha256.clone()))
+            .is_err());
+        // The access policy doesn't match the blob header.
+        assert!(ledger
+            .authorize_access(request(b"blob3", b"other".to_vec()))
+            .is_err());
+
+        assert_eq!(
+            ledger.get_key_stats(GetKeyStatsRequest {
+                key_id: cose_key.key_id.clone(),
+                ..Default::default()
+            }),
+            Ok(GetKeyStatsResponse {
+                authorizations_granted: 2,
+                authorizations_denied: 2,
+                budget_exhaustions: 1,
+                distinct_blobs: 2,
+            })
+        );
+
+        // The counters are retained in the snapshot.
+        let snapshot = ledger.save_snapshot().unwrap();
+        assert_eq!(
+            snapshot.min_compatible_version,
+            KEY_USAGE_MIN_COMPATIBLE_VERSION
+        );
+        let (mut ledger, _) = create_ledger_service();
+        assert_eq!(ledger.load_snapshot(snapshot), Ok(()));
+        assert_eq!(
+            ledger.get_key_stats(GetKeyStatsRequest {
+                key_id: cose_key.key_id.clone(),
+                ..Default::default()
+            }),
+            Ok(GetKeyStatsResponse {
+                authorizations_granted: 2,
+                authorizations_denied: 2,
+                budget_exhaustions: 1,
+                distinct_blobs: 2,
+            })
+        );
+
+        assert_err!(
+            ledger.get_key_stats(GetKeyStatsRequest {
+                key_id: b"unknown".to_vec(),
+                ..Default::default()
+            }),
+            micro_rpc::StatusCode::NotFound,
+            "public key not found"
+        );
+    }
+
+    #[test]
+    fn test_apply_access_denied_event() {
+        let (mut leader, public_key) = create_ledger_service();
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+
+        // The follower has the same state as the leader.
+        let (mut follower, _) = create_ledger_service();
+        assert_eq!(
+            follower.load_snapshot(leader.save_snapshot().unwrap()),
+            Ok(())
+        );
+
+        // The access policy doesn't match the blob header, hence the request is rejected
+        // before any AuthorizeAccessEvent is produced.
+        let blob_header = BlobHeader {
+            blob_id: b"blob-id".to_vec(),
+            key_id: cose_key.key_id.clone(),
+            access_policy_sha256: b"other".to_vec(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let (_, encapsulated_key, encrypted_symmetric_key) =
+            cfc_crypto::encrypt_message(b"plaintext", &cose_key, &blob_header).unwrap();
+        assert!(leader
+            .attest_and_produce_authorize_access_event(AuthorizeAccessRequest {
+                now: Some(prost_types::Timestamp {
+                    seconds: 100,
+                    ..Default::default()
+                }),
+                access_policy: DataAccessPolicy::default().encode_to_vec(),
+                blob_header: blob_header.clone(),
+                encapsulated_key,
+                encrypted_symmetric_key,
+                recipient_public_key: create_recipient_cwt(cfc_crypto::gen_keypair(b"key-id").1),
+                recipient_nonce: b"nonce".to_vec(),
+                ..Default::default()
+            })
+            .is_err());
+
+        // The denial isn't counted until its event is applied.
+        let stats_request = GetKeyStatsRequest {
+            key_id: cose_key.key_id.clone(),
+            ..Default::default()
+        };
+        assert_eq!(
+            leader.get_key_stats(stats_request.clone()),
+            Ok(GetKeyStatsResponse::default())
+        );
+        let access_denied_events = leader.take_access_denied_events();
+        assert_eq!(
+            access_denied_events,
+            vec![AccessDeniedEvent {
+                event_time: Some(prost_types::Timestamp {
+                    seconds: 100,
+                    ..Default::default()
+                }),
+                namespace: String::new(),
+                blob_header,
+                budget_exhausted: false,
+            }]
+        );
+        assert!(leader.take_access_denied_events().is_empty());
+
+        // Every replica counts the denial once the event is applied.
+        for ledger in [&mut leader, &mut follower] {
+            for access_denied_event in access_denied_events.clone() {
+                assert_eq!(
+                    ledger.apply_access_denied_event(access_denied_event),
+                    Ok(())
+                );
+            }
+            assert_eq!(
+                ledger.get_key_stats(stats_request.clone()),
+                Ok(GetKeyStatsResponse {
+                    authorizations_denied: 1,
+                    ..Default::default()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_authorize_access_idempotency_token() {
+        let (mut ledger, public_key) = create_ledger_service();
+        assert_eq!(
+            ledger.set_idempotency_token_ttl(prost_types::Duration {
+                seconds: 100,
+                ..Default::default()
+            }),
+            Ok(())
+        );
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+        let access_policy = DataAccessPolicy {
+            transforms: vec![Transform {
+                access_budget: Some(AccessBudget {
+                    kind: Some(AccessBudgetKind::Times(1)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let blob_header = BlobHeader {
+            blob_id: b"blob-id".to_vec(),
+            key_id: cose_key.key_id.clone(),
+            access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let (_, encapsulated_key, encrypted_symmetric_key) =
+            cfc_crypto::encrypt_message(b"plaintext", &cose_key, &blob_header).unwrap();
+        let recipient_public_key = create_recipient_cwt(cfc_crypto::gen_keypair(b"key-id").1);
+        let request = |seconds: i64, idempotency_token: &[u8]| AuthorizeAccessRequest {
+            now: Some(prost_types::Timestamp {
+                seconds,
+                ..Default::default()
+            }),
+            access_policy: access_policy.clone(),
+            blob_header: blob_header.clone(),
+            encapsulated_key: encapsulated_key.clone(),
+            encrypted_symmetric_key: encrypted_symmetric_key.clone(),
+            recipient_public_key: recipient_public_key.clone(),
+            recipient_nonce: b"nonce".to_vec(),
+            idempotency_token: idempotency_token.to_vec(),
+            ..Default::default()
+        };
+
+        let response = ledger.authorize_access(request(10, b"token")).unwrap();
+        // The retry gets the same response although the budget has been exhausted.
+        assert_eq!(
+            ledger.authorize_access(request(20, b"token")),
+            Ok(response.clone())
+        );
+        assert_err!(
+            ledger.authorize_access(request(20, b"")),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "data access budget exhausted"
+        );
+        // The token can't be used for a different request.
+        assert_err!(
+            ledger.authorize_access(AuthorizeAccessRequest {
+                recipient_nonce: b"other".to_vec(),
+                ..request(20, b"token")
+            }),
+            micro_rpc::StatusCode::InvalidArgument,
+            "`idempotency_token` has been used for a different request"
+        );
+        assert_eq!(
+            ledger
+                .get_key_stats(GetKeyStatsRequest {
+                    key_id: cose_key.key_id.clone(),
+                    ..Default::default()
+                })
+                .map(|stats| stats.authorizations_granted),
+            Ok(1)
+        );
+
+        // The token is retained in the snapshot.
+        let snapshot = ledger.save_snapshot().unwrap();
+        assert_eq!(snapshot.idempotency_tokens.len(), 1);
+        let (mut ledger, _) = create_ledger_service();
+        assert_eq!(ledger.load_snapshot(snapshot), Ok(()));
+        assert_eq!(ledger.authorize_access(request(30, b"token")), Ok(response));
+
+        // Once the token expires, the retry is authorized anew.
+        assert_err!(
+            ledger.authorize_access(request(110, b"token")),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "data access budget exhausted"
+        );
+    }
+
+    #[test]
+    fn test_authorize_access_idempotency_token_after_losing_access() {
+        let (mut ledger, public_key) = create_ledger_service();
+        assert_eq!(
+            ledger.set_idempotency_token_ttl(prost_types::Duration {
+                seconds: 1000,
+                ..Default::default()
+            }),
+            Ok(())
+        );
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+        let access_policy = DataAccessPolicy {
+            transforms: vec![Transform {
+                access_budget: Some(AccessBudget {
+                    kind: Some(AccessBudgetKind::Times(2)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let recipient_public_key = create_recipient_cwt(cfc_crypto::gen_keypair(b"key-id").1);
+        let request = |seconds: i64, blob_id: &[u8], expiration: Option<i64>| {
+            let blob_header = BlobHeader {
+                blob_id: blob_id.to_vec(),
+                key_id: cose_key.key_id.clone(),
+                access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+                expiration: expiration.map(|seconds| prost_types::Timestamp {
+                    seconds,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }
+            .encode_to_vec();
+            let (_, encapsulated_key, encrypted_symmetric_key) =
+                cfc_crypto::encrypt_message(b"plaintext", &cose_key, &blob_header).unwrap();
+            AuthorizeAccessRequest {
+                now: Some(prost_types::Timestamp {
+                    seconds,
+                    ..Default::default()
+                }),
+                access_policy: access_policy.clone(),
+                blob_header,
+                encapsulated_key,
+                encrypted_symmetric_key,
+                recipient_public_key: recipient_public_key.clone(),
+                recipient_nonce: b"nonce".to_vec(),
+                idempotency_token: blob_id.to_vec(),
+                ..Default::default()
+            }
+        };
+
+        // The retry after the access has been revoked is denied.
+        let revoked_request = request(10, b"revoked", None);
+        assert!(ledger.authorize_access(revoked_request.clone()).is_ok());
+        assert_eq!(
+            ledger.revoke_access(RevokeAccessRequest {
+                key_id: cose_key.key_id.clone(),
+                blob_id: b"revoked".to_vec(),
+                ..Default::default()
+            }),
+            Ok(RevokeAccessResponse::default())
+        );
+        assert_err!(
+            ledger.authorize_access(AuthorizeAccessRequest {
+                now: Some(prost_types::Timestamp {
+                    seconds: 20,
+                    ..Default::default()
+                }),
+                ..revoked_request
+            }),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "data access budget consumed"
+        );
+
+        // The retry after the blob has expired is denied.
+        let expired_request = request(20, b"expired", Some(50));
+        assert!(ledger.authorize_access(expired_request.clone()).is_ok());
+        assert_err!(
+            ledger.authorize_access(AuthorizeAccessRequest {
+                now: Some(prost_types::Timestamp {
+                    seconds: 60,
+                    ..Default::default()
+                }),
+                ..expired_request
+            }),
+            micro_rpc::StatusCode::FailedPrecondition,
+            "blob has expired"
+        );
+
+        // The retry after the key has been deleted is denied.
+        let deleted_request = request(60, b"deleted", None);
+        assert!(ledger.authorize_access(deleted_request.clone()).is_ok());
+        assert_eq!(
+            ledger.delete_key(DeleteKeyRequest {
+                public_key,
+                ..Default::default()
+            }),
+            Ok(DeleteKeyResponse::default())
+        );
+        assert_err!(
+            ledger.authorize_access(AuthorizeAccessRequest {
+                now: Some(prost_types::Timestamp {
+                    seconds: 70,
+                    ..Default::default()
+                }),
+                ..deleted_request
+            }),
+            micro_rpc::StatusCode::NotFound,
+            "public key not found"
+        );
+    }
+
+    #[test]
+    fn test_namespace_isolation() {
+        let mut ledger = LedgerService::create(
+            Box::new(MockEvidenceProvider::create().unwrap()),
+            Box::new(MockSigner::create().unwrap()),
+        )
+        .unwrap();
+        let public_key = ledger
+            .create_key(CreateKeyRequest {
+                ttl: Some(prost_types::Duration {
+                    seconds: 3600,
+                    ..Default::default()
+                }),
+                namespace: "tenant".into(),
+                ..Default::default()
+            })
+            .unwrap()
+            .public_key;
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+        let access_policy = DataAccessPolicy {
+            transforms: vec![Transform::default()],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let blob_header = BlobHeader {
+            blob_id: b"blob-id".to_vec(),
+            key_id: cose_key.key_id.clone(),
+            access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let (_, encapsulated_key, encrypted_symmetric_key) =
+            cfc_crypto::encrypt_message(b"plaintext", &cose_key, &blob_header).unwrap();
+        let request = |namespace: &str| AuthorizeAccessRequest {
+            access_policy: access_policy.clone(),
+            blob_header: blob_header.clone(),
+            encapsulated_key: encapsulated_key.clone(),
+            encrypted_symmetric_key: encrypted_symmetric_key.clone(),
+            recipient_public_key: create_recipient_cwt(cfc_crypto::gen_keypair(b"key-id").1),
+            recipient_nonce: b"nonce".to_vec(),
+            namespace: namespace.to_owned(),
+            ..Default::default()
+        };
+
+        // The key is only found within its namespace.
+        assert_err!(
+            ledger.authorize_access(request("")),
+            micro_rpc::StatusCode::NotFound,
+            "public key not found"
+        );
+        assert!(ledger.authorize_access(request("tenant")).is_ok());
+        assert_eq!(
+            ledger
+                .list_keys(ListKeysRequest::default())
+                .map(|response| response.keys.len()),
+            Ok(0)
+        );
+        assert_eq!(
+            ledger
+                .list_keys(ListKeysRequest {
+                    namespace: "tenant".into(),
+                    ..Default::default()
+                })
+                .map(|response| response.keys.len()),
+            Ok(1)
+        );
+        assert_err!(
+            ledger.revoke_access(RevokeAccessRequest {
+                key_id: cose_key.key_id.clone(),
+                blob_id: b"blob-id".to_vec(),
+                namespace: "other".into(),
+                ..Default::default()
+            }),
+            micro_rpc::StatusCode::NotFound,
+            "public key not found"
+        );
+        assert_err!(
+            ledger.delete_key(DeleteKeyRequest {
+                public_key: public_key.clone(),
+                ..Default::default()
+            }),
+            micro_rpc::StatusCode::NotFound,
+            "public key not found"
+        );
+
+        // The namespace is retained in the snapshot.
+        let snapshot = ledger.save_snapshot().unwrap();
+        assert_eq!(snapshot.per_key_snapshots[0].namespace, "tenant");
+        assert_eq!(
+            snapshot.min_compatible_version,
+            NAMESPACES_MIN_COMPATIBLE_VERSION
+        );
+        assert_eq!(ledger.load_snapshot(snapshot), Ok(()));
+        assert_eq!(
+            ledger.delete_key(DeleteKeyRequest {
+                public_key,
+                namespace: "tenant".into(),
+            }),
+            Ok(DeleteKeyResponse::default())
+        );
+    }
+
+    #[test]
+    fn test_key_rotation_namespaces() {
+        let mut ledger = LedgerService::create(
+            Box::new(MockEvidenceProvider::create().unwrap()),
+            Box::new(MockSigner::create().unwrap()),
+        )
+        .unwrap();
+        assert_eq!(
+            ledger.set_key_rotation_config(KeyRotationConfig {
+                period: Some(prost_types::Duration {
+                    seconds: 100,
+                    ..Default::default()
+                }),
+                overlap: Some(prost_types::Duration {
+                    seconds: 20,
+                    ..Default::default()
+                }),
+            }),
+            Ok(())
+        );
+        // Creating the key in the namespace also mints the first key of the default
+        // namespace.
+        assert!(ledger
+            .create_key(CreateKeyRequest {
+                ttl: Some(prost_types::Duration {
+                    seconds: 50,
+                    ..Default::default()
+                }),
+                namespace: "tenant".into(),
+                ..Default::default()
+            })
+            .is_ok());
+        assert_eq!(ledger.produce_rotate_key_event(&fill_os_random), Ok(None));
+
+        // The key of the namespace is rotated once it expires within the overlap.
+        ledger
+            .update_current_time(&Some(prost_types::Timestamp {
+                seconds: 30,
+                ..Default::default()
+            }))
+            .unwrap();
+        let event = ledger
+            .produce_rotate_key_event(&fill_os_random)
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.namespace, "tenant");
+        assert!(ledger.apply_rotate_key_event(event).is_ok());
+        assert_eq!(ledger.produce_rotate_key_event(&fill_os_random), Ok(None));
+        assert_eq!(
+            ledger
+                .list_keys(ListKeysRequest {
+                    namespace: "tenant".into(),
+                    ..Default::default()
+                })
+                .map(|response| response.keys.len()),
+            Ok(2)
+        );
+    }
+
+    #[test]
+    fn test_create_key_invalid_cipher_suite() {
+        let mut ledger = LedgerService::create(
+            Box::new(MockEvidenceProvider::create().unwrap()),
+            Box::new(MockSigner::create().unwrap()),
+        )
+        .unwrap();
+        assert_err!(
+            ledger.create_key(CreateKeyRequest {
+                ttl: Some(prost_types::Duration {
+                    seconds: 3600,
+                    ..Default::default()
+                }),
+                cipher_suite: 100,
+                ..Default::default()
+            }),
+            micro_rpc::StatusCode::InvalidArgument,
+            "`cipher_suite` is invalid"
+        );
+    }
+
+    #[test]
+    fn test_authorize_access_batch() {
+        let (mut ledger, public_key) = create_ledger_service();
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+
+        // Define an access policy that grants access.
+        let recipient_tag = "tag";
+        let access_policy = DataAccessPolicy {
+            transforms: vec![Transform {
+                application: Some(ApplicationMatcher {
+                    tag: Some(recipient_tag.to_owned()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+        .encode_to_vec();
+
+        // Construct the blobs: the first one is accessible, the second one is
+        // subject to a different policy and the third one uses an unknown key.
+        let plaintext = b"plaintext";
+        let blob_headers = [
+            BlobHeader {
+                blob_id: "blob-id".into(),
+                key_id: cose_key.key_id.clone(),
+                access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+                ..Default::default()
+            },
+            BlobHeader {
+                blob_id: "blob-id".into(),
+                key_id: cose_key.key_id.clone(),
+                access_policy_sha256: b"invalid".into(),
+                ..Default::default()
+            },
+            BlobHeader {
+                blob_id: "blob-id".into(),
+                key_id: b"invalid".into(),
+                access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+                ..Default::default()
+            },
+        ]
+        .map(|header| header.encode_to_vec());
+        let recipient_nonce: &[u8] = b"nonce";
+        let mut ciphertexts = Vec::new();
+        let mut blobs = Vec::new();
+        for blob_header in &blob_headers {
+            let (ciphertext, encapsulated_key, encrypted_symmetric_key) =
+                cfc_crypto::encrypt_message(plaintext, &cose_key, blob_header).unwrap();
+            ciphertexts.push(ciphertext);
+            blobs.push(authorize_access_batch_request::BlobAccess {
+                blob_header: blob_header.clone(),
+                encapsulated_key,
+                encrypted_symmetric_key,
+                recipient_nonce: recipient_nonce.to_owned(),
+            });
+        }
+
+        // Request access.
+        let (recipient_private_key, recipient_public_key) = cfc_crypto::gen_keypair(b"key-id");
+        let response = ledger
+            .authorize_access_batch(AuthorizeAccessBatchRequest {
+                access_policy,
+                recipient_public_key: create_recipient_cwt(recipient_public_key),
+                recipient_tag: recipient_tag.to_owned(),
+                blobs,
+                ..Default::default()
+            })
+            .unwrap();
+
+        // Verify that the results are in the order of the request and the first one
+        // allows the message to be read.
+        assert_eq!(response.results.len(), 3);
+        let Some(blob_result::Outcome::Authorized(authorized)) = &response.results[0].outcome
+        else {
+            panic!("Authorized outcome expected");
+        };
+        assert_eq!(authorized.reencryption_public_key, public_key);
+        assert_eq!(
+            cfc_crypto::decrypt_message(
+                &ciphertexts[0],
+                &blob_headers[0],
+                &authorized.encrypted_symmetric_key,
+                &[&authorized.reencryption_public_key, recipient_nonce].concat(),
+                &authorized.encapsulated_key,
+                &recipient_private_key
+            )
+            .unwrap(),
+            plaintext
+        );
+        let Some(blob_result::Outcome::Error(error)) = &response.results[1].outcome else {
+            panic!("Error outcome expected");
+        };
+        assert_eq!(error.code, micro_rpc::StatusCode::InvalidArgument as i32);
+        assert!(error
+            .message
+            .contains("access policy does not match blob header"));
+        let Some(blob_result::Outcome::Error(error)) = &response.results[2].outcome else {
+            panic!("Error outcome expected");
+        };
+        assert_eq!(error.code, micro_rpc::StatusCode::NotFound as i32);
+    }
+
+    #[test]
+    fn test_authorize_access_batch_invalid_evidence() {
+        let (mut ledger, _) = create_ledger_service();
+
+        // The whole batch fails if the attestation cannot be verified.
+        let (_, recipient_public_key) = cfc_crypto::gen_keypair(b"key-id");
+        assert_err!(
+            ledger.authorize_access_batch(AuthorizeAccessBatchRequest {
+                recipient_public_key: create_recipient_cwt(recipient_public_key),
+                recipient_attestation_evidence: Some(Evidence::default()),
+                blobs: vec![Default::default()],
+                ..Default::default()
+            }),
+            micro_rpc::StatusCode::InvalidArgument,
+            "attestation validation failed"
+        );
+    }
+
+    #[test]
+    fn test_list_keys() {
+        let mut ledger = LedgerService::create(
+            Box::new(MockEvidenceProvider::create().unwrap()),
+            Box::new(MockSigner::create().unwrap()),
+        )
+        .unwrap();
+        let mut public_keys = Vec::new();
+        for (now, ttl) in [(1000, 100), (1050, 200)] {
+            let response = ledger
+                .create_key(CreateKeyRequest {
+                    now: Some(prost_types::Timestamp {
+                        seconds: now,
+                        ..Default::default()
+                    }),
+                    ttl: Some(prost_types::Duration {
+                        seconds: ttl,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+                .unwrap();
+            public_keys.push(response.public_key);
+        }
+
+        // Both keys are listed in the order of their ids.
+        let mut expected_keys = vec![
+            KeyDetails {
+                key_id: extract_key_from_cwt(&public_keys[0]).unwrap().key_id,
+                public_key: public_keys[0].clone(),
+                issued_at: Some(prost_types::Timestamp {
+                    seconds: 1000,
+                    ..Default::default()
+                }),
+                expiration: Some(prost_types::Timestamp {
+                    seconds: 1100,
+                    ..Default::default()
+                }),
+                state: KeyState::Active.into(),
+                purge_time: None,
+                cipher_suite: HpkeCipherSuite::X25519HkdfSha256Aes128gcm.into(),
+            },
+            KeyDetails {
+                key_id: extract_key_from_cwt(&public_keys[1]).unwrap().key_id,
+                public_key: public_keys[1].clone(),
+                issued_at: Some(prost_types::Timestamp {
+                    seconds: 1050,
+                    ..Default::default()
+                }),
+                expiration: Some(prost_types::Timestamp {
+                    seconds: 1250,
+                    ..Default::default()
+                }),
+                state: KeyState::Active.into(),
+                purge_time: None,
+                cipher_suite: HpkeCipherSuite::X25519HkdfSha256Aes128gcm.into(),
+            },
+        ];
+        expected_keys.sort_by(|a, b| a.key_id.cmp(&b.key_id));
+        assert_eq!(
+            ledger.list_keys(ListKeysRequest::default()),
+            Ok(ListKeysResponse {
+                keys: expected_keys.clone()
+            })
+        );
+
+        // The first key is expired by the time of the query.
+        let now = Some(prost_types::Timestamp {
+            seconds: 1100,
+            ..Default::default()
+        });
+        expected_keys.retain(|key| key.public_key == public_keys[1]);
+        assert_eq!(
+            ledger.list_keys(ListKeysRequest {
+                now: now.clone(),
+                ..Default::default()
+            }),
+            Ok(ListKeysResponse {
+                keys: expected_keys.clone()
+            })
+        );
+        assert_eq!(
+            ledger.get_key_details(GetKeyDetailsRequest {
+                now: now.clone(),
+                key_id: expected_keys[0].key_id.clone(),
+                ..Default::default()
+            }),
+            Ok(GetKeyDetailsResponse {
+                key: Some(expected_keys[0].clone())
+            })
+        );
+        assert_err!(
+            ledger.get_key_details(GetKeyDetailsRequest {
+                now,
+                key_id: extract_key_from_cwt(&public_keys[0]).unwrap().key_id,
+                ..Default::default()
+            }),
+            micro_rpc::StatusCode::NotFound,
+            "public key not found"
+        );
+
+        // Queries don't update the current time, so the expired key is still
+        // listed without the time.
+        assert_eq!(
+            ledger
+                .list_keys(ListKeysRequest::default())
+                .unwrap()
+                .keys
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let mut ledger = LedgerService::create(
+            Box::new(MockEvidenceProvider::create().unwrap()),
+            Box::new(MockSigner::create().unwrap()),
+        )
+        .unwrap();
+        assert_eq!(ledger.produce_rotate_key_event(&fill_os_random), Ok(None));
+        assert_err!(
+            ledger.set_key_rotation_config(KeyRotationConfig::default()),
+            micro_rpc::StatusCode::InvalidArgument,
+            "`period` must be positive"
+        );
+        assert_eq!(
+            ledger.set_key_rotation_config(KeyRotationConfig {
+                period: Some(prost_types::Duration {
+                    seconds: 100,
+                    ..Default::default()
+                }),
+                overlap: Some(prost_types::Duration {
+                    seconds: 20,
+                    ..Default::default()
+                }),
+            }),
+            Ok(())
+        );
+        let timestamp = |seconds| {
+            Some(prost_types::Timestamp {
+                seconds,
+                ..Default::default()
+            })
+        };
+        let expirations = |ledger: &mut LedgerService| {
+            ledger
+                .list_keys(ListKeysRequest::default())
+                .unwrap()
+                .keys
+                .into_iter()
+                .map(|key| key.expiration)
+                .collect::<Vec<_>>()
+        };
+
+        // The first key is minted right away since there are no keys.
+        ledger.update_current_time(&timestamp(1000)).unwrap();
+        let event = ledger
+            .produce_rotate_key_event(&fill_os_random)
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.expiration, timestamp(1120));
+        // The rotation is not proposed again while the proposal is replicated.
+        assert_eq!(ledger.produce_rotate_key_event(&fill_os_random), Ok(None));
+        assert!(ledger.apply_rotate_key_event(event).is_ok());
+        assert_eq!(ledger.produce_rotate_key_event(&fill_os_random), Ok(None));
+
+        // The successor is minted once the key expires within the overlap and
+        // both keys are advertised.
+        ledger.update_current_time(&timestamp(1099)).unwrap();
+        assert_eq!(ledger.produce_rotate_key_event(&fill_os_random), Ok(None));
+        ledger.update_current_time(&timestamp(1100)).unwrap();
+        let event = ledger
+            .produce_rotate_key_event(&fill_os_random)
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.expiration, timestamp(1220));
+        // The lost proposal is proposed again after half of the overlap.
+        ledger.update_current_time(&timestamp(1109)).unwrap();
+        assert_eq!(ledger.produce_rotate_key_event(&fill_os_random), Ok(None));
+        ledger.update_current_time(&timestamp(1110)).unwrap();
+        let event = ledger
+            .produce_rotate_key_event(&fill_os_random)
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.expiration, timestamp(1230));
+        assert!(ledger.apply_rotate_key_event(event).is_ok());
+        let mut actual = expirations(&mut ledger);
+        actual.sort();
+        assert_eq!(actual, vec![timestamp(1120), timestamp(1230)]);
+
+        // The key rotation is applied along with the requests that advance the time.
+        assert!(ledger
+            .create_key(CreateKeyRequest {
+                now: timestamp(1210),
+                ttl: Some(prost_types::Duration {
+                    seconds: 10,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .is_ok());
+        let mut actual = expirations(&mut ledger);
+        actual.sort();
+        assert_eq!(
+            actual,
+            vec![timestamp(1220), timestamp(1230), timestamp(1330)]
+        );
+    }
+
+    #[test]
+    fn test_revoke_access() {
+        let (mut ledger, public_key) = create_ledger_service();
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+        let blob_id = b"blob-id";
+        assert_eq!(
+            ledger.revoke_access(RevokeAccessRequest {
+                key_id: cose_key.key_id.clone(),
+                blob_id: blob_id.to_vec(),
+                ..Default::default()
+            }),
+            Ok(RevokeAccessResponse::default())
+        );
+
+        // Subsequent access should not be granted.
+        let access_policy = DataAccessPolicy {
+            transforms: vec![Transform::default()],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let plaintext = b"plaintext";
+        let blob_header = BlobHeader {
+            blob_id: blob_id.to_vec(),
+            key_id: cose_key.key_id.clone(),
+            access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let (_, encapsulated_key, encrypted_symmetric_key) =
+            cfc_crypto::encrypt_message(plaintext, &cose_key, &blob_header).unwrap();
+
+        assert_err!(
+            ledger.authorize_access(AuthorizeAccessRequest {
                 access_policy,
                 blob_header: blob_header.clone(),
                 encapsulated_key,
@@ -1412,20 +5003,477 @@ mod tests {
             micro_rpc::StatusCode::ResourceExhausted,
             ""
         );
-    }
+    }
+
+    #[test]
+    fn test_revoke_access_transform() {
+        let (mut ledger, public_key) = create_ledger_service();
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+        let blob_id = b"blob-id";
+        assert_eq!(
+            ledger.revoke_access(RevokeAccessRequest {
+                key_id: cose_key.key_id.clone(),
+                blob_id: blob_id.to_vec(),
+                transform_index: Some(0),
+                ..Default::default()
+            }),
+            Ok(RevokeAccessResponse::default())
+        );
+
+        // Access through the revoked transform should not be granted.
+        let access_policy = DataAccessPolicy {
+            transforms: vec![
+                Transform {
+                    application: Some(ApplicationMatcher {
+                        tag: Some("tag1".to_owned()),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                Transform {
+                    application: Some(ApplicationMatcher {
+                        tag: Some("tag2".to_owned()),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let blob_header = BlobHeader {
+            blob_id: blob_id.to_vec(),
+            key_id: cose_key.key_id.clone(),
+            access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let (_, encapsulated_key, encrypted_symmetric_key) =
+            cfc_crypto::encrypt_message(b"plaintext", &cose_key, &blob_header).unwrap();
+        let request = AuthorizeAccessRequest {
+            access_policy,
+            blob_header,
+            encapsulated_key,
+            encrypted_symmetric_key,
+            recipient_public_key: create_recipient_cwt(cfc_crypto::gen_keypair(b"key-id").1),
+            recipient_nonce: b"nonce".to_vec(),
+            ..Default::default()
+        };
+        assert_err!(
+            ledger.authorize_access(AuthorizeAccessRequest {
+                recipient_tag: "tag1".to_owned(),
+                ..request.clone()
+            }),
+            micro_rpc::StatusCode::ResourceExhausted,
+            ""
+        );
+
+        // But access through the other transform should still be granted.
+        assert!(ledger
+            .authorize_access(AuthorizeAccessRequest {
+                recipient_tag: "tag2".to_owned(),
+                ..request
+            })
+            .is_ok());
+    }
+
+    #[test]
+    fn test_revoke_access_key_not_found() {
+        let (mut ledger, public_key) = create_ledger_service();
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+        assert_err!(
+            ledger.revoke_access(RevokeAccessRequest {
+                key_id: cose_key.key_id.iter().chain(b"x").cloned().collect(),
+                blob_id: "blob-id".into(),
+                ..Default::default()
+            }),
+            micro_rpc::StatusCode::NotFound,
+            "public key not found"
+        );
+    }
+
+    #[test]
+    fn test_audit_log_revocations_and_deletions() {
+        let (mut ledger, public_key) = create_ledger_service();
+        ledger
+            .set_key_deletion_grace_period(prost_types::Duration {
+                seconds: 100,
+                ..Default::default()
+            })
+            .unwrap();
+        let key_id = extract_key_from_cwt(&public_key).unwrap().key_id;
+
+        assert_eq!(
+            ledger.revoke_access(RevokeAccessRequest {
+                key_id: key_id.clone(),
+                blob_id: b"blob1".to_vec(),
+                transform_index: Some(1),
+                ..Default::default()
+            }),
+            Ok(RevokeAccessResponse::default())
+        );
+        assert_eq!(
+            ledger.revoke_access(RevokeAccessRequest {
+                key_id: key_id.clone(),
+                blob_id: b"blob2".to_vec(),
+                ..Default::default()
+            }),
+            Ok(RevokeAccessResponse::default())
+        );
+        // Deleting the disabled key again isn't recorded.
+        for _ in 0..2 {
+            assert_eq!(
+                ledger.delete_key(DeleteKeyRequest {
+                    public_key: public_key.clone(),
+                    ..Default::default()
+                }),
+                Ok(DeleteKeyResponse::default())
+            );
+        }
+
+        let response = ledger
+            .query_audit_log(QueryAuditLogRequest::default())
+            .unwrap();
+        assert_eq!(
+            response
+                .entries
+                .iter()
+                .map(|entry| (
+                    entry.action(),
+                    entry.key_id.clone(),
+                    entry.blob_id.clone(),
+                    entry.transform_index
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    audit_log_entry::Action::TransformRevoked,
+                    key_id.clone(),
+                    b"blob1".to_vec(),
+                    1
+                ),
+                (
+                    audit_log_entry::Action::BudgetConsumed,
+                    key_id.clone(),
+                    b"blob2".to_vec(),
+                    0
+                ),
+                (
+                    audit_log_entry::Action::KeyDisabled,
+                    key_id.clone(),
+                    vec![],
+                    0
+                ),
+            ]
+        );
+
+        // Snapshots with entries other than authorized accesses can't be loaded by older
+        // ledgers.
+        let snapshot = ledger.save_snapshot().unwrap();
+        assert_eq!(
+            snapshot.min_compatible_version,
+            AUDIT_LOG_CHECKPOINT_MIN_COMPATIBLE_VERSION
+        );
+    }
+
+    #[test]
+    fn test_audit_log_retention() {
+        let (mut ledger, public_key) = create_ledger_service();
+        ledger.set_audit_log_retained_entries(2);
+        let key_id = extract_key_from_cwt(&public_key).unwrap().key_id;
+        for blob_id in [b"blob1", b"blob2", b"blob3"] {
+            assert_eq!(
+                ledger.revoke_access(RevokeAccessRequest {
+                    key_id: key_id.clone(),
+                    blob_id: blob_id.to_vec(),
+                    ..Default::default()
+                }),
+                Ok(RevokeAccessResponse::default())
+            );
+        }
+
+        // The oldest entry is compacted into the checkpoint, which is carried in the snapshot.
+        let snapshot = ledger.save_snapshot().unwrap();
+        assert_eq!(snapshot.audit_log.len(), 2);
+        assert_eq!(snapshot.audit_log[0].sequence_number, 1);
+        let checkpoint = snapshot.audit_log_checkpoint.clone().unwrap();
+        assert_eq!(checkpoint.sequence_number, 1);
+        assert_eq!(
+            snapshot.audit_log[0].entry_sha256,
+            AuditLog::compute_entry_sha256(&checkpoint.entry_sha256, &snapshot.audit_log[0])
+        );
+
+        assert_eq!(ledger.load_snapshot(snapshot.clone()), Ok(()));
+        let reloaded = ledger.save_snapshot().unwrap();
+        assert_eq!(reloaded.audit_log_checkpoint, snapshot.audit_log_checkpoint);
+        assert_eq!(reloaded.audit_log, snapshot.audit_log);
+        let response = ledger
+            .query_audit_log(QueryAuditLogRequest::default())
+            .unwrap();
+        assert_eq!(response.first_sequence_number, 1);
+        assert_eq!(response.previous_entry_sha256, checkpoint.entry_sha256);
+    }
+
+    #[test]
+    fn test_rewrap_keys() {
+        let (mut ledger, old_public_key) = create_ledger_service();
+        let old_cose_key = extract_key_from_cwt(&old_public_key).unwrap();
+        let old_key_id = old_cose_key.key_id.clone();
+        let new_public_key = ledger
+            .create_key(CreateKeyRequest {
+                ttl: Some(prost_types::Duration {
+                    seconds: 7200,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .unwrap()
+            .public_key;
+        let new_key_id = extract_key_from_cwt(&new_public_key).unwrap().key_id;
+
+        // Encrypt a blob with the old key and access it once.
+        let access_policy = DataAccessPolicy {
+            transforms: vec![Transform {
+                access_budget: Some(AccessBudget {
+                    kind: Some(AccessBudgetKind::Times(2)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let plaintext = b"plaintext";
+        let blob_header = BlobHeader {
+            blob_id: "blob-id".into(),
+            key_id: old_key_id.clone(),
+            access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let (ciphertext, encapsulated_key, encrypted_symmetric_key) =
+            cfc_crypto::encrypt_message(plaintext, &old_cose_key, &blob_header).unwrap();
+        let (recipient_private_key, recipient_public_key) = cfc_crypto::gen_keypair(b"key-id");
+        let request = AuthorizeAccessRequest {
+            access_policy,
+            blob_header: blob_header.clone(),
+            encapsulated_key: encapsulated_key.clone(),
+            encrypted_symmetric_key: encrypted_symmetric_key.clone(),
+            recipient_public_key: create_recipient_cwt(recipient_public_key),
+            recipient_nonce: b"nonce".to_vec(),
+            ..Default::default()
+        };
+        assert!(ledger.authorize_access(request.clone()).is_ok());
+
+        // Rewrap the symmetric key to the new key. A blob that isn't encrypted with the old key
+        // is rejected without affecting the rest of the batch.
+        let response = ledger
+            .rewrap_keys(RewrapKeysRequest {
+                old_key_id: old_key_id.clone(),
+                new_key_id: new_key_id.clone(),
+                blobs: vec![
+                    rewrap_keys_request::Blob {
+                        blob_header: blob_header.clone(),
+                        encapsulated_key,
+                        encrypted_symmetric_key,
+                    },
+                    rewrap_keys_request::Blob {
+                        blob_header: BlobHeader {
+                            blob_id: "other".into(),
+                            key_id: new_key_id.clone(),
+                            ..Default::default()
+                        }
+                        .encode_to_vec(),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(response.results.len(), 2);
+        let Some(rewrap_keys_response::blob_result::Outcome::Rewrapped(rewrapped)) =
+            response.results[0].outcome.clone()
+        else {
+            panic!("Rewrapped outcome expected");
+        };
+        assert_eq!(
+            BlobHeader::decode(rewrapped.blob_header.as_ref())
+                .unwrap()
+                .key_id,
+            new_key_id
+        );
+        let Some(rewrap_keys_response::blob_result::Outcome::Error(error)) =
+            &response.results[1].outcome
+        else {
+            panic!("Error outcome expected");
+        };
+        assert_eq!(error.code, micro_rpc::StatusCode::InvalidArgument as i32);
+        assert!(error
+            .message
+            .contains("blob is not encrypted with `old_key_id`"));
+
+        // The blob can no longer be accessed through the old key.
+        assert_err!(
+            ledger.authorize_access(request.clone()),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "data access budget consumed"
+        );
+
+        // The rewrapped blob can be accessed through the new key with the remaining budget.
+        let response = ledger
+            .authorize_access(AuthorizeAccessRequest {
+                blob_header: rewrapped.blob_header.clone(),
+                encapsulated_key: rewrapped.encapsulated_key.clone(),
+                encrypted_symmetric_key: rewrapped.encrypted_symmetric_key.clone(),
+                ..request.clone()
+            })
+            .unwrap();
+        assert_eq!(response.reencryption_public_key, new_public_key);
+        assert_eq!(
+            cfc_crypto::decrypt_message(
+                &ciphertext,
+                &blob_header,
+                &response.encrypted_symmetric_key,
+                &[&response.reencryption_public_key, &b"nonce"[..]].concat(),
+                &response.encapsulated_key,
+                &recipient_private_key
+            )
+            .unwrap(),
+            plaintext
+        );
+        assert_err!(
+            ledger.authorize_access(AuthorizeAccessRequest {
+                blob_header: rewrapped.blob_header,
+                encapsulated_key: rewrapped.encapsulated_key,
+                encrypted_symmetric_key: rewrapped.encrypted_symmetric_key,
+                ..request
+            }),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "data access budget exhausted"
+        );
+    }
+
+    #[test]
+    fn test_rewrap_keys_invalid_keys() {
+        let (mut ledger, old_public_key) = create_ledger_service();
+        let old_key_id = extract_key_from_cwt(&old_public_key).unwrap().key_id;
+
+        assert_err!(
+            ledger.rewrap_keys(RewrapKeysRequest {
+                old_key_id: old_key_id.clone(),
+                new_key_id: old_key_id.clone(),
+                ..Default::default()
+            }),
+            micro_rpc::StatusCode::InvalidArgument,
+            "`old_key_id` and `new_key_id` must differ"
+        );
+        assert_err!(
+            ledger.rewrap_keys(RewrapKeysRequest {
+                old_key_id: old_key_id.clone(),
+                new_key_id: b"unknown".to_vec(),
+                ..Default::default()
+            }),
+            micro_rpc::StatusCode::NotFound,
+            "public key not found"
+        );
+
+        // The new key must outlive the old key.
+        let new_public_key = ledger
+            .create_key(CreateKeyRequest {
+                ttl: Some(prost_types::Duration {
+                    seconds: 100,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .unwrap()
+            .public_key;
+        assert_err!(
+            ledger.rewrap_keys(RewrapKeysRequest {
+                old_key_id,
+                new_key_id: extract_key_from_cwt(&new_public_key).unwrap().key_id,
+                ..Default::default()
+            }),
+            micro_rpc::StatusCode::FailedPrecondition,
+            "`new_key_id` must expire after `old_key_id`"
+        );
+    }
+
+    #[test]
+    fn test_apply_rewrap_keys_event_without_trusted_time() {
+        let (mut leader, old_public_key) = create_ledger_service();
+        let old_cose_key = extract_key_from_cwt(&old_public_key).unwrap();
+        leader.set_trusted_time(Some(Duration::from_secs(1000)));
+        let new_public_key = leader
+            .create_key(CreateKeyRequest {
+                ttl: Some(prost_types::Duration {
+                    seconds: 7200,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .unwrap()
+            .public_key;
+        let new_key_id = extract_key_from_cwt(&new_public_key).unwrap().key_id;
+
+        // The follower has the same state as the leader but no trusted time source.
+        let mut follower = LedgerService::create(
+            Box::new(MockEvidenceProvider::create().unwrap()),
+            Box::new(MockSigner::create().unwrap()),
+        )
+        .unwrap();
+        follower.set_require_trusted_time(true);
+        assert_eq!(
+            follower.load_snapshot(leader.save_snapshot().unwrap()),
+            Ok(())
+        );
+
+        let blob_header = BlobHeader {
+            blob_id: "blob-id".into(),
+            key_id: old_cose_key.key_id.clone(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let (_, encapsulated_key, encrypted_symmetric_key) =
+            cfc_crypto::encrypt_message(b"plaintext", &old_cose_key, &blob_header).unwrap();
+
+        // The event is stamped with the trusted time of the leader, the time passed by the
+        // untrusted side is ignored.
+        leader.set_trusted_time(Some(Duration::from_secs(2000)));
+        let event = leader
+            .produce_rewrap_keys_event(RewrapKeysRequest {
+                now: Some(prost_types::Timestamp {
+                    seconds: 5000,
+                    ..Default::default()
+                }),
+                old_key_id: old_cose_key.key_id,
+                new_key_id,
+                blobs: vec![rewrap_keys_request::Blob {
+                    blob_header,
+                    encapsulated_key,
+                    encrypted_symmetric_key,
+                }],
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            event.event_time,
+            Some(prost_types::Timestamp {
+                seconds: 2000,
+                ..Default::default()
+            })
+        );
 
-    #[test]
-    fn test_revoke_access_key_not_found() {
-        let (mut ledger, public_key) = create_ledger_service();
-        let cose_key = extract_key_from_cwt(&public_key).unwrap();
-        assert_err!(
-            ledger.revoke_access(RevokeAccessRequest {
-                key_id: cose_key.key_id.iter().chain(b"x").cloned().collect(),
-                blob_id: "blob-id".into(),
-                ..Default::default()
-            }),
-            micro_rpc::StatusCode::NotFound,
-            "public key not found"
+        // The follower applies the event as of the time of the leader.
+        let response = follower.apply_rewrap_keys_event(event.clone()).unwrap();
+        assert!(matches!(
+            response.results[0].outcome,
+            Some(rewrap_keys_response::blob_result::Outcome::Rewrapped(_))
+        ));
+        assert!(leader.apply_rewrap_keys_event(event).is_ok());
+        assert_eq!(
+            follower.save_snapshot().unwrap().current_time,
+            leader.save_snapshot().unwrap().current_time
         );
     }
 
@@ -1438,31 +5486,39 @@ mod tests {
         .unwrap();
 
         let event1 = ledger
-            .produce_create_key_event(CreateKeyRequest {
-                now: Some(prost_types::Timestamp {
-                    seconds: 1000,
-                    ..Default::default()
-                }),
-                ttl: Some(prost_types::Duration {
-                    seconds: 100,
+            .produce_create_key_event(
+                CreateKeyRequest {
+                    now: Some(prost_types::Timestamp {
+                        seconds: 1000,
+                        ..Default::default()
+                    }),
+                    ttl: Some(prost_types::Duration {
+                        seconds: 100,
+                        ..Default::default()
+                    }),
                     ..Default::default()
-                }),
-            })
+                },
+                &fill_os_random,
+            )
             .unwrap();
 
         // The second request uses the `now` time that is before the `now` time from
         // the first request
         let event2 = ledger
-            .produce_create_key_event(CreateKeyRequest {
-                now: Some(prost_types::Timestamp {
-                    seconds: 500,
-                    ..Default::default()
-                }),
-                ttl: Some(prost_types::Duration {
-                    seconds: 100,
+            .produce_create_key_event(
+                CreateKeyRequest {
+                    now: Some(prost_types::Timestamp {
+                        seconds: 500,
+                        ..Default::default()
+                    }),
+                    ttl: Some(prost_types::Duration {
+                        seconds: 100,
+                        ..Default::default()
+                    }),
                     ..Default::default()
-                }),
-            })
+                },
+                &fill_os_random,
+            )
             .unwrap();
 
         // Despite that the event_time should not go back and the expiration time for
@@ -1506,16 +5562,20 @@ mod tests {
         .unwrap();
 
         let event = ledger
-            .produce_create_key_event(CreateKeyRequest {
-                now: Some(prost_types::Timestamp {
-                    seconds: 1000,
-                    ..Default::default()
-                }),
-                ttl: Some(prost_types::Duration {
-                    seconds: 100,
+            .produce_create_key_event(
+                CreateKeyRequest {
+                    now: Some(prost_types::Timestamp {
+                        seconds: 1000,
+                        ..Default::default()
+                    }),
+                    ttl: Some(prost_types::Duration {
+                        seconds: 100,
+                        ..Default::default()
+                    }),
                     ..Default::default()
-                }),
-            })
+                },
+                &fill_os_random,
+            )
             .unwrap();
 
         // Applying the event for the first time should work.
@@ -1538,16 +5598,20 @@ mod tests {
         .unwrap();
 
         let mut event = ledger
-            .produce_create_key_event(CreateKeyRequest {
-                now: Some(prost_types::Timestamp {
-                    seconds: 1000,
-                    ..Default::default()
-                }),
-                ttl: Some(prost_types::Duration {
-                    seconds: 100,
+            .produce_create_key_event(
+                CreateKeyRequest {
+                    now: Some(prost_types::Timestamp {
+                        seconds: 1000,
+                        ..Default::default()
+                    }),
+                    ttl: Some(prost_types::Duration {
+                        seconds: 100,
+                        ..Default::default()
+                    }),
                     ..Default::default()
-                }),
-            })
+                },
+                &fill_os_random,
+            )
             .unwrap();
 
         event.public_key = b"public-key".into();
@@ -1567,18 +5631,33 @@ mod tests {
         .unwrap();
 
         let mut event = ledger
-            .produce_create_key_event(CreateKeyRequest {
-                now: Some(prost_types::Timestamp {
-                    seconds: 1000,
-                    ..Default::default()
-                }),
-                ttl: Some(prost_types::Duration {
-                    seconds: 100,
+            .produce_create_key_event(
+                CreateKeyRequest {
+                    now: Some(prost_types::Timestamp {
+                        seconds: 1000,
+                        ..Default::default()
+                    }),
+                    ttl: Some(prost_types::Duration {
+                        seconds: 100,
+                        ..Default::default()
+                    }),
                     ..Default::default()
-                }),
-            })
+                },
+                &fill_os_random,
+            )
             .unwrap();
 
+        let key_id = extract_key_from_cwt(&event.public_key).unwrap().key_id;
+        event.wrapped_private_key =
+            wrap_private_key(&ledger.key_wrapping_key, &key_id, b"private-key").unwrap();
+        assert_err!(
+            ledger.apply_create_key_event(event.clone()),
+            micro_rpc::StatusCode::InvalidArgument,
+            "failed to parse private_key"
+        );
+
+        // The plaintext private keys of the older ledgers are parsed as well.
+        event.wrapped_private_key = vec![];
         event.private_key = b"private-key".into();
         assert_err!(
             ledger.apply_create_key_event(event),
@@ -1587,6 +5666,113 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_apply_create_key_event_invalid_wrapped_private_key() {
+        let mut ledger = LedgerService::create(
+            Box::new(MockEvidenceProvider::create().unwrap()),
+            Box::new(MockSigner::create().unwrap()),
+        )
+        .unwrap();
+
+        let event = ledger
+            .produce_create_key_event(
+                CreateKeyRequest {
+                    now: Some(prost_types::Timestamp {
+                        seconds: 1000,
+                        ..Default::default()
+                    }),
+                    ttl: Some(prost_types::Duration {
+                        seconds: 100,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                &fill_os_random,
+            )
+            .unwrap();
+        assert!(event.private_key.is_empty());
+
+        // Replicas that don't share the key wrapping key can't unwrap the private key.
+        ledger.set_key_wrapping_key(vec![1; 32]);
+        assert_err!(
+            ledger.apply_create_key_event(event),
+            micro_rpc::StatusCode::InvalidArgument,
+            "failed to unwrap private_key"
+        );
+    }
+
+    #[test]
+    fn test_apply_create_key_event_mismatching_private_key() {
+        let mut ledger = LedgerService::create(
+            Box::new(MockEvidenceProvider::create().unwrap()),
+            Box::new(MockSigner::create().unwrap()),
+        )
+        .unwrap();
+
+        let mut event = ledger
+            .produce_create_key_event(
+                CreateKeyRequest {
+                    now: Some(prost_types::Timestamp {
+                        seconds: 1000,
+                        ..Default::default()
+                    }),
+                    ttl: Some(prost_types::Duration {
+                        seconds: 100,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                &fill_os_random,
+            )
+            .unwrap();
+
+        let (private_key, _) = cfc_crypto::gen_keypair(b"key-id");
+        let key_id = extract_key_from_cwt(&event.public_key).unwrap().key_id;
+        event.wrapped_private_key = ledger.wrap_private_key(&key_id, &private_key).unwrap();
+        assert_err!(
+            ledger.apply_create_key_event(event),
+            micro_rpc::StatusCode::InvalidArgument,
+            "private_key does not match public_key"
+        );
+    }
+
+    #[test]
+    fn test_produce_create_key_event_private_key_not_from_random_source() {
+        let mut ledger = LedgerService::create(
+            Box::new(MockEvidenceProvider::create().unwrap()),
+            Box::new(MockSigner::create().unwrap()),
+        )
+        .unwrap();
+        let request = CreateKeyRequest {
+            now: Some(prost_types::Timestamp {
+                seconds: 1000,
+                ..Default::default()
+            }),
+            ttl: Some(prost_types::Duration {
+                seconds: 100,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        // The key id is drawn from the source of random bytes, which may be visible
+        // to the host, while the keypair is not.
+        let fill_fixed = |dest: &mut [u8]| dest.fill(7);
+        let event1 = ledger
+            .produce_create_key_event(request.clone(), &fill_fixed)
+            .unwrap();
+        let event2 = ledger
+            .produce_create_key_event(request, &fill_fixed)
+            .unwrap();
+        let cose_key1 = extract_key_from_cwt(&event1.public_key).unwrap();
+        let cose_key2 = extract_key_from_cwt(&event2.public_key).unwrap();
+        assert_eq!(cose_key1.key_id, cose_key2.key_id);
+        assert_ne!(cose_key1, cose_key2);
+        assert!(event1.private_key.is_empty());
+        assert_ne!(event1.wrapped_private_key, event2.wrapped_private_key);
+        assert!(ledger.apply_create_key_event(event1).is_ok());
+    }
+
     #[test]
     fn test_save_snapshot() {
         let (mut ledger, public_key) = create_ledger_service();
@@ -1643,19 +5829,34 @@ mod tests {
         assert_eq!(snapshot.per_key_snapshots.len(), 1);
         // Since the private key isn't exposed we have to assume that the one
         // in the snapshot is the right one.
-        let private_key = &snapshot.per_key_snapshots[0].private_key;
+        let wrapped_private_key = &snapshot.per_key_snapshots[0].wrapped_private_key;
+        assert!(unwrap_private_key(
+            &ledger.key_wrapping_key,
+            &cose_key.key_id,
+            wrapped_private_key
+        )
+        .is_ok());
+        // The audit log is verified separately since the recipient CWT isn't known.
+        assert_eq!(snapshot.audit_log.len(), 1);
+        assert_eq!(snapshot.audit_log[0].blob_id, b"blob-id".to_vec());
+        assert_eq!(snapshot.audit_log[0].recipient_tag, recipient_tag);
         assert_eq!(
             snapshot,
             LedgerSnapshot {
+                version: LEDGER_SNAPSHOT_VERSION,
+                min_compatible_version: WRAPPED_PRIVATE_KEYS_MIN_COMPATIBLE_VERSION,
+                audit_log: snapshot.audit_log.clone(),
                 current_time: Some(now),
                 per_key_snapshots: vec![PerKeySnapshot {
                     key_id: cose_key.key_id.clone(),
                     public_key,
-                    private_key: private_key.clone(),
+                    private_key: vec![],
+                    wrapped_private_key: wrapped_private_key.clone(),
                     expiration: Some(prost_types::Timestamp {
                         seconds: 3600,
                         ..Default::default()
                     }),
+                    issued_at: Some(prost_types::Timestamp::default()),
                     budgets: Some(BudgetSnapshot {
                         per_policy_snapshots: vec![PerPolicyBudgetSnapshot {
                             access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
@@ -1663,11 +5864,21 @@ mod tests {
                                 blob_id: "blob-id".into(),
                                 transform_access_budgets: vec![0],
                                 shared_access_budgets: vec![],
-                            }]
+                                charged_policy_access_budgets: vec![],
+                                node_id: 0,
+                            }],
+                            policy_access_budgets: vec![],
                         }],
                         consumed_budgets: vec![],
+                        blob_expirations: vec![],
+                        revoked_transforms: vec![],
+                        version: budget::BUDGET_SNAPSHOT_VERSION,
+                        min_compatible_version: budget::BUDGET_SNAPSHOT_MIN_COMPATIBLE_VERSION,
                     }),
+                    purge_time: None,
+                    namespace: String::new(),
                 }],
+                idempotency_tokens: vec![],
             }
         );
     }
@@ -1677,6 +5888,8 @@ mod tests {
         let (mut ledger, _) = create_ledger_service();
         let (private_key, public_key) = cfc_crypto::gen_keypair(b"key-id");
         let snapshot = LedgerSnapshot {
+            version: LEDGER_SNAPSHOT_VERSION,
+            min_compatible_version: WRAPPED_PRIVATE_KEYS_MIN_COMPATIBLE_VERSION,
             current_time: Some(prost_types::Timestamp {
                 seconds: 1000,
                 ..Default::default()
@@ -1685,11 +5898,16 @@ mod tests {
                 PerKeySnapshot {
                     key_id: b"key1".to_vec(),
                     public_key: create_recipient_cwt(public_key.clone()),
-                    private_key: private_key.to_bytes().to_vec(),
+                    private_key: vec![],
+                    wrapped_private_key: ledger.wrap_private_key(b"key1", &private_key).unwrap(),
                     expiration: Some(prost_types::Timestamp {
                         seconds: 2000,
                         ..Default::default()
                     }),
+                    issued_at: Some(prost_types::Timestamp {
+                        seconds: 500,
+                        ..Default::default()
+                    }),
                     budgets: Some(BudgetSnapshot {
                         per_policy_snapshots: vec![PerPolicyBudgetSnapshot {
                             access_policy_sha256: b"hash1".to_vec(),
@@ -1697,24 +5915,46 @@ mod tests {
                                 blob_id: b"blob1".to_vec(),
                                 ..Default::default()
                             }],
+                            policy_access_budgets: vec![],
                         }],
                         consumed_budgets: vec![],
+                        blob_expirations: vec![],
+                        revoked_transforms: vec![],
+                        version: budget::BUDGET_SNAPSHOT_VERSION,
+                        min_compatible_version: budget::BUDGET_SNAPSHOT_MIN_COMPATIBLE_VERSION,
                     }),
+                    purge_time: None,
+                    namespace: String::new(),
                 },
                 PerKeySnapshot {
                     key_id: b"key2".to_vec(),
                     public_key: create_recipient_cwt(public_key.clone()),
-                    private_key: private_key.to_bytes().to_vec(),
+                    private_key: vec![],
+                    wrapped_private_key: ledger.wrap_private_key(b"key2", &private_key).unwrap(),
                     expiration: Some(prost_types::Timestamp {
                         seconds: 2500,
                         ..Default::default()
                     }),
+                    issued_at: Some(prost_types::Timestamp {
+                        seconds: 900,
+                        ..Default::default()
+                    }),
                     budgets: Some(BudgetSnapshot {
                         per_policy_snapshots: vec![],
                         consumed_budgets: vec![b"blob2".to_vec()],
+                        blob_expirations: vec![],
+                        revoked_transforms: vec![],
+                        version: budget::BUDGET_SNAPSHOT_VERSION,
+                        min_compatible_version: budget::BUDGET_SNAPSHOT_MIN_COMPATIBLE_VERSION,
                     }),
+                    purge_time: None,
+                    namespace: String::new(),
                 },
             ],
+            audit_log: vec![],
+            idempotency_tokens: vec![],
+            audit_log_checkpoint: None,
+            rate_limit_counters: vec![],
         };
         // Load the snapshot then save a new one and verify that the same
         // snapshot is produced.
@@ -1726,6 +5966,8 @@ mod tests {
     fn test_load_snapshot_replaces_state() {
         let (mut ledger, _) = create_ledger_service();
         let snapshot = LedgerSnapshot {
+            version: LEDGER_SNAPSHOT_VERSION,
+            min_compatible_version: LEDGER_SNAPSHOT_MIN_COMPATIBLE_VERSION,
             current_time: Some(prost_types::Timestamp::default()),
             ..Default::default()
         };
@@ -1755,9 +5997,182 @@ mod tests {
                         ..Default::default()
                     }
                 ],
+                ..Default::default()
             }),
             micro_rpc::StatusCode::InvalidArgument,
             "Duplicated key_id in the snapshot"
         );
     }
+
+    #[test]
+    fn test_load_snapshot_unversioned() {
+        let (mut ledger, _) = create_ledger_service();
+        let (private_key, public_key) = cfc_crypto::gen_keypair(b"key-id");
+        // Snapshots taken before the versioning was introduced are loaded
+        // and saved back with the current version.
+        let snapshot = LedgerSnapshot {
+            current_time: Some(prost_types::Timestamp::default()),
+            per_key_snapshots: vec![PerKeySnapshot {
+                key_id: b"key1".to_vec(),
+                public_key: create_recipient_cwt(public_key),
+                private_key: private_key.to_bytes().to_vec(),
+                expiration: Some(prost_types::Timestamp::default()),
+                budgets: Some(BudgetSnapshot::default()),
+                issued_at: Some(prost_types::Timestamp::default()),
+                purge_time: None,
+                namespace: String::new(),
+                wrapped_private_key: vec![],
+            }],
+            version: 0,
+            min_compatible_version: 0,
+            audit_log: vec![],
+            idempotency_tokens: vec![],
+            audit_log_checkpoint: None,
+            rate_limit_counters: vec![],
+        };
+        assert_eq!(ledger.load_snapshot(snapshot.clone()), Ok(()));
+        // The plaintext private keys are saved back wrapped.
+        let mut expected = LedgerSnapshot {
+            version: LEDGER_SNAPSHOT_VERSION,
+            min_compatible_version: WRAPPED_PRIVATE_KEYS_MIN_COMPATIBLE_VERSION,
+            ..snapshot
+        };
+        expected.per_key_snapshots[0].private_key = vec![];
+        expected.per_key_snapshots[0].wrapped_private_key =
+            ledger.wrap_private_key(b"key1", &private_key).unwrap();
+        expected.per_key_snapshots[0].budgets = Some(BudgetSnapshot {
+            version: budget::BUDGET_SNAPSHOT_VERSION,
+            min_compatible_version: budget::BUDGET_SNAPSHOT_MIN_COMPATIBLE_VERSION,
+            ..Default::default()
+        });
+        assert_eq!(ledger.save_snapshot(), Ok(expected));
+    }
+
+    #[test]
+    fn test_load_snapshot_unsupported_version() {
+        let (mut ledger, _) = create_ledger_service();
+        let snapshot = ledger.save_snapshot().unwrap();
+        assert_err!(
+            ledger.load_snapshot(LedgerSnapshot {
+                version: LEDGER_SNAPSHOT_VERSION + 1,
+                min_compatible_version: LEDGER_SNAPSHOT_VERSION + 1,
+                current_time: Some(prost_types::Timestamp::default()),
+                ..Default::default()
+            }),
+            micro_rpc::StatusCode::InvalidArgument,
+            "Unsupported snapshot version"
+        );
+        // The state is left intact.
+        assert_eq!(ledger.save_snapshot(), Ok(snapshot));
+    }
+
+    #[test]
+    fn test_load_snapshot_newer_compatible_version() {
+        let (mut ledger, _) = create_ledger_service();
+        let snapshot = LedgerSnapshot {
+            version: LEDGER_SNAPSHOT_VERSION + 1,
+            min_compatible_version: LEDGER_SNAPSHOT_VERSION,
+            current_time: Some(prost_types::Timestamp {
+                seconds: 1000,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        // Snapshots of a newer version are loaded as long as they are
+        // declared compatible, and saved back with the current version.
+        assert_eq!(ledger.load_snapshot(snapshot.clone()), Ok(()));
+        assert_eq!(
+            ledger.save_snapshot(),
+            Ok(LedgerSnapshot {
+                version: LEDGER_SNAPSHOT_VERSION,
+                min_compatible_version: LEDGER_SNAPSHOT_MIN_COMPATIBLE_VERSION,
+                ..snapshot
+            })
+        );
+    }
+
+    #[test]
+    fn test_trusted_time() {
+        let mut ledger = LedgerService::create(
+            Box::new(MockEvidenceProvider::create().unwrap()),
+            Box::new(MockSigner::create().unwrap()),
+        )
+        .unwrap();
+        ledger.set_trusted_time(Some(Duration::from_secs(1000)));
+        // The time passed by the untrusted side is ignored.
+        let public_key = ledger
+            .create_key(CreateKeyRequest {
+                now: Some(prost_types::Timestamp {
+                    seconds: 5000,
+                    ..Default::default()
+                }),
+                ttl: Some(prost_types::Duration {
+                    seconds: 100,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .unwrap()
+            .public_key;
+        let keys = ledger
+            .list_keys(ListKeysRequest {
+                now: Some(prost_types::Timestamp {
+                    seconds: 5000,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .unwrap()
+            .keys;
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].public_key, public_key);
+        assert_eq!(
+            keys[0].expiration,
+            Some(prost_types::Timestamp {
+                seconds: 1100,
+                ..Default::default()
+            })
+        );
+
+        // The key expires once the attested time passes its expiration.
+        ledger.set_trusted_time(Some(Duration::from_secs(1100)));
+        assert_eq!(
+            ledger.list_keys(ListKeysRequest::default()).unwrap().keys,
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_require_trusted_time() {
+        let mut ledger = LedgerService::create(
+            Box::new(MockEvidenceProvider::create().unwrap()),
+            Box::new(MockSigner::create().unwrap()),
+        )
+        .unwrap();
+        ledger.set_require_trusted_time(true);
+        let request = CreateKeyRequest {
+            now: Some(prost_types::Timestamp {
+                seconds: 1000,
+                ..Default::default()
+            }),
+            ttl: Some(prost_types::Duration {
+                seconds: 100,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_err!(
+            ledger.create_key(request.clone()),
+            micro_rpc::StatusCode::Unavailable,
+            "trusted wall clock time is not available"
+        );
+        assert_err!(
+            ledger.list_keys(ListKeysRequest::default()),
+            micro_rpc::StatusCode::Unavailable,
+            "trusted wall clock time is not available"
+        );
+
+        ledger.set_trusted_time(Some(Duration::from_secs(1000)));
+        assert!(ledger.create_key(request).is_ok());
+    }
 }
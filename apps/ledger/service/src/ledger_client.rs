@@ -0,0 +1,833 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Client-side helpers for the integrators uploading blobs to be guarded by the Ledger and
+// requesting access to them. The client encrypts the blobs with the most suitable Ledger key,
+// builds the BlobHeader bound to the ciphertext and the access requests with fresh nonces and
+// idempotency tokens, and retries the requests that fail transiently.
+
+#![cfg(feature = "std")]
+
+extern crate alloc;
+
+use alloc::{format, string::String, vec, vec::Vec};
+use anyhow::anyhow;
+use cfc_crypto::{extract_key_from_cwt, CipherSuite};
+use core::time::Duration;
+use coset::{cwt, cwt::ClaimsSet, CborSerializable, CoseKey, CoseSign1};
+use std::time::SystemTime;
+
+use crate::ledger::service::{KeyState, ListKeysRequest};
+use crate::ledger::Ledger;
+use federated_compute::proto::{
+    AuthorizeAccessRequest, AuthorizeAccessResponse, BlobHeader, HpkeCipherSuite,
+    RevokeAccessRequest,
+};
+use oak_proto_rust::oak::attestation::v1::{Endorsements, Evidence};
+
+use prost::Message;
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+/// Length of the random blob ids, recipient nonces and idempotency tokens.
+const RANDOM_ID_LEN: usize = 16;
+
+/// A public key of the Ledger that blobs can be encrypted with.
+#[derive(Clone, Debug)]
+pub struct LedgerPublicKey {
+    cose_key: CoseKey,
+    cipher_suite: CipherSuite,
+    /// The time after which the key is no longer usable, None if it never expires.
+    expiration: Option<Duration>,
+}
+
+impl LedgerPublicKey {
+    /// Parses the public key CWT returned by the Ledger, see `CreateKeyResponse.public_key`.
+    /// The CWT signature isn't verified; it's the caller's responsibility to check the
+    /// Ledger attestation evidence before trusting the key.
+    pub fn from_cwt(cwt: &[u8]) -> Result<Self, micro_rpc::Status> {
+        let invalid = |err: anyhow::Error| {
+            micro_rpc::Status::new_with_message(
+                micro_rpc::StatusCode::InvalidArgument,
+                format!("public_key is invalid: {:?}", err),
+            )
+        };
+        let cose_key = extract_key_from_cwt(cwt).map_err(invalid)?;
+        let cipher_suite = CipherSuite::from_public_key(&cose_key).map_err(invalid)?;
+        let claims = CoseSign1::from_slice(cwt)
+            .and_then(|cwt| ClaimsSet::from_slice(cwt.payload.as_deref().unwrap_or_default()))
+            .map_err(|err| invalid(anyhow!("failed to decode CWT claims: {:?}", err)))?;
+        let expiration = match claims.expiration_time {
+            Some(cwt::Timestamp::WholeSeconds(seconds)) => {
+                Some(Duration::from_secs(seconds.try_into().unwrap_or(0)))
+            }
+            Some(cwt::Timestamp::FractionalSeconds(seconds)) => {
+                Some(Duration::try_from_secs_f64(seconds).unwrap_or(Duration::ZERO))
+            }
+            None => None,
+        };
+        Ok(Self {
+            cose_key,
+            cipher_suite,
+            expiration,
+        })
+    }
+
+    /// Returns the COSE "kid" property of the key.
+    pub fn key_id(&self) -> &[u8] {
+        &self.cose_key.key_id
+    }
+
+    /// Returns the time after which the key is no longer usable, None if it never expires.
+    pub fn expiration(&self) -> Option<Duration> {
+        self.expiration
+    }
+
+    /// Returns whether blobs expiring at `blob_expiration` can be encrypted with the key at
+    /// `now`: the key must not have expired, and must remain usable while the blob can be
+    /// accessed.
+    fn is_usable(&self, now: Duration, blob_expiration: Option<Duration>) -> bool {
+        self.expiration.map_or(true, |expiration| {
+            expiration > now && blob_expiration.map_or(true, |blob| blob <= expiration)
+        })
+    }
+}
+
+/// Properties of a blob that are recorded in its BlobHeader.
+#[derive(Clone, Debug, Default)]
+pub struct BlobOptions {
+    /// The id of the access policy node of the blob, 0 for non-derived blobs.
+    pub access_policy_node_id: u32,
+    /// The time after which the blob may no longer be accessed, None if it never expires.
+    pub expiration: Option<Duration>,
+}
+
+/// A blob encrypted for upload, along with what's needed to authorize access to it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EncryptedBlob {
+    pub blob_id: Vec<u8>,
+    /// The id of the Ledger key the symmetric key is wrapped with.
+    pub key_id: Vec<u8>,
+    /// The serialized BlobHeader, which is the associated data of the ciphertext.
+    pub header: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+    pub encapsulated_key: Vec<u8>,
+    pub encrypted_symmetric_key: Vec<u8>,
+}
+
+/// The application requesting access to a blob.
+#[derive(Clone, Debug, Default)]
+pub struct Recipient {
+    /// The CWT of the public key the symmetric key is re-wrapped for, see
+    /// `AuthorizeAccessRequest.recipient_public_key`.
+    pub public_key: Vec<u8>,
+    pub attestation_evidence: Option<Evidence>,
+    pub attestation_endorsements: Option<Endorsements>,
+    pub tag: String,
+}
+
+/// An access authorized by the Ledger.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Authorization {
+    pub response: AuthorizeAccessResponse,
+    /// The nonce sent with the request, which the recipient needs to unwrap the symmetric key.
+    pub recipient_nonce: Vec<u8>,
+}
+
+impl Authorization {
+    /// Returns the associated data the symmetric key in the response is wrapped with.
+    pub fn symmetric_key_associated_data(&self) -> Vec<u8> {
+        [
+            self.response.reencryption_public_key.as_slice(),
+            self.recipient_nonce.as_slice(),
+        ]
+        .concat()
+    }
+}
+
+/// Limits on the retries of the requests that fail transiently. The backoff between the
+/// attempts doubles after every retry, up to `max_backoff`.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the first one.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Returns whether a request that failed with the code may succeed if retried.
+fn is_retryable(code: micro_rpc::StatusCode) -> bool {
+    matches!(
+        code,
+        micro_rpc::StatusCode::Unavailable
+            | micro_rpc::StatusCode::Aborted
+            | micro_rpc::StatusCode::DeadlineExceeded
+    )
+}
+
+fn system_time() -> Duration {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+fn format_timestamp(time: Duration) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: time.as_secs().try_into().unwrap_or(i64::MAX),
+        nanos: time.subsec_nanos().try_into().unwrap(),
+    }
+}
+
+fn random_id() -> Vec<u8> {
+    let mut id = vec![0; RANDOM_ID_LEN];
+    OsRng.fill_bytes(&mut id);
+    id
+}
+
+/// A client of the Ledger, which uploaders use to encrypt blobs with the Ledger keys and
+/// recipients use to request access to them.
+pub struct LedgerClient<L: Ledger> {
+    ledger: L,
+    namespace: String,
+    keys: Vec<LedgerPublicKey>,
+    retry_policy: RetryPolicy,
+    clock: fn() -> Duration,
+}
+
+impl<L: Ledger> LedgerClient<L> {
+    pub fn new(ledger: L) -> Self {
+        Self {
+            ledger,
+            namespace: String::new(),
+            keys: Vec::new(),
+            retry_policy: RetryPolicy::default(),
+            clock: system_time,
+        }
+    }
+
+    /// Sets the namespace of the keys used by the client, see `CreateKeyRequest.namespace`.
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets the source of the current time, the system time by default.
+    pub fn with_clock(mut self, clock: fn() -> Duration) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn ledger_mut(&mut self) -> &mut L {
+        &mut self.ledger
+    }
+
+    /// Adds a key blobs can be encrypted with, replacing any key with the same id.
+    pub fn add_public_key(&mut self, cwt: &[u8]) -> Result<(), micro_rpc::Status> {
+        let key = LedgerPublicKey::from_cwt(cwt)?;
+        self.keys.retain(|k| k.key_id() != key.key_id());
+        self.keys.push(key);
+        Ok(())
+    }
+
+    /// Replaces the keys blobs can be encrypted with by the active keys of the namespace.
+    pub fn refresh_keys(&mut self) -> Result<(), micro_rpc::Status> {
+        let namespace = self.namespace.clone();
+        let response = self.call_with_retries(|ledger, now| {
+            ledger.list_keys(ListKeysRequest {
+                now: Some(now),
+                namespace: namespace.clone(),
+            })
+        })?;
+        self.keys = response
+            .keys
+            .iter()
+            .filter(|key| key.state() == KeyState::Active)
+            .map(|key| LedgerPublicKey::from_cwt(&key.public_key))
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    /// Selects the key to encrypt a blob expiring at `blob_expiration` with: among the keys
+    /// that remain usable until the blob expires, the one expiring last.
+    pub fn select_key(
+        &self,
+        blob_expiration: Option<Duration>,
+    ) -> Result<&LedgerPublicKey, micro_rpc::Status> {
+        let now = (self.clock)();
+        self.keys
+            .iter()
+            .filter(|key| key.is_usable(now, blob_expiration))
+            .max_by_key(|key| key.expiration.unwrap_or(Duration::MAX))
+            .ok_or_else(|| {
+                micro_rpc::Status::new_with_message(
+                    micro_rpc::StatusCode::FailedPrecondition,
+                    "no public key remains usable until the blob expires",
+                )
+            })
+    }
+
+    /// Encrypts the blob subject to the serialized access policy with the key selected by
+    /// `select_key`, binding the BlobHeader to the ciphertext.
+    pub fn encrypt_blob(
+        &self,
+        plaintext: &[u8],
+        access_policy: &[u8],
+        options: &BlobOptions,
+    ) -> Result<EncryptedBlob, micro_rpc::Status> {
+        let key = self.select_key(options.expiration)?;
+        let blob_id = random_id();
+        let header = BlobHeader {
+            blob_id: blob_id.clone(),
+            key_id: key.key_id().to_vec(),
+            access_policy_sha256: Sha256::digest(access_policy).to_vec(),
+            access_policy_node_id: options.access_policy_node_id,
+            expiration: options.expiration.map(format_timestamp),
+            cipher_suite: match key.cipher_suite {
+                CipherSuite::X25519HkdfSha256Aes128Gcm => {
+                    HpkeCipherSuite::X25519HkdfSha256Aes128gcm
+                }
+                CipherSuite::X25519HkdfSha256ChaCha20Poly1305 => {
+                    HpkeCipherSuite::X25519HkdfSha256Chacha20poly1305
+                }
+            }
+            .into(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let (ciphertext, encapsulated_key, encrypted_symmetric_key) =
+            cfc_crypto::encrypt_message(plaintext, &key.cose_key, &header).map_err(|err| {
+                micro_rpc::Status::new_with_message(
+                    micro_rpc::StatusCode::Internal,
+                    format!("failed to encrypt blob: {:?}", err),
+                )
+            })?;
+        Ok(EncryptedBlob {
+            blob_id,
+            key_id: key.key_id().to_vec(),
+            header,
+            ciphertext,
+            encapsulated_key,
+            encrypted_symmetric_key,
+        })
+    }
+
+    /// Builds the request for access to the blob with a fresh recipient nonce and idempotency
+    /// token. The current time is set when the request is sent.
+    pub fn authorize_access_request(
+        &self,
+        blob: &EncryptedBlob,
+        access_policy: &[u8],
+        recipient: &Recipient,
+    ) -> AuthorizeAccessRequest {
+        AuthorizeAccessRequest {
+            access_policy: access_policy.to_vec(),
+            blob_header: blob.header.clone(),
+            encapsulated_key: blob.encapsulated_key.clone(),
+            encrypted_symmetric_key: blob.encrypted_symmetric_key.clone(),
+            recipient_public_key: recipient.public_key.clone(),
+            recipient_attestation_evidence: recipient.attestation_evidence.clone(),
+            recipient_attestation_endorsements: recipient.attestation_endorsements.clone(),
+            recipient_tag: recipient.tag.clone(),
+            recipient_nonce: random_id(),
+            idempotency_token: random_id(),
+            namespace: self.namespace.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Requests access to the blob. The retries reuse the idempotency token, so an access
+    /// that has been authorized before the response was lost doesn't consume the budget again.
+    pub fn authorize_access(
+        &mut self,
+        blob: &EncryptedBlob,
+        access_policy: &[u8],
+        recipient: &Recipient,
+    ) -> Result<Authorization, micro_rpc::Status> {
+        let request = self.authorize_access_request(blob, access_policy, recipient);
+        let response = self.call_with_retries(|ledger, now| {
+            ledger.authorize_access(AuthorizeAccessRequest {
+                now: Some(now),
+                ..request.clone()
+            })
+        })?;
+        Ok(Authorization {
+            response,
+            recipient_nonce: request.recipient_nonce,
+        })
+    }
+
+    /// Builds the request revoking access to the blob, either through the transform with the
+    /// given index only or through all transforms.
+    pub fn revoke_access_request(
+        &self,
+        blob: &EncryptedBlob,
+        transform_index: Option<u32>,
+    ) -> RevokeAccessRequest {
+        RevokeAccessRequest {
+            key_id: blob.key_id.clone(),
+            blob_id: blob.blob_id.clone(),
+            transform_index,
+            namespace: self.namespace.clone(),
+        }
+    }
+
+    /// Revokes access to the blob, see `revoke_access_request`. Revocations are idempotent,
+    /// so they are retried like the authorizations.
+    pub fn revoke_access(
+        &mut self,
+        blob: &EncryptedBlob,
+        transform_index: Option<u32>,
+    ) -> Result<(), micro_rpc::Status> {
+        let request = self.revoke_access_request(blob, transform_index);
+        self.call_with_retries(|ledger, _| ledger.revoke_access(request.clone()))?;
+        Ok(())
+    }
+
+    /// Sends a request built for the current time, retrying it as long as it fails
+    /// transiently and the retry policy allows.
+    fn call_with_retries<T>(
+        &mut self,
+        mut call: impl FnMut(&mut L, prost_types::Timestamp) -> Result<T, micro_rpc::Status>,
+    ) -> Result<T, micro_rpc::Status> {
+        let mut backoff = self.retry_policy.initial_backoff;
+        let mut attempts = 1;
+        loop {
+            match call(&mut self.ledger, format_timestamp((self.clock)())) {
+                Err(err) if is_retryable(err.code) && attempts < self.retry_policy.max_attempts => {
+                    std::thread::sleep(backoff);
+                    backoff = backoff.saturating_mul(2).min(self.retry_policy.max_backoff);
+                    attempts += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::assert_err;
+    use crate::ledger::{service::*, LedgerService};
+    use alloc::{borrow::ToOwned, boxed::Box};
+    use cfc_crypto::PUBLIC_KEY_CLAIM;
+    use coset::{cbor::Value, cwt::ClaimsSetBuilder, CoseSign1Builder};
+    use federated_compute::proto::{
+        data_access_policy::Transform, ApplicationMatcher, CreateKeyRequest, CreateKeyResponse,
+        DataAccessPolicy, DeleteKeyRequest, DeleteKeyResponse, RevokeAccessResponse,
+    };
+    use oak_restricted_kernel_sdk::testing::{MockEvidenceProvider, MockSigner};
+
+    fn now() -> Duration {
+        Duration::from_secs(100)
+    }
+
+    /// A Ledger whose AuthorizeAccess, RevokeAccess and ListKeys fail with Unavailable as many
+    /// times as set by `failures`, recording the authorization requests.
+    struct FlakyLedger {
+        ledger: LedgerService,
+        failures: u32,
+        authorize_access_requests: Vec<AuthorizeAccessRequest>,
+    }
+
+    impl FlakyLedger {
+        fn new() -> Self {
+            Self {
+                ledger: LedgerService::create(
+                    Box::new(MockEvidenceProvider::create().unwrap()),
+                    Box::new(MockSigner::create().unwrap()),
+                )
+                .unwrap(),
+                failures: 0,
+                authorize_access_requests: Vec::new(),
+            }
+        }
+
+        fn maybe_fail(&mut self) -> Result<(), micro_rpc::Status> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(micro_rpc::Status::new_with_message(
+                    micro_rpc::StatusCode::Unavailable,
+                    "ledger unavailable",
+                ));
+            }
+            Ok(())
+        }
+    }
+
+    impl Ledger for FlakyLedger {
+        fn create_key(
+            &mut self,
+            request: CreateKeyRequest,
+        ) -> Result<CreateKeyResponse, micro_rpc::Status> {
+            self.ledger.create_key(request)
+        }
+
+        fn delete_key(
+            &mut self,
+            request: DeleteKeyRequest,
+        ) -> Result<DeleteKeyResponse, micro_rpc::Status> {
+            self.ledger.delete_key(request)
+        }
+
+        fn authorize_access(
+            &mut self,
+            request: AuthorizeAccessRequest,
+        ) -> Result<AuthorizeAccessResponse, micro_rpc::Status> {
+            self.authorize_access_requests.push(request.clone());
+            self.maybe_fail()?;
+            self.ledger.authorize_access(request)
+        }
+
+        fn revoke_access(
+            &mut self,
+            request: RevokeAccessRequest,
+        ) -> Result<RevokeAccessResponse, micro_rpc::Status> {
+            self.maybe_fail()?;
+            self.ledger.revoke_access(request)
+        }
+
+        fn authorize_access_batch(
+            &mut self,
+            request: AuthorizeAccessBatchRequest,
+        ) -> Result<AuthorizeAccessBatchResponse, micro_rpc::Status> {
+            self.ledger.authorize_access_batch(request)
+        }
+
+        fn list_keys(
+            &mut self,
+            request: ListKeysRequest,
+        ) -> Result<ListKeysResponse, micro_rpc::Status> {
+            self.maybe_fail()?;
+            self.ledger.list_keys(request)
+        }
+
+        fn get_key_details(
+            &mut self,
+            request: GetKeyDetailsRequest,
+        ) -> Result<GetKeyDetailsResponse, micro_rpc::Status> {
+            self.ledger.get_key_details(request)
+        }
+
+        fn query_audit_log(
+            &mut self,
+            request: QueryAuditLogRequest,
+        ) -> Result<QueryAuditLogResponse, micro_rpc::Status> {
+            self.ledger.query_audit_log(request)
+        }
+
+        fn check_access(
+            &mut self,
+            request: CheckAccessRequest,
+        ) -> Result<CheckAccessResponse, micro_rpc::Status> {
+            self.ledger.check_access(request)
+        }
+
+        fn get_key_stats(
+            &mut self,
+            request: GetKeyStatsRequest,
+        ) -> Result<GetKeyStatsResponse, micro_rpc::Status> {
+            self.ledger.get_key_stats(request)
+        }
+
+        fn rewrap_keys(
+            &mut self,
+            request: RewrapKeysRequest,
+        ) -> Result<RewrapKeysResponse, micro_rpc::Status> {
+            self.ledger.rewrap_keys(request)
+        }
+    }
+
+    /// Creates a client with a key expiring at the given time.
+    fn create_client(key_expiration: u64) -> LedgerClient<FlakyLedger> {
+        let mut client = LedgerClient::new(FlakyLedger::new())
+            .with_clock(now)
+            .with_retry_policy(RetryPolicy {
+                max_attempts: 3,
+                initial_backoff: Duration::ZERO,
+                max_backoff: Duration::ZERO,
+            });
+        create_key(&mut client, key_expiration);
+        assert_eq!(client.refresh_keys(), Ok(()));
+        client
+    }
+
+    fn create_key(client: &mut LedgerClient<FlakyLedger>, expiration: u64) -> Vec<u8> {
+        client
+            .ledger_mut()
+            .create_key(CreateKeyRequest {
+                now: Some(format_timestamp(now())),
+                ttl: Some(prost_types::Duration {
+                    seconds: (expiration - now().as_secs()).try_into().unwrap(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .unwrap()
+            .public_key
+    }
+
+    fn access_policy() -> Vec<u8> {
+        DataAccessPolicy {
+            transforms: vec![Transform {
+                application: Some(ApplicationMatcher {
+                    tag: Some("tag".to_owned()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+        .encode_to_vec()
+    }
+
+    /// Creates a recipient along with its private key.
+    fn create_recipient() -> (cfc_crypto::PrivateKey, Recipient) {
+        let (private_key, public_key) = cfc_crypto::gen_keypair(b"key-id");
+        let claims = ClaimsSetBuilder::new()
+            .private_claim(PUBLIC_KEY_CLAIM, Value::from(public_key.to_vec().unwrap()))
+            .build();
+        let cwt = CoseSign1Builder::new()
+            .payload(claims.to_vec().unwrap())
+            .build()
+            .to_vec()
+            .unwrap();
+        (
+            private_key,
+            Recipient {
+                public_key: cwt,
+                tag: "tag".into(),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_encrypt_and_authorize() {
+        let mut client = create_client(/* key_expiration= */ 1000);
+        let access_policy = access_policy();
+        let blob = client
+            .encrypt_blob(
+                b"plaintext",
+                &access_policy,
+                &BlobOptions {
+                    expiration: Some(Duration::from_secs(500)),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let header = BlobHeader::decode(blob.header.as_slice()).unwrap();
+        assert_eq!(header.blob_id, blob.blob_id);
+        assert_eq!(header.key_id, blob.key_id);
+        assert_eq!(
+            header.access_policy_sha256,
+            Sha256::digest(&access_policy).to_vec()
+        );
+        assert_eq!(
+            header.expiration,
+            Some(format_timestamp(Duration::from_secs(500)))
+        );
+        assert_eq!(
+            header.cipher_suite(),
+            HpkeCipherSuite::X25519HkdfSha256Aes128gcm
+        );
+
+        let (private_key, recipient) = create_recipient();
+        let authorization = client
+            .authorize_access(&blob, &access_policy, &recipient)
+            .unwrap();
+        assert_eq!(
+            cfc_crypto::decrypt_message(
+                &blob.ciphertext,
+                &blob.header,
+                &authorization.response.encrypted_symmetric_key,
+                &authorization.symmetric_key_associated_data(),
+                &authorization.response.encapsulated_key,
+                &private_key
+            )
+            .unwrap(),
+            b"plaintext"
+        );
+
+        // Blobs get distinct ids, and requests distinct nonces and idempotency tokens.
+        let other_blob = client
+            .encrypt_blob(b"plaintext", &access_policy, &BlobOptions::default())
+            .unwrap();
+        assert_ne!(other_blob.blob_id, blob.blob_id);
+        let request = client.authorize_access_request(&blob, &access_policy, &recipient);
+        let other_request = client.authorize_access_request(&blob, &access_policy, &recipient);
+        assert_ne!(request.recipient_nonce, other_request.recipient_nonce);
+        assert_ne!(request.idempotency_token, other_request.idempotency_token);
+    }
+
+    #[test]
+    fn test_select_key() {
+        let mut client = create_client(/* key_expiration= */ 1000);
+        let long_lived_key = create_key(&mut client, 2000);
+        let long_lived_key_id = extract_key_from_cwt(&long_lived_key).unwrap().key_id;
+
+        // Keys added with `add_public_key` are selected until the keys are refreshed.
+        assert_eq!(client.add_public_key(&long_lived_key), Ok(()));
+        assert_eq!(client.select_key(None).unwrap().key_id(), long_lived_key_id);
+        assert_eq!(
+            client
+                .select_key(Some(Duration::from_secs(500)))
+                .unwrap()
+                .key_id(),
+            long_lived_key_id
+        );
+        assert_eq!(
+            client.select_key(None).unwrap().expiration(),
+            Some(Duration::from_secs(2000))
+        );
+
+        // No key remains usable until the blob expires.
+        assert_err!(
+            client.select_key(Some(Duration::from_secs(3000))),
+            micro_rpc::StatusCode::FailedPrecondition,
+            "no public key remains usable"
+        );
+        assert_err!(
+            client.encrypt_blob(
+                b"plaintext",
+                &access_policy(),
+                &BlobOptions {
+                    expiration: Some(Duration::from_secs(3000)),
+                    ..Default::default()
+                },
+            ),
+            micro_rpc::StatusCode::FailedPrecondition,
+            "no public key remains usable"
+        );
+
+        // Deleted keys are dropped when the keys are refreshed.
+        client
+            .ledger_mut()
+            .delete_key(DeleteKeyRequest {
+                public_key: long_lived_key,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(client.refresh_keys(), Ok(()));
+        assert_eq!(
+            client.select_key(None).unwrap().expiration(),
+            Some(Duration::from_secs(1000))
+        );
+    }
+
+    #[test]
+    fn test_add_public_key_invalid() {
+        let mut client = LedgerClient::new(FlakyLedger::new());
+        assert_err!(
+            client.add_public_key(b"invalid"),
+            micro_rpc::StatusCode::InvalidArgument,
+            "public_key is invalid"
+        );
+        assert_err!(
+            client.select_key(None),
+            micro_rpc::StatusCode::FailedPrecondition,
+            "no public key remains usable"
+        );
+    }
+
+    #[test]
+    fn test_authorize_access_retries() {
+        let mut client = create_client(/* key_expiration= */ 1000);
+        let access_policy = access_policy();
+        let blob = client
+            .encrypt_blob(b"plaintext", &access_policy, &BlobOptions::default())
+            .unwrap();
+        let (_, recipient) = create_recipient();
+
+        // The request is retried with the same nonce and idempotency token.
+        client.ledger_mut().failures = 2;
+        let authorization = client
+            .authorize_access(&blob, &access_policy, &recipient)
+            .unwrap();
+        let requests = &client.ledger_mut().authorize_access_requests;
+        assert_eq!(requests.len(), 3);
+        for request in requests {
+            assert_eq!(request.recipient_nonce, authorization.recipient_nonce);
+            assert_eq!(request.idempotency_token, requests[0].idempotency_token);
+            assert_eq!(request.now, Some(format_timestamp(now())));
+        }
+
+        // Attempts stop once the retry policy is exhausted.
+        client.ledger_mut().authorize_access_requests.clear();
+        client.ledger_mut().failures = 3;
+        assert_err!(
+            client.authorize_access(&blob, &access_policy, &recipient),
+            micro_rpc::StatusCode::Unavailable,
+            "ledger unavailable"
+        );
+        assert_eq!(client.ledger_mut().authorize_access_requests.len(), 3);
+
+        // Errors that aren't transient aren't retried.
+        client.ledger_mut().authorize_access_requests.clear();
+        assert_err!(
+            client.authorize_access(
+                &blob,
+                &DataAccessPolicy::default().encode_to_vec(),
+                &recipient
+            ),
+            micro_rpc::StatusCode::InvalidArgument,
+            "access policy does not match blob header"
+        );
+        assert_eq!(client.ledger_mut().authorize_access_requests.len(), 1);
+    }
+
+    #[test]
+    fn test_revoke_access() {
+        let mut client = create_client(/* key_expiration= */ 1000);
+        let access_policy = access_policy();
+        let blob = client
+            .encrypt_blob(b"plaintext", &access_policy, &BlobOptions::default())
+            .unwrap();
+        let (_, recipient) = create_recipient();
+
+        client.ledger_mut().failures = 1;
+        assert_eq!(client.revoke_access(&blob, None), Ok(()));
+        assert_err!(
+            client.authorize_access(&blob, &access_policy, &recipient),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "data access budget consumed"
+        );
+        assert_eq!(
+            client.revoke_access_request(&blob, Some(1)),
+            RevokeAccessRequest {
+                key_id: blob.key_id.clone(),
+                blob_id: blob.blob_id.clone(),
+                transform_index: Some(1),
+                namespace: String::new(),
+            }
+        );
+    }
+}
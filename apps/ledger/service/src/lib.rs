@@ -24,7 +24,15 @@ extern crate tcp_runtime;
 
 pub mod actor;
 pub mod attestation;
+pub mod error;
 pub mod ledger;
+pub mod ledger_client;
 pub mod test_util;
 
+mod audit;
 mod budget;
+#[cfg(test)]
+mod budget_properties;
+mod idempotency;
+mod key_wrapping;
+mod rate_limit;
@@ -0,0 +1,499 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate alloc;
+
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use core::time::Duration;
+
+use crate::attestation::Application;
+use crate::ledger::service::{AttestedRecipient, RateLimitConfig, RateLimitCounterSnapshot};
+
+/// Parsed rate limit.
+struct Limit {
+    recipient_binary_sha256: Vec<u8>,
+    window: Duration,
+    max_accesses: u64,
+    namespace: Option<String>,
+}
+
+impl Limit {
+    /// Returns whether the limit applies to the recipient within the namespace.
+    fn applies_to(&self, recipient: &AttestedRecipient, namespace: &str) -> bool {
+        self.namespace
+            .as_ref()
+            .map_or(true, |limit_namespace| limit_namespace == namespace)
+            && (self.recipient_binary_sha256.is_empty()
+                || recipient.binary_sha256 == self.recipient_binary_sha256)
+    }
+}
+
+/// Number of accesses counted within the window that started at `window_start`.
+struct WindowCounter {
+    window_start: Duration,
+    count: u64,
+}
+
+/// Counters are keyed by the limit index, the namespace, the recipient binary digest and the
+/// recipient signing key.
+type CounterKey = (usize, String, Vec<u8>, Vec<u8>);
+
+/// A RateLimiter enforces the per-recipient rate limits of the access authorizations using
+/// windowed counters. Each recipient, identified by the binary digest and signing key
+/// established by verifying its attestation, is counted separately in each namespace against
+/// every limit that applies to it. Recipients whose identity hasn't been established share
+/// the same counters. A window starts with the first access counted after the previous window
+/// has ended.
+///
+/// The accesses are counted when the authorization events are applied, so the counters are
+/// replicated along with the rest of the ledger state, and checked before the events are
+/// produced to reject the requests early.
+#[derive(Default)]
+pub struct RateLimiter {
+    limits: Vec<Limit>,
+    counters: BTreeMap<CounterKey, WindowCounter>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the identity of the recipient that the accesses are counted against. The tag
+    /// is chosen by the recipient, so it's not part of the identity.
+    pub fn attested_recipient(recipient: &Application) -> AttestedRecipient {
+        AttestedRecipient {
+            binary_sha256: recipient.binary_sha256.clone().unwrap_or_default(),
+            signing_public_key: recipient.signing_public_key.clone().unwrap_or_default(),
+        }
+    }
+
+    /// Replaces the limits, discarding the accesses counted so far.
+    pub fn set_config(&mut self, config: RateLimitConfig) -> Result<(), micro_rpc::Status> {
+        let mut limits = Vec::with_capacity(config.limits.len());
+        for limit in config.limits {
+            let window = limit
+                .window
+                .as_ref()
+                .and_then(|window| {
+                    Some(Duration::new(
+                        window.seconds.try_into().ok()?,
+                        window.nanos.try_into().ok()?,
+                    ))
+                })
+                .filter(|window| !window.is_zero())
+                .ok_or_else(|| {
+                    micro_rpc::Status::new_with_message(
+                        micro_rpc::StatusCode::InvalidArgument,
+                        format!("rate limit `window` must be positive: {:?}", limit.window),
+                    )
+                })?;
+            limits.push(Limit {
+                recipient_binary_sha256: limit.recipient_binary_sha256,
+                window,
+                max_accesses: limit.max_accesses,
+                namespace: limit.namespace,
+            });
+        }
+        self.limits = limits;
+        self.counters.clear();
+        Ok(())
+    }
+
+    fn counter_key(i: usize, recipient: &AttestedRecipient, namespace: &str) -> CounterKey {
+        (
+            i,
+            namespace.into(),
+            recipient.binary_sha256.clone(),
+            recipient.signing_public_key.clone(),
+        )
+    }
+
+    /// Checks that `count` more accesses of the recipient within the namespace at the given
+    /// time wouldn't exceed any of the limits that apply to it, without counting them.
+    pub fn check(
+        &self,
+        recipient: &AttestedRecipient,
+        namespace: &str,
+        count: u64,
+        now: Duration,
+    ) -> Result<(), micro_rpc::Status> {
+        for (i, limit) in self.limits.iter().enumerate() {
+            if !limit.applies_to(recipient, namespace) {
+                continue;
+            }
+            // Counters of the windows that have ended no longer count.
+            let counted = self
+                .counters
+                .get(&Self::counter_key(i, recipient, namespace))
+                .filter(|counter| counter.window_start.saturating_add(limit.window) > now)
+                .map_or(0, |counter| counter.count);
+            if counted.saturating_add(count) > limit.max_accesses {
+                return Err(micro_rpc::Status::new_with_message(
+                    micro_rpc::StatusCode::ResourceExhausted,
+                    "rate limit exceeded for the recipient",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts `count` accesses of the recipient within the namespace at the given time. Fails
+    /// without counting anything if any of the limits that apply to the recipient would be
+    /// exceeded.
+    pub fn check_and_count(
+        &mut self,
+        recipient: &AttestedRecipient,
+        namespace: &str,
+        count: u64,
+        now: Duration,
+    ) -> Result<(), micro_rpc::Status> {
+        // Discard the counters of the windows that have ended.
+        let limits = &self.limits;
+        self.counters.retain(|(i, _, _, _), counter| {
+            counter.window_start.saturating_add(limits[*i].window) > now
+        });
+
+        self.check(recipient, namespace, count, now)?;
+        for (i, limit) in self.limits.iter().enumerate() {
+            if !limit.applies_to(recipient, namespace) {
+                continue;
+            }
+            self.counters
+                .entry(Self::counter_key(i, recipient, namespace))
+                .or_insert(WindowCounter {
+                    window_start: now,
+                    count: 0,
+                })
+                .count += count;
+        }
+        Ok(())
+    }
+
+    pub fn save_snapshot(&self) -> Vec<RateLimitCounterSnapshot> {
+        self.counters
+            .iter()
+            .map(
+                |((i, namespace, binary_sha256, signing_public_key), counter)| {
+                    RateLimitCounterSnapshot {
+                        limit_index: (*i).try_into().unwrap(),
+                        namespace: namespace.clone(),
+                        recipient: Some(AttestedRecipient {
+                            binary_sha256: binary_sha256.clone(),
+                            signing_public_key: signing_public_key.clone(),
+                        }),
+                        window_start: Some(prost_types::Timestamp {
+                            seconds: counter
+                                .window_start
+                                .as_secs()
+                                .try_into()
+                                .unwrap_or(i64::MAX),
+                            nanos: counter.window_start.subsec_nanos().try_into().unwrap(),
+                        }),
+                        count: counter.count,
+                    }
+                },
+            )
+            .collect()
+    }
+
+    /// Replaces the counters with the ones in the snapshot, keeping the configured limits. The
+    /// counters of the limits that are no longer configured are dropped.
+    pub fn load_snapshot(
+        &mut self,
+        snapshot: Vec<RateLimitCounterSnapshot>,
+    ) -> Result<(), micro_rpc::Status> {
+        let mut counters = BTreeMap::new();
+        for counter_snapshot in snapshot {
+            let window_start = counter_snapshot
+                .window_start
+                .and_then(|ts| {
+                    Some(Duration::new(
+                        ts.seconds.try_into().ok()?,
+                        ts.nanos.try_into().ok()?,
+                    ))
+                })
+                .ok_or_else(|| {
+                    micro_rpc::Status::new_with_message(
+                        micro_rpc::StatusCode::InvalidArgument,
+                        "Invalid `rate_limit_counters` entry in the snapshot",
+                    )
+                })?;
+            let i = usize::try_from(counter_snapshot.limit_index).unwrap();
+            if i >= self.limits.len() {
+                continue;
+            }
+            counters.insert(
+                Self::counter_key(
+                    i,
+                    &counter_snapshot.recipient.unwrap_or_default(),
+                    &counter_snapshot.namespace,
+                ),
+                WindowCounter {
+                    window_start,
+                    count: counter_snapshot.count,
+                },
+            );
+        }
+        self.counters = counters;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::assert_err;
+    use crate::ledger::service::rate_limit_config;
+    use alloc::{borrow::ToOwned, vec};
+
+    fn limit(
+        recipient_binary_sha256: &[u8],
+        window_seconds: i64,
+        max_accesses: u64,
+    ) -> rate_limit_config::Limit {
+        rate_limit_config::Limit {
+            recipient_binary_sha256: recipient_binary_sha256.to_vec(),
+            window: Some(prost_types::Duration {
+                seconds: window_seconds,
+                ..Default::default()
+            }),
+            max_accesses,
+            namespace: None,
+        }
+    }
+
+    fn recipient(binary_sha256: &[u8]) -> AttestedRecipient {
+        AttestedRecipient {
+            binary_sha256: binary_sha256.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let mut rate_limiter = RateLimiter::new();
+        for _ in 0..100 {
+            assert_eq!(
+                rate_limiter.check_and_count(&AttestedRecipient::default(), "", 10, Duration::ZERO),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn test_attested_recipient() {
+        // The tag isn't part of the identity of the recipient.
+        assert_eq!(
+            RateLimiter::attested_recipient(&Application {
+                tag: "foo",
+                binary_sha256: Some(b"digest".to_vec()),
+                signing_public_key: Some(b"key".to_vec()),
+                ..Default::default()
+            }),
+            AttestedRecipient {
+                binary_sha256: b"digest".to_vec(),
+                signing_public_key: b"key".to_vec(),
+            }
+        );
+        assert_eq!(
+            RateLimiter::attested_recipient(&Application {
+                tag: "foo",
+                ..Default::default()
+            }),
+            AttestedRecipient::default()
+        );
+    }
+
+    #[test]
+    fn test_window() {
+        let mut rate_limiter = RateLimiter::new();
+        assert_eq!(
+            rate_limiter.set_config(RateLimitConfig {
+                limits: vec![limit(b"", 10, 3)],
+            }),
+            Ok(())
+        );
+        let app = recipient(b"foo");
+
+        assert_eq!(
+            rate_limiter.check_and_count(&app, "", 2, Duration::from_secs(100)),
+            Ok(())
+        );
+        // The whole batch is rejected if it doesn't fit into the limit.
+        assert_err!(
+            rate_limiter.check_and_count(&app, "", 2, Duration::from_secs(105)),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "rate limit exceeded"
+        );
+        assert_eq!(
+            rate_limiter.check_and_count(&app, "", 1, Duration::from_secs(109)),
+            Ok(())
+        );
+        assert_err!(
+            rate_limiter.check_and_count(&app, "", 1, Duration::from_secs(109)),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "rate limit exceeded"
+        );
+        // Other recipients are counted separately.
+        assert_eq!(
+            rate_limiter.check_and_count(&recipient(b"bar"), "", 3, Duration::from_secs(109)),
+            Ok(())
+        );
+        // The next window starts once the previous one has ended.
+        assert_eq!(
+            rate_limiter.check(&app, "", 3, Duration::from_secs(110)),
+            Ok(())
+        );
+        assert_eq!(
+            rate_limiter.check_and_count(&app, "", 3, Duration::from_secs(110)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_check_does_not_count() {
+        let mut rate_limiter = RateLimiter::new();
+        assert_eq!(
+            rate_limiter.set_config(RateLimitConfig {
+                limits: vec![limit(b"", 10, 1)],
+            }),
+            Ok(())
+        );
+        let app = recipient(b"foo");
+
+        for _ in 0..2 {
+            assert_eq!(rate_limiter.check(&app, "", 1, Duration::ZERO), Ok(()));
+        }
+        assert_eq!(
+            rate_limiter.check_and_count(&app, "", 1, Duration::ZERO),
+            Ok(())
+        );
+        assert_err!(
+            rate_limiter.check(&app, "", 1, Duration::ZERO),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "rate limit exceeded"
+        );
+    }
+
+    #[test]
+    fn test_applicable_limits() {
+        let mut rate_limiter = RateLimiter::new();
+        assert_eq!(
+            rate_limiter.set_config(RateLimitConfig {
+                limits: vec![limit(b"digest", 10, 1)],
+            }),
+            Ok(())
+        );
+
+        // The binary limit applies regardless of the signing key.
+        let app = AttestedRecipient {
+            binary_sha256: b"digest".to_vec(),
+            signing_public_key: b"key".to_vec(),
+        };
+        assert_eq!(
+            rate_limiter.check_and_count(&app, "", 1, Duration::ZERO),
+            Ok(())
+        );
+        assert!(rate_limiter
+            .check_and_count(&app, "", 1, Duration::ZERO)
+            .is_err());
+
+        // No limit applies.
+        assert_eq!(
+            rate_limiter.check_and_count(&recipient(b"other"), "", 100, Duration::ZERO),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let mut rate_limiter = RateLimiter::new();
+        assert_eq!(
+            rate_limiter.set_config(RateLimitConfig {
+                limits: vec![
+                    limit(b"", 10, 1),
+                    rate_limit_config::Limit {
+                        namespace: Some("tenant".to_owned()),
+                        ..limit(b"", 10, 2)
+                    },
+                ],
+            }),
+            Ok(())
+        );
+        let app = AttestedRecipient::default();
+
+        // The accesses are counted separately in each namespace.
+        assert_eq!(
+            rate_limiter.check_and_count(&app, "", 1, Duration::ZERO),
+            Ok(())
+        );
+        assert_eq!(
+            rate_limiter.check_and_count(&app, "other", 1, Duration::ZERO),
+            Ok(())
+        );
+        assert!(rate_limiter
+            .check_and_count(&app, "other", 1, Duration::ZERO)
+            .is_err());
+        // Both limits apply within the namespace of the second limit.
+        assert!(rate_limiter
+            .check_and_count(&app, "tenant", 2, Duration::ZERO)
+            .is_err());
+        assert_eq!(
+            rate_limiter.check_and_count(&app, "tenant", 1, Duration::ZERO),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let config = RateLimitConfig {
+            limits: vec![limit(b"", 10, 2)],
+        };
+        let mut rate_limiter = RateLimiter::new();
+        assert_eq!(rate_limiter.set_config(config.clone()), Ok(()));
+        let app = recipient(b"foo");
+        assert_eq!(
+            rate_limiter.check_and_count(&app, "", 2, Duration::from_secs(100)),
+            Ok(())
+        );
+        let snapshot = rate_limiter.save_snapshot();
+
+        // The counters carry over to the replica that loads the snapshot.
+        let mut loaded = RateLimiter::new();
+        assert_eq!(loaded.set_config(config), Ok(()));
+        assert_eq!(loaded.load_snapshot(snapshot.clone()), Ok(()));
+        assert_eq!(loaded.save_snapshot(), snapshot);
+        assert!(loaded.check(&app, "", 1, Duration::from_secs(105)).is_err());
+        assert_eq!(loaded.check(&app, "", 1, Duration::from_secs(110)), Ok(()));
+
+        // Counters of the limits that aren't configured are dropped.
+        let mut unlimited = RateLimiter::new();
+        assert_eq!(unlimited.load_snapshot(snapshot), Ok(()));
+        assert_eq!(unlimited.save_snapshot(), vec![]);
+    }
+
+    #[test]
+    fn test_invalid_window() {
+        let mut rate_limiter = RateLimiter::new();
+        assert_err!(
+            rate_limiter.set_config(RateLimitConfig {
+                limits: vec![limit(b"", 0, 1)],
+            }),
+            micro_rpc::StatusCode::InvalidArgument,
+            "rate limit `window` must be positive"
+        );
+    }
+}
@@ -59,7 +59,7 @@ mod test {
 
     impl LedgerService {
         fn create(create_actor_fn: fn() -> LedgerActor) -> Self {
-            let config = LedgerConfig {};
+            let config = LedgerConfig::default();
             let mut service = LedgerService {
                 cluster: FakeCluster::new(config.encode_to_vec().into()),
                 create_actor_fn,
@@ -202,6 +202,127 @@ mod test {
                 Err(LedgerService::parse_error(ledger_response))
             }
         }
+
+        fn authorize_access_batch(
+            &mut self,
+            request: AuthorizeAccessBatchRequest,
+        ) -> Result<AuthorizeAccessBatchResponse, micro_rpc::Status> {
+            let ledger_request = LedgerRequest {
+                request: Some(ledger_request::Request::AuthorizeAccessBatch(request)),
+            };
+            self.send_request(ledger_request);
+            let ledger_response = self.advance_until_response();
+            if let Some(ledger_response::Response::AuthorizeAccessBatch(response)) =
+                ledger_response.response
+            {
+                Ok(response)
+            } else {
+                Err(LedgerService::parse_error(ledger_response))
+            }
+        }
+
+        fn list_keys(
+            &mut self,
+            request: ListKeysRequest,
+        ) -> Result<ListKeysResponse, micro_rpc::Status> {
+            let ledger_request = LedgerRequest {
+                request: Some(ledger_request::Request::ListKeys(request)),
+            };
+            self.send_request(ledger_request);
+            let ledger_response = self.advance_until_response();
+            if let Some(ledger_response::Response::ListKeys(response)) = ledger_response.response {
+                Ok(response)
+            } else {
+                Err(LedgerService::parse_error(ledger_response))
+            }
+        }
+
+        fn get_key_details(
+            &mut self,
+            request: GetKeyDetailsRequest,
+        ) -> Result<GetKeyDetailsResponse, micro_rpc::Status> {
+            let ledger_request = LedgerRequest {
+                request: Some(ledger_request::Request::GetKeyDetails(request)),
+            };
+            self.send_request(ledger_request);
+            let ledger_response = self.advance_until_response();
+            if let Some(ledger_response::Response::GetKeyDetails(response)) =
+                ledger_response.response
+            {
+                Ok(response)
+            } else {
+                Err(LedgerService::parse_error(ledger_response))
+            }
+        }
+
+        fn query_audit_log(
+            &mut self,
+            request: QueryAuditLogRequest,
+        ) -> Result<QueryAuditLogResponse, micro_rpc::Status> {
+            let ledger_request = LedgerRequest {
+                request: Some(ledger_request::Request::QueryAuditLog(request)),
+            };
+            self.send_request(ledger_request);
+            let ledger_response = self.advance_until_response();
+            if let Some(ledger_response::Response::QueryAuditLog(response)) =
+                ledger_response.response
+            {
+                Ok(response)
+            } else {
+                Err(LedgerService::parse_error(ledger_response))
+            }
+        }
+
+        fn check_access(
+            &mut self,
+            request: CheckAccessRequest,
+        ) -> Result<CheckAccessResponse, micro_rpc::Status> {
+            let ledger_request = LedgerRequest {
+                request: Some(ledger_request::Request::CheckAccess(request)),
+            };
+            self.send_request(ledger_request);
+            let ledger_response = self.advance_until_response();
+            if let Some(ledger_response::Response::CheckAccess(response)) = ledger_response.response
+            {
+                Ok(response)
+            } else {
+                Err(LedgerService::parse_error(ledger_response))
+            }
+        }
+
+        fn get_key_stats(
+            &mut self,
+            request: GetKeyStatsRequest,
+        ) -> Result<GetKeyStatsResponse, micro_rpc::Status> {
+            let ledger_request = LedgerRequest {
+                request: Some(ledger_request::Request::GetKeyStats(request)),
+            };
+            self.send_request(ledger_request);
+            let ledger_response = self.advance_until_response();
+            if let Some(ledger_response::Response::GetKeyStats(response)) = ledger_response.response
+            {
+                Ok(response)
+            } else {
+                Err(LedgerService::parse_error(ledger_response))
+            }
+        }
+
+        fn rewrap_keys(
+            &mut self,
+            request: RewrapKeysRequest,
+        ) -> Result<RewrapKeysResponse, micro_rpc::Status> {
+            let ledger_request = LedgerRequest {
+                request: Some(ledger_request::Request::RewrapKeys(request)),
+            };
+            self.send_request(ledger_request);
+            let ledger_response = self.advance_until_response();
+            if let Some(ledger_response::Response::RewrapKeys(response)) = ledger_response.response
+            {
+                Ok(response)
+            } else {
+                Err(LedgerService::parse_error(ledger_response))
+            }
+        }
     }
 
     /// Helper function to create a LedgerService with one key.
@@ -254,6 +375,7 @@ mod test {
                     seconds: 100,
                     ..Default::default()
                 }),
+                ..Default::default()
             })
             .unwrap();
         assert!(response1.attestation_evidence.is_some());
@@ -288,6 +410,7 @@ mod test {
                     seconds: 100,
                     ..Default::default()
                 }),
+                ..Default::default()
             })
             .unwrap();
         let key2 = extract_key_from_cwt(&response2.public_key).unwrap();
@@ -933,6 +1056,7 @@ mod test {
             transforms: vec![Transform {
                 access_budget: Some(AccessBudget {
                     kind: Some(AccessBudgetKind::Times(1)),
+                    ..Default::default()
                 }),
                 ..Default::default()
             }],
@@ -979,6 +1103,200 @@ mod test {
             micro_rpc::StatusCode::ResourceExhausted,
             ""
         );
+
+        // The denial is replicated along with the granted access.
+        assert_eq!(
+            ledger.get_key_stats(GetKeyStatsRequest {
+                key_id: cose_key.key_id,
+                ..Default::default()
+            }),
+            Ok(GetKeyStatsResponse {
+                authorizations_granted: 1,
+                authorizations_denied: 1,
+                budget_exhaustions: 1,
+                distinct_blobs: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn test_authorize_access_batch() {
+        let (mut ledger, public_key) = create_ledger_service();
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+        let access_policy = DataAccessPolicy {
+            transforms: vec![Transform {
+                access_budget: Some(AccessBudget {
+                    kind: Some(AccessBudgetKind::Times(1)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let plaintext = b"plaintext";
+        let mut blobs = Vec::new();
+        for blob_id in [b"blob-id1", b"blob-id2", b"blob-id1"] {
+            let blob_header = BlobHeader {
+                blob_id: blob_id.to_vec(),
+                key_id: cose_key.key_id.clone(),
+                access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+                ..Default::default()
+            }
+            .encode_to_vec();
+            let (_, encapsulated_key, encrypted_symmetric_key) =
+                cfc_crypto::encrypt_message(plaintext, &cose_key, &blob_header).unwrap();
+            blobs.push(authorize_access_batch_request::BlobAccess {
+                blob_header,
+                encapsulated_key,
+                encrypted_symmetric_key,
+                recipient_nonce: b"nonce".to_vec(),
+            });
+        }
+
+        // Both blobs are authorized once, the repeated access to the first blob
+        // exhausts its budget without failing the batch.
+        let response = ledger
+            .authorize_access_batch(AuthorizeAccessBatchRequest {
+                access_policy,
+                recipient_public_key: create_recipient_cwt(cfc_crypto::gen_keypair(b"key-id").1),
+                recipient_tag: "tag".to_owned(),
+                blobs,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(response.results.len(), 3);
+        assert!(matches!(
+            response.results[0].outcome,
+            Some(authorize_access_batch_response::blob_result::Outcome::Authorized(_))
+        ));
+        assert!(matches!(
+            response.results[1].outcome,
+            Some(authorize_access_batch_response::blob_result::Outcome::Authorized(_))
+        ));
+        assert!(matches!(
+            response.results[2].outcome,
+            Some(authorize_access_batch_response::blob_result::Outcome::Error(_))
+        ));
+    }
+
+    #[test]
+    fn test_list_keys() {
+        let (mut ledger, public_key) = create_ledger_service();
+        let key_id = extract_key_from_cwt(&public_key).unwrap().key_id;
+
+        let response = ledger.list_keys(ListKeysRequest::default()).unwrap();
+        assert_eq!(response.keys.len(), 1);
+        assert_eq!(response.keys[0].key_id, key_id);
+        assert_eq!(response.keys[0].public_key, public_key);
+
+        assert_eq!(
+            ledger
+                .get_key_details(GetKeyDetailsRequest {
+                    key_id,
+                    ..Default::default()
+                })
+                .unwrap()
+                .key,
+            Some(response.keys[0].clone())
+        );
+        assert_err!(
+            ledger.get_key_details(GetKeyDetailsRequest {
+                key_id: b"invalid".to_vec(),
+                ..Default::default()
+            }),
+            micro_rpc::StatusCode::NotFound,
+            "public key not found"
+        );
+    }
+
+    #[test]
+    fn test_query_audit_log() {
+        let (mut ledger, public_key) = create_ledger_service();
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+        let access_policy = DataAccessPolicy {
+            transforms: vec![Transform::default()],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let recipient_public_key = create_recipient_cwt(cfc_crypto::gen_keypair(b"key-id").1);
+
+        // Authorize access to two blobs.
+        for blob_id in [b"blob1", b"blob2"] {
+            let blob_header = BlobHeader {
+                blob_id: blob_id.to_vec(),
+                key_id: cose_key.key_id.clone(),
+                access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+                ..Default::default()
+            }
+            .encode_to_vec();
+            let (_, encapsulated_key, encrypted_symmetric_key) =
+                cfc_crypto::encrypt_message(b"plaintext", &cose_key, &blob_header).unwrap();
+            assert!(ledger
+                .authorize_access(AuthorizeAccessRequest {
+                    access_policy: access_policy.clone(),
+                    blob_header,
+                    encapsulated_key,
+                    encrypted_symmetric_key,
+                    recipient_public_key: recipient_public_key.clone(),
+                    recipient_tag: "tag".to_owned(),
+                    recipient_nonce: b"nonce".to_vec(),
+                    ..Default::default()
+                })
+                .is_ok());
+        }
+
+        // Read the audit log one entry at a time.
+        let first_page = ledger
+            .query_audit_log(QueryAuditLogRequest {
+                start_sequence_number: 0,
+                page_size: 1,
+            })
+            .unwrap();
+        assert_eq!(first_page.next_sequence_number, Some(1));
+        let second_page = ledger
+            .query_audit_log(QueryAuditLogRequest {
+                start_sequence_number: 1,
+                page_size: 1,
+            })
+            .unwrap();
+        assert_eq!(second_page.next_sequence_number, None);
+        assert_eq!(
+            second_page.previous_entry_sha256,
+            first_page.entries[0].entry_sha256
+        );
+
+        let entries: Vec<AuditLogEntry> = [first_page.entries, second_page.entries].concat();
+        let mut previous_entry_sha256 = Vec::new();
+        for (i, (entry, blob_id)) in entries.iter().zip([b"blob1", b"blob2"]).enumerate() {
+            assert_eq!(entry.sequence_number, i as u64);
+            assert_eq!(entry.key_id, cose_key.key_id);
+            assert_eq!(entry.blob_id, blob_id.to_vec());
+            assert_eq!(
+                entry.access_policy_sha256,
+                Sha256::digest(&access_policy).to_vec()
+            );
+            assert_eq!(
+                entry.recipient_sha256,
+                Sha256::digest(&recipient_public_key).to_vec()
+            );
+            assert_eq!(entry.recipient_tag, "tag");
+
+            // Verify the digest chain.
+            let unhashed_entry = AuditLogEntry {
+                entry_sha256: vec![],
+                ..entry.clone()
+            };
+            assert_eq!(
+                entry.entry_sha256,
+                Sha256::new()
+                    .chain_update(&previous_entry_sha256)
+                    .chain_update(unhashed_entry.encode_to_vec())
+                    .finalize()
+                    .to_vec()
+            );
+            previous_entry_sha256 = entry.entry_sha256.clone();
+        }
     }
 
     #[test]
@@ -1028,6 +1346,69 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_rewrap_keys() {
+        let (mut ledger, old_public_key) = create_ledger_service();
+        let old_cose_key = extract_key_from_cwt(&old_public_key).unwrap();
+        let new_public_key = ledger
+            .create_key(CreateKeyRequest {
+                ttl: Some(prost_types::Duration {
+                    seconds: 7200,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .unwrap()
+            .public_key;
+        let new_key_id = extract_key_from_cwt(&new_public_key).unwrap().key_id;
+
+        let access_policy = DataAccessPolicy {
+            transforms: vec![Transform::default()],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let blob_header = BlobHeader {
+            blob_id: b"blob-id".to_vec(),
+            key_id: old_cose_key.key_id.clone(),
+            access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let (_, encapsulated_key, encrypted_symmetric_key) =
+            cfc_crypto::encrypt_message(b"plaintext", &old_cose_key, &blob_header).unwrap();
+        let response = ledger
+            .rewrap_keys(RewrapKeysRequest {
+                old_key_id: old_cose_key.key_id.clone(),
+                new_key_id,
+                blobs: vec![rewrap_keys_request::Blob {
+                    blob_header,
+                    encapsulated_key,
+                    encrypted_symmetric_key,
+                }],
+                ..Default::default()
+            })
+            .unwrap();
+        let Some(rewrap_keys_response::blob_result::Outcome::Rewrapped(rewrapped)) =
+            &response.results[0].outcome
+        else {
+            panic!("Rewrapped outcome expected");
+        };
+
+        // The rewrapped blob is accessed through the new key.
+        let response = ledger
+            .authorize_access(AuthorizeAccessRequest {
+                access_policy,
+                blob_header: rewrapped.blob_header.clone(),
+                encapsulated_key: rewrapped.encapsulated_key.clone(),
+                encrypted_symmetric_key: rewrapped.encrypted_symmetric_key.clone(),
+                recipient_public_key: create_recipient_cwt(cfc_crypto::gen_keypair(b"key-id").1),
+                recipient_nonce: b"nonce".to_vec(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(response.reencryption_public_key, new_public_key);
+    }
+
     #[test]
     fn test_revoke_access_key_not_found() {
         let (mut ledger, public_key) = create_ledger_service();
@@ -1042,4 +1423,140 @@ mod test {
             "public key not found"
         );
     }
+
+    #[test]
+    fn test_error_details() {
+        let (mut ledger, _) = create_ledger_service();
+        ledger.send_request(LedgerRequest {
+            request: Some(ledger_request::Request::RevokeAccess(RevokeAccessRequest {
+                key_id: b"unknown".to_vec(),
+                blob_id: b"blob-id".to_vec(),
+                ..Default::default()
+            })),
+        });
+        let Some(ledger_response::Response::Error(error)) =
+            ledger.advance_until_response().response
+        else {
+            panic!("expected an error response");
+        };
+        assert_eq!(error.code, micro_rpc::StatusCode::NotFound as i32);
+        assert_eq!(
+            error.details,
+            Some(LedgerErrorDetails {
+                reason: ledger_error_details::Reason::KeyNotFound.into(),
+                field: "key_id".into(),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_check_access() {
+        let (mut ledger, public_key) = create_ledger_service();
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+        let access_policy = DataAccessPolicy {
+            transforms: vec![Transform {
+                access_budget: Some(AccessBudget {
+                    kind: Some(AccessBudgetKind::Times(1)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let blob_header = BlobHeader {
+            blob_id: b"blob-id".to_vec(),
+            key_id: cose_key.key_id.clone(),
+            access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let (_, encapsulated_key, encrypted_symmetric_key) =
+            cfc_crypto::encrypt_message(b"plaintext", &cose_key, &blob_header).unwrap();
+        let recipient_public_key = create_recipient_cwt(cfc_crypto::gen_keypair(b"key-id").1);
+        let check_access_request = CheckAccessRequest {
+            access_policy: access_policy.clone(),
+            blob_header: blob_header.clone(),
+            recipient_public_key: recipient_public_key.clone(),
+            ..Default::default()
+        };
+
+        // Checking the access doesn't consume the budget.
+        for _ in 0..2 {
+            assert_eq!(
+                ledger.check_access(check_access_request.clone()),
+                Ok(CheckAccessResponse { transform_index: 0 })
+            );
+        }
+        assert!(ledger
+            .authorize_access(AuthorizeAccessRequest {
+                access_policy,
+                blob_header,
+                encapsulated_key,
+                encrypted_symmetric_key,
+                recipient_public_key,
+                recipient_nonce: b"nonce".to_vec(),
+                ..Default::default()
+            })
+            .is_ok());
+
+        // Once the budget is exhausted, the check fails the same way as the authorization.
+        assert_err!(
+            ledger.check_access(check_access_request),
+            micro_rpc::StatusCode::ResourceExhausted,
+            "data access budget exhausted"
+        );
+    }
+
+    #[test]
+    fn test_get_key_stats() {
+        let (mut ledger, public_key) = create_ledger_service();
+        let cose_key = extract_key_from_cwt(&public_key).unwrap();
+        let access_policy = DataAccessPolicy {
+            transforms: vec![Transform {
+                access_budget: Some(AccessBudget {
+                    kind: Some(AccessBudgetKind::Times(1)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let blob_header = BlobHeader {
+            blob_id: b"blob-id".to_vec(),
+            key_id: cose_key.key_id.clone(),
+            access_policy_sha256: Sha256::digest(&access_policy).to_vec(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let (_, encapsulated_key, encrypted_symmetric_key) =
+            cfc_crypto::encrypt_message(b"plaintext", &cose_key, &blob_header).unwrap();
+        let request = AuthorizeAccessRequest {
+            access_policy,
+            blob_header,
+            encapsulated_key,
+            encrypted_symmetric_key,
+            recipient_public_key: create_recipient_cwt(cfc_crypto::gen_keypair(b"key-id").1),
+            recipient_nonce: b"nonce".to_vec(),
+            ..Default::default()
+        };
+
+        assert!(ledger.authorize_access(request.clone()).is_ok());
+        assert!(ledger.authorize_access(request).is_err());
+
+        assert_eq!(
+            ledger.get_key_stats(GetKeyStatsRequest {
+                key_id: cose_key.key_id.clone(),
+                ..Default::default()
+            }),
+            Ok(GetKeyStatsResponse {
+                authorizations_granted: 1,
+                authorizations_denied: 1,
+                budget_exhaustions: 1,
+                distinct_blobs: 1,
+            })
+        );
+    }
 }
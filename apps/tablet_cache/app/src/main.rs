@@ -19,25 +19,19 @@
 extern crate alloc;
 extern crate hashbrown;
 extern crate prost;
-extern crate tcp_proto;
 extern crate tcp_runtime;
 
-use alloc::{boxed::Box, string::ToString};
+use alloc::{boxed::Box, rc::Rc, string::ToString};
 use hashbrown::HashMap;
-use oak_restricted_kernel_sdk::{
-    channel::{start_blocking_server, FileDescriptorChannel},
-    entrypoint,
-    utils::{log, samplestore::StaticSampleStore},
-};
+use oak_restricted_kernel_sdk::entrypoint;
 use prost::bytes::Bytes;
-use tcp_proto::runtime::endpoint::EndpointServiceServer;
-use tcp_runtime::service::ApplicationService;
+use tcp_runtime::{clock::HostClock, server::run_blocking_server, service::ApplicationService};
 use tcp_tablet_cache_service::{
     actor::TabletCacheActor,
     store::SimpleKeyValueStore,
     transaction::{
         coordinator::DefaultTabletTransactionCoordinator,
-        data::{BytesTabletDataSerializer, DefaultTabletDataCache, DefaultTabletDataCachePolicy},
+        data::{BytesTabletDataSerializer, DefaultTabletDataCache, LruTabletDataCachePolicy},
         manager::DefaultTabletTransactionManager,
         metadata::DefaultTabletMetadataCache,
     },
@@ -49,34 +43,29 @@ const METADATA_CACHE_CORRELATION_COUNTER: u64 = 3 << 56;
 
 #[entrypoint]
 fn run_server() -> ! {
-    // Only log warnings and errors to reduce the risk of accidentally leaking execution
-    // information through debug logs.
-    log::set_max_level(log::LevelFilter::Warn);
-
-    let mut invocation_stats = StaticSampleStore::<1000>::new().unwrap();
+    // The clock is shared between the runtime and the tablet data cache.
+    let clock = Rc::new(HostClock::new());
     let service: ApplicationService<
         TabletCacheActor<DefaultTabletTransactionManager<Bytes>, SimpleKeyValueStore>,
-    > = ApplicationService::new(TabletCacheActor::new(
-        DefaultTabletTransactionManager::create(
-            Box::new(DefaultTabletTransactionCoordinator::create(
-                TRANSACTION_COORDINATOR_CORRELATION_COUNTER,
-            )),
-            Box::new(DefaultTabletMetadataCache::create(
-                METADATA_CACHE_CORRELATION_COUNTER,
-            )),
-            Box::new(DefaultTabletDataCache::create(
-                DATA_CACHE_CORRELATION_COUNTER,
-                Box::new(BytesTabletDataSerializer {}),
-                Box::new(DefaultTabletDataCachePolicy::new()),
-            )),
+    > = ApplicationService::with_clock(
+        TabletCacheActor::new(
+            DefaultTabletTransactionManager::create(
+                Box::new(DefaultTabletTransactionCoordinator::create(
+                    TRANSACTION_COORDINATOR_CORRELATION_COUNTER,
+                )),
+                Box::new(DefaultTabletMetadataCache::create(
+                    METADATA_CACHE_CORRELATION_COUNTER,
+                )),
+                Box::new(DefaultTabletDataCache::create(
+                    DATA_CACHE_CORRELATION_COUNTER,
+                    Box::new(BytesTabletDataSerializer {}),
+                    Box::new(LruTabletDataCachePolicy::new()),
+                    clock.clone(),
+                )),
+            ),
+            SimpleKeyValueStore::create(),
         ),
-        SimpleKeyValueStore::create(),
-    ));
-    let server = EndpointServiceServer::new(service);
-    start_blocking_server(
-        Box::<FileDescriptorChannel>::default(),
-        server,
-        &mut invocation_stats,
-    )
-    .expect("Server encountered an unrecoverable error");
+        clock,
+    );
+    run_blocking_server(service)
 }
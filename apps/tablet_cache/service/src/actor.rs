@@ -17,17 +17,29 @@ use crate::{
         tablet_cache_in_message::*, tablet_cache_out_message::OutMsg, TabletCacheConfig,
         TabletCacheInMessage, TabletCacheOutMessage, TransactionManagerConfig,
     },
-    store, transaction,
+    store,
+    transaction::{self, data::TabletDataCacheStats, envelope::TABLET_KEY_WRAPPING_CONTEXT},
 };
 use alloc::boxed::Box;
 use prost::{bytes::Bytes, Message};
-use slog::{debug, o};
+use slog::{debug, error, o};
 use tcp_runtime::model::{
     Actor, ActorCommand, ActorContext, ActorError, ActorEvent, ActorEventContext, CommandOutcome,
     EventOutcome,
 };
 use tcp_tablet_store_service::apps::tablet_store::service::TabletsResponse;
 
+// Names of the metrics reported by the tablet cache actor.
+pub const TABLET_DATA_CACHE_HITS: &str = "tablet_cache.data.hits";
+pub const TABLET_DATA_CACHE_MISSES: &str = "tablet_cache.data.misses";
+pub const TABLET_DATA_CACHE_EVICTIONS: &str = "tablet_cache.data.evictions";
+pub const TABLET_DATA_CACHE_CACHED_BYTES: &str = "tablet_cache.data.cached_bytes";
+pub const TABLET_DATA_CACHE_PENDING_LOADS: &str = "tablet_cache.data.pending_loads";
+pub const TABLET_DATA_CACHE_PENDING_STORES: &str = "tablet_cache.data.pending_stores";
+pub const TABLET_DATA_CACHE_AVERAGE_LOAD_LATENCY: &str = "tablet_cache.data.average_load_latency";
+
+// Tablet cache actor. The tablet keys are wrapped under the key derived from
+// the cluster secret, hence the actor requires `RaftConfig.share_cluster_secret`.
 pub struct TabletCacheActor<
     T: transaction::TabletTransactionManager<Bytes>,
     S: store::KeyValueStore,
@@ -35,6 +47,11 @@ pub struct TabletCacheActor<
     transaction_manager: T,
     key_value_store: S,
     context: Option<Box<dyn ActorContext>>,
+    // Indicates whether the key the tablet keys are wrapped under has been
+    // derived from the cluster secret and passed to the transaction manager.
+    has_key_wrapping_key: bool,
+    // Tablet data cache statistics as of the last metrics report.
+    reported_data_cache_stats: TabletDataCacheStats,
 }
 
 impl<T: transaction::TabletTransactionManager<Bytes>, S: store::KeyValueStore>
@@ -45,6 +62,8 @@ impl<T: transaction::TabletTransactionManager<Bytes>, S: store::KeyValueStore>
             transaction_manager,
             key_value_store,
             context: None,
+            has_key_wrapping_key: false,
+            reported_data_cache_stats: TabletDataCacheStats::default(),
         }
     }
 
@@ -55,6 +74,59 @@ impl<T: transaction::TabletTransactionManager<Bytes>, S: store::KeyValueStore>
             .as_mut()
     }
 
+    // Passes the key the tablet keys are wrapped under to the transaction manager
+    // once the cluster secret is available. The replica must be configured to
+    // share the cluster secret, otherwise tablets can't be loaded or stored.
+    fn sync_key_wrapping_key(&mut self) -> bool {
+        if !self.has_key_wrapping_key {
+            match self.get_context().cluster_key(TABLET_KEY_WRAPPING_CONTEXT) {
+                Some(key_wrapping_key) => {
+                    self.transaction_manager
+                        .set_key_wrapping_key(key_wrapping_key.into());
+                    self.has_key_wrapping_key = true;
+                }
+                None => {
+                    error!(
+                        self.get_context().logger(),
+                        "Cluster secret is not available"
+                    );
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    // Reports tablet data cache statistics to the runtime metrics. Counters are
+    // advanced by the change since the previous report.
+    fn report_metrics(&mut self) {
+        let metrics = self.get_context().metrics();
+        let stats = self.transaction_manager.get_data_cache_stats();
+        let reported_stats = &self.reported_data_cache_stats;
+
+        metrics.increment_counter(TABLET_DATA_CACHE_HITS, stats.hits - reported_stats.hits);
+        metrics.increment_counter(
+            TABLET_DATA_CACHE_MISSES,
+            stats.misses - reported_stats.misses,
+        );
+        metrics.increment_counter(
+            TABLET_DATA_CACHE_EVICTIONS,
+            stats.evictions - reported_stats.evictions,
+        );
+        metrics.set_gauge(TABLET_DATA_CACHE_CACHED_BYTES, stats.cached_bytes as i64);
+        metrics.set_gauge(TABLET_DATA_CACHE_PENDING_LOADS, stats.pending_loads as i64);
+        metrics.set_gauge(
+            TABLET_DATA_CACHE_PENDING_STORES,
+            stats.pending_stores as i64,
+        );
+        metrics.set_gauge(
+            TABLET_DATA_CACHE_AVERAGE_LOAD_LATENCY,
+            stats.average_load_latency as i64,
+        );
+
+        self.reported_data_cache_stats = stats;
+    }
+
     fn command_with_proto<M: Message + Sized>(
         correlation_id: u64,
         out_header: OutMsg,
@@ -120,6 +192,10 @@ impl<T: transaction::TabletTransactionManager<Bytes>, S: store::KeyValueStore> A
         &mut self,
         command: Option<ActorCommand>,
     ) -> Result<CommandOutcome, ActorError> {
+        if !self.sync_key_wrapping_key() {
+            return Err(ActorError::Internal);
+        }
+
         if let Some(command) = command {
             let in_header = match TabletCacheInMessage::decode(command.header.clone()) {
                 Ok(in_message) => in_message.in_msg,
@@ -173,6 +249,12 @@ impl<T: transaction::TabletTransactionManager<Bytes>, S: store::KeyValueStore> A
                             command.correlation_id,
                             error,
                         )),
+                    InMsg::DeleteTabletResponse(response) => self
+                        .transaction_manager
+                        .process_in_message(transaction::InMessage::DeleteTabletResponse(
+                            command.correlation_id,
+                            response,
+                        )),
                 },
                 None => {
                     return Err(ActorError::Internal);
@@ -180,9 +262,10 @@ impl<T: transaction::TabletTransactionManager<Bytes>, S: store::KeyValueStore> A
             };
         }
 
-        let instant = self.get_context().instant();
         self.key_value_store
-            .make_progress(instant, &mut self.transaction_manager);
+            .make_progress(&mut self.transaction_manager);
+
+        self.report_metrics();
 
         let transaction_out_commands = self
             .transaction_manager
@@ -214,6 +297,14 @@ impl<T: transaction::TabletTransactionManager<Bytes>, S: store::KeyValueStore> A
                     OutMsg::ExecuteTabletOpsRequest(execute_tablet_ops_tequest),
                     tablets_request,
                 ),
+                transaction::OutMessage::DeleteTabletRequest(
+                    correlation_id,
+                    delete_tablet_request,
+                ) => Self::command_with_bytes(
+                    correlation_id,
+                    OutMsg::DeleteTabletRequest(delete_tablet_request),
+                    Bytes::new(),
+                ),
             });
 
         let store_out_commands =
@@ -22,13 +22,7 @@ extern crate slog;
 extern crate tcp_runtime;
 extern crate tcp_tablet_store_service;
 
-pub mod apps {
-    pub mod tablet_cache {
-        pub mod service {
-            include!(concat!(env!("OUT_DIR"), "/apps.tablet_cache.service.rs"));
-        }
-    }
-}
+pub use tcp_proto::apps;
 
 pub mod actor;
 #[cfg(feature = "std")]
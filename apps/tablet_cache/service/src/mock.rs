@@ -35,6 +35,21 @@ mock! {
         );
 
         fn start_transaction(&mut self) -> Box<dyn TabletTransaction<T>>;
+
+        fn export_tablets(
+            &mut self,
+            table_name: String,
+            transfer_key: Bytes,
+        ) -> ResultHandle<Vec<TabletMetadata>, TabletsRequestStatus>;
+
+        fn import_tablets(
+            &mut self,
+            table_name: String,
+            tablets: Vec<TabletMetadata>,
+            transfer_key: Bytes,
+        ) -> ResultHandle<(), TabletsRequestStatus>;
+
+        fn start_read_transaction(&mut self, as_of_version: u64) -> Box<dyn TabletTransaction<T>>;
     }
 }
 
@@ -75,7 +90,9 @@ mock! {
     impl<T> TabletDataCache<T> for TabletDataCache<T> {
         fn init(&mut self, logger: Logger, config: TabletDataCacheConfig);
 
-        fn make_progress(&mut self, instant: u64);
+        fn set_key_wrapping_key(&mut self, key_wrapping_key: Bytes);
+
+        fn make_progress(&mut self);
 
         fn load_tablets(
             &mut self,
@@ -87,9 +104,17 @@ mock! {
             data: Vec<(&'a mut TabletMetadata, T)>,
         ) -> ResultHandle<(), TabletDataStorageStatus>;
 
+        fn release_tablets(
+            &mut self,
+            superseded_metadata: Vec<TabletMetadata>,
+            discarded_metadata: Vec<TabletMetadata>,
+        );
+
         fn process_in_message(&mut self, in_message: TabletDataCacheInMessage);
 
         fn take_out_messages(&mut self) -> Vec<TabletDataCacheOutMessage>;
+
+        fn get_stats(&self) -> TabletDataCacheStats;
     }
 }
 
@@ -100,7 +125,9 @@ mock! {
     impl TabletMetadataCache for TabletMetadataCache {
         fn init(&mut self, logger: Logger, config: TabletMetadataCacheConfig);
 
-        fn make_progress(&mut self, instant: u64);
+        fn set_key_wrapping_key(&mut self, key_wrapping_key: Bytes);
+
+        fn make_progress(&mut self);
 
         fn resolve_tablets(
             &mut self,
@@ -113,6 +140,25 @@ mock! {
             handler: Box<ResolveHandler>,
         );
 
+        fn resolve_tablets_as_of(
+            &mut self,
+            queries: &Vec<TableQuery>,
+            as_of_version: u64,
+        ) -> ResultHandle<Vec<(TableQuery, TabletMetadata)>, TabletsRequestStatus>;
+
+        fn export_tablets(
+            &mut self,
+            table_name: String,
+            transfer_key: Bytes,
+        ) -> ResultHandle<Vec<TabletMetadata>, TabletsRequestStatus>;
+
+        fn import_tablets(
+            &mut self,
+            table_name: String,
+            tablets: Vec<TabletMetadata>,
+            transfer_key: Bytes,
+        ) -> ResultHandle<(), TabletsRequestStatus>;
+
         fn update_tablet(&mut self, table_name: String, tablet_metadata: TabletMetadata, conflict: bool);
 
         fn process_in_message(&mut self, in_message: TabletMetadataCacheInMessage);
@@ -130,7 +176,6 @@ mock! {
 
         fn make_progress(
             &mut self,
-            instant: u64,
             metadata_cache: &mut dyn TabletMetadataCache,
             data_cache: &mut dyn TabletDataCache<T>,
         );
@@ -141,6 +186,8 @@ mock! {
 
         fn create_transaction(&mut self) -> u64;
 
+        fn create_read_transaction(&mut self, as_of_version: u64) -> u64;
+
         fn process_transaction(
             &mut self,
             transaction_id: u64,
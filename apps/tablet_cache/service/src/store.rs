@@ -60,13 +60,12 @@ pub trait KeyValueStore {
     // Initializes key value store.
     fn init(&mut self, logger: Logger, config: StoreConfig);
 
-    // Periodically called with the current instant and reference to the transaction
-    // context. Key value store may start a new transaction after enough consumer
-    // requests has been batched together, process consumer requests, commit
-    // transaction, check transaction result and produce consumer responses.
+    // Periodically called with reference to the transaction context. Key value
+    // store may start a new transaction after enough consumer requests has been
+    // batched together, process consumer requests, commit transaction, check
+    // transaction result and produce consumer responses.
     fn make_progress(
         &mut self,
-        instant: u64,
         transaction_context: &mut dyn transaction::TabletTransactionContext<Bytes>,
     );
 
@@ -111,7 +110,6 @@ impl KeyValueStore for SimpleKeyValueStore {
 
     fn make_progress(
         &mut self,
-        _instant: u64,
         transaction_context: &mut dyn transaction::TabletTransactionContext<Bytes>,
     ) {
         let mut core = self.core.borrow_mut();
@@ -960,7 +958,7 @@ mod tests {
             CORRELATION_ID_3,
             create_get_request(KEY_3),
         ));
-        store.make_progress(1, &mut transaction_context);
+        store.make_progress(&mut transaction_context);
 
         assert!(store.take_responses().is_empty());
 
@@ -968,7 +966,7 @@ mod tests {
 
         resolve_handler(vec![(table_query_1.clone(), tablet_descriptor_1.clone())]);
 
-        store.make_progress(2, &mut transaction_context);
+        store.make_progress(&mut transaction_context);
 
         assert!(store.take_responses().is_empty());
 
@@ -981,7 +979,7 @@ mod tests {
             vec![(table_query_2.clone(), &mut tablet_1)],
         );
 
-        store.make_progress(3, &mut transaction_context);
+        store.make_progress(&mut transaction_context);
 
         assert_eq!(
             vec![
@@ -21,8 +21,9 @@ use alloc::{
     string::String,
     vec::Vec,
 };
-use data::TabletData;
+use data::{TabletData, TabletDataCacheStats};
 use prost::bytes::Bytes;
+use result::ResultHandle;
 use slog::Logger;
 use tcp_tablet_store_service::apps::tablet_store::service::{
     tablet_op, tablet_op_result::OpResult, CheckTabletOp, TabletMetadata, TabletOp, TabletOpResult,
@@ -30,14 +31,16 @@ use tcp_tablet_store_service::apps::tablet_store::service::{
 };
 
 use crate::apps::tablet_cache::service::{
-    ExecuteTabletOpsError, ExecuteTabletOpsRequest, ExecuteTabletOpsResponse, LoadTabletRequest,
-    LoadTabletResponse, StoreTabletRequest, StoreTabletResponse, TabletDataStorageStatus,
-    TransactionManagerConfig,
+    DeleteTabletRequest, DeleteTabletResponse, ExecuteTabletOpsError, ExecuteTabletOpsRequest,
+    ExecuteTabletOpsResponse, LoadTabletRequest, LoadTabletResponse, StoreTabletRequest,
+    StoreTabletResponse, TabletDataStorageStatus, TransactionManagerConfig,
 };
 use hashbrown::{HashMap, HashSet};
 
+pub mod codec;
 pub mod coordinator;
 pub mod data;
+pub mod envelope;
 pub mod manager;
 pub mod metadata;
 pub mod result;
@@ -53,6 +56,8 @@ pub enum InMessage {
     ExecuteTabletOpsResponse(u64, ExecuteTabletOpsResponse, TabletsResponse),
     // Error to tablet ops executing in tablet store along with correlation id.
     ExecuteTabletOpsError(u64, ExecuteTabletOpsError),
+    // Response to tablet deleting from data storage along with correlation id.
+    DeleteTabletResponse(u64, DeleteTabletResponse),
 }
 
 // Messages that may go from the transction manager.
@@ -64,6 +69,8 @@ pub enum OutMessage {
     StoreTabletRequest(u64, StoreTabletRequest, Bytes),
     // Request to execute tablet ops in tablet store alogn with tablets request and correlation id.
     ExecuteTabletOpsRequest(u64, ExecuteTabletOpsRequest, TabletsRequest),
+    // Request to delete tablet from data storage along with correlation id.
+    DeleteTabletRequest(u64, DeleteTabletRequest),
 }
 
 // Transaction manager is responsible for providing efficient cache for the
@@ -81,9 +88,14 @@ pub trait TabletTransactionManager<T>: TabletTransactionContext<T> {
     // Initializes transaction manager with tablet cache config.
     fn init(&mut self, logger: Logger, config: TransactionManagerConfig);
 
+    // Sets the key the tablet keys are wrapped under, see
+    // TabletDataCache::set_key_wrapping_key and
+    // TabletMetadataCache::set_key_wrapping_key.
+    fn set_key_wrapping_key(&mut self, key_wrapping_key: Bytes);
+
     // Advances internal state machine of the transaction manager. Essentially
     // it tries to make progress on all pending tablet resolutions and transactions.
-    fn make_progress(&mut self, instant: u64);
+    fn make_progress(&mut self);
 
     // Processes incoming message, which maybe load or store tablet
     // result, outcome of the tablet ops execution.
@@ -92,6 +104,9 @@ pub trait TabletTransactionManager<T>: TabletTransactionContext<T> {
     // Takes outgoing messages to be send out, which maybe requests to
     // load or store tablet, execute tablet ops.
     fn take_out_messages(&mut self) -> Vec<OutMessage>;
+
+    // Gets current statistics of the tablet data cache.
+    fn get_data_cache_stats(&self) -> TabletDataCacheStats;
 }
 
 pub type ResolveHandler = dyn FnMut(Vec<(TableQuery, TabletDescriptor)>) -> ();
@@ -107,6 +122,31 @@ pub trait TabletTransactionContext<T> {
 
     // Starts a new transaction. Multiple concurrent transactions may coexist.
     fn start_transaction(&mut self) -> Box<dyn TabletTransaction<T>>;
+
+    // Requests to export metadata, including data uris, of all tablets of the
+    // given table. Used to migrate the table to another cluster, tablet keys are
+    // re-wrapped under the transfer key the importing cluster must be given.
+    fn export_tablets(
+        &mut self,
+        table_name: String,
+        transfer_key: Bytes,
+    ) -> ResultHandle<Vec<TabletMetadata>, TabletsRequestStatus>;
+
+    // Requests to import externally provided tablets into the given table, which
+    // must not have been written before. Used to migrate the table from another
+    // cluster or backfill it from offline bulk loads. Tablet keys must be wrapped
+    // under the transfer key the tablets have been exported with.
+    fn import_tablets(
+        &mut self,
+        table_name: String,
+        tablets: Vec<TabletMetadata>,
+        transfer_key: Bytes,
+    ) -> ResultHandle<(), TabletsRequestStatus>;
+
+    // Starts a new read-only transaction that observes tablets as of the given
+    // Tablet Store metadata version. Historical tablets are loaded from the
+    // Tablet Data Storage. Writing tablets fails the transaction.
+    fn start_read_transaction(&mut self, as_of_version: u64) -> Box<dyn TabletTransaction<T>>;
 }
 
 // Provides succint tablet metadata and contents. Enables transaction to
@@ -0,0 +1,152 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec_with_limit};
+use prost::bytes::Bytes;
+use tcp_tablet_store_service::apps::tablet_store::service::{TabletBlobCodec, TabletMetadata};
+
+// Compression level balancing compression ratio and speed.
+const DEFLATE_COMPRESSION_LEVEL: u8 = 6;
+
+// Compresses tablet contents with the given codec and records the codec used in
+// the tablet metadata. Contents are left uncompressed if compression doesn't
+// reduce their size.
+pub fn compress_tablet_contents(
+    tablet_metadata: &mut TabletMetadata,
+    tablet_codec: TabletBlobCodec,
+    tablet_contents: Bytes,
+) -> Bytes {
+    let compressed_contents = match tablet_codec {
+        TabletBlobCodec::Unspecified => None,
+        TabletBlobCodec::Deflate => {
+            Some(compress_to_vec(&tablet_contents, DEFLATE_COMPRESSION_LEVEL))
+        }
+    };
+
+    match compressed_contents {
+        Some(compressed_contents) if compressed_contents.len() < tablet_contents.len() => {
+            tablet_metadata.set_blob_codec(tablet_codec);
+            compressed_contents.into()
+        }
+        _ => {
+            tablet_metadata.set_blob_codec(TabletBlobCodec::Unspecified);
+            tablet_contents
+        }
+    }
+}
+
+// Decompresses tablet contents according to the codec recorded in the tablet
+// metadata. Fails if decompressed contents would exceed the given maximum size,
+// such that crafted tablets can't exhaust the memory.
+pub fn decompress_tablet_contents(
+    tablet_metadata: &TabletMetadata,
+    tablet_contents: Bytes,
+    max_tablet_size: usize,
+) -> Result<Bytes, ()> {
+    match TabletBlobCodec::from_i32(tablet_metadata.blob_codec) {
+        Some(TabletBlobCodec::Unspecified) => Ok(tablet_contents),
+        Some(TabletBlobCodec::Deflate) => {
+            decompress_to_vec_with_limit(&tablet_contents, max_tablet_size)
+                .map(Bytes::from)
+                .map_err(|_| ())
+        }
+        None => Err(()),
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    const MAX_TABLET_SIZE: usize = 4096;
+
+    #[test]
+    fn test_compress_decompress_success() {
+        let tablet_contents = Bytes::from("tablet contents ".repeat(64));
+        let mut tablet_metadata = TabletMetadata::default();
+
+        let compressed_contents = compress_tablet_contents(
+            &mut tablet_metadata,
+            TabletBlobCodec::Deflate,
+            tablet_contents.clone(),
+        );
+
+        assert_eq!(TabletBlobCodec::Deflate, tablet_metadata.blob_codec());
+        assert!(compressed_contents.len() < tablet_contents.len());
+        assert_eq!(
+            Ok(tablet_contents),
+            decompress_tablet_contents(&tablet_metadata, compressed_contents, MAX_TABLET_SIZE)
+        );
+    }
+
+    #[test]
+    fn test_compress_incompressible_contents() {
+        let tablet_contents = Bytes::from_static(b"t1");
+        let mut tablet_metadata = TabletMetadata::default();
+
+        assert_eq!(
+            tablet_contents,
+            compress_tablet_contents(
+                &mut tablet_metadata,
+                TabletBlobCodec::Deflate,
+                tablet_contents.clone()
+            )
+        );
+        assert_eq!(TabletBlobCodec::Unspecified, tablet_metadata.blob_codec());
+    }
+
+    #[test]
+    fn test_decompress_failure() {
+        let tablet_contents = Bytes::from_static(b"not deflated");
+        let mut tablet_metadata = TabletMetadata::default();
+
+        tablet_metadata.set_blob_codec(TabletBlobCodec::Deflate);
+        assert_eq!(
+            Err(()),
+            decompress_tablet_contents(&tablet_metadata, tablet_contents.clone(), MAX_TABLET_SIZE)
+        );
+
+        tablet_metadata.blob_codec = 42;
+        assert_eq!(
+            Err(()),
+            decompress_tablet_contents(&tablet_metadata, tablet_contents, MAX_TABLET_SIZE)
+        );
+    }
+
+    #[test]
+    fn test_decompress_exceeding_max_size() {
+        let tablet_contents = Bytes::from(vec![0; MAX_TABLET_SIZE + 1]);
+        let mut tablet_metadata = TabletMetadata::default();
+
+        let compressed_contents = compress_tablet_contents(
+            &mut tablet_metadata,
+            TabletBlobCodec::Deflate,
+            tablet_contents.clone(),
+        );
+
+        assert_eq!(TabletBlobCodec::Deflate, tablet_metadata.blob_codec());
+        assert_eq!(
+            Err(()),
+            decompress_tablet_contents(
+                &tablet_metadata,
+                compressed_contents.clone(),
+                MAX_TABLET_SIZE
+            )
+        );
+        assert_eq!(
+            Ok(tablet_contents),
+            decompress_tablet_contents(&tablet_metadata, compressed_contents, MAX_TABLET_SIZE + 1)
+        );
+    }
+}
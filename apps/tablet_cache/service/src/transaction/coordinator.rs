@@ -61,7 +61,6 @@ pub trait TabletTransactionCoordinator<T> {
     // Advances internal state machine of the Tablet Transaction Coordinator.
     fn make_progress(
         &mut self,
-        instant: u64,
         metadata_cache: &mut dyn TabletMetadataCache,
         data_cache: &mut dyn TabletDataCache<T>,
     );
@@ -81,6 +80,11 @@ pub trait TabletTransactionCoordinator<T> {
     // Creates new transaction state and returns its id.
     fn create_transaction(&mut self) -> u64;
 
+    // Creates new read-only transaction state that observes tablets as of the given
+    // Tablet Store metadata version and returns its id. Read-only transaction fails
+    // if any of the tablets is written.
+    fn create_read_transaction(&mut self, as_of_version: u64) -> u64;
+
     // Requests to process given queries with the given handler within the context of the
     // transaction with given id. The handler will be called only when all affected by the
     // queries tablets are loaded.
@@ -120,6 +124,10 @@ pub struct DefaultTabletTransactionCoordinator<T> {
     correlations: HashMap<u64, u64>,
     // Stash of outgoing messages waiting to be sent out.
     out_messages: Vec<TabletTransactionCoordinatorOutMessage>,
+    // Previous versions of the tablets replaced by committed transactions.
+    superseded_tablets: Vec<TabletMetadata>,
+    // Versions of the tablets stored by transactions that have not committed.
+    discarded_tablets: Vec<TabletMetadata>,
 }
 
 impl<T> DefaultTabletTransactionCoordinator<T> {
@@ -131,9 +139,29 @@ impl<T> DefaultTabletTransactionCoordinator<T> {
             transactions: HashMap::new(),
             correlations: HashMap::new(),
             out_messages: Vec::new(),
+            superseded_tablets: Vec::new(),
+            discarded_tablets: Vec::new(),
         }
     }
 
+    fn insert_transaction(&mut self, as_of_version: Option<u64>) -> u64 {
+        self.transaction_counter += 1;
+        let transaction_id = self.transaction_counter;
+
+        // Transactions start in preparing state where all local processing will happen.
+        self.transactions.insert(
+            transaction_id,
+            TabletTransactionState::Preparing(PreparingTabletTransactionState::create(
+                transaction_id,
+                as_of_version,
+            )),
+        );
+
+        // Generated unique transaction id is subsequently used for all interactions with
+        // transaction coordinator.
+        transaction_id
+    }
+
     fn transaction_stash_request(&mut self, transaction_id: u64, tablet_ops: Vec<TabletOp>) {
         self.correlation_counter += 1;
 
@@ -148,6 +176,16 @@ impl<T> DefaultTabletTransactionCoordinator<T> {
         self.correlations
             .insert(self.correlation_counter, transaction_id);
     }
+
+    // Remembers versions of the tablets stored by the transaction that will never
+    // be committed, so that Tablet Data Cache can delete them.
+    fn discard_tablets(&mut self, stored_tablets: Vec<(TabletMetadata, TabletMetadata)>) {
+        self.discarded_tablets.extend(
+            stored_tablets
+                .into_iter()
+                .map(|(_, stored_metadata)| stored_metadata),
+        );
+    }
 }
 
 impl<T> TabletTransactionCoordinator<T> for DefaultTabletTransactionCoordinator<T> {
@@ -157,7 +195,6 @@ impl<T> TabletTransactionCoordinator<T> for DefaultTabletTransactionCoordinator<
 
     fn make_progress(
         &mut self,
-        instant: u64,
         metadata_cache: &mut dyn TabletMetadataCache,
         data_cache: &mut dyn TabletDataCache<T>,
     ) {
@@ -165,12 +202,20 @@ impl<T> TabletTransactionCoordinator<T> for DefaultTabletTransactionCoordinator<
             match transaction {
                 // Make progress on each pending transaction.
                 TabletTransactionState::Preparing(transaction_state) => {
-                    transaction_state.make_progress(instant, metadata_cache, data_cache);
+                    transaction_state.make_progress(metadata_cache, data_cache);
                 }
                 // Do nothing for committing or completed transaction.
                 _ => {}
             }
         }
+
+        // Let Tablet Data Cache release tablets of the completed transactions.
+        if !self.superseded_tablets.is_empty() || !self.discarded_tablets.is_empty() {
+            data_cache.release_tablets(
+                mem::take(&mut self.superseded_tablets),
+                mem::take(&mut self.discarded_tablets),
+            );
+        }
     }
 
     fn process_in_message(
@@ -190,6 +235,20 @@ impl<T> TabletTransactionCoordinator<T> for DefaultTabletTransactionCoordinator<
                         if let TabletTransactionState::Committing(transaction_state) = transaction {
                             let (transaction_outcome, metadata_to_update_cache) =
                                 transaction_state.complete(tablet_op_results);
+                            // Committed transaction supersedes previous versions of the tablets,
+                            // otherwise stored versions of the tablets are discarded.
+                            for (base_metadata, stored_metadata) in
+                                transaction_state.take_stored_tablets()
+                            {
+                                match transaction_outcome {
+                                    TabletTransactionOutcome::Succeeded => {
+                                        self.superseded_tablets.push(base_metadata)
+                                    }
+                                    TabletTransactionOutcome::Failed => {
+                                        self.discarded_tablets.push(stored_metadata)
+                                    }
+                                }
+                            }
                             *transaction = TabletTransactionState::Completed(transaction_outcome);
 
                             for (table_name, tablet_metadata, conflict) in metadata_to_update_cache
@@ -208,20 +267,11 @@ impl<T> TabletTransactionCoordinator<T> for DefaultTabletTransactionCoordinator<
     }
 
     fn create_transaction(&mut self) -> u64 {
-        self.transaction_counter += 1;
-        let transaction_id = self.transaction_counter;
-
-        // Transactions start in preparing state where all local processing will happen.
-        self.transactions.insert(
-            transaction_id,
-            TabletTransactionState::Preparing(PreparingTabletTransactionState::create(
-                transaction_id,
-            )),
-        );
+        self.insert_transaction(None)
+    }
 
-        // Generated unique transaction id is subsequently used for all interactions with
-        // transaction coordinator.
-        transaction_id
+    fn create_read_transaction(&mut self, as_of_version: u64) -> u64 {
+        self.insert_transaction(Some(as_of_version))
     }
 
     fn process_transaction(
@@ -271,17 +321,27 @@ impl<T> TabletTransactionCoordinator<T> for DefaultTabletTransactionCoordinator<
                 );
                 // Completion in preparing state goes over results of each process call.
                 match transaction_state.complete() {
+                    // Read-only transaction observes retained past version of the tablets
+                    // and therefore has nothing to commit to the Tablet Store.
+                    PreparingTabletTransactionOutcome::Succeeded(_)
+                        if transaction_state.is_read_only() =>
+                    {
+                        TabletTransactionState::Completed(TabletTransactionOutcome::Succeeded)
+                    }
                     // Try to commit transaction if all processing succeeded.
                     PreparingTabletTransactionOutcome::Succeeded(tablet_ops) => {
+                        let stored_tablets = transaction_state.stored_tablets();
                         // Stash outgoing message to commit transaction to the Tablet Store.
                         self.transaction_stash_request(transaction_id, tablet_ops.clone());
                         // Wait for the outcome.
                         TabletTransactionState::Committing(
-                            CommittingTabletTransactionState::create(tablet_ops),
+                            CommittingTabletTransactionState::create(tablet_ops, stored_tablets),
                         )
                     }
                     // Fail transaction if any of the processing failed.
                     PreparingTabletTransactionOutcome::Failed => {
+                        let stored_tablets = transaction_state.stored_tablets();
+                        self.discard_tablets(stored_tablets);
                         TabletTransactionState::Completed(TabletTransactionOutcome::Failed)
                     }
                 }
@@ -295,8 +355,14 @@ impl<T> TabletTransactionCoordinator<T> for DefaultTabletTransactionCoordinator<
 
     fn abort_transaction(&mut self, transaction_id: u64) {
         // Remove transaction and let Tablet Metadata Cache and Tablet Data Caches
-        // clean themselve eventually up.
-        self.transactions.remove(&transaction_id);
+        // clean themselve eventually up. Tablets stored by the preparing transaction
+        // will never be committed, whereas the outcome of the committing transaction
+        // is unknown and its tablets must be kept.
+        if let Some(TabletTransactionState::Preparing(transaction_state)) =
+            self.transactions.remove(&transaction_id)
+        {
+            self.discard_tablets(transaction_state.stored_tablets());
+        }
     }
 
     fn check_transaction_result(
@@ -354,19 +420,25 @@ struct PreparingTabletTransactionState<T> {
     transaction_id: u64,
     // Holds state of all pending requests to process tablets as part of this transaction.
     process_requests: Vec<TabletProcessState<T>>,
+    // Tablet Store metadata version that read-only transaction observes tablets as of.
+    as_of_version: Option<u64>,
 }
 
 impl<T> PreparingTabletTransactionState<T> {
-    fn create(transaction_id: u64) -> Self {
+    fn create(transaction_id: u64, as_of_version: Option<u64>) -> Self {
         Self {
             transaction_id,
             process_requests: Vec::new(),
+            as_of_version,
         }
     }
 
+    fn is_read_only(&self) -> bool {
+        self.as_of_version.is_some()
+    }
+
     fn make_progress(
         &mut self,
-        _instant: u64,
         metadata_cache: &mut dyn TabletMetadataCache,
         data_cache: &mut dyn TabletDataCache<T>,
     ) {
@@ -377,7 +449,12 @@ impl<T> PreparingTabletTransactionState<T> {
                 TabletProcessStatus::Pending(process_queries) => {
                     // Initiate affected tablets resolution and switch to resolve result resolving state
                     // waiting for completion.
-                    let resolve_result = metadata_cache.resolve_tablets(process_queries);
+                    let resolve_result = match self.as_of_version {
+                        Some(as_of_version) => {
+                            metadata_cache.resolve_tablets_as_of(process_queries, as_of_version)
+                        }
+                        None => metadata_cache.resolve_tablets(process_queries),
+                    };
                     Some(TabletProcessStatus::Resolving(resolve_result))
                 }
                 TabletProcessStatus::Resolving(resolve_result) => {
@@ -447,10 +524,18 @@ impl<T> PreparingTabletTransactionState<T> {
                                     }
                                 }
 
-                                // Initiate store for the updated tablets through Tablet Data Cache and switch to storing
-                                // state waiting for completion.
-                                let store_result = data_cache.store_tablets(tablet_writes);
-                                Some(TabletProcessStatus::Storing(store_result))
+                                if self.as_of_version.is_some() && !tablet_writes.is_empty() {
+                                    // Read-only transaction must not write tablets, switch to failed state.
+                                    Some(TabletProcessStatus::Failed)
+                                } else {
+                                    // Initiate store for the updated tablets through Tablet Data Cache and switch to
+                                    // storing state waiting for completion.
+                                    let store_result = data_cache.store_tablets(tablet_writes);
+                                    for tablet_state in process_state.tablets.values_mut() {
+                                        tablet_state.tablet_stored();
+                                    }
+                                    Some(TabletProcessStatus::Storing(store_result))
+                                }
                             }
                             Err(_) => {
                                 // Loading failed, switch to failed state.
@@ -520,16 +605,45 @@ impl<T> PreparingTabletTransactionState<T> {
         // Succeed transaction preparation with corresponding Tablet Store ops collected.
         PreparingTabletTransactionOutcome::Succeeded(tablet_ops)
     }
+
+    // Gets base and stored metadata of the tablets that have been stored in the
+    // Tablet Data Storage as part of this transaction.
+    fn stored_tablets(&self) -> Vec<(TabletMetadata, TabletMetadata)> {
+        let mut stored_tablets = Vec::new();
+        for process_state in &self.process_requests {
+            for tablet_state in process_state.tablets.values() {
+                if tablet_state.stored {
+                    stored_tablets.push((
+                        tablet_state.base_metadata.clone(),
+                        tablet_state.tablet.get_metadata().clone(),
+                    ));
+                }
+            }
+        }
+        stored_tablets
+    }
 }
 
 #[derive(Default, Debug, Clone)]
 struct CommittingTabletTransactionState {
     tablet_ops: Vec<TabletOp>,
+    // Base and stored metadata of the tablets stored by the transaction.
+    stored_tablets: Vec<(TabletMetadata, TabletMetadata)>,
 }
 
 impl CommittingTabletTransactionState {
-    fn create(tablet_ops: Vec<TabletOp>) -> Self {
-        Self { tablet_ops }
+    fn create(
+        tablet_ops: Vec<TabletOp>,
+        stored_tablets: Vec<(TabletMetadata, TabletMetadata)>,
+    ) -> Self {
+        Self {
+            tablet_ops,
+            stored_tablets,
+        }
+    }
+
+    fn take_stored_tablets(&mut self) -> Vec<(TabletMetadata, TabletMetadata)> {
+        mem::take(&mut self.stored_tablets)
     }
 
     fn complete(
@@ -609,13 +723,19 @@ struct TabletState<T> {
     query: TableQuery,
     // Tablet metadata and data, along with tracking if this tablet was updated.
     tablet: Tablet<T>,
+    // Metadata of the tablet version the transaction has started from.
+    base_metadata: TabletMetadata,
+    // Indicates if the updated tablet has been stored in the Tablet Data Storage.
+    stored: bool,
 }
 
 impl<T> TabletState<T> {
     fn create(query: TableQuery, metadata: TabletMetadata) -> Self {
         Self {
             query,
-            tablet: Tablet::<T>::create(metadata),
+            tablet: Tablet::<T>::create(metadata.clone()),
+            base_metadata: metadata,
+            stored: false,
         }
     }
 
@@ -641,6 +761,13 @@ impl<T> TabletState<T> {
         }
     }
 
+    // Marks updated tablet as stored once its storing has been initiated.
+    fn tablet_stored(&mut self) {
+        if self.tablet.is_dirty() {
+            self.stored = true;
+        }
+    }
+
     // Gets tablet op that must be executed as part of the transaction.
     fn get_tablet_op(&self) -> TabletOp {
         let tablet_metadata = self.tablet.get_metadata().clone();
@@ -877,6 +1004,20 @@ mod tests {
             self
         }
 
+        fn expect_release_tablets(
+            &mut self,
+            superseded_metadata: Vec<TabletMetadata>,
+            discarded_metadata: Vec<TabletMetadata>,
+        ) -> &mut Self {
+            self.mock_tablet_data_cache
+                .expect_release_tablets()
+                .times(1)
+                .with(eq(superseded_metadata), eq(discarded_metadata))
+                .return_const(());
+
+            self
+        }
+
         fn take(self) -> MockTabletDataCache<Bytes> {
             self.mock_tablet_data_cache
         }
@@ -907,14 +1048,10 @@ mod tests {
 
         fn execute_step(
             &mut self,
-            instant: u64,
             in_message: Option<TabletTransactionCoordinatorInMessage>,
         ) -> Vec<TabletTransactionCoordinatorOutMessage> {
-            self.transaction_coordinator.make_progress(
-                instant,
-                &mut self.metadata_cache,
-                &mut self.data_cache,
-            );
+            self.transaction_coordinator
+                .make_progress(&mut self.metadata_cache, &mut self.data_cache);
 
             let out_messages = self.transaction_coordinator.take_out_messages();
 
@@ -989,23 +1126,23 @@ mod tests {
             .get_mut()
             .has_transaction_pending_process(transaction_id_1));
 
-        assert!(transaction_loop.execute_step(1, None).is_empty());
+        assert!(transaction_loop.execute_step(None).is_empty());
 
         resolve_result_source_1
             .set_result(vec![(table_query_1.clone(), tablet_metadata_1_v_1.clone())]);
 
-        assert!(transaction_loop.execute_step(2, None).is_empty());
+        assert!(transaction_loop.execute_step(None).is_empty());
 
         load_result_source_1.set_result(vec![(
             tablet_metadata_1_v_1.clone(),
             TabletData::create(tablet_data_1_v_1.clone()),
         )]);
 
-        assert!(transaction_loop.execute_step(3, None).is_empty());
+        assert!(transaction_loop.execute_step(None).is_empty());
 
         store_result_source_1.set_result(());
 
-        assert!(transaction_loop.execute_step(4, None).is_empty());
+        assert!(transaction_loop.execute_step(None).is_empty());
 
         assert!(!transaction_loop
             .get_mut()
@@ -1030,19 +1167,16 @@ mod tests {
                     )]
                 )
             ],
-            transaction_loop.execute_step(
-                5,
-                Some(
-                    TabletTransactionCoordinatorInMessage::ExecuteTabletOpsResponse(
-                        CORRELATION_ID_1,
-                        vec![create_update_tablet_result(
-                            TABLE_NAME.to_string(),
-                            TabletOpStatus::Succeeded,
-                            None
-                        )]
-                    )
+            transaction_loop.execute_step(Some(
+                TabletTransactionCoordinatorInMessage::ExecuteTabletOpsResponse(
+                    CORRELATION_ID_1,
+                    vec![create_update_tablet_result(
+                        TABLE_NAME.to_string(),
+                        TabletOpStatus::Succeeded,
+                        None
+                    )]
                 )
-            )
+            ))
         );
 
         assert_eq!(
@@ -1052,4 +1186,79 @@ mod tests {
                 .check_transaction_result(transaction_id_1)
         );
     }
+
+    #[test]
+    fn test_abort_releases_stored_tablets() {
+        let transaction_coordinator = create_transaction_coordinator();
+
+        let table_query_1 = create_table_query(TABLE_QUERY_1, vec![KEY_HASH_1]);
+        let tablet_metadata_1_v_1 = create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1);
+        let tablet_metadata_1_v_2 = create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_2);
+        let tablet_data_1_v_1 = Bytes::from(TABLET_DATA_VERSION_1);
+        let tablet_data_1_v_2 = Bytes::from(TABLET_DATA_VERSION_2);
+        let tablet_data_1_v_2_copy = tablet_data_1_v_2.clone();
+
+        let mut metadata_cache_builder = TabletMetadataCacheBuilder::new();
+        let (resolve_result_handle_1, mut resolve_result_source_1) =
+            create_resolve_source_and_handle();
+        metadata_cache_builder
+            .expect_resolve_tablets(vec![table_query_1.clone()], resolve_result_handle_1);
+        let metadata_cache = metadata_cache_builder.take();
+
+        let mut data_cache_builder = TabletDataCacheBuilder::new();
+        let (load_result_handle_1, mut load_result_source_1) = create_load_source_and_handle();
+        data_cache_builder
+            .expect_load_tablets(vec![tablet_metadata_1_v_1.clone()], load_result_handle_1);
+        let (store_result_handle_1, mut store_result_source_1) = create_store_source_and_handle();
+        data_cache_builder
+            .expect_store_tablets(
+                vec![(
+                    tablet_metadata_1_v_1.clone(),
+                    tablet_data_1_v_2.clone(),
+                    tablet_metadata_1_v_2.clone(),
+                )],
+                store_result_handle_1,
+            )
+            .expect_release_tablets(vec![], vec![tablet_metadata_1_v_2.clone()]);
+        let data_cache = data_cache_builder.take();
+
+        let mut transaction_loop =
+            TranactionCoordinatorLoop::create(transaction_coordinator, metadata_cache, data_cache);
+
+        let transaction_id_1 = transaction_loop.get_mut().create_transaction();
+
+        transaction_loop.get_mut().process_transaction(
+            transaction_id_1,
+            vec![table_query_1.clone()],
+            Box::new(move |_, mut tablets| {
+                let (_, tablet) = tablets.pop().unwrap();
+                tablet.set_contents(tablet_data_1_v_2_copy.clone());
+            }),
+        );
+
+        assert!(transaction_loop.execute_step(None).is_empty());
+
+        resolve_result_source_1
+            .set_result(vec![(table_query_1.clone(), tablet_metadata_1_v_1.clone())]);
+
+        assert!(transaction_loop.execute_step(None).is_empty());
+
+        load_result_source_1.set_result(vec![(
+            tablet_metadata_1_v_1.clone(),
+            TabletData::create(tablet_data_1_v_1.clone()),
+        )]);
+
+        assert!(transaction_loop.execute_step(None).is_empty());
+
+        store_result_source_1.set_result(());
+
+        assert!(transaction_loop.execute_step(None).is_empty());
+
+        // Stored version of the tablet will never be committed and must be released.
+        transaction_loop
+            .get_mut()
+            .abort_transaction(transaction_id_1);
+
+        assert!(transaction_loop.execute_step(None).is_empty());
+    }
 }
@@ -12,43 +12,79 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use core::{cell::RefCell, mem, ops::Deref};
+use core::{
+    cell::RefCell,
+    hash::{BuildHasher, Hash},
+    marker::PhantomData,
+    mem,
+    ops::Deref,
+};
 
+#[cfg(debug_assertions)]
+use alloc::collections::BTreeSet;
 use alloc::{
     boxed::Box,
+    collections::BTreeMap,
     format,
     rc::Rc,
     string::{String, ToString},
+    vec,
     vec::Vec,
 };
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use hashbrown::{
-    hash_map::Entry::{Occupied, Vacant},
+    hash_map::{
+        DefaultHashBuilder,
+        Entry::{Occupied, Vacant},
+    },
     HashMap, HashSet,
 };
-use prost::bytes::Bytes;
-use sha2::{Digest, Sha256};
-use slog::Logger;
-use tcp_runtime::logger::log::create_logger;
-use tcp_tablet_store_service::apps::tablet_store::service::TabletMetadata;
+use prost::{bytes::Bytes, Message};
+use slog::{warn, Logger};
+use tcp_runtime::{clock::Clock, logger::log::create_logger};
+use tcp_tablet_store_service::apps::tablet_store::service::{TabletBlobCodec, TabletMetadata};
 
 use crate::apps::tablet_cache::service::{
-    LoadTabletRequest, LoadTabletResponse, StoreTabletRequest, StoreTabletResponse,
-    TabletDataCacheConfig, TabletDataStorageStatus,
+    DeleteTabletRequest, DeleteTabletResponse, LoadTabletRequest, LoadTabletResponse,
+    StoreTabletRequest, StoreTabletResponse, TabletDataCacheConfig, TabletDataStorageStatus,
 };
 
+use super::codec::{compress_tablet_contents, decompress_tablet_contents};
+use super::envelope::{open_tablet_blob, seal_tablet_contents};
 use super::result::{create_eventual_result, create_result_from_error, ResultHandle, ResultSource};
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum TabletDataCacheInMessage {
     LoadResponse(u64, LoadTabletResponse, Bytes),
     StoreResponse(u64, StoreTabletResponse),
+    DeleteResponse(u64, DeleteTabletResponse),
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum TabletDataCacheOutMessage {
     LoadRequest(u64, LoadTabletRequest),
     StoreRequest(u64, StoreTabletRequest, Bytes),
+    DeleteRequest(u64, DeleteTabletRequest),
+}
+
+// Statistics of the tablet data cache used to tune its capacity.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TabletDataCacheStats {
+    // Number of requested tablets found in the cache, including tablets that
+    // are still being loaded or stored.
+    pub hits: u64,
+    // Number of requested tablets that had to be loaded from Tablet Data Storage.
+    pub misses: u64,
+    // Number of tablets evicted by the tablet cache policy.
+    pub evictions: u64,
+    // Size of the tablets maintained by the cache, including pending ones.
+    pub cached_bytes: u64,
+    // Number of tablets being loaded.
+    pub pending_loads: u64,
+    // Number of tablets being stored.
+    pub pending_stores: u64,
+    // Average duration of the completed tablet loads as measured by the clock.
+    pub average_load_latency: u64,
 }
 
 // Maintains cache of recently used tablet data. Tablet data cache follows soft capacity
@@ -62,8 +98,12 @@ pub trait TabletDataCache<T> {
     // Initializes tablet data cache.
     fn init(&mut self, logger: Logger, config: TabletDataCacheConfig);
 
+    // Sets the key the tablet keys are wrapped under. The key is derived from the
+    // cluster secret and must be set before any tablets are loaded or stored.
+    fn set_key_wrapping_key(&mut self, key_wrapping_key: Bytes);
+
     // Advances internal state machine of the tablet data cache.
-    fn make_progress(&mut self, instant: u64);
+    fn make_progress(&mut self);
 
     // Requests to load and cache tablet data described by provided metadata. Returned result
     // handle must be checked for the operation completion. The operation is completed only when
@@ -84,11 +124,26 @@ pub trait TabletDataCache<T> {
         data: Vec<(&mut TabletMetadata, T)>,
     ) -> ResultHandle<(), TabletDataStorageStatus>;
 
-    // Processes incoming messages. Message may contain load or store tablet responses.
+    // Releases tablet data of the completed transaction. Superseded metadata describes
+    // previous versions of the tablets that have been replaced by the committed transaction.
+    // Discarded metadata describes versions of the tablets stored by the transaction that
+    // has been aborted or failed to commit. Tablet data stored by this cache that is
+    // discarded, or superseded if so configured, is evicted from the cache and deleted from
+    // Tablet Data Storage. Tablet data stored elsewhere is left to its creator.
+    fn release_tablets(
+        &mut self,
+        superseded_metadata: Vec<TabletMetadata>,
+        discarded_metadata: Vec<TabletMetadata>,
+    );
+
+    // Processes incoming messages. Message may contain load, store or delete tablet responses.
     fn process_in_message(&mut self, in_message: TabletDataCacheInMessage);
 
-    // Takes outgoing messages. Message may contain load or store tablet requests.
+    // Takes outgoing messages. Message may contain load, store or delete tablet requests.
     fn take_out_messages(&mut self) -> Vec<TabletDataCacheOutMessage>;
+
+    // Gets current statistics of the tablet data cache.
+    fn get_stats(&self) -> TabletDataCacheStats;
 }
 
 // Provides a readonly shared access to the strongly typed tablet data.
@@ -102,6 +157,12 @@ impl<T> TabletData<T> {
     pub fn create(t: T) -> Self {
         Self { data: Rc::new(t) }
     }
+
+    // Gets the number of outstanding references to the tablet data, including
+    // the one held by the tablet data cache itself.
+    pub fn ref_count(&self) -> usize {
+        Rc::strong_count(&self.data)
+    }
 }
 
 impl<T> Deref for TabletData<T> {
@@ -149,6 +210,39 @@ impl TabletDataSerializer<Bytes> for BytesTabletDataSerializer {
     }
 }
 
+// Serializer for the tablet data represented by a protobuf message. A single message
+// type is used for all tables, for example a message with a oneof representing
+// specific tables.
+pub struct ProstTabletDataSerializer<M> {
+    phantom: PhantomData<M>,
+}
+
+impl<M> ProstTabletDataSerializer<M> {
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<M: Message + Default> TabletDataSerializer<M> for ProstTabletDataSerializer<M> {
+    fn serialize(&self, tablet_value: &M) -> Result<Bytes, ()> {
+        Ok(tablet_value.encode_to_vec().into())
+    }
+
+    fn deserialize(&self, _table_name: &String, tablet_data: Bytes) -> Result<M, ()> {
+        M::decode(tablet_data).map_err(|_| ())
+    }
+
+    fn get_size(&self, tablet_value: &M) -> usize {
+        tablet_value.encoded_len()
+    }
+}
+
+// Maximum size of the decompressed tablet contents used if the configuration
+// doesn't set one.
+const DEFAULT_MAX_TABLET_SIZE: usize = 64 << 20;
+
 // Formats blob uri using a combination of tablet id, tablet version and blob hash to construct
 // a unique tablet blob uri.
 fn format_blob_uri(tablet_id: u32, tablet_version: u32, blob_hash: Bytes) -> String {
@@ -234,6 +328,9 @@ fn format_blob_uri(tablet_id: u32, tablet_version: u32, blob_hash: Bytes) -> Str
 //   * Consult with the tablet data cache policy if any of the cache entries must be
 // evicted.
 //   * Evict indicated cache entries.
+//   * Evict cache entries for the released tablets that must be deleted and request
+// deletion from storage. Entries that are still loading or storing are deleted once
+// the operation completes.
 //
 // Type parameter T represents a variant type for the deserialized tablet data.
 pub struct DefaultTabletDataCache<T> {
@@ -241,35 +338,74 @@ pub struct DefaultTabletDataCache<T> {
     correlation_counter: u64,
     batch_counter: u64,
     config: TabletDataCacheConfig,
+    // Key the tablet keys are wrapped under, tablets can't be loaded or stored
+    // until it is set.
+    key_wrapping_key: Bytes,
     tablet_serializer: Box<dyn TabletDataSerializer<T>>,
     tablet_cache_policy: Box<dyn TabletDataCachePolicy<T>>,
+    clock: Rc<dyn Clock>,
     tablet_cache_entries: HashMap<TabletCacheKey, TabletCacheEntry<T>>,
     tablet_batches: HashMap<u64, TabletBatch<T>>,
     tablet_operations: HashMap<u64, TabletCacheKey>,
+    // Tablets stored by this cache that may need to be deleted once released.
+    stored_tablets: HashSet<TabletCacheKey>,
+    // Released tablets waiting to be deleted from storage.
+    pending_deletes: Vec<TabletCacheKey>,
+    // Maps correlation id of the delete request to the deleted tablet.
+    tablet_deletes: HashMap<u64, TabletCacheKey>,
     out_messages: Vec<TabletDataCacheOutMessage>,
+    // Counters of the cache statistics, gauges are computed when requested.
+    stats: TabletDataCacheStats,
+    // Maps correlation id of the load request to the instant it has been sent.
+    load_instants: HashMap<u64, u64>,
+    completed_load_count: u64,
+    total_load_latency: u64,
+    #[cfg(debug_assertions)]
+    leak_tracker: TabletDataLeakTracker,
 }
 
 impl<T> DefaultTabletDataCache<T> {
     // Creates new tablet data cache with given capacity. Configured capacity is considered
     // a soft limit. Tablet data cache may grow larger temporarily than requested capacity.
+    // Provided clock is used to measure when tablets have been accessed.
     pub fn create(
         correlation_counter: u64,
         tablet_serializer: Box<dyn TabletDataSerializer<T>>,
         tablet_cache_policy: Box<dyn TabletDataCachePolicy<T>>,
+        clock: Rc<dyn Clock>,
     ) -> Self {
         Self {
             logger: create_logger(),
             correlation_counter,
             batch_counter: 0,
             config: TabletDataCacheConfig::default(),
+            key_wrapping_key: Bytes::new(),
             tablet_serializer,
             tablet_cache_policy,
+            clock,
             tablet_cache_entries: HashMap::new(),
             tablet_batches: HashMap::new(),
             tablet_operations: HashMap::new(),
+            stored_tablets: HashSet::new(),
+            pending_deletes: Vec::new(),
+            tablet_deletes: HashMap::new(),
             out_messages: Vec::new(),
+            stats: TabletDataCacheStats::default(),
+            load_instants: HashMap::new(),
+            completed_load_count: 0,
+            total_load_latency: 0,
+            #[cfg(debug_assertions)]
+            leak_tracker: TabletDataLeakTracker::default(),
         }
     }
+
+    // Takes reports about tablet cache entries that have been evicted while
+    // their tablet data was still referenced outside of the cache. Only
+    // available in debug builds.
+    #[cfg(debug_assertions)]
+    pub fn take_leak_reports(&mut self) -> Vec<TabletDataLeakReport> {
+        self.leak_tracker.take_reports()
+    }
 }
 
 impl<T> DefaultTabletDataCache<T> {
@@ -284,7 +420,53 @@ impl<T> DefaultTabletDataCache<T> {
         for tablet_cache_key in tablet_cache_keys {
             if let Some(tablet_cache_entry) = self.tablet_cache_entries.get_mut(tablet_cache_key) {
                 tablet_batch.add_pending_cache_entry(tablet_cache_entry);
+
+                #[cfg(debug_assertions)]
+                if tablet_cache_entry.get_data_ref_count().is_some() {
+                    self.leak_tracker
+                        .record_holder(tablet_cache_key, tablet_batch.batch_id);
+                }
+            }
+        }
+    }
+
+    fn release_tablet(&mut self, tablet_metadata: &TabletMetadata, delete: bool) {
+        // Only tablets stored by this cache are deleted, each of them at most once.
+        let tablet_cache_key = TabletCacheKey::from(tablet_metadata);
+        if self.stored_tablets.remove(&tablet_cache_key) && delete {
+            self.pending_deletes.push(tablet_cache_key);
+        }
+    }
+
+    fn delete_released_tablets(&mut self) {
+        for tablet_cache_key in mem::take(&mut self.pending_deletes) {
+            // Wait until loading or storing completes to not leave orphaned data behind.
+            let is_pending = self
+                .tablet_cache_entries
+                .get(&tablet_cache_key)
+                .is_some_and(|tablet_cache_entry| tablet_cache_entry.is_pending());
+            if is_pending {
+                self.pending_deletes.push(tablet_cache_key);
+                continue;
             }
+
+            // Released tablet data may still be referenced by transactions that
+            // are bound to fail, hence it is not reported as leaked.
+            self.tablet_cache_entries.remove(&tablet_cache_key);
+            self.tablet_cache_policy.on_remove(&tablet_cache_key);
+            #[cfg(debug_assertions)]
+            self.leak_tracker.release_holders(&tablet_cache_key);
+
+            self.correlation_counter += 1;
+            self.out_messages
+                .push(TabletDataCacheOutMessage::DeleteRequest(
+                    self.correlation_counter,
+                    DeleteTabletRequest {
+                        blob_uri: tablet_cache_key.uri.clone(),
+                    },
+                ));
+            self.tablet_deletes
+                .insert(self.correlation_counter, tablet_cache_key);
         }
     }
 
@@ -294,10 +476,18 @@ impl<T> DefaultTabletDataCache<T> {
         tablet_value: &T,
     ) -> Result<Bytes, ()> {
         let tablet_contents = self.tablet_serializer.serialize(tablet_value)?;
-        // Create new version of the tablet metadata.
+        let tablet_codec = if self.config.compress_tablets {
+            TabletBlobCodec::Deflate
+        } else {
+            TabletBlobCodec::Unspecified
+        };
+        let tablet_contents =
+            compress_tablet_contents(tablet_metadata, tablet_codec, tablet_contents);
+        // Create new version of the tablet metadata and encrypt tablet contents
+        // bound to it.
         tablet_metadata.tablet_version += 1;
-        tablet_metadata.blob_size = tablet_contents.len() as u32;
-        tablet_metadata.blob_hash = Sha256::digest(&tablet_contents).to_vec().into();
+        let tablet_blob =
+            seal_tablet_contents(&self.key_wrapping_key, tablet_metadata, &tablet_contents)?;
         // To ensure the tablet uri is unique use composite name based on tablet
         // id, version and content hash.
         tablet_metadata.blob_uri = format_blob_uri(
@@ -306,14 +496,26 @@ impl<T> DefaultTabletDataCache<T> {
             tablet_metadata.blob_hash.clone(),
         );
 
-        Ok(tablet_contents)
+        Ok(tablet_blob)
+    }
+
+    fn max_tablet_size(&self) -> usize {
+        match self.config.max_tablet_size {
+            0 => DEFAULT_MAX_TABLET_SIZE,
+            max_tablet_size => max_tablet_size.try_into().unwrap_or(usize::MAX),
+        }
     }
 
     fn prepare_tablet_read(
         &self,
         tablet_metadata: &TabletMetadata,
-        tablet_contents: Bytes,
+        tablet_blob: Bytes,
     ) -> Result<T, ()> {
+        // Loaded tablet blob is verified and decrypted before deserialization.
+        let tablet_contents =
+            open_tablet_blob(&self.key_wrapping_key, tablet_metadata, tablet_blob)?;
+        let tablet_contents =
+            decompress_tablet_contents(tablet_metadata, tablet_contents, self.max_tablet_size())?;
         // Currently table name is not propagated to the serializer, will be
         // added later.
         let tablet_value = self
@@ -330,7 +532,11 @@ impl<T> TabletDataCache<T> for DefaultTabletDataCache<T> {
         self.config = config;
     }
 
-    fn make_progress(&mut self, instant: u64) {
+    fn set_key_wrapping_key(&mut self, key_wrapping_key: Bytes) {
+        self.key_wrapping_key = key_wrapping_key;
+    }
+
+    fn make_progress(&mut self) {
         let mut failed_tablet_cache_entries = Vec::new();
 
         // For every tablet cache entry that has become ready, notify corresponding
@@ -341,6 +547,17 @@ impl<T> TabletDataCache<T> for DefaultTabletDataCache<T> {
                     .get_mut(&tablet_batch_id)
                     .unwrap()
                     .resolve_pending_cache_entry(tablet_cache_entry);
+
+                #[cfg(debug_assertions)]
+                self.leak_tracker
+                    .record_holder(tablet_cache_key, tablet_batch_id);
+            }
+
+            // Forget holders of the tablet data once all references except the
+            // one owned by the cache have been returned.
+            #[cfg(debug_assertions)]
+            if tablet_cache_entry.get_data_ref_count() == Some(1) {
+                self.leak_tracker.release_holders(tablet_cache_key);
             }
 
             if let TabletCacheEntryState::Error = tablet_cache_entry.get_state() {
@@ -356,43 +573,75 @@ impl<T> TabletDataCache<T> for DefaultTabletDataCache<T> {
         // tablet batches have already been notified.
         for failed_tablet_cache_key in failed_tablet_cache_entries {
             self.tablet_cache_entries.remove(&failed_tablet_cache_key);
+            self.tablet_cache_policy.on_remove(&failed_tablet_cache_key);
         }
 
         // Consult with tablet cache policy and evict entries from tablet cache.
-        for evicted_tablet_cache_key in self.tablet_cache_policy.evict(
-            instant,
+        for evicted_tablet_cache_key in self.tablet_cache_policy.select_evictions(
             self.config.tablet_cache_capacity,
             &self.tablet_cache_entries,
         ) {
-            self.tablet_cache_entries.remove(&evicted_tablet_cache_key);
+            self.tablet_cache_policy
+                .on_remove(&evicted_tablet_cache_key);
+            if let Some(evicted_tablet_cache_entry) =
+                self.tablet_cache_entries.remove(&evicted_tablet_cache_key)
+            {
+                self.stats.evictions += 1;
+
+                // Evicted tablet data that is still referenced elsewhere remains pinned
+                // in memory, report holders that never released their references.
+                #[cfg(debug_assertions)]
+                self.leak_tracker.check_evicted(
+                    &self.logger,
+                    &evicted_tablet_cache_key,
+                    &evicted_tablet_cache_entry,
+                );
+            }
         }
+
+        // Delete released tablets that are no longer needed.
+        self.delete_released_tablets();
     }
 
     fn load_tablets(
         &mut self,
         tablets_metadata: &Vec<TabletMetadata>,
     ) -> ResultHandle<Vec<(TabletMetadata, TabletData<T>)>, TabletDataStorageStatus> {
+        let instant = self.clock.instant();
         let mut tablet_cache_keys = Vec::with_capacity(tablets_metadata.len());
         for tablet_metadata in tablets_metadata {
             let tablet_cache_key = TabletCacheKey::from(tablet_metadata);
             tablet_cache_keys.push(tablet_cache_key.clone());
 
-            if let Vacant(map_entry) = self.tablet_cache_entries.entry(tablet_cache_key.clone()) {
-                // Create new tablet cache entry and corresponding storage request if the tablet
-                // is not being maintained by the cache.
-                self.correlation_counter += 1;
+            match self.tablet_cache_entries.entry(tablet_cache_key.clone()) {
+                Vacant(map_entry) => {
+                    // Create new tablet cache entry and corresponding storage request if the
+                    // tablet is not being maintained by the cache.
+                    self.correlation_counter += 1;
 
-                self.tablet_operations
-                    .insert(self.correlation_counter, tablet_cache_key);
+                    self.tablet_operations
+                        .insert(self.correlation_counter, tablet_cache_key);
+                    self.load_instants.insert(self.correlation_counter, instant);
+                    self.stats.misses += 1;
 
-                let (tablet_cache_entry, load_tablet_request) =
-                    TabletCacheEntry::<T>::with_load_state(
-                        self.correlation_counter,
-                        tablet_metadata,
-                    );
+                    let (tablet_cache_entry, load_tablet_request) =
+                        TabletCacheEntry::<T>::with_load_state(
+                            self.correlation_counter,
+                            tablet_metadata,
+                            instant,
+                        );
 
-                self.out_messages.push(load_tablet_request);
-                map_entry.insert(tablet_cache_entry);
+                    self.out_messages.push(load_tablet_request);
+                    self.tablet_cache_policy
+                        .on_insert(map_entry.key(), &tablet_cache_entry);
+                    map_entry.insert(tablet_cache_entry);
+                }
+                Occupied(mut map_entry) => {
+                    self.stats.hits += 1;
+                    map_entry.get_mut().record_access(instant);
+                    self.tablet_cache_policy
+                        .on_access(map_entry.key(), map_entry.get());
+                }
             }
         }
 
@@ -429,6 +678,7 @@ impl<T> TabletDataCache<T> for DefaultTabletDataCache<T> {
             }
         }
 
+        let instant = self.clock.instant();
         let mut tablet_cache_keys = Vec::with_capacity(tablets_data.len());
         for ((tablet_metadata, tablet_value), tablet_contents) in
             tablets_data.into_iter().zip(tablets_contents.into_iter())
@@ -443,7 +693,8 @@ impl<T> TabletDataCache<T> for DefaultTabletDataCache<T> {
                     self.correlation_counter += 1;
 
                     self.tablet_operations
-                        .insert(self.correlation_counter, tablet_cache_key);
+                        .insert(self.correlation_counter, tablet_cache_key.clone());
+                    self.stored_tablets.insert(tablet_cache_key);
 
                     let (tablet_cache_entry, store_tablet_request) =
                         TabletCacheEntry::<T>::with_store_state(
@@ -451,9 +702,12 @@ impl<T> TabletDataCache<T> for DefaultTabletDataCache<T> {
                             tablet_metadata,
                             tablet_value,
                             tablet_contents,
+                            instant,
                         );
 
                     self.out_messages.push(store_tablet_request);
+                    self.tablet_cache_policy
+                        .on_insert(map_entry.key(), &tablet_cache_entry);
                     map_entry.insert(tablet_cache_entry);
                 }
                 Occupied(map_entry) => {
@@ -480,6 +734,20 @@ impl<T> TabletDataCache<T> for DefaultTabletDataCache<T> {
         result_handle
     }
 
+    fn release_tablets(
+        &mut self,
+        superseded_metadata: Vec<TabletMetadata>,
+        discarded_metadata: Vec<TabletMetadata>,
+    ) {
+        let delete_superseded = self.config.delete_superseded_tablets;
+        for tablet_metadata in &superseded_metadata {
+            self.release_tablet(tablet_metadata, delete_superseded);
+        }
+        for tablet_metadata in &discarded_metadata {
+            self.release_tablet(tablet_metadata, true);
+        }
+    }
+
     fn process_in_message(&mut self, in_message: TabletDataCacheInMessage) {
         match in_message {
             TabletDataCacheInMessage::LoadResponse(
@@ -488,6 +756,12 @@ impl<T> TabletDataCache<T> for DefaultTabletDataCache<T> {
                 tablet_contents,
             ) => {
                 if let Some(tablet_cache_key) = self.tablet_operations.remove(&correlation_id) {
+                    if let Some(load_instant) = self.load_instants.remove(&correlation_id) {
+                        self.completed_load_count += 1;
+                        self.total_load_latency +=
+                            self.clock.instant().saturating_sub(load_instant);
+                    }
+
                     // Prepare loaded raw tablet contents to enter the cache in deserialized form.
                     let tablet_value = self.prepare_tablet_read(
                         self.tablet_cache_entries
@@ -499,20 +773,40 @@ impl<T> TabletDataCache<T> for DefaultTabletDataCache<T> {
 
                     // Delegate tablet loading response to the corresponding tablet cache entry. Note
                     // that notifications to the tablet batches happens later when making progress.
-                    self.tablet_cache_entries
+                    let tablet_cache_entry = self
+                        .tablet_cache_entries
                         .get_mut(&tablet_cache_key)
-                        .unwrap()
-                        .process_load_response(load_tablet_response, tablet_value);
+                        .unwrap();
+                    tablet_cache_entry.process_load_response(load_tablet_response, tablet_value);
+                    tablet_cache_entry.record_access(self.clock.instant());
+                    self.tablet_cache_policy
+                        .on_access(&tablet_cache_key, tablet_cache_entry);
                 }
             }
             TabletDataCacheInMessage::StoreResponse(correlation_id, store_tablet_response) => {
                 if let Some(tablet_cache_key) = self.tablet_operations.remove(&correlation_id) {
                     // Delegate tablet storing response to the corresponding tablet cache entry. Note
                     // that notifications to the tablet batches happens later when making progress.
-                    self.tablet_cache_entries
+                    let tablet_cache_entry = self
+                        .tablet_cache_entries
                         .get_mut(&tablet_cache_key)
-                        .unwrap()
-                        .process_store_response(store_tablet_response);
+                        .unwrap();
+                    tablet_cache_entry.process_store_response(store_tablet_response);
+                    tablet_cache_entry.record_access(self.clock.instant());
+                    self.tablet_cache_policy
+                        .on_access(&tablet_cache_key, tablet_cache_entry);
+                }
+            }
+            TabletDataCacheInMessage::DeleteResponse(correlation_id, delete_tablet_response) => {
+                if let Some(tablet_cache_key) = self.tablet_deletes.remove(&correlation_id) {
+                    // Failure to delete only leaves orphaned data in storage that can be
+                    // reclaimed by the storage maintenance.
+                    if delete_tablet_response.status != TabletDataStorageStatus::Succeeded as i32 {
+                        warn!(
+                            self.logger,
+                            "Failed to delete tablet data: {}", tablet_cache_key.uri
+                        );
+                    }
                 }
             }
         }
@@ -521,20 +815,55 @@ impl<T> TabletDataCache<T> for DefaultTabletDataCache<T> {
     fn take_out_messages(&mut self) -> Vec<TabletDataCacheOutMessage> {
         mem::take(&mut self.out_messages)
     }
+
+    fn get_stats(&self) -> TabletDataCacheStats {
+        let mut stats = self.stats.clone();
+        for tablet_cache_entry in self.tablet_cache_entries.values() {
+            stats.cached_bytes += tablet_cache_entry.get_size();
+            match tablet_cache_entry.get_state() {
+                TabletCacheEntryState::Load => stats.pending_loads += 1,
+                TabletCacheEntryState::Store(_) => stats.pending_stores += 1,
+                _ => {}
+            }
+        }
+        if self.completed_load_count > 0 {
+            stats.average_load_latency = self.total_load_latency / self.completed_load_count;
+        }
+        stats
+    }
 }
 
-// Policy that decides which entries can be evicted from the cache.
+// Policy that decides which entries can be evicted from the cache. The policy is
+// notified about every entry inserted into, accessed in and removed from the cache.
 // Type parameter T represents a variant type for the deserialized tablet data.
 pub trait TabletDataCachePolicy<T> {
-    // Decides which entries can be evicted from the cache given the maximum cache size.
+    // Notifies the policy that a new entry has been inserted into the cache.
+    fn on_insert(
+        &mut self,
+        tablet_cache_key: &TabletCacheKey,
+        tablet_cache_entry: &TabletCacheEntry<T>,
+    );
+
+    // Notifies the policy that an entry has been accessed, either by a tablet batch
+    // or because loading or storing of the tablet has completed.
+    fn on_access(
+        &mut self,
+        tablet_cache_key: &TabletCacheKey,
+        tablet_cache_entry: &TabletCacheEntry<T>,
+    );
+
+    // Notifies the policy that an entry has been removed from the cache, either
+    // evicted, failed or deleted.
+    fn on_remove(&mut self, tablet_cache_key: &TabletCacheKey);
+
+    // Decides which entries must be evicted from the cache given the maximum cache size.
     // Note that only ready cache entries can be evicted. Cache entries that are still
-    // loading or storing cannot be evicted. Both pending and ready entries contribute
-    // to the cache usage (e.g. size of a tablet still being loaded is counted towards
-    // used space).
-    fn evict(
+    // loading or storing, or locked by pending tablet batches, cannot be evicted. Both
+    // pending and ready entries contribute to the cache usage (e.g. size of a tablet
+    // still being loaded is counted towards used space).
+    fn select_evictions(
         &mut self,
-        instant: u64,
-        tablet_cache_size: u64,
+        tablet_cache_capacity: u64,
         tablet_cache_entries: &HashMap<TabletCacheKey, TabletCacheEntry<T>>,
     ) -> Vec<TabletCacheKey>;
 }
@@ -551,16 +880,430 @@ impl<T> DefaultTabletDataCachePolicy<T> {
 }
 
 impl<T> TabletDataCachePolicy<T> for DefaultTabletDataCachePolicy<T> {
-    fn evict(
+    fn on_insert(
         &mut self,
-        instant: u64,
-        tablet_cache_size: u64,
-        tablet_cache_entries: &HashMap<TabletCacheKey, TabletCacheEntry<T>>,
+        _tablet_cache_key: &TabletCacheKey,
+        _tablet_cache_entry: &TabletCacheEntry<T>,
+    ) {
+    }
+
+    fn on_access(
+        &mut self,
+        _tablet_cache_key: &TabletCacheKey,
+        _tablet_cache_entry: &TabletCacheEntry<T>,
+    ) {
+    }
+
+    fn on_remove(&mut self, _tablet_cache_key: &TabletCacheKey) {}
+
+    fn select_evictions(
+        &mut self,
+        _tablet_cache_capacity: u64,
+        _tablet_cache_entries: &HashMap<TabletCacheKey, TabletCacheEntry<T>>,
     ) -> Vec<TabletCacheKey> {
         Vec::new()
     }
 }
 
+// Least recently used policy evicts ready tablets that have not been accessed for
+// the longest time until the cache usage fits into the capacity. Locked tablets are
+// skipped and hence the cache may temporarily grow larger than its capacity.
+pub struct LruTabletDataCachePolicy<T> {
+    // Breaks ties between tablets accessed at the same instant.
+    access_counter: u64,
+    // Tablets ordered from least to most recently used, keyed by the last access
+    // instant and access counter.
+    access_order: BTreeMap<(u64, u64), TabletCacheKey>,
+    // Maps tablet to its position in the access order.
+    access_positions: HashMap<TabletCacheKey, (u64, u64)>,
+    phantom: PhantomData<T>,
+}
+
+impl<T> LruTabletDataCachePolicy<T> {
+    pub fn new() -> Self {
+        Self {
+            access_counter: 0,
+            access_order: BTreeMap::new(),
+            access_positions: HashMap::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    // Moves the tablet to the most recently used position.
+    fn touch(
+        &mut self,
+        tablet_cache_key: &TabletCacheKey,
+        tablet_cache_entry: &TabletCacheEntry<T>,
+    ) {
+        self.remove(tablet_cache_key);
+        self.access_counter += 1;
+        let position = (
+            tablet_cache_entry.get_last_access_instant(),
+            self.access_counter,
+        );
+        self.access_order.insert(position, tablet_cache_key.clone());
+        self.access_positions
+            .insert(tablet_cache_key.clone(), position);
+    }
+
+    fn remove(&mut self, tablet_cache_key: &TabletCacheKey) {
+        if let Some(position) = self.access_positions.remove(tablet_cache_key) {
+            self.access_order.remove(&position);
+        }
+    }
+}
+
+impl<T> TabletDataCachePolicy<T> for LruTabletDataCachePolicy<T> {
+    fn on_insert(
+        &mut self,
+        tablet_cache_key: &TabletCacheKey,
+        tablet_cache_entry: &TabletCacheEntry<T>,
+    ) {
+        self.touch(tablet_cache_key, tablet_cache_entry);
+    }
+
+    fn on_access(
+        &mut self,
+        tablet_cache_key: &TabletCacheKey,
+        tablet_cache_entry: &TabletCacheEntry<T>,
+    ) {
+        self.touch(tablet_cache_key, tablet_cache_entry);
+    }
+
+    fn on_remove(&mut self, tablet_cache_key: &TabletCacheKey) {
+        self.remove(tablet_cache_key);
+    }
+
+    fn select_evictions(
+        &mut self,
+        tablet_cache_capacity: u64,
+        tablet_cache_entries: &HashMap<TabletCacheKey, TabletCacheEntry<T>>,
+    ) -> Vec<TabletCacheKey> {
+        let mut tablet_cache_size: u64 = tablet_cache_entries
+            .values()
+            .map(|tablet_cache_entry| tablet_cache_entry.get_size())
+            .sum();
+
+        let mut evicted_tablet_cache_keys = Vec::new();
+        for tablet_cache_key in self.access_order.values() {
+            if tablet_cache_size <= tablet_cache_capacity {
+                break;
+            }
+
+            if let Some(tablet_cache_entry) = tablet_cache_entries.get(tablet_cache_key) {
+                if tablet_cache_entry.is_evictable() {
+                    tablet_cache_size -= tablet_cache_entry.get_size();
+                    evicted_tablet_cache_keys.push(tablet_cache_key.clone());
+                }
+            }
+        }
+
+        evicted_tablet_cache_keys
+    }
+}
+
+// Percentage of the tablet cache capacity reserved for the protected segment of
+// the TinyLFU policy.
+const PROTECTED_SEGMENT_PERCENT: u64 = 80;
+
+// TinyLFU policy combines a frequency based admission filter with segmented LRU
+// eviction. Newly inserted tablets are candidates that are admitted into the probation
+// segment only if they are accessed more frequently than the tablet that would be
+// evicted to make space for them, otherwise the candidates themselves are evicted.
+// Tablets accessed again while in probation are promoted into the protected segment
+// limited to a fraction of the cache capacity. Hence scans touching many tablets once
+// cannot flush frequently accessed tablets from the cache.
+pub struct TinyLfuTabletDataCachePolicy<T> {
+    frequency_sketch: FrequencySketch,
+    // Orders tablets within segments from least to most recently used.
+    access_counter: u64,
+    candidate_segment: BTreeMap<u64, TabletCacheKey>,
+    probation_segment: BTreeMap<u64, TabletCacheKey>,
+    protected_segment: BTreeMap<u64, TabletCacheKey>,
+    protected_segment_size: u64,
+    // Maps tablet to its segment and position within the segment.
+    segment_entries: HashMap<TabletCacheKey, TabletCacheSegmentEntry>,
+    phantom: PhantomData<T>,
+}
+
+impl<T> TinyLfuTabletDataCachePolicy<T> {
+    // Creates new policy with the frequency sketch sized for the given number of
+    // tablets expected to be held by the cache.
+    pub fn new(expected_tablet_count: usize) -> Self {
+        Self {
+            frequency_sketch: FrequencySketch::new(expected_tablet_count),
+            access_counter: 0,
+            candidate_segment: BTreeMap::new(),
+            probation_segment: BTreeMap::new(),
+            protected_segment: BTreeMap::new(),
+            protected_segment_size: 0,
+            segment_entries: HashMap::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    fn get_segment_mut(
+        &mut self,
+        segment: TabletCacheSegment,
+    ) -> &mut BTreeMap<u64, TabletCacheKey> {
+        match segment {
+            TabletCacheSegment::Candidate => &mut self.candidate_segment,
+            TabletCacheSegment::Probation => &mut self.probation_segment,
+            TabletCacheSegment::Protected => &mut self.protected_segment,
+        }
+    }
+
+    // Places the tablet at the most recently used position of the given segment.
+    fn push(&mut self, tablet_cache_key: &TabletCacheKey, segment: TabletCacheSegment, size: u64) {
+        self.access_counter += 1;
+        let position = self.access_counter;
+        self.get_segment_mut(segment)
+            .insert(position, tablet_cache_key.clone());
+        if segment == TabletCacheSegment::Protected {
+            self.protected_segment_size += size;
+        }
+        self.segment_entries.insert(
+            tablet_cache_key.clone(),
+            TabletCacheSegmentEntry {
+                segment,
+                position,
+                size,
+            },
+        );
+    }
+
+    fn remove(&mut self, tablet_cache_key: &TabletCacheKey) -> Option<TabletCacheSegmentEntry> {
+        let segment_entry = self.segment_entries.remove(tablet_cache_key)?;
+        self.get_segment_mut(segment_entry.segment)
+            .remove(&segment_entry.position);
+        if segment_entry.segment == TabletCacheSegment::Protected {
+            self.protected_segment_size -= segment_entry.size;
+        }
+        Some(segment_entry)
+    }
+
+    // Finds the least recently used admitted tablet that can be evicted, preferring
+    // probation tablets over protected ones.
+    fn find_victim(
+        &self,
+        tablet_cache_entries: &HashMap<TabletCacheKey, TabletCacheEntry<T>>,
+    ) -> Option<TabletCacheKey> {
+        self.probation_segment
+            .values()
+            .chain(self.protected_segment.values())
+            .find(|tablet_cache_key| {
+                tablet_cache_entries
+                    .get(*tablet_cache_key)
+                    .is_some_and(|tablet_cache_entry| tablet_cache_entry.is_evictable())
+            })
+            .cloned()
+    }
+}
+
+impl<T> TabletDataCachePolicy<T> for TinyLfuTabletDataCachePolicy<T> {
+    fn on_insert(
+        &mut self,
+        tablet_cache_key: &TabletCacheKey,
+        tablet_cache_entry: &TabletCacheEntry<T>,
+    ) {
+        self.frequency_sketch.increment(tablet_cache_key);
+        self.remove(tablet_cache_key);
+        self.push(
+            tablet_cache_key,
+            TabletCacheSegment::Candidate,
+            tablet_cache_entry.get_size(),
+        );
+    }
+
+    fn on_access(
+        &mut self,
+        tablet_cache_key: &TabletCacheKey,
+        _tablet_cache_entry: &TabletCacheEntry<T>,
+    ) {
+        self.frequency_sketch.increment(tablet_cache_key);
+        if let Some(segment_entry) = self.remove(tablet_cache_key) {
+            // Candidates remain candidates until admitted, admitted tablets are
+            // promoted into the protected segment.
+            let segment = match segment_entry.segment {
+                TabletCacheSegment::Candidate => TabletCacheSegment::Candidate,
+                _ => TabletCacheSegment::Protected,
+            };
+            self.push(tablet_cache_key, segment, segment_entry.size);
+        }
+    }
+
+    fn on_remove(&mut self, tablet_cache_key: &TabletCacheKey) {
+        self.remove(tablet_cache_key);
+    }
+
+    fn select_evictions(
+        &mut self,
+        tablet_cache_capacity: u64,
+        tablet_cache_entries: &HashMap<TabletCacheKey, TabletCacheEntry<T>>,
+    ) -> Vec<TabletCacheKey> {
+        // Demote least recently used protected tablets that no longer fit into the
+        // protected segment.
+        let protected_segment_capacity = tablet_cache_capacity * PROTECTED_SEGMENT_PERCENT / 100;
+        while self.protected_segment_size > protected_segment_capacity {
+            let tablet_cache_key = self.protected_segment.values().next().unwrap().clone();
+            let segment_entry = self.remove(&tablet_cache_key).unwrap();
+            self.push(
+                &tablet_cache_key,
+                TabletCacheSegment::Probation,
+                segment_entry.size,
+            );
+        }
+
+        let mut tablet_cache_size: u64 = tablet_cache_entries
+            .values()
+            .map(|tablet_cache_entry| tablet_cache_entry.get_size())
+            .sum();
+        // Space taken by candidates that are still loading or storing is reclaimed
+        // once they are ready and the admission is decided.
+        let mut undecided_size = 0;
+        let mut evicted_tablet_cache_keys = Vec::new();
+
+        let candidate_keys: Vec<TabletCacheKey> =
+            self.candidate_segment.values().cloned().collect();
+        for candidate_key in candidate_keys {
+            let candidate_entry = &tablet_cache_entries[&candidate_key];
+            if !candidate_entry.is_evictable() {
+                undecided_size += candidate_entry.get_size();
+                continue;
+            }
+
+            let segment_entry = self.remove(&candidate_key).unwrap();
+            if tablet_cache_size <= tablet_cache_capacity {
+                self.push(
+                    &candidate_key,
+                    TabletCacheSegment::Probation,
+                    segment_entry.size,
+                );
+                continue;
+            }
+
+            // Admit the candidate only if it is accessed more frequently than the
+            // tablet that would be evicted in its place.
+            let evicted_key = match self.find_victim(tablet_cache_entries) {
+                Some(victim_key)
+                    if self.frequency_sketch.get_frequency(&candidate_key)
+                        > self.frequency_sketch.get_frequency(&victim_key) =>
+                {
+                    self.push(
+                        &candidate_key,
+                        TabletCacheSegment::Probation,
+                        segment_entry.size,
+                    );
+                    self.remove(&victim_key);
+                    victim_key
+                }
+                _ => candidate_key,
+            };
+            tablet_cache_size -= tablet_cache_entries[&evicted_key].get_size();
+            evicted_tablet_cache_keys.push(evicted_key);
+        }
+
+        // Evict least recently used admitted tablets until the cache fits into its
+        // capacity.
+        while tablet_cache_size - undecided_size > tablet_cache_capacity {
+            match self.find_victim(tablet_cache_entries) {
+                Some(victim_key) => {
+                    self.remove(&victim_key);
+                    tablet_cache_size -= tablet_cache_entries[&victim_key].get_size();
+                    evicted_tablet_cache_keys.push(victim_key);
+                }
+                None => break,
+            }
+        }
+
+        evicted_tablet_cache_keys
+    }
+}
+
+// Segments of the tablet cache maintained by the TinyLFU policy.
+#[derive(Clone, Copy, PartialEq)]
+enum TabletCacheSegment {
+    // Inserted tablets not yet admitted into the cache.
+    Candidate,
+    // Admitted tablets that have not been accessed since admission.
+    Probation,
+    // Admitted tablets that have been accessed since admission.
+    Protected,
+}
+
+struct TabletCacheSegmentEntry {
+    segment: TabletCacheSegment,
+    position: u64,
+    size: u64,
+}
+
+// Number of counters estimating the frequency of each tablet.
+const FREQUENCY_SKETCH_DEPTH: usize = 4;
+// Maximum value of a single frequency counter.
+const FREQUENCY_SKETCH_MAX_COUNT: u8 = 15;
+// Number of increments per counter row after which all counters are halved.
+const FREQUENCY_SKETCH_SAMPLE_FACTOR: usize = 10;
+
+// Count-min sketch estimating how often tablets are accessed. Counters are halved
+// periodically such that the estimates favor recent accesses.
+struct FrequencySketch {
+    hash_builder: DefaultHashBuilder,
+    counters: Vec<u8>,
+    width: usize,
+    increments: usize,
+    sample_size: usize,
+}
+
+impl FrequencySketch {
+    fn new(width: usize) -> Self {
+        let width = width.max(1).next_power_of_two();
+        Self {
+            hash_builder: DefaultHashBuilder::default(),
+            counters: vec![0; FREQUENCY_SKETCH_DEPTH * width],
+            width,
+            increments: 0,
+            sample_size: FREQUENCY_SKETCH_SAMPLE_FACTOR * width,
+        }
+    }
+
+    // Gets the index of the counter for the key in each of the rows.
+    fn get_indexes<K: Hash>(&self, key: &K) -> [usize; FREQUENCY_SKETCH_DEPTH] {
+        let hash = self.hash_builder.hash_one(key);
+        let (low, high) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+        let mut indexes = [0; FREQUENCY_SKETCH_DEPTH];
+        for (row, index) in indexes.iter_mut().enumerate() {
+            let column =
+                low.wrapping_add(high.wrapping_mul(row as u64)) as usize & (self.width - 1);
+            *index = row * self.width + column;
+        }
+        indexes
+    }
+
+    fn increment<K: Hash>(&mut self, key: &K) {
+        for index in self.get_indexes(key) {
+            if self.counters[index] < FREQUENCY_SKETCH_MAX_COUNT {
+                self.counters[index] += 1;
+            }
+        }
+
+        self.increments += 1;
+        if self.increments >= self.sample_size {
+            for counter in &mut self.counters {
+                *counter /= 2;
+            }
+            self.increments /= 2;
+        }
+    }
+
+    fn get_frequency<K: Hash>(&self, key: &K) -> u8 {
+        self.get_indexes(key)
+            .into_iter()
+            .map(|index| self.counters[index])
+            .min()
+            .unwrap()
+    }
+}
+
 // Uniquely identifies tablet cache entry.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
 struct TabletCacheKey {
@@ -598,18 +1341,22 @@ struct TabletCacheEntry<T> {
     cache_entry_state: TabletCacheEntryState<T>,
     // The list of tablet batches that are interested in this tablet.
     tablet_batch_ids: Vec<u64>,
+    // The instant when the tablet has been last accessed.
+    last_access_instant: u64,
 }
 
 impl<T> TabletCacheEntry<T> {
     fn with_load_state(
         correlation_id: u64,
         tablet_metadata: &TabletMetadata,
+        instant: u64,
     ) -> (Self, TabletDataCacheOutMessage) {
         (
             Self {
                 tablet_metadata: tablet_metadata.clone(),
                 cache_entry_state: TabletCacheEntryState::Load,
                 tablet_batch_ids: Vec::new(),
+                last_access_instant: instant,
             },
             TabletDataCacheOutMessage::LoadRequest(
                 correlation_id,
@@ -625,6 +1372,7 @@ impl<T> TabletCacheEntry<T> {
         tablet_metadata: &TabletMetadata,
         tablet_value: T,
         tablet_contents: Bytes,
+        instant: u64,
     ) -> (Self, TabletDataCacheOutMessage) {
         (
             Self {
@@ -633,6 +1381,7 @@ impl<T> TabletCacheEntry<T> {
                     tablet_value,
                 )),
                 tablet_batch_ids: Vec::new(),
+                last_access_instant: instant,
             },
             TabletDataCacheOutMessage::StoreRequest(
                 correlation_id,
@@ -652,6 +1401,49 @@ impl<T> TabletCacheEntry<T> {
         &self.cache_entry_state
     }
 
+    // Checks if the tablet is still being loaded or stored.
+    fn is_pending(&self) -> bool {
+        matches!(
+            self.cache_entry_state,
+            TabletCacheEntryState::Load | TabletCacheEntryState::Store(_)
+        )
+    }
+
+    // Checks if the tablet is referenced by a pending tablet batch and hence
+    // cannot be evicted.
+    fn is_locked(&self) -> bool {
+        self.is_pending() || !self.tablet_batch_ids.is_empty()
+    }
+
+    // Checks if the tablet is cached and not locked, hence can be evicted.
+    fn is_evictable(&self) -> bool {
+        self.get_data_ref_count().is_some() && !self.is_locked()
+    }
+
+    // Gets the size of the tablet data counted towards the cache usage. Tablets
+    // that are still loading or storing are counted as well.
+    fn get_size(&self) -> u64 {
+        self.tablet_metadata.blob_size as u64
+    }
+
+    fn get_last_access_instant(&self) -> u64 {
+        self.last_access_instant
+    }
+
+    // Records that the tablet has been accessed at the given instant.
+    fn record_access(&mut self, instant: u64) {
+        self.last_access_instant = self.last_access_instant.max(instant);
+    }
+
+    // Gets the number of references to the cached tablet data, none if the
+    // tablet data is not cached.
+    fn get_data_ref_count(&self) -> Option<usize> {
+        match &self.cache_entry_state {
+            TabletCacheEntryState::Cache(tablet_data) => Some(tablet_data.ref_count()),
+            _ => None,
+        }
+    }
+
     fn register_waiting_batch(&mut self, tablet_batch_id: u64) {
         self.tablet_batch_ids.push(tablet_batch_id);
     }
@@ -711,6 +1503,80 @@ impl<T> TabletCacheEntry<T> {
     }
 }
 
+// Describes a tablet cache entry that has been evicted while its tablet data
+// was still referenced outside of the cache.
+#[cfg(debug_assertions)]
+#[derive(PartialEq, Debug, Clone)]
+pub struct TabletDataLeakReport {
+    // Uri of the leaked tablet blob in Tablet Data Storage.
+    pub blob_uri: String,
+    // Number of references to the tablet data that have not been returned.
+    pub leaked_refs: usize,
+    // Ids of the tablet batches that have been handed the tablet data and
+    // may still hold it.
+    pub tablet_batch_ids: Vec<u64>,
+}
+
+// Tracks which tablet batches have been handed references to the cached tablet
+// data. Used in debug builds to detect tablet data references that outlive the
+// consumer processing and pin memory past the eviction from cache.
+#[cfg(debug_assertions)]
+#[derive(Default)]
+struct TabletDataLeakTracker {
+    holders: HashMap<TabletCacheKey, BTreeSet<u64>>,
+    reports: Vec<TabletDataLeakReport>,
+}
+
+#[cfg(debug_assertions)]
+impl TabletDataLeakTracker {
+    fn record_holder(&mut self, tablet_cache_key: &TabletCacheKey, tablet_batch_id: u64) {
+        self.holders
+            .entry(tablet_cache_key.clone())
+            .or_default()
+            .insert(tablet_batch_id);
+    }
+
+    fn release_holders(&mut self, tablet_cache_key: &TabletCacheKey) {
+        self.holders.remove(tablet_cache_key);
+    }
+
+    fn check_evicted<T>(
+        &mut self,
+        logger: &Logger,
+        tablet_cache_key: &TabletCacheKey,
+        tablet_cache_entry: &TabletCacheEntry<T>,
+    ) {
+        let tablet_batch_ids = self.holders.remove(tablet_cache_key).unwrap_or_default();
+        // Cache entry is being dropped, hence any remaining reference is held
+        // outside of the cache.
+        let leaked_refs = tablet_cache_entry
+            .get_data_ref_count()
+            .map_or(0, |ref_count| ref_count - 1);
+        if leaked_refs == 0 {
+            return;
+        }
+
+        let tablet_batch_ids: Vec<u64> = tablet_batch_ids.into_iter().collect();
+        warn!(
+            logger,
+            "Evicted tablet {} is still referenced {} times, holders {:?}",
+            tablet_cache_key.uri,
+            leaked_refs,
+            tablet_batch_ids
+        );
+
+        self.reports.push(TabletDataLeakReport {
+            blob_uri: tablet_cache_key.uri.clone(),
+            leaked_refs,
+            tablet_batch_ids,
+        });
+    }
+
+    fn take_reports(&mut self) -> Vec<TabletDataLeakReport> {
+        mem::take(&mut self.reports)
+    }
+}
+
 // Represents the state of the tablet batch. Both loading and storing of individual
 // tablets happens independently (separate requests are issued to the Tablet Data Storage),
 // however the result must be presented in one shot (e.g. as a batch). Specifically,
@@ -839,6 +1705,7 @@ mod tests {
 
     use super::*;
     use crate::mock::*;
+    use tcp_runtime::clock::ManualClock;
 
     const DATA_CACHE_CAPACITY: u64 = 1024;
     const TABLET_ID_1: u32 = 1;
@@ -850,24 +1717,63 @@ mod tests {
     const CORRELATION_ID_2: u64 = 2;
     const TABLET_BLOB_URI_1: &'static str = "blob 1";
     const TABLET_BLOB_URI_2: &'static str = "blob 2";
+    const KEY_WRAPPING_KEY: [u8; 32] = [1; 32];
 
     fn create_tablet_data_cache() -> DefaultTabletDataCache<Bytes> {
         let mut cache = DefaultTabletDataCache::create(
             0,
             Box::new(BytesTabletDataSerializer {}),
             Box::new(DefaultTabletDataCachePolicy::new()),
+            Rc::new(ManualClock::new(0)),
         );
 
         cache.init(
             create_logger(),
             TabletDataCacheConfig {
                 tablet_cache_capacity: DATA_CACHE_CAPACITY,
+                delete_superseded_tablets: false,
+                compress_tablets: false,
+                max_tablet_size: 0,
             },
         );
+        cache.set_key_wrapping_key(Bytes::from_static(&KEY_WRAPPING_KEY));
 
         cache
     }
 
+    // Policy that evicts every cached entry, used to exercise eviction paths.
+    struct EvictAllTabletDataCachePolicy {}
+
+    impl TabletDataCachePolicy<Bytes> for EvictAllTabletDataCachePolicy {
+        fn on_insert(
+            &mut self,
+            _tablet_cache_key: &TabletCacheKey,
+            _tablet_cache_entry: &TabletCacheEntry<Bytes>,
+        ) {
+        }
+
+        fn on_access(
+            &mut self,
+            _tablet_cache_key: &TabletCacheKey,
+            _tablet_cache_entry: &TabletCacheEntry<Bytes>,
+        ) {
+        }
+
+        fn on_remove(&mut self, _tablet_cache_key: &TabletCacheKey) {}
+
+        fn select_evictions(
+            &mut self,
+            _tablet_cache_capacity: u64,
+            tablet_cache_entries: &HashMap<TabletCacheKey, TabletCacheEntry<Bytes>>,
+        ) -> Vec<TabletCacheKey> {
+            tablet_cache_entries
+                .iter()
+                .filter(|(_, entry)| entry.get_data_ref_count().is_some())
+                .map(|(key, _)| key.clone())
+                .collect()
+        }
+    }
+
     fn create_tablet_metadata(
         tablet_id: u32,
         tablet_version: u32,
@@ -881,6 +1787,45 @@ mod tests {
         }
     }
 
+    // Seals tablet contents the way the cache storing the tablet does, updating
+    // the metadata to describe the produced blob.
+    fn seal_tablet(tablet_metadata: &mut TabletMetadata, tablet_contents: &str) -> Bytes {
+        seal_tablet_contents(
+            &KEY_WRAPPING_KEY,
+            tablet_metadata,
+            tablet_contents.as_bytes(),
+        )
+        .unwrap()
+    }
+
+    // Checks that the only out message requests to store the blob that opens to
+    // the tablet contents. Blobs are encrypted with fresh nonces, hence they can't
+    // be compared as is.
+    fn assert_store_request(
+        out_messages: Vec<TabletDataCacheOutMessage>,
+        correlation_id: u64,
+        tablet_metadata: &TabletMetadata,
+        tablet_contents: Bytes,
+    ) {
+        let [TabletDataCacheOutMessage::StoreRequest(
+            store_correlation_id,
+            store_tablet_request,
+            tablet_blob,
+        )] = out_messages.as_slice()
+        else {
+            panic!("Expected store request");
+        };
+        assert_eq!(correlation_id, *store_correlation_id);
+        assert_eq!(
+            create_store_tablet_request(tablet_metadata.blob_uri.clone()),
+            *store_tablet_request
+        );
+        assert_eq!(
+            Ok(tablet_contents),
+            open_tablet_blob(&KEY_WRAPPING_KEY, tablet_metadata, tablet_blob.clone())
+        );
+    }
+
     fn create_load_tablet_request(blob_uri: String) -> LoadTabletRequest {
         LoadTabletRequest { blob_uri }
     }
@@ -889,6 +1834,10 @@ mod tests {
         StoreTabletRequest { blob_uri }
     }
 
+    fn create_delete_tablet_request(blob_uri: String) -> DeleteTabletRequest {
+        DeleteTabletRequest { blob_uri }
+    }
+
     fn create_load_tablet_response(status: TabletDataStorageStatus) -> LoadTabletResponse {
         LoadTabletResponse {
             status: status.into(),
@@ -901,6 +1850,12 @@ mod tests {
         }
     }
 
+    fn create_delete_tablet_response(status: TabletDataStorageStatus) -> DeleteTabletResponse {
+        DeleteTabletResponse {
+            status: status.into(),
+        }
+    }
+
     struct TabletDataCacheLoop {
         tablet_data_cache: DefaultTabletDataCache<Bytes>,
     }
@@ -916,10 +1871,9 @@ mod tests {
 
         fn execute_step(
             &mut self,
-            instant: u64,
             in_message: Option<TabletDataCacheInMessage>,
         ) -> Vec<TabletDataCacheOutMessage> {
-            self.tablet_data_cache.make_progress(instant);
+            self.tablet_data_cache.make_progress();
 
             let out_messages = self.tablet_data_cache.take_out_messages();
 
@@ -932,13 +1886,141 @@ mod tests {
         }
     }
 
+    // Fixture exercising tablet cache policies with equally sized tablets.
+    struct TabletEvictionFixture {
+        clock: Rc<ManualClock>,
+        tablets_metadata: Vec<TabletMetadata>,
+        tablet_blobs: Vec<Bytes>,
+        tablet_data_cache_loop: TabletDataCacheLoop,
+    }
+
+    impl TabletEvictionFixture {
+        const TABLET_SIZE: u32 = 4;
+
+        // Creates tablet data cache with given policy that fits the given number
+        // of tablets.
+        fn create(
+            tablet_cache_policy: Box<dyn TabletDataCachePolicy<Bytes>>,
+            tablet_count: u32,
+            tablet_cache_capacity: u64,
+        ) -> Self {
+            let clock = Rc::new(ManualClock::new(0));
+            let mut tablet_data_cache = DefaultTabletDataCache::create(
+                0,
+                Box::new(BytesTabletDataSerializer {}),
+                tablet_cache_policy,
+                clock.clone(),
+            );
+            tablet_data_cache.init(
+                create_logger(),
+                TabletDataCacheConfig {
+                    tablet_cache_capacity: tablet_cache_capacity * Self::TABLET_SIZE as u64,
+                    delete_superseded_tablets: false,
+                    compress_tablets: false,
+                    max_tablet_size: 0,
+                },
+            );
+            tablet_data_cache.set_key_wrapping_key(Bytes::from_static(&KEY_WRAPPING_KEY));
+
+            let (tablets_metadata, tablet_blobs) = (1..=tablet_count)
+                .map(|tablet_id| {
+                    let mut tablet_metadata = create_tablet_metadata(
+                        tablet_id,
+                        TABLET_VERSION_1,
+                        format!("blob {}", tablet_id),
+                    );
+                    let tablet_blob = seal_tablet(&mut tablet_metadata, TABLET_DATA_VERSION_1);
+                    // Tablets are accounted for as equally sized.
+                    tablet_metadata.blob_size = Self::TABLET_SIZE;
+                    (tablet_metadata, tablet_blob)
+                })
+                .unzip();
+
+            Self {
+                clock,
+                tablets_metadata,
+                tablet_blobs,
+                tablet_data_cache_loop: TabletDataCacheLoop::create(tablet_data_cache),
+            }
+        }
+
+        fn load_tablet(&mut self, instant: u64, tablet_index: usize) {
+            self.clock.set_instant(instant);
+            self.tablet_data_cache_loop
+                .get_mut()
+                .load_tablets(&vec![self.tablets_metadata[tablet_index].clone()]);
+        }
+
+        fn complete_load(&mut self, correlation_id: u64) {
+            let tablet_cache_key =
+                &self.tablet_data_cache_loop.get_mut().tablet_operations[&correlation_id];
+            let tablet_index = self
+                .tablets_metadata
+                .iter()
+                .position(|tablet_metadata| tablet_metadata.blob_uri == tablet_cache_key.uri)
+                .unwrap();
+            let tablet_blob = self.tablet_blobs[tablet_index].clone();
+            self.tablet_data_cache_loop
+                .execute_step(Some(TabletDataCacheInMessage::LoadResponse(
+                    correlation_id,
+                    create_load_tablet_response(TabletDataStorageStatus::Succeeded),
+                    tablet_blob,
+                )));
+            self.make_progress();
+        }
+
+        fn make_progress(&mut self) {
+            self.tablet_data_cache_loop.execute_step(None);
+        }
+
+        fn get_cached_tablets(&mut self) -> Vec<bool> {
+            let tablet_cache_entries = &self.tablet_data_cache_loop.get_mut().tablet_cache_entries;
+            self.tablets_metadata
+                .iter()
+                .map(|tablet_metadata| {
+                    tablet_cache_entries.contains_key(&TabletCacheKey::from(tablet_metadata))
+                })
+                .collect()
+        }
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct TestTablet {
+        #[prost(string, tag = "1")]
+        name: String,
+        #[prost(uint64, repeated, tag = "2")]
+        values: Vec<u64>,
+    }
+
+    #[test]
+    fn test_prost_tablet_data_serializer() {
+        let tablet_serializer = ProstTabletDataSerializer::<TestTablet>::new();
+        let tablet_value = TestTablet {
+            name: "tablet".to_string(),
+            values: vec![1, 2, 3],
+        };
+
+        let tablet_data = tablet_serializer.serialize(&tablet_value).unwrap();
+        assert_eq!(tablet_data.len(), tablet_serializer.get_size(&tablet_value));
+        assert_eq!(
+            Ok(tablet_value),
+            tablet_serializer.deserialize(&"table".to_string(), tablet_data)
+        );
+
+        assert_eq!(
+            Err(()),
+            tablet_serializer.deserialize(&"table".to_string(), Bytes::from_static(&[0xff]))
+        );
+    }
+
     #[test]
     fn test_load_tablets_success() {
         let tablet_data_cache = create_tablet_data_cache();
         let mut tablet_data_cache_loop = TabletDataCacheLoop::create(tablet_data_cache);
 
-        let tablet_metadata_1_v_1 =
+        let mut tablet_metadata_1_v_1 =
             create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1, TABLET_BLOB_URI_1.to_string());
+        let tablet_blob_1_v_1 = seal_tablet(&mut tablet_metadata_1_v_1, TABLET_DATA_VERSION_1);
         let tablet_data_1_v_1 = Bytes::from(TABLET_DATA_VERSION_1);
 
         let load_tablets_result = tablet_data_cache_loop
@@ -952,17 +2034,14 @@ mod tests {
                 CORRELATION_ID_1,
                 create_load_tablet_request(TABLET_BLOB_URI_1.to_string())
             )],
-            tablet_data_cache_loop.execute_step(
-                1,
-                Some(TabletDataCacheInMessage::LoadResponse(
-                    CORRELATION_ID_1,
-                    create_load_tablet_response(TabletDataStorageStatus::Succeeded),
-                    tablet_data_1_v_1.clone()
-                ))
-            )
+            tablet_data_cache_loop.execute_step(Some(TabletDataCacheInMessage::LoadResponse(
+                CORRELATION_ID_1,
+                create_load_tablet_response(TabletDataStorageStatus::Succeeded),
+                tablet_blob_1_v_1
+            )))
         );
 
-        assert!(tablet_data_cache_loop.execute_step(2, None).is_empty());
+        assert!(tablet_data_cache_loop.execute_step(None).is_empty());
 
         assert_eq!(
             Some(Ok(vec![(
@@ -1002,22 +2081,17 @@ mod tests {
 
         assert!(store_tablets_result.check_result().is_none());
 
-        assert_eq!(
-            vec![TabletDataCacheOutMessage::StoreRequest(
+        assert_store_request(
+            tablet_data_cache_loop.execute_step(Some(TabletDataCacheInMessage::StoreResponse(
                 CORRELATION_ID_1,
-                create_store_tablet_request(tablet_metadata_1_v_1_to_v_2.blob_uri.clone()),
-                tablet_data_1_v_2.clone()
-            )],
-            tablet_data_cache_loop.execute_step(
-                1,
-                Some(TabletDataCacheInMessage::StoreResponse(
-                    CORRELATION_ID_1,
-                    create_store_tablet_response(TabletDataStorageStatus::Succeeded)
-                ))
-            )
+                create_store_tablet_response(TabletDataStorageStatus::Succeeded),
+            ))),
+            CORRELATION_ID_1,
+            &tablet_metadata_1_v_1_to_v_2,
+            tablet_data_1_v_2.clone(),
         );
 
-        assert!(tablet_data_cache_loop.execute_step(2, None).is_empty());
+        assert!(tablet_data_cache_loop.execute_step(None).is_empty());
 
         assert_eq!(Some(Ok(())), store_tablets_result.check_result());
 
@@ -1033,4 +2107,365 @@ mod tests {
             load_tablets_result.check_result()
         );
     }
+
+    #[test]
+    fn test_store_tablets_compressed() {
+        let mut tablet_data_cache = create_tablet_data_cache();
+        tablet_data_cache.init(
+            create_logger(),
+            TabletDataCacheConfig {
+                tablet_cache_capacity: DATA_CACHE_CAPACITY,
+                delete_superseded_tablets: false,
+                compress_tablets: true,
+                max_tablet_size: 0,
+            },
+        );
+        let mut tablet_data_cache_loop = TabletDataCacheLoop::create(tablet_data_cache);
+
+        let mut tablet_metadata_1_v_2 =
+            create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1, TABLET_BLOB_URI_1.to_string());
+        let tablet_data_1_v_2 = Bytes::from(TABLET_DATA_VERSION_2.repeat(64));
+
+        tablet_data_cache_loop.get_mut().store_tablets(vec![(
+            &mut tablet_metadata_1_v_2,
+            tablet_data_1_v_2.clone(),
+        )]);
+
+        let out_messages = tablet_data_cache_loop.execute_step(None);
+        let Some(TabletDataCacheOutMessage::StoreRequest(_, _, tablet_blob_1_v_2)) =
+            out_messages.first()
+        else {
+            panic!("Expected store request");
+        };
+
+        assert_eq!(TabletBlobCodec::Deflate, tablet_metadata_1_v_2.blob_codec());
+        assert!((tablet_metadata_1_v_2.blob_size as usize) < tablet_data_1_v_2.len());
+
+        // Compressed tablet is decompressed when loaded by another cache.
+        let mut tablet_data_cache_loop = TabletDataCacheLoop::create(create_tablet_data_cache());
+        let load_tablets_result = tablet_data_cache_loop
+            .get_mut()
+            .load_tablets(&vec![tablet_metadata_1_v_2.clone()]);
+
+        tablet_data_cache_loop.execute_step(Some(TabletDataCacheInMessage::LoadResponse(
+            CORRELATION_ID_1,
+            create_load_tablet_response(TabletDataStorageStatus::Succeeded),
+            tablet_blob_1_v_2.clone(),
+        )));
+        tablet_data_cache_loop.execute_step(None);
+
+        assert_eq!(
+            Some(Ok(vec![(
+                tablet_metadata_1_v_2.clone(),
+                TabletData::create(tablet_data_1_v_2.clone())
+            ),])),
+            load_tablets_result.check_result()
+        );
+    }
+
+    #[test]
+    fn test_load_tablets_verification_failure() {
+        let tablet_data_cache = create_tablet_data_cache();
+        let mut tablet_data_cache_loop = TabletDataCacheLoop::create(tablet_data_cache);
+
+        let mut tablet_metadata_1_v_1 =
+            create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1, TABLET_BLOB_URI_1.to_string());
+        let tablet_blob_1_v_1 = seal_tablet(&mut tablet_metadata_1_v_1, TABLET_DATA_VERSION_1);
+        let mut tampered_blob_1_v_1 = tablet_blob_1_v_1.to_vec();
+        tampered_blob_1_v_1[0] ^= 1;
+
+        let load_tablets_result = tablet_data_cache_loop
+            .get_mut()
+            .load_tablets(&vec![tablet_metadata_1_v_1.clone()]);
+
+        tablet_data_cache_loop.execute_step(Some(TabletDataCacheInMessage::LoadResponse(
+            CORRELATION_ID_1,
+            create_load_tablet_response(TabletDataStorageStatus::Succeeded),
+            tampered_blob_1_v_1.into(),
+        )));
+        tablet_data_cache_loop.execute_step(None);
+
+        assert_eq!(
+            Some(Err(TabletDataStorageStatus::Failed)),
+            load_tablets_result.check_result()
+        );
+
+        // Tablet that failed verification is not cached and is loaded again.
+        let load_tablets_result = tablet_data_cache_loop
+            .get_mut()
+            .load_tablets(&vec![tablet_metadata_1_v_1.clone()]);
+
+        tablet_data_cache_loop.execute_step(Some(TabletDataCacheInMessage::LoadResponse(
+            CORRELATION_ID_2,
+            create_load_tablet_response(TabletDataStorageStatus::Succeeded),
+            tablet_blob_1_v_1,
+        )));
+        tablet_data_cache_loop.execute_step(None);
+
+        assert_eq!(
+            Some(Ok(vec![(
+                tablet_metadata_1_v_1.clone(),
+                TabletData::create(Bytes::from(TABLET_DATA_VERSION_1))
+            ),])),
+            load_tablets_result.check_result()
+        );
+    }
+
+    #[test]
+    fn test_release_discarded_tablets() {
+        let tablet_data_cache = create_tablet_data_cache();
+        let mut tablet_data_cache_loop = TabletDataCacheLoop::create(tablet_data_cache);
+
+        let tablet_metadata_1_v_1 =
+            create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1, TABLET_BLOB_URI_1.to_string());
+        let mut tablet_metadata_1_v_1_to_v_2 = tablet_metadata_1_v_1.clone();
+        let tablet_data_1_v_2 = Bytes::from(TABLET_DATA_VERSION_2);
+
+        let store_tablets_result = tablet_data_cache_loop.get_mut().store_tablets(vec![(
+            &mut tablet_metadata_1_v_1_to_v_2,
+            tablet_data_1_v_2.clone(),
+        )]);
+
+        // Tablet being stored is deleted only after storing completes.
+        tablet_data_cache_loop
+            .get_mut()
+            .release_tablets(vec![], vec![tablet_metadata_1_v_1_to_v_2.clone()]);
+
+        assert_store_request(
+            tablet_data_cache_loop.execute_step(Some(TabletDataCacheInMessage::StoreResponse(
+                CORRELATION_ID_1,
+                create_store_tablet_response(TabletDataStorageStatus::Succeeded),
+            ))),
+            CORRELATION_ID_1,
+            &tablet_metadata_1_v_1_to_v_2,
+            tablet_data_1_v_2.clone(),
+        );
+
+        assert_eq!(
+            vec![TabletDataCacheOutMessage::DeleteRequest(
+                CORRELATION_ID_2,
+                create_delete_tablet_request(tablet_metadata_1_v_1_to_v_2.blob_uri.clone())
+            )],
+            tablet_data_cache_loop.execute_step(Some(TabletDataCacheInMessage::DeleteResponse(
+                CORRELATION_ID_2,
+                create_delete_tablet_response(TabletDataStorageStatus::Succeeded)
+            )))
+        );
+
+        assert_eq!(Some(Ok(())), store_tablets_result.check_result());
+
+        // Tablet is deleted at most once and tablets not stored by the cache are never deleted.
+        tablet_data_cache_loop.get_mut().release_tablets(
+            vec![tablet_metadata_1_v_1.clone()],
+            vec![tablet_metadata_1_v_1_to_v_2.clone()],
+        );
+        assert!(tablet_data_cache_loop.execute_step(None).is_empty());
+    }
+
+    #[test]
+    fn test_tablet_access_tracking() {
+        let clock = Rc::new(ManualClock::new(10));
+        let mut tablet_data_cache = DefaultTabletDataCache::create(
+            0,
+            Box::new(BytesTabletDataSerializer {}),
+            Box::new(DefaultTabletDataCachePolicy::new()),
+            clock.clone(),
+        );
+        tablet_data_cache.init(
+            create_logger(),
+            TabletDataCacheConfig {
+                tablet_cache_capacity: DATA_CACHE_CAPACITY,
+                delete_superseded_tablets: false,
+                compress_tablets: false,
+                max_tablet_size: 0,
+            },
+        );
+        tablet_data_cache.set_key_wrapping_key(Bytes::from_static(&KEY_WRAPPING_KEY));
+        let mut tablet_data_cache_loop = TabletDataCacheLoop::create(tablet_data_cache);
+
+        let mut tablet_metadata_1_v_1 =
+            create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1, TABLET_BLOB_URI_1.to_string());
+        let tablet_blob_1_v_1 = seal_tablet(&mut tablet_metadata_1_v_1, TABLET_DATA_VERSION_1);
+        let tablet_cache_key = TabletCacheKey::from(&tablet_metadata_1_v_1);
+
+        tablet_data_cache_loop
+            .get_mut()
+            .load_tablets(&vec![tablet_metadata_1_v_1.clone()]);
+
+        // Tablet being loaded is locked.
+        let tablet_cache_entry =
+            &tablet_data_cache_loop.get_mut().tablet_cache_entries[&tablet_cache_key];
+        assert!(tablet_cache_entry.is_locked());
+        assert_eq!(10, tablet_cache_entry.get_last_access_instant());
+
+        clock.advance(10);
+        tablet_data_cache_loop.execute_step(Some(TabletDataCacheInMessage::LoadResponse(
+            CORRELATION_ID_1,
+            create_load_tablet_response(TabletDataStorageStatus::Succeeded),
+            tablet_blob_1_v_1,
+        )));
+        tablet_data_cache_loop.execute_step(None);
+
+        // Tablet is unlocked once all interested batches have been notified.
+        let tablet_cache_entry =
+            &tablet_data_cache_loop.get_mut().tablet_cache_entries[&tablet_cache_key];
+        assert!(!tablet_cache_entry.is_locked());
+        assert_eq!(20, tablet_cache_entry.get_last_access_instant());
+
+        clock.advance(10);
+        tablet_data_cache_loop
+            .get_mut()
+            .load_tablets(&vec![tablet_metadata_1_v_1.clone()]);
+
+        // Cache hit updates last access instant.
+        let tablet_cache_entry =
+            &tablet_data_cache_loop.get_mut().tablet_cache_entries[&tablet_cache_key];
+        assert!(!tablet_cache_entry.is_locked());
+        assert_eq!(30, tablet_cache_entry.get_last_access_instant());
+    }
+
+    #[test]
+    fn test_lru_policy_evicts_least_recently_used() {
+        let mut fixture =
+            TabletEvictionFixture::create(Box::new(LruTabletDataCachePolicy::new()), 3, 2);
+
+        fixture.load_tablet(0, 0);
+        fixture.complete_load(CORRELATION_ID_1);
+        fixture.load_tablet(10, 1);
+        fixture.complete_load(CORRELATION_ID_2);
+        assert_eq!(vec![true, true, false], fixture.get_cached_tablets());
+
+        // Accessing the first tablet makes the second one least recently used.
+        fixture.load_tablet(20, 0);
+        fixture.load_tablet(30, 2);
+        fixture.make_progress();
+        assert_eq!(vec![true, false, true], fixture.get_cached_tablets());
+        fixture.complete_load(3);
+
+        // Tablet being loaded is locked and cannot be evicted even though it is
+        // least recently used.
+        fixture.load_tablet(40, 1);
+        fixture.load_tablet(50, 2);
+        fixture.load_tablet(60, 0);
+        fixture.make_progress();
+        assert_eq!(vec![true, true, false], fixture.get_cached_tablets());
+    }
+
+    #[test]
+    fn test_get_stats() {
+        let mut fixture =
+            TabletEvictionFixture::create(Box::new(LruTabletDataCachePolicy::new()), 2, 1);
+
+        fixture.load_tablet(0, 0);
+        fixture.clock.set_instant(10);
+        fixture.complete_load(CORRELATION_ID_1);
+        fixture.load_tablet(20, 0);
+        fixture.load_tablet(20, 1);
+
+        assert_eq!(
+            TabletDataCacheStats {
+                hits: 1,
+                misses: 2,
+                evictions: 0,
+                cached_bytes: 2 * TabletEvictionFixture::TABLET_SIZE as u64,
+                pending_loads: 1,
+                pending_stores: 0,
+                average_load_latency: 10,
+            },
+            fixture.tablet_data_cache_loop.get_mut().get_stats()
+        );
+
+        // Completed tablet load makes least recently used tablet evicted.
+        fixture.clock.set_instant(50);
+        fixture.complete_load(CORRELATION_ID_2);
+
+        assert_eq!(
+            TabletDataCacheStats {
+                hits: 1,
+                misses: 2,
+                evictions: 1,
+                cached_bytes: TabletEvictionFixture::TABLET_SIZE as u64,
+                pending_loads: 0,
+                pending_stores: 0,
+                average_load_latency: 20,
+            },
+            fixture.tablet_data_cache_loop.get_mut().get_stats()
+        );
+    }
+
+    #[test]
+    fn test_tiny_lfu_policy_admits_frequent_tablets() {
+        let mut fixture =
+            TabletEvictionFixture::create(Box::new(TinyLfuTabletDataCachePolicy::new(64)), 3, 2);
+
+        // Repeatedly accessed tablet is promoted into the protected segment.
+        fixture.load_tablet(0, 0);
+        fixture.complete_load(CORRELATION_ID_1);
+        fixture.load_tablet(10, 0);
+        fixture.load_tablet(20, 0);
+        fixture.load_tablet(30, 1);
+        fixture.complete_load(CORRELATION_ID_2);
+        assert_eq!(vec![true, true, false], fixture.get_cached_tablets());
+
+        // Tablet accessed once is not admitted in place of the tablet accessed
+        // equally often.
+        fixture.load_tablet(40, 2);
+        fixture.complete_load(3);
+        assert_eq!(vec![true, true, false], fixture.get_cached_tablets());
+
+        // Tablet accessed more often is admitted in place of the probation tablet,
+        // while the protected tablet is retained.
+        fixture.load_tablet(50, 2);
+        fixture.complete_load(4);
+        assert_eq!(vec![true, false, true], fixture.get_cached_tablets());
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn test_evicted_tablet_leak_reported() {
+        let mut tablet_data_cache = DefaultTabletDataCache::create(
+            0,
+            Box::new(BytesTabletDataSerializer {}),
+            Box::new(EvictAllTabletDataCachePolicy {}),
+            Rc::new(ManualClock::new(0)),
+        );
+        tablet_data_cache.init(
+            create_logger(),
+            TabletDataCacheConfig {
+                tablet_cache_capacity: DATA_CACHE_CAPACITY,
+                delete_superseded_tablets: false,
+                compress_tablets: false,
+                max_tablet_size: 0,
+            },
+        );
+        tablet_data_cache.set_key_wrapping_key(Bytes::from_static(&KEY_WRAPPING_KEY));
+        let mut tablet_data_cache_loop = TabletDataCacheLoop::create(tablet_data_cache);
+
+        let mut tablet_metadata_1_v_1 =
+            create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1, TABLET_BLOB_URI_1.to_string());
+        let tablet_blob_1_v_1 = seal_tablet(&mut tablet_metadata_1_v_1, TABLET_DATA_VERSION_1);
+
+        let load_tablets_result = tablet_data_cache_loop
+            .get_mut()
+            .load_tablets(&vec![tablet_metadata_1_v_1.clone()]);
+
+        tablet_data_cache_loop.execute_step(Some(TabletDataCacheInMessage::LoadResponse(
+            CORRELATION_ID_1,
+            create_load_tablet_response(TabletDataStorageStatus::Succeeded),
+            tablet_blob_1_v_1,
+        )));
+
+        // Loaded tablet data is held by the result handle while being evicted.
+        tablet_data_cache_loop.execute_step(None);
+        assert!(load_tablets_result.check_result().is_some());
+
+        assert_eq!(
+            vec![TabletDataLeakReport {
+                blob_uri: TABLET_BLOB_URI_1.to_string(),
+                leaked_refs: 1,
+                tablet_batch_ids: vec![1],
+            }],
+            tablet_data_cache_loop.get_mut().take_leak_reports()
+        );
+    }
 }
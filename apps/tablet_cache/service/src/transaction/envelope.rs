@@ -0,0 +1,429 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use aes_gcm_siv::{
+    aead::{Aead, KeyInit, Payload as AeadPayload},
+    Aes256GcmSiv, Nonce,
+};
+use alloc::vec::Vec;
+use prost::bytes::Bytes;
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use tcp_tablet_store_service::apps::tablet_store::service::TabletMetadata;
+
+// Tablet envelope encrypts tablet contents before they leave the enclave and
+// verifies them once they are loaded back:
+//   * Every tablet blob is encrypted with a freshly generated tablet key that is
+// kept in the tablet metadata wrapped under the key wrapping key. The key
+// wrapping key is derived from the cluster secret and never leaves the enclave,
+// hence neither the Tablet Store nor the Tablet Data Storage learn the tablet
+// keys.
+//   * Tablet id and version are authenticated as associated data of both the
+// tablet key and the blob, binding them to the version of the tablet they have
+// been created for.
+//   * Every encryption, of either a tablet key or a tablet blob, uses a freshly
+// generated nonce that is stored in front of the ciphertext, hence no nonce is
+// reused even though the key wrapping key wraps many tablet keys and a tablet key
+// may be re-wrapped several times.
+//   * Blob size and hash are captured after encryption, hash is verified before
+// decryption.
+//
+// Only the initial tablets that have never been stored (i.e. version 0 with no
+// blob uri) carry no tablet key and hash, their blob must be empty. Blobs of any
+// other tablet are rejected unless they are verified.
+//
+// Tablets migrated to another cluster carry their tablet keys re-wrapped under a
+// transfer key both clusters agree on, the importing cluster re-wraps them under
+// its own key wrapping key. Tablet blobs are not re-encrypted.
+
+/// Context the key wrapping key is derived from the cluster secret with.
+pub const TABLET_KEY_WRAPPING_CONTEXT: &[u8] = b"tablet key wrapping";
+
+// Size of the per tablet symmetric encryption key.
+const TABLET_KEY_SIZE: usize = 32;
+
+// Size of the nonce stored in front of every ciphertext.
+const NONCE_SIZE: usize = 12;
+
+// Encrypts tablet contents with a new tablet key and updates tablet metadata to
+// describe produced tablet blob. Tablet id and version must already be set.
+pub fn seal_tablet_contents(
+    key_wrapping_key: &[u8],
+    tablet_metadata: &mut TabletMetadata,
+    tablet_contents: &[u8],
+) -> Result<Bytes, ()> {
+    let mut tablet_key = [0; TABLET_KEY_SIZE];
+    OsRng.fill_bytes(&mut tablet_key);
+    let associated_data = create_associated_data(tablet_metadata);
+    let wrapped_tablet_key = encrypt(key_wrapping_key, &tablet_key, &associated_data)?;
+    tablet_metadata.blob_encryption_key = wrapped_tablet_key.into();
+
+    let tablet_blob = encrypt(&tablet_key, tablet_contents, &associated_data)?;
+    tablet_metadata.blob_size = tablet_blob.len() as u32;
+    tablet_metadata.blob_hash = Sha256::digest(&tablet_blob).to_vec().into();
+
+    Ok(tablet_blob.into())
+}
+
+// Re-wraps the tablet key in the tablet metadata from one key wrapping key to
+// another. Tablets that carry no tablet key have nothing to re-wrap.
+pub fn rewrap_tablet_key(
+    from_key_wrapping_key: &[u8],
+    to_key_wrapping_key: &[u8],
+    tablet_metadata: &mut TabletMetadata,
+) -> Result<(), ()> {
+    if tablet_metadata.blob_encryption_key.is_empty() {
+        return Ok(());
+    }
+
+    let associated_data = create_associated_data(tablet_metadata);
+    let tablet_key = decrypt(
+        from_key_wrapping_key,
+        &tablet_metadata.blob_encryption_key,
+        &associated_data,
+    )?;
+    let wrapped_tablet_key = encrypt(to_key_wrapping_key, &tablet_key, &associated_data)?;
+    tablet_metadata.blob_encryption_key = wrapped_tablet_key.into();
+
+    Ok(())
+}
+
+// Verifies tablet blob against tablet metadata and decrypts it.
+pub fn open_tablet_blob(
+    key_wrapping_key: &[u8],
+    tablet_metadata: &TabletMetadata,
+    tablet_blob: Bytes,
+) -> Result<Bytes, ()> {
+    if is_initial_tablet(tablet_metadata) {
+        // Initial tablet has never been stored, hence there is nothing to load.
+        return if tablet_blob.is_empty() {
+            Ok(tablet_blob)
+        } else {
+            Err(())
+        };
+    }
+
+    if tablet_metadata.blob_hash != Sha256::digest(&tablet_blob).as_slice() {
+        return Err(());
+    }
+
+    let associated_data = create_associated_data(tablet_metadata);
+    let tablet_key = decrypt(
+        key_wrapping_key,
+        &tablet_metadata.blob_encryption_key,
+        &associated_data,
+    )?;
+    let tablet_contents = decrypt(&tablet_key, &tablet_blob, &associated_data)?;
+
+    Ok(tablet_contents.into())
+}
+
+// Checks if the tablet has been created by the Tablet Store and has never been
+// stored since.
+fn is_initial_tablet(tablet_metadata: &TabletMetadata) -> bool {
+    tablet_metadata.tablet_version == 0
+        && tablet_metadata.blob_uri.is_empty()
+        && tablet_metadata.blob_encryption_key.is_empty()
+        && tablet_metadata.blob_hash.is_empty()
+}
+
+// Encrypts plaintext with a fresh nonce, which is prepended to the ciphertext.
+fn encrypt(key: &[u8], plaintext: &[u8], associated_data: &[u8]) -> Result<Vec<u8>, ()> {
+    let mut nonce = [0; NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = Aes256GcmSiv::new_from_slice(key)
+        .map_err(|_| ())?
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            AeadPayload {
+                msg: plaintext,
+                aad: associated_data,
+            },
+        )
+        .map_err(|_| ())?;
+
+    let mut sealed = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+// Decrypts ciphertext prepended with the nonce it has been encrypted with.
+fn decrypt(key: &[u8], sealed: &[u8], associated_data: &[u8]) -> Result<Vec<u8>, ()> {
+    if sealed.len() < NONCE_SIZE {
+        return Err(());
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+    Aes256GcmSiv::new_from_slice(key)
+        .map_err(|_| ())?
+        .decrypt(
+            Nonce::from_slice(nonce),
+            AeadPayload {
+                msg: ciphertext,
+                aad: associated_data,
+            },
+        )
+        .map_err(|_| ())
+}
+
+fn create_associated_data(tablet_metadata: &TabletMetadata) -> Vec<u8> {
+    let mut associated_data = Vec::with_capacity(8);
+    associated_data.extend_from_slice(&tablet_metadata.tablet_id.to_le_bytes());
+    associated_data.extend_from_slice(&tablet_metadata.tablet_version.to_le_bytes());
+    associated_data
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    const TABLET_CONTENTS: &'static [u8] = b"tablet contents";
+    const KEY_WRAPPING_KEY: [u8; 32] = [1; 32];
+
+    fn create_tablet_metadata() -> TabletMetadata {
+        TabletMetadata {
+            tablet_id: 1,
+            tablet_version: 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_seal_open_success() {
+        let mut tablet_metadata = create_tablet_metadata();
+        let tablet_blob =
+            seal_tablet_contents(&KEY_WRAPPING_KEY, &mut tablet_metadata, TABLET_CONTENTS).unwrap();
+
+        assert_ne!(TABLET_CONTENTS, tablet_blob.as_ref());
+        assert_eq!(tablet_blob.len() as u32, tablet_metadata.blob_size);
+        assert_eq!(
+            Ok(Bytes::from_static(TABLET_CONTENTS)),
+            open_tablet_blob(&KEY_WRAPPING_KEY, &tablet_metadata, tablet_blob)
+        );
+    }
+
+    #[test]
+    fn test_seal_uses_fresh_keys() {
+        let mut tablet_metadata_1 = create_tablet_metadata();
+        let mut tablet_metadata_2 = create_tablet_metadata();
+        let tablet_blob_1 =
+            seal_tablet_contents(&KEY_WRAPPING_KEY, &mut tablet_metadata_1, TABLET_CONTENTS)
+                .unwrap();
+        let tablet_blob_2 =
+            seal_tablet_contents(&KEY_WRAPPING_KEY, &mut tablet_metadata_2, TABLET_CONTENTS)
+                .unwrap();
+
+        assert_ne!(
+            tablet_metadata_1.blob_encryption_key,
+            tablet_metadata_2.blob_encryption_key
+        );
+        assert_ne!(tablet_blob_1, tablet_blob_2);
+    }
+
+    #[test]
+    fn test_rewrap_uses_fresh_nonce() {
+        let mut tablet_metadata = create_tablet_metadata();
+        let tablet_blob =
+            seal_tablet_contents(&KEY_WRAPPING_KEY, &mut tablet_metadata, TABLET_CONTENTS).unwrap();
+        let wrapped_tablet_key = tablet_metadata.blob_encryption_key.clone();
+
+        // Wrapping the same tablet key under the same key again produces another
+        // nonce, and hence another wrapped key.
+        assert_eq!(
+            Ok(()),
+            rewrap_tablet_key(&KEY_WRAPPING_KEY, &KEY_WRAPPING_KEY, &mut tablet_metadata)
+        );
+        assert_ne!(wrapped_tablet_key, tablet_metadata.blob_encryption_key);
+        assert_eq!(
+            wrapped_tablet_key.len(),
+            tablet_metadata.blob_encryption_key.len()
+        );
+        assert_eq!(
+            Ok(Bytes::from_static(TABLET_CONTENTS)),
+            open_tablet_blob(&KEY_WRAPPING_KEY, &tablet_metadata, tablet_blob)
+        );
+    }
+
+    #[test]
+    fn test_open_truncated_blob() {
+        let mut tablet_metadata = create_tablet_metadata();
+        seal_tablet_contents(&KEY_WRAPPING_KEY, &mut tablet_metadata, TABLET_CONTENTS).unwrap();
+
+        // Blob too short to carry the nonce is rejected.
+        let truncated_blob = Bytes::from_static(&[0; NONCE_SIZE - 1]);
+        tablet_metadata.blob_hash = Sha256::digest(&truncated_blob).to_vec().into();
+        assert_eq!(
+            Err(()),
+            open_tablet_blob(&KEY_WRAPPING_KEY, &tablet_metadata, truncated_blob)
+        );
+    }
+
+    #[test]
+    fn test_tablet_key_is_wrapped() {
+        let mut tablet_metadata = create_tablet_metadata();
+        let tablet_blob =
+            seal_tablet_contents(&KEY_WRAPPING_KEY, &mut tablet_metadata, TABLET_CONTENTS).unwrap();
+
+        // Tablet key in the metadata can't be used to decrypt the blob directly.
+        assert_eq!(
+            Err(()),
+            decrypt(
+                &tablet_metadata.blob_encryption_key,
+                &tablet_blob,
+                &create_associated_data(&tablet_metadata)
+            )
+        );
+        // Tablet key can only be unwrapped with the key wrapping key.
+        assert_eq!(
+            Err(()),
+            open_tablet_blob(&[2; 32], &tablet_metadata, tablet_blob)
+        );
+    }
+
+    #[test]
+    fn test_rewrap_tablet_key_across_clusters() {
+        const TRANSFER_KEY: [u8; 32] = [3; 32];
+        const DESTINATION_KEY_WRAPPING_KEY: [u8; 32] = [4; 32];
+        let mut tablet_metadata = create_tablet_metadata();
+        let tablet_blob =
+            seal_tablet_contents(&KEY_WRAPPING_KEY, &mut tablet_metadata, TABLET_CONTENTS).unwrap();
+
+        // Source cluster exports the tablet key under the transfer key.
+        assert_eq!(
+            Ok(()),
+            rewrap_tablet_key(&KEY_WRAPPING_KEY, &TRANSFER_KEY, &mut tablet_metadata)
+        );
+        assert_eq!(
+            Err(()),
+            open_tablet_blob(&KEY_WRAPPING_KEY, &tablet_metadata, tablet_blob.clone())
+        );
+
+        // Destination cluster imports the tablet key under its own key wrapping key.
+        assert_eq!(
+            Ok(()),
+            rewrap_tablet_key(
+                &TRANSFER_KEY,
+                &DESTINATION_KEY_WRAPPING_KEY,
+                &mut tablet_metadata
+            )
+        );
+        assert_eq!(
+            Ok(Bytes::from_static(TABLET_CONTENTS)),
+            open_tablet_blob(&DESTINATION_KEY_WRAPPING_KEY, &tablet_metadata, tablet_blob)
+        );
+
+        // Tablet key wrapped under another key can't be re-wrapped.
+        assert_eq!(
+            Err(()),
+            rewrap_tablet_key(&TRANSFER_KEY, &KEY_WRAPPING_KEY, &mut tablet_metadata)
+        );
+
+        // Initial tablet has no tablet key to re-wrap.
+        let mut initial_tablet_metadata = TabletMetadata {
+            tablet_id: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            Ok(()),
+            rewrap_tablet_key(
+                &KEY_WRAPPING_KEY,
+                &TRANSFER_KEY,
+                &mut initial_tablet_metadata
+            )
+        );
+        assert!(initial_tablet_metadata.blob_encryption_key.is_empty());
+    }
+
+    #[test]
+    fn test_open_tampered_blob() {
+        let mut tablet_metadata = create_tablet_metadata();
+        let tablet_blob =
+            seal_tablet_contents(&KEY_WRAPPING_KEY, &mut tablet_metadata, TABLET_CONTENTS).unwrap();
+
+        let mut tampered_blob = tablet_blob.to_vec();
+        tampered_blob[0] ^= 1;
+        assert_eq!(
+            Err(()),
+            open_tablet_blob(
+                &KEY_WRAPPING_KEY,
+                &tablet_metadata,
+                tampered_blob.clone().into()
+            )
+        );
+
+        // Blob is rejected if the hash is not available.
+        tablet_metadata.blob_hash = Bytes::new();
+        assert_eq!(
+            Err(()),
+            open_tablet_blob(&KEY_WRAPPING_KEY, &tablet_metadata, tablet_blob)
+        );
+    }
+
+    #[test]
+    fn test_open_blob_of_other_version() {
+        let mut tablet_metadata = create_tablet_metadata();
+        let tablet_blob =
+            seal_tablet_contents(&KEY_WRAPPING_KEY, &mut tablet_metadata, TABLET_CONTENTS).unwrap();
+
+        tablet_metadata.tablet_version += 1;
+        assert_eq!(
+            Err(()),
+            open_tablet_blob(&KEY_WRAPPING_KEY, &tablet_metadata, tablet_blob)
+        );
+    }
+
+    #[test]
+    fn test_open_unencrypted_blob() {
+        // Only the initial tablet that has never been stored is loaded unverified,
+        // and only if it is empty.
+        let initial_tablet_metadata = TabletMetadata {
+            tablet_id: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            Ok(Bytes::new()),
+            open_tablet_blob(&KEY_WRAPPING_KEY, &initial_tablet_metadata, Bytes::new())
+        );
+        assert_eq!(
+            Err(()),
+            open_tablet_blob(
+                &KEY_WRAPPING_KEY,
+                &initial_tablet_metadata,
+                Bytes::from_static(TABLET_CONTENTS)
+            )
+        );
+
+        // Blobs of other tablets without the tablet key are rejected.
+        let tablet_metadata = create_tablet_metadata();
+        assert_eq!(
+            Err(()),
+            open_tablet_blob(
+                &KEY_WRAPPING_KEY,
+                &tablet_metadata,
+                Bytes::from_static(TABLET_CONTENTS)
+            )
+        );
+        assert_eq!(
+            Err(()),
+            open_tablet_blob(
+                &KEY_WRAPPING_KEY,
+                &TabletMetadata {
+                    blob_uri: "blob".to_string(),
+                    ..initial_tablet_metadata
+                },
+                Bytes::new()
+            )
+        );
+    }
+}
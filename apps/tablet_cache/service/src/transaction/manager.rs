@@ -14,14 +14,16 @@
 
 use core::cell::RefCell;
 
-use alloc::{boxed::Box, rc::Rc, vec::Vec};
+use alloc::{boxed::Box, rc::Rc, string::String, vec::Vec};
 use hashbrown::HashMap;
+use prost::bytes::Bytes;
 use slog::{o, Logger};
 use tcp_runtime::logger::log::create_logger;
 use tcp_tablet_store_service::apps::tablet_store::service::{
     tablet_op::Op, tablet_op_result::OpResult, AddTabletResult, CheckTabletResult,
-    ListTabletResult, RemoveTabletResult, TabletOp, TabletOpResult, TabletsRequest,
-    TabletsRequestStatus, TabletsResponse, UpdateTabletResult,
+    ExportTabletsResult, ImportTabletsResult, ListTabletResult, RemoveTabletResult, TabletMetadata,
+    TabletOp, TabletOpResult, TabletsRequest, TabletsRequestStatus, TabletsResponse,
+    UpdateTabletResult,
 };
 
 use crate::apps::tablet_cache::service::{ExecuteTabletOpsRequest, TransactionManagerConfig};
@@ -33,12 +35,13 @@ use super::{
     },
     data::{
         DefaultTabletDataCache, TabletDataCache, TabletDataCacheInMessage,
-        TabletDataCacheOutMessage,
+        TabletDataCacheOutMessage, TabletDataCacheStats,
     },
     metadata::{
         DefaultTabletMetadataCache, TabletMetadataCache, TabletMetadataCacheInMessage,
         TabletMetadataCacheOutMessage,
     },
+    result::ResultHandle,
     InMessage, OutMessage, ProcessHandler, ResolveHandler, TableQuery, TabletTransaction,
     TabletTransactionCommit, TabletTransactionContext, TabletTransactionManager,
     TabletTransactionOutcome,
@@ -70,8 +73,15 @@ impl<T: 'static> TabletTransactionManager<T> for DefaultTabletTransactionManager
         self.core.borrow_mut().init(logger, config);
     }
 
-    fn make_progress(&mut self, instant: u64) {
-        self.core.borrow_mut().make_progress(instant)
+    fn set_key_wrapping_key(&mut self, key_wrapping_key: Bytes) {
+        let mut core = self.core.borrow_mut();
+        core.metadata_cache
+            .set_key_wrapping_key(key_wrapping_key.clone());
+        core.data_cache.set_key_wrapping_key(key_wrapping_key);
+    }
+
+    fn make_progress(&mut self) {
+        self.core.borrow_mut().make_progress()
     }
 
     fn process_in_message(&mut self, message: InMessage) {
@@ -81,6 +91,10 @@ impl<T: 'static> TabletTransactionManager<T> for DefaultTabletTransactionManager
     fn take_out_messages(&mut self) -> Vec<OutMessage> {
         self.core.borrow_mut().take_out_messages()
     }
+
+    fn get_data_cache_stats(&self) -> TabletDataCacheStats {
+        self.core.borrow().data_cache.get_stats()
+    }
 }
 
 impl<T: 'static> TabletTransactionContext<T> for DefaultTabletTransactionManager<T> {
@@ -94,6 +108,36 @@ impl<T: 'static> TabletTransactionContext<T> for DefaultTabletTransactionManager
             self.core.clone(),
         ))
     }
+
+    fn export_tablets(
+        &mut self,
+        table_name: String,
+        transfer_key: Bytes,
+    ) -> ResultHandle<Vec<TabletMetadata>, TabletsRequestStatus> {
+        self.core
+            .borrow_mut()
+            .export_tablets(table_name, transfer_key)
+    }
+
+    fn import_tablets(
+        &mut self,
+        table_name: String,
+        tablets: Vec<TabletMetadata>,
+        transfer_key: Bytes,
+    ) -> ResultHandle<(), TabletsRequestStatus> {
+        self.core
+            .borrow_mut()
+            .import_tablets(table_name, tablets, transfer_key)
+    }
+
+    fn start_read_transaction(&mut self, as_of_version: u64) -> Box<dyn TabletTransaction<T>> {
+        Box::new(DefaultTabletTransaction::create(
+            self.core
+                .borrow_mut()
+                .create_read_transaction(as_of_version),
+            self.core.clone(),
+        ))
+    }
 }
 
 struct DefaultTabletTransaction<T> {
@@ -202,14 +246,11 @@ impl<T> TabletTransactionManagerCore<T> {
             .init(transaction_coordinator_logger);
     }
 
-    fn make_progress(&mut self, instant: u64) {
-        self.metadata_cache.make_progress(instant);
-        self.data_cache.make_progress(instant);
-        self.transaction_coordinator.make_progress(
-            instant,
-            &mut *self.metadata_cache,
-            &mut *self.data_cache,
-        );
+    fn make_progress(&mut self) {
+        self.metadata_cache.make_progress();
+        self.data_cache.make_progress();
+        self.transaction_coordinator
+            .make_progress(&mut *self.metadata_cache, &mut *self.data_cache);
     }
 
     fn resolve(&mut self, queries: Vec<TableQuery>, handler: Box<ResolveHandler>) {
@@ -217,6 +258,24 @@ impl<T> TabletTransactionManagerCore<T> {
             .resolve_tablets_with_handler(&queries, handler);
     }
 
+    fn export_tablets(
+        &mut self,
+        table_name: String,
+        transfer_key: Bytes,
+    ) -> ResultHandle<Vec<TabletMetadata>, TabletsRequestStatus> {
+        self.metadata_cache.export_tablets(table_name, transfer_key)
+    }
+
+    fn import_tablets(
+        &mut self,
+        table_name: String,
+        tablets: Vec<TabletMetadata>,
+        transfer_key: Bytes,
+    ) -> ResultHandle<(), TabletsRequestStatus> {
+        self.metadata_cache
+            .import_tablets(table_name, tablets, transfer_key)
+    }
+
     fn process_transaction(
         &mut self,
         transaction_id: u64,
@@ -231,6 +290,11 @@ impl<T> TabletTransactionManagerCore<T> {
         self.transaction_coordinator.create_transaction()
     }
 
+    fn create_read_transaction(&mut self, as_of_version: u64) -> u64 {
+        self.transaction_coordinator
+            .create_read_transaction(as_of_version)
+    }
+
     fn has_transaction_pending_process(&self, transaction_id: u64) -> bool {
         self.transaction_coordinator
             .has_transaction_pending_process(transaction_id)
@@ -283,6 +347,12 @@ impl<T> TabletTransactionManagerCore<T> {
                     self.process_in_tablets_op_response(correlation_id, tablets_error);
                 }
             }
+            InMessage::DeleteTabletResponse(correlation_id, delete_tablet_response) => self
+                .data_cache
+                .process_in_message(TabletDataCacheInMessage::DeleteResponse(
+                    correlation_id,
+                    delete_tablet_response,
+                )),
         }
     }
 
@@ -301,6 +371,7 @@ impl<T> TabletTransactionManagerCore<T> {
                             .iter()
                             .map(|tablet_op| create_failed_op_result(tablet_op))
                             .collect(),
+                        metadata_version: 0,
                     };
 
                     assert!(self
@@ -330,6 +401,7 @@ impl<T> TabletTransactionManagerCore<T> {
                             .iter()
                             .map(|tablet_op| create_failed_op_result(tablet_op))
                             .collect(),
+                        metadata_version: 0,
                     };
 
                     assert!(self
@@ -364,6 +436,9 @@ impl<T> TabletTransactionManagerCore<T> {
                     store_tablet_request,
                     tablet_data,
                 ),
+                TabletDataCacheOutMessage::DeleteRequest(correlation_id, delete_tablet_request) => {
+                    OutMessage::DeleteTabletRequest(correlation_id, delete_tablet_request)
+                }
             });
         }
 
@@ -380,7 +455,11 @@ impl<T> TabletTransactionManagerCore<T> {
 
         for tablet_op_result in tablets_response.tablet_results {
             if let Some(op_result) = &tablet_op_result.op_result {
-                if let OpResult::ListTablet(_) = op_result {
+                // Listing, export and import of tablets are handled by the metadata cache.
+                if let OpResult::ListTablet(_)
+                | OpResult::ExportTablets(_)
+                | OpResult::ImportTablets(_) = op_result
+                {
                     list_op_results.push(tablet_op_result);
                 } else {
                     execute_op_results.push(tablet_op_result);
@@ -419,6 +498,8 @@ fn create_failed_op_result(tablet_op: &TabletOp) -> TabletOpResult {
         Some(Op::UpdateTablet(_)) => Some(OpResult::UpdateTablet(UpdateTabletResult::default())),
         Some(Op::AddTablet(_)) => Some(OpResult::AddTablet(AddTabletResult::default())),
         Some(Op::RemoveTablet(_)) => Some(OpResult::RemoveTablet(RemoveTabletResult::default())),
+        Some(Op::ExportTablets(_)) => Some(OpResult::ExportTablets(ExportTabletsResult::default())),
+        Some(Op::ImportTablets(_)) => Some(OpResult::ImportTablets(ImportTabletsResult::default())),
         None => None,
     };
     TabletOpResult {
@@ -17,22 +17,25 @@ use core::mem;
 use crate::apps::tablet_cache::service::{TableMetadataCacheConfig, TabletMetadataCacheConfig};
 use alloc::{
     boxed::Box,
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
     fmt::format,
     string::{String, ToString},
     vec::Vec,
 };
 use hashbrown::{hash_map::Entry, HashMap, HashSet};
+use prost::bytes::Bytes;
 use slog::Logger;
 use tcp_runtime::logger::log::create_logger;
 use tcp_tablet_store_service::apps::tablet_store::service::{
     tablet_op::{self, Op},
-    tablet_op_result, ExecuteTabletOpsRequest, ListTabletOp, ListTabletResult, TabletMetadata,
-    TabletOp, TabletOpResult, TabletOpStatus, TabletsRequestStatus,
+    tablet_op_result, ExecuteTabletOpsRequest, ExportTabletsOp, ImportTabletsOp, ListTabletOp,
+    ListTabletResult, TabletMetadata, TabletOp, TabletOpResult, TabletOpStatus,
+    TabletsRequestStatus,
 };
 
 use super::{
-    result::{create_eventual_result, ResultHandle, ResultSource},
+    envelope::rewrap_tablet_key,
+    result::{create_eventual_result, create_result_from_error, ResultHandle, ResultSource},
     ResolveHandler, TableQuery, TabletDescriptor,
 };
 
@@ -60,8 +63,12 @@ pub trait TabletMetadataCache {
     // Initializes tablet metadata cache.
     fn init(&mut self, logger: Logger, config: TabletMetadataCacheConfig);
 
+    // Sets the key the tablet keys are wrapped under, used to re-wrap the tablet
+    // keys of exported and imported tablets.
+    fn set_key_wrapping_key(&mut self, key_wrapping_key: Bytes);
+
     // Advances internal state machine of the tablet metadata cache.
-    fn make_progress(&mut self, instant: u64);
+    fn make_progress(&mut self);
 
     // Requests to resolve tablets that given set of the table queries affect. Returned
     // result handle must be checked for the operation completion. The operation is
@@ -80,6 +87,38 @@ pub trait TabletMetadataCache {
         handler: Box<ResolveHandler>,
     );
 
+    // Requests to resolve tablets that given set of the table queries affect as of
+    // the given Tablet Store metadata version. Bypasses cached regions and lists
+    // historical tablets directly from the Tablet Store. Returned result handle must
+    // be checked for the operation completion.
+    fn resolve_tablets_as_of(
+        &mut self,
+        queries: &Vec<TableQuery>,
+        as_of_version: u64,
+    ) -> ResultHandle<Vec<(TableQuery, TabletMetadata)>, TabletsRequestStatus>;
+
+    // Requests to export metadata of all tablets of the given table. Tablets are
+    // exported from the Tablet Store page by page, all pages as of the metadata
+    // version of the first one. Tablet keys are re-wrapped under the transfer key
+    // so that the importing cluster can unwrap them. Returned result handle must
+    // be checked for the operation completion.
+    fn export_tablets(
+        &mut self,
+        table_name: String,
+        transfer_key: Bytes,
+    ) -> ResultHandle<Vec<TabletMetadata>, TabletsRequestStatus>;
+
+    // Requests to import externally provided tablets into the given table. Tablet
+    // keys wrapped under the transfer key are re-wrapped under the key wrapping key
+    // of this cluster. Once import succeeds all cached metadata for the table is
+    // considered stale.
+    fn import_tablets(
+        &mut self,
+        table_name: String,
+        tablets: Vec<TabletMetadata>,
+        transfer_key: Bytes,
+    ) -> ResultHandle<(), TabletsRequestStatus>;
+
     // Instructs cache to update tablet metadata. Metadata maybe updated after
     // transaction execution.
     fn update_tablet(
@@ -101,8 +140,12 @@ pub struct DefaultTabletMetadataCache {
     correlation_counter: u64,
     resolve_request_counter: u64,
     config: TabletMetadataCacheConfig,
+    key_wrapping_key: Bytes,
     tables: HashMap<String, TableMetadata>,
     resolve_requests: HashMap<u64, TabletResolve>,
+    // Maps correlation id of the Tablet Store request to a pending request that
+    // bypasses cached regions.
+    direct_requests: HashMap<u64, DirectTabletsRequest>,
     out_messages: Vec<TabletMetadataCacheOutMessage>,
 }
 
@@ -113,8 +156,10 @@ impl DefaultTabletMetadataCache {
             correlation_counter,
             resolve_request_counter: 1,
             config: TabletMetadataCacheConfig::default(),
+            key_wrapping_key: Bytes::new(),
             tables: HashMap::new(),
             resolve_requests: HashMap::new(),
+            direct_requests: HashMap::new(),
             out_messages: Vec::new(),
         }
     }
@@ -134,6 +179,52 @@ impl DefaultTabletMetadataCache {
         self.resolve_requests
             .insert(self.resolve_request_counter, resolve_request);
     }
+
+    // Stashes outgoing Tablet Store request and remembers the pending request
+    // that will process the response.
+    fn stash_direct_request(&mut self, tablet_ops: Vec<TabletOp>, request: DirectTabletsRequest) {
+        self.correlation_counter += 1;
+        self.out_messages
+            .push(TabletMetadataCacheOutMessage::ListRequest(
+                self.correlation_counter,
+                tablet_ops,
+            ));
+        self.direct_requests
+            .insert(self.correlation_counter, request);
+    }
+
+    fn process_direct_results(
+        &mut self,
+        request: DirectTabletsRequest,
+        tablet_op_results: Vec<TabletOpResult>,
+    ) {
+        match request {
+            DirectTabletsRequest::ResolveAsOf(as_of_resolve_request) => {
+                as_of_resolve_request.process_results(tablet_op_results);
+            }
+            DirectTabletsRequest::Export(mut export_request) => {
+                if let Some(next_tablet_id_from) =
+                    export_request.process_results(tablet_op_results, &self.key_wrapping_key)
+                {
+                    // Request the next page of tablets.
+                    let export_op = export_request.create_export_op(next_tablet_id_from);
+                    self.stash_direct_request(
+                        vec![export_op],
+                        DirectTabletsRequest::Export(export_request),
+                    );
+                }
+            }
+            DirectTabletsRequest::Import(mut import_request) => {
+                if import_request.process_results(tablet_op_results) {
+                    // Imported tablets replaced existing ones, so everything known
+                    // about the table must be refreshed.
+                    if let Some(table_metadata) = self.tables.get_mut(&import_request.table_name) {
+                        table_metadata.mark_stale();
+                    }
+                }
+            }
+        }
+    }
 }
 
 impl TabletMetadataCache for DefaultTabletMetadataCache {
@@ -152,7 +243,11 @@ impl TabletMetadataCache for DefaultTabletMetadataCache {
         }
     }
 
-    fn make_progress(&mut self, _instant: u64) {
+    fn set_key_wrapping_key(&mut self, key_wrapping_key: Bytes) {
+        self.key_wrapping_key = key_wrapping_key;
+    }
+
+    fn make_progress(&mut self) {
         let mut list_ops = Vec::new();
         for table_metadata in self.tables.values_mut() {
             table_metadata.prepare_list_op(&mut list_ops);
@@ -191,6 +286,102 @@ impl TabletMetadataCache for DefaultTabletMetadataCache {
         self.resolve_tablets_with_notification(queries, TableResolveNotification::Handler(handler));
     }
 
+    fn resolve_tablets_as_of(
+        &mut self,
+        queries: &Vec<TableQuery>,
+        as_of_version: u64,
+    ) -> ResultHandle<Vec<(TableQuery, TabletMetadata)>, TabletsRequestStatus> {
+        let (result_handle, mut result_source) =
+            create_eventual_result::<Vec<(TableQuery, TabletMetadata)>, TabletsRequestStatus>();
+
+        let queries: Vec<TableQuery> = queries
+            .iter()
+            .filter(|query| !query.get_key_hashes().is_empty())
+            .cloned()
+            .collect();
+        if queries.is_empty() {
+            result_source.set_result(Vec::new());
+            return result_handle;
+        }
+
+        // List tablets covering the range of key hashes of each query as of the
+        // requested version.
+        let mut list_ops = Vec::with_capacity(queries.len());
+        for query in &queries {
+            list_ops.push(TabletOp {
+                table_name: query.get_table_name().clone(),
+                op: Some(tablet_op::Op::ListTablet(ListTabletOp {
+                    key_hash_from: *query.get_key_hashes().first().unwrap(),
+                    key_hash_to: query.get_key_hashes().last().unwrap().wrapping_add(1),
+                    as_of_version,
+                })),
+            });
+        }
+
+        self.stash_direct_request(
+            list_ops,
+            DirectTabletsRequest::ResolveAsOf(TabletResolveAsOf {
+                queries,
+                result_source,
+            }),
+        );
+
+        result_handle
+    }
+
+    fn export_tablets(
+        &mut self,
+        table_name: String,
+        transfer_key: Bytes,
+    ) -> ResultHandle<Vec<TabletMetadata>, TabletsRequestStatus> {
+        let (result_handle, result_source) =
+            create_eventual_result::<Vec<TabletMetadata>, TabletsRequestStatus>();
+
+        let export_request = TabletExport {
+            table_name,
+            as_of_version: None,
+            transfer_key,
+            tablets: Vec::new(),
+            result_source,
+        };
+        let export_op = export_request.create_export_op(0);
+        self.stash_direct_request(
+            vec![export_op],
+            DirectTabletsRequest::Export(export_request),
+        );
+
+        result_handle
+    }
+
+    fn import_tablets(
+        &mut self,
+        table_name: String,
+        mut tablets: Vec<TabletMetadata>,
+        transfer_key: Bytes,
+    ) -> ResultHandle<(), TabletsRequestStatus> {
+        for tablet_metadata in &mut tablets {
+            if rewrap_tablet_key(&transfer_key, &self.key_wrapping_key, tablet_metadata).is_err() {
+                return create_result_from_error(TabletsRequestStatus::Failed);
+            }
+        }
+
+        let (result_handle, result_source) = create_eventual_result::<(), TabletsRequestStatus>();
+
+        let import_op = TabletOp {
+            table_name: table_name.clone(),
+            op: Some(tablet_op::Op::ImportTablets(ImportTabletsOp { tablets })),
+        };
+        self.stash_direct_request(
+            vec![import_op],
+            DirectTabletsRequest::Import(TabletImport {
+                table_name,
+                result_source,
+            }),
+        );
+
+        result_handle
+    }
+
     fn update_tablet(
         &mut self,
         table_name: String,
@@ -205,6 +396,13 @@ impl TabletMetadataCache for DefaultTabletMetadataCache {
     fn process_in_message(&mut self, in_message: TabletMetadataCacheInMessage) {
         match in_message {
             TabletMetadataCacheInMessage::ListResponse(correlation_id, list_op_results) => {
+                // Responses to the direct requests, such as resolving as of a past version
+                // or export, must not affect cached latest tablets metadata.
+                if let Some(direct_request) = self.direct_requests.remove(&correlation_id) {
+                    self.process_direct_results(direct_request, list_op_results);
+                    return;
+                }
+
                 for list_op_result in list_op_results {
                     if let Some(tablet_op_result::OpResult::ListTablet(list_tablet_result)) =
                         list_op_result.op_result
@@ -284,6 +482,7 @@ impl TableRegion {
                 return Some(tablet_op::Op::ListTablet(ListTabletOp {
                     key_hash_from: region_from,
                     key_hash_to: region_to,
+                    as_of_version: 0,
                 }));
             }
         }
@@ -400,6 +599,14 @@ impl TableMetadata {
         }
     }
 
+    // Marks all regions of the table as stale and forgets known tablets.
+    fn mark_stale(&mut self) {
+        for table_region in self.regions.values_mut() {
+            table_region.mark_stale();
+        }
+        self.tablets.clear();
+    }
+
     fn update_tablet(&mut self, tablet_metadata: TabletMetadata, conflict: bool) {
         let tablet_id = tablet_metadata.tablet_id;
         Self::insert_latest_tablet(&mut self.tablets, tablet_metadata);
@@ -675,6 +882,159 @@ impl TabletResolve {
     }
 }
 
+// Tracks pending request that is sent directly to the Tablet Store bypassing
+// cached regions.
+enum DirectTabletsRequest {
+    ResolveAsOf(TabletResolveAsOf),
+    Export(TabletExport),
+    Import(TabletImport),
+}
+
+// Maximum number of tablets to export from the Tablet Store in one page.
+const EXPORT_PAGE_SIZE: u32 = 128;
+
+// Tracks pending request to export tablets of a table page by page.
+struct TabletExport {
+    table_name: String,
+    // Tablet Store metadata version the first page has been exported as of.
+    as_of_version: Option<u64>,
+    // Key the tablet keys of the exported tablets are re-wrapped under.
+    transfer_key: Bytes,
+    // Tablets exported so far.
+    tablets: Vec<TabletMetadata>,
+    result_source: ResultSource<Vec<TabletMetadata>, TabletsRequestStatus>,
+}
+
+impl TabletExport {
+    fn create_export_op(&self, tablet_id_from: u32) -> TabletOp {
+        TabletOp {
+            table_name: self.table_name.clone(),
+            op: Some(tablet_op::Op::ExportTablets(ExportTabletsOp {
+                tablet_id_from,
+                max_tablets: EXPORT_PAGE_SIZE,
+                as_of_version: self.as_of_version,
+            })),
+        }
+    }
+
+    // Accumulates exported page of tablets with their tablet keys re-wrapped under
+    // the transfer key. Returns the tablet id to export the next page from if there
+    // are more tablets to export.
+    fn process_results(
+        &mut self,
+        tablet_op_results: Vec<TabletOpResult>,
+        key_wrapping_key: &[u8],
+    ) -> Option<u32> {
+        match tablet_op_results.into_iter().next() {
+            Some(TabletOpResult {
+                status,
+                op_result: Some(tablet_op_result::OpResult::ExportTablets(export_tablets_result)),
+                ..
+            }) if status == TabletOpStatus::Succeeded as i32 => {
+                // Remaining pages are exported as of the version of the first page.
+                self.as_of_version = Some(export_tablets_result.metadata_version);
+                for mut tablet_metadata in export_tablets_result.tablets {
+                    if rewrap_tablet_key(key_wrapping_key, &self.transfer_key, &mut tablet_metadata)
+                        .is_err()
+                    {
+                        self.result_source.set_error(TabletsRequestStatus::Failed);
+                        return None;
+                    }
+                    self.tablets.push(tablet_metadata);
+                }
+                if export_tablets_result.has_more {
+                    return Some(export_tablets_result.next_tablet_id_from);
+                }
+                self.result_source.set_result(mem::take(&mut self.tablets));
+            }
+            _ => self.result_source.set_error(TabletsRequestStatus::Failed),
+        }
+        None
+    }
+}
+
+// Tracks pending request to import tablets into a table.
+struct TabletImport {
+    table_name: String,
+    result_source: ResultSource<(), TabletsRequestStatus>,
+}
+
+impl TabletImport {
+    // Completes import request. Returns true if import succeeded.
+    fn process_results(&mut self, tablet_op_results: Vec<TabletOpResult>) -> bool {
+        let succeeded = tablet_op_results.len() == 1
+            && tablet_op_results[0].status == TabletOpStatus::Succeeded as i32;
+        if succeeded {
+            self.result_source.set_result(());
+        } else {
+            self.result_source.set_error(TabletsRequestStatus::Failed);
+        }
+        succeeded
+    }
+}
+
+// Tracks pending request to resolve tablets as of a past metadata version. Each
+// query corresponds to a list op at the same position in the list request.
+struct TabletResolveAsOf {
+    queries: Vec<TableQuery>,
+    result_source: ResultSource<Vec<(TableQuery, TabletMetadata)>, TabletsRequestStatus>,
+}
+
+impl TabletResolveAsOf {
+    fn process_results(mut self, list_op_results: Vec<TabletOpResult>) {
+        if list_op_results.len() != self.queries.len() {
+            self.result_source.set_error(TabletsRequestStatus::Failed);
+            return;
+        }
+
+        let mut results = Vec::new();
+        for (query, list_op_result) in self.queries.iter().zip(list_op_results.into_iter()) {
+            let listed_tablets = match list_op_result.op_result {
+                Some(tablet_op_result::OpResult::ListTablet(list_tablet_result))
+                    if list_op_result.status == TabletOpStatus::Succeeded as i32
+                        && !list_tablet_result.tablets.is_empty() =>
+                {
+                    list_tablet_result.tablets
+                }
+                _ => {
+                    self.result_source.set_error(TabletsRequestStatus::Failed);
+                    return;
+                }
+            };
+
+            let tablets: BTreeMap<u32, TabletMetadata> = listed_tablets
+                .into_iter()
+                .map(|tablet_metadata| (tablet_metadata.tablet_id, tablet_metadata))
+                .collect();
+
+            // Key hashes are handled by a tablet that is clockwise on the consistent
+            // hashing ring, possibly wrapping around zero.
+            let mut tablet_key_hashes: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+            for key_hash in query.get_key_hashes() {
+                let tablet_id = tablets
+                    .range(*key_hash..)
+                    .next()
+                    .or_else(|| tablets.first_key_value())
+                    .map(|(tablet_id, _)| *tablet_id)
+                    .unwrap();
+                tablet_key_hashes
+                    .entry(tablet_id)
+                    .or_default()
+                    .push(*key_hash);
+            }
+
+            for (tablet_id, key_hashes) in tablet_key_hashes {
+                results.push((
+                    query.create_from(key_hashes),
+                    tablets.get(&tablet_id).unwrap().clone(),
+                ));
+            }
+        }
+
+        self.result_source.set_result(results);
+    }
+}
+
 struct TabletResolveResult {
     table_query_id: u64,
     table_name: String,
@@ -709,8 +1069,10 @@ impl TabletResolveResult {
 #[cfg(all(test, feature = "std"))]
 mod tests {
     use tcp_proto::runtime::endpoint::in_message;
+    use tcp_tablet_store_service::apps::tablet_store::service::ExportTabletsResult;
 
     use super::*;
+    use crate::transaction::envelope::{open_tablet_blob, seal_tablet_contents};
 
     const TABLE_NAME: &'static str = "A";
     const TABLE_QUERY_1: u64 = 1;
@@ -729,6 +1091,11 @@ mod tests {
     const CORRELATION_ID_1: u64 = 1;
     const CORRELATION_ID_2: u64 = 2;
 
+    const AS_OF_VERSION: u64 = 7;
+
+    const KEY_WRAPPING_KEY: [u8; 32] = [1; 32];
+    const TRANSFER_KEY: [u8; 32] = [2; 32];
+
     fn create_tablet_metadata_cache(
         table_name: String,
         region_size: u32,
@@ -766,6 +1133,7 @@ mod tests {
             op: Some(tablet_op::Op::ListTablet(ListTabletOp {
                 key_hash_from,
                 key_hash_to,
+                as_of_version: 0,
             })),
         }
     }
@@ -805,10 +1173,9 @@ mod tests {
 
         fn execute_step(
             &mut self,
-            instant: u64,
             in_message: Option<TabletMetadataCacheInMessage>,
         ) -> Vec<TabletMetadataCacheOutMessage> {
-            self.tablet_metadata_cache.make_progress(instant);
+            self.tablet_metadata_cache.make_progress();
 
             let out_messages = self.tablet_metadata_cache.take_out_messages();
 
@@ -851,9 +1218,8 @@ mod tests {
                     )
                 ]
             )],
-            tablet_metadata_cache_loop.execute_step(
-                1,
-                Some(TabletMetadataCacheInMessage::ListResponse(
+            tablet_metadata_cache_loop.execute_step(Some(
+                TabletMetadataCacheInMessage::ListResponse(
                     CORRELATION_ID_1,
                     vec![
                         create_list_op_result(
@@ -871,11 +1237,11 @@ mod tests {
                             vec![create_tablet_metadata(TABLET_ID_2, TABLET_VERSION_2)]
                         )
                     ]
-                ))
-            )
+                )
+            ))
         );
 
-        assert!(tablet_metadata_cache_loop.execute_step(2, None).is_empty());
+        assert!(tablet_metadata_cache_loop.execute_step(None).is_empty());
 
         assert_eq!(
             Some(Ok(vec![
@@ -899,4 +1265,249 @@ mod tests {
             resolve_result_1.check_result()
         );
     }
+
+    #[test]
+    fn test_resolve_tablets_as_of_success() {
+        let tablet_metadata_cache =
+            create_tablet_metadata_cache(TABLE_NAME.to_string(), TABLE_REGION_SIZE);
+        let mut tablet_metadata_cache_loop = TabletMetadataCacheLoop::create(tablet_metadata_cache);
+
+        let table_query_1 = create_table_query(
+            TABLE_QUERY_1,
+            TABLE_NAME.to_string(),
+            vec![KEY_HASH_1, KEY_HASH_2, KEY_HASH_3],
+        );
+
+        let resolve_result_1 = tablet_metadata_cache_loop
+            .get_mut()
+            .resolve_tablets_as_of(&vec![table_query_1.clone()], AS_OF_VERSION);
+
+        assert!(resolve_result_1.check_result().is_none());
+
+        assert_eq!(
+            vec![TabletMetadataCacheOutMessage::ListRequest(
+                CORRELATION_ID_1,
+                vec![TabletOp {
+                    table_name: TABLE_NAME.to_string(),
+                    op: Some(tablet_op::Op::ListTablet(ListTabletOp {
+                        key_hash_from: KEY_HASH_1,
+                        key_hash_to: KEY_HASH_3 + 1,
+                        as_of_version: AS_OF_VERSION,
+                    })),
+                }]
+            )],
+            tablet_metadata_cache_loop.execute_step(Some(
+                TabletMetadataCacheInMessage::ListResponse(
+                    CORRELATION_ID_1,
+                    vec![create_list_op_result(
+                        TABLE_NAME.to_string(),
+                        KEY_HASH_1,
+                        KEY_HASH_3 + 1,
+                        TabletOpStatus::Succeeded,
+                        vec![
+                            create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1),
+                            create_tablet_metadata(TABLET_ID_2, TABLET_VERSION_2)
+                        ]
+                    )]
+                )
+            ))
+        );
+
+        // Historical listing must not be used to populate cached regions.
+        assert!(tablet_metadata_cache_loop.execute_step(None).is_empty());
+
+        assert_eq!(
+            Some(Ok(vec![
+                (
+                    create_table_query(
+                        TABLE_QUERY_1,
+                        TABLE_NAME.to_string(),
+                        vec![KEY_HASH_1, KEY_HASH_2],
+                    ),
+                    create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1)
+                ),
+                (
+                    create_table_query(TABLE_QUERY_1, TABLE_NAME.to_string(), vec![KEY_HASH_3]),
+                    create_tablet_metadata(TABLET_ID_2, TABLET_VERSION_2)
+                )
+            ])),
+            resolve_result_1.check_result()
+        );
+    }
+
+    fn create_export_op(
+        table_name: String,
+        tablet_id_from: u32,
+        as_of_version: Option<u64>,
+    ) -> TabletOp {
+        TabletOp {
+            table_name,
+            op: Some(tablet_op::Op::ExportTablets(ExportTabletsOp {
+                tablet_id_from,
+                max_tablets: EXPORT_PAGE_SIZE,
+                as_of_version,
+            })),
+        }
+    }
+
+    fn create_export_op_result(
+        table_name: String,
+        tablets: Vec<TabletMetadata>,
+        next_tablet_id_from: Option<u32>,
+    ) -> TabletOpResult {
+        TabletOpResult {
+            table_name,
+            status: TabletOpStatus::Succeeded.into(),
+            op_result: Some(tablet_op_result::OpResult::ExportTablets(
+                ExportTabletsResult {
+                    tablets,
+                    has_more: next_tablet_id_from.is_some(),
+                    next_tablet_id_from: next_tablet_id_from.unwrap_or_default(),
+                    metadata_version: AS_OF_VERSION,
+                },
+            )),
+        }
+    }
+
+    #[test]
+    fn test_export_tablets_success() {
+        let tablet_metadata_cache =
+            create_tablet_metadata_cache(TABLE_NAME.to_string(), TABLE_REGION_SIZE);
+        let mut tablet_metadata_cache_loop = TabletMetadataCacheLoop::create(tablet_metadata_cache);
+
+        let export_result_1 = tablet_metadata_cache_loop
+            .get_mut()
+            .export_tablets(TABLE_NAME.to_string(), Bytes::from_static(&TRANSFER_KEY));
+
+        assert_eq!(
+            vec![TabletMetadataCacheOutMessage::ListRequest(
+                CORRELATION_ID_1,
+                vec![create_export_op(TABLE_NAME.to_string(), 0, None)]
+            )],
+            tablet_metadata_cache_loop.execute_step(Some(
+                TabletMetadataCacheInMessage::ListResponse(
+                    CORRELATION_ID_1,
+                    vec![create_export_op_result(
+                        TABLE_NAME.to_string(),
+                        vec![create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1)],
+                        Some(TABLET_ID_2)
+                    )]
+                )
+            ))
+        );
+
+        assert!(export_result_1.check_result().is_none());
+
+        assert_eq!(
+            vec![TabletMetadataCacheOutMessage::ListRequest(
+                CORRELATION_ID_2,
+                // The next page is exported as of the version of the first one.
+                vec![create_export_op(
+                    TABLE_NAME.to_string(),
+                    TABLET_ID_2,
+                    Some(AS_OF_VERSION)
+                )]
+            )],
+            tablet_metadata_cache_loop.execute_step(Some(
+                TabletMetadataCacheInMessage::ListResponse(
+                    CORRELATION_ID_2,
+                    vec![create_export_op_result(
+                        TABLE_NAME.to_string(),
+                        vec![create_tablet_metadata(TABLET_ID_2, TABLET_VERSION_2)],
+                        None
+                    )]
+                )
+            ))
+        );
+
+        assert_eq!(
+            Some(Ok(vec![
+                create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1),
+                create_tablet_metadata(TABLET_ID_2, TABLET_VERSION_2)
+            ])),
+            export_result_1.check_result()
+        );
+    }
+
+    #[test]
+    fn test_export_import_tablets_across_clusters() {
+        const TABLET_CONTENTS: &'static [u8] = b"tablet contents";
+        const DESTINATION_KEY_WRAPPING_KEY: [u8; 32] = [3; 32];
+        let mut tablet_metadata = create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1);
+        let tablet_blob =
+            seal_tablet_contents(&KEY_WRAPPING_KEY, &mut tablet_metadata, TABLET_CONTENTS).unwrap();
+
+        // Source cluster exports the tablet with its key wrapped under the transfer key.
+        let mut source_tablet_metadata_cache =
+            create_tablet_metadata_cache(TABLE_NAME.to_string(), TABLE_REGION_SIZE);
+        source_tablet_metadata_cache.set_key_wrapping_key(Bytes::from_static(&KEY_WRAPPING_KEY));
+        let mut source_loop = TabletMetadataCacheLoop::create(source_tablet_metadata_cache);
+        let export_result = source_loop
+            .get_mut()
+            .export_tablets(TABLE_NAME.to_string(), Bytes::from_static(&TRANSFER_KEY));
+        assert_eq!(
+            vec![TabletMetadataCacheOutMessage::ListRequest(
+                CORRELATION_ID_1,
+                vec![create_export_op(TABLE_NAME.to_string(), 0, None)]
+            )],
+            source_loop.execute_step(Some(TabletMetadataCacheInMessage::ListResponse(
+                CORRELATION_ID_1,
+                vec![create_export_op_result(
+                    TABLE_NAME.to_string(),
+                    vec![tablet_metadata.clone()],
+                    None
+                )]
+            )))
+        );
+        let exported_tablets = export_result.check_result().unwrap().unwrap();
+        assert_eq!(exported_tablets.len(), 1);
+        assert_eq!(
+            Err(()),
+            open_tablet_blob(&KEY_WRAPPING_KEY, &exported_tablets[0], tablet_blob.clone())
+        );
+
+        // Destination cluster imports the tablet with its key wrapped under its own
+        // key wrapping key.
+        let mut destination_tablet_metadata_cache =
+            create_tablet_metadata_cache(TABLE_NAME.to_string(), TABLE_REGION_SIZE);
+        destination_tablet_metadata_cache
+            .set_key_wrapping_key(Bytes::from_static(&DESTINATION_KEY_WRAPPING_KEY));
+        let mut destination_loop =
+            TabletMetadataCacheLoop::create(destination_tablet_metadata_cache);
+        let import_result = destination_loop.get_mut().import_tablets(
+            TABLE_NAME.to_string(),
+            exported_tablets.clone(),
+            Bytes::from_static(&TRANSFER_KEY),
+        );
+        let out_messages = destination_loop.execute_step(None);
+        let [TabletMetadataCacheOutMessage::ListRequest(_, import_ops)] = out_messages.as_slice()
+        else {
+            panic!("Import request expected");
+        };
+        let Some(tablet_op::Op::ImportTablets(import_tablets_op)) = &import_ops[0].op else {
+            panic!("Import tablets op expected");
+        };
+        assert_eq!(
+            Ok(Bytes::from_static(TABLET_CONTENTS)),
+            open_tablet_blob(
+                &DESTINATION_KEY_WRAPPING_KEY,
+                &import_tablets_op.tablets[0],
+                tablet_blob
+            )
+        );
+        assert!(import_result.check_result().is_none());
+
+        // Tablets with keys not wrapped under the transfer key are rejected.
+        assert_eq!(
+            Some(Err(TabletsRequestStatus::Failed)),
+            destination_loop
+                .get_mut()
+                .import_tablets(
+                    TABLE_NAME.to_string(),
+                    vec![tablet_metadata],
+                    Bytes::from_static(&TRANSFER_KEY),
+                )
+                .check_result()
+        );
+    }
 }
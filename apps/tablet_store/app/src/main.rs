@@ -17,33 +17,15 @@
 #![feature(alloc_error_handler)]
 
 extern crate alloc;
-extern crate tcp_proto;
 extern crate tcp_runtime;
 
-use alloc::boxed::Box;
-use oak_restricted_kernel_sdk::{
-    channel::{start_blocking_server, FileDescriptorChannel},
-    entrypoint,
-    utils::{log, samplestore::StaticSampleStore},
-};
-use tcp_proto::runtime::endpoint::EndpointServiceServer;
-use tcp_runtime::service::ApplicationService;
+use oak_restricted_kernel_sdk::entrypoint;
+use tcp_runtime::{server::run_blocking_server, service::ApplicationService};
 use tcp_tablet_store_service::actor::{RandomTabletConfigurator, TabletStoreActor};
 
 #[entrypoint]
 fn run_server() -> ! {
-    // Only log warnings and errors to reduce the risk of accidentally leaking execution
-    // information through debug logs.
-    log::set_max_level(log::LevelFilter::Warn);
-
-    let mut invocation_stats = StaticSampleStore::<1000>::new().unwrap();
     let service: ApplicationService<TabletStoreActor<RandomTabletConfigurator>> =
         ApplicationService::new(TabletStoreActor::new(RandomTabletConfigurator {}));
-    let server = EndpointServiceServer::new(service);
-    start_blocking_server(
-        Box::<FileDescriptorChannel>::default(),
-        server,
-        &mut invocation_stats,
-    )
-    .expect("Server encountered an unrecoverable error");
+    run_blocking_server(service)
 }
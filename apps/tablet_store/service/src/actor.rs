@@ -21,7 +21,7 @@ use alloc::collections::BTreeMap;
 use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
-use core::mem::swap;
+use core::mem::{self, swap};
 use hashbrown::{HashMap, HashSet};
 use prost::{bytes::Bytes, Message};
 use rand::{rngs::OsRng, RngCore};
@@ -43,6 +43,9 @@ impl ExecuteTabletOpsError {
 struct TableMetadata {
     config: TableConfig,
     tablets: BTreeMap<u32, TabletMetadata>,
+    // Tablet metadata replaced by a given Tablet Store metadata version. Used to
+    // reconstruct table state as of retained past versions.
+    history: BTreeMap<u64, Vec<TabletMetadata>>,
 }
 
 impl TableMetadata {
@@ -62,6 +65,7 @@ impl TableMetadata {
         TableMetadata {
             config: config.clone(),
             tablets,
+            history: BTreeMap::new(),
         }
     }
 
@@ -70,6 +74,15 @@ impl TableMetadata {
         for table_tablet in snapshot.table_tablets {
             self.tablets.insert(table_tablet.tablet_id, table_tablet);
         }
+        self.history.clear();
+        for retained_tablet in snapshot.retained_tablets {
+            if let Some(tablet_metadata) = retained_tablet.tablet_metadata {
+                self.history
+                    .entry(retained_tablet.metadata_version)
+                    .or_default()
+                    .push(tablet_metadata);
+            }
+        }
     }
 
     fn save_snapshot(&self) -> TableSnapshot {
@@ -79,13 +92,61 @@ impl TableMetadata {
         }
         table_tablets.sort_by(|a, b| a.tablet_id.cmp(&b.tablet_id));
 
+        let mut retained_tablets = Vec::new();
+        for (metadata_version, replaced_tablets) in &self.history {
+            for tablet_metadata in replaced_tablets {
+                retained_tablets.push(RetainedTabletMetadata {
+                    metadata_version: *metadata_version,
+                    tablet_metadata: Some(tablet_metadata.clone()),
+                });
+            }
+        }
+
         TableSnapshot {
             table_name: self.config.table_name.clone(),
             table_tablets,
+            retained_tablets,
         }
     }
 
-    fn find_tablets(&self, key_hash_from: u32, key_hash_to: u32) -> Vec<TabletMetadata> {
+    // Reconstructs tablets of the table as they were at the given metadata
+    // version. Returns None if the version is not retained.
+    fn tablets_as_of(
+        &self,
+        as_of_version: u64,
+        metadata_version: u64,
+    ) -> Option<BTreeMap<u32, TabletMetadata>> {
+        let retained_from = metadata_version.saturating_sub(self.config.retained_versions as u64);
+        if as_of_version > metadata_version || as_of_version < retained_from {
+            return None;
+        }
+
+        let mut tablets = self.tablets.clone();
+        // Revert replacements made after the requested version starting from
+        // the most recent one.
+        for (_, replaced_tablets) in self.history.range(as_of_version + 1..).rev() {
+            for tablet_metadata in replaced_tablets {
+                tablets.insert(tablet_metadata.tablet_id, tablet_metadata.clone());
+            }
+        }
+        // Drop tombstones of the tablets that didn't exist at the requested version.
+        tablets.retain(|_, tablet_metadata| !tablet_metadata.deleted);
+
+        Some(tablets)
+    }
+
+    // Drops replaced tablet metadata that is no longer needed to serve reads
+    // as of retained versions.
+    fn prune_history(&mut self, metadata_version: u64) {
+        let retained_from = metadata_version.saturating_sub(self.config.retained_versions as u64);
+        self.history = self.history.split_off(&(retained_from + 1));
+    }
+
+    fn find_tablets(
+        tablets: &BTreeMap<u32, TabletMetadata>,
+        key_hash_from: u32,
+        key_hash_to: u32,
+    ) -> Vec<TabletMetadata> {
         let mut seen_tablet_ids = HashSet::new();
         let mut listed_tablets = Vec::new();
 
@@ -105,7 +166,7 @@ impl TableMetadata {
         for (key_hash_from, key_hash_to) in key_hash_intervals {
             let mut found_last = false;
             // Starting from the first tablet id that equal or larger than range start
-            for (tablet_id, tablet_metadata) in self.tablets.range(key_hash_from..) {
+            for (tablet_id, tablet_metadata) in tablets.range(key_hash_from..) {
                 // Check if we have seen that tablet id before due to wrap around.
                 // and remember its metadata if needed.
                 if seen_tablet_ids.insert(*tablet_id) {
@@ -122,7 +183,7 @@ impl TableMetadata {
             // It is possible that we haven't found the last tablet covering the
             // end of the range due to wrap around zero.
             if !found_last {
-                if let Some((tablet_id, tablet_metadata)) = self.tablets.first_key_value() {
+                if let Some((tablet_id, tablet_metadata)) = tablets.first_key_value() {
                     // If so, wee need to remember the very first tablet after zero
                     // wrap around.
                     if seen_tablet_ids.insert(*tablet_id) {
@@ -135,7 +196,12 @@ impl TableMetadata {
         listed_tablets
     }
 
-    fn prepare_tablet_op(&self, table_name: String, tablet_op: &Op) -> TabletOpResult {
+    fn prepare_tablet_op(
+        &self,
+        table_name: String,
+        tablet_op: &Op,
+        metadata_version: u64,
+    ) -> TabletOpResult {
         let mut op_result = TabletOpResult {
             table_name,
             status: TabletOpStatus::Failed.into(),
@@ -148,10 +214,25 @@ impl TableMetadata {
                 // responsible for the given range. These tablets maybe outside of the
                 // range and must be found by traversing the consistent hashing
                 // ring clockwise, and possible wrap around zero.
-                let listed_tablets =
-                    self.find_tablets(list_tablet_op.key_hash_from, list_tablet_op.key_hash_to);
+                let mut listed_tablets = Vec::new();
+                if list_tablet_op.as_of_version == 0 {
+                    listed_tablets = Self::find_tablets(
+                        &self.tablets,
+                        list_tablet_op.key_hash_from,
+                        list_tablet_op.key_hash_to,
+                    );
+                    op_result.status = TabletOpStatus::Succeeded.into();
+                } else if let Some(tablets) =
+                    self.tablets_as_of(list_tablet_op.as_of_version, metadata_version)
+                {
+                    listed_tablets = Self::find_tablets(
+                        &tablets,
+                        list_tablet_op.key_hash_from,
+                        list_tablet_op.key_hash_to,
+                    );
+                    op_result.status = TabletOpStatus::Succeeded.into();
+                }
 
-                op_result.status = TabletOpStatus::Succeeded.into();
                 OpResult::ListTablet(ListTabletResult {
                     key_hash_from: list_tablet_op.key_hash_from,
                     key_hash_to: list_tablet_op.key_hash_to,
@@ -181,6 +262,66 @@ impl TableMetadata {
                 }
                 OpResult::UpdateTablet(update_tablet_result)
             }
+            Op::ExportTablets(export_tablets_op) => {
+                let mut export_tablets_result = ExportTabletsResult {
+                    metadata_version: export_tablets_op.as_of_version.unwrap_or(metadata_version),
+                    ..Default::default()
+                };
+                // Pages are exported as of the version the export has started at, hence
+                // tablets updated meanwhile are exported as they were at that version.
+                let as_of_tablets;
+                let tablets = match export_tablets_op.as_of_version {
+                    None => Some(&self.tablets),
+                    Some(as_of_version) => {
+                        as_of_tablets = self.tablets_as_of(as_of_version, metadata_version);
+                        as_of_tablets.as_ref()
+                    }
+                };
+                if let Some(tablets) = tablets {
+                    for (tablet_id, tablet_metadata) in
+                        tablets.range(export_tablets_op.tablet_id_from..)
+                    {
+                        if export_tablets_op.max_tablets > 0
+                            && export_tablets_result.tablets.len()
+                                == export_tablets_op.max_tablets as usize
+                        {
+                            export_tablets_result.has_more = true;
+                            export_tablets_result.next_tablet_id_from = *tablet_id;
+                            break;
+                        }
+                        export_tablets_result.tablets.push(tablet_metadata.clone());
+                    }
+                    op_result.status = TabletOpStatus::Succeeded.into();
+                }
+
+                OpResult::ExportTablets(export_tablets_result)
+            }
+            Op::ImportTablets(import_tablets_op) => {
+                let mut import_tablets_result = ImportTabletsResult::default();
+                // Imported tablets replace the existing ones, hence import is only allowed
+                // if none of the existing tablets has ever been written.
+                for existing_tablet in self.tablets.values() {
+                    if !existing_tablet.blob_uri.is_empty() {
+                        import_tablets_result
+                            .existing_tablets
+                            .push(existing_tablet.clone());
+                    }
+                }
+
+                let mut imported_tablet_ids = HashSet::new();
+                let valid_import = !import_tablets_op.tablets.is_empty()
+                    && import_tablets_op.tablets.iter().all(|imported_tablet| {
+                        imported_tablet_ids.insert(imported_tablet.tablet_id)
+                            && !imported_tablet.deleted
+                    });
+
+                if !valid_import {
+                    op_result.status = TabletOpStatus::Invalid.into();
+                } else if import_tablets_result.existing_tablets.is_empty() {
+                    op_result.status = TabletOpStatus::Succeeded.into();
+                }
+                OpResult::ImportTablets(import_tablets_result)
+            }
             Op::AddTablet(_add_tablet_op) => todo!(),
             Op::RemoveTablet(_remove_tablet_op) => todo!(),
         });
@@ -192,6 +333,7 @@ impl TableMetadata {
         &mut self,
         tablet_op: Op,
         tablet_op_prepare_result: TabletOpResult,
+        metadata_version: u64,
     ) -> TabletOpResult {
         let mut op_result = tablet_op_prepare_result;
 
@@ -200,8 +342,43 @@ impl TableMetadata {
             Op::CheckTablet(_check_tablet_op) => op_result.op_result.unwrap(),
             Op::UpdateTablet(update_tablet_op) => {
                 let updated_tablet = update_tablet_op.tablet_metadata.unwrap();
-                self.tablets
-                    .insert(updated_tablet.tablet_id, updated_tablet);
+                if let Some(replaced_tablet) = self
+                    .tablets
+                    .insert(updated_tablet.tablet_id, updated_tablet)
+                {
+                    if self.config.retained_versions > 0 {
+                        self.history
+                            .entry(metadata_version)
+                            .or_default()
+                            .push(replaced_tablet);
+                    }
+                }
+
+                op_result.op_result.unwrap()
+            }
+            Op::ExportTablets(_export_tablets_op) => op_result.op_result.unwrap(),
+            Op::ImportTablets(import_tablets_op) => {
+                let mut imported_tablets = BTreeMap::new();
+                for imported_tablet in import_tablets_op.tablets {
+                    imported_tablets.insert(imported_tablet.tablet_id, imported_tablet);
+                }
+                let replaced_tablets = mem::replace(&mut self.tablets, imported_tablets);
+
+                if self.config.retained_versions > 0 {
+                    // Retain replaced tablets, and tombstones for the tablets that didn't
+                    // exist before the import, to serve reads as of earlier versions.
+                    let retained_tablets = self.history.entry(metadata_version).or_default();
+                    for tablet_id in self.tablets.keys() {
+                        if !replaced_tablets.contains_key(tablet_id) {
+                            retained_tablets.push(TabletMetadata {
+                                tablet_id: *tablet_id,
+                                deleted: true,
+                                ..Default::default()
+                            });
+                        }
+                    }
+                    retained_tablets.extend(replaced_tablets.into_values());
+                }
 
                 op_result.op_result.unwrap()
             }
@@ -235,6 +412,7 @@ pub struct TabletStoreActor<C: TabletConfigurator> {
     context: Option<Box<dyn ActorContext>>,
     config: TabletStoreConfig,
     tables: HashMap<String, TableMetadata>,
+    metadata_version: u64,
 }
 
 impl<C: TabletConfigurator> TabletStoreActor<C> {
@@ -244,6 +422,7 @@ impl<C: TabletConfigurator> TabletStoreActor<C> {
             context: None,
             config: TabletStoreConfig::default(),
             tables: HashMap::new(),
+            metadata_version: 0,
         }
     }
 
@@ -304,6 +483,17 @@ impl<C: TabletConfigurator> TabletStoreActor<C> {
 
         let mut tablet_op_results = Vec::new();
         if all_succeeded {
+            // Only requests that modify tablets produce a new metadata version.
+            let modifies_tablets = request.tablet_ops.iter().any(|tablet_op| {
+                !matches!(
+                    tablet_op.op,
+                    Some(Op::ListTablet(_)) | Some(Op::CheckTablet(_)) | Some(Op::ExportTablets(_))
+                )
+            });
+            if modifies_tablets {
+                self.metadata_version += 1;
+            }
+
             for (tablet_op, tablet_op_prepare_result) in request
                 .tablet_ops
                 .into_iter()
@@ -311,6 +501,12 @@ impl<C: TabletConfigurator> TabletStoreActor<C> {
             {
                 tablet_op_results.push(self.commit_tablet_op(tablet_op, tablet_op_prepare_result));
             }
+
+            if modifies_tablets {
+                for table in self.tables.values_mut() {
+                    table.prune_history(self.metadata_version);
+                }
+            }
         } else {
             tablet_op_results = tablet_op_prepare_results;
         }
@@ -324,6 +520,7 @@ impl<C: TabletConfigurator> TabletStoreActor<C> {
         let tablets_response = TabletsResponse {
             status: tablets_request_status.into(),
             tablet_results: tablet_op_results,
+            metadata_version: self.metadata_version,
         };
 
         (
@@ -341,9 +538,11 @@ impl<C: TabletConfigurator> TabletStoreActor<C> {
                 op_result: None,
             };
         }
-        table_opt
-            .unwrap()
-            .prepare_tablet_op(tablet_op.table_name.clone(), tablet_op.op.as_ref().unwrap())
+        table_opt.unwrap().prepare_tablet_op(
+            tablet_op.table_name.clone(),
+            tablet_op.op.as_ref().unwrap(),
+            self.metadata_version,
+        )
     }
 
     fn commit_tablet_op(
@@ -352,7 +551,11 @@ impl<C: TabletConfigurator> TabletStoreActor<C> {
         tablet_op_prepare_result: TabletOpResult,
     ) -> TabletOpResult {
         let table = self.tables.get_mut(&tablet_op.table_name).unwrap();
-        table.commit_tablet_op(tablet_op.op.unwrap(), tablet_op_prepare_result)
+        table.commit_tablet_op(
+            tablet_op.op.unwrap(),
+            tablet_op_prepare_result,
+            self.metadata_version,
+        )
     }
 }
 
@@ -383,7 +586,10 @@ impl<C: TabletConfigurator> Actor for TabletStoreActor<C> {
         for table in self.tables.values() {
             table_snapshots.push(table.save_snapshot());
         }
-        let snapshot = TabletStoreSnapshot { table_snapshots };
+        let snapshot = TabletStoreSnapshot {
+            table_snapshots,
+            metadata_version: self.metadata_version,
+        };
 
         Ok(snapshot.encode_to_vec().into())
     }
@@ -394,6 +600,7 @@ impl<C: TabletConfigurator> Actor for TabletStoreActor<C> {
         let snapshot =
             TabletStoreSnapshot::decode(snapshot).map_err(|_| ActorError::SnapshotLoading)?;
 
+        self.metadata_version = snapshot.metadata_version;
         for table_snapshot in snapshot.table_snapshots {
             let table = self
                 .tables
@@ -494,6 +701,7 @@ mod tests {
     const TABLET_ID_2: u32 = 20;
     const TABLET_VERSION_2: u32 = 7;
     const INITIAL_TABLET_COUNT: u32 = 3;
+    const RETAINED_VERSIONS: u32 = 2;
 
     const CORRELATION_ID_1: u64 = 11;
 
@@ -513,6 +721,7 @@ mod tests {
                 max_tablet_size: 1024,
                 min_tablet_size: 512,
                 initial_tablet_count: INITIAL_TABLET_COUNT,
+                retained_versions: RETAINED_VERSIONS,
             }],
         }
     }
@@ -526,6 +735,7 @@ mod tests {
             blob_size: 128,
             blob_hash: Bytes::new(),
             blob_uri: format!("{}", tablet_id),
+            blob_codec: TabletBlobCodec::Unspecified.into(),
         }
     }
 
@@ -537,16 +747,24 @@ mod tests {
                     create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1),
                     create_tablet_metadata(TABLET_ID_2, TABLET_VERSION_2),
                 ],
+                retained_tablets: vec![],
             }],
+            metadata_version: 0,
         }
     }
 
-    fn create_list_tablet_op(table_name: String, key_hash_from: u32, key_hash_to: u32) -> TabletOp {
+    fn create_list_tablet_op(
+        table_name: String,
+        key_hash_from: u32,
+        key_hash_to: u32,
+        as_of_version: u64,
+    ) -> TabletOp {
         TabletOp {
             table_name,
             op: Some(Op::ListTablet(ListTabletOp {
                 key_hash_from,
                 key_hash_to,
+                as_of_version,
             })),
         }
     }
@@ -636,10 +854,12 @@ mod tests {
     fn create_execute_tablet_ops_response(
         status: TabletsRequestStatus,
         tablet_results: Vec<TabletOpResult>,
+        metadata_version: u64,
     ) -> TabletsResponse {
         TabletsResponse {
             status: status.into(),
             tablet_results,
+            metadata_version,
         }
     }
 
@@ -721,6 +941,7 @@ mod tests {
                     TABLE_NAME.to_string(),
                     TABLET_ID_1 - 1,
                     TABLET_ID_2 + 1,
+                    0,
                 )],
             )))
             .unwrap();
@@ -731,7 +952,7 @@ mod tests {
                     index: 1,
                     owned: true,
                 },
-                command_outcome.event.unwrap(),
+                command_outcome.events.into_iter().next().unwrap(),
             )
             .unwrap();
 
@@ -755,11 +976,274 @@ mod tests {
                         create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1),
                         create_tablet_metadata(TABLET_ID_2, TABLET_VERSION_2),
                     ]
-                )]
+                )],
+                0
             )
         );
     }
 
+    fn execute_tablet_ops(
+        actor: &mut TabletStoreActor<MockTabletConfigurator>,
+        index: u64,
+        tablet_ops: Vec<TabletOp>,
+    ) -> TabletsResponse {
+        let command_outcome = actor
+            .on_process_command(Some(create_execute_tablet_ops_request(
+                CORRELATION_ID_1,
+                tablet_ops,
+            )))
+            .unwrap();
+
+        let event_outcome = actor
+            .on_apply_event(
+                ActorEventContext { index, owned: true },
+                command_outcome.events.into_iter().next().unwrap(),
+            )
+            .unwrap();
+
+        assert_eq!(event_outcome.commands.len(), 1);
+        let (_, tablets_response) =
+            decode_execute_tablet_ops_response(event_outcome.commands[0].clone());
+        tablets_response
+    }
+
+    #[test]
+    fn test_list_tablet_as_of_version() {
+        let mut mock_context = MockActorContext::new();
+        mock_context.expect_leader().return_const(true);
+
+        let mut actor = create_actor(mock_context);
+        let snapshot = create_actor_snapshot();
+
+        actor
+            .on_load_snapshot(snapshot.encode_to_vec().into())
+            .unwrap();
+
+        for (index, tablet_version) in [(1, TABLET_VERSION_1 + 1), (2, TABLET_VERSION_1 + 2)] {
+            let tablets_response = execute_tablet_ops(
+                &mut actor,
+                index,
+                vec![create_update_tablet_op(
+                    TABLE_NAME.to_string(),
+                    create_tablet_metadata(TABLET_ID_1, tablet_version),
+                )],
+            );
+            assert_eq!(tablets_response.metadata_version, index);
+        }
+
+        // Version 1 is retained and must reflect only the first update.
+        assert_eq!(
+            execute_tablet_ops(
+                &mut actor,
+                3,
+                vec![create_list_tablet_op(
+                    TABLE_NAME.to_string(),
+                    TABLET_ID_1 - 1,
+                    TABLET_ID_1,
+                    1,
+                )],
+            ),
+            create_execute_tablet_ops_response(
+                TabletsRequestStatus::Succeeded,
+                vec![create_list_tablet_result(
+                    TABLE_NAME.to_string(),
+                    TabletOpStatus::Succeeded,
+                    TABLET_ID_1 - 1,
+                    TABLET_ID_1,
+                    vec![create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1 + 1)]
+                )],
+                2
+            )
+        );
+
+        // Version from the future is not available.
+        assert_eq!(
+            execute_tablet_ops(
+                &mut actor,
+                4,
+                vec![create_list_tablet_op(
+                    TABLE_NAME.to_string(),
+                    TABLET_ID_1 - 1,
+                    TABLET_ID_1,
+                    3,
+                )],
+            ),
+            create_execute_tablet_ops_response(
+                TabletsRequestStatus::Failed,
+                vec![create_list_tablet_result(
+                    TABLE_NAME.to_string(),
+                    TabletOpStatus::Failed,
+                    TABLET_ID_1 - 1,
+                    TABLET_ID_1,
+                    vec![]
+                )],
+                2
+            )
+        );
+    }
+
+    #[test]
+    fn test_export_tablets_success() {
+        let mut mock_context = MockActorContext::new();
+        mock_context.expect_leader().return_const(true);
+
+        let mut actor = create_actor(mock_context);
+        let snapshot = create_actor_snapshot();
+
+        actor
+            .on_load_snapshot(snapshot.encode_to_vec().into())
+            .unwrap();
+
+        let tablets_response = execute_tablet_ops(
+            &mut actor,
+            1,
+            vec![TabletOp {
+                table_name: TABLE_NAME.to_string(),
+                op: Some(Op::ExportTablets(ExportTabletsOp {
+                    tablet_id_from: 0,
+                    max_tablets: 1,
+                    as_of_version: None,
+                })),
+            }],
+        );
+
+        assert_eq!(
+            tablets_response.tablet_results[0].op_result,
+            Some(OpResult::ExportTablets(ExportTabletsResult {
+                tablets: vec![create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1)],
+                has_more: true,
+                next_tablet_id_from: TABLET_ID_2,
+                metadata_version: 0,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_export_tablets_as_of_version() {
+        let mut mock_context = MockActorContext::new();
+        mock_context.expect_leader().return_const(true);
+
+        let mut actor = create_actor(mock_context);
+        let snapshot = create_actor_snapshot();
+
+        actor
+            .on_load_snapshot(snapshot.encode_to_vec().into())
+            .unwrap();
+
+        let create_export_op = |tablet_id_from, as_of_version| TabletOp {
+            table_name: TABLE_NAME.to_string(),
+            op: Some(Op::ExportTablets(ExportTabletsOp {
+                tablet_id_from,
+                max_tablets: 1,
+                as_of_version,
+            })),
+        };
+
+        // The first page is exported as of the most recent version.
+        let tablets_response = execute_tablet_ops(&mut actor, 1, vec![create_export_op(0, None)]);
+        let Some(OpResult::ExportTablets(first_page)) =
+            tablets_response.tablet_results[0].op_result.clone()
+        else {
+            panic!("Export tablets result expected");
+        };
+        assert_eq!(first_page.metadata_version, 0);
+
+        // The tablet is updated before the next page is exported.
+        let tablets_response = execute_tablet_ops(
+            &mut actor,
+            2,
+            vec![create_update_tablet_op(
+                TABLE_NAME.to_string(),
+                create_tablet_metadata(TABLET_ID_2, TABLET_VERSION_2 + 1),
+            )],
+        );
+        assert_eq!(tablets_response.metadata_version, 1);
+
+        // The next page is exported as of the version of the first page.
+        let tablets_response = execute_tablet_ops(
+            &mut actor,
+            3,
+            vec![create_export_op(
+                first_page.next_tablet_id_from,
+                Some(first_page.metadata_version),
+            )],
+        );
+        assert_eq!(
+            tablets_response.tablet_results[0].op_result,
+            Some(OpResult::ExportTablets(ExportTabletsResult {
+                tablets: vec![create_tablet_metadata(TABLET_ID_2, TABLET_VERSION_2)],
+                has_more: false,
+                next_tablet_id_from: 0,
+                metadata_version: 0,
+            }))
+        );
+
+        // Export fails if the version is not available.
+        let tablets_response =
+            execute_tablet_ops(&mut actor, 4, vec![create_export_op(0, Some(2))]);
+        assert_eq!(
+            tablets_response.tablet_results[0].status,
+            TabletOpStatus::Failed as i32
+        );
+    }
+
+    #[test]
+    fn test_import_tablets() {
+        let mut mock_context = MockActorContext::new();
+        mock_context.expect_leader().return_const(true);
+
+        let mut actor = create_actor(mock_context);
+        let imported_tablets = vec![
+            create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1),
+            create_tablet_metadata(TABLET_ID_2, TABLET_VERSION_2),
+        ];
+        let import_tablets_op = TabletOp {
+            table_name: TABLE_NAME.to_string(),
+            op: Some(Op::ImportTablets(ImportTabletsOp {
+                tablets: imported_tablets.clone(),
+            })),
+        };
+
+        // Initially configured tablets have never been written and can be replaced.
+        let tablets_response = execute_tablet_ops(&mut actor, 1, vec![import_tablets_op.clone()]);
+        assert_eq!(
+            tablets_response.status,
+            TabletsRequestStatus::Succeeded as i32
+        );
+
+        let tablets_response = execute_tablet_ops(
+            &mut actor,
+            2,
+            vec![create_list_tablet_op(
+                TABLE_NAME.to_string(),
+                0,
+                u32::MAX,
+                0,
+            )],
+        );
+        assert_eq!(
+            tablets_response.tablet_results[0].op_result,
+            Some(OpResult::ListTablet(ListTabletResult {
+                key_hash_from: 0,
+                key_hash_to: u32::MAX,
+                tablets: imported_tablets.clone(),
+            }))
+        );
+
+        // Imported tablets have been written, hence another import must fail.
+        let tablets_response = execute_tablet_ops(&mut actor, 3, vec![import_tablets_op]);
+        assert_eq!(
+            tablets_response.tablet_results[0],
+            TabletOpResult {
+                table_name: TABLE_NAME.to_string(),
+                status: TabletOpStatus::Failed.into(),
+                op_result: Some(OpResult::ImportTablets(ImportTabletsResult {
+                    existing_tablets: imported_tablets,
+                })),
+            }
+        );
+    }
+
     #[test]
     fn test_check_tablet_success() {
         let mut mock_context = MockActorContext::new();
@@ -789,7 +1273,7 @@ mod tests {
                     index: 1,
                     owned: true,
                 },
-                command_outcome.event.unwrap(),
+                command_outcome.events.into_iter().next().unwrap(),
             )
             .unwrap();
 
@@ -808,7 +1292,8 @@ mod tests {
                     TABLE_NAME.to_string(),
                     TabletOpStatus::Succeeded,
                     None
-                )]
+                )],
+                0
             )
         );
     }
@@ -841,7 +1326,7 @@ mod tests {
                     index: 1,
                     owned: true,
                 },
-                command_outcome.event.unwrap(),
+                command_outcome.events.into_iter().next().unwrap(),
             )
             .unwrap();
 
@@ -860,7 +1345,8 @@ mod tests {
                     TABLE_NAME.to_string(),
                     TabletOpStatus::Succeeded,
                     create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1)
-                )]
+                )],
+                1
             )
         );
     }
@@ -896,7 +1382,7 @@ mod tests {
                     index: 1,
                     owned: true,
                 },
-                command_outcome.event.unwrap(),
+                command_outcome.events.into_iter().next().unwrap(),
             )
             .unwrap();
 
@@ -922,7 +1408,8 @@ mod tests {
                         TabletOpStatus::Succeeded,
                         create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1)
                     )
-                ]
+                ],
+                1
             )
         );
     }
@@ -943,7 +1430,12 @@ mod tests {
             .on_process_command(Some(create_execute_tablet_ops_request(
                 CORRELATION_ID_1,
                 vec![
-                    create_list_tablet_op(TABLE_NAME.to_string(), TABLET_ID_2 + 1, TABLET_ID_2 + 2),
+                    create_list_tablet_op(
+                        TABLE_NAME.to_string(),
+                        TABLET_ID_2 + 1,
+                        TABLET_ID_2 + 2,
+                        0,
+                    ),
                     create_check_tablet_op(
                         TABLE_NAME.to_string(),
                         TABLET_ID_1,
@@ -963,7 +1455,7 @@ mod tests {
                     index: 1,
                     owned: true,
                 },
-                command_outcome.event.unwrap(),
+                command_outcome.events.into_iter().next().unwrap(),
             )
             .unwrap();
 
@@ -996,7 +1488,8 @@ mod tests {
                         TabletOpStatus::Failed,
                         create_tablet_metadata(TABLET_ID_1, TABLET_VERSION_1)
                     )
-                ]
+                ],
+                0
             )
         );
     }
@@ -21,12 +21,6 @@ extern crate prost;
 extern crate slog;
 extern crate tcp_runtime;
 
-pub mod apps {
-    pub mod tablet_store {
-        pub mod service {
-            include!(concat!(env!("OUT_DIR"), "/apps.tablet_store.service.rs"));
-        }
-    }
-}
+pub use tcp_proto::apps;
 
 pub mod actor;
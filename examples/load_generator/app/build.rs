@@ -12,19 +12,6 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::Result;
-
-fn main() -> Result<()> {
-    micro_rpc_build::compile(
-        &["proto/atomic_counter.proto"],
-        &["proto"],
-        micro_rpc_build::CompileOptions {
-            bytes: vec![
-                ".apps.atomic_counter.service.CounterSnapshotValue".to_string(),
-                ".apps.atomic_counter.service.CounterRequest".to_string(),
-            ],
-            ..Default::default()
-        },
-    );
-    Ok(())
+fn main() {
+    println!("cargo:rustc-link-arg=-zmax-page-size=0x200000");
 }
@@ -0,0 +1,31 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_std]
+#![no_main]
+#![feature(alloc_error_handler)]
+
+extern crate alloc;
+extern crate tcp_runtime;
+
+use oak_restricted_kernel_sdk::entrypoint;
+use tcp_load_generator_service::actor::LoadGeneratorActor;
+use tcp_runtime::{server::run_blocking_server, service::ApplicationService};
+
+#[entrypoint]
+fn run_server() -> ! {
+    let service: ApplicationService<LoadGeneratorActor> =
+        ApplicationService::new(LoadGeneratorActor::new());
+    run_blocking_server(service)
+}
@@ -0,0 +1,424 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::fmt;
+use std::time::{Duration, Instant};
+
+use hashbrown::HashMap;
+use prost::{bytes::Bytes, Message};
+use tcp_integration::harness::FakeCluster;
+use tcp_load_generator_service::actor::LoadGeneratorActor;
+use tcp_load_generator_service::apps::load_generator::service::{
+    load_generator_in_message, load_generator_out_message, LoadGeneratorConfig,
+    LoadGeneratorInMessage, LoadGeneratorOutMessage, LoadStatus, ReadRequest, WriteRequest,
+};
+use tcp_proto::runtime::endpoint::out_message;
+
+use crate::allocator::{allocated_bytes, peak_allocated_bytes};
+use crate::histogram::Histogram;
+
+// Maximum number of cluster advances to wait for the inflight requests to
+// complete once no more requests are sent.
+const DRAIN_ROUNDS: u64 = 1000;
+
+/// Configuration of the generated load.
+#[derive(Clone, Debug)]
+pub struct LoadConfig {
+    /// Number of replicas in the cluster.
+    pub replica_count: u64,
+    /// How long to generate the load for.
+    pub duration: Duration,
+    /// Maximum number of requests to send, zero means no limit.
+    pub max_requests: u64,
+    /// Maximum number of requests awaiting response at any time.
+    pub max_inflight: usize,
+    /// Percentage of read requests, the rest are write requests.
+    pub read_percent: u32,
+    /// Percentage of write requests that carry large values.
+    pub large_percent: u32,
+    /// Size of the small values in bytes.
+    pub small_value_size: usize,
+    /// Size of the large values in bytes.
+    pub large_value_size: usize,
+    /// Number of distinct keys the values are written under.
+    pub key_count: u64,
+    /// How often the progress is reported.
+    pub report_interval: Duration,
+    /// Seed of the pseudo random request mix.
+    pub seed: u64,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        LoadConfig {
+            replica_count: 3,
+            duration: Duration::from_secs(60),
+            max_requests: 0,
+            max_inflight: 16,
+            read_percent: 50,
+            large_percent: 10,
+            small_value_size: 64,
+            large_value_size: 64 * 1024,
+            key_count: 1024,
+            report_interval: Duration::from_secs(10),
+            seed: 1,
+        }
+    }
+}
+
+/// Statistics collected over a period of the load generation.
+#[derive(Clone)]
+pub struct LoadReport {
+    /// Time since the load generation has started.
+    pub elapsed: Duration,
+    /// Time covered by this report.
+    pub period: Duration,
+    /// Number of requests sent during the period.
+    pub sent: u64,
+    /// Number of requests rejected during the period.
+    pub rejected: u64,
+    /// Number of requests awaiting response at the end of the period.
+    pub inflight: usize,
+    /// Wall clock latency of the read requests in microseconds.
+    pub read_latency: Histogram,
+    /// Wall clock latency of the write requests in microseconds.
+    pub write_latency: Histogram,
+    /// Number of cluster advances it took to complete write requests.
+    pub write_rounds: Histogram,
+    /// Number of bytes allocated at the end of the period.
+    pub allocated_bytes: usize,
+    /// Difference between the bytes allocated at the end of the period and
+    /// at the end of the first period, which serves as a warmed up baseline.
+    pub allocated_bytes_growth: i64,
+    /// Maximum number of bytes allocated since the start.
+    pub peak_allocated_bytes: usize,
+}
+
+impl LoadReport {
+    fn new() -> LoadReport {
+        LoadReport {
+            elapsed: Duration::ZERO,
+            period: Duration::ZERO,
+            sent: 0,
+            rejected: 0,
+            inflight: 0,
+            read_latency: Histogram::new(),
+            write_latency: Histogram::new(),
+            write_rounds: Histogram::new(),
+            allocated_bytes: 0,
+            allocated_bytes_growth: 0,
+            peak_allocated_bytes: 0,
+        }
+    }
+
+    pub fn completed(&self) -> u64 {
+        self.read_latency.count() + self.write_latency.count()
+    }
+
+    fn record_sent(&mut self) {
+        self.sent += 1;
+    }
+
+    fn record_response(&mut self, request: &PendingRequest, status: LoadStatus, round: u64) {
+        if status != LoadStatus::Success {
+            self.rejected += 1;
+            return;
+        }
+
+        let latency = request.sent_at.elapsed().as_micros() as u64;
+        match request.kind {
+            RequestKind::Read => self.read_latency.record(latency),
+            RequestKind::Write => {
+                self.write_latency.record(latency);
+                self.write_rounds.record(round - request.sent_round);
+            }
+        }
+    }
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let period_secs = self.period.as_secs_f64().max(f64::EPSILON);
+        writeln!(
+            f,
+            "elapsed {:?}: sent {}, completed {} ({:.1}/s), rejected {}, inflight {}",
+            self.elapsed,
+            self.sent,
+            self.completed(),
+            self.completed() as f64 / period_secs,
+            self.rejected,
+            self.inflight
+        )?;
+        for (name, histogram) in [
+            ("read latency us", &self.read_latency),
+            ("write latency us", &self.write_latency),
+            ("write rounds", &self.write_rounds),
+        ] {
+            writeln!(
+                f,
+                "  {}: count {}, min {}, mean {}, p50 {}, p90 {}, p99 {}, max {}",
+                name,
+                histogram.count(),
+                histogram.min(),
+                histogram.mean(),
+                histogram.percentile(50.0),
+                histogram.percentile(90.0),
+                histogram.percentile(99.0),
+                histogram.max()
+            )?;
+        }
+        write!(
+            f,
+            "  memory: allocated {} bytes, growth {} bytes, peak {} bytes",
+            self.allocated_bytes, self.allocated_bytes_growth, self.peak_allocated_bytes
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+enum RequestKind {
+    Read,
+    Write,
+}
+
+struct PendingRequest {
+    kind: RequestKind,
+    sent_at: Instant,
+    sent_round: u64,
+}
+
+/// Generates a configurable mix of read and write requests against a fake
+/// cluster of load generator replicas, tracking latency distributions and
+/// memory usage along the way.
+pub struct LoadGenerator {
+    config: LoadConfig,
+    cluster: FakeCluster<LoadGeneratorActor>,
+    random_state: u64,
+    next_correlation_id: u64,
+    round: u64,
+    inflight: HashMap<u64, PendingRequest>,
+    small_value: Bytes,
+    large_value: Bytes,
+}
+
+impl LoadGenerator {
+    /// Creates a generator and brings up the cluster with the configured
+    /// number of replicas.
+    pub fn new(config: LoadConfig) -> LoadGenerator {
+        assert!(config.replica_count > 0);
+        assert!(config.max_inflight > 0);
+
+        let app_config = LoadGeneratorConfig {
+            key_count: config.key_count,
+        };
+        let mut cluster = FakeCluster::new(app_config.encode_to_vec().into());
+
+        cluster.start_node(1, true, LoadGeneratorActor::new());
+        cluster.advance_until_elected_leader(None);
+        for node_id in 2..=config.replica_count {
+            cluster.start_node(node_id, false, LoadGeneratorActor::new());
+            cluster.add_node_to_cluster(node_id);
+        }
+
+        LoadGenerator {
+            random_state: config.seed,
+            next_correlation_id: 1,
+            round: 0,
+            inflight: HashMap::new(),
+            small_value: Bytes::from(vec![0xA5; config.small_value_size]),
+            large_value: Bytes::from(vec![0x5A; config.large_value_size]),
+            config,
+            cluster,
+        }
+    }
+
+    /// Generates the load until the configured duration elapses or the
+    /// configured number of requests has been sent, then waits for the
+    /// inflight requests to complete. Invokes `on_report` with the statistics
+    /// of every report interval and returns statistics of the whole run.
+    pub fn run(&mut self, on_report: &mut impl FnMut(&LoadReport)) -> LoadReport {
+        let started_at = Instant::now();
+        let mut reported_at = started_at;
+        let mut baseline_allocated_bytes: Option<usize> = None;
+        let mut period_report = LoadReport::new();
+        let mut total_report = LoadReport::new();
+        let mut sent = 0;
+        let mut drain_round = None;
+
+        loop {
+            let exhausted = started_at.elapsed() >= self.config.duration
+                || (self.config.max_requests != 0 && sent >= self.config.max_requests);
+
+            if !exhausted {
+                while self.inflight.len() < self.config.max_inflight
+                    && (self.config.max_requests == 0 || sent < self.config.max_requests)
+                {
+                    self.send_request();
+                    sent += 1;
+                    period_report.record_sent();
+                    total_report.record_sent();
+                }
+            } else if self.inflight.is_empty()
+                || *drain_round.get_or_insert(self.round) + DRAIN_ROUNDS <= self.round
+            {
+                break;
+            }
+
+            self.cluster.advance();
+            self.round += 1;
+            self.process_responses(&mut [&mut period_report, &mut total_report]);
+
+            if reported_at.elapsed() >= self.config.report_interval {
+                let allocated = allocated_bytes();
+                let baseline = *baseline_allocated_bytes.get_or_insert(allocated);
+                self.complete_report(&mut period_report, started_at, reported_at, baseline);
+                on_report(&period_report);
+
+                period_report = LoadReport::new();
+                reported_at = Instant::now();
+            }
+        }
+
+        let baseline = baseline_allocated_bytes.unwrap_or_else(allocated_bytes);
+        self.complete_report(&mut total_report, started_at, started_at, baseline);
+        total_report
+    }
+
+    fn complete_report(
+        &self,
+        report: &mut LoadReport,
+        started_at: Instant,
+        period_started_at: Instant,
+        baseline_allocated_bytes: usize,
+    ) {
+        report.elapsed = started_at.elapsed();
+        report.period = period_started_at.elapsed();
+        report.inflight = self.inflight.len();
+        report.allocated_bytes = allocated_bytes();
+        report.allocated_bytes_growth =
+            report.allocated_bytes as i64 - baseline_allocated_bytes as i64;
+        report.peak_allocated_bytes = peak_allocated_bytes();
+    }
+
+    fn send_request(&mut self) {
+        let correlation_id = self.next_correlation_id;
+        self.next_correlation_id += 1;
+
+        let key = self.next_random() % self.config.key_count.max(1);
+        let (kind, msg) = if self.next_percent() < self.config.read_percent {
+            (
+                RequestKind::Read,
+                load_generator_in_message::Msg::ReadRequest(ReadRequest { key }),
+            )
+        } else {
+            let value = if self.next_percent() < self.config.large_percent {
+                self.large_value.clone()
+            } else {
+                self.small_value.clone()
+            };
+            (
+                RequestKind::Write,
+                load_generator_in_message::Msg::WriteRequest(WriteRequest { key, value }),
+            )
+        };
+
+        let leader_id = self.cluster.leader_id();
+        self.cluster.send_app_message(
+            leader_id,
+            correlation_id,
+            LoadGeneratorInMessage { msg: Some(msg) }
+                .encode_to_vec()
+                .into(),
+            Bytes::new(),
+        );
+        self.inflight.insert(
+            correlation_id,
+            PendingRequest {
+                kind,
+                sent_at: Instant::now(),
+                sent_round: self.round,
+            },
+        );
+    }
+
+    fn process_responses(&mut self, reports: &mut [&mut LoadReport]) {
+        // All pulled messages are extracted so that messages the generator
+        // doesn't care about don't accumulate in the cluster.
+        for message in self.cluster.extract_pull_messages(&mut |_| true) {
+            let Some(out_message::Msg::DeliverAppMessage(deliver_app_message)) = message.msg else {
+                continue;
+            };
+            let Some(request) = self.inflight.remove(&deliver_app_message.correlation_id) else {
+                continue;
+            };
+            let status = match LoadGeneratorOutMessage::decode(
+                deliver_app_message.message_header.as_ref(),
+            ) {
+                Ok(LoadGeneratorOutMessage {
+                    msg: Some(load_generator_out_message::Msg::WriteResponse(response)),
+                }) => response.status(),
+                Ok(LoadGeneratorOutMessage {
+                    msg: Some(load_generator_out_message::Msg::ReadResponse(response)),
+                }) => response.status(),
+                _ => LoadStatus::Unspecified,
+            };
+
+            for report in reports.iter_mut() {
+                report.record_response(&request, status, self.round);
+            }
+        }
+    }
+
+    // Advances xorshift pseudo random generator, which keeps request mix
+    // reproducible for the given seed.
+    fn next_random(&mut self) -> u64 {
+        let mut x = self.random_state.max(1);
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.random_state = x;
+        x
+    }
+
+    fn next_percent(&mut self) -> u32 {
+        (self.next_random() % 100) as u32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::generator::*;
+
+    #[test]
+    fn test_generate_load() {
+        let mut generator = LoadGenerator::new(LoadConfig {
+            duration: Duration::from_secs(600),
+            max_requests: 200,
+            max_inflight: 8,
+            large_value_size: 4096,
+            key_count: 16,
+            ..Default::default()
+        });
+
+        let report = generator.run(&mut |_| {});
+
+        assert_eq!(report.sent, 200);
+        assert_eq!(report.completed(), 200);
+        assert_eq!(report.rejected, 0);
+        assert_eq!(report.inflight, 0);
+        assert!(report.read_latency.count() > 0);
+        assert!(report.write_latency.count() > 0);
+        assert!(report.write_rounds.min() > 0);
+    }
+}
@@ -0,0 +1,142 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+const BUCKET_COUNT: usize = 64;
+
+/// Histogram of recorded values with power of two buckets. Memory used by the
+/// histogram doesn't depend on the number of recorded values which makes it
+/// suitable for runs that last for hours.
+#[derive(Clone)]
+pub struct Histogram {
+    buckets: [u64; BUCKET_COUNT],
+    count: u64,
+    sum: u128,
+    min: u64,
+    max: u64,
+}
+
+impl Histogram {
+    pub fn new() -> Histogram {
+        Histogram {
+            buckets: [0; BUCKET_COUNT],
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    pub fn record(&mut self, value: u64) {
+        self.buckets[Self::bucket(value)] += 1;
+        self.count += 1;
+        self.sum += value as u128;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn min(&self) -> u64 {
+        if self.count == 0 {
+            0
+        } else {
+            self.min
+        }
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    pub fn mean(&self) -> u64 {
+        if self.count == 0 {
+            0
+        } else {
+            (self.sum / self.count as u128) as u64
+        }
+    }
+
+    /// Returns the upper bound of the bucket that holds the given percentile
+    /// of the recorded values, capped by the maximum recorded value.
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+
+        let rank = ((self.count as f64 * percentile / 100.0).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, bucket_count) in self.buckets.iter().enumerate() {
+            seen += bucket_count;
+            if seen >= rank {
+                return Self::upper_bound(bucket).min(self.max);
+            }
+        }
+
+        self.max
+    }
+
+    pub fn reset(&mut self) {
+        *self = Histogram::new();
+    }
+
+    // Values are placed into the bucket indexed by the number of significant
+    // bits so that bucket i holds values in [2^(i-1), 2^i - 1].
+    fn bucket(value: u64) -> usize {
+        ((u64::BITS - value.leading_zeros()) as usize).min(BUCKET_COUNT - 1)
+    }
+
+    fn upper_bound(bucket: usize) -> u64 {
+        if bucket >= BUCKET_COUNT - 1 {
+            u64::MAX
+        } else {
+            (1u64 << bucket) - 1
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::histogram::*;
+
+    #[test]
+    fn test_empty() {
+        let histogram = Histogram::new();
+
+        assert_eq!(histogram.count(), 0);
+        assert_eq!(histogram.min(), 0);
+        assert_eq!(histogram.mean(), 0);
+        assert_eq!(histogram.percentile(99.0), 0);
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let mut histogram = Histogram::new();
+        for value in 1..=100 {
+            histogram.record(value);
+        }
+
+        assert_eq!(histogram.count(), 100);
+        assert_eq!(histogram.min(), 1);
+        assert_eq!(histogram.max(), 100);
+        assert_eq!(histogram.mean(), 50);
+        assert_eq!(histogram.percentile(50.0), 63);
+        assert_eq!(histogram.percentile(99.0), 100);
+        assert_eq!(histogram.percentile(1.0), 1);
+
+        histogram.reset();
+        assert_eq!(histogram.count(), 0);
+    }
+}
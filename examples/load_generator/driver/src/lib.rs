@@ -0,0 +1,26 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Host side driver that puts long running load on a cluster of load
+//! generator replicas to validate stability of the runtime.
+
+extern crate hashbrown;
+extern crate prost;
+extern crate tcp_integration;
+extern crate tcp_load_generator_service;
+extern crate tcp_proto;
+
+pub mod allocator;
+pub mod generator;
+pub mod histogram;
@@ -0,0 +1,114 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runs the load generator against a fake cluster and periodically prints
+//! latency distributions and memory usage. Options are passed as
+//! `--name=value`, see `usage` for the list of supported options.
+
+extern crate tcp_load_generator_driver;
+
+use std::env;
+use std::process;
+use std::time::Duration;
+
+use tcp_load_generator_driver::allocator::TrackingAllocator;
+use tcp_load_generator_driver::generator::{LoadConfig, LoadGenerator};
+
+#[global_allocator]
+static ALLOCATOR: TrackingAllocator = TrackingAllocator;
+
+fn usage() -> ! {
+    let defaults = LoadConfig::default();
+    eprintln!(
+        "Usage: tcp_load_generator_driver [--name=value]...
+
+Options:
+  --replicas=N           number of replicas in the cluster (default {})
+  --duration-secs=N      how long to generate the load for (default {})
+  --max-requests=N       maximum number of requests to send, 0 is unlimited (default {})
+  --max-inflight=N       maximum number of requests awaiting response (default {})
+  --read-percent=N       percentage of read requests (default {})
+  --large-percent=N      percentage of writes carrying large values (default {})
+  --small-size=N         size of small values in bytes (default {})
+  --large-size=N         size of large values in bytes (default {})
+  --keys=N               number of distinct keys (default {})
+  --report-secs=N        how often to report progress (default {})
+  --seed=N               seed of the request mix (default {})",
+        defaults.replica_count,
+        defaults.duration.as_secs(),
+        defaults.max_requests,
+        defaults.max_inflight,
+        defaults.read_percent,
+        defaults.large_percent,
+        defaults.small_value_size,
+        defaults.large_value_size,
+        defaults.key_count,
+        defaults.report_interval.as_secs(),
+        defaults.seed
+    );
+    process::exit(2)
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> T {
+    value.parse().unwrap_or_else(|_| {
+        eprintln!("Invalid value '{}' of option --{}", value, name);
+        usage()
+    })
+}
+
+fn parse_config() -> LoadConfig {
+    let mut config = LoadConfig::default();
+
+    for arg in env::args().skip(1) {
+        let Some((name, value)) = arg
+            .strip_prefix("--")
+            .and_then(|option| option.split_once('='))
+        else {
+            usage()
+        };
+
+        match name {
+            "replicas" => config.replica_count = parse_number(name, value),
+            "duration-secs" => config.duration = Duration::from_secs(parse_number(name, value)),
+            "max-requests" => config.max_requests = parse_number(name, value),
+            "max-inflight" => config.max_inflight = parse_number(name, value),
+            "read-percent" => config.read_percent = parse_number(name, value),
+            "large-percent" => config.large_percent = parse_number(name, value),
+            "small-size" => config.small_value_size = parse_number(name, value),
+            "large-size" => config.large_value_size = parse_number(name, value),
+            "keys" => config.key_count = parse_number(name, value),
+            "report-secs" => {
+                config.report_interval = Duration::from_secs(parse_number(name, value))
+            }
+            "seed" => config.seed = parse_number(name, value),
+            _ => usage(),
+        }
+    }
+
+    if config.replica_count == 0 || config.max_inflight == 0 {
+        usage()
+    }
+
+    config
+}
+
+fn main() {
+    let config = parse_config();
+    println!("Generating load: {:?}", config);
+
+    let mut generator = LoadGenerator::new(config);
+    let report = generator.run(&mut |report| println!("{}", report));
+
+    println!("Summary:\n{}", report);
+}
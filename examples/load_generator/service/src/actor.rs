@@ -0,0 +1,250 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::apps::load_generator::service::{
+    load_generator_in_message, load_generator_out_message, LoadGeneratorConfig,
+    LoadGeneratorInMessage, LoadGeneratorOutMessage, LoadGeneratorSnapshot, LoadStatus,
+    ReadRequest, ReadResponse, WriteRequest, WriteResponse,
+};
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use hashbrown::HashMap;
+use prost::{bytes::Bytes, Message};
+use slog::{debug, warn};
+use tcp_runtime::model::{
+    Actor, ActorCommand, ActorContext, ActorError, ActorEvent, ActorEventContext, CommandOutcome,
+    EventOutcome, SnapshotWriter,
+};
+
+/// Replicated state machine used to put configurable load on the runtime.
+/// Writes are replicated through the log and stored in a bounded key space,
+/// reads are served by the leader from its local state once confirmed through
+/// ReadIndex.
+pub struct LoadGeneratorActor {
+    context: Option<Box<dyn ActorContext>>,
+    key_count: u64,
+    values: HashMap<u64, Bytes>,
+}
+
+impl LoadGeneratorActor {
+    pub fn new() -> Self {
+        LoadGeneratorActor {
+            context: None,
+            key_count: 0,
+            values: HashMap::new(),
+        }
+    }
+
+    fn get_context(&mut self) -> &mut dyn ActorContext {
+        self.context
+            .as_mut()
+            .expect("Context is initialized")
+            .as_mut()
+    }
+
+    fn fold_key(&self, key: u64) -> u64 {
+        if self.key_count == 0 {
+            key
+        } else {
+            key % self.key_count
+        }
+    }
+
+    fn process_read(&mut self, read_request: &ReadRequest) -> ReadResponse {
+        let value_size = self
+            .values
+            .get(&self.fold_key(read_request.key))
+            .map_or(0, |value| value.len() as u64);
+
+        ReadResponse {
+            status: LoadStatus::Success.into(),
+            value_size,
+        }
+    }
+
+    fn apply_write(&mut self, index: u64, write_request: WriteRequest) -> WriteResponse {
+        let key = self.fold_key(write_request.key);
+        debug!(
+            self.get_context().logger(),
+            "Applying at index #{} write of {} bytes to key {}",
+            index,
+            write_request.value.len(),
+            key
+        );
+
+        self.values.insert(key, write_request.value);
+
+        WriteResponse {
+            status: LoadStatus::Success.into(),
+            index,
+        }
+    }
+
+    fn create_response(correlation_id: u64, msg: load_generator_out_message::Msg) -> ActorCommand {
+        ActorCommand::with_header(correlation_id, &LoadGeneratorOutMessage { msg: Some(msg) })
+    }
+}
+
+impl Actor for LoadGeneratorActor {
+    fn on_init(&mut self, context: Box<dyn ActorContext>) -> Result<(), ActorError> {
+        self.context = Some(context);
+        self.values = HashMap::new();
+
+        let config = LoadGeneratorConfig::decode(self.get_context().config().as_ref())
+            .map_err(|_| ActorError::ConfigLoading)?;
+        self.key_count = config.key_count;
+
+        Ok(())
+    }
+
+    fn on_shutdown(&mut self) {}
+
+    fn on_save_snapshot(&mut self) -> Result<Bytes, ActorError> {
+        debug!(self.get_context().logger(), "Saving snapshot");
+
+        let snapshot = LoadGeneratorSnapshot {
+            values: self
+                .values
+                .iter()
+                .map(|(key, value)| (*key, value.clone()))
+                .collect::<BTreeMap<u64, Bytes>>(),
+        };
+
+        Ok(snapshot.encode_to_vec().into())
+    }
+
+    fn on_save_snapshot_chunked(
+        &mut self,
+        writer: &mut dyn SnapshotWriter,
+    ) -> Result<(), ActorError> {
+        debug!(self.get_context().logger(), "Saving snapshot in chunks");
+
+        // Concatenation of encoded messages decodes as a single message with
+        // merged map fields, hence every value is written out on its own.
+        let mut keys: Vec<u64> = self.values.keys().copied().collect();
+        keys.sort();
+        for key in keys {
+            let snapshot = LoadGeneratorSnapshot {
+                values: BTreeMap::from([(key, self.values[&key].clone())]),
+            };
+            writer.write(&snapshot.encode_to_vec())?;
+        }
+
+        Ok(())
+    }
+
+    fn on_load_snapshot(&mut self, snapshot: Bytes) -> Result<(), ActorError> {
+        debug!(self.get_context().logger(), "Loading snapshot");
+
+        let snapshot =
+            LoadGeneratorSnapshot::decode(snapshot).map_err(|_| ActorError::SnapshotLoading)?;
+
+        self.values = snapshot.values.into_iter().collect();
+
+        Ok(())
+    }
+
+    fn on_process_command(
+        &mut self,
+        command: Option<ActorCommand>,
+    ) -> Result<CommandOutcome, ActorError> {
+        if command.is_none() {
+            return Ok(CommandOutcome::with_none());
+        }
+        let command = command.unwrap();
+
+        let msg = match LoadGeneratorInMessage::decode(command.header.clone()) {
+            Ok(in_message) => in_message.msg,
+            Err(e) => {
+                warn!(self.get_context().logger(), "Rejecting command: {}", e);
+                None
+            }
+        };
+        let Some(msg) = msg else {
+            return Ok(CommandOutcome::with_command(Self::create_response(
+                command.correlation_id,
+                load_generator_out_message::Msg::WriteResponse(WriteResponse {
+                    status: LoadStatus::InvalidOperationError.into(),
+                    index: 0,
+                }),
+            )));
+        };
+
+        if !self.get_context().leader() {
+            warn!(
+                self.get_context().logger(),
+                "Rejecting #{} command: not a leader", command.correlation_id
+            );
+            let response = match msg {
+                load_generator_in_message::Msg::WriteRequest(_) => {
+                    load_generator_out_message::Msg::WriteResponse(WriteResponse {
+                        status: LoadStatus::Rejected.into(),
+                        index: 0,
+                    })
+                }
+                load_generator_in_message::Msg::ReadRequest(_) => {
+                    load_generator_out_message::Msg::ReadResponse(ReadResponse {
+                        status: LoadStatus::Rejected.into(),
+                        value_size: 0,
+                    })
+                }
+            };
+            return Ok(CommandOutcome::with_command(Self::create_response(
+                command.correlation_id,
+                response,
+            )));
+        }
+
+        match msg {
+            load_generator_in_message::Msg::WriteRequest(write_request) => {
+                Ok(CommandOutcome::with_event(ActorEvent::with_proto(
+                    command.correlation_id,
+                    &write_request,
+                )))
+            }
+            load_generator_in_message::Msg::ReadRequest(_)
+                if !self.get_context().read_confirmed() =>
+            {
+                // Reads are answered once confirmed to observe all committed writes.
+                Ok(CommandOutcome::with_read(command))
+            }
+            load_generator_in_message::Msg::ReadRequest(read_request) => {
+                let response = self.process_read(&read_request);
+                Ok(CommandOutcome::with_command(Self::create_response(
+                    command.correlation_id,
+                    load_generator_out_message::Msg::ReadResponse(response),
+                )))
+            }
+        }
+    }
+
+    fn on_apply_event(
+        &mut self,
+        context: ActorEventContext,
+        event: ActorEvent,
+    ) -> Result<EventOutcome, ActorError> {
+        let write_request =
+            WriteRequest::decode(event.contents).map_err(|_| ActorError::Internal)?;
+
+        let response = self.apply_write(context.index, write_request);
+
+        if context.owned {
+            return Ok(EventOutcome::with_command(Self::create_response(
+                event.correlation_id,
+                load_generator_out_message::Msg::WriteResponse(response),
+            )));
+        }
+
+        Ok(EventOutcome::with_none())
+    }
+}
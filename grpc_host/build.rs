@@ -15,14 +15,12 @@
 use std::io::Result;
 
 fn main() -> Result<()> {
-    micro_rpc_build::compile(
-        &["proto/tablet_store.proto"],
-        &["proto"],
-        micro_rpc_build::CompileOptions {
-            bytes: vec![".apps.tablet_store.service.TabletMetadata".to_string()],
-            extern_paths: vec![],
-            ..Default::default()
-        },
-    );
-    Ok(())
+    // Only the gRPC service is generated, the messages are shared with the
+    // runtime through tcp_proto.
+    tonic_build::configure()
+        .extern_path(".runtime.endpoint", "::tcp_proto::runtime::endpoint")
+        .compile(
+            &["../proto/src/endpoint.proto"],
+            &["../proto/src", "../proto_stubs"],
+        )
 }
@@ -0,0 +1,160 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Serves the `EndpointService` defined in endpoint.proto over gRPC, so that
+//! the replicas can run as ordinary processes for local development and
+//! integration testing. The requests are forwarded to the endpoint service
+//! running on its own thread through the [EndpointHandle], and the messages
+//! the replica sends out are carried back in the responses.
+
+pub mod proto {
+    tonic::include_proto!("runtime.endpoint");
+}
+
+use proto::endpoint_service_server::{EndpointService, EndpointServiceServer};
+use tcp_proto::runtime::endpoint::{ReceiveMessageRequest, ReceiveMessageResponse};
+use tcp_runtime::endpoint::EndpointHandle;
+use tonic::{Code, Request, Response, Status};
+
+/// Implements the gRPC endpoint service by passing the requests to the
+/// endpoint service behind the handle.
+pub struct GrpcEndpoint {
+    handle: EndpointHandle,
+}
+
+impl GrpcEndpoint {
+    pub fn new(handle: EndpointHandle) -> Self {
+        Self { handle }
+    }
+
+    /// Wraps the endpoint into the service to add to a tonic server.
+    pub fn into_service(self) -> EndpointServiceServer<Self> {
+        EndpointServiceServer::new(self)
+    }
+}
+
+#[tonic::async_trait]
+impl EndpointService for GrpcEndpoint {
+    async fn receive_message(
+        &self,
+        request: Request<ReceiveMessageRequest>,
+    ) -> Result<Response<ReceiveMessageResponse>, Status> {
+        let handle = self.handle.clone();
+        let request = request.into_inner();
+        // Handle blocks until the endpoint service thread responds, which must
+        // not stall the executor.
+        tokio::task::spawn_blocking(move || handle.receive_message(request))
+            .await
+            .map_err(|err| Status::internal(format!("Endpoint request has failed: {}", err)))?
+            .map(Response::new)
+            .map_err(convert_status)
+    }
+}
+
+fn convert_status(status: micro_rpc::Status) -> Status {
+    Status::new(
+        Code::from_i32(status.code as i32),
+        status.message.to_string(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proto::endpoint_service_client::EndpointServiceClient;
+    use tcp_proto::runtime::endpoint::OutMessage;
+    use tcp_runtime::endpoint::spawn_endpoint;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+
+    // Responds with as many messages as the instant of the request, fails on
+    // the instant of zero.
+    struct FakeService {}
+
+    impl tcp_proto::runtime::endpoint::EndpointService for FakeService {
+        fn receive_message(
+            &mut self,
+            request: ReceiveMessageRequest,
+        ) -> Result<ReceiveMessageResponse, micro_rpc::Status> {
+            if request.instant == 0 {
+                return Err(micro_rpc::Status::new_with_message(
+                    micro_rpc::StatusCode::InvalidArgument,
+                    "instant is zero",
+                ));
+            }
+            Ok(ReceiveMessageResponse {
+                messages: (0..request.instant)
+                    .map(|_| OutMessage::default())
+                    .collect(),
+                ..Default::default()
+            })
+        }
+    }
+
+    fn create_request(instant: u64) -> ReceiveMessageRequest {
+        ReceiveMessageRequest {
+            instant,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_grpc_endpoint() {
+        let (handle, _) = spawn_endpoint(|| FakeService {});
+        let endpoint = GrpcEndpoint::new(handle);
+
+        let response = EndpointService::receive_message(&endpoint, Request::new(create_request(2)))
+            .await
+            .unwrap();
+        assert_eq!(response.into_inner().messages.len(), 2);
+
+        let status = EndpointService::receive_message(&endpoint, Request::new(create_request(0)))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "instant is zero");
+    }
+
+    #[tokio::test]
+    async fn test_grpc_endpoint_server() {
+        let (handle, _) = spawn_endpoint(|| FakeService {});
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(GrpcEndpoint::new(handle).into_service())
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let mut client = EndpointServiceClient::connect(format!("http://{}", address))
+            .await
+            .unwrap();
+        for instant in 1..4 {
+            let response = client
+                .receive_message(create_request(instant))
+                .await
+                .unwrap();
+            assert_eq!(response.into_inner().messages.len(), instant as usize);
+        }
+        assert_eq!(
+            client
+                .receive_message(create_request(0))
+                .await
+                .unwrap_err()
+                .code(),
+            Code::InvalidArgument
+        );
+    }
+}
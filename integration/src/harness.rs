@@ -14,15 +14,16 @@
 
 #![allow(dead_code)]
 
-use alloc::{boxed::Box, vec::Vec};
+use alloc::{boxed::Box, rc::Rc, string::String, vec::Vec};
 use core::cell::RefCell;
 use core::mem;
 use hashbrown::HashMap;
 use prost::bytes::Bytes;
 use slog::{info, Logger};
-use tcp_proto::runtime::endpoint::raft_config::SnapshotConfig;
+use tcp_proto::runtime::endpoint::raft_config::{ActorErrorPolicy, SnapshotConfig};
 use tcp_proto::runtime::endpoint::*;
 use tcp_runtime::attestation::DefaultAttestationProvider;
+use tcp_runtime::clock::{Clock, ManualClock};
 use tcp_runtime::communication::DefaultCommunicationModule;
 use tcp_runtime::driver::Driver;
 use tcp_runtime::handshake::DefaultHandshakeSessionProvider;
@@ -30,6 +31,8 @@ use tcp_runtime::logger::log::create_logger;
 use tcp_runtime::model::Actor;
 use tcp_runtime::oak_handshaker::DefaultOakHandshakerFactory;
 use tcp_runtime::platform::{Application, Host};
+use tcp_runtime::recovery::StateJournal;
+use tcp_runtime::sealed::{HostSealedStorage, SealingEncryptor, SealingKeyDeriver};
 use tcp_runtime::snapshot::{
     DefaultSnapshotProcessor, DefaultSnapshotReceiver, DefaultSnapshotSender,
 };
@@ -93,6 +96,57 @@ impl<A: Actor> FakeCluster<A> {
             .send_start_node(self.app_config.clone(), leader);
     }
 
+    /// Restarts the node from the replica state persisted by its host so that
+    /// it rejoins the cluster at its previous position.
+    pub fn restart_node(&mut self, node_id: u64, actor: A) {
+        let mut platform = self.platforms.remove(&node_id).unwrap();
+        let recovery_state = platform.take_recovery_state();
+        let sealed_secrets = platform.take_sealed_secrets();
+
+        if self.leader_id == node_id {
+            self.leader_id = 0;
+        }
+
+        self.platforms.insert(
+            node_id,
+            FakePlatform::new(node_id, self.app_config.clone(), actor),
+        );
+
+        self.platforms.get_mut(&node_id).unwrap().send_restart_node(
+            self.app_config.clone(),
+            recovery_state,
+            sealed_secrets,
+        );
+    }
+
+    /// Starts the node as the leader of a new cluster with the actor state
+    /// restored from the snapshot exported by a node of the previous cluster.
+    /// The sealed secrets of a node of the previous cluster carry the cluster
+    /// secret the snapshot is authenticated with.
+    pub fn start_node_from_snapshot(
+        &mut self,
+        node_id: u64,
+        actor: A,
+        exported_snapshot: ExportedSnapshot,
+        sealed_secrets: Vec<SealedSecret>,
+    ) {
+        self.platforms.insert(
+            node_id,
+            FakePlatform::new(node_id, self.app_config.clone(), actor),
+        );
+
+        self.platforms.get_mut(&node_id).unwrap().send_import_node(
+            self.app_config.clone(),
+            exported_snapshot,
+            sealed_secrets,
+        );
+    }
+
+    /// Returns the secrets the node has sealed so far.
+    pub fn sealed_secrets(&self, node_id: u64) -> Vec<SealedSecret> {
+        self.platforms.get(&node_id).unwrap().sealed_secrets()
+    }
+
     pub fn stop_node(&mut self, node_id: u64) {
         self.platforms.remove(&node_id);
 
@@ -119,6 +173,218 @@ impl<A: Actor> FakeCluster<A> {
         });
     }
 
+    pub fn reconfigure_cluster(&mut self, add_node_ids: Vec<u64>, remove_node_ids: Vec<u64>) {
+        self.platforms
+            .get_mut(&self.leader_id)
+            .unwrap()
+            .send_reconfigure_cluster(0, add_node_ids.clone(), remove_node_ids.clone());
+
+        // Wait until the cluster has left the joint consensus.
+        self.advance_until(&mut |envelope_out| match &envelope_out.msg {
+            Some(out_message::Msg::CheckCluster(response)) => {
+                !response.has_pending_changes
+                    && add_node_ids
+                        .iter()
+                        .all(|node_id| response.cluster_replica_ids.contains(node_id))
+                    && !remove_node_ids
+                        .iter()
+                        .any(|node_id| response.cluster_replica_ids.contains(node_id))
+            }
+            _ => false,
+        });
+    }
+
+    pub fn add_learner_to_cluster(&mut self, node_id: u64) {
+        self.platforms
+            .get_mut(&self.leader_id)
+            .unwrap()
+            .send_change_cluster(0, node_id, ChangeClusterType::ChangeTypeAddLearner);
+
+        self.advance_until_learner_in_cluster(node_id);
+    }
+
+    pub fn promote_learner_in_cluster(&mut self, node_id: u64) {
+        // Promotion is rejected until the learner catches up with the leader log,
+        // keep retrying until it is accepted.
+        loop {
+            self.platforms
+                .get_mut(&self.leader_id)
+                .unwrap()
+                .send_change_cluster(
+                    node_id,
+                    node_id,
+                    ChangeClusterType::ChangeTypePromoteLearner,
+                );
+
+            let responses = self.advance_until(&mut |envelope_out| match &envelope_out.msg {
+                Some(out_message::Msg::ChangeCluster(response)) => response.change_id == node_id,
+                _ => false,
+            });
+
+            let accepted = responses
+                .iter()
+                .any(|envelope_out| match &envelope_out.msg {
+                    Some(out_message::Msg::ChangeCluster(response)) => {
+                        response.change_status() == ChangeClusterStatus::ChangeStatusPending
+                    }
+                    _ => false,
+                });
+            if accepted {
+                break;
+            }
+        }
+
+        self.advance_until_added_to_cluster(node_id);
+    }
+
+    pub fn demote_node_in_cluster(&mut self, node_id: u64) {
+        // Drop stale cluster reports that may still list the node as a learner.
+        self.extract_pull_messages(&mut |envelope_out| {
+            matches!(envelope_out.msg, Some(out_message::Msg::CheckCluster(_)))
+        });
+
+        self.platforms
+            .get_mut(&self.leader_id)
+            .unwrap()
+            .send_change_cluster(0, node_id, ChangeClusterType::ChangeTypeDemoteReplica);
+
+        self.advance_until_learner_in_cluster(node_id);
+    }
+
+    pub fn advance_until_learner_in_cluster(&mut self, node_id: u64) {
+        self.advance_until(&mut |envelope_out| match &envelope_out.msg {
+            Some(out_message::Msg::CheckCluster(response)) => {
+                !response.has_pending_changes && response.cluster_learner_ids.contains(&node_id)
+            }
+            _ => false,
+        });
+    }
+
+    pub fn transfer_leadership(&mut self, target_node_id: u64) -> TransferLeadershipStatus {
+        let leader_id = self.leader_id;
+        self.platforms
+            .get_mut(&leader_id)
+            .unwrap()
+            .send_transfer_leadership(target_node_id, target_node_id);
+
+        let mut transfer_status = TransferLeadershipStatus::TransferStatusUnspecified;
+        self.advance_until(&mut |envelope_out| match &envelope_out.msg {
+            Some(out_message::Msg::TransferLeadership(response))
+                if response.transfer_id == target_node_id =>
+            {
+                transfer_status = response.transfer_status();
+                true
+            }
+            _ => false,
+        });
+
+        if transfer_status == TransferLeadershipStatus::TransferStatusCompleted {
+            self.advance_until_elected_leader(Some(leader_id));
+        }
+
+        transfer_status
+    }
+
+    pub fn prepare_shutdown(&mut self, node_id: u64) -> u64 {
+        self.platforms
+            .get_mut(&node_id)
+            .unwrap()
+            .send_prepare_shutdown();
+
+        let mut applied_index = 0;
+        self.advance_until(&mut |envelope_out| match &envelope_out.msg {
+            Some(out_message::Msg::PrepareShutdown(response)) => {
+                applied_index = response.applied_index;
+                true
+            }
+            _ => false,
+        });
+
+        if node_id == self.leader_id {
+            self.advance_until_elected_leader(Some(node_id));
+        }
+
+        applied_index
+    }
+
+    pub fn replication_status(&mut self) -> GetReplicationStatusResponse {
+        let leader_id = self.leader_id;
+        self.platforms
+            .get_mut(&leader_id)
+            .unwrap()
+            .send_get_replication_status();
+
+        let mut replication_status = GetReplicationStatusResponse::default();
+        self.advance_until(&mut |envelope_out| match &envelope_out.msg {
+            Some(out_message::Msg::GetReplicationStatus(response)) => {
+                replication_status = response.clone();
+                true
+            }
+            _ => false,
+        });
+
+        replication_status
+    }
+
+    pub fn replica_status(&mut self, node_id: u64) -> GetReplicaStatusResponse {
+        self.platforms
+            .get_mut(&node_id)
+            .unwrap()
+            .send_get_replica_status();
+
+        let mut replica_status = GetReplicaStatusResponse::default();
+        self.advance_until(&mut |envelope_out| match &envelope_out.msg {
+            Some(out_message::Msg::GetReplicaStatus(response))
+                if response.replica_id == node_id =>
+            {
+                replica_status = response.clone();
+                true
+            }
+            _ => false,
+        });
+
+        replica_status
+    }
+
+    pub fn health_check(&mut self, node_id: u64, max_apply_lag: u64) -> HealthCheckResponse {
+        self.platforms
+            .get_mut(&node_id)
+            .unwrap()
+            .send_health_check(max_apply_lag);
+
+        let mut health = HealthCheckResponse::default();
+        self.extract_pull_messages(&mut |envelope_out| {
+            matches!(envelope_out.msg, Some(out_message::Msg::HealthCheck(_)))
+        });
+        self.advance_until(&mut |envelope_out| match &envelope_out.msg {
+            Some(out_message::Msg::HealthCheck(response)) => {
+                health = response.clone();
+                true
+            }
+            _ => false,
+        });
+
+        health
+    }
+
+    pub fn export_snapshot(&mut self, node_id: u64) -> Option<ExportedSnapshot> {
+        self.platforms
+            .get_mut(&node_id)
+            .unwrap()
+            .send_export_snapshot();
+
+        let mut exported_snapshot = None;
+        self.advance_until(&mut |envelope_out| match &envelope_out.msg {
+            Some(out_message::Msg::ExportSnapshot(response)) => {
+                exported_snapshot = response.exported_snapshot.clone();
+                true
+            }
+            _ => false,
+        });
+
+        exported_snapshot
+    }
+
     pub fn advance_until_elected_leader(&mut self, excluding_node_id: Option<u64>) {
         let mut leader_id = 0;
 
@@ -190,6 +456,12 @@ impl<A: Actor> FakeCluster<A> {
                             in_message::Msg::SecureChannelHandshake(secure_channel_handshake),
                         ));
                     }
+                    Some(out_message::Msg::PersistReplicaState(persist_replica_state)) => {
+                        platform.persist_replica_state(persist_replica_state);
+                    }
+                    Some(out_message::Msg::PersistSealedSecret(sealed_secret)) => {
+                        platform.persist_sealed_secret(sealed_secret);
+                    }
                     _ => {
                         self.pull_messages.push(message_out);
                     }
@@ -252,7 +524,12 @@ impl<A: Actor> FakeCluster<A> {
 pub struct FakePlatform<A: Actor> {
     id: u64,
     messages_in: Vec<InMessage>,
-    instant: u64,
+    // Replica state updates starting with the latest checkpoint.
+    persisted_updates: Vec<PersistReplicaState>,
+    // Latest sealed secrets persisted by the replica keyed by name.
+    persisted_secrets: HashMap<String, SealedSecret>,
+    sealed_storage: Rc<HostSealedStorage>,
+    clock: Rc<ManualClock>,
     driver: RefCell<
         Driver<
             RaftSimple<MemoryStorage>,
@@ -267,23 +544,38 @@ pub struct FakePlatform<A: Actor> {
 
 impl<A: Actor> FakePlatform<A> {
     pub fn new(id: u64, app_config: Bytes, actor: A) -> FakePlatform<A> {
+        let clock = Rc::new(ManualClock::new(0));
+        let sealed_storage = Rc::new(HostSealedStorage::new(Box::new(SealingEncryptor::new(
+            Box::new(FakeSealingKeyDeriver {}),
+            FAKE_SECURITY_VERSION,
+        ))));
         FakePlatform {
             id,
             messages_in: Vec::new(),
-            instant: 0,
-            driver: RefCell::new(Driver::new(
-                RaftSimple::new(),
-                Box::new(MemoryStorage::new),
-                DefaultSnapshotProcessor::new(
-                    Box::new(DefaultSnapshotSender::new()),
-                    Box::new(DefaultSnapshotReceiver::new()),
-                ),
-                actor,
-                DefaultCommunicationModule::new(Box::new(DefaultHandshakeSessionProvider::new(
-                    Box::new(DefaultAttestationProvider {}),
-                    Box::new(DefaultOakHandshakerFactory {}),
-                ))),
-            )),
+            persisted_updates: Vec::new(),
+            persisted_secrets: HashMap::new(),
+            sealed_storage: Rc::clone(&sealed_storage),
+            clock: Rc::clone(&clock),
+            driver: RefCell::new(
+                Driver::new(
+                    RaftSimple::new(),
+                    Box::new(MemoryStorage::new),
+                    DefaultSnapshotProcessor::new(
+                        Box::new(DefaultSnapshotSender::new()),
+                        Box::new(DefaultSnapshotReceiver::new()),
+                    ),
+                    actor,
+                    DefaultCommunicationModule::new(Box::new(
+                        DefaultHandshakeSessionProvider::new(
+                            Box::new(DefaultAttestationProvider {}),
+                            Box::new(DefaultOakHandshakerFactory {}),
+                        ),
+                    )),
+                    Box::new(StateJournal::new()),
+                    clock,
+                )
+                .with_sealed_storage(sealed_storage),
+            ),
             host: RefCell::new(FakeHost::new(app_config)),
         }
     }
@@ -293,25 +585,138 @@ impl<A: Actor> FakePlatform<A> {
             msg: Some(in_message::Msg::StartReplica(StartReplicaRequest {
                 is_leader,
                 replica_id_hint: self.id,
-                raft_config: Some(RaftConfig {
-                    tick_period: 10,
-                    election_tick: 20,
-                    heartbeat_tick: 2,
-                    max_size_per_msg: 0,
-                    snapshot_config: Some(SnapshotConfig {
-                        snapshot_count: 1000,
-                        chunk_size: 20,
-                        max_pending_chunks: 2,
-                    }),
-                    handshake_retry_tick: 1,
-                }),
+                raft_config: Some(Self::create_raft_config()),
+                app_config: app_config,
+                attestation_config: None,
+                is_ephemeral: false,
+                recovery_state: None,
+                is_witness: false,
+                imported_snapshot: None,
+                sealed_secrets: vec![],
+            })),
+        });
+    }
+
+    pub fn send_restart_node(
+        &mut self,
+        app_config: Bytes,
+        recovery_state: ReplicaRecoveryState,
+        sealed_secrets: Vec<SealedSecret>,
+    ) {
+        self.append_message_in(InMessage {
+            msg: Some(in_message::Msg::StartReplica(StartReplicaRequest {
+                is_leader: false,
+                replica_id_hint: self.id,
+                raft_config: Some(Self::create_raft_config()),
+                app_config: app_config,
+                attestation_config: None,
+                is_ephemeral: false,
+                recovery_state: Some(recovery_state),
+                is_witness: false,
+                imported_snapshot: None,
+                sealed_secrets,
+            })),
+        });
+    }
+
+    pub fn send_import_node(
+        &mut self,
+        app_config: Bytes,
+        exported_snapshot: ExportedSnapshot,
+        sealed_secrets: Vec<SealedSecret>,
+    ) {
+        self.append_message_in(InMessage {
+            msg: Some(in_message::Msg::StartReplica(StartReplicaRequest {
+                is_leader: true,
+                replica_id_hint: self.id,
+                raft_config: Some(Self::create_raft_config()),
                 app_config: app_config,
                 attestation_config: None,
                 is_ephemeral: false,
+                recovery_state: None,
+                is_witness: false,
+                imported_snapshot: Some(exported_snapshot),
+                sealed_secrets,
             })),
         });
     }
 
+    fn create_raft_config() -> RaftConfig {
+        RaftConfig {
+            tick_period: 10,
+            election_tick: 20,
+            heartbeat_tick: 2,
+            max_size_per_msg: 0,
+            snapshot_config: Some(SnapshotConfig {
+                snapshot_count: 1000,
+                chunk_size: 20,
+                max_pending_chunks: 2,
+                delta_transfer: false,
+                max_delivery_retries: 0,
+                encrypt_payloads: false,
+            }),
+            handshake_retry_tick: 1,
+            message_priority_config: None,
+            mailbox_config: None,
+            flow_control_config: None,
+            persist_replica_state: true,
+            follower_read_config: None,
+            pre_vote: true,
+            check_quorum: false,
+            lease_based_reads: false,
+            max_clock_drift_ppm: 0,
+            proposal_batch_config: None,
+            eviction_config: None,
+            min_election_tick: 0,
+            max_election_tick: 0,
+            compaction_config: None,
+            replicated_randomness: false,
+            actor_error_policy: ActorErrorPolicy::Unspecified.into(),
+            max_wall_clock_skew: 0,
+            attestation_policy: None,
+            rekey_config: None,
+            log_config: None,
+            metrics_config: None,
+            share_cluster_secret: true,
+        }
+    }
+
+    pub fn persist_replica_state(&mut self, persist_replica_state: PersistReplicaState) {
+        // Updates preceding the checkpoint are no longer needed.
+        if persist_replica_state.checkpoint {
+            self.persisted_updates.clear();
+        }
+        self.persisted_updates.push(persist_replica_state);
+    }
+
+    pub fn persist_sealed_secret(&mut self, sealed_secret: SealedSecret) {
+        // Only the latest version of the secret is needed to unseal it.
+        self.persisted_secrets
+            .insert(sealed_secret.name.clone(), sealed_secret);
+    }
+
+    pub fn sealed_secrets(&self) -> Vec<SealedSecret> {
+        self.persisted_secrets.values().cloned().collect()
+    }
+
+    pub fn take_sealed_secrets(&mut self) -> Vec<SealedSecret> {
+        mem::take(&mut self.persisted_secrets)
+            .into_values()
+            .collect()
+    }
+
+    pub fn sealed_storage(&self) -> Rc<HostSealedStorage> {
+        Rc::clone(&self.sealed_storage)
+    }
+
+    pub fn take_recovery_state(&mut self) -> ReplicaRecoveryState {
+        let persisted_updates = mem::take(&mut self.persisted_updates);
+        ReplicaRecoveryState {
+            min_counter: persisted_updates.last().map_or(0, |update| update.counter),
+            updates: persisted_updates,
+        }
+    }
+
     pub fn send_stop_node(&mut self) {
         self.append_message_in(InMessage {
             msg: Some(in_message::Msg::StopReplica(StopReplicaRequest {})),
@@ -333,6 +738,70 @@ impl<A: Actor> FakePlatform<A> {
         });
     }
 
+    pub fn send_reconfigure_cluster(
+        &mut self,
+        change_id: u64,
+        add_replica_ids: Vec<u64>,
+        remove_replica_ids: Vec<u64>,
+    ) {
+        self.append_message_in(InMessage {
+            msg: Some(in_message::Msg::ReconfigureCluster(
+                ReconfigureClusterRequest {
+                    change_id,
+                    add_replica_ids,
+                    remove_replica_ids,
+                },
+            )),
+        });
+    }
+
+    pub fn send_transfer_leadership(&mut self, transfer_id: u64, target_replica_id: u64) {
+        self.append_message_in(InMessage {
+            msg: Some(in_message::Msg::TransferLeadership(
+                TransferLeadershipRequest {
+                    transfer_id,
+                    target_replica_id,
+                },
+            )),
+        });
+    }
+
+    pub fn send_prepare_shutdown(&mut self) {
+        self.append_message_in(InMessage {
+            msg: Some(in_message::Msg::PrepareShutdown(PrepareShutdownRequest {})),
+        });
+    }
+
+    pub fn send_get_replication_status(&mut self) {
+        self.append_message_in(InMessage {
+            msg: Some(in_message::Msg::GetReplicationStatus(
+                GetReplicationStatusRequest {},
+            )),
+        });
+    }
+
+    pub fn send_get_replica_status(&mut self) {
+        self.append_message_in(InMessage {
+            msg: Some(in_message::Msg::GetReplicaStatus(
+                GetReplicaStatusRequest {},
+            )),
+        });
+    }
+
+    pub fn send_health_check(&mut self, max_apply_lag: u64) {
+        self.append_message_in(InMessage {
+            msg: Some(in_message::Msg::HealthCheck(HealthCheckRequest {
+                max_apply_lag,
+            })),
+        });
+    }
+
+    pub fn send_export_snapshot(&mut self) {
+        self.append_message_in(InMessage {
+            msg: Some(in_message::Msg::ExportSnapshot(ExportSnapshotRequest {})),
+        });
+    }
+
     pub fn send_check_cluster(&mut self) {
         self.append_message_in(InMessage {
             msg: Some(in_message::Msg::CheckCluster(CheckClusterRequest {})),
@@ -345,12 +814,14 @@ impl<A: Actor> FakePlatform<A> {
                 correlation_id,
                 message_header: header,
                 message_payload: payload,
+                route: String::new(),
+                status_code: 0,
             })),
         });
     }
 
     pub fn advance_time(&mut self, duration: u64) {
-        self.instant += duration;
+        self.clock.advance(duration);
     }
 
     pub fn append_message_in(&mut self, message_in: InMessage) {
@@ -365,12 +836,12 @@ impl<A: Actor> FakePlatform<A> {
 
         if messages.is_empty() {
             driver
-                .receive_message(&mut *host, self.instant, None)
+                .receive_message(&mut *host, self.clock.instant(), None)
                 .unwrap();
         } else {
             for message in messages {
                 driver
-                    .receive_message(&mut *host, self.instant, Some(message))
+                    .receive_message(&mut *host, self.clock.instant(), Some(message))
                     .unwrap()
             }
         }
@@ -381,6 +852,21 @@ impl<A: Actor> FakePlatform<A> {
     }
 }
 
+// Security version of the enclave the fake platform derives the sealing keys for.
+const FAKE_SECURITY_VERSION: u32 = 1;
+
+// Derives the sealing keys from the fixed identity of the fake enclave such that
+// the secrets sealed by a replica can be unsealed by the restarted replica.
+struct FakeSealingKeyDeriver {}
+
+impl SealingKeyDeriver for FakeSealingKeyDeriver {
+    fn derive_key(&self, security_version: u32) -> anyhow::Result<Vec<u8>> {
+        let mut key = b"fake enclave identity".to_vec();
+        key.extend_from_slice(&security_version.to_le_bytes());
+        Ok(key)
+    }
+}
+
 pub struct FakeHost {
     config: Bytes,
     messages_out: Vec<OutMessage>,
@@ -12,9 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::Result;
+use std::{env, io::Result};
+
+// Checks if the cargo feature with the given name is enabled for this crate.
+fn feature_enabled(name: &str) -> bool {
+    env::var_os(format!("CARGO_FEATURE_{}", name.to_uppercase())).is_some()
+}
 
 fn main() -> Result<()> {
+    // Runtime protos are always compiled.
     micro_rpc_build::compile(
         &["src/endpoint.proto"],
         &["src", "../proto_stubs"],
@@ -28,6 +34,12 @@ fn main() -> Result<()> {
                 ".runtime.endpoint.ExecuteProposalResponse".to_string(),
                 ".runtime.endpoint.DeliverAppMessage".to_string(),
                 ".runtime.endpoint.Entry".to_string(),
+                ".runtime.endpoint.PersistReplicaState".to_string(),
+                ".runtime.endpoint.RoutedEvent".to_string(),
+                ".runtime.endpoint.RoutedSnapshot".to_string(),
+                ".runtime.endpoint.SendAppMessage".to_string(),
+                ".runtime.endpoint.UpdateActorConfigRequest".to_string(),
+                ".runtime.endpoint.ActorConfigUpdate".to_string(),
             ],
             extern_paths: vec![
                 micro_rpc_build::ExternPath::new(
@@ -42,5 +54,80 @@ fn main() -> Result<()> {
             ..Default::default()
         },
     );
+
+    // App protos are compiled only for the apps enabled through cargo features.
+    if feature_enabled("atomic_counter") {
+        micro_rpc_build::compile(
+            &["../apps/atomic_counter/service/proto/atomic_counter.proto"],
+            &["../apps/atomic_counter/service/proto"],
+            micro_rpc_build::CompileOptions {
+                bytes: vec![
+                    ".apps.atomic_counter.service.CounterSnapshotValue".to_string(),
+                    ".apps.atomic_counter.service.CounterRequest".to_string(),
+                ],
+                ..Default::default()
+            },
+        );
+    }
+
+    if feature_enabled("ledger") {
+        micro_rpc_build::compile(
+            &["../apps/ledger/service/proto/ledger_actor.proto"],
+            &[
+                "../apps/ledger/service/proto",
+                "../apps/ledger/federated_compute/proto",
+                "../proto_stubs",
+            ],
+            micro_rpc_build::CompileOptions {
+                extern_paths: vec![
+                    micro_rpc_build::ExternPath::new(
+                        ".oak.attestation.v1",
+                        "::oak_proto_rust::oak::attestation::v1",
+                    ),
+                    micro_rpc_build::ExternPath::new(
+                        ".fcp.confidentialcompute",
+                        "::federated_compute::proto",
+                    ),
+                ],
+                ..Default::default()
+            },
+        );
+    }
+
+    // All bytes fields of the tablet protos are represented as shared bytes to
+    // avoid copying tablet contents and hashes.
+    if feature_enabled("tablet_store") {
+        micro_rpc_build::compile(
+            &["../apps/tablet_store/service/proto/tablet_store.proto"],
+            &["../apps/tablet_store/service/proto"],
+            micro_rpc_build::CompileOptions {
+                bytes: vec![".apps.tablet_store.service".to_string()],
+                ..Default::default()
+            },
+        );
+    }
+
+    if feature_enabled("tablet_cache") {
+        micro_rpc_build::compile(
+            &["../apps/tablet_cache/service/proto/tablet_cache.proto"],
+            &["../apps/tablet_cache/service/proto"],
+            micro_rpc_build::CompileOptions {
+                bytes: vec![".apps.tablet_cache.service".to_string()],
+                ..Default::default()
+            },
+        );
+    }
+
+    if feature_enabled("load_generator") {
+        micro_rpc_build::compile(
+            &["../examples/load_generator/service/proto/load_generator.proto"],
+            &["../examples/load_generator/service/proto"],
+            micro_rpc_build::CompileOptions {
+                bytes: vec![".apps.load_generator.service".to_string()],
+                ..Default::default()
+            },
+        );
+    }
+
     Ok(())
 }
@@ -23,3 +23,100 @@ pub mod runtime {
         include!(concat!(env!("OUT_DIR"), "/runtime.endpoint.rs"));
     }
 }
+
+#[cfg(any(
+    feature = "atomic_counter",
+    feature = "tablet_store",
+    feature = "tablet_cache",
+    feature = "load_generator"
+))]
+pub mod apps {
+    #[cfg(feature = "atomic_counter")]
+    pub mod atomic_counter {
+        pub mod service {
+            include!(concat!(env!("OUT_DIR"), "/apps.atomic_counter.service.rs"));
+        }
+    }
+
+    #[cfg(feature = "tablet_store")]
+    pub mod tablet_store {
+        pub mod service {
+            include!(concat!(env!("OUT_DIR"), "/apps.tablet_store.service.rs"));
+        }
+    }
+
+    #[cfg(feature = "tablet_cache")]
+    pub mod tablet_cache {
+        pub mod service {
+            include!(concat!(env!("OUT_DIR"), "/apps.tablet_cache.service.rs"));
+        }
+    }
+
+    #[cfg(feature = "load_generator")]
+    pub mod load_generator {
+        pub mod service {
+            include!(concat!(env!("OUT_DIR"), "/apps.load_generator.service.rs"));
+        }
+    }
+}
+
+#[cfg(feature = "ledger")]
+pub mod ledger {
+    pub mod service {
+        include!(concat!(env!("OUT_DIR"), "/ledger.service.rs"));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use prost::bytes::Bytes;
+    use prost::Message;
+
+    #[test]
+    fn test_runtime_protos() {
+        use crate::runtime::endpoint::StartReplicaRequest;
+
+        let request = StartReplicaRequest {
+            is_leader: true,
+            app_config: Bytes::from_static(b"config"),
+            ..Default::default()
+        };
+        assert_eq!(
+            StartReplicaRequest::decode(request.encode_to_vec().as_slice()).unwrap(),
+            request
+        );
+    }
+
+    #[cfg(feature = "atomic_counter")]
+    #[test]
+    fn test_atomic_counter_protos() {
+        use crate::apps::atomic_counter::service::CounterRequest;
+
+        let request = CounterRequest {
+            name: "counter".into(),
+            context: Bytes::from_static(b"context"),
+            ..Default::default()
+        };
+        assert_eq!(
+            CounterRequest::decode(request.encode_to_vec().as_slice()).unwrap(),
+            request
+        );
+    }
+
+    #[cfg(feature = "tablet_store")]
+    #[test]
+    fn test_tablet_store_protos() {
+        use crate::apps::tablet_store::service::TabletMetadata;
+
+        // All bytes fields of the tablet protos are shared bytes.
+        let metadata = TabletMetadata {
+            tablet_id: 1,
+            blob_hash: Bytes::from_static(b"hash"),
+            ..Default::default()
+        };
+        assert_eq!(
+            TabletMetadata::decode(metadata.encode_to_vec().as_slice()).unwrap(),
+            metadata
+        );
+    }
+}
@@ -0,0 +1,140 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Measures allocations performed per committed entry on the path from the
+//! proposal through append to apply. Run with `cargo bench --features std`.
+
+use prost::bytes::Bytes;
+use prost::Message;
+use raft::eraftpb::{Entry as RaftEntry, EntryType as RaftEntryType};
+use slog::{o, Discard, Logger};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use tcp_proto::runtime::endpoint::Entry;
+use tcp_runtime::consensus::Store;
+use tcp_runtime::storage::MemoryStorage;
+use tcp_runtime::util::raft::{create_entry, create_entry_id, create_raft_entry};
+
+/// Allocator that counts the number of allocations.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const ENTRY_COUNT: u64 = 1000;
+const ENTRY_SIZE: usize = 4096;
+const BATCH_SIZE: u64 = 10;
+
+/// Creates Raft entries carrying encoded proposals the same way the driver does.
+fn create_raft_entries(first_index: u64, count: u64) -> Vec<RaftEntry> {
+    (first_index..first_index + count)
+        .map(|index| {
+            let proposal = create_entry(
+                create_entry_id(1, index),
+                Bytes::from(vec![index as u8; ENTRY_SIZE]),
+            );
+            create_raft_entry(
+                index,
+                1,
+                RaftEntryType::EntryNormal,
+                proposal.encode_to_vec().into(),
+            )
+        })
+        .collect()
+}
+
+/// Appends entries by copying them and decodes committed entries from a
+/// borrowed payload, which copies entry contents once more.
+fn copy_entries(
+    storage: &mut MemoryStorage,
+    entries: Vec<RaftEntry>,
+    committed_entries: Vec<RaftEntry>,
+) -> usize {
+    storage.append_entries(entries.to_vec()).unwrap();
+
+    let mut size = 0;
+    for committed_entry in &committed_entries {
+        let entry = Entry::decode(committed_entry.data.as_slice()).unwrap();
+        size += entry.entry_contents.len();
+    }
+    size
+}
+
+/// Moves entries into the storage and decodes committed entries from shared
+/// bytes so that entry contents reference the payload buffer.
+fn move_entries(
+    storage: &mut MemoryStorage,
+    entries: Vec<RaftEntry>,
+    mut committed_entries: Vec<RaftEntry>,
+) -> usize {
+    storage.append_entries(entries).unwrap();
+
+    let mut size = 0;
+    for committed_entry in &mut committed_entries {
+        let entry = Entry::decode(Bytes::from(mem::take(&mut committed_entry.data))).unwrap();
+        size += entry.entry_contents.len();
+    }
+    size
+}
+
+fn run(name: &str, process: impl Fn(&mut MemoryStorage, Vec<RaftEntry>, Vec<RaftEntry>) -> usize) {
+    let mut storage = MemoryStorage::new(Logger::root(Discard, o!()), ENTRY_COUNT);
+    let mut allocations = 0;
+    let mut size = 0;
+
+    let start = Instant::now();
+    for first_index in (1..=ENTRY_COUNT).step_by(BATCH_SIZE as usize) {
+        // Entries to append and to apply are handed out by Raft, allocations made
+        // to create them are not attributed to the processing.
+        let entries = create_raft_entries(first_index, BATCH_SIZE);
+        let committed_entries = entries.clone();
+        let allocations_before = ALLOCATIONS.load(Ordering::Relaxed);
+        size += process(&mut storage, entries, committed_entries);
+        allocations += ALLOCATIONS.load(Ordering::Relaxed) - allocations_before;
+    }
+    let elapsed = start.elapsed();
+
+    assert_eq!(size, (ENTRY_COUNT as usize) * ENTRY_SIZE);
+    println!(
+        "{}: {:.2} allocations per committed entry, {:?} total",
+        name,
+        allocations as f64 / ENTRY_COUNT as f64,
+        elapsed
+    );
+}
+
+fn main() {
+    run("copy", copy_entries);
+    run("move", move_entries);
+}
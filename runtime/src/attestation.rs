@@ -12,17 +12,29 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::clock::Clock;
 use alloc::boxed::Box;
+use alloc::rc::Rc;
 use alloc::string::String;
 use alloc::vec;
+use alloc::vec::Vec;
+use anyhow::{anyhow, ensure};
+use core::cell::RefCell;
+use hashbrown::HashMap;
 use oak_proto_rust::oak::{
-    attestation::v1::{AttestationResults, ExtractedEvidence},
+    attestation::v1::{
+        endorsements, AttestationResults, EndorsedEvidence, Endorsements, Evidence,
+        ExtractedEvidence, OakRestrictedKernelEndorsements, RootLayerEndorsements,
+        RootLayerEvidence, TeePlatform,
+    },
     session::v1::{AttestRequest, AttestResponse},
 };
 use oak_session::attestation::{
     AttestationType, ClientAttestationProvider, ServerAttestationProvider,
 };
 use oak_session::config::AttestationProviderConfig;
+use sha2::{Digest, Sha256};
+use tcp_proto::runtime::endpoint::AttestationPolicy;
 
 pub trait ClientAttestation = Attestation<AttestResponse, AttestRequest>;
 pub trait ServerAttestation = Attestation<AttestRequest, AttestResponse>;
@@ -30,45 +42,60 @@ pub trait ServerAttestation = Attestation<AttestRequest, AttestResponse>;
 // Provider for `ClientAttestation` and `ServerAttestation` traits which
 // are used for performing remote bidirectional attestation between 2 raft replicas
 // before an encrypted secure channel is established between them.
+//
+// The evidence presented by each replica binds the public key of the key pair it
+// has generated for the session, such that the session keys agreed on with the
+// public key the peer evidence binds can only be derived by the attested peer.
 pub trait AttestationProvider {
     // Returns ClientAttestation, responsible for initiating attestation between 2
     // raft replicas.
-    fn get_client_attestation(&self) -> Box<dyn ClientAttestation>;
+    fn get_client_attestation(&self, session_public_key: &[u8]) -> Box<dyn ClientAttestation>;
     // Returns ServerAttestation, recipient of the initial attestation message from
     // the client.
-    fn get_server_attestation(&self) -> Box<dyn ServerAttestation>;
+    fn get_server_attestation(&self, session_public_key: &[u8]) -> Box<dyn ServerAttestation>;
+    // Replaces the policy the attestation evidence of the peers is verified
+    // against by the attestations returned from now on.
+    fn update_policy(&self, policy: &AttestationPolicy);
 }
 
 // Responsible for performing remote bidirectional attestation between 2 raft replicas.
 // Receives incoming attestation specific messages and prepares outgoing messages in
 // response. `AttestationResults` can be retrieved once remote attestation has successfully
-// completed after an initial exchange of messages.
+// completed after an initial exchange of messages. The results carry the session
+// public key the peer evidence binds as the encryption public key.
 pub trait Attestation<I, O> {
     fn get_attestation_results(self: Box<Self>) -> Option<AttestationResults>;
     fn put_incoming_message(&mut self, incoming_message: &I) -> anyhow::Result<Option<()>>;
     fn get_outgoing_message(&mut self) -> anyhow::Result<Option<O>>;
 }
 
-// Default implementation of `AttestationProvider`.
+// Default implementation of `AttestationProvider`. The replicas exchange their
+// session public keys without any evidence, hence the peers are not
+// authenticated.
 pub struct DefaultAttestationProvider {}
 
 impl AttestationProvider for DefaultAttestationProvider {
-    fn get_client_attestation(&self) -> Box<dyn ClientAttestation> {
-        Box::new(DefaultClientAttestation::new())
+    fn get_client_attestation(&self, session_public_key: &[u8]) -> Box<dyn ClientAttestation> {
+        Box::new(DefaultClientAttestation::new(session_public_key.to_vec()))
     }
 
-    fn get_server_attestation(&self) -> Box<dyn ServerAttestation> {
-        Box::new(DefaultServerAttestation::new())
+    fn get_server_attestation(&self, session_public_key: &[u8]) -> Box<dyn ServerAttestation> {
+        Box::new(DefaultServerAttestation::new(session_public_key.to_vec()))
     }
+
+    // Evidence is not verified yet, hence there is no policy to enforce.
+    fn update_policy(&self, _policy: &AttestationPolicy) {}
 }
 
 // Default implementation of `ClientAttestation`.
 pub struct DefaultClientAttestation<'a> {
     _inner: ClientAttestationProvider<'a>,
+    session_public_key: Vec<u8>,
+    peer_session_public_key: Option<Vec<u8>>,
 }
 
 impl<'a> DefaultClientAttestation<'a> {
-    pub fn new() -> Self {
+    pub fn new(session_public_key: Vec<u8>) -> Self {
         let config = AttestationProviderConfig {
             attestation_type: AttestationType::Bidirectional,
             self_attesters: vec![],
@@ -76,6 +103,8 @@ impl<'a> DefaultClientAttestation<'a> {
         };
         Self {
             _inner: ClientAttestationProvider::new(config),
+            session_public_key,
+            peer_session_public_key: None,
         }
     }
 }
@@ -83,29 +112,21 @@ impl<'a> DefaultClientAttestation<'a> {
 impl<'a> Attestation<AttestResponse, AttestRequest> for DefaultClientAttestation<'a> {
     // TODO: Delegate to `inner` once the implementation is complete on Oak side.
     fn get_attestation_results(self: Box<Self>) -> Option<AttestationResults> {
-        Some(AttestationResults {
-            status: 0,
-            reason: String::new(),
-            encryption_public_key: vec![],
-            signing_public_key: vec![],
-            extracted_evidence: Some(ExtractedEvidence {
-                encryption_public_key: vec![],
-                signing_public_key: vec![],
-                evidence_values: None,
-            }),
-        })
+        self.peer_session_public_key.map(create_attestation_results)
     }
 
     fn get_outgoing_message(&mut self) -> anyhow::Result<Option<AttestRequest>> {
         Ok(Some(AttestRequest {
-            endorsed_evidence: vec![],
+            endorsed_evidence: vec![create_unattested_evidence(&self.session_public_key)],
         }))
     }
 
     fn put_incoming_message(
         &mut self,
-        _incoming_message: &AttestResponse,
+        incoming_message: &AttestResponse,
     ) -> anyhow::Result<Option<()>> {
+        let root_layer = get_root_layer(&incoming_message.endorsed_evidence)?;
+        self.peer_session_public_key = Some(root_layer.eca_public_key.clone());
         Ok(Some(()))
     }
 }
@@ -113,10 +134,12 @@ impl<'a> Attestation<AttestResponse, AttestRequest> for DefaultClientAttestation
 // Default implementation of `ServerAttestation`.
 pub struct DefaultServerAttestation<'a> {
     _inner: ServerAttestationProvider<'a>,
+    session_public_key: Vec<u8>,
+    peer_session_public_key: Option<Vec<u8>>,
 }
 
 impl<'a> DefaultServerAttestation<'a> {
-    pub fn new() -> Self {
+    pub fn new(session_public_key: Vec<u8>) -> Self {
         let config = AttestationProviderConfig {
             attestation_type: AttestationType::Bidirectional,
             self_attesters: vec![],
@@ -124,6 +147,8 @@ impl<'a> DefaultServerAttestation<'a> {
         };
         Self {
             _inner: ServerAttestationProvider::new(config),
+            session_public_key,
+            peer_session_public_key: None,
         }
     }
 }
@@ -131,29 +156,415 @@ impl<'a> DefaultServerAttestation<'a> {
 impl<'a> Attestation<AttestRequest, AttestResponse> for DefaultServerAttestation<'a> {
     // TODO: Delegate to `inner` once the implementation is complete on Oak side.
     fn get_attestation_results(self: Box<Self>) -> Option<AttestationResults> {
-        Some(AttestationResults {
-            status: 0,
-            reason: String::new(),
-            encryption_public_key: vec![],
-            signing_public_key: vec![],
-            extracted_evidence: Some(ExtractedEvidence {
-                encryption_public_key: vec![],
-                signing_public_key: vec![],
-                evidence_values: None,
-            }),
-        })
+        self.peer_session_public_key.map(create_attestation_results)
     }
 
     fn get_outgoing_message(&mut self) -> anyhow::Result<Option<AttestResponse>> {
         Ok(Some(AttestResponse {
-            endorsed_evidence: vec![],
+            endorsed_evidence: vec![create_unattested_evidence(&self.session_public_key)],
         }))
     }
 
     fn put_incoming_message(
         &mut self,
-        _incoming_message: &AttestRequest,
+        incoming_message: &AttestRequest,
+    ) -> anyhow::Result<Option<()>> {
+        let root_layer = get_root_layer(&incoming_message.endorsed_evidence)?;
+        self.peer_session_public_key = Some(root_layer.eca_public_key.clone());
+        Ok(Some(()))
+    }
+}
+
+// Creates the evidence that carries the session public key without any report.
+fn create_unattested_evidence(session_public_key: &[u8]) -> EndorsedEvidence {
+    EndorsedEvidence {
+        evidence: Some(Evidence {
+            root_layer: Some(RootLayerEvidence {
+                eca_public_key: session_public_key.to_vec(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+// Returns the root layer of the peer evidence.
+fn get_root_layer(endorsed_evidence: &[EndorsedEvidence]) -> anyhow::Result<&RootLayerEvidence> {
+    endorsed_evidence
+        .first()
+        .and_then(|endorsed_evidence| endorsed_evidence.evidence.as_ref())
+        .and_then(|evidence| evidence.root_layer.as_ref())
+        .ok_or_else(|| anyhow!("Peer evidence is missing"))
+}
+
+// Creates the results that carry the session public key the peer evidence binds.
+fn create_attestation_results(peer_session_public_key: Vec<u8>) -> AttestationResults {
+    AttestationResults {
+        status: 0,
+        reason: String::new(),
+        encryption_public_key: peer_session_public_key.clone(),
+        signing_public_key: vec![],
+        extracted_evidence: Some(ExtractedEvidence {
+            encryption_public_key: peer_session_public_key,
+            signing_public_key: vec![],
+            evidence_values: None,
+        }),
+    }
+}
+
+// Checks if the value is in the list of allowed values. Empty list allows no
+// value unless any value is explicitly allowed.
+pub fn allows(allowed_values: &[Vec<u8>], allow_any: bool, value: &[u8]) -> bool {
+    allow_any
+        || allowed_values
+            .iter()
+            .any(|allowed_value| allowed_value.as_slice() == value)
+}
+
+// Requests the reports from the confidential computing hardware the replica
+// runs on, i.e. the root layer of the replica evidence.
+pub trait RootLayerReportGenerator {
+    // Gets the report that binds the given public key.
+    fn get_report(&self, public_key: &[u8]) -> anyhow::Result<Vec<u8>>;
+    // Gets the certificate of the key the reports are signed with, empty if the
+    // reports carry the certification data themselves.
+    fn get_tee_certificate(&self) -> anyhow::Result<Vec<u8>>;
+}
+
+// Verifies the report produced by the confidential computing hardware the peer
+// runs on, i.e. the root layer of the peer evidence, and checks that the report
+// binds the given public key.
+pub trait RootLayerVerifier {
+    // Returns the platform the verified reports are produced by.
+    fn platform(&self) -> TeePlatform;
+    // Verifies the certificate of the key the reports are signed with up to the
+    // root key of the hardware vendor.
+    fn verify_tee_certificate(&self, tee_certificate: &[u8]) -> anyhow::Result<()>;
+    // Verifies that the report is signed with the key of the verified
+    // certificate, matches the reference values and binds the public key.
+    fn verify_report(
+        &self,
+        report: &[u8],
+        tee_certificate: &[u8],
+        public_key: &[u8],
+    ) -> anyhow::Result<()>;
+    // Replaces the policy the reports are verified against.
+    fn update_policy(&self, policy: &AttestationPolicy);
+}
+
+// Certificates of the peers that have been verified recently. Verifying the
+// certificate chain of the key the peer reports are signed with is expensive,
+// while the certificate of a peer that reconnects is unchanged. Hence the
+// identical certificate is accepted without verification until the entry
+// expires as measured by the replica clock. The reports themselves bind the
+// session keys and are verified for every session. Entries must be
+// invalidated whenever the reference values change.
+pub struct VerifiedPeerCache {
+    clock: Rc<dyn Clock>,
+    // Time in milliseconds the verified certificate is trusted for.
+    ttl: u64,
+    // Instants at which the certificates have been verified, keyed by the
+    // digest of the certificate.
+    entries: RefCell<HashMap<Vec<u8>, u64>>,
+}
+
+impl VerifiedPeerCache {
+    pub fn new(clock: Rc<dyn Clock>, ttl: u64) -> Self {
+        Self {
+            clock,
+            ttl,
+            entries: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // Checks if the certificate has been verified and the entry has not
+    // expired yet.
+    pub fn contains(&self, tee_certificate: &[u8]) -> bool {
+        let now = self.clock.instant();
+        self.entries
+            .borrow()
+            .get(Sha256::digest(tee_certificate).as_slice())
+            .is_some_and(|verified_at| now < verified_at.saturating_add(self.ttl))
+    }
+
+    // Records that the certificate has been verified. Expired entries are
+    // dropped.
+    pub fn insert(&self, tee_certificate: &[u8]) {
+        let now = self.clock.instant();
+        let mut entries = self.entries.borrow_mut();
+        entries.retain(|_, verified_at| now < verified_at.saturating_add(self.ttl));
+        entries.insert(Sha256::digest(tee_certificate).to_vec(), now);
+    }
+
+    // Drops all entries so that the certificate of every peer is verified
+    // again.
+    pub fn invalidate(&self) {
+        self.entries.borrow_mut().clear();
+    }
+
+    // Returns the number of entries, including the expired ones that have not
+    // been dropped yet.
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+}
+
+// Implementation of `AttestationProvider` for the replicas that attest to one
+// another with the reports produced by the confidential computing hardware.
+// Each replica presents a fresh report that binds its session public key and
+// verifies the report of its peer.
+pub struct RootLayerAttestationProvider {
+    generator: Rc<dyn RootLayerReportGenerator>,
+    verifier: Rc<dyn RootLayerVerifier>,
+    cache: Option<Rc<VerifiedPeerCache>>,
+}
+
+impl RootLayerAttestationProvider {
+    pub fn new(
+        generator: Rc<dyn RootLayerReportGenerator>,
+        verifier: Rc<dyn RootLayerVerifier>,
+    ) -> Self {
+        Self {
+            generator,
+            verifier,
+            cache: None,
+        }
+    }
+
+    // Skips the verification of the peer certificates found in the cache. The
+    // cache is invalidated when the policy is replaced.
+    pub fn with_cache(mut self, cache: Rc<VerifiedPeerCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    fn create_attestation(&self, session_public_key: &[u8]) -> Box<RootLayerAttestation> {
+        Box::new(RootLayerAttestation {
+            session_public_key: session_public_key.to_vec(),
+            generator: Rc::clone(&self.generator),
+            verifier: Rc::clone(&self.verifier),
+            cache: self.cache.clone(),
+            peer_public_key: None,
+        })
+    }
+}
+
+impl AttestationProvider for RootLayerAttestationProvider {
+    fn get_client_attestation(&self, session_public_key: &[u8]) -> Box<dyn ClientAttestation> {
+        self.create_attestation(session_public_key)
+    }
+
+    fn get_server_attestation(&self, session_public_key: &[u8]) -> Box<dyn ServerAttestation> {
+        self.create_attestation(session_public_key)
+    }
+
+    fn update_policy(&self, policy: &AttestationPolicy) {
+        self.verifier.update_policy(policy);
+        if let Some(cache) = &self.cache {
+            cache.invalidate();
+        }
+    }
+}
+
+// Implementation of both `ClientAttestation` and `ServerAttestation` as the
+// attestation is symmetric.
+struct RootLayerAttestation {
+    session_public_key: Vec<u8>,
+    generator: Rc<dyn RootLayerReportGenerator>,
+    verifier: Rc<dyn RootLayerVerifier>,
+    cache: Option<Rc<VerifiedPeerCache>>,
+    // Session public key of the peer set once its report has been verified.
+    peer_public_key: Option<Vec<u8>>,
+}
+
+impl RootLayerAttestation {
+    fn create_evidence(&self) -> anyhow::Result<EndorsedEvidence> {
+        // The report is carried as the root layer evidence, along with the
+        // session public key it binds and the certificate of the key it is
+        // signed with.
+        let report = self.generator.get_report(&self.session_public_key)?;
+        let tee_certificate = self.generator.get_tee_certificate()?;
+        Ok(EndorsedEvidence {
+            evidence: Some(Evidence {
+                root_layer: Some(RootLayerEvidence {
+                    platform: self.verifier.platform().into(),
+                    remote_attestation_report: report,
+                    eca_public_key: self.session_public_key.clone(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            endorsements: Some(Endorsements {
+                r#type: Some(endorsements::Type::OakRestrictedKernel(
+                    OakRestrictedKernelEndorsements {
+                        root_layer: Some(RootLayerEndorsements {
+                            tee_certificate,
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    },
+                )),
+            }),
+        })
+    }
+
+    fn verify_peer(
+        &mut self,
+        endorsed_evidence: &[EndorsedEvidence],
     ) -> anyhow::Result<Option<()>> {
+        let root_layer = get_root_layer(endorsed_evidence)?;
+        ensure!(
+            root_layer.platform() == self.verifier.platform(),
+            "Peer evidence is produced by unexpected platform"
+        );
+        let tee_certificate = endorsed_evidence
+            .first()
+            .and_then(|endorsed_evidence| endorsed_evidence.endorsements.as_ref())
+            .and_then(|endorsements| match &endorsements.r#type {
+                Some(endorsements::Type::OakRestrictedKernel(endorsements)) => {
+                    endorsements.root_layer.as_ref()
+                }
+                _ => None,
+            })
+            .map(|root_layer| root_layer.tee_certificate.as_slice())
+            .unwrap_or_default();
+
+        match &self.cache {
+            Some(cache) => {
+                if !cache.contains(tee_certificate) {
+                    self.verifier.verify_tee_certificate(tee_certificate)?;
+                    cache.insert(tee_certificate);
+                }
+            }
+            None => self.verifier.verify_tee_certificate(tee_certificate)?,
+        }
+        // The report is always verified as it binds the session public key of
+        // the peer.
+        self.verifier.verify_report(
+            &root_layer.remote_attestation_report,
+            tee_certificate,
+            &root_layer.eca_public_key,
+        )?;
+        self.peer_public_key = Some(root_layer.eca_public_key.clone());
         Ok(Some(()))
     }
+
+    fn take_results(self: Box<Self>) -> Option<AttestationResults> {
+        self.peer_public_key.map(create_attestation_results)
+    }
+}
+
+impl Attestation<AttestResponse, AttestRequest> for RootLayerAttestation {
+    fn get_attestation_results(self: Box<Self>) -> Option<AttestationResults> {
+        self.take_results()
+    }
+
+    fn put_incoming_message(
+        &mut self,
+        incoming_message: &AttestResponse,
+    ) -> anyhow::Result<Option<()>> {
+        self.verify_peer(&incoming_message.endorsed_evidence)
+    }
+
+    fn get_outgoing_message(&mut self) -> anyhow::Result<Option<AttestRequest>> {
+        Ok(Some(AttestRequest {
+            endorsed_evidence: vec![self.create_evidence()?],
+        }))
+    }
+}
+
+impl Attestation<AttestRequest, AttestResponse> for RootLayerAttestation {
+    fn get_attestation_results(self: Box<Self>) -> Option<AttestationResults> {
+        self.take_results()
+    }
+
+    fn put_incoming_message(
+        &mut self,
+        incoming_message: &AttestRequest,
+    ) -> anyhow::Result<Option<()>> {
+        self.verify_peer(&incoming_message.endorsed_evidence)
+    }
+
+    fn get_outgoing_message(&mut self) -> anyhow::Result<Option<AttestResponse>> {
+        Ok(Some(AttestResponse {
+            endorsed_evidence: vec![self.create_evidence()?],
+        }))
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::attestation::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_verified_peer_cache() {
+        let clock = Rc::new(ManualClock::new(100));
+        let cache = VerifiedPeerCache::new(clock.clone(), 50);
+        assert!(!cache.contains(b"certificate"));
+
+        cache.insert(b"certificate");
+        assert!(cache.contains(b"certificate"));
+
+        // Entry applies to the identical certificate only.
+        assert!(!cache.contains(b"other_certificate"));
+
+        // Entry expires once the time to live has passed.
+        clock.advance(49);
+        assert!(cache.contains(b"certificate"));
+        clock.advance(1);
+        assert!(!cache.contains(b"certificate"));
+
+        // Expired entries are dropped when new entries are inserted.
+        cache.insert(b"other_certificate");
+        assert_eq!(cache.len(), 1);
+        assert!(cache.contains(b"other_certificate"));
+
+        cache.invalidate();
+        assert!(cache.is_empty());
+        assert!(!cache.contains(b"other_certificate"));
+    }
+
+    #[test]
+    fn test_default_attestation() {
+        let provider = DefaultAttestationProvider {};
+        let mut client = provider.get_client_attestation(&[1]);
+        let mut server = provider.get_server_attestation(&[2]);
+
+        let request = client.get_outgoing_message().unwrap().unwrap();
+        assert_eq!(server.put_incoming_message(&request).unwrap(), Some(()));
+        let response = server.get_outgoing_message().unwrap().unwrap();
+        assert_eq!(client.put_incoming_message(&response).unwrap(), Some(()));
+
+        // Session public keys are exchanged.
+        assert_eq!(
+            client
+                .get_attestation_results()
+                .unwrap()
+                .encryption_public_key,
+            vec![2]
+        );
+        assert_eq!(
+            server
+                .get_attestation_results()
+                .unwrap()
+                .encryption_public_key,
+            vec![1]
+        );
+
+        // No results until the peer evidence has been received.
+        let client = provider.get_client_attestation(&[1]);
+        assert!(client.get_attestation_results().is_none());
+        let mut server = provider.get_server_attestation(&[2]);
+        assert!(server
+            .put_incoming_message(&AttestRequest {
+                endorsed_evidence: vec![]
+            })
+            .is_err());
+    }
 }
@@ -0,0 +1,142 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use hashbrown::HashMap;
+
+struct PeerBackoff {
+    // Number of appends the peer has rejected in a row.
+    rejections: u32,
+    // Instant until which appends to the peer are held back.
+    until: u64,
+}
+
+/// Tracks appends rejected by the peers. A peer that rejects appends twice in
+/// a row has diverged from the leader log and is probed with exponentially
+/// increasing backoff until it accepts an append again.
+pub struct ProbeBackoff {
+    base_backoff: u64,
+    max_backoff: u64,
+    peers: HashMap<u64, PeerBackoff>,
+}
+
+impl ProbeBackoff {
+    /// Creates probe backoff with backoff disabled.
+    pub fn new() -> ProbeBackoff {
+        ProbeBackoff {
+            base_backoff: 0,
+            max_backoff: 0,
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Sets backoff after the second rejection in a row along with its upper
+    /// bound. Zero base disables backoff, zero bound means no bound.
+    pub fn configure(&mut self, base_backoff: u64, max_backoff: u64) {
+        self.base_backoff = base_backoff;
+        self.max_backoff = max_backoff;
+    }
+
+    /// Checks if appends to the diverged peers are held back.
+    pub fn enabled(&self) -> bool {
+        self.base_backoff != 0
+    }
+
+    /// Records that the peer has responded to an append.
+    pub fn observe_append_response(&mut self, peer_id: u64, rejected: bool, instant: u64) {
+        if !rejected {
+            self.peers.remove(&peer_id);
+            return;
+        }
+
+        let peer = self.peers.entry(peer_id).or_insert(PeerBackoff {
+            rejections: 0,
+            until: 0,
+        });
+        peer.rejections = peer.rejections.saturating_add(1);
+        if self.base_backoff != 0 && peer.rejections > 1 {
+            let mut backoff = self
+                .base_backoff
+                .saturating_mul(1 << (peer.rejections - 2).min(32));
+            if self.max_backoff != 0 {
+                backoff = backoff.min(self.max_backoff);
+            }
+            peer.until = instant.saturating_add(backoff);
+        }
+    }
+
+    /// Gets number of appends the peer has rejected in a row.
+    pub fn rejections(&self, peer_id: u64) -> u32 {
+        self.peers.get(&peer_id).map_or(0, |peer| peer.rejections)
+    }
+
+    /// Gets time left until appends to the peer are resumed.
+    pub fn remaining(&self, peer_id: u64, instant: u64) -> u64 {
+        self.peers
+            .get(&peer_id)
+            .map_or(0, |peer| peer.until.saturating_sub(instant))
+    }
+
+    /// Checks if appends to the peer must be held back.
+    pub fn backed_off(&self, peer_id: u64, instant: u64) -> bool {
+        self.remaining(peer_id, instant) > 0
+    }
+
+    /// Forgets all observations, must be called when the leadership changes.
+    pub fn reset(&mut self) {
+        self.peers.clear();
+    }
+}
+
+impl Default for ProbeBackoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::backoff::ProbeBackoff;
+
+    #[test]
+    fn test_probe_backoff() {
+        let mut backoff = ProbeBackoff::new();
+        backoff.observe_append_response(2, true, 0);
+        backoff.observe_append_response(2, true, 0);
+        assert_eq!(backoff.rejections(2), 2);
+        assert!(!backoff.backed_off(2, 0));
+
+        backoff.reset();
+        backoff.configure(10, 25);
+
+        // Single rejection is part of the regular probing.
+        backoff.observe_append_response(2, true, 0);
+        assert!(!backoff.backed_off(2, 0));
+
+        // Backoff doubles with every further rejection up to the bound.
+        backoff.observe_append_response(2, true, 0);
+        assert_eq!(backoff.remaining(2, 0), 10);
+        backoff.observe_append_response(2, true, 10);
+        assert_eq!(backoff.remaining(2, 10), 20);
+        backoff.observe_append_response(2, true, 30);
+        assert_eq!(backoff.remaining(2, 30), 25);
+        assert!(backoff.backed_off(2, 54));
+        assert!(!backoff.backed_off(2, 55));
+        assert!(!backoff.backed_off(3, 30));
+
+        // Accepted append ends the backoff.
+        backoff.observe_append_response(2, false, 40);
+        assert_eq!(backoff.rejections(2), 0);
+        assert!(!backoff.backed_off(2, 40));
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::vec::Vec;
+use core::mem;
+use prost::{bytes::Bytes, Message};
+use tcp_proto::runtime::endpoint::Entry;
+
+/// Coalesces proposals into a single entry of the replicated log. The batch
+/// is ready once it reaches the maximum number of proposals or size, or once
+/// its first proposal has waited for the maximum delay.
+pub struct ProposalBatcher {
+    max_batch_proposals: usize,
+    max_batch_size: u64,
+    max_batch_delay: u64,
+    entries: Vec<Entry>,
+    size: u64,
+    // Instant at which the first proposal in the batch has been added.
+    first_instant: u64,
+}
+
+impl ProposalBatcher {
+    /// Creates batcher with batching disabled.
+    pub fn new() -> ProposalBatcher {
+        ProposalBatcher {
+            max_batch_proposals: 0,
+            max_batch_size: 0,
+            max_batch_delay: 0,
+            entries: Vec::new(),
+            size: 0,
+            first_instant: 0,
+        }
+    }
+
+    /// Sets the limits of the batch. Maximum number of proposals below two
+    /// disables batching, zero size means no size limit.
+    pub fn configure(
+        &mut self,
+        max_batch_proposals: usize,
+        max_batch_size: u64,
+        max_batch_delay: u64,
+    ) {
+        self.max_batch_proposals = max_batch_proposals;
+        self.max_batch_size = max_batch_size;
+        self.max_batch_delay = max_batch_delay;
+    }
+
+    /// Checks if the proposals must be batched.
+    pub fn enabled(&self) -> bool {
+        self.max_batch_proposals > 1
+    }
+
+    /// Adds proposal to the batch.
+    pub fn push(&mut self, entry: Entry, instant: u64) {
+        if self.entries.is_empty() {
+            self.first_instant = instant;
+        }
+        self.size += entry.encoded_len() as u64;
+        self.entries.push(entry);
+    }
+
+    /// Gets number of proposals in the batch.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Gets correlation ids of the proposals in the batch.
+    pub fn correlation_ids(&self) -> Vec<u64> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.entry_id.as_ref())
+            .map(|entry_id| entry_id.entry_id)
+            .collect()
+    }
+
+    /// Checks if the batch has no proposals.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Takes the batch encoded as a single entry if any of the limits has been
+    /// reached.
+    pub fn take_ready(&mut self, instant: u64) -> Option<Bytes> {
+        if self.entries.is_empty() {
+            return None;
+        }
+
+        let ready = self.entries.len() >= self.max_batch_proposals
+            || (self.max_batch_size > 0 && self.size >= self.max_batch_size)
+            || instant >= self.first_instant.saturating_add(self.max_batch_delay);
+        if !ready {
+            return None;
+        }
+
+        self.size = 0;
+        let batch = Entry {
+            entry_id: None,
+            entry_contents: Bytes::new(),
+            batched_entries: mem::take(&mut self.entries),
+            fired_timers: Vec::new(),
+            random_seed: Bytes::new(),
+            skipped_index: 0,
+            schema_version_report: None,
+            activated_schema_version: 0,
+            actor_config_update: None,
+            skipped_positions: Vec::new(),
+            proposer_applied_index: 0,
+        };
+        Some(batch.encode_to_vec().into())
+    }
+}
+
+impl Default for ProposalBatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::batcher::*;
+    use crate::util::raft::{create_entry, create_entry_id};
+    use alloc::vec;
+
+    fn create_test_entry(entry_id: u64, size: usize) -> Entry {
+        create_entry(create_entry_id(1, entry_id), Bytes::from(vec![0; size]))
+    }
+
+    #[test]
+    fn test_proposal_batcher() {
+        let mut batcher = ProposalBatcher::new();
+        assert!(!batcher.enabled());
+
+        batcher.configure(3, 100, 10);
+        assert!(batcher.enabled());
+        assert_eq!(batcher.take_ready(0), None);
+
+        // Batch is ready once the maximum number of proposals is reached.
+        batcher.push(create_test_entry(1, 1), 0);
+        batcher.push(create_test_entry(2, 1), 1);
+        assert_eq!(batcher.take_ready(1), None);
+        batcher.push(create_test_entry(3, 1), 2);
+        assert_eq!(batcher.correlation_ids(), vec![1, 2, 3]);
+        let batch = Entry::decode(batcher.take_ready(2).unwrap()).unwrap();
+        assert_eq!(
+            batch.batched_entries,
+            vec![
+                create_test_entry(1, 1),
+                create_test_entry(2, 1),
+                create_test_entry(3, 1)
+            ]
+        );
+        assert!(batcher.is_empty());
+
+        // Batch is ready once the maximum size is reached.
+        batcher.push(create_test_entry(4, 120), 3);
+        assert_eq!(
+            Entry::decode(batcher.take_ready(3).unwrap())
+                .unwrap()
+                .batched_entries,
+            vec![create_test_entry(4, 120)]
+        );
+
+        // Batch is ready once the first proposal has waited long enough.
+        batcher.push(create_test_entry(5, 1), 20);
+        batcher.push(create_test_entry(6, 1), 25);
+        assert_eq!(batcher.take_ready(29), None);
+        assert_eq!(
+            Entry::decode(batcher.take_ready(30).unwrap())
+                .unwrap()
+                .batched_entries
+                .len(),
+            2
+        );
+    }
+}
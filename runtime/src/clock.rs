@@ -0,0 +1,496 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use anyhow::{anyhow, ensure, Result};
+use core::cell::{Cell, RefCell};
+use ed25519_dalek::{Signature, VerifyingKey};
+use rand::{rngs::OsRng, RngCore};
+use tcp_proto::runtime::endpoint::SignedTimestamp;
+
+// Prefix of the message the time authority signs, separates the signatures
+// over the timestamps from other signatures made with the same key.
+const TIMESTAMP_CONTEXT: &[u8] = b"TCP signed timestamp";
+
+// Size of the nonce the signed timestamps are bound to.
+const TIME_NONCE_SIZE: usize = 32;
+
+/// Source of time for the trusted application. The clock is shared by all
+/// components that need to measure time, which allows tests and simulations to
+/// control time deterministically by injecting their own clock.
+pub trait Clock {
+    /// Gets a measurement of a monotonically nondecreasing clock. The resolution
+    /// of the instant is measured in milliseconds. Instants are opaque and can only
+    /// be compared to one another.
+    fn instant(&self) -> u64;
+
+    /// Observes the instant provided by the untrusted launcher along with a message
+    /// to the trusted host.
+    fn observe_host_instant(&self, instant: u64);
+
+    /// Gets the wall clock time in milliseconds since the Unix epoch, if a
+    /// trusted wall clock is available. The readings are not validated, see
+    /// [WallClockValidator].
+    fn wall_time(&self) -> Option<u64>;
+
+    /// Observes the wall clock time read by the trusted host along with a
+    /// message from the untrusted launcher.
+    fn observe_host_wall_time(&self, wall_time: Option<u64>);
+
+    /// Observes the wall clock time signed by the time authority along with a
+    /// message from the untrusted launcher. Ignored by the clocks that do not
+    /// trust any time authority, which is the default.
+    fn observe_signed_wall_time(&self, _timestamp: Option<&SignedTimestamp>) {}
+
+    /// Gets the nonce the untrusted launcher must bind the next signed wall
+    /// clock time to. None for the clocks that do not trust any time authority,
+    /// which is the default.
+    fn time_nonce(&self) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+/// Clock that follows the instants provided by the untrusted launcher and the
+/// wall clock time read by the trusted host. Instants that go backwards are
+/// ignored to keep the clock monotonic.
+pub struct HostClock {
+    instant: Cell<u64>,
+    wall_time: Cell<Option<u64>>,
+}
+
+impl HostClock {
+    pub fn new() -> HostClock {
+        HostClock {
+            instant: Cell::new(0),
+            wall_time: Cell::new(None),
+        }
+    }
+}
+
+impl Default for HostClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for HostClock {
+    fn instant(&self) -> u64 {
+        self.instant.get()
+    }
+
+    fn observe_host_instant(&self, instant: u64) {
+        if instant > self.instant.get() {
+            self.instant.set(instant);
+        }
+    }
+
+    fn wall_time(&self) -> Option<u64> {
+        self.wall_time.get()
+    }
+
+    fn observe_host_wall_time(&self, wall_time: Option<u64>) {
+        self.wall_time.set(wall_time);
+    }
+}
+
+/// Clock that is advanced explicitly and ignores the instants and the wall
+/// clock time provided by the host. Useful for tests and simulations.
+pub struct ManualClock {
+    instant: Cell<u64>,
+    wall_time: Cell<Option<u64>>,
+}
+
+impl ManualClock {
+    pub fn new(instant: u64) -> ManualClock {
+        ManualClock {
+            instant: Cell::new(instant),
+            wall_time: Cell::new(None),
+        }
+    }
+
+    /// Sets the wall clock time, none makes the wall clock unavailable.
+    pub fn set_wall_time(&self, wall_time: Option<u64>) {
+        self.wall_time.set(wall_time);
+    }
+
+    /// Sets the current instant. Instants that go backwards are ignored.
+    pub fn set_instant(&self, instant: u64) {
+        if instant > self.instant.get() {
+            self.instant.set(instant);
+        }
+    }
+
+    /// Advances the current instant by the given duration in milliseconds.
+    pub fn advance(&self, duration: u64) {
+        self.instant.set(self.instant.get() + duration);
+    }
+}
+
+impl Clock for ManualClock {
+    fn instant(&self) -> u64 {
+        self.instant.get()
+    }
+
+    fn observe_host_instant(&self, _instant: u64) {}
+
+    fn wall_time(&self) -> Option<u64> {
+        self.wall_time.get()
+    }
+
+    fn observe_host_wall_time(&self, _wall_time: Option<u64>) {}
+}
+
+/// Creates the message the time authority signs for the given time, its
+/// uncertainty and the nonce of the request.
+pub fn create_timestamp_message(wall_time: u64, radius: u64, nonce: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(TIMESTAMP_CONTEXT.len() + 16 + nonce.len());
+    message.extend_from_slice(TIMESTAMP_CONTEXT);
+    message.extend_from_slice(&wall_time.to_le_bytes());
+    message.extend_from_slice(&radius.to_le_bytes());
+    message.extend_from_slice(nonce);
+    message
+}
+
+/// Verifies that the message has been signed with the key of the time
+/// authority.
+pub trait TimestampSignatureVerifier {
+    fn verify_signature(&self, message: &[u8], signature: &[u8]) -> Result<()>;
+}
+
+/// Verifies the Ed25519 signatures of the time authority, e.g. a Roughtime
+/// style server that signs the timestamp messages with its long term key.
+pub struct Ed25519TimestampSignatureVerifier {
+    verifying_key: VerifyingKey,
+}
+
+impl Ed25519TimestampSignatureVerifier {
+    /// Creates verifier that trusts the given 32 byte public key of the time
+    /// authority.
+    pub fn new(public_key: &[u8]) -> Result<Ed25519TimestampSignatureVerifier> {
+        let public_key = public_key
+            .try_into()
+            .map_err(|_| anyhow!("Time authority public key must be 32 bytes"))?;
+        Ok(Ed25519TimestampSignatureVerifier {
+            verifying_key: VerifyingKey::from_bytes(public_key)
+                .map_err(|e| anyhow!("Invalid time authority public key: {}", e))?,
+        })
+    }
+}
+
+impl TimestampSignatureVerifier for Ed25519TimestampSignatureVerifier {
+    fn verify_signature(&self, message: &[u8], signature: &[u8]) -> Result<()> {
+        let signature = Signature::from_slice(signature)
+            .map_err(|e| anyhow!("Invalid timestamp signature: {}", e))?;
+        self.verifying_key
+            .verify_strict(message, &signature)
+            .map_err(|e| anyhow!("Timestamp signature is invalid: {}", e))
+    }
+}
+
+/// Time authority the trusted application takes the wall clock time from
+/// instead of the trusted host.
+pub struct TimeAuthority {
+    signature_verifier: Box<dyn TimestampSignatureVerifier>,
+    // Maximum uncertainty in milliseconds of the accepted timestamps.
+    max_radius: u64,
+}
+
+impl TimeAuthority {
+    pub fn new(
+        signature_verifier: Box<dyn TimestampSignatureVerifier>,
+        max_radius: u64,
+    ) -> TimeAuthority {
+        TimeAuthority {
+            signature_verifier,
+            max_radius,
+        }
+    }
+
+    /// Verifies the timestamp the time authority has signed in response to the
+    /// request with the given nonce, returns the attested wall clock time.
+    pub fn verify(&self, timestamp: &SignedTimestamp, nonce: &[u8]) -> Result<u64> {
+        ensure!(
+            timestamp.radius <= self.max_radius,
+            "Timestamp radius {} exceeds maximum {}",
+            timestamp.radius,
+            self.max_radius
+        );
+        ensure!(timestamp.nonce == nonce, "Timestamp nonce mismatch");
+        self.signature_verifier.verify_signature(
+            &create_timestamp_message(timestamp.wall_time, timestamp.radius, &timestamp.nonce),
+            &timestamp.signature,
+        )?;
+        Ok(timestamp.wall_time)
+    }
+}
+
+fn generate_time_nonce() -> Vec<u8> {
+    let mut nonce = vec![0; TIME_NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+    nonce
+}
+
+/// Clock that follows the instants provided by the untrusted launcher and the
+/// wall clock time signed by the time authority. The wall clock time read by
+/// the trusted host is ignored, as are the timestamps without a valid signature.
+///
+/// Timestamps are bound to the nonce the clock hands out to the untrusted
+/// launcher, see [Clock::time_nonce], hence the time authority must have signed
+/// the timestamp after the nonce has been generated. Once a timestamp is
+/// accepted the nonce is replaced, so that the timestamp can't be replayed. The
+/// last accepted time is kept until the untrusted launcher passes the timestamp
+/// bound to the new nonce, i.e. the clock reads no later than the true time and
+/// withholding the timestamps can only delay the expirations. With the skew
+/// check enabled the timestamps must be refreshed within the maximum skew, see
+/// [WallClockValidator].
+pub struct AuthorityClock {
+    authority: TimeAuthority,
+    instant: Cell<u64>,
+    wall_time: Cell<Option<u64>>,
+    nonce: RefCell<Vec<u8>>,
+}
+
+impl AuthorityClock {
+    pub fn new(authority: TimeAuthority) -> AuthorityClock {
+        AuthorityClock {
+            authority,
+            instant: Cell::new(0),
+            wall_time: Cell::new(None),
+            nonce: RefCell::new(generate_time_nonce()),
+        }
+    }
+}
+
+impl Clock for AuthorityClock {
+    fn instant(&self) -> u64 {
+        self.instant.get()
+    }
+
+    fn observe_host_instant(&self, instant: u64) {
+        if instant > self.instant.get() {
+            self.instant.set(instant);
+        }
+    }
+
+    fn wall_time(&self) -> Option<u64> {
+        self.wall_time.get()
+    }
+
+    fn observe_host_wall_time(&self, _wall_time: Option<u64>) {}
+
+    fn observe_signed_wall_time(&self, timestamp: Option<&SignedTimestamp>) {
+        let Some(timestamp) = timestamp else {
+            return;
+        };
+        let Ok(wall_time) = self.authority.verify(timestamp, &self.nonce.borrow()) else {
+            return;
+        };
+        // The time of the timestamps bound to the later nonces doesn't go
+        // backwards, unless the time authority is at fault.
+        if self.wall_time.get() <= Some(wall_time) {
+            self.wall_time.set(Some(wall_time));
+        }
+        self.nonce.replace(generate_time_nonce());
+    }
+
+    fn time_nonce(&self) -> Option<Vec<u8>> {
+        Some(self.nonce.borrow().clone())
+    }
+}
+
+/// Validates the wall clock readings before they are passed to the actor. The
+/// readings must not go backwards and must progress along with the monotonic
+/// clock, within the maximum skew since the last accepted reading. Once a
+/// reading is rejected the following ones are checked against the last accepted
+/// reading, hence a wall clock that jumps stays rejected until the replica is
+/// restarted.
+pub struct WallClockValidator {
+    max_skew: u64,
+    // Instant and wall clock time of the last accepted reading.
+    accepted: Option<(u64, u64)>,
+}
+
+impl WallClockValidator {
+    /// Creates validator with the skew check disabled.
+    pub fn new() -> WallClockValidator {
+        WallClockValidator {
+            max_skew: 0,
+            accepted: None,
+        }
+    }
+
+    /// Sets the maximum skew in milliseconds, zero disables the skew check.
+    pub fn configure(&mut self, max_skew: u64) {
+        self.max_skew = max_skew;
+    }
+
+    /// Validates the wall clock reading taken at the given instant, returns the
+    /// reading if it has been accepted.
+    pub fn validate(&mut self, instant: u64, wall_time: Option<u64>) -> Option<u64> {
+        let wall_time = wall_time?;
+        if let Some((accepted_instant, accepted_wall_time)) = self.accepted {
+            if wall_time < accepted_wall_time {
+                return None;
+            }
+
+            let skew =
+                (wall_time - accepted_wall_time).abs_diff(instant.saturating_sub(accepted_instant));
+            if self.max_skew > 0 && skew > self.max_skew {
+                return None;
+            }
+        }
+
+        self.accepted = Some((instant, wall_time));
+        Some(wall_time)
+    }
+}
+
+impl Default for WallClockValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::clock::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    // Accepts the signatures that equal the signed message.
+    struct FakeSignatureVerifier {}
+
+    impl TimestampSignatureVerifier for FakeSignatureVerifier {
+        fn verify_signature(&self, message: &[u8], signature: &[u8]) -> Result<()> {
+            ensure!(message == signature, "Invalid signature");
+            Ok(())
+        }
+    }
+
+    fn create_signed_timestamp(wall_time: u64, radius: u64, nonce: Vec<u8>) -> SignedTimestamp {
+        SignedTimestamp {
+            wall_time,
+            radius,
+            signature: create_timestamp_message(wall_time, radius, &nonce),
+            nonce,
+        }
+    }
+
+    #[test]
+    fn test_host_clock_is_monotonic() {
+        let clock = HostClock::new();
+
+        clock.observe_host_instant(10);
+        assert_eq!(clock.instant(), 10);
+
+        clock.observe_host_instant(5);
+        assert_eq!(clock.instant(), 10);
+    }
+
+    #[test]
+    fn test_manual_clock_ignores_host() {
+        let clock = ManualClock::new(10);
+
+        clock.observe_host_instant(100);
+        assert_eq!(clock.instant(), 10);
+
+        clock.advance(5);
+        assert_eq!(clock.instant(), 15);
+
+        clock.observe_host_wall_time(Some(100));
+        assert_eq!(clock.wall_time(), None);
+        clock.set_wall_time(Some(1000));
+        assert_eq!(clock.wall_time(), Some(1000));
+    }
+
+    #[test]
+    fn test_authority_clock() {
+        let clock = AuthorityClock::new(TimeAuthority::new(Box::new(FakeSignatureVerifier {}), 10));
+
+        // Wall clock time read by the trusted host is ignored.
+        clock.observe_host_wall_time(Some(100));
+        assert_eq!(clock.wall_time(), None);
+
+        let nonce = clock.time_nonce().unwrap();
+        clock.observe_signed_wall_time(Some(&create_signed_timestamp(1000, 10, nonce.clone())));
+        assert_eq!(clock.wall_time(), Some(1000));
+
+        // Accepted timestamp can't be replayed, the last accepted time is kept.
+        assert_ne!(clock.time_nonce().unwrap(), nonce);
+        clock.observe_signed_wall_time(Some(&create_signed_timestamp(2000, 10, nonce)));
+        assert_eq!(clock.wall_time(), Some(1000));
+        clock.observe_signed_wall_time(None);
+        assert_eq!(clock.wall_time(), Some(1000));
+
+        // Timestamps with invalid signature or too uncertain are rejected.
+        let nonce = clock.time_nonce().unwrap();
+        clock.observe_signed_wall_time(Some(&SignedTimestamp {
+            wall_time: 2000,
+            radius: 10,
+            signature: vec![1, 2, 3],
+            nonce: nonce.clone(),
+        }));
+        assert_eq!(clock.wall_time(), Some(1000));
+        clock.observe_signed_wall_time(Some(&create_signed_timestamp(2000, 11, nonce.clone())));
+        assert_eq!(clock.wall_time(), Some(1000));
+
+        clock.observe_signed_wall_time(Some(&create_signed_timestamp(2000, 0, nonce)));
+        assert_eq!(clock.wall_time(), Some(2000));
+    }
+
+    #[test]
+    fn test_ed25519_timestamp_signature_verifier() {
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let verifier =
+            Ed25519TimestampSignatureVerifier::new(signing_key.verifying_key().as_bytes()).unwrap();
+        let message = create_timestamp_message(1000, 10, &[1; TIME_NONCE_SIZE]);
+        let signature = signing_key.sign(&message).to_bytes();
+
+        assert!(verifier.verify_signature(&message, &signature).is_ok());
+        assert!(verifier
+            .verify_signature(
+                &create_timestamp_message(1000, 10, &[2; TIME_NONCE_SIZE]),
+                &signature
+            )
+            .is_err());
+        assert!(verifier
+            .verify_signature(&message, &signature[1..])
+            .is_err());
+        assert!(Ed25519TimestampSignatureVerifier::new(&[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn test_wall_clock_validator() {
+        let mut validator = WallClockValidator::new();
+        validator.configure(10);
+        assert_eq!(validator.validate(0, None), None);
+        assert_eq!(validator.validate(0, Some(1000)), Some(1000));
+
+        // Wall clock progresses along with the monotonic clock.
+        assert_eq!(validator.validate(100, Some(1105)), Some(1105));
+
+        // Wall clock that goes backwards or jumps is rejected.
+        assert_eq!(validator.validate(110, Some(1100)), None);
+        assert_eq!(validator.validate(120, Some(2000)), None);
+
+        // Readings are checked against the last accepted one.
+        assert_eq!(validator.validate(200, Some(1200)), Some(1200));
+
+        // Disabled skew check only requires the wall clock to not go backwards.
+        validator.configure(0);
+        assert_eq!(validator.validate(210, Some(5000)), Some(5000));
+        assert_eq!(validator.validate(220, Some(4000)), None);
+    }
+}
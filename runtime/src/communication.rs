@@ -25,14 +25,29 @@ use alloc::vec::Vec;
 use alloc::{boxed::Box, vec};
 use anyhow::anyhow;
 use hashbrown::HashMap;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 use slog::{debug, o, warn, Logger};
-use tcp_proto::runtime::endpoint::*;
+use tcp_proto::runtime::endpoint::{
+    secure_channel_handshake::{noise_protocol, Encryption, NoiseProtocol},
+    *,
+};
+
+// Context of the secret exported from the session keys that authenticates the
+// rekey messages.
+const REKEY_AUTHENTICATION_CONTEXT: &[u8] = b"TCP rekey authentication";
 
 // Configuration for the Communication Module.
 pub struct CommunicationConfig {
     // Number of tick events that must pass before retrying handshake with a failed
     // replica.
     pub handshake_retry_tick: u64,
+    // Number of messages encrypted or decrypted with the same session keys
+    // after which the channel is rekeyed. Zero means no limit.
+    pub rekey_message_count: u64,
+    // Number of tick events after which the channel is rekeyed. Zero means no
+    // limit.
+    pub rekey_tick: u64,
 }
 
 /// Responsible for managing communication between raft replicas such as
@@ -90,8 +105,32 @@ pub trait CommunicationModule {
     fn process_cluster_change(&mut self, new_replica_ids: &[u64]);
 
     // Processes a new tick event and resets any internal failed state if enough ticks
-    // have passed.
+    // have passed. Initiates rekeying of the channels that have reached the rekey limits.
     fn make_tick(&mut self);
+
+    /// Exports secret bound to the attested session with the peer replica and the
+    /// given context. Both replicas export the same secret for the same context.
+    ///
+    /// Returns None if the handshake with the peer replica has not completed.
+    fn export_secret(&self, peer_replica_id: u64, context: &[u8]) -> Option<Vec<u8>>;
+
+    /// Replaces the policy the attestation evidence of the peer replicas is
+    /// verified against. Applies to the handshakes initiated from now on, the
+    /// sessions already established are left intact.
+    fn update_attestation_policy(&mut self, policy: &AttestationPolicy);
+
+    /// Sets the secret shared by the replicas of the cluster, which is sent to
+    /// the peer replicas that request it over their attested channels.
+    fn set_cluster_secret(&mut self, cluster_secret: Vec<u8>);
+
+    /// Requests the secret shared by the replicas of the cluster from the peer
+    /// replicas the handshake has completed with, repeatedly until one of them
+    /// responds.
+    fn request_cluster_secret(&mut self);
+
+    /// Takes the secret shared by the replicas of the cluster once received in
+    /// response to the request.
+    fn take_cluster_secret(&mut self) -> Option<Vec<u8>>;
 }
 
 // Default implementation of CommunicationModule.
@@ -103,6 +142,13 @@ pub struct DefaultCommunicationModule {
     replica_id: u64,
     handshake_session_provider: Box<dyn HandshakeSessionProvider>,
     config: CommunicationConfig,
+    // Secret shared by the replicas of the cluster if known to this replica.
+    cluster_secret: Option<Vec<u8>>,
+    // Number of ticks passed since the cluster secret has been last requested
+    // if the replica is waiting for it.
+    cluster_secret_request: Option<u64>,
+    // Cluster secret received from a peer replica that has not been taken yet.
+    received_cluster_secret: Option<Vec<u8>>,
 }
 
 impl DefaultCommunicationModule {
@@ -115,7 +161,12 @@ impl DefaultCommunicationModule {
             config: CommunicationConfig {
                 // System defaults.
                 handshake_retry_tick: 1,
+                rekey_message_count: 0,
+                rekey_tick: 0,
             },
+            cluster_secret: None,
+            cluster_secret_request: None,
+            received_cluster_secret: None,
         }
     }
 
@@ -133,6 +184,8 @@ impl CommunicationModule for DefaultCommunicationModule {
         self.logger = logger;
         if let Some(communication_config) = config {
             self.config.handshake_retry_tick = communication_config.handshake_retry_tick;
+            self.config.rekey_message_count = communication_config.rekey_message_count;
+            self.config.rekey_tick = communication_config.rekey_tick;
         }
     }
 
@@ -159,10 +212,10 @@ impl CommunicationModule for DefaultCommunicationModule {
         // in the closure passed to "or_insert_with". "self" is mutably borrowed in
         // "self.replicas.entry(...)" so it cannot be immutably borrowed in the closure again.
         let logger = &self.logger;
-        let replica_state = self
-            .replicas
-            .entry(peer_replica_id)
-            .or_insert_with(|| CommunicationState::new(logger.clone()));
+        let replica_id = self.replica_id;
+        let replica_state = self.replicas.entry(peer_replica_id).or_insert_with(|| {
+            CommunicationState::new(logger.clone(), replica_id, peer_replica_id)
+        });
 
         if !replica_state.is_initialized() {
             replica_state.init(self.handshake_session_provider.get(
@@ -215,10 +268,10 @@ impl CommunicationModule for DefaultCommunicationModule {
         // in the closure passed to "or_insert_with". "self" is mutably borrowed in
         // "self.replicas.entry(...)" so it cannot be immutably borrowed in the closure again.
         let logger = &self.logger;
-        let replica_state = self
-            .replicas
-            .entry(peer_replica_id)
-            .or_insert_with(|| CommunicationState::new(logger.clone()));
+        let replica_id = self.replica_id;
+        let replica_state = self.replicas.entry(peer_replica_id).or_insert_with(|| {
+            CommunicationState::new(logger.clone(), replica_id, peer_replica_id)
+        });
 
         if !replica_state.is_initialized() {
             replica_state.init(self.handshake_session_provider.get(
@@ -232,6 +285,12 @@ impl CommunicationModule for DefaultCommunicationModule {
         // Failure to process message should not lead to program termination, so simply log and
         // return.
         let result = replica_state.process_in_message(message);
+        if let Some(cluster_secret) = replica_state.received_cluster_secret.take()
+            && self.cluster_secret_request.is_some()
+        {
+            self.cluster_secret_request = None;
+            self.received_cluster_secret = Some(cluster_secret);
+        }
         if result.is_err() {
             warn!(
                 self.logger,
@@ -246,7 +305,9 @@ impl CommunicationModule for DefaultCommunicationModule {
     fn take_out_messages(&mut self) -> Vec<OutMessage> {
         let mut messages = Vec::new();
         for (_, replica_state) in self.replicas.iter_mut() {
-            messages.append(&mut replica_state.take_out_messages())
+            messages.append(
+                &mut replica_state.take_out_messages(&self.config, self.cluster_secret.as_deref()),
+            )
         }
         messages
     }
@@ -268,19 +329,60 @@ impl CommunicationModule for DefaultCommunicationModule {
 
     fn make_tick(&mut self) {
         for replica in self.replicas.values_mut() {
-            replica.make_tick();
+            replica.make_tick(&self.config);
             if let HandshakeState::Failed(ticks_since_failed) = replica.handshake_state
                 && ticks_since_failed >= self.config.handshake_retry_tick
             {
                 replica.reset_state_machine();
             }
         }
+
+        if let Some(ticks_since_request) = self.cluster_secret_request {
+            if ticks_since_request + 1 >= self.config.handshake_retry_tick {
+                for replica in self.replicas.values_mut() {
+                    replica.send_cluster_secret_request();
+                }
+                self.cluster_secret_request = Some(0);
+            } else {
+                self.cluster_secret_request = Some(ticks_since_request + 1);
+            }
+        }
+    }
+
+    fn export_secret(&self, peer_replica_id: u64, context: &[u8]) -> Option<Vec<u8>> {
+        let encryptor = self.replicas.get(&peer_replica_id)?.encryptor.as_ref()?;
+        encryptor
+            .export_secret(context)
+            .inspect_err(|err| warn!(self.logger, "Failed to export secret {:?}", err))
+            .ok()
+    }
+
+    fn update_attestation_policy(&mut self, policy: &AttestationPolicy) {
+        self.handshake_session_provider
+            .update_attestation_policy(policy)
+    }
+
+    fn set_cluster_secret(&mut self, cluster_secret: Vec<u8>) {
+        self.cluster_secret = Some(cluster_secret);
+        self.cluster_secret_request = None;
+    }
+
+    fn request_cluster_secret(&mut self) {
+        if self.cluster_secret.is_none() && self.cluster_secret_request.is_none() {
+            self.cluster_secret_request = Some(0);
+        }
+    }
+
+    fn take_cluster_secret(&mut self) -> Option<Vec<u8>> {
+        self.received_cluster_secret.take()
     }
 }
 
 // Manages communication with a given peer replica.
 pub struct CommunicationState {
     logger: Logger,
+    replica_id: u64,
+    peer_replica_id: u64,
     handshake_state: HandshakeState,
     pending_handshake_message: Option<SecureChannelHandshake>,
     handshake_session: Option<Box<dyn HandshakeSession>>,
@@ -288,6 +390,28 @@ pub struct CommunicationState {
     // handshake completes.
     unencrypted_messages: Vec<out_message::Msg>,
     encryptor: Option<Box<dyn Encryptor>>,
+    // Number of the session keys in use, incremented each time the channel is
+    // rekeyed.
+    key_epoch: u64,
+    // Number of messages encrypted or decrypted and number of ticks passed
+    // since the session keys were last replaced.
+    messages_since_rekey: u64,
+    ticks_since_rekey: u64,
+    // Number of ticks passed since the rekey request has been sent if the
+    // response is yet to be received. Outgoing messages are held back until
+    // then since the peer may have already replaced its keys.
+    pending_rekey: Option<u64>,
+    // Secrets authenticating the rekey messages exported from the current
+    // and from the replaced session keys. The exchange switching to an epoch
+    // is authenticated with the keys of the preceding epoch, the recipient
+    // keeps the replaced secret to respond to the repeated requests.
+    rekey_authentication_key: Option<Vec<u8>>,
+    previous_rekey_authentication_key: Option<Vec<u8>>,
+    // Indicates if the peer has requested the cluster secret, which is sent
+    // along with the encrypted messages.
+    cluster_secret_requested: bool,
+    // Cluster secret received from the peer.
+    received_cluster_secret: Option<Vec<u8>>,
 }
 
 #[derive(PartialEq)]
@@ -307,14 +431,24 @@ enum HandshakeState {
 impl CommunicationState {
     // Create the ReplicaCommunicationState. This happens the first time a
     // message is sent to or received from a peer replica.
-    fn new(logger: Logger) -> Self {
+    fn new(logger: Logger, replica_id: u64, peer_replica_id: u64) -> Self {
         Self {
             logger,
+            replica_id,
+            peer_replica_id,
             handshake_state: HandshakeState::Unknown,
             pending_handshake_message: None,
             handshake_session: None,
             unencrypted_messages: Vec::new(),
             encryptor: None,
+            key_epoch: 0,
+            messages_since_rekey: 0,
+            ticks_since_rekey: 0,
+            pending_rekey: None,
+            rekey_authentication_key: None,
+            previous_rekey_authentication_key: None,
+            cluster_secret_requested: false,
+            received_cluster_secret: None,
         }
     }
 
@@ -335,11 +469,265 @@ impl CommunicationState {
         self.handshake_state != HandshakeState::Unknown
     }
 
-    fn make_tick(&mut self) {
+    fn make_tick(&mut self, config: &CommunicationConfig) {
         if let HandshakeState::Failed(mut ticks_since_failed) = self.handshake_state {
             ticks_since_failed += 1;
             self.handshake_state = HandshakeState::Failed(ticks_since_failed);
         }
+
+        if self.handshake_state == HandshakeState::Completed {
+            self.ticks_since_rekey += 1;
+            if let Some(ticks_since_request) = self.pending_rekey {
+                // Request or response may have been lost, hence the request is
+                // sent again. Recipient responds again to the repeated request.
+                if ticks_since_request + 1 >= config.handshake_retry_tick {
+                    self.send_rekey_request();
+                } else {
+                    self.pending_rekey = Some(ticks_since_request + 1);
+                }
+            }
+            self.check_rekey(config);
+        }
+    }
+
+    // Initiates rekeying of the channel if either of the limits has been
+    // reached. Only the replica with the lower replica id initiates rekeying
+    // so that the replicas never rekey concurrently.
+    fn check_rekey(&mut self, config: &CommunicationConfig) {
+        if self.replica_id > self.peer_replica_id
+            || self.handshake_state != HandshakeState::Completed
+            || self.pending_rekey.is_some()
+            || self.pending_handshake_message.is_some()
+        {
+            return;
+        }
+
+        let messages_exceeded = config.rekey_message_count > 0
+            && self.messages_since_rekey >= config.rekey_message_count;
+        let ticks_exceeded = config.rekey_tick > 0 && self.ticks_since_rekey >= config.rekey_tick;
+        if messages_exceeded || ticks_exceeded {
+            debug!(
+                self.logger,
+                "Initiating rekey to epoch {}",
+                self.key_epoch + 1
+            );
+            self.send_rekey_request();
+        }
+    }
+
+    fn send_rekey_request(&mut self) {
+        match self.create_rekey_message(self.key_epoch + 1, true) {
+            Ok(message) => {
+                self.pending_rekey = Some(0);
+                self.pending_handshake_message = Some(message);
+            }
+            Err(err) => self.transition_to_failed(&err),
+        }
+    }
+
+    fn create_rekey_message(
+        &mut self,
+        epoch: u64,
+        is_request: bool,
+    ) -> anyhow::Result<SecureChannelHandshake> {
+        let key = self.rekey_authentication_key(epoch)?;
+        let tag = rekey_mac(
+            &key,
+            self.replica_id,
+            self.peer_replica_id,
+            is_request,
+            epoch,
+        )?
+        .finalize()
+        .into_bytes()
+        .to_vec();
+        let rekey = noise_protocol::Rekey { epoch, tag };
+        Ok(SecureChannelHandshake {
+            recipient_replica_id: self.peer_replica_id,
+            sender_replica_id: self.replica_id,
+            encryption: Some(Encryption::NoiseProtocol(NoiseProtocol {
+                message: Some(if is_request {
+                    noise_protocol::Message::RekeyRequest(rekey)
+                } else {
+                    noise_protocol::Message::RekeyResponse(rekey)
+                }),
+            })),
+        })
+    }
+
+    // Verifies that the rekey message has been sent by the peer holding the
+    // session keys the exchange is authenticated with.
+    fn verify_rekey_message(
+        &mut self,
+        rekey: &noise_protocol::Rekey,
+        is_request: bool,
+    ) -> anyhow::Result<()> {
+        let key = self.rekey_authentication_key(rekey.epoch)?;
+        rekey_mac(
+            &key,
+            self.peer_replica_id,
+            self.replica_id,
+            is_request,
+            rekey.epoch,
+        )?
+        .verify_slice(&rekey.tag)
+        .map_err(|_| anyhow!("Rekey message for epoch {} is not authentic", rekey.epoch))
+    }
+
+    // Returns the secret authenticating the exchange switching to the given
+    // epoch, which is exported from the session keys preceding the epoch.
+    fn rekey_authentication_key(&mut self, epoch: u64) -> anyhow::Result<Vec<u8>> {
+        if epoch == self.key_epoch + 1 {
+            if self.rekey_authentication_key.is_none() {
+                self.rekey_authentication_key = Some(
+                    self.encryptor
+                        .as_ref()
+                        .unwrap()
+                        .export_secret(REKEY_AUTHENTICATION_CONTEXT)?,
+                );
+            }
+            return Ok(self.rekey_authentication_key.clone().unwrap());
+        }
+        match &self.previous_rekey_authentication_key {
+            Some(key) if epoch == self.key_epoch => Ok(key.clone()),
+            _ => Err(anyhow!(
+                "Rekey message for epoch {} does not follow epoch {}",
+                epoch,
+                self.key_epoch
+            )),
+        }
+    }
+
+    // Requests the cluster secret from the peer unless another exchange is in
+    // progress, the request is repeated on the following ticks anyway.
+    fn send_cluster_secret_request(&mut self) {
+        if self.handshake_state != HandshakeState::Completed
+            || self.pending_rekey.is_some()
+            || self.pending_handshake_message.is_some()
+        {
+            return;
+        }
+        self.pending_handshake_message = Some(SecureChannelHandshake {
+            recipient_replica_id: self.peer_replica_id,
+            sender_replica_id: self.replica_id,
+            encryption: Some(Encryption::NoiseProtocol(NoiseProtocol {
+                message: Some(noise_protocol::Message::ClusterSecretRequest(
+                    noise_protocol::ClusterSecretRequest {},
+                )),
+            })),
+        });
+    }
+
+    // Creates the response carrying the cluster secret encrypted with the
+    // session keys, hence only the attested peer learns it.
+    fn create_cluster_secret_message(
+        &self,
+        cluster_secret: &[u8],
+    ) -> anyhow::Result<SecureChannelHandshake> {
+        let encrypted_secret = self.encryptor.as_ref().unwrap().encrypt(cluster_secret)?;
+        Ok(SecureChannelHandshake {
+            recipient_replica_id: self.peer_replica_id,
+            sender_replica_id: self.replica_id,
+            encryption: Some(Encryption::NoiseProtocol(NoiseProtocol {
+                message: Some(noise_protocol::Message::ClusterSecret(
+                    noise_protocol::ClusterSecret { encrypted_secret },
+                )),
+            })),
+        })
+    }
+
+    // Processes the handshake message received once the handshake has
+    // completed, i.e. the rekey and the cluster secret messages. Stale or
+    // unexpected messages are rejected without affecting the channel.
+    fn process_channel_message(&mut self, message: SecureChannelHandshake) -> anyhow::Result<()> {
+        let channel_message = match message.encryption {
+            Some(Encryption::NoiseProtocol(NoiseProtocol {
+                message: Some(channel_message),
+            })) => channel_message,
+            _ => {
+                return Err(anyhow!(
+                    "Handshake message expected but found {:?}",
+                    message
+                ))
+            }
+        };
+        match &channel_message {
+            noise_protocol::Message::RekeyRequest(rekey) => {
+                self.verify_channel_message(rekey, true)?
+            }
+            noise_protocol::Message::RekeyResponse(rekey) => {
+                self.verify_channel_message(rekey, false)?
+            }
+            noise_protocol::Message::ClusterSecretRequest(_) => {
+                self.cluster_secret_requested = true;
+                return Ok(());
+            }
+            noise_protocol::Message::ClusterSecret(cluster_secret) => {
+                self.received_cluster_secret = Some(
+                    self.encryptor
+                        .as_ref()
+                        .unwrap()
+                        .decrypt(&cluster_secret.encrypted_secret)?,
+                );
+                return Ok(());
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Unexpected handshake message {:?}",
+                    channel_message
+                ))
+            }
+        }
+
+        match channel_message {
+            noise_protocol::Message::RekeyRequest(rekey)
+                if self.replica_id > self.peer_replica_id =>
+            {
+                if rekey.epoch == self.key_epoch + 1 {
+                    self.rekey()?;
+                }
+                if rekey.epoch != self.key_epoch {
+                    return Err(anyhow!(
+                        "Rekey request for epoch {} does not follow epoch {}",
+                        rekey.epoch,
+                        self.key_epoch
+                    ));
+                }
+                // Repeated request is responded again since the previous
+                // response may have been lost.
+                self.pending_handshake_message =
+                    Some(self.create_rekey_message(self.key_epoch, false)?);
+                Ok(())
+            }
+            noise_protocol::Message::RekeyResponse(rekey)
+                if self.pending_rekey.is_some() && rekey.epoch == self.key_epoch + 1 =>
+            {
+                self.rekey()?;
+                self.pending_rekey = None;
+                // Repeated request that has not been sent yet is obsolete.
+                self.pending_handshake_message = None;
+                Ok(())
+            }
+            _ => Err(anyhow!("Unexpected rekey message {:?}", channel_message)),
+        }
+    }
+
+    fn rekey(&mut self) -> anyhow::Result<()> {
+        self.previous_rekey_authentication_key =
+            Some(self.rekey_authentication_key(self.key_epoch + 1)?);
+        self.encryptor
+            .as_mut()
+            .unwrap()
+            .rekey()
+            .inspect_err(|err| {
+                self.transition_to_failed(&err);
+            })?;
+        self.key_epoch += 1;
+        self.rekey_authentication_key = None;
+        self.messages_since_rekey = 0;
+        self.ticks_since_rekey = 0;
+        debug!(self.logger, "Rekeyed to epoch {}", self.key_epoch);
+        Ok(())
     }
 
     // Resets the state machine but preserves any messages not sent out yet.
@@ -349,6 +737,13 @@ impl CommunicationState {
         self.pending_handshake_message = None;
         self.handshake_session = None;
         self.encryptor = None;
+        self.key_epoch = 0;
+        self.messages_since_rekey = 0;
+        self.ticks_since_rekey = 0;
+        self.pending_rekey = None;
+        self.rekey_authentication_key = None;
+        self.previous_rekey_authentication_key = None;
+        self.cluster_secret_requested = false;
     }
 
     fn transition_to_failed(&mut self, err: &anyhow::Error) {
@@ -447,7 +842,19 @@ impl CommunicationState {
                     }
                 }
             }
-            HandshakeState::Completed => Ok(self.decrypt_message(message)),
+            HandshakeState::Completed => match message {
+                in_message::Msg::SecureChannelHandshake(handshake_message) => {
+                    self.process_channel_message(handshake_message)?;
+                    Ok(None)
+                }
+                _ => {
+                    let message = self.decrypt_message(message);
+                    if message.is_some() {
+                        self.messages_since_rekey += 1;
+                    }
+                    Ok(message)
+                }
+            },
             HandshakeState::Failed(_) => {
                 warn!(self.logger, "HandshakeState Failed.");
                 Ok(None)
@@ -533,10 +940,17 @@ impl CommunicationState {
                 warn!(self.logger, "Failed to encrypt {:?}", result.err());
             }
         }
+        self.messages_since_rekey += messages.len() as u64;
         messages
     }
 
-    fn take_out_messages(&mut self) -> Vec<OutMessage> {
+    fn take_out_messages(
+        &mut self,
+        config: &CommunicationConfig,
+        cluster_secret: Option<&[u8]>,
+    ) -> Vec<OutMessage> {
+        self.check_rekey(config);
+
         let mut messages = Vec::new();
         if let Some(pending_handshake_message) = self.pending_handshake_message.take() {
             messages = vec![OutMessage {
@@ -544,14 +958,45 @@ impl CommunicationState {
                     pending_handshake_message,
                 )),
             }];
-        } else if self.handshake_state == HandshakeState::Completed {
-            messages = self.take_encrypted_messages();
+        } else if self.handshake_state == HandshakeState::Completed && self.pending_rekey.is_none()
+        {
+            // The cluster secret is encrypted with the same keys as the other
+            // messages, hence it is held back while the channel is rekeyed.
+            if mem::take(&mut self.cluster_secret_requested)
+                && let Some(cluster_secret) = cluster_secret
+            {
+                match self.create_cluster_secret_message(cluster_secret) {
+                    Ok(message) => messages.push(OutMessage {
+                        msg: Some(out_message::Msg::SecureChannelHandshake(message)),
+                    }),
+                    Err(err) => warn!(self.logger, "Failed to send cluster secret {:?}", err),
+                }
+            }
+            messages.append(&mut self.take_encrypted_messages());
         }
 
         messages
     }
 }
 
+// Creates the MAC over the rekey message fields that identify the exchange,
+// so that the message can't be replayed in another direction or exchange.
+fn rekey_mac(
+    key: &[u8],
+    sender_replica_id: u64,
+    recipient_replica_id: u64,
+    is_request: bool,
+    epoch: u64,
+) -> anyhow::Result<Hmac<Sha256>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key)
+        .map_err(|e| anyhow!("Invalid rekey authentication key: {}", e))?;
+    mac.update(&sender_replica_id.to_le_bytes());
+    mac.update(&recipient_replica_id.to_le_bytes());
+    mac.update(&[is_request as u8]);
+    mac.update(&epoch.to_le_bytes());
+    Ok(mac)
+}
+
 #[cfg(all(test, feature = "std"))]
 mod test {
     extern crate mockall;
@@ -559,7 +1004,11 @@ mod test {
     use self::mockall::predicate::{always, eq};
     use crate::logger::log::create_logger;
     use crate::{
-        communication::{CommunicationConfig, CommunicationModule, DefaultCommunicationModule},
+        communication::{
+            rekey_mac, CommunicationConfig, CommunicationModule, DefaultCommunicationModule,
+            REKEY_AUTHENTICATION_CONTEXT,
+        },
+        encryptor::DefaultEncryptor,
         platform::PalError,
     };
     use alloc::vec;
@@ -567,9 +1016,14 @@ mod test {
     use anyhow::anyhow;
     use communication::mem;
     use handshake::Role;
+    use hmac::Mac;
     use mock::{MockEncryptor, MockHandshakeSession, MockHandshakeSessionProvider};
+    use oak_proto_rust::oak::crypto::v1::SessionKeys;
     use prost::bytes::Bytes;
-    use tcp_proto::runtime::endpoint::*;
+    use tcp_proto::runtime::endpoint::{
+        secure_channel_handshake::{noise_protocol, Encryption, NoiseProtocol},
+        *,
+    };
 
     fn create_deliver_system_message(
         sender_replica_id: u64,
@@ -655,6 +1109,39 @@ mod test {
         }
     }
 
+    fn create_rekey_message(
+        sender_replica_id: u64,
+        recipient_replica_id: u64,
+        is_request: bool,
+        epoch: u64,
+        key: &[u8],
+    ) -> SecureChannelHandshake {
+        let tag = rekey_mac(
+            key,
+            sender_replica_id,
+            recipient_replica_id,
+            is_request,
+            epoch,
+        )
+        .unwrap()
+        .finalize()
+        .into_bytes()
+        .to_vec();
+        let rekey = noise_protocol::Rekey { epoch, tag };
+        let message = if is_request {
+            noise_protocol::Message::RekeyRequest(rekey)
+        } else {
+            noise_protocol::Message::RekeyResponse(rekey)
+        };
+        SecureChannelHandshake {
+            recipient_replica_id,
+            sender_replica_id,
+            encryption: Some(Encryption::NoiseProtocol(NoiseProtocol {
+                message: Some(message),
+            })),
+        }
+    }
+
     fn create_unsupported_out_message() -> out_message::Msg {
         out_message::Msg::StartReplica(StartReplicaResponse { replica_id: 0 })
     }
@@ -667,6 +1154,10 @@ mod test {
             app_config: Bytes::new(),
             attestation_config: None,
             is_ephemeral: false,
+            recovery_state: None,
+            is_witness: false,
+            imported_snapshot: None,
+            sealed_secrets: vec![],
         })
     }
 
@@ -756,6 +1247,17 @@ mod test {
             self
         }
 
+        fn expect_get_default_encryptor(
+            mut self,
+            session_keys: SessionKeys,
+        ) -> HandshakeSessionBuilder {
+            self.mock_handshake_session
+                .expect_get_encryptor()
+                .once()
+                .return_once(move || Some(Box::new(DefaultEncryptor::new(session_keys))));
+            self
+        }
+
         fn take(mut self) -> MockHandshakeSession {
             mem::take(&mut self.mock_handshake_session)
         }
@@ -798,6 +1300,27 @@ mod test {
             self
         }
 
+        fn expect_export_secret(
+            mut self,
+            context: Vec<u8>,
+            result: anyhow::Result<Vec<u8>>,
+        ) -> EncryptorBuilder {
+            self.mock_encryptor
+                .expect_export_secret()
+                .with(eq(context))
+                .once()
+                .return_once(move |_| result);
+            self
+        }
+
+        fn expect_rekey(mut self, result: anyhow::Result<()>) -> EncryptorBuilder {
+            self.mock_encryptor
+                .expect_rekey()
+                .once()
+                .return_once(move || result);
+            self
+        }
+
         fn take(mut self) -> MockEncryptor {
             mem::take(&mut self.mock_encryptor)
         }
@@ -1039,6 +1562,49 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_export_secret() {
+        let peer_replica_id = 11111;
+        let self_replica_id = 88888;
+        let handshake_message = create_secure_channel_handshake(peer_replica_id, self_replica_id);
+        let mock_encryptor = EncryptorBuilder::new()
+            .expect_export_secret(b"context".to_vec(), Ok(b"secret".to_vec()))
+            .take();
+        let mock_handshake_session = HandshakeSessionBuilder::new()
+            .expect_process_message(handshake_message.clone(), Ok(()))
+            .expect_take_out_message(Ok(Some(handshake_message.clone())))
+            .expect_is_completed(true)
+            .expect_get_encryptor(mock_encryptor)
+            .take();
+        let mock_handshake_session_provider = HandshakeSessionProviderBuilder::new()
+            .expect_get(
+                self_replica_id,
+                peer_replica_id,
+                Role::Recipient,
+                mock_handshake_session,
+            )
+            .take();
+        let mut communication_module =
+            DefaultCommunicationModule::new(Box::new(mock_handshake_session_provider));
+        communication_module.init(self_replica_id, create_logger(), None);
+
+        // No secret can be exported before the handshake completes.
+        assert_eq!(
+            None,
+            communication_module.export_secret(peer_replica_id, b"context")
+        );
+
+        assert_eq!(
+            Ok(None),
+            communication_module
+                .process_in_message(in_message::Msg::SecureChannelHandshake(handshake_message))
+        );
+        assert_eq!(
+            Some(b"secret".to_vec()),
+            communication_module.export_secret(peer_replica_id, b"context")
+        );
+    }
+
     #[test]
     fn test_mutual_handshake_single_roundtrip_success() {
         let peer_replica_id_a = 11111;
@@ -1574,6 +2140,8 @@ mod test {
         let peer_replica_id_b = 22222;
         let config = Some(CommunicationConfig {
             handshake_retry_tick: 2,
+            rekey_message_count: 0,
+            rekey_tick: 0,
         });
         let handshake_message_a_to_b =
             create_secure_channel_handshake(peer_replica_id_a, peer_replica_id_b);
@@ -1722,4 +2290,552 @@ mod test {
             communication_module.take_out_messages()
         );
     }
+
+    #[test]
+    fn test_rekey() {
+        let peer_replica_id_a = 11111;
+        let peer_replica_id_b = 22222;
+        let create_config = || {
+            Some(CommunicationConfig {
+                handshake_retry_tick: 2,
+                rekey_message_count: 1,
+                rekey_tick: 0,
+            })
+        };
+        let handshake_message_a_to_b =
+            create_secure_channel_handshake(peer_replica_id_a, peer_replica_id_b);
+        let handshake_message_b_to_a =
+            create_secure_channel_handshake(peer_replica_id_b, peer_replica_id_a);
+        let rekey_key = b"rekey_key".to_vec();
+        let rekey_request =
+            create_rekey_message(peer_replica_id_a, peer_replica_id_b, true, 1, &rekey_key);
+        let rekey_response =
+            create_rekey_message(peer_replica_id_b, peer_replica_id_a, false, 1, &rekey_key);
+        let forged_rekey_request =
+            create_rekey_message(peer_replica_id_a, peer_replica_id_b, true, 1, b"other_key");
+        let deliver_system_message_1 = create_deliver_system_message_with_contents(
+            peer_replica_id_a,
+            peer_replica_id_b,
+            "foo".into(),
+        );
+        let deliver_system_message_2 = create_deliver_system_message_with_contents(
+            peer_replica_id_a,
+            peer_replica_id_b,
+            "bar".into(),
+        );
+        let mock_encryptor_a = EncryptorBuilder::new()
+            .expect_encrypt(
+                deliver_system_message_1.message_contents.clone(),
+                Ok(deliver_system_message_1.message_contents.to_vec()),
+            )
+            .expect_export_secret(REKEY_AUTHENTICATION_CONTEXT.to_vec(), Ok(rekey_key.clone()))
+            .expect_rekey(Ok(()))
+            .expect_encrypt(
+                deliver_system_message_2.message_contents.clone(),
+                Ok(deliver_system_message_2.message_contents.to_vec()),
+            )
+            .take();
+        let mock_encryptor_b = EncryptorBuilder::new()
+            .expect_decrypt(
+                deliver_system_message_1.message_contents.clone(),
+                Ok(deliver_system_message_1.message_contents.to_vec()),
+            )
+            .expect_export_secret(REKEY_AUTHENTICATION_CONTEXT.to_vec(), Ok(rekey_key.clone()))
+            .expect_rekey(Ok(()))
+            .take();
+        let mock_handshake_session_a = HandshakeSessionBuilder::new()
+            .expect_take_out_message(Ok(Some(handshake_message_a_to_b.clone())))
+            .expect_process_message(handshake_message_b_to_a.clone(), Ok(()))
+            .expect_take_out_message(Ok(None))
+            .expect_is_completed(true)
+            .expect_get_encryptor(mock_encryptor_a)
+            .take();
+        let mock_handshake_session_b = HandshakeSessionBuilder::new()
+            .expect_process_message(handshake_message_a_to_b.clone(), Ok(()))
+            .expect_take_out_message(Ok(Some(handshake_message_b_to_a.clone())))
+            .expect_is_completed(true)
+            .expect_get_encryptor(mock_encryptor_b)
+            .take();
+        let mock_handshake_session_provider_a = HandshakeSessionProviderBuilder::new()
+            .expect_get(
+                peer_replica_id_a,
+                peer_replica_id_b,
+                Role::Initiator,
+                mock_handshake_session_a,
+            )
+            .take();
+        let mock_handshake_session_provider_b = HandshakeSessionProviderBuilder::new()
+            .expect_get(
+                peer_replica_id_b,
+                peer_replica_id_a,
+                Role::Recipient,
+                mock_handshake_session_b,
+            )
+            .take();
+        let mut communication_module_a =
+            DefaultCommunicationModule::new(Box::new(mock_handshake_session_provider_a));
+        let mut communication_module_b =
+            DefaultCommunicationModule::new(Box::new(mock_handshake_session_provider_b));
+        communication_module_a.init(peer_replica_id_a, create_logger(), create_config());
+        communication_module_b.init(peer_replica_id_b, create_logger(), create_config());
+
+        // Complete the handshake and send the first message.
+        assert_eq!(
+            Ok(()),
+            communication_module_a.process_out_message(out_message::Msg::DeliverSystemMessage(
+                deliver_system_message_1.clone()
+            ))
+        );
+        communication_module_a.take_out_messages();
+        assert_eq!(
+            Ok(None),
+            communication_module_b.process_in_message(in_message::Msg::SecureChannelHandshake(
+                handshake_message_a_to_b
+            ))
+        );
+        communication_module_b.take_out_messages();
+        assert_eq!(
+            Ok(None),
+            communication_module_a.process_in_message(in_message::Msg::SecureChannelHandshake(
+                handshake_message_b_to_a
+            ))
+        );
+        assert_eq!(
+            vec![OutMessage {
+                msg: Some(out_message::Msg::DeliverSystemMessage(
+                    deliver_system_message_1.clone()
+                ))
+            }],
+            communication_module_a.take_out_messages()
+        );
+
+        // Message limit is reached, the replica with the lower id initiates
+        // rekeying and holds back the outgoing messages until it completes.
+        assert_eq!(
+            Ok(()),
+            communication_module_a.process_out_message(out_message::Msg::DeliverSystemMessage(
+                deliver_system_message_2.clone()
+            ))
+        );
+        assert_eq!(
+            vec![OutMessage {
+                msg: Some(out_message::Msg::SecureChannelHandshake(
+                    rekey_request.clone()
+                ))
+            }],
+            communication_module_a.take_out_messages()
+        );
+        assert_eq!(
+            Vec::<OutMessage>::new(),
+            communication_module_a.take_out_messages()
+        );
+
+        // Request is sent again if no response has been received.
+        communication_module_a.make_tick();
+        communication_module_a.make_tick();
+        assert_eq!(
+            vec![OutMessage {
+                msg: Some(out_message::Msg::SecureChannelHandshake(
+                    rekey_request.clone()
+                ))
+            }],
+            communication_module_a.take_out_messages()
+        );
+
+        // Messages sent before the request are decrypted with the old keys.
+        assert_eq!(
+            Ok(Some(in_message::Msg::DeliverSystemMessage(
+                deliver_system_message_1.clone()
+            ))),
+            communication_module_b.process_in_message(in_message::Msg::DeliverSystemMessage(
+                deliver_system_message_1
+            ))
+        );
+
+        // Request not authenticated with the session keys is ignored.
+        assert_eq!(
+            Ok(None),
+            communication_module_b.process_in_message(in_message::Msg::SecureChannelHandshake(
+                forged_rekey_request
+            ))
+        );
+        assert_eq!(
+            Vec::<OutMessage>::new(),
+            communication_module_b.take_out_messages()
+        );
+
+        assert_eq!(
+            Ok(None),
+            communication_module_b.process_in_message(in_message::Msg::SecureChannelHandshake(
+                rekey_request.clone()
+            ))
+        );
+        assert_eq!(
+            vec![OutMessage {
+                msg: Some(out_message::Msg::SecureChannelHandshake(
+                    rekey_response.clone()
+                ))
+            }],
+            communication_module_b.take_out_messages()
+        );
+
+        // Repeated request is responded again without rekeying.
+        assert_eq!(
+            Ok(None),
+            communication_module_b
+                .process_in_message(in_message::Msg::SecureChannelHandshake(rekey_request))
+        );
+        assert_eq!(
+            vec![OutMessage {
+                msg: Some(out_message::Msg::SecureChannelHandshake(
+                    rekey_response.clone()
+                ))
+            }],
+            communication_module_b.take_out_messages()
+        );
+
+        // Held back messages are released once the response is received,
+        // repeated response is ignored.
+        assert_eq!(
+            Ok(None),
+            communication_module_a.process_in_message(in_message::Msg::SecureChannelHandshake(
+                rekey_response.clone()
+            ))
+        );
+        assert_eq!(
+            Ok(None),
+            communication_module_a
+                .process_in_message(in_message::Msg::SecureChannelHandshake(rekey_response))
+        );
+        assert_eq!(
+            vec![OutMessage {
+                msg: Some(out_message::Msg::DeliverSystemMessage(
+                    deliver_system_message_2
+                ))
+            }],
+            communication_module_a.take_out_messages()
+        );
+    }
+
+    #[test]
+    fn test_rekey_with_default_encryptor() {
+        let peer_replica_id_a = 11111;
+        let peer_replica_id_b = 22222;
+        let create_config = || {
+            Some(CommunicationConfig {
+                handshake_retry_tick: 2,
+                rekey_message_count: 1,
+                rekey_tick: 0,
+            })
+        };
+        let key_a = vec![1; 32];
+        let key_b = vec![2; 32];
+        let handshake_message_a_to_b =
+            create_secure_channel_handshake(peer_replica_id_a, peer_replica_id_b);
+        let handshake_message_b_to_a =
+            create_secure_channel_handshake(peer_replica_id_b, peer_replica_id_a);
+        let deliver_system_message_1 = create_deliver_system_message_with_contents(
+            peer_replica_id_a,
+            peer_replica_id_b,
+            "foo".into(),
+        );
+        let deliver_system_message_2 = create_deliver_system_message_with_contents(
+            peer_replica_id_a,
+            peer_replica_id_b,
+            "bar".into(),
+        );
+        let mock_handshake_session_a = HandshakeSessionBuilder::new()
+            .expect_take_out_message(Ok(Some(handshake_message_a_to_b.clone())))
+            .expect_process_message(handshake_message_b_to_a.clone(), Ok(()))
+            .expect_take_out_message(Ok(None))
+            .expect_is_completed(true)
+            .expect_get_default_encryptor(SessionKeys {
+                request_key: key_a.clone(),
+                response_key: key_b.clone(),
+            })
+            .take();
+        let mock_handshake_session_b = HandshakeSessionBuilder::new()
+            .expect_process_message(handshake_message_a_to_b.clone(), Ok(()))
+            .expect_take_out_message(Ok(Some(handshake_message_b_to_a.clone())))
+            .expect_is_completed(true)
+            .expect_get_default_encryptor(SessionKeys {
+                request_key: key_b,
+                response_key: key_a,
+            })
+            .take();
+        let mock_handshake_session_provider_a = HandshakeSessionProviderBuilder::new()
+            .expect_get(
+                peer_replica_id_a,
+                peer_replica_id_b,
+                Role::Initiator,
+                mock_handshake_session_a,
+            )
+            .take();
+        let mock_handshake_session_provider_b = HandshakeSessionProviderBuilder::new()
+            .expect_get(
+                peer_replica_id_b,
+                peer_replica_id_a,
+                Role::Recipient,
+                mock_handshake_session_b,
+            )
+            .take();
+        let mut communication_module_a =
+            DefaultCommunicationModule::new(Box::new(mock_handshake_session_provider_a));
+        let mut communication_module_b =
+            DefaultCommunicationModule::new(Box::new(mock_handshake_session_provider_b));
+        communication_module_a.init(peer_replica_id_a, create_logger(), create_config());
+        communication_module_b.init(peer_replica_id_b, create_logger(), create_config());
+
+        // Complete the handshake, the first message is encrypted with the
+        // session keys.
+        assert_eq!(
+            Ok(()),
+            communication_module_a.process_out_message(out_message::Msg::DeliverSystemMessage(
+                deliver_system_message_1.clone()
+            ))
+        );
+        communication_module_a.take_out_messages();
+        communication_module_b
+            .process_in_message(in_message::Msg::SecureChannelHandshake(
+                handshake_message_a_to_b,
+            ))
+            .unwrap();
+        communication_module_b.take_out_messages();
+        communication_module_a
+            .process_in_message(in_message::Msg::SecureChannelHandshake(
+                handshake_message_b_to_a,
+            ))
+            .unwrap();
+        let encrypted_message_1 = match communication_module_a.take_out_messages().pop() {
+            Some(OutMessage {
+                msg: Some(out_message::Msg::DeliverSystemMessage(message)),
+            }) => message,
+            message => panic!("Unexpected message {:?}", message),
+        };
+        assert_ne!(
+            deliver_system_message_1.message_contents,
+            encrypted_message_1.message_contents
+        );
+        assert_eq!(
+            Ok(Some(in_message::Msg::DeliverSystemMessage(
+                deliver_system_message_1
+            ))),
+            communication_module_b.process_in_message(in_message::Msg::DeliverSystemMessage(
+                encrypted_message_1.clone()
+            ))
+        );
+
+        // Request forged by the host without the session keys is ignored.
+        assert_eq!(
+            Ok(None),
+            communication_module_b.process_in_message(in_message::Msg::SecureChannelHandshake(
+                create_rekey_message(peer_replica_id_a, peer_replica_id_b, true, 1, &[0; 32])
+            ))
+        );
+        assert_eq!(
+            Vec::<OutMessage>::new(),
+            communication_module_b.take_out_messages()
+        );
+
+        // Message limit is reached, the exchange authenticated with the
+        // session keys replaces them.
+        assert_eq!(
+            Ok(()),
+            communication_module_a.process_out_message(out_message::Msg::DeliverSystemMessage(
+                deliver_system_message_2.clone()
+            ))
+        );
+        let rekey_request = communication_module_a.take_out_messages().pop().unwrap();
+        let rekey_request = match rekey_request.msg {
+            Some(out_message::Msg::SecureChannelHandshake(message)) => message,
+            message => panic!("Unexpected message {:?}", message),
+        };
+        communication_module_b
+            .process_in_message(in_message::Msg::SecureChannelHandshake(rekey_request))
+            .unwrap();
+        let rekey_response = match communication_module_b.take_out_messages().pop() {
+            Some(OutMessage {
+                msg: Some(out_message::Msg::SecureChannelHandshake(message)),
+            }) => message,
+            message => panic!("Unexpected message {:?}", message),
+        };
+        communication_module_a
+            .process_in_message(in_message::Msg::SecureChannelHandshake(rekey_response))
+            .unwrap();
+        let encrypted_message_2 = match communication_module_a.take_out_messages().pop() {
+            Some(OutMessage {
+                msg: Some(out_message::Msg::DeliverSystemMessage(message)),
+            }) => message,
+            message => panic!("Unexpected message {:?}", message),
+        };
+        assert_eq!(
+            Ok(Some(in_message::Msg::DeliverSystemMessage(
+                deliver_system_message_2
+            ))),
+            communication_module_b
+                .process_in_message(in_message::Msg::DeliverSystemMessage(encrypted_message_2))
+        );
+
+        // Messages encrypted with the replaced keys are rejected.
+        assert_eq!(
+            Ok(None),
+            communication_module_b
+                .process_in_message(in_message::Msg::DeliverSystemMessage(encrypted_message_1))
+        );
+    }
+
+    #[test]
+    fn test_cluster_secret() {
+        let peer_replica_id_a = 11111;
+        let peer_replica_id_b = 22222;
+        let create_config = || {
+            Some(CommunicationConfig {
+                handshake_retry_tick: 2,
+                rekey_message_count: 0,
+                rekey_tick: 0,
+            })
+        };
+        let key_a = vec![1; 32];
+        let key_b = vec![2; 32];
+        let cluster_secret = vec![3; 32];
+        let handshake_message_a_to_b =
+            create_secure_channel_handshake(peer_replica_id_a, peer_replica_id_b);
+        let handshake_message_b_to_a =
+            create_secure_channel_handshake(peer_replica_id_b, peer_replica_id_a);
+        let mock_handshake_session_a = HandshakeSessionBuilder::new()
+            .expect_take_out_message(Ok(Some(handshake_message_a_to_b.clone())))
+            .expect_process_message(handshake_message_b_to_a.clone(), Ok(()))
+            .expect_take_out_message(Ok(None))
+            .expect_is_completed(true)
+            .expect_get_default_encryptor(SessionKeys {
+                request_key: key_a.clone(),
+                response_key: key_b.clone(),
+            })
+            .take();
+        let mock_handshake_session_b = HandshakeSessionBuilder::new()
+            .expect_process_message(handshake_message_a_to_b.clone(), Ok(()))
+            .expect_take_out_message(Ok(Some(handshake_message_b_to_a.clone())))
+            .expect_is_completed(true)
+            .expect_get_default_encryptor(SessionKeys {
+                request_key: key_b,
+                response_key: key_a,
+            })
+            .take();
+        let mock_handshake_session_provider_a = HandshakeSessionProviderBuilder::new()
+            .expect_get(
+                peer_replica_id_a,
+                peer_replica_id_b,
+                Role::Initiator,
+                mock_handshake_session_a,
+            )
+            .take();
+        let mock_handshake_session_provider_b = HandshakeSessionProviderBuilder::new()
+            .expect_get(
+                peer_replica_id_b,
+                peer_replica_id_a,
+                Role::Recipient,
+                mock_handshake_session_b,
+            )
+            .take();
+        let mut communication_module_a =
+            DefaultCommunicationModule::new(Box::new(mock_handshake_session_provider_a));
+        let mut communication_module_b =
+            DefaultCommunicationModule::new(Box::new(mock_handshake_session_provider_b));
+        communication_module_a.init(peer_replica_id_a, create_logger(), create_config());
+        communication_module_b.init(peer_replica_id_b, create_logger(), create_config());
+        communication_module_a.set_cluster_secret(cluster_secret.clone());
+        communication_module_b.request_cluster_secret();
+
+        // Complete the handshake.
+        assert_eq!(
+            Ok(()),
+            communication_module_a.process_out_message(out_message::Msg::DeliverSystemMessage(
+                create_deliver_system_message(peer_replica_id_a, peer_replica_id_b)
+            ))
+        );
+        communication_module_a.take_out_messages();
+        communication_module_b
+            .process_in_message(in_message::Msg::SecureChannelHandshake(
+                handshake_message_a_to_b,
+            ))
+            .unwrap();
+        communication_module_b.take_out_messages();
+        communication_module_a
+            .process_in_message(in_message::Msg::SecureChannelHandshake(
+                handshake_message_b_to_a,
+            ))
+            .unwrap();
+        communication_module_a.take_out_messages();
+
+        // Request is sent once the retry ticks have passed.
+        communication_module_b.make_tick();
+        assert_eq!(
+            Vec::<OutMessage>::new(),
+            communication_module_b.take_out_messages()
+        );
+        communication_module_b.make_tick();
+        let cluster_secret_request = match communication_module_b.take_out_messages().pop() {
+            Some(OutMessage {
+                msg: Some(out_message::Msg::SecureChannelHandshake(message)),
+            }) => message,
+            message => panic!("Unexpected message {:?}", message),
+        };
+
+        // Secret forged by the host without the session keys is ignored.
+        let forged_cluster_secret = SecureChannelHandshake {
+            recipient_replica_id: peer_replica_id_b,
+            sender_replica_id: peer_replica_id_a,
+            encryption: Some(Encryption::NoiseProtocol(NoiseProtocol {
+                message: Some(noise_protocol::Message::ClusterSecret(
+                    noise_protocol::ClusterSecret {
+                        encrypted_secret: vec![0; 60],
+                    },
+                )),
+            })),
+        };
+        assert_eq!(
+            Ok(None),
+            communication_module_b.process_in_message(in_message::Msg::SecureChannelHandshake(
+                forged_cluster_secret
+            ))
+        );
+        assert_eq!(None, communication_module_b.take_cluster_secret());
+
+        // Secret is sent encrypted with the session keys.
+        communication_module_a
+            .process_in_message(in_message::Msg::SecureChannelHandshake(
+                cluster_secret_request,
+            ))
+            .unwrap();
+        let cluster_secret_response = match communication_module_a.take_out_messages().pop() {
+            Some(OutMessage {
+                msg: Some(out_message::Msg::SecureChannelHandshake(message)),
+            }) => message,
+            message => panic!("Unexpected message {:?}", message),
+        };
+        match &cluster_secret_response.encryption {
+            Some(Encryption::NoiseProtocol(NoiseProtocol {
+                message: Some(noise_protocol::Message::ClusterSecret(message)),
+            })) => assert_ne!(cluster_secret, message.encrypted_secret),
+            message => panic!("Unexpected message {:?}", message),
+        }
+        communication_module_b
+            .process_in_message(in_message::Msg::SecureChannelHandshake(
+                cluster_secret_response,
+            ))
+            .unwrap();
+        assert_eq!(
+            Some(cluster_secret),
+            communication_module_b.take_cluster_secret()
+        );
+        assert_eq!(None, communication_module_b.take_cluster_secret());
+
+        // Secret is no longer requested once received.
+        communication_module_b.make_tick();
+        communication_module_b.make_tick();
+        assert_eq!(
+            Vec::<OutMessage>::new(),
+            communication_module_b.take_out_messages()
+        );
+    }
 }
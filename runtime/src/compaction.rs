@@ -0,0 +1,137 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::boxed::Box;
+use tcp_proto::runtime::endpoint::raft_config::{compaction_config, CompactionConfig};
+
+/// Describes the part of the Raft log that has not been compacted yet.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LogStatus {
+    /// Number of applied entries that can be compacted.
+    pub compactable_entries: u64,
+    /// Size of the applied entries that can be compacted in bytes.
+    pub compactable_size: u64,
+    /// Time elapsed since the log has been compacted last time.
+    pub since_compaction: u64,
+}
+
+/// Decides when the Raft log must be compacted proactively i.e. before any of
+/// the peers requests a snapshot, so that memory used by the log stays bounded.
+pub trait CompactionPolicy {
+    /// Checks if the log must be compacted at the applied index.
+    fn should_compact(&self, status: &LogStatus) -> bool;
+}
+
+/// Compacts once the number of applied entries in the log reaches the limit.
+pub struct EntryCountPolicy {
+    max_entries: u64,
+}
+
+impl EntryCountPolicy {
+    pub fn new(max_entries: u64) -> EntryCountPolicy {
+        EntryCountPolicy { max_entries }
+    }
+}
+
+impl CompactionPolicy for EntryCountPolicy {
+    fn should_compact(&self, status: &LogStatus) -> bool {
+        status.compactable_entries >= self.max_entries
+    }
+}
+
+/// Compacts once the size of the applied log entries reaches the limit.
+pub struct LogSizePolicy {
+    max_log_size: u64,
+}
+
+impl LogSizePolicy {
+    pub fn new(max_log_size: u64) -> LogSizePolicy {
+        LogSizePolicy { max_log_size }
+    }
+}
+
+impl CompactionPolicy for LogSizePolicy {
+    fn should_compact(&self, status: &LogStatus) -> bool {
+        status.compactable_size >= self.max_log_size
+    }
+}
+
+/// Compacts once the interval has passed since the last compaction.
+pub struct TimePolicy {
+    interval: u64,
+}
+
+impl TimePolicy {
+    pub fn new(interval: u64) -> TimePolicy {
+        TimePolicy { interval }
+    }
+}
+
+impl CompactionPolicy for TimePolicy {
+    fn should_compact(&self, status: &LogStatus) -> bool {
+        status.since_compaction >= self.interval
+    }
+}
+
+/// Creates compaction policy selected by the configuration. Returns None if no
+/// policy is selected or the selected policy has zero limit, in which case the
+/// log is only compacted when the storage asks for it.
+pub fn create_compaction_policy(config: &CompactionConfig) -> Option<Box<dyn CompactionPolicy>> {
+    match config.policy? {
+        compaction_config::Policy::MaxEntries(max_entries) if max_entries != 0 => {
+            Some(Box::new(EntryCountPolicy::new(max_entries)))
+        }
+        compaction_config::Policy::MaxLogSize(max_log_size) if max_log_size != 0 => {
+            Some(Box::new(LogSizePolicy::new(max_log_size)))
+        }
+        compaction_config::Policy::Interval(interval) if interval != 0 => {
+            Some(Box::new(TimePolicy::new(interval)))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::compaction::*;
+
+    fn create_policy(policy: compaction_config::Policy) -> Box<dyn CompactionPolicy> {
+        create_compaction_policy(&CompactionConfig {
+            policy: Some(policy),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_compaction_policies() {
+        let status = LogStatus {
+            compactable_entries: 10,
+            compactable_size: 1000,
+            since_compaction: 50,
+        };
+
+        assert!(create_compaction_policy(&CompactionConfig { policy: None }).is_none());
+        assert!(create_compaction_policy(&CompactionConfig {
+            policy: Some(compaction_config::Policy::MaxEntries(0)),
+        })
+        .is_none());
+
+        assert!(create_policy(compaction_config::Policy::MaxEntries(10)).should_compact(&status));
+        assert!(!create_policy(compaction_config::Policy::MaxEntries(11)).should_compact(&status));
+        assert!(create_policy(compaction_config::Policy::MaxLogSize(1000)).should_compact(&status));
+        assert!(!create_policy(compaction_config::Policy::MaxLogSize(1001)).should_compact(&status));
+        assert!(create_policy(compaction_config::Policy::Interval(50)).should_compact(&status));
+        assert!(!create_policy(compaction_config::Policy::Interval(51)).should_compact(&status));
+    }
+}
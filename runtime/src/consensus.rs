@@ -20,14 +20,20 @@ use alloc::vec::Vec;
 use hashbrown::HashMap;
 use prost::bytes::Bytes;
 use raft::{
-    eraftpb::ConfChange as RaftConfigChange, eraftpb::ConfState as RaftConfigState,
-    eraftpb::Entry as RaftEntry, eraftpb::HardState as RaftHardState,
-    eraftpb::Message as RaftMessage, eraftpb::Snapshot as RaftSnapshot, Config as RaftConfig,
-    Error as RaftError, RawNode as RaftNode, RawNode, Ready, SnapshotStatus as RaftSnapshotStatus,
-    SoftState as RaftSoftState, StateRole as RaftStateRole, Storage as RaftStorage,
+    eraftpb::ConfChange as RaftConfigChange, eraftpb::ConfChangeV2 as RaftConfigChangeV2,
+    eraftpb::ConfState as RaftConfigState, eraftpb::Entry as RaftEntry,
+    eraftpb::HardState as RaftHardState, eraftpb::Message as RaftMessage,
+    eraftpb::Snapshot as RaftSnapshot, Config as RaftConfig, Error as RaftError,
+    ProgressState as RaftProgressState, RawNode as RaftNode, RawNode, ReadState as RaftReadState,
+    Ready, SnapshotStatus as RaftSnapshotStatus, SoftState as RaftSoftState,
+    StateRole as RaftStateRole, Storage as RaftStorage,
 };
 use slog::Logger;
+use tcp_proto::runtime::endpoint::{
+    ExportedSnapshot, PersistReplicaState, ReplicaRecoveryState, ReplicationState,
+};
 
+use crate::recovery::{RecoveredState, RecoveryError};
 use crate::util::raft::{
     create_raft_config_state, create_raft_snapshot, create_raft_snapshot_metadata,
 };
@@ -39,13 +45,14 @@ pub trait Store {
     /// Saves the current Raft hard state.
     fn set_hard_state(&mut self, state: RaftHardState);
 
-    /// Appends the new entries to storage.
+    /// Appends the new entries to storage. Entries are moved into the storage so
+    /// that their payloads are not copied.
     ///
     /// # Panics
     ///
     /// Panics if `entries` contains compacted entries, or there's a gap between `entries`
     /// and the last received entry in the storage.
-    fn append_entries(&mut self, entries: &[RaftEntry]) -> Result<(), RaftError>;
+    fn append_entries(&mut self, entries: Vec<RaftEntry>) -> Result<(), RaftError>;
 
     /// Overwrites the contents of this Storage object with those of the given snapshot.
     fn apply_snapshot(&mut self, snapshot: RaftSnapshot) -> Result<(), RaftError>;
@@ -65,9 +72,70 @@ pub trait Store {
         snapshot_data: Bytes,
     ) -> Result<(), RaftError>;
 
+    /// Returns the total size of the log entries that have not been compacted
+    /// up to and including the given index (in bytes).
+    fn log_size(&self, high_index: u64) -> u64;
+
     // Returns the size of the latest snapshot (in bytes).
     // If there are no snapshots, returns 0.
     fn latest_snapshot_size(&self) -> u64;
+
+    /// Returns the latest snapshot along with its data.
+    fn latest_snapshot(&self) -> RaftSnapshot;
+}
+
+/// Raft persistent storage abstraction. Produces the append-only chain of
+/// replica state updates that the untrusted host persists on behalf of the
+/// replica and restores the replica state from the persisted chain. Updates
+/// are authenticated with the key the replica keeps in its sealed storage and
+/// may be encrypted, so that the host can neither read nor undetectably modify
+/// them. The host can still restart the replica from an earlier chain along
+/// with the sealed secrets it has been produced with.
+pub trait PersistentStore {
+    /// Enables or disables production of the updates by the given replica.
+    /// Updates are authenticated with the given key, which must not be empty
+    /// if enabled.
+    fn configure(&mut self, enabled: bool, replica_id: u64, key: Vec<u8>);
+
+    /// Checks if the updates must be produced.
+    fn enabled(&self) -> bool;
+
+    /// Verifies the chain of persisted updates and folds it into the replica
+    /// state. Fails unless enabled, i.e. the replica state is only restored
+    /// from the chain authenticated with the configured key. Subsequent updates
+    /// continue the chain after the recovered state.
+    fn recover(
+        &mut self,
+        recovery_state: ReplicaRecoveryState,
+    ) -> Result<RecoveredState, RecoveryError>;
+
+    /// Creates the next update in the chain. Checkpoint must capture the whole
+    /// state of the replica, other updates only capture the changes.
+    fn record(
+        &mut self,
+        checkpoint: bool,
+        hard_state: Option<&RaftHardState>,
+        snapshot: Option<&RaftSnapshot>,
+        entries: &[RaftEntry],
+    ) -> Result<PersistReplicaState, RecoveryError>;
+
+    /// Captures the actor state of the snapshot for the host to retain out of
+    /// band, sealed the same way as the persisted updates and authenticated
+    /// with the given key that only the replicas of the cluster can derive.
+    fn export(
+        &self,
+        snapshot: &RaftSnapshot,
+        key: &[u8],
+    ) -> Result<ExportedSnapshot, RecoveryError>;
+
+    /// Verifies the snapshot retained out of band with the given key, checks
+    /// its position against the snapshot metadata and returns the actor state
+    /// it captures.
+    fn import(
+        &self,
+        exported_snapshot: ExportedSnapshot,
+        key: &[u8],
+    ) -> Result<Bytes, RecoveryError>;
 }
 
 #[derive(PartialEq, Eq, Clone, Default, Debug)]
@@ -75,6 +143,8 @@ pub struct RaftState {
     pub leader_replica_id: u64,
     pub leader_term: u64,
     pub committed_cluster_config: Vec<u64>,
+    pub committed_cluster_learners: Vec<u64>,
+    pub committed_cluster_outgoing_config: Vec<u64>,
     pub has_pending_change: bool,
 }
 
@@ -84,6 +154,31 @@ impl RaftState {
             ..Default::default()
         }
     }
+
+    /// Returns ids of voter, outgoing voter and learner replicas in the committed
+    /// cluster config.
+    pub fn committed_cluster_members(&self) -> Vec<u64> {
+        let mut members = self.committed_cluster_config.clone();
+        for replica_id in self
+            .committed_cluster_outgoing_config
+            .iter()
+            .chain(self.committed_cluster_learners.iter())
+        {
+            if !members.contains(replica_id) {
+                members.push(*replica_id);
+            }
+        }
+        members
+    }
+}
+
+/// Replication progress of a follower as tracked by the leader.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct FollowerProgress {
+    pub replica_id: u64,
+    pub state: ReplicationState,
+    pub matched_index: u64,
+    pub next_index: u64,
 }
 
 #[derive(Default, Clone)]
@@ -95,6 +190,7 @@ pub struct RaftReady {
     hard_state: Option<RaftHardState>,
     snapshot: RaftSnapshot,
     number: u64,
+    read_states: Vec<RaftReadState>,
 }
 
 impl RaftReady {
@@ -115,9 +211,15 @@ impl RaftReady {
             hard_state,
             snapshot,
             number,
+            read_states: Vec::new(),
         }
     }
 
+    pub fn with_read_states(mut self, read_states: Vec<RaftReadState>) -> RaftReady {
+        self.read_states = read_states;
+        self
+    }
+
     pub fn take_messages(&mut self) -> Vec<RaftMessage> {
         mem::take(&mut self.messages)
     }
@@ -145,6 +247,10 @@ impl RaftReady {
     pub fn number(&self) -> u64 {
         self.number
     }
+
+    pub fn take_read_states(&mut self) -> Vec<RaftReadState> {
+        mem::take(&mut self.read_states)
+    }
 }
 
 #[derive(Default, Clone)]
@@ -200,6 +306,17 @@ pub trait Raft {
         config_change: RaftConfigChange,
     ) -> Result<(), RaftError>;
 
+    /// Proposes config change that may consist of multiple changes applied
+    /// atomically through joint consensus.
+    fn make_config_change_v2_proposal(
+        &mut self,
+        config_change: RaftConfigChangeV2,
+    ) -> Result<(), RaftError>;
+
+    /// Requests the read index for a read-only query identified by the given
+    /// context. The read index is reported through the ready read states.
+    fn make_read_index(&mut self, context: Bytes);
+
     fn make_tick(&mut self);
 
     fn apply_config_change(
@@ -207,6 +324,12 @@ pub trait Raft {
         config_change: &RaftConfigChange,
     ) -> Result<RaftConfigState, RaftError>;
 
+    /// Applies committed config change that enters or leaves joint consensus.
+    fn apply_config_change_v2(
+        &mut self,
+        config_change: &RaftConfigChangeV2,
+    ) -> Result<RaftConfigState, RaftError>;
+
     fn has_ready(&self) -> bool;
 
     fn get_ready(&mut self) -> RaftReady;
@@ -216,6 +339,28 @@ pub trait Raft {
     fn advance_apply(&mut self);
 
     fn report_snapshot(&mut self, replica_id: u64, status: RaftSnapshotStatus);
+
+    /// Sets maximum number of append messages in flight to the given replica.
+    /// Has no effect unless this replica is the leader.
+    fn adjust_max_inflight_msgs(&mut self, replica_id: u64, max_inflight_msgs: usize);
+
+    /// Checks if the given replica has replicated the log up to the committed
+    /// index. Always false unless this replica is the leader.
+    fn replica_caught_up(&self, replica_id: u64) -> bool;
+
+    /// Gets the index of the last entry known to be committed by this replica.
+    /// Leader advances it as the replicas acknowledge the entries, followers
+    /// learn it from the leader.
+    fn committed_index(&self) -> u64;
+
+    /// Gets replication progress of the other replicas in the cluster. Always
+    /// empty unless this replica is the leader.
+    fn followers_progress(&self) -> Vec<FollowerProgress>;
+
+    /// Requests leadership to be handed off to the given voter replica. The
+    /// transfer is aborted by Raft if it doesn't complete within the election
+    /// timeout. Has no effect unless this replica is the leader.
+    fn transfer_leadership(&mut self, target_replica_id: u64);
 }
 
 #[derive(Default)]
@@ -320,6 +465,18 @@ impl<S: Store + RaftStorage> Raft for RaftSimple<S> {
             .propose_conf_change(vec![], config_change)
     }
 
+    fn make_config_change_v2_proposal(
+        &mut self,
+        config_change: RaftConfigChangeV2,
+    ) -> Result<(), RaftError> {
+        self.mut_raft_node()
+            .propose_conf_change(vec![], config_change)
+    }
+
+    fn make_read_index(&mut self, context: Bytes) {
+        self.mut_raft_node().read_index(context.to_vec());
+    }
+
     fn make_tick(&mut self) {
         self.mut_raft_node().tick();
     }
@@ -332,6 +489,13 @@ impl<S: Store + RaftStorage> Raft for RaftSimple<S> {
         config_state
     }
 
+    fn apply_config_change_v2(
+        &mut self,
+        config_change: &RaftConfigChangeV2,
+    ) -> Result<RaftConfigState, RaftError> {
+        self.mut_raft_node().apply_conf_change(config_change)
+    }
+
     fn has_ready(&self) -> bool {
         self.raft_node().has_ready()
     }
@@ -347,7 +511,8 @@ impl<S: Store + RaftStorage> Raft for RaftSimple<S> {
             // Cloning of the snapshot is unfortunate here, will address this later.
             ready.snapshot().clone(),
             ready.number(),
-        );
+        )
+        .with_read_states(ready.take_read_states());
 
         self.raft_ready.insert(ready.number(), ready);
 
@@ -370,4 +535,51 @@ impl<S: Store + RaftStorage> Raft for RaftSimple<S> {
     fn report_snapshot(&mut self, replica_id: u64, status: RaftSnapshotStatus) {
         self.mut_raft_node().report_snapshot(replica_id, status);
     }
+
+    fn adjust_max_inflight_msgs(&mut self, replica_id: u64, max_inflight_msgs: usize) {
+        self.mut_raft_node()
+            .raft
+            .adjust_max_inflight_msgs(replica_id, max_inflight_msgs);
+    }
+
+    fn replica_caught_up(&self, replica_id: u64) -> bool {
+        if !self.leader() {
+            return false;
+        }
+
+        let raft = &self.raft_node().raft;
+        raft.prs()
+            .get(replica_id)
+            .is_some_and(|progress| progress.matched >= raft.raft_log.committed)
+    }
+
+    fn committed_index(&self) -> u64 {
+        self.raft_node().raft.raft_log.committed
+    }
+
+    fn followers_progress(&self) -> Vec<FollowerProgress> {
+        if !self.leader() {
+            return Vec::new();
+        }
+
+        let raft = &self.raft_node().raft;
+        raft.prs()
+            .iter()
+            .filter(|(replica_id, _)| **replica_id != raft.id)
+            .map(|(replica_id, progress)| FollowerProgress {
+                replica_id: *replica_id,
+                state: match progress.state {
+                    RaftProgressState::Probe => ReplicationState::Probe,
+                    RaftProgressState::Replicate => ReplicationState::Replicate,
+                    RaftProgressState::Snapshot => ReplicationState::Snapshot,
+                },
+                matched_index: progress.matched,
+                next_index: progress.next_idx,
+            })
+            .collect()
+    }
+
+    fn transfer_leadership(&mut self, target_replica_id: u64) {
+        self.mut_raft_node().transfer_leader(target_replica_id);
+    }
 }
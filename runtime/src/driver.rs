@@ -13,60 +13,188 @@
 // limitations under the License.
 
 #![allow(clippy::useless_conversion)]
+use crate::backoff::ProbeBackoff;
+use crate::batcher::ProposalBatcher;
+use crate::clock::{Clock, WallClockValidator};
 use crate::communication::{CommunicationConfig, CommunicationModule};
-use crate::consensus::{Raft, RaftState, Store};
+use crate::compaction::{create_compaction_policy, CompactionPolicy, LogStatus};
+use crate::consensus::{PersistentStore, Raft, RaftState, Store};
+use crate::failure_detector::FailureDetector;
+use crate::flow_control::FlowControl;
 use crate::logger::log::create_remote_logger;
-use crate::logger::DrainOutput;
-use crate::model::{Actor, ActorCommand, ActorContext, ActorEvent, ActorEventContext};
-use crate::snapshot::{SnapshotError, SnapshotProcessor, SnapshotProcessorRole};
+use crate::logger::{DefaultLogSink, DrainOutput};
+use crate::mailbox::Mailbox;
+use crate::metrics::{
+    MetricsRegistry, RAFT_APPLIED_INDEX, RAFT_APPLY_LAG, RAFT_COMMIT_INDEX, RAFT_LEADER_ID,
+    RAFT_TERM, SNAPSHOT_CREATED, SNAPSHOT_LOADED, SNAPSHOT_TRANSFER_PROGRESS,
+};
+use crate::model::{
+    Actor, ActorCommand, ActorContext, ActorError, ActorEvent, ActorEventContext, ActorInterceptor,
+    CommandOutcome, InterceptorChain,
+};
+use crate::priority::{MessageClass, MessageQueue};
+use crate::random::{decrypt_seed, encrypt_seed, RandomSource, RANDOM_SEED_KEY_CONTEXT};
+use crate::read_index::ReadIndexQueue;
+use crate::recovery::{generate_journal_key, EXPORTED_SNAPSHOT_KEY_CONTEXT, JOURNAL_KEY_NAME};
+use crate::sealed::HostSealedStorage;
+use crate::secret::{ClusterSecret, CLUSTER_SECRET_NAME};
+use crate::snapshot::{
+    split_attestation_policy, split_chunks, write_attestation_policy_header, SnapshotBuffer,
+    SnapshotError, SnapshotProcessor, SnapshotProcessorRole, SESSION_SECRET_CONTEXT,
+};
+use crate::timer::TimerQueue;
+use crate::upgrade::{split_schema_version, write_schema_version_header, SchemaUpgrade};
 use crate::util::raft::{
-    create_entry, create_raft_config_change, create_raft_message, deserialize_config_change,
-    deserialize_raft_message, get_config_state, get_metadata, serialize_raft_message,
+    create_entry, create_raft_config_change, create_raft_config_change_v2, create_raft_message,
+    deserialize_config_change, deserialize_config_change_v2, deserialize_raft_message,
+    get_config_state, get_metadata, serialize_raft_message,
 };
 use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
 use alloc::rc::Rc;
+use alloc::string::String;
 use alloc::{vec, vec::Vec};
 use core::convert::TryFrom;
 use core::{
     cell::{RefCell, RefMut},
-    cmp, mem,
+    mem,
 };
-use platform::{Application, Host, PalError};
+use hashbrown::HashSet;
+use micro_rpc::StatusCode;
+use platform::{Application, Host, LogSink, PalError};
 use prost::{bytes::Bytes, Message};
 use raft::{
     eraftpb::ConfChangeType as RaftConfigChangeType, eraftpb::ConfState as RaftConfigState,
     eraftpb::Entry as RaftEntry, eraftpb::EntryType as RaftEntryType,
     eraftpb::Message as RaftMessage, eraftpb::MessageType as RaftMessageType, eraftpb::MessageType,
-    eraftpb::Snapshot as RaftSnapshot, Error as RaftError, SnapshotStatus as RaftSnapshotStatus,
+    eraftpb::Snapshot as RaftSnapshot, Error as RaftError, GetEntriesContext,
+    ReadOnlyOption as RaftReadOnlyOption, SnapshotStatus as RaftSnapshotStatus,
     Storage as RaftStorage,
 };
 use slog::{debug, error, info, o, warn, Logger};
-use tcp_proto::runtime::endpoint::*;
+use tcp_proto::runtime::endpoint::{
+    raft_config::{ActorErrorPolicy, FollowerReadConfig, MessagePriorityConfig},
+    *,
+};
+
+// Upper bound of the clock drift between the replicas lease based reads can
+// be enabled with, in parts per million.
+const MAX_LEASE_CLOCK_DRIFT_PPM: u32 = 100_000;
+
+// Checks that the leader lease cannot outlive the election timeout of the
+// followers under the configured clock drift. The leader holds the lease for
+// the election timeout measured by its own clock since it last heard from a
+// majority, while a follower starts an election no earlier than the minimum
+// election timeout measured by its clock.
+fn check_lease_based_reads(raft_config: &RaftConfig) -> Result<(), &'static str> {
+    // Leader lease is only valid if the leader steps down on losing the quorum.
+    if !raft_config.check_quorum {
+        return Err("check quorum is disabled");
+    }
+    let drift = raft_config.max_clock_drift_ppm as u64;
+    if drift == 0 || drift > MAX_LEASE_CLOCK_DRIFT_PPM as u64 {
+        return Err("clock drift is not bounded");
+    }
+    let election_tick = raft_config.election_tick as u64;
+    let min_election_tick = match raft_config.min_election_tick as u64 {
+        0 => election_tick,
+        min_election_tick => min_election_tick,
+    };
+    // The lease measured by the fastest clock must end before the minimum
+    // election timeout measured by the slowest clock.
+    if election_tick * (1_000_000 + drift) > min_election_tick * (1_000_000 - drift) {
+        return Err("minimum election timeout does not cover the clock drift");
+    }
+    Ok(())
+}
 
 struct DriverContextCore {
     id: u64,
-    instant: u64,
     config: Bytes,
     leader: bool,
-    proposals: Vec<Bytes>,
+    read_confirmed: bool,
+    applied_index: u64,
+    committed_index: u64,
+    // Lowest index the actor waits to be committed.
+    commit_watermark: Option<u64>,
+    // Proposals along with the correlation ids of the actor events each of them
+    // carries.
+    proposals: Vec<(Bytes, Vec<u64>)>,
+    // Responses to the pending commands completed by the actor.
+    completed_commands: Vec<ActorCommand>,
+    // Messages sent by the actor on its own initiative.
+    sent_app_messages: Vec<SendAppMessage>,
+    // Timers scheduled by the actor.
+    timers: TimerQueue,
+    // Size of the actor state as last reported by the actor.
+    state_size: u64,
+    // Source of the random bytes drawn by the actor.
+    random: RandomSource,
+    // Rolling upgrade of the actor snapshot schema.
+    schema: SchemaUpgrade,
+    // Wall clock time accepted for the current message.
+    wall_time: Option<u64>,
+    // Metrics of the driver and the actor.
+    metrics: Rc<MetricsRegistry>,
+    // Secret shared by the replicas of the cluster the actor derives its keys
+    // from, if the replica is configured to share it and has received it.
+    cluster_secret: Option<ClusterSecret>,
 }
 
 impl DriverContextCore {
     fn new() -> DriverContextCore {
         DriverContextCore {
             id: 0,
-            instant: 0,
             config: Bytes::new(),
             leader: false,
+            read_confirmed: false,
+            applied_index: 0,
+            committed_index: 0,
+            commit_watermark: None,
             proposals: Vec::new(),
+            completed_commands: Vec::new(),
+            sent_app_messages: Vec::new(),
+            timers: TimerQueue::new(),
+            state_size: 0,
+            random: RandomSource::new(),
+            schema: SchemaUpgrade::new(),
+            wall_time: None,
+            metrics: Rc::new(MetricsRegistry::new()),
+            cluster_secret: None,
         }
     }
 
-    fn set_state(&mut self, instant: u64, leader: bool) {
-        self.instant = instant;
+    fn set_state(&mut self, leader: bool) {
         self.leader = leader;
     }
 
+    fn set_read_confirmed(&mut self, read_confirmed: bool) {
+        self.read_confirmed = read_confirmed;
+    }
+
+    fn set_progress(&mut self, applied_index: u64, committed_index: u64) {
+        self.applied_index = applied_index;
+        self.committed_index = committed_index;
+    }
+
+    fn subscribe_commit_watermark(&mut self, index: u64) {
+        self.commit_watermark = Some(
+            self.commit_watermark
+                .map_or(index, |watermark| watermark.min(index)),
+        );
+    }
+
+    // Takes the subscription if the watermark has been reached.
+    fn take_commit_watermark(&mut self, committed_index: u64) -> bool {
+        match self.commit_watermark {
+            Some(watermark) if watermark <= committed_index => {
+                self.commit_watermark = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
     fn set_immutable_state(&mut self, id: u64, config: Bytes) {
         self.id = id;
         self.config = config;
@@ -76,35 +204,114 @@ impl DriverContextCore {
         self.id
     }
 
-    fn instant(&self) -> u64 {
-        self.instant
+    fn metrics(&self) -> Rc<MetricsRegistry> {
+        Rc::clone(&self.metrics)
     }
 
     fn leader(&self) -> bool {
         self.leader
     }
 
+    fn read_confirmed(&self) -> bool {
+        self.read_confirmed
+    }
+
+    fn applied_index(&self) -> u64 {
+        self.applied_index
+    }
+
+    fn committed_index(&self) -> u64 {
+        self.committed_index
+    }
+
     fn config(&self) -> Bytes {
         self.config.clone()
     }
 
-    fn append_proposal(&mut self, proposal: Bytes) {
-        self.proposals.push(proposal);
+    fn append_proposal(&mut self, proposal: Bytes, correlation_ids: Vec<u64>) {
+        self.proposals.push((proposal, correlation_ids));
     }
 
-    fn take_outputs(&mut self) -> Vec<Bytes> {
+    fn take_outputs(&mut self) -> Vec<(Bytes, Vec<u64>)> {
         mem::take(&mut self.proposals)
     }
+
+    fn complete_command(&mut self, correlation_id: u64, mut response: ActorCommand) {
+        response.correlation_id = correlation_id;
+        self.completed_commands.push(response);
+    }
+
+    fn take_completed_commands(&mut self) -> Vec<ActorCommand> {
+        mem::take(&mut self.completed_commands)
+    }
+
+    fn send_app_message(&mut self, destination: &str, message: Bytes) {
+        self.sent_app_messages.push(SendAppMessage {
+            destination: destination.into(),
+            message_contents: message,
+        });
+    }
+
+    fn take_sent_app_messages(&mut self) -> Vec<SendAppMessage> {
+        mem::take(&mut self.sent_app_messages)
+    }
+
+    fn timers(&mut self) -> &mut TimerQueue {
+        &mut self.timers
+    }
+
+    fn set_state_size(&mut self, state_size: u64) {
+        self.state_size = state_size;
+    }
+
+    fn state_size(&self) -> u64 {
+        self.state_size
+    }
+
+    fn random(&mut self) -> &mut RandomSource {
+        &mut self.random
+    }
+
+    fn schema(&mut self) -> &mut SchemaUpgrade {
+        &mut self.schema
+    }
+
+    fn set_wall_time(&mut self, wall_time: Option<u64>) {
+        self.wall_time = wall_time;
+    }
+
+    fn wall_time(&self) -> Option<u64> {
+        self.wall_time
+    }
+
+    fn set_cluster_secret(&mut self, cluster_secret: ClusterSecret) {
+        self.cluster_secret = Some(cluster_secret);
+    }
+
+    fn has_cluster_secret(&self) -> bool {
+        self.cluster_secret.is_some()
+    }
+
+    fn cluster_key(&self, context: &[u8]) -> Option<Vec<u8>> {
+        self.cluster_secret
+            .as_ref()
+            .map(|cluster_secret| cluster_secret.derive_key(context))
+    }
 }
 
 struct DriverContext {
     core: Rc<RefCell<DriverContextCore>>,
+    clock: Rc<dyn Clock>,
     logger: Logger,
 }
 
 impl DriverContext {
-    fn new(core: Rc<RefCell<DriverContextCore>>, logger: Logger) -> Self {
-        DriverContext { core, logger }
+    fn new(core: Rc<RefCell<DriverContextCore>>, clock: Rc<dyn Clock>, logger: Logger) -> Self {
+        DriverContext {
+            core,
+            clock,
+            logger,
+        }
     }
 }
 
@@ -118,7 +325,11 @@ impl ActorContext for DriverContext {
     }
 
     fn instant(&self) -> u64 {
-        self.core.borrow().instant()
+        self.clock.instant()
+    }
+
+    fn wall_time(&self) -> Option<u64> {
+        self.core.borrow().wall_time()
     }
 
     fn config(&self) -> Bytes {
@@ -128,6 +339,62 @@ impl ActorContext for DriverContext {
     fn leader(&self) -> bool {
         self.core.borrow().leader()
     }
+
+    fn read_confirmed(&self) -> bool {
+        self.core.borrow().read_confirmed()
+    }
+
+    fn applied_index(&self) -> u64 {
+        self.core.borrow().applied_index()
+    }
+
+    fn leader_commit_hint(&self) -> u64 {
+        self.core.borrow().committed_index()
+    }
+
+    fn subscribe_commit_watermark(&self, index: u64) {
+        self.core.borrow_mut().subscribe_commit_watermark(index)
+    }
+
+    fn complete_command(&self, correlation_id: u64, response: ActorCommand) {
+        self.core
+            .borrow_mut()
+            .complete_command(correlation_id, response)
+    }
+
+    fn set_timer(&self, timer_id: u64, deadline: u64) {
+        self.core.borrow_mut().timers().set(timer_id, deadline)
+    }
+
+    fn cancel_timer(&self, timer_id: u64) {
+        self.core.borrow_mut().timers().cancel(timer_id)
+    }
+
+    fn report_state_size(&self, state_size: u64) {
+        self.core.borrow_mut().set_state_size(state_size)
+    }
+
+    fn fill_random(&self, dest: &mut [u8]) {
+        self.core.borrow_mut().random().fill(dest)
+    }
+
+    fn send_app_message(&self, destination: &str, message: Bytes) {
+        self.core
+            .borrow_mut()
+            .send_app_message(destination, message)
+    }
+
+    fn snapshot_schema_version(&self) -> u32 {
+        self.core.borrow_mut().schema().active_version()
+    }
+
+    fn metrics(&self) -> Rc<MetricsRegistry> {
+        self.core.borrow().metrics()
+    }
+
+    fn cluster_key(&self, context: &[u8]) -> Option<Vec<u8>> {
+        self.core.borrow().cluster_key(context)
+    }
 }
 
 #[derive(PartialEq, Eq)]
@@ -139,7 +406,22 @@ enum DriverState {
 #[derive(Default)]
 struct DriverConfig {
     tick_period: u64,
+    election_tick: u64,
     snapshot_count: u64,
+    // Size of the pieces the actor restores its state from if it restores
+    // snapshots piece by piece.
+    snapshot_chunk_size: u64,
+    max_pending_proposals: u64,
+    max_state_size: u64,
+    replicated_randomness: bool,
+    actor_error_policy: ActorErrorPolicy,
+    follower_read_config: FollowerReadConfig,
+    min_voters: u32,
+    encrypt_snapshots: bool,
+    // Period the metrics are reported with, zero disables reporting.
+    metrics_report_period: u64,
+    // Indicates if the replicas share the cluster secret.
+    share_cluster_secret: bool,
 }
 
 struct RaftProgress {
@@ -147,6 +429,12 @@ struct RaftProgress {
     applied_index: u64,
     // The lastest configuration of the cluster that has been committed.
     config_state: RaftConfigState,
+    // Number of proposals made by this replica that have not been applied yet.
+    pending_proposals: u64,
+    // Index of the last committed entry as reported by the leader.
+    committed_index: u64,
+    // Instant at which the replica last received a message from the leader.
+    leader_contact_instant: Option<u64>,
 }
 
 impl RaftProgress {
@@ -154,30 +442,130 @@ impl RaftProgress {
         RaftProgress {
             applied_index: 0,
             config_state: RaftConfigState::default(),
+            pending_proposals: 0,
+            committed_index: 0,
+            leader_contact_instant: None,
         }
     }
 }
 
+// Graceful shutdown requested by the host.
+struct LameDuck {
+    // Instant after which the replica proceeds with the shutdown even if it has
+    // not handed over leadership.
+    deadline: u64,
+    // Indicates if the replica has acknowledged that it is ready to shut down.
+    acknowledged: bool,
+}
+
+// Leadership transfer requested by the host.
+struct LeadershipTransfer {
+    transfer_id: u64,
+    target_replica_id: u64,
+    // Instant after which the transfer is considered failed.
+    deadline: u64,
+}
+
 pub struct Driver<R: Raft, S: Store, P: SnapshotProcessor, A: Actor, C: CommunicationModule> {
     core: Rc<RefCell<DriverContextCore>>,
     driver_config: DriverConfig,
     driver_state: DriverState,
-    messages: Vec<OutMessage>,
+    messages: MessageQueue<OutMessage>,
+    // Messages to peers that are prioritized and budgeted before being passed to
+    // the communication module for encryption.
+    system_messages: MessageQueue<out_message::Msg>,
+    // Application messages from the host waiting to be passed to the actor.
+    mailbox: Mailbox<DeliverAppMessage>,
+    // Replication latency of the peers used to limit appends in flight to them.
+    flow_control: FlowControl,
+    // Appends rejected by the peers used to back off probing of the diverged ones.
+    probe_backoff: ProbeBackoff,
+    // Chain of replica state updates the host persists to restart the replica from.
+    journal: Box<dyn PersistentStore>,
+    // Index up to which committed entries had been applied before the replica
+    // restarted. Outcomes of these entries are not sent out again.
+    replayed_index: u64,
+    // Leadership transfer in progress, completes once this replica steps down.
+    leadership_transfer: Option<LeadershipTransfer>,
+    // Actor proposals waiting to be proposed together as a single entry.
+    batcher: ProposalBatcher,
+    // Proposals along with the correlation ids of the events they carry waiting
+    // for a leader to be known, Raft would drop them otherwise.
+    queued_proposals: VecDeque<(Bytes, Vec<u64>)>,
+    // Correlation ids of the actor events proposed to Raft that have not been
+    // applied yet.
+    proposed_events: BTreeSet<u64>,
+    // Correlation ids of the proposed events failed on losing the leadership.
+    // Their outcomes are not sent out if they still get applied.
+    abandoned_events: BTreeSet<u64>,
+    // Tracks responsiveness of the peers to evict dead replicas.
+    failure_detector: FailureDetector,
+    // Policy to compact the log proactively with, if any.
+    compaction_policy: Option<Box<dyn CompactionPolicy>>,
+    // Instant at which the log has been compacted last time.
+    compaction_instant: u64,
+    // Instant at which the metrics have been reported last time.
+    metrics_instant: u64,
+    // Shutdown preparation requested by the host.
+    lame_duck: Option<LameDuck>,
+    // Read-only queries waiting for Raft to confirm that they can be answered.
+    reads: ReadIndexQueue,
+    // Correlation ids of the commands whose responses are deferred by the actor.
+    pending_commands: HashSet<u64>,
+    // Seed the command being processed draws random bytes from, replicated
+    // along with the first entry the command proposes.
+    command_seed: Option<Bytes>,
+    // Positions within the batch of the entries skipped after the actor has
+    // failed to apply them by the index of the Raft entry, for the entries the
+    // tombstones have not been applied for yet.
+    skipped_entries: BTreeMap<u64, Vec<u32>>,
+    // Indexes of the skipped entries whose tombstones have been applied, the
+    // next leader may replicate the tombstone again.
+    tombstoned_indexes: BTreeSet<u64>,
+    // Highest index the leaders have applied the log up to when proposing the
+    // entries applied so far. Whether the entries up to it have been applied or
+    // skipped has been settled by the log.
+    settled_index: u64,
+    // Highest index of the skipped entries the tombstones have been proposed for
+    // since the replica has become the leader.
+    proposed_tombstone_index: u64,
+    // Number of entries skipped since the replica has started.
+    skipped_entry_count: u64,
+    // Index of the snapshot the actor state has been last loaded from.
+    loaded_snapshot_index: u64,
+    // Attestation policy last applied from the replicated log, captured by the
+    // snapshots so that the replicas catching up from them follow it.
+    attestation_policy: Option<AttestationPolicy>,
+    // Indicates that the entries are replayed to restore the actor state.
+    restoring_actor: bool,
     snapshots: Vec<RaftMessage>,
     id: u64,
-    instant: u64,
+    clock: Rc<dyn Clock>,
+    // Validates the wall clock readings before they are passed to the actor.
+    wall_clock: WallClockValidator,
     tick_instant: u64,
     logger: Logger,
     logger_output: Box<dyn DrainOutput>,
+    // Sink the log messages pass through on their way to the host.
+    log_sink: Box<dyn LogSink>,
     raft: R,
     store: Box<dyn FnMut(Logger, u64) -> S>,
     snapshot: P,
     actor: A,
+    // Interceptors the commands and events pass through to the actor.
+    interceptors: InterceptorChain,
+    // Storage of the actor secrets persisted by the untrusted launcher.
+    sealed_storage: Option<Rc<HostSealedStorage>>,
     raft_state: RaftState,
     prev_raft_state: RaftState,
+    // Leader replica id and term the pending proposals, queries and timers have
+    // been started under.
+    progress_leader: (u64, u64),
     raft_progress: RaftProgress,
     communication: C,
     is_ephemeral: bool,
+    // Witness replica votes and keeps the log but has no actor state.
+    is_witness: bool,
 }
 
 impl<
@@ -194,34 +582,101 @@ impl<
         snapshot: P,
         actor: A,
         communication: C,
+        journal: Box<dyn PersistentStore>,
+        clock: Rc<dyn Clock>,
     ) -> Self {
         let (logger, logger_output) = create_remote_logger();
         Driver {
             core: Rc::new(RefCell::new(DriverContextCore::new())),
             driver_config: DriverConfig {
                 tick_period: 100,
+                election_tick: 10,
                 snapshot_count: 1000,
+                snapshot_chunk_size: 1024 * 1024,
+                max_pending_proposals: 0,
+                max_state_size: 0,
+                replicated_randomness: false,
+                actor_error_policy: ActorErrorPolicy::Unspecified,
+                follower_read_config: FollowerReadConfig::default(),
+                min_voters: 0,
+                encrypt_snapshots: false,
+                metrics_report_period: 0,
+                share_cluster_secret: false,
             },
             driver_state: DriverState::Created,
-            messages: Vec::new(),
+            messages: MessageQueue::new(),
+            system_messages: MessageQueue::new(),
+            mailbox: Mailbox::new(),
+            flow_control: FlowControl::new(),
+            probe_backoff: ProbeBackoff::new(),
+            journal,
+            replayed_index: 0,
+            leadership_transfer: None,
+            batcher: ProposalBatcher::new(),
+            queued_proposals: VecDeque::new(),
+            proposed_events: BTreeSet::new(),
+            abandoned_events: BTreeSet::new(),
+            failure_detector: FailureDetector::new(),
+            compaction_policy: None,
+            compaction_instant: 0,
+            metrics_instant: 0,
+            lame_duck: None,
+            reads: ReadIndexQueue::new(),
+            pending_commands: HashSet::new(),
+            command_seed: None,
+            skipped_entries: BTreeMap::new(),
+            tombstoned_indexes: BTreeSet::new(),
+            settled_index: 0,
+            proposed_tombstone_index: 0,
+            skipped_entry_count: 0,
+            loaded_snapshot_index: 0,
+            attestation_policy: None,
+            restoring_actor: false,
             snapshots: Vec::new(),
             id: 0,
-            instant: 0,
+            clock,
+            wall_clock: WallClockValidator::new(),
             tick_instant: 0,
             logger,
             logger_output,
+            log_sink: Box::new(DefaultLogSink::new()),
             raft,
             store,
             snapshot,
             actor,
+            interceptors: InterceptorChain::new(),
+            sealed_storage: None,
             raft_state: RaftState::new(),
             prev_raft_state: RaftState::new(),
+            progress_leader: (0, 0),
             raft_progress: RaftProgress::new(),
             communication,
             is_ephemeral: false,
+            is_witness: false,
         }
     }
 
+    /// Adds the interceptor the commands and events pass through on their way
+    /// to the actor, after the interceptors added before.
+    pub fn with_interceptor(mut self, interceptor: Box<dyn ActorInterceptor>) -> Self {
+        self.interceptors.add(interceptor);
+        self
+    }
+
+    /// Replaces the default log sink, e.g. with the one that redacts the actor
+    /// secrets. The sink is configured once the replica is started.
+    pub fn with_log_sink(mut self, log_sink: Box<dyn LogSink>) -> Self {
+        self.log_sink = log_sink;
+        self
+    }
+
+    /// Sets the sealed storage shared with the actor. Secrets persisted before
+    /// the restart are loaded into the storage before the actor is initialized.
+    pub fn with_sealed_storage(mut self, sealed_storage: Rc<HostSealedStorage>) -> Self {
+        self.sealed_storage = Some(sealed_storage);
+        self
+    }
+
     fn mut_core(&mut self) -> RefMut<'_, DriverContextCore> {
         self.core.borrow_mut()
     }
@@ -231,38 +686,154 @@ impl<
         raft_config: &Option<RaftConfig>,
         snapshot: Bytes,
         leader: bool,
+        recovery_state: Option<ReplicaRecoveryState>,
     ) -> Result<(), PalError> {
         let mut config = raft::Config {
             id: self.id,
             ..Default::default()
         };
 
+        let persist_replica_state = raft_config
+            .as_ref()
+            .is_some_and(|raft_config| raft_config.persist_replica_state);
+        let journal_key = if persist_replica_state {
+            self.initialize_journal_key(recovery_state.is_some())?
+        } else {
+            Vec::new()
+        };
+        self.journal
+            .configure(persist_replica_state, self.id, journal_key);
+
         if let Some(raft_config) = raft_config {
             // Store driver relavant parts of the config.
             self.driver_config.tick_period = raft_config.tick_period;
+            self.driver_config.election_tick = raft_config.election_tick as u64;
+            self.driver_config.replicated_randomness = raft_config.replicated_randomness;
+            self.driver_config.actor_error_policy = raft_config.actor_error_policy();
             if let Some(snapshot_config) = &raft_config.snapshot_config {
                 self.driver_config.snapshot_count = snapshot_config.snapshot_count;
+                if snapshot_config.chunk_size != 0 {
+                    self.driver_config.snapshot_chunk_size = snapshot_config.chunk_size;
+                }
+                self.driver_config.encrypt_snapshots = snapshot_config.encrypt_payloads;
             }
 
             // Update Raft native configuration.
             config.election_tick = raft_config.election_tick as usize;
             config.heartbeat_tick = raft_config.heartbeat_tick as usize;
+            config.min_election_tick = raft_config.min_election_tick as usize;
+            config.max_election_tick = raft_config.max_election_tick as usize;
             config.max_size_per_msg = raft_config.max_size_per_msg;
+            config.pre_vote = raft_config.pre_vote;
+            config.check_quorum = raft_config.check_quorum;
+            if raft_config.lease_based_reads {
+                match check_lease_based_reads(&raft_config) {
+                    Ok(()) => config.read_only_option = RaftReadOnlyOption::LeaseBased,
+                    Err(reason) => {
+                        warn!(self.logger, "Ignoring lease based reads: {}", reason);
+                    }
+                }
+            }
+
+            if let Some(flow_control_config) = &raft_config.flow_control_config {
+                if flow_control_config.max_inflight_msgs != 0 {
+                    config.max_inflight_msgs = flow_control_config.max_inflight_msgs as usize;
+                }
+                if flow_control_config.max_committed_size_per_ready != 0 {
+                    config.max_committed_size_per_ready =
+                        flow_control_config.max_committed_size_per_ready;
+                }
+                if flow_control_config.max_uncommitted_size != 0 {
+                    config.max_uncommitted_size = flow_control_config.max_uncommitted_size;
+                }
+                self.flow_control.configure(
+                    config.max_inflight_msgs,
+                    flow_control_config.slow_peer_max_inflight_msgs as usize,
+                    flow_control_config.slow_peer_latency,
+                );
+                self.probe_backoff.configure(
+                    flow_control_config.probe_backoff_base,
+                    flow_control_config.probe_backoff_max,
+                );
+            }
+
+            if let Some(follower_read_config) = &raft_config.follower_read_config {
+                self.driver_config.follower_read_config = follower_read_config.clone();
+            }
+
+            if let Some(eviction_config) = &raft_config.eviction_config {
+                self.failure_detector
+                    .configure(eviction_config.dead_replica_timeout);
+                self.driver_config.min_voters = eviction_config.min_voters;
+            }
+
+            if let Some(compaction_config) = &raft_config.compaction_config {
+                self.compaction_policy = create_compaction_policy(compaction_config);
+            }
+        }
+
+        // Witness replica never campaigns, hence it never becomes the leader that
+        // would have to serve the actor and send out the snapshots. The lease that
+        // makes it ignore the votes while the leader is alive still expires after
+        // the election tick, hence it keeps voting in the elections of others.
+        if self.is_witness {
+            config.min_election_tick = usize::MAX / 2;
+            config.max_election_tick = config.min_election_tick + 1;
         }
 
+        self.compaction_instant = self.clock.instant();
+
+        let mut store = (self.store)(
+            self.logger.new(o!("type" => "store")),
+            self.driver_config.snapshot_count,
+        );
+
+        // Restore the persisted state into the storage so that Raft starts at the
+        // position the replica had before the restart.
+        let recovered_state = match recovery_state {
+            Some(recovery_state) => {
+                if leader {
+                    error!(self.logger, "Recovered replica cannot start as the leader");
+                    return Err(PalError::InvalidOperation);
+                }
+
+                let mut recovered_state = self.journal.recover(recovery_state).map_err(|e| {
+                    error!(self.logger, "Failed to recover replica state: {}", e);
+
+                    // Failure to recover replica state must lead to termination.
+                    PalError::Raft
+                })?;
+
+                info!(
+                    self.logger,
+                    "Recovering replica state: {:?}, snapshot: {:?}, entries: {}",
+                    recovered_state.hard_state,
+                    get_metadata(&recovered_state.snapshot),
+                    recovered_state.entries.len()
+                );
+
+                let mut restore_result = Ok(());
+                if !recovered_state.snapshot.is_empty() {
+                    restore_result = store.apply_snapshot(recovered_state.snapshot.clone());
+                }
+                restore_result
+                    .and_then(|_| store.append_entries(mem::take(&mut recovered_state.entries)))
+                    .map_err(|e| {
+                        error!(self.logger, "Failed to restore replica storage: {}", e);
+
+                        // Failure to restore replica storage must lead to termination.
+                        PalError::Raft
+                    })?;
+                store.set_hard_state(recovered_state.hard_state.clone());
+
+                Some(recovered_state)
+            }
+            None => None,
+        };
+
         // Initialize Raft instance.
         self.raft
-            .init(
-                self.id,
-                &config,
-                snapshot,
-                leader,
-                (self.store)(
-                    self.logger.new(o!("type" => "store")),
-                    self.driver_config.snapshot_count,
-                ),
-                &self.logger,
-            )
+            .init(self.id, &config, snapshot, leader, store, &self.logger)
             .map_err(|e| {
                 error!(self.logger, "Failed to create Raft node: {}", e);
 
@@ -272,7 +843,7 @@ impl<
 
         // Initialize raft progress to match the current state of raft.
         // Note that we have non zero applied index only if the node has
-        // been initialized as leader.
+        // been initialized as leader or recovered from a snapshot.
         if leader {
             self.raft_progress = RaftProgress {
                 applied_index: 1,
@@ -280,10 +851,23 @@ impl<
                     voters: vec![self.id],
                     ..Default::default()
                 },
+                pending_proposals: 0,
             };
         }
 
-        self.tick_instant = self.instant;
+        match recovered_state {
+            Some(mut recovered_state) => {
+                // Entries committed after the snapshot are applied again through
+                // Raft once the actor state is restored from the snapshot.
+                self.replayed_index = recovered_state.hard_state.commit;
+                if !recovered_state.snapshot.is_empty() {
+                    self.load_raft_snapshot(&mut recovered_state.snapshot)?;
+                }
+            }
+            None => self.stash_state_checkpoint()?,
+        }
+
+        self.tick_instant = self.clock.instant();
         // No need to initially report the state of the cluster, only after the changes.
         self.prev_raft_state = self.get_raft_state();
 
@@ -299,6 +883,13 @@ impl<
         // Report committed cluster config only if current replica is the leader.
         if raft_state.leader_replica_id == self.id {
             raft_state.committed_cluster_config = self.raft_progress.config_state.voters.clone();
+            raft_state.committed_cluster_learners =
+                self.raft_progress.config_state.learners.clone();
+            raft_state.committed_cluster_outgoing_config =
+                self.raft_progress.config_state.voters_outgoing.clone();
+            // Joint consensus is left through another config change.
+            raft_state.has_pending_change |=
+                !raft_state.committed_cluster_outgoing_config.is_empty();
         }
         raft_state
     }
@@ -335,6 +926,43 @@ impl<
                     );
                 }
 
+                // Any message from the peer proves that it is alive.
+                self.failure_detector
+                    .observe(message.get_from(), self.clock.instant());
+
+                // Acknowledged appends let the leader estimate replication latency of the peer.
+                if message.get_msg_type() == RaftMessageType::MsgAppendResponse {
+                    let instant = self.clock.instant();
+                    self.flow_control
+                        .observe_append_response(message.get_from(), instant);
+                    self.probe_backoff.observe_append_response(
+                        message.get_from(),
+                        message.get_reject(),
+                        instant,
+                    );
+                }
+
+                // Appends, heartbeats and snapshots are only sent by the leader.
+                if matches!(
+                    message.get_msg_type(),
+                    RaftMessageType::MsgAppend
+                        | RaftMessageType::MsgHeartbeat
+                        | RaftMessageType::MsgSnapshot
+                ) {
+                    self.raft_progress.leader_contact_instant = Some(self.clock.instant());
+                }
+
+                // Witness replica refuses to take over the leadership from the
+                // leader handing it over.
+                if self.is_witness && message.get_msg_type() == RaftMessageType::MsgTimeoutNow {
+                    warn!(
+                        self.logger,
+                        "Witness replica ignoring leadership transfer from replica {}",
+                        message.get_from()
+                    );
+                    return Ok(());
+                }
+
                 // Advance Raft internal state by one step.
                 match self.raft.make_step(message) {
                     Err(e) => {
@@ -349,10 +977,50 @@ impl<
         }
     }
 
-    fn make_raft_proposal(&mut self, proposal_contents: Bytes) {
+    fn make_raft_proposal(&mut self, proposal_contents: Bytes) -> Result<bool, PalError> {
         debug!(self.logger, "Making Raft proposal");
 
-        self.raft.make_proposal(proposal_contents).unwrap();
+        // Proposal contents are uniquely owned after being encoded, hence conversion
+        // into the Raft entry payload reuses the buffer.
+        let proposal_contents = self.stamp_raft_proposal(proposal_contents);
+        match self.raft.make_proposal(proposal_contents) {
+            Ok(_) => Ok(true),
+            Err(RaftError::ProposalDropped) => {
+                // Raft drops proposals once the uncommitted entries exceed the limit
+                // or if there is no leader to forward them to.
+                warn!(self.logger, "Dropping Raft proposal");
+                Ok(false)
+            }
+            Err(e) => {
+                error!(self.logger, "Raft experienced unrecoverable error: {}", e);
+
+                // Unrecoverable Raft errors must lead to termination.
+                Err(PalError::Raft)
+            }
+        }
+    }
+
+    // Records in the proposal the index the leader has applied the log up to, to
+    // let the replicas settle whether the entries up to it have been skipped. The
+    // field is merged into the encoded entry by appending it.
+    fn stamp_raft_proposal(&self, proposal_contents: Bytes) -> Bytes {
+        let applied_index = self.raft_progress.applied_index;
+        if matches!(
+            self.driver_config.actor_error_policy,
+            ActorErrorPolicy::Unspecified | ActorErrorPolicy::Halt
+        ) || applied_index == 0
+            || !self.check_raft_leadership()
+        {
+            return proposal_contents;
+        }
+
+        let stamp = Entry {
+            proposer_applied_index: applied_index,
+            ..Default::default()
+        };
+        let mut contents = Vec::from(proposal_contents);
+        contents.extend_from_slice(&stamp.encode_to_vec());
+        contents.into()
     }
 
     fn make_raft_config_change_proposal(
@@ -379,11 +1047,50 @@ impl<
         }
     }
 
+    fn make_raft_learner_promotion_proposal(
+        &mut self,
+        node_id: u64,
+    ) -> Result<ChangeClusterStatus, PalError> {
+        // Promoted learner must not slow down the commit progress, hence it must
+        // have caught up with the leader log first.
+        if !self.raft_progress.config_state.learners.contains(&node_id)
+            || !self.raft.replica_caught_up(node_id)
+        {
+            warn!(
+                self.logger,
+                "Rejecting learner {} promotion: not a caught up learner", node_id
+            );
+
+            return Ok(ChangeClusterStatus::ChangeStatusRejected);
+        }
+
+        // Adding existing learner as a voter promotes it.
+        self.make_raft_config_change_proposal(node_id, RaftConfigChangeType::AddNode)
+    }
+
+    fn make_raft_voter_demotion_proposal(
+        &mut self,
+        node_id: u64,
+    ) -> Result<ChangeClusterStatus, PalError> {
+        if !self.raft_progress.config_state.voters.contains(&node_id) {
+            warn!(
+                self.logger,
+                "Rejecting replica {} demotion: not a voter", node_id
+            );
+
+            return Ok(ChangeClusterStatus::ChangeStatusRejected);
+        }
+
+        // Adding existing voter as a learner demotes it.
+        self.make_raft_config_change_proposal(node_id, RaftConfigChangeType::AddLearnerNode)
+    }
+
     fn trigger_raft_tick(&mut self) {
         // Given that Raft is being driven from the outside and arbitrary amount of time can
         // pass between driver invocation we may need to produce multiple ticks.
-        if self.instant - self.tick_instant >= self.driver_config.tick_period {
-            self.tick_instant = self.instant;
+        let instant = self.clock.instant();
+        if instant - self.tick_instant >= self.driver_config.tick_period {
+            self.tick_instant = instant;
             // invoke Raft tick to trigger timer based changes.
             self.raft.make_tick();
         }
@@ -395,11 +1102,13 @@ impl<
     ) -> Result<(), PalError> {
         // Sort committed entries by entry index to make sure they are applied in order.
         committed_entries.sort_by(|a, b| a.index.cmp(&b.index));
-        for committed_entry in committed_entries {
+        for mut committed_entry in committed_entries {
             // Remember progress of applying committed entries.
             self.raft_progress.applied_index = committed_entry.index;
 
-            if committed_entry.data.is_empty() {
+            if committed_entry.data.is_empty()
+                && committed_entry.get_entry_type() == RaftEntryType::EntryNormal
+            {
                 // Empty entry is produced by the newly elected leader to commit entries
                 // from the previous terms.
                 continue;
@@ -434,1797 +1143,5898 @@ impl<
                         self.collect_config_state(config_state);
                     }
                 };
+            } else if let RaftEntryType::EntryConfChangeV2 = committed_entry.get_entry_type() {
+                // Make committed configuration effective, this includes entering and
+                // leaving joint consensus. Note that leaving is an empty config change.
+                let config_change = match deserialize_config_change_v2(&committed_entry.data) {
+                    Ok(config_change) => config_change,
+                    Err(e) => {
+                        error!(
+                            self.logger,
+                            "Failed to deserialize Raft config change: {}", e
+                        );
+                        // Failure to deserialize Raft config change must lead to termination.
+                        return Err(PalError::Raft);
+                    }
+                };
+
+                debug!(
+                    self.logger,
+                    "Applying Raft joint config change entry: {:?}", config_change
+                );
+
+                match self.raft.apply_config_change_v2(&config_change) {
+                    Err(e) => {
+                        error!(self.logger, "Failed to apply Raft config change: {}", e);
+                        // Failure to apply Raft config change must lead to termination.
+                        return Err(PalError::Raft);
+                    }
+                    Ok(config_state) => {
+                        self.collect_config_state(config_state);
+                    }
+                };
             } else {
                 debug!(
                     self.logger,
                     "Applying Raft entry #{}", committed_entry.index
                 );
+                // Move the payload into shared bytes without copying, the decoded entry
+                // contents passed to the actor then reference the same buffer.
+                let entry_data = Bytes::from(mem::take(&mut committed_entry.data));
                 // Recover the entry id so that original message can be correlated
-                let entry = Entry::decode(committed_entry.get_data()).map_err(|e| {
-                    error!(self.logger, "Failed to deserialize Raft entry: {}", e);
-                    // Failure to deserialize Raft config change must lead to termination.
-                    return PalError::Raft;
-                })?;
-
-                let entry_id = entry.entry_id.unwrap();
-
-                // Pass committed entry to the actor to make effective.
-                let event_outcome = self
-                    .actor
-                    .on_apply_event(
-                        ActorEventContext {
-                            index: committed_entry.index,
-                            owned: entry_id.replica_id == self.id,
-                        },
-                        ActorEvent::with_bytes(entry_id.entry_id, entry.entry_contents),
-                    )
-                    .map_err(|e| {
-                        error!(
-                            self.logger,
-                            "Failed to apply committed event to actor state: {}", e
-                        );
-                        // Failure to apply committed event to actor state must lead to termination.
-                        PalError::Actor
-                    })?;
-
-                for actor_command in event_outcome.commands {
-                    self.stash_message(out_message::Msg::DeliverAppMessage(DeliverAppMessage {
-                        correlation_id: actor_command.correlation_id,
-                        message_header: actor_command.header,
-                        message_payload: actor_command.payload,
-                    }));
+                if self.is_witness {
+                    // Witness replica has no actor state to apply entries to, yet it
+                    // attests its peers hence follows the attestation policy updates.
+                    self.apply_witness_entry(entry_data);
+                    continue;
                 }
+
+                self.apply_entry(committed_entry.index, entry_data)?;
             }
         }
 
         Ok(())
     }
 
-    fn send_raft_messages(&mut self, raft_messages: Vec<RaftMessage>) -> Result<(), PalError> {
-        for raft_message in raft_messages {
-            // Stash messages that contain snapshot to be sent out by the snapshot processor.
-            if raft_message.msg_type
-                == <MessageType as Into<i32>>::into(RaftMessageType::MsgSnapshot)
-            {
-                self.stash_snapshot(raft_message);
-                continue;
-            }
+    fn apply_witness_entry(&mut self, entry_data: Bytes) {
+        // Entries are applied by the other replicas, malformed entries halt them.
+        let Ok(entry) = Entry::decode(entry_data) else {
+            return;
+        };
 
-            self.communication
-                .process_out_message(out_message::Msg::DeliverSystemMessage(
-                    DeliverSystemMessage {
-                        recipient_replica_id: raft_message.to,
-                        sender_replica_id: self.id,
-                        message_contents: serialize_raft_message(&raft_message).unwrap(),
-                    },
-                ))?;
+        if let Some(attestation_policy) = entry
+            .actor_config_update
+            .and_then(|update| update.attestation_policy)
+        {
+            self.apply_attestation_policy(attestation_policy);
         }
-
-        Ok(())
     }
 
-    fn restore_raft_snapshot(&mut self, raft_snapshot: &mut RaftSnapshot) -> Result<(), PalError> {
-        if raft_snapshot.is_empty() {
-            // Nothing to restore if the snapshot is empty.
-            return Ok(());
-        }
+    fn apply_attestation_policy(&mut self, attestation_policy: AttestationPolicy) {
+        self.communication
+            .update_attestation_policy(&attestation_policy);
+        self.attestation_policy = Some(attestation_policy);
+    }
 
-        info!(
-            self.logger,
-            "Restoring Raft snappshot: {:?}",
-            get_metadata(raft_snapshot)
-        );
+    fn apply_entry(&mut self, index: u64, entry_data: Bytes) -> Result<(), PalError> {
+        let mut entry = Entry::decode(entry_data).map_err(|e| {
+            error!(self.logger, "Failed to deserialize Raft entry: {}", e);
+            // Failure to deserialize Raft config change must lead to termination.
+            return PalError::Raft;
+        })?;
 
-        self.collect_config_state(get_config_state(raft_snapshot).clone());
+        if entry.skipped_index != 0 {
+            return self.apply_tombstone(entry.skipped_index, &entry.skipped_positions);
+        }
+        self.settle_entries(entry.proposer_applied_index)?;
+
+        if let Some(report) = &entry.schema_version_report {
+            self.mut_core().schema().record_report(report);
+        } else if entry.activated_schema_version != 0 {
+            self.activate_schema_version(entry.activated_schema_version)?;
+        } else if let Some(update) = entry.actor_config_update.take() {
+            let owned = update.replica_id == self.id && index > self.replayed_index;
+            self.apply_actor_config(update, owned);
+        } else if !entry.fired_timers.is_empty() {
+            let random_seed = self.decrypt_random_seed(&entry.random_seed)?;
+            self.mut_core().random().seed(&random_seed);
+            for fired_timer in mem::take(&mut entry.fired_timers) {
+                self.apply_fired_timer(index, fired_timer)?;
+            }
+        } else if entry.batched_entries.is_empty() {
+            self.apply_actor_entry(index, 0, entry)?;
+        } else {
+            // Batched proposals are applied in the order they were made.
+            for (position, batched_entry) in mem::take(&mut entry.batched_entries)
+                .into_iter()
+                .enumerate()
+            {
+                self.apply_actor_entry(index, position, batched_entry)?;
+            }
+        }
+        // Random bytes drawn outside of applying entries are local to the replica.
+        self.mut_core().random().unseed();
 
-        // Persist unstable snapshot received from a peer into the stable storage.
-        let apply_result = self.raft.mut_store().apply_snapshot(raft_snapshot.clone());
-        if let Err(e) = apply_result {
-            error!(
-                self.logger,
-                "Failed to apply Raft snapshot to storage: {}", e
-            );
-            // Failure to apply Raft snapshot to storage snapshot must lead to termination.
+        self.propose_tombstones()
+    }
+
+    // Position is the position of the entry within the batch, zero if the entry
+    // has not been batched.
+    fn apply_actor_entry(
+        &mut self,
+        index: u64,
+        position: usize,
+        entry: Entry,
+    ) -> Result<(), PalError> {
+        let Some(entry_id) = entry.entry_id else {
+            error!(self.logger, "Raft entry #{} has no entry id", index);
+            // Malformed entry must lead to termination.
             return Err(PalError::Raft);
-        }
+        };
 
-        // Pass snapshot to the actor to restore.
-        let snapshot = Bytes::from(raft_snapshot.take_data());
-        self.actor.on_load_snapshot(snapshot).map_err(|e| {
-            error!(self.logger, "Failed to load actor state snapshot: {}", e);
-            // Failure to load actor snapshot must lead to termination.
-            PalError::Actor
-        })?;
+        // Entries replayed to restore the actor state have been accounted for, and
+        // so have the events failed on losing the leadership.
+        let owned = entry_id.replica_id == self.id
+            && index > self.replayed_index
+            && !self.abandoned_events.remove(&entry_id.entry_id);
+        if owned {
+            self.proposed_events.remove(&entry_id.entry_id);
+            self.raft_progress.pending_proposals =
+                self.raft_progress.pending_proposals.saturating_sub(1);
+        }
+        let random_seed = self.decrypt_random_seed(&entry.random_seed)?;
+        self.mut_core().random().seed(&random_seed);
+
+        // Pass committed entry to the actor to make effective.
+        let apply_result = self.interceptors.apply_event(
+            &mut self.actor,
+            ActorEventContext { index, owned },
+            ActorEvent::with_bytes(entry_id.entry_id, entry.entry_contents),
+        );
+        let event_outcome = match apply_result {
+            Ok(event_outcome) => event_outcome,
+            Err(e) => {
+                error!(
+                    self.logger,
+                    "Failed to apply committed event to actor state: {}", e
+                );
+                return self.recover_from_actor_error(index, position, owned, entry_id.entry_id);
+            }
+        };
 
-        // Applied index is reset to the snapshot index.
-        self.raft_progress.applied_index = get_metadata(raft_snapshot).index;
+        for actor_command in event_outcome.commands {
+            self.stash_message(out_message::Msg::DeliverAppMessage(DeliverAppMessage {
+                correlation_id: actor_command.correlation_id,
+                message_header: actor_command.header,
+                message_payload: actor_command.payload,
+                route: actor_command.route,
+                status_code: actor_command.status_code,
+            }));
+        }
 
         Ok(())
     }
 
-    fn maybe_create_raft_snapshot(&mut self) -> Result<(), PalError> {
-        if !self.raft.mut_store().should_snapshot(
-            self.raft_progress.applied_index,
-            &self.raft_progress.config_state,
-        ) {
+    fn apply_fired_timer(&mut self, index: u64, fired_timer: FiredTimer) -> Result<(), PalError> {
+        // Timer may have been cancelled or rescheduled after the firing has been
+        // proposed, or may have been fired already by the previous leader.
+        if !self.mut_core().timers().fire(&fired_timer) {
             return Ok(());
         }
 
-        let snapshot_data = self.actor.on_save_snapshot().map_err(|e| {
-            error!(self.logger, "Failed to save actor state to snapshot: {}", e);
-            // Failure to apply Raft snapshot to storage snapshot must lead to termination.
-            PalError::Actor
-        })?;
-
-        let applied_index = self.raft_progress.applied_index;
-        let config_state = self.raft_progress.config_state.clone();
-        self.raft
-            .mut_store()
-            .create_snapshot(applied_index, config_state, snapshot_data)
+        let event_outcome = self
+            .actor
+            .on_timer(
+                ActorEventContext {
+                    index,
+                    owned: false,
+                },
+                fired_timer.timer_id,
+            )
             .map_err(|e| {
-                error!(self.logger, "Failed to save actor state to snapshot: {}", e);
-                // Failure to create Raft snapshot to storage snapshot must lead to termination.
+                error!(self.logger, "Failed to handle fired timer by actor: {}", e);
+                // Failure to handle fired timer must lead to termination.
                 PalError::Actor
-            })
-    }
+            })?;
 
-    fn advance_raft(&mut self) -> Result<(), PalError> {
-        // Given that instant only set once trigger Raft tick once as well.
-        self.trigger_raft_tick();
+        for actor_command in event_outcome.commands {
+            self.stash_message(out_message::Msg::DeliverAppMessage(DeliverAppMessage {
+                correlation_id: actor_command.correlation_id,
+                message_header: actor_command.header,
+                message_payload: actor_command.payload,
+                route: actor_command.route,
+                status_code: actor_command.status_code,
+            }));
+        }
 
-        if !self.raft.has_ready() {
-            // There is nothing process.
+        Ok(())
+    }
+
+    fn recover_from_actor_error(
+        &mut self,
+        index: u64,
+        position: usize,
+        owned: bool,
+        correlation_id: u64,
+    ) -> Result<(), PalError> {
+        if self.restoring_actor {
+            // Entry has been skipped before the actor state is restored.
             return Ok(());
         }
 
-        let mut raft_ready = self.raft.get_ready();
+        match self.driver_config.actor_error_policy {
+            ActorErrorPolicy::Unspecified | ActorErrorPolicy::Halt => {
+                // Failure to apply committed event to actor state must lead to termination.
+                return Err(PalError::Actor);
+            }
+            ActorErrorPolicy::SkipEntry => {}
+            ActorErrorPolicy::RestoreSnapshot => self.restore_actor_state(index, position)?,
+        }
 
-        // Send out messages to the peers.
-        self.send_raft_messages(raft_ready.take_messages())?;
+        warn!(self.logger, "Skipping Raft entry #{}", index);
+        self.skipped_entries
+            .entry(index)
+            .or_default()
+            .push(position as u32);
+        self.skipped_entry_count += 1;
 
-        if let Some(raft_hard_state) = raft_ready.hard_state() {
-            // Persist changed hard state into the stable storage.
-            self.raft
-                .mut_store()
-                .set_hard_state(raft_hard_state.clone());
+        if owned {
+            // Let the consumer know that the proposal has failed.
+            self.stash_message(out_message::Msg::DeliverAppMessage(DeliverAppMessage {
+                correlation_id,
+                message_header: Bytes::new(),
+                message_payload: Bytes::new(),
+                route: String::new(),
+                status_code: StatusCode::Internal as i32,
+            }));
         }
 
-        // If not empty persist snapshot to stable storage and apply it to the
-        // actor.
-        let mut snapshot = raft_ready.take_snapshot();
-        self.restore_raft_snapshot(&mut snapshot)?;
+        Ok(())
+    }
 
-        // Apply committed entries to the actor state machine.
-        self.apply_raft_committed_entries(raft_ready.take_committed_entries())?;
-        // Send out messages that had to await the persistence of the hard state, entries
-        // and snapshot to the stable storage.
-        self.send_raft_messages(raft_ready.take_persisted_messages())?;
+    // Replicates the decision of the leader to skip the entries it or, before
+    // having been elected, the previous leaders have failed to apply, once for
+    // all skipped entries of the batch. The tombstones precede all entries the
+    // leader proposes afterwards.
+    fn propose_tombstones(&mut self) -> Result<(), PalError> {
+        if self.restoring_actor || !self.check_raft_leadership() {
+            return Ok(());
+        }
 
-        let entries = raft_ready.take_entries();
-        if !entries.is_empty() {
-            // Persist unstable entries into the stable storage.
-            let append_result = self.raft.mut_store().append_entries(entries.as_ref());
-            if let Err(e) = append_result {
+        let tombstones: Vec<Entry> = self
+            .skipped_entries
+            .range(self.proposed_tombstone_index + 1..)
+            .map(|(skipped_index, skipped_positions)| Entry {
+                skipped_index: *skipped_index,
+                skipped_positions: skipped_positions.clone(),
+                ..Default::default()
+            })
+            .collect();
+        for tombstone in tombstones {
+            if !self.make_raft_proposal(tombstone.encode_to_vec().into())? {
                 error!(
                     self.logger,
-                    "Failed to append Raft entries to storage: {}", e
+                    "Failed to propose tombstone for Raft entry #{}", tombstone.skipped_index
                 );
-                // Failure to append Raft entries to storage snapshot must lead to termination.
-                return Err(PalError::Actor);
+                // Leader that cannot replicate its decision to skip the entry must lead to termination.
+                return Err(PalError::Raft);
             }
+            self.proposed_tombstone_index = tombstone.skipped_index;
         }
 
-        // Advance Raft state after fully processing ready.
-        let mut light_raft_ready = self.raft.advance_ready(raft_ready);
-
-        // Send out messages to the peers.
-        self.send_raft_messages(light_raft_ready.take_messages())?;
-        // Apply all committed entries.
-        self.apply_raft_committed_entries(light_raft_ready.take_committed_entries())?;
-        // Advance the apply index.
-        self.raft.advance_apply();
-
         Ok(())
     }
 
-    fn reset_leader_state(&mut self) {
-        self.prev_raft_state = RaftState::new();
-    }
-
-    fn collect_config_state(&mut self, config_state: RaftConfigState) {
-        self.raft_progress.config_state = config_state;
-    }
-
-    fn stash_leader_state(&mut self) {
-        self.raft_state = self.get_raft_state();
-
-        if self.prev_raft_state == self.raft_state {
-            return;
+    // Settles the outcome of the entries up to the index the leader proposing
+    // the entry being applied has applied. The leader has replicated the
+    // tombstones for the entries it has skipped before proposing the entry,
+    // hence the entries skipped by this replica alone have diverged.
+    fn settle_entries(&mut self, applied_index: u64) -> Result<(), PalError> {
+        if self.restoring_actor || applied_index <= self.settled_index {
+            return Ok(());
         }
+        self.settled_index = applied_index;
 
-        self.prev_raft_state = self.raft_state.clone();
-
-        // Update snapshot processor with the latest raft cluster state.
-        self.update_snapshot_cluster_change();
-
-        // Update communication module with the latest raft cluster state.
-        self.communication
-            .process_cluster_change(&self.raft_state.committed_cluster_config);
-
-        // Sent out cluster check message with the update.
-        self.stash_message(out_message::Msg::CheckCluster(CheckClusterResponse {
-            leader_replica_id: self.raft_state.leader_replica_id,
-            leader_term: self.raft_state.leader_term,
-            cluster_replica_ids: self.raft_state.committed_cluster_config.clone(),
-            has_pending_changes: self.raft_state.has_pending_change,
-        }));
-    }
-
-    fn preset_state_machine(&mut self, instant: u64) {
-        self.prev_raft_state = self.raft_state.clone();
-        self.instant = cmp::max(self.instant, instant);
-        let instant = self.instant;
-        let leader = self.check_raft_leadership();
-        self.mut_core().set_state(instant, leader);
-    }
-
-    fn check_driver_state(&self, state: DriverState) -> Result<(), PalError> {
-        if self.driver_state != state {
-            return Err(PalError::InvalidOperation);
+        if let Some((&skipped_index, _)) = self.skipped_entries.first_key_value()
+            && skipped_index <= applied_index
+        {
+            error!(
+                self.logger,
+                "Raft entry #{} has been skipped by this replica but applied by the leader",
+                skipped_index
+            );
+            // Actor state diverged from the leader must lead to termination.
+            return Err(PalError::Actor);
         }
 
-        Ok(())
-    }
-
-    fn check_driver_started(&self) -> Result<(), PalError> {
-        self.check_driver_state(DriverState::Started)
-    }
+        // Tombstones of the settled entries are ignored from now on.
+        self.tombstoned_indexes = self.tombstoned_indexes.split_off(&(applied_index + 1));
 
-    fn check_non_ephemeral(&self) -> Result<(), PalError> {
-        if self.is_ephemeral {
-            return Err(PalError::InvalidOperation);
-        }
         Ok(())
     }
 
-    fn initialize_driver(&mut self, _app_signing_key: Vec<u8>, replica_id_hint: u64) {
-        self.id = replica_id_hint;
-        self.logger = self.logger.new(o!("raft_id" => self.id));
-    }
-
-    fn process_start_node(
+    // Restores the actor state as it was right before the entry at the given
+    // index and position within the batch has been applied.
+    fn restore_actor_state(
         &mut self,
-        start_replica_request: &mut StartReplicaRequest,
-        app_signing_key: Vec<u8>,
+        failed_index: u64,
+        failed_position: usize,
     ) -> Result<(), PalError> {
-        self.check_driver_state(DriverState::Created)?;
-
-        self.initialize_driver(app_signing_key, start_replica_request.replica_id_hint);
+        let mut snapshot = self.raft.mut_store().latest_snapshot();
+        let snapshot_index = get_metadata(&snapshot).index;
+        info!(
+            self.logger,
+            "Restoring actor state from snapshot #{}", snapshot_index
+        );
 
-        let id = self.id;
-        let app_config = mem::take(&mut start_replica_request.app_config);
-        self.mut_core().set_immutable_state(id, app_config);
+        let entries = self
+            .raft
+            .mut_store()
+            .entries(
+                snapshot_index + 1,
+                failed_index + 1,
+                u64::MAX,
+                GetEntriesContext::empty(false),
+            )
+            .map_err(|e| {
+                error!(self.logger, "Failed to read Raft entries to replay: {}", e);
+                // Failure to restore actor state must lead to termination.
+                PalError::Raft
+            })?;
 
+        self.actor.on_shutdown();
         let actor_context = Box::new(DriverContext::new(
             Rc::clone(&self.core),
+            Rc::clone(&self.clock),
             self.logger.new(o!("type" => "actor")),
         ));
-
         self.actor.on_init(actor_context).map_err(|e| {
             error!(self.logger, "Failed to initialize actor: {}", e);
-
             // Failure to initialize actor must lead to termination.
             PalError::Actor
         })?;
-        self.is_ephemeral = start_replica_request.is_ephemeral;
+        self.mut_core().timers().clear();
+        let snapshot_data = self.load_attestation_policy(Bytes::from(snapshot.take_data()))?;
+        if !snapshot_data.is_empty() {
+            self.load_actor_snapshot(snapshot_data)?;
+        }
+        self.loaded_snapshot_index = snapshot_index;
+
+        // Outcomes of the replayed entries have been sent out already.
+        let replayed_index = mem::replace(&mut self.replayed_index, failed_index);
+        self.restoring_actor = true;
+        for mut raft_entry in entries {
+            if raft_entry.get_entry_type() != RaftEntryType::EntryNormal
+                || raft_entry.data.is_empty()
+            {
+                continue;
+            }
 
-        // Initialize Raft and Snapshot only for non-ephemeral nodes.
-        if !self.is_ephemeral {
-            let snapshot = self.actor.on_save_snapshot().map_err(|e| {
-                error!(self.logger, "Failed to save actor snapshot: {}", e);
+            let entry_data = Bytes::from(mem::take(&mut raft_entry.data));
+            if raft_entry.index < failed_index {
+                self.apply_entry(raft_entry.index, entry_data)?;
+                continue;
+            }
 
-                // Failure to save actor snapshot must lead to termination.
-                PalError::Actor
+            // Only the batched entries that precede the failed one have been applied.
+            let entry = Entry::decode(entry_data).map_err(|e| {
+                error!(self.logger, "Failed to deserialize Raft entry: {}", e);
+                // Failure to deserialize Raft entry must lead to termination.
+                PalError::Raft
             })?;
+            for (position, batched_entry) in entry
+                .batched_entries
+                .into_iter()
+                .take(failed_position)
+                .enumerate()
+            {
+                self.apply_actor_entry(raft_entry.index, position, batched_entry)?;
+            }
+        }
+        self.restoring_actor = false;
+        self.replayed_index = replayed_index;
 
-            // Initialize snapshot processor.
-            let snapshot_config = match &start_replica_request.raft_config {
-                Some(raft_config) => &raft_config.snapshot_config,
-                None => &None,
-            };
-            self.snapshot.init(
-                self.logger.new(o!("type" => "snapshot")),
-                self.id,
-                snapshot_config,
-            );
+        Ok(())
+    }
 
-            self.initialize_raft_node(
-                &start_replica_request.raft_config,
-                snapshot,
-                start_replica_request.is_leader,
-            )?;
+    fn activate_schema_version(&mut self, schema_version: u32) -> Result<(), PalError> {
+        if !self.mut_core().schema().activate(schema_version) {
+            error!(
+                self.logger,
+                "Activated snapshot schema version {} is not supported", schema_version
+            );
+            // Replica that cannot save snapshots in the agreed version must lead to termination.
+            return Err(PalError::Actor);
         }
 
-        let communication_config = match &start_replica_request.raft_config {
-            Some(raft_config) => Some(CommunicationConfig {
-                handshake_retry_tick: raft_config.handshake_retry_tick,
-            }),
-            None => None,
-        };
-
-        self.communication.init(
-            self.id,
-            self.logger.new(o!("type" => "communication")),
-            communication_config,
+        info!(
+            self.logger,
+            "Activated snapshot schema version {}", schema_version
         );
-
-        self.driver_state = DriverState::Started;
-
-        self.stash_message(out_message::Msg::StartReplica(StartReplicaResponse {
-            replica_id: self.id,
-        }));
-
         Ok(())
     }
 
-    fn process_stop_node(
+    fn apply_tombstone(
         &mut self,
-        _stop_replica_request: &StopReplicaRequest,
+        skipped_index: u64,
+        skipped_positions: &[u32],
     ) -> Result<(), PalError> {
-        if let DriverState::Stopped = self.driver_state {
+        // Tombstones replayed to restore the actor state have been applied already.
+        if self.restoring_actor {
             return Ok(());
         }
 
-        self.actor.on_shutdown();
-
-        self.driver_state = DriverState::Stopped;
+        if let Some(positions) = self.skipped_entries.remove(&skipped_index) {
+            if positions != skipped_positions {
+                error!(
+                    self.logger,
+                    "Raft entry #{} has been skipped differently by the leader", skipped_index
+                );
+                // Actor state diverged from the leader must lead to termination.
+                return Err(PalError::Actor);
+            }
+            self.tombstoned_indexes.insert(skipped_index);
+            return Ok(());
+        }
 
-        self.stash_message(out_message::Msg::StopReplica(StopReplicaResponse {}));
+        // Entries settled before the tombstone have been applied by all replicas,
+        // entries covered by the snapshot the actor state has been loaded from have
+        // not been applied by this replica, and the next leader may replicate the
+        // tombstone again.
+        if skipped_index <= self.settled_index
+            || skipped_index <= self.loaded_snapshot_index
+            || self.tombstoned_indexes.contains(&skipped_index)
+        {
+            return Ok(());
+        }
 
-        Ok(())
+        error!(
+            self.logger,
+            "Raft entry #{} has been skipped by the leader but applied by this replica",
+            skipped_index
+        );
+        // Actor state diverged from the leader must lead to termination.
+        Err(PalError::Actor)
     }
 
-    fn process_change_cluster(
-        &mut self,
-        change_cluster_request: &ChangeClusterRequest,
-    ) -> Result<(), PalError> {
-        self.check_driver_started()?;
-        self.check_non_ephemeral()?;
-
-        let change_status = match ChangeClusterType::try_from(change_cluster_request.change_type) {
-            Ok(ChangeClusterType::ChangeTypeAddReplica) => self.make_raft_config_change_proposal(
-                change_cluster_request.replica_id,
-                RaftConfigChangeType::AddNode,
-            )?,
-            Ok(ChangeClusterType::ChangeTypeRemoveReplica) => self
-                .make_raft_config_change_proposal(
-                    change_cluster_request.replica_id,
-                    RaftConfigChangeType::RemoveNode,
-                )?,
-            _ => {
-                warn!(self.logger, "Rejecting cluster change command: unknown");
-
-                ChangeClusterStatus::ChangeStatusRejected
+    fn send_raft_messages(&mut self, raft_messages: Vec<RaftMessage>) {
+        for raft_message in raft_messages {
+            // Stash messages that contain snapshot to be sent out by the snapshot processor.
+            if raft_message.msg_type
+                == <MessageType as Into<i32>>::into(RaftMessageType::MsgSnapshot)
+            {
+                // Witness replica snapshots carry no actor state.
+                if self.is_witness {
+                    error!(
+                        self.logger,
+                        "Witness replica dropping snapshot to replica {}", raft_message.to
+                    );
+                    continue;
+                }
+                self.stash_snapshot(raft_message);
+                continue;
             }
-        };
 
-        self.stash_message(out_message::Msg::ChangeCluster(ChangeClusterResponse {
-            change_id: change_cluster_request.change_id,
-            change_status: change_status.into(),
-        }));
+            if raft_message.get_msg_type() == RaftMessageType::MsgAppend {
+                let instant = self.clock.instant();
+                // Raft treats the dropped append as lost and probes the peer again
+                // once it responds to a heartbeat.
+                if self.probe_backoff.backed_off(raft_message.to, instant) {
+                    debug!(
+                        self.logger,
+                        "Holding back append to diverged replica {}", raft_message.to
+                    );
+                    continue;
+                }
+                self.flow_control.observe_append(raft_message.to, instant);
+            }
 
-        Ok(())
+            // Classify message before it gets encrypted so that heartbeats and elections
+            // are not delayed by the log replication.
+            let message_class = MessageClass::from_raft_message_type(raft_message.get_msg_type());
+            self.stash_system_message(
+                message_class,
+                out_message::Msg::DeliverSystemMessage(DeliverSystemMessage {
+                    recipient_replica_id: raft_message.to,
+                    sender_replica_id: self.id,
+                    message_contents: serialize_raft_message(&raft_message).unwrap(),
+                }),
+            );
+        }
     }
 
-    fn process_check_cluster(
-        &mut self,
-        _check_cluster_request: &CheckClusterRequest,
-    ) -> Result<(), PalError> {
-        self.check_driver_started()?;
-        self.check_non_ephemeral()?;
+    fn restore_raft_snapshot(&mut self, raft_snapshot: &mut RaftSnapshot) -> Result<(), PalError> {
+        if raft_snapshot.is_empty() {
+            // Nothing to restore if the snapshot is empty.
+            return Ok(());
+        }
 
-        self.reset_leader_state();
+        info!(
+            self.logger,
+            "Restoring Raft snappshot: {:?}",
+            get_metadata(raft_snapshot)
+        );
 
-        Ok(())
+        // Persist unstable snapshot received from a peer into the stable storage.
+        let apply_result = self.raft.mut_store().apply_snapshot(raft_snapshot.clone());
+        if let Err(e) = apply_result {
+            error!(
+                self.logger,
+                "Failed to apply Raft snapshot to storage: {}", e
+            );
+            // Failure to apply Raft snapshot to storage snapshot must lead to termination.
+            return Err(PalError::Raft);
+        }
+
+        self.load_raft_snapshot(raft_snapshot)
     }
 
-    fn process_deliver_system_message(
-        &mut self,
-        deliver_system_message: DeliverSystemMessage,
-    ) -> Result<(), PalError> {
-        self.check_driver_started()?;
-        self.check_non_ephemeral()?;
+    fn load_raft_snapshot(&mut self, raft_snapshot: &mut RaftSnapshot) -> Result<(), PalError> {
+        self.collect_config_state(get_config_state(raft_snapshot).clone());
+        self.loaded_snapshot_index = get_metadata(raft_snapshot).index;
+
+        // Entries covered by the snapshot are settled, hence their skips and
+        // tombstones are no longer tracked.
+        self.skipped_entries = self
+            .skipped_entries
+            .split_off(&(self.loaded_snapshot_index + 1));
+        self.tombstoned_indexes = self
+            .tombstoned_indexes
+            .split_off(&(self.loaded_snapshot_index + 1));
+        self.settled_index = self.settled_index.max(self.loaded_snapshot_index);
+
+        // Pass snapshot to the actor to restore, witness replica discards it.
+        let snapshot = self.load_attestation_policy(Bytes::from(raft_snapshot.take_data()))?;
+        if !self.is_witness {
+            self.load_actor_snapshot(snapshot)?;
+        }
 
-        let message =
-            self.communication
-                .process_in_message(in_message::Msg::DeliverSystemMessage(
-                    deliver_system_message,
-                ))?;
+        // Applied index is reset to the snapshot index.
+        self.raft_progress.applied_index = get_metadata(raft_snapshot).index;
+        self.core
+            .borrow()
+            .metrics()
+            .increment_counter(SNAPSHOT_LOADED, 1);
 
-        if message.is_none() {
-            return Ok(());
-        }
+        Ok(())
+    }
 
-        let message = message.unwrap();
-        match message {
-            in_message::Msg::DeliverSystemMessage(m) => self.make_raft_step(
-                m.sender_replica_id,
-                m.recipient_replica_id,
-                m.message_contents,
-            ),
-            _ => {
-                warn!(self.logger, "Unexpected message type {:?}", message);
-                Err(PalError::Internal)
-            }
+    fn load_attestation_policy(&mut self, snapshot: Bytes) -> Result<Bytes, PalError> {
+        let (attestation_policy, snapshot) = split_attestation_policy(snapshot).map_err(|e| {
+            error!(
+                self.logger,
+                "Failed to load attestation policy from snapshot: {}", e
+            );
+            // Failure to load snapshot must lead to termination.
+            PalError::Internal
+        })?;
+        if let Some(attestation_policy) = attestation_policy {
+            self.apply_attestation_policy(attestation_policy);
         }
+        Ok(snapshot)
     }
 
-    fn update_snapshot_cluster_change(&mut self) {
-        if self.driver_state != DriverState::Started {
-            return;
+    fn load_actor_snapshot(&mut self, snapshot: Bytes) -> Result<(), PalError> {
+        // Actors unaware of the schema versions only ever see snapshots without header.
+        let supported_version = self.mut_core().schema().supported_version();
+        let (schema_version, mut snapshot) = if supported_version > 0 {
+            split_schema_version(snapshot)
+        } else {
+            (0, snapshot)
+        };
+        if schema_version > supported_version {
+            error!(
+                self.logger,
+                "Snapshot schema version {} is not supported, highest supported is {}",
+                schema_version,
+                supported_version
+            );
+            // Failure to load actor snapshot must lead to termination.
+            return Err(PalError::Actor);
+        }
+        if schema_version < supported_version {
+            snapshot = self
+                .actor
+                .on_migrate_snapshot(schema_version, snapshot)
+                .map_err(|e| {
+                    error!(
+                        self.logger,
+                        "Failed to migrate snapshot from schema version {}: {}", schema_version, e
+                    );
+                    // Failure to migrate actor snapshot must lead to termination.
+                    PalError::Actor
+                })?;
         }
+        // Schema version agreed upon by the cluster is captured by the snapshot.
+        self.mut_core().schema().load(schema_version);
 
-        // Notify snapshot processor of the latest state of the cluster.
-        let snapshot_updates = self.snapshot.process_cluster_change(
-            self.raft_state.leader_replica_id,
-            self.raft_state.leader_term,
-            &self.raft_state.committed_cluster_config,
-        );
+        // Actor schedules the timers captured by its state again while loading.
+        self.mut_core().timers().clear();
 
-        // Notify raft if any of the snapshot transfers has been cancelled.
-        for (replica_id, snapshot_status) in snapshot_updates {
-            self.raft.report_snapshot(replica_id, snapshot_status);
-        }
+        let load_result = if self.actor.loads_snapshot_in_chunks() {
+            self.load_actor_snapshot_in_chunks(snapshot)
+        } else {
+            self.actor.on_load_snapshot(snapshot)
+        };
+        load_result.map_err(|e| {
+            error!(self.logger, "Failed to load actor state snapshot: {}", e);
+            // Failure to load actor snapshot must lead to termination.
+            PalError::Actor
+        })
     }
 
-    fn process_deliver_snapshot_request(
-        &mut self,
-        deliver_snapshot_request: DeliverSnapshotRequest,
-    ) -> Result<(), PalError> {
-        self.check_non_ephemeral()?;
-        let message =
-            self.communication
-                .process_in_message(in_message::Msg::DeliverSnapshotRequest(
-                    deliver_snapshot_request,
-                ))?;
+    fn load_actor_snapshot_in_chunks(&mut self, snapshot: Bytes) -> Result<(), ActorError> {
+        // Pieces are sliced out of the snapshot without copying.
+        for chunk in split_chunks(&snapshot, self.driver_config.snapshot_chunk_size) {
+            self.actor.on_load_snapshot_chunk(chunk)?;
+        }
 
-        if message.is_none() {
-            return Ok(());
+        self.actor.on_load_snapshot_complete()
+    }
+
+    fn save_actor_snapshot(&mut self) -> Result<Bytes, PalError> {
+        let mut snapshot_buffer = SnapshotBuffer::new();
+        if let Some(attestation_policy) = &self.attestation_policy {
+            write_attestation_policy_header(&mut snapshot_buffer, attestation_policy).map_err(
+                |e| {
+                    error!(self.logger, "Failed to save attestation policy: {}", e);
+                    // Failure to save snapshot must lead to termination.
+                    PalError::Internal
+                },
+            )?;
         }
 
-        let message = message.unwrap();
-        match message {
-            in_message::Msg::DeliverSnapshotRequest(m) => {
-                let deliver_snapshot_response = match self.snapshot.mut_processor(self.instant) {
-                    SnapshotProcessorRole::Sender(sender) => {
-                        warn!(
-                            self.logger,
-                            "Node is in snapshot sending mode, unexpected deliver snapshot request"
-                        );
-                        sender.process_unexpected_request(m)
-                    }
-                    SnapshotProcessorRole::Receiver(receiver) => receiver.process_request(m),
-                };
-                self.communication
-                    .process_out_message(out_message::Msg::DeliverSnapshotResponse(
-                        deliver_snapshot_response,
-                    ))
-            }
-            _ => {
-                warn!(self.logger, "Unexpected message type {:?}", message);
-                Err(PalError::Internal)
-            }
+        // Witness replica only compacts its log, its snapshots carry no actor state.
+        if self.is_witness {
+            return Ok(snapshot_buffer.into_bytes());
         }
-    }
 
-    fn process_deliver_snapshot_response(
-        &mut self,
-        deliver_snapshot_response: DeliverSnapshotResponse,
-    ) -> Result<(), PalError> {
-        self.check_non_ephemeral()?;
-        let message =
-            self.communication
-                .process_in_message(in_message::Msg::DeliverSnapshotResponse(
-                    deliver_snapshot_response,
-                ))?;
+        // Snapshots saved in the schema version zero carry no header so that they
+        // remain readable by the actors unaware of the schema versions.
+        let schema_version = self.mut_core().schema().active_version();
+        let save_result = if schema_version > 0 {
+            write_schema_version_header(&mut snapshot_buffer, schema_version)
+        } else {
+            Ok(())
+        };
+        save_result
+            .and_then(|_| self.actor.on_save_snapshot_chunked(&mut snapshot_buffer))
+            .map_err(|e| {
+                error!(self.logger, "Failed to save actor state to snapshot: {}", e);
+                // Failure to save actor snapshot must lead to termination.
+                PalError::Actor
+            })?;
 
-        if message.is_none() {
+        Ok(snapshot_buffer.into_bytes())
+    }
+
+    fn maybe_create_raft_snapshot(&mut self) -> Result<(), PalError> {
+        if !self.raft.mut_store().should_snapshot(
+            self.raft_progress.applied_index,
+            &self.raft_progress.config_state,
+        ) && !self.should_compact_log()
+        {
             return Ok(());
         }
 
-        let message = message.unwrap();
-        match message {
-            in_message::Msg::DeliverSnapshotResponse(m) => {
-                match self.snapshot.mut_processor(self.instant) {
-                    SnapshotProcessorRole::Sender(sender) => {
-                        sender.process_response(m.sender_replica_id, m.delivery_id, Ok(m))
-                    }
-                    SnapshotProcessorRole::Receiver(_) => {
-                        warn!(
-                            self.logger,
-                            "Node is snapshot receiving mode, unexpected deliver snapshot response"
-                        );
-                    }
-                }
-                Ok(())
-            }
-            _ => {
-                warn!(self.logger, "Unexpected message type {:?}", message);
-                Err(PalError::Internal)
-            }
+        self.create_raft_snapshot()
+    }
+
+    fn should_compact_log(&mut self) -> bool {
+        let Some(compaction_policy) = &self.compaction_policy else {
+            return false;
+        };
+
+        let store = self.raft.mut_store();
+        let Ok(first_index) = store.first_index() else {
+            return false;
+        };
+        // Nothing to compact until entries in the log have been applied.
+        let compactable_entries =
+            (self.raft_progress.applied_index + 1).saturating_sub(first_index);
+        if compactable_entries == 0 {
+            return false;
         }
+
+        compaction_policy.should_compact(&LogStatus {
+            compactable_entries,
+            compactable_size: store.log_size(self.raft_progress.applied_index),
+            since_compaction: self.clock.instant().saturating_sub(self.compaction_instant),
+        })
     }
 
-    fn process_deliver_snapshot_failure(
-        &mut self,
-        deliver_snapshot_failure: DeliverSnapshotFailure,
-    ) -> Result<(), PalError> {
-        self.check_non_ephemeral()?;
-        match self.snapshot.mut_processor(self.instant) {
-            SnapshotProcessorRole::Sender(sender) => sender.process_response(
-                deliver_snapshot_failure.sender_replica_id,
-                deliver_snapshot_failure.delivery_id,
-                Err(SnapshotError::FailedDelivery),
-            ),
-            SnapshotProcessorRole::Receiver(_) => {
-                warn!(
+    fn create_raft_snapshot(&mut self) -> Result<(), PalError> {
+        let snapshot_data = self.save_actor_snapshot()?;
+
+        let applied_index = self.raft_progress.applied_index;
+        let config_state = self.raft_progress.config_state.clone();
+        self.raft
+            .mut_store()
+            .create_snapshot(applied_index, config_state, snapshot_data)
+            .map_err(|e| {
+                error!(self.logger, "Failed to save actor state to snapshot: {}", e);
+                // Failure to create Raft snapshot to storage snapshot must lead to termination.
+                PalError::Actor
+            })?;
+        self.compaction_instant = self.clock.instant();
+        self.core
+            .borrow()
+            .metrics()
+            .increment_counter(SNAPSHOT_CREATED, 1);
+
+        // The log has been compacted, let the host discard the updates preceding
+        // the snapshot.
+        self.stash_state_checkpoint()
+    }
+
+    fn stash_state_checkpoint(&mut self) -> Result<(), PalError> {
+        if !self.journal.enabled() {
+            return Ok(());
+        }
+
+        let store = self.raft.mut_store();
+        let checkpoint_result = store.initial_state().and_then(|raft_state| {
+            let first_index = store.first_index()?;
+            let last_index = store.last_index()?;
+            let entries = if first_index > last_index {
+                Vec::new()
+            } else {
+                store.entries(
+                    first_index,
+                    last_index + 1,
+                    u64::MAX,
+                    GetEntriesContext::empty(false),
+                )?
+            };
+            Ok((raft_state.hard_state, store.latest_snapshot(), entries))
+        });
+        let (hard_state, snapshot, entries) = checkpoint_result.map_err(|e| {
+            error!(
+                self.logger,
+                "Failed to read replica state checkpoint: {}", e
+            );
+            // Failure to read replica state from storage must lead to termination.
+            PalError::Raft
+        })?;
+
+        let checkpoint = self
+            .journal
+            .record(true, Some(&hard_state), Some(&snapshot), &entries)
+            .map_err(|e| {
+                error!(
                     self.logger,
-                    "Node is snapshot receiving mode, unexpected deliver snapshot failure"
+                    "Failed to record replica state checkpoint: {}", e
                 );
-            }
-        }
+                // Failure to record replica state must lead to termination.
+                PalError::Internal
+            })?;
+        self.stash_message(out_message::Msg::PersistReplicaState(checkpoint));
 
         Ok(())
     }
 
-    fn process_snapshot_progress(&mut self) {
-        if self.driver_state != DriverState::Started {
-            return;
-        }
+    fn advance_raft(&mut self) -> Result<(), PalError> {
+        // Given that instant only set once trigger Raft tick once as well.
+        self.trigger_raft_tick();
 
-        match self.snapshot.mut_processor(self.instant) {
-            SnapshotProcessorRole::Sender(sender) => {
-                if let Some((replica_id, snapshot_status)) = sender.try_complete() {
-                    self.raft.report_snapshot(replica_id, snapshot_status);
-                }
-            }
-            SnapshotProcessorRole::Receiver(receiver) => {
-                if let Some(snapshot_result) = receiver.try_complete() {
-                    match snapshot_result {
-                        Ok((sender_replica_id, snapshot)) => {
-                            let mut snapshot_message = create_raft_message(
-                                sender_replica_id,
-                                self.id,
-                                RaftMessageType::MsgSnapshot,
-                            );
-                            snapshot_message.set_snapshot(snapshot);
-                            if let Err(e) = self.raft.make_step(snapshot_message) {
-                                error!(self.logger, "Raft experienced unrecoverable error: {}", e);
-                            }
-                        }
-                        Err(_) => {
-                            warn!(
-                                self.logger,
-                                "Snapshot has been received but failed validation"
-                            );
-                        }
-                    }
-                }
-            }
+        if !self.raft.has_ready() {
+            // There is nothing process.
+            return Ok(());
         }
-    }
 
-    fn process_snapshot_sending(&mut self) -> Result<(), PalError> {
-        let snapshot_messages = mem::take(&mut self.snapshots);
+        let mut raft_ready = self.raft.get_ready();
+        self.collect_committed_index();
 
-        let mut out_messages: Vec<out_message::Msg> = Vec::new();
-        match self.snapshot.mut_processor(self.instant) {
-            SnapshotProcessorRole::Sender(sender) => {
-                for snapshot_message in snapshot_messages {
-                    sender.start(snapshot_message.to, snapshot_message.snapshot.unwrap());
-                }
+        // Send out messages to the peers.
+        self.send_raft_messages(raft_ready.take_messages());
 
-                while let Some(request) = sender.next_request() {
-                    out_messages.push(out_message::Msg::DeliverSnapshotRequest(request));
-                }
-            }
-            SnapshotProcessorRole::Receiver(_) => {
-                if !snapshot_messages.is_empty() {
-                    warn!(
-                        self.logger,
-                        "Unexpected snapshot sending while playing receiver role"
-                    );
-                    for snapshot_message in snapshot_messages {
-                        self.raft
-                            .report_snapshot(snapshot_message.to, RaftSnapshotStatus::Failure);
-                    }
-                }
-            }
+        // Record read indexes confirmed for the queries, these are served once applied.
+        for read_state in raft_ready.take_read_states() {
+            self.reads
+                .confirm(&read_state.request_ctx, read_state.index);
         }
 
-        for out_message in out_messages {
-            self.communication.process_out_message(out_message)?;
+        if let Some(raft_hard_state) = raft_ready.hard_state() {
+            // Persist changed hard state into the stable storage.
+            self.raft
+                .mut_store()
+                .set_hard_state(raft_hard_state.clone());
         }
 
-        Ok(())
-    }
-
-    fn process_deliver_app_message(
-        &mut self,
-        deliver_app_message: Option<DeliverAppMessage>,
-    ) -> Result<(), PalError> {
-        self.check_driver_started()?;
-
-        let message_outcome = self
-            .actor
-            .on_process_command(deliver_app_message.map(|m| ActorCommand {
-                correlation_id: m.correlation_id,
-                header: m.message_header,
-                payload: m.message_payload,
-            }))
-            .map_err(|e| {
-                error!(self.logger, "Failed to process actor command: {}", e);
-
-                // Failure to process actor command must lead to termination.
-                PalError::Actor
-            })?;
+        let mut snapshot = raft_ready.take_snapshot();
+        let entries = raft_ready.take_entries();
 
-        for actor_message in message_outcome.commands {
-            self.stash_message(out_message::Msg::DeliverAppMessage(DeliverAppMessage {
-                correlation_id: actor_message.correlation_id,
-                message_header: actor_message.header,
-                message_payload: actor_message.payload,
-            }));
+        // Let the host persist the changed state before it delivers the messages
+        // that depend on it. Persistence requests are sent out ahead of the peer
+        // messages as control messages.
+        if self.journal.enabled()
+            && (raft_ready.hard_state().is_some() || !snapshot.is_empty() || !entries.is_empty())
+        {
+            let update = self
+                .journal
+                .record(
+                    false,
+                    raft_ready.hard_state(),
+                    (!snapshot.is_empty()).then_some(&snapshot),
+                    &entries,
+                )
+                .map_err(|e| {
+                    error!(self.logger, "Failed to record replica state update: {}", e);
+                    // Failure to record replica state must lead to termination.
+                    PalError::Internal
+                })?;
+            self.stash_message(out_message::Msg::PersistReplicaState(update));
         }
 
-        if let Some(actor_event) = message_outcome.event {
-            if self.is_ephemeral {
-                // For ephemeral replica, apply the event immediately since it is not replicated.
-                let event_outcome = self
-                    .actor
-                    .on_apply_event(
-                        ActorEventContext {
-                            index: 0,
-                            owned: true,
-                        },
-                        actor_event,
-                    )
-                    .map_err(|e| {
-                        error!(self.logger, "Failed to apply event to actor state: {}", e);
-                        // Failure to apply event to actor state must lead to termination.
-                        PalError::Actor
-                    })?;
+        // If not empty persist snapshot to stable storage and apply it to the
+        // actor.
+        self.restore_raft_snapshot(&mut snapshot)?;
 
-                for actor_command in event_outcome.commands {
-                    self.stash_message(out_message::Msg::DeliverAppMessage(DeliverAppMessage {
-                        correlation_id: actor_command.correlation_id,
-                        message_header: actor_command.header,
-                        message_payload: actor_command.payload,
-                    }));
-                }
-            } else {
-                let entry = create_entry(
-                    EntryId {
-                        entry_id: actor_event.correlation_id,
-                        replica_id: self.id,
-                    },
-                    actor_event.contents,
+        // Apply committed entries to the actor state machine.
+        self.apply_raft_committed_entries(raft_ready.take_committed_entries())?;
+        // Send out messages that had to await the persistence of the hard state, entries
+        // and snapshot to the stable storage.
+        self.send_raft_messages(raft_ready.take_persisted_messages());
+
+        if !entries.is_empty() {
+            // Persist unstable entries into the stable storage.
+            let append_result = self.raft.mut_store().append_entries(entries);
+            if let Err(e) = append_result {
+                error!(
+                    self.logger,
+                    "Failed to append Raft entries to storage: {}", e
                 );
-                self.mut_core()
-                    .append_proposal(entry.encode_to_vec().into())
+                // Failure to append Raft entries to storage snapshot must lead to termination.
+                return Err(PalError::Actor);
             }
         }
 
+        // Advance Raft state after fully processing ready.
+        let mut light_raft_ready = self.raft.advance_ready(raft_ready);
+
+        // Send out messages to the peers.
+        self.send_raft_messages(light_raft_ready.take_messages());
+        // Apply all committed entries.
+        self.apply_raft_committed_entries(light_raft_ready.take_committed_entries())?;
+        // Advance the apply index.
+        self.raft.advance_apply();
+        // Leader commits the entries acknowledged while the ready was processed.
+        self.collect_committed_index();
+
         Ok(())
     }
 
-    fn process_get_replica_state(
-        &mut self,
-        _get_replica_state_request: &GetReplicaStateRequest,
-    ) -> Result<(), PalError> {
-        self.check_driver_started()?;
+    fn collect_committed_index(&mut self) {
+        // Commit index is taken from the Raft log, as the hard state is only
+        // reported when it changes and lags the commits made while advancing.
+        self.raft_progress.committed_index = self.raft.committed_index();
+    }
 
-        if self.is_ephemeral {
-            self.stash_message(out_message::Msg::GetReplicaState(
-                GetReplicaStateResponse::default(),
-            ));
-        } else {
-            let latest_snapshot_size = self.raft.mut_store().latest_snapshot_size();
-            self.stash_message(out_message::Msg::GetReplicaState(GetReplicaStateResponse {
-                applied_index: self.raft_progress.applied_index,
-                latest_snapshot_size,
-            }));
+    fn adjust_flow_control(&mut self) {
+        if !self.flow_control.enabled() || !self.check_raft_leadership() {
+            return;
         }
 
-        Ok(())
+        // Shrink windows of the slow peers and restore windows of the recovered ones.
+        let instant = self.clock.instant();
+        for (replica_id, max_inflight_msgs) in self.flow_control.take_adjustments(instant) {
+            debug!(
+                self.logger,
+                "Adjusting max inflight messages for replica {} to {}",
+                replica_id,
+                max_inflight_msgs
+            );
+            self.raft
+                .adjust_max_inflight_msgs(replica_id, max_inflight_msgs);
+        }
     }
 
-    fn process_secure_channel_handshake(
-        &mut self,
-        secure_channel_handshake: SecureChannelHandshake,
-    ) -> Result<(), PalError> {
-        self.check_driver_started()?;
+    fn reset_leader_state(&mut self) {
+        self.prev_raft_state = RaftState::new();
+    }
 
-        self.communication
-            .process_in_message(in_message::Msg::SecureChannelHandshake(
-                secure_channel_handshake,
-            ))?;
-        Ok(())
+    fn collect_config_state(&mut self, config_state: RaftConfigState) {
+        self.raft_progress.config_state = config_state;
     }
 
-    fn process_actor_raft_proposals(&mut self) {
-        let proposals = self.mut_core().take_outputs();
+    fn stash_leader_state(&mut self) {
+        self.raft_state = self.get_raft_state();
 
-        for proposal in proposals {
-            self.make_raft_proposal(proposal);
+        // Changes of the cluster config alone keep the progress made under the leader.
+        let leader = (
+            self.raft_state.leader_replica_id,
+            self.raft_state.leader_term,
+        );
+        if self.progress_leader != leader {
+            self.progress_leader = leader;
+            self.reset_leader_progress();
         }
-    }
 
-    fn process_state_machine(&mut self) -> Result<(), PalError> {
-        if self.raft.initialized() {
-            // Advance Raft internal state.
-            self.advance_raft()?;
+        if self.prev_raft_state == self.raft_state {
+            return;
+        }
 
-            // Maybe create a snashot of the actor to reduce the size of the log.
-            self.maybe_create_raft_snapshot()?;
+        self.prev_raft_state = self.raft_state.clone();
 
-            // If the leader state has changed send it out for observability.
-            self.stash_leader_state();
-        }
+        // Give the replicas the full timeout to respond to the new leader or config.
+        self.failure_detector.reset(self.clock.instant());
 
-        Ok(())
-    }
+        // Update snapshot processor with the latest raft cluster state.
+        self.update_snapshot_cluster_change();
 
-    fn take_out_messages(&mut self) -> Vec<OutMessage> {
-        // Take messages to be sent out.
-        mem::take(&mut self.messages)
-    }
+        // Update communication module with the latest raft cluster state.
+        self.communication
+            .process_cluster_change(&self.raft_state.committed_cluster_members());
 
-    fn stash_log_entries(&mut self) {
-        for log_message in self.logger_output.take_entries() {
-            self.stash_message(out_message::Msg::Log(log_message));
-        }
+        // Sent out cluster check message with the update.
+        self.stash_message(out_message::Msg::CheckCluster(CheckClusterResponse {
+            leader_replica_id: self.raft_state.leader_replica_id,
+            leader_term: self.raft_state.leader_term,
+            cluster_replica_ids: self.raft_state.committed_cluster_config.clone(),
+            has_pending_changes: self.raft_state.has_pending_change,
+            cluster_learner_ids: self.raft_state.committed_cluster_learners.clone(),
+            outgoing_replica_ids: self.raft_state.committed_cluster_outgoing_config.clone(),
+        }));
     }
 
-    fn stash_comms_module_entries(&mut self) {
-        self.messages
-            .append(&mut self.communication.take_out_messages());
-    }
+    // Fails the work started under the leader the replica has known, once that
+    // leader steps down or a new term starts.
+    fn reset_leader_progress(&mut self) {
+        // Proposals made before the leadership change may have been dropped and will
+        // never be applied, stop waiting for them to not block the mailbox forever.
+        // The queued proposals are yet to be made.
+        self.raft_progress.pending_proposals = self
+            .queued_proposals
+            .iter()
+            .map(|(_, correlation_ids)| correlation_ids.len() as u64)
+            .sum();
+        let abandoned_events = mem::take(&mut self.proposed_events);
+        if !abandoned_events.is_empty() {
+            warn!(
+                self.logger,
+                "Failing {} proposals made before the leadership change",
+                abandoned_events.len()
+            );
+        }
+        // The proposals may still get applied, in which case their outcomes are
+        // not sent out.
+        for correlation_id in &abandoned_events {
+            self.fail_app_message(*correlation_id, String::new(), StatusCode::Unavailable);
+        }
+        self.abandoned_events = abandoned_events;
 
-    fn stash_message(&mut self, message: out_message::Msg) {
-        self.messages.push(OutMessage { msg: Some(message) });
+        // Queries started before the leadership change may never be confirmed either.
+        let dropped_reads = self.reads.reset();
+        if !dropped_reads.is_empty() {
+            warn!(
+                self.logger,
+                "Failing {} unconfirmed queries",
+                dropped_reads.len()
+            );
+        }
+        for read_command in dropped_reads {
+            self.fail_app_message(
+                read_command.correlation_id,
+                read_command.route,
+                StatusCode::Unavailable,
+            );
+        }
+
+        // Timers proposed to be fired by the previous leader may never be applied,
+        // they are proposed again once due.
+        let forgotten_timers = self.mut_core().timers().reset_proposed();
+        if forgotten_timers > 0 {
+            warn!(
+                self.logger,
+                "Proposing {} timers again after the leadership change", forgotten_timers
+            );
+        }
+
+        // Neither may the snapshot schema reports and activations.
+        self.mut_core().schema().reset_proposed();
+
+        // Nor the tombstones, the new leader proposes them for the entries skipped so far.
+        self.proposed_tombstone_index = 0;
+
+        // Latency and rejections observed by the previous leader are no longer relevant,
+        // restore the windows shrunk based on them.
+        for (replica_id, max_inflight_msgs) in self.flow_control.reset() {
+            self.raft
+                .adjust_max_inflight_msgs(replica_id, max_inflight_msgs);
+        }
+        self.probe_backoff.reset();
     }
 
-    fn stash_snapshot(&mut self, snapshot_message: RaftMessage) {
-        self.snapshots.push(snapshot_message);
+    // Lets the consumer know that the application message has failed.
+    fn fail_app_message(&mut self, correlation_id: u64, route: String, status_code: StatusCode) {
+        self.stash_message(out_message::Msg::DeliverAppMessage(DeliverAppMessage {
+            correlation_id,
+            message_header: Bytes::new(),
+            message_payload: Bytes::new(),
+            route,
+            status_code: status_code as i32,
+        }));
     }
-}
 
-impl<
-        R: Raft<S = S>,
-        S: Store + RaftStorage,
-        P: SnapshotProcessor,
-        A: Actor,
-        C: CommunicationModule,
-    > Application for Driver<R, S, P, A, C>
-{
-    /// Handles messages received from the trusted host.
-    fn receive_message(
+    fn preset_state_machine(
         &mut self,
-        host: &mut impl Host,
         instant: u64,
-        opt_message: Option<InMessage>,
-    ) -> Result<(), PalError> {
-        // Update state of the context that will remain unchanged while messages are
-        // dispatched for processing.
-        self.preset_state_machine(instant);
+        wall_time: Option<u64>,
+        signed_wall_time: Option<SignedTimestamp>,
+    ) {
+        self.prev_raft_state = self.raft_state.clone();
+        self.clock.observe_host_instant(instant);
+        self.clock.observe_host_wall_time(wall_time);
+        self.clock
+            .observe_signed_wall_time(signed_wall_time.as_ref());
+        self.preset_wall_time();
+        let leader = self.check_raft_leadership();
+        self.mut_core().set_state(leader);
+        self.update_context_progress();
+    }
 
-        let mut deliver_app_message_opt = None;
-        // Dispatch incoming message for processing.
-        if let Some(deserialized_message) = opt_message {
-            match deserialized_message.msg {
-                None => {
-                    warn!(self.logger, "Rejecting message: unknown");
-                    // Ignore unknown message.
-                    return Ok(());
-                }
-                Some(mut message) => {
-                    match message {
-                        in_message::Msg::StartReplica(ref mut start_node_request) => {
-                            self.process_start_node(start_node_request, host.public_signing_key())
-                        }
-                        in_message::Msg::StopReplica(ref stop_node_request) => {
-                            self.process_stop_node(stop_node_request)
-                        }
-                        in_message::Msg::ChangeCluster(ref change_cluster_request) => {
-                            self.process_change_cluster(change_cluster_request)
-                        }
-                        in_message::Msg::CheckCluster(ref check_cluster_request) => {
-                            self.process_check_cluster(check_cluster_request)
-                        }
-                        in_message::Msg::DeliverSystemMessage(deliver_system_message) => {
-                            self.process_deliver_system_message(deliver_system_message)
-                        }
-                        in_message::Msg::DeliverSnapshotRequest(deliver_snapshot_request) => {
-                            self.process_deliver_snapshot_request(deliver_snapshot_request)
-                        }
-                        in_message::Msg::DeliverSnapshotResponse(deliver_snapshot_response) => {
-                            self.process_deliver_snapshot_response(deliver_snapshot_response)
-                        }
-                        in_message::Msg::DeliverSnapshotFailure(deliver_snapshot_failure) => {
-                            self.process_deliver_snapshot_failure(deliver_snapshot_failure)
-                        }
-                        in_message::Msg::GetReplicaState(ref get_replica_state_request) => {
-                            self.process_get_replica_state(get_replica_state_request)
-                        }
-                        in_message::Msg::SecureChannelHandshake(secure_channel_handshake) => {
-                            self.process_secure_channel_handshake(secure_channel_handshake)
-                        }
-                        in_message::Msg::DeliverAppMessage(deliver_app_message) => {
-                            deliver_app_message_opt = Some(deliver_app_message);
-                            Ok(())
-                        }
-                    }?;
-                }
-            };
-        }
-        if self.driver_state == DriverState::Started {
-            self.process_deliver_app_message(deliver_app_message_opt)?;
-            self.communication.make_tick();
+    fn preset_wall_time(&mut self) {
+        let wall_time = self.clock.wall_time();
+        let accepted_wall_time = self.wall_clock.validate(self.clock.instant(), wall_time);
+        if wall_time.is_some() && accepted_wall_time.is_none() {
+            warn!(
+                self.logger,
+                "Rejecting wall clock time {:?}: skewed against monotonic clock", wall_time
+            );
         }
+        self.mut_core().set_wall_time(accepted_wall_time);
+    }
 
-        if !self.is_ephemeral {
-            // Processes outputs from the actor to make raft proposals.
-            self.process_actor_raft_proposals();
+    fn update_context_progress(&mut self) {
+        let applied_index = self.raft_progress.applied_index;
+        let committed_index = self.raft_progress.committed_index.max(applied_index);
+        self.mut_core().set_progress(applied_index, committed_index);
+    }
 
-            // Process snapshot transfer completion or failures.
-            self.process_snapshot_progress();
+    fn check_follower_read(&self) -> bool {
+        let follower_read_config = &self.driver_config.follower_read_config;
+        if !follower_read_config.enabled || self.check_raft_leadership() {
+            return false;
+        }
 
-            // Advance the Raft and collect results messages.
-            self.process_state_machine()?;
+        // Follower state is only served if it is known to be recent enough.
+        let instant = self.clock.instant();
+        let leader_contact =
+            self.raft_progress
+                .leader_contact_instant
+                .is_some_and(|contact_instant| {
+                    instant.saturating_sub(contact_instant)
+                        <= follower_read_config.max_leader_silence
+                });
+        let index_lag = self
+            .raft_progress
+            .committed_index
+            .saturating_sub(self.raft_progress.applied_index);
+
+        leader_contact && index_lag <= follower_read_config.max_index_lag
+    }
 
-            // Initiate if needed snapshot sending.
-            self.process_snapshot_sending()?;
+    fn check_driver_state(&self, state: DriverState) -> Result<(), PalError> {
+        if self.driver_state != state {
+            return Err(PalError::InvalidOperation);
         }
 
-        self.stash_log_entries();
-        self.stash_comms_module_entries();
+        Ok(())
+    }
 
-        // Send messages to Raft peers and consumers through the trusted host.
-        host.send_messages(self.take_out_messages());
+    fn check_driver_started(&self) -> Result<(), PalError> {
+        self.check_driver_state(DriverState::Started)
+    }
 
+    fn check_non_ephemeral(&self) -> Result<(), PalError> {
+        if self.is_ephemeral {
+            return Err(PalError::InvalidOperation);
+        }
         Ok(())
     }
-}
 
-#[cfg(all(test, feature = "std"))]
-mod test {
-    extern crate mockall;
-    extern crate spin;
+    fn initialize_driver(&mut self, _app_signing_key: Vec<u8>, replica_id_hint: u64) {
+        self.id = replica_id_hint;
+        self.logger = self.logger.new(o!("raft_id" => self.id));
+    }
 
-    use crate::{
-        consensus::{RaftLightReady, RaftReady},
-        mock::{MockSnapshotReceiver, MockSnapshotSender},
-        model::{CommandOutcome, EventOutcome},
-        snapshot::DefaultSnapshotProcessor,
-        util::raft::{
-            create_empty_raft_entry, create_entry_id, create_raft_config_state, create_raft_entry,
-            create_raft_message, create_raft_snapshot, create_raft_snapshot_metadata,
-            serialize_config_change,
-        },
-    };
+    fn process_start_node(
+        &mut self,
+        start_replica_request: &mut StartReplicaRequest,
+        app_signing_key: Vec<u8>,
+    ) -> Result<(), PalError> {
+        self.check_driver_state(DriverState::Created)?;
 
-    use self::mockall::predicate::{always, eq};
-    use super::*;
-    use mock::{MockActor, MockCommunicationModule, MockHost, MockRaft, MockStore};
-    use model::ActorError;
-    use raft::eraftpb::{
-        ConfChange as RaftConfigChange, EntryType as RaftEntryType, MessageType as RaftMessageType,
-    };
-    use tcp_proto::runtime::endpoint::raft_config::SnapshotConfig;
+        self.initialize_driver(app_signing_key, start_replica_request.replica_id_hint);
 
-    const REPLICA_1: u64 = 1;
-    const REPLICA_2: u64 = 2;
-    const REPLICA_3: u64 = 3;
+        let id = self.id;
+        let app_config = mem::take(&mut start_replica_request.app_config);
+        self.mut_core().set_immutable_state(id, app_config);
 
-    fn create_default_parameters() -> (u64, u64, RaftConfig) {
-        let node_id = REPLICA_1;
-        let instant = 100;
+        self.is_witness = start_replica_request.is_witness;
+        if self.is_witness
+            && (start_replica_request.is_ephemeral || start_replica_request.is_leader)
+        {
+            error!(
+                self.logger,
+                "Witness replica can be neither ephemeral nor started as the leader"
+            );
+            return Err(PalError::InvalidOperation);
+        }
 
-        let raft_config = RaftConfig {
-            tick_period: 100,
-            election_tick: 20,
-            heartbeat_tick: 2,
-            max_size_per_msg: 0,
-            snapshot_config: Some(SnapshotConfig {
-                snapshot_count: 10,
-                chunk_size: 20,
-                max_pending_chunks: 2,
-            }),
-            handshake_retry_tick: 1,
+        // Witness replica has no actor state, hence it supports any snapshot schema.
+        let supported_version = if self.is_witness {
+            u32::MAX
+        } else {
+            self.actor.snapshot_schema_version()
         };
+        self.mut_core().schema().configure(supported_version);
+        if start_replica_request.is_ephemeral {
+            // Ephemeral replica state is not replicated, hence there is nothing to agree upon.
+            self.mut_core().schema().load(supported_version);
+        }
 
-        (node_id, instant, raft_config)
-    }
-
-    fn create_default_raft_state(node_id: u64) -> RaftState {
-        RaftState {
-            leader_replica_id: node_id,
-            leader_term: 1,
-            committed_cluster_config: vec![node_id],
-            has_pending_change: false,
+        if let Some(sealed_storage) = &self.sealed_storage {
+            sealed_storage
+                .load(&start_replica_request.sealed_secrets)
+                .map_err(|e| {
+                    error!(self.logger, "Failed to unseal secrets: {:?}", e);
+                    e
+                })?;
         }
-    }
 
-    fn create_raft_state(leader_replica_id: u64, committed_cluster_config: Vec<u64>) -> RaftState {
-        RaftState {
-            leader_replica_id,
-            leader_term: 1,
-            committed_cluster_config,
-            has_pending_change: false,
+        // Witness replica has no actor state to protect and hence no use for the
+        // cluster secret.
+        if let Some(raft_config) = &start_replica_request.raft_config
+            && raft_config.share_cluster_secret
+            && !self.is_witness
+        {
+            self.driver_config.share_cluster_secret = true;
+            self.initialize_cluster_secret(start_replica_request)?;
         }
-    }
 
-    fn create_start_replica_request(
-        raft_config: RaftConfig,
-        leader: bool,
-        replica_id_hint: u64,
-        app_config: Bytes,
-    ) -> InMessage {
-        let envelope = InMessage {
-            msg: Some(in_message::Msg::StartReplica(StartReplicaRequest {
-                is_leader: leader,
-                replica_id_hint,
-                raft_config: Some(raft_config),
-                app_config: app_config,
-                attestation_config: None,
-                is_ephemeral: false,
-            })),
-        };
-        envelope
-    }
+        // Witness replica has no actor state and hence the actor is never initialized.
+        if !self.is_witness {
+            let actor_context = Box::new(DriverContext::new(
+                Rc::clone(&self.core),
+                Rc::clone(&self.clock),
+                self.logger.new(o!("type" => "actor")),
+            ));
 
-    fn create_start_replica_response(replica_id: u64) -> out_message::Msg {
-        out_message::Msg::StartReplica(StartReplicaResponse { replica_id })
-    }
+            self.actor.on_init(actor_context).map_err(|e| {
+                error!(self.logger, "Failed to initialize actor: {}", e);
 
-    fn create_stop_replica_request() -> InMessage {
-        let envelope = InMessage {
-            msg: Some(in_message::Msg::StopReplica(StopReplicaRequest {})),
-        };
-        envelope
-    }
+                // Failure to initialize actor must lead to termination.
+                PalError::Actor
+            })?;
+        }
+        self.is_ephemeral = start_replica_request.is_ephemeral;
 
-    fn create_stop_replica_response() -> out_message::Msg {
-        out_message::Msg::StopReplica(StopReplicaResponse {})
-    }
+        // Restore the actor state retained out of band before the new cluster is
+        // bootstrapped with it.
+        if let Some(imported_snapshot) = start_replica_request.imported_snapshot.take() {
+            if !start_replica_request.is_leader || start_replica_request.recovery_state.is_some() {
+                error!(
+                    self.logger,
+                    "Imported snapshot can only bootstrap a new cluster from the leader"
+                );
+                return Err(PalError::InvalidOperation);
+            }
+            self.import_actor_snapshot(imported_snapshot)?;
+        }
 
-    fn create_in_deliver_app_message(correlation_id: u64, message_header: Bytes) -> InMessage {
-        let envelope = InMessage {
-            msg: Some(in_message::Msg::DeliverAppMessage(DeliverAppMessage {
-                correlation_id,
-                message_header,
-                message_payload: Bytes::new(),
-            })),
-        };
-        envelope
-    }
+        // Initialize Raft and Snapshot only for non-ephemeral nodes.
+        if !self.is_ephemeral {
+            let snapshot = self.save_actor_snapshot()?;
 
-    fn create_out_deliver_app_message(
-        correlation_id: u64,
-        message_header: Bytes,
-    ) -> out_message::Msg {
-        out_message::Msg::DeliverAppMessage(DeliverAppMessage {
-            correlation_id,
-            message_header,
-            message_payload: Bytes::new(),
-        })
-    }
+            // Initialize snapshot processor.
+            let snapshot_config = match &start_replica_request.raft_config {
+                Some(raft_config) => &raft_config.snapshot_config,
+                None => &None,
+            };
+            self.snapshot.init(
+                self.logger.new(o!("type" => "snapshot")),
+                self.id,
+                snapshot_config,
+            );
 
-    fn create_change_cluster_request(replica_id: u64, change_type: ChangeClusterType) -> InMessage {
-        let envelope = InMessage {
-            msg: Some(in_message::Msg::ChangeCluster(ChangeClusterRequest {
-                change_id: 1,
-                replica_id,
-                change_type: change_type.into(),
-            })),
-        };
-        envelope
-    }
+            self.initialize_raft_node(
+                &start_replica_request.raft_config,
+                snapshot,
+                start_replica_request.is_leader,
+                start_replica_request.recovery_state.take(),
+            )?;
+        }
 
-    fn create_change_cluster_response(change_status: ChangeClusterStatus) -> out_message::Msg {
-        out_message::Msg::ChangeCluster(ChangeClusterResponse {
-            change_id: 1,
-            change_status: change_status.into(),
-        })
-    }
+        if let Some(raft_config) = &start_replica_request.raft_config
+            && let Some(message_priority_config) = &raft_config.message_priority_config
+        {
+            self.set_message_budgets(message_priority_config);
+        }
 
-    fn create_check_cluster_request() -> InMessage {
-        let envelope = InMessage {
-            msg: Some(in_message::Msg::CheckCluster(CheckClusterRequest {})),
-        };
-        envelope
-    }
+        if let Some(raft_config) = &start_replica_request.raft_config
+            && let Some(mailbox_config) = &raft_config.mailbox_config
+        {
+            self.mailbox.configure(
+                mailbox_config.capacity as usize,
+                mailbox_config.shed_policy(),
+            );
+            self.driver_config.max_pending_proposals = mailbox_config.max_pending_proposals as u64;
+            self.driver_config.max_state_size = mailbox_config.max_state_size;
+        }
 
-    fn create_check_cluster_response(raft_state: &RaftState) -> out_message::Msg {
-        out_message::Msg::CheckCluster(CheckClusterResponse {
-            leader_replica_id: raft_state.leader_replica_id,
-            leader_term: raft_state.leader_term,
-            cluster_replica_ids: raft_state.committed_cluster_config.clone(),
-            has_pending_changes: raft_state.has_pending_change,
-        })
-    }
+        if let Some(raft_config) = &start_replica_request.raft_config {
+            self.wall_clock.configure(raft_config.max_wall_clock_skew);
+        }
 
-    fn create_get_replica_state_request() -> InMessage {
-        let envelope = InMessage {
-            msg: Some(in_message::Msg::GetReplicaState(GetReplicaStateRequest {})),
-        };
-        envelope
-    }
+        if let Some(raft_config) = &start_replica_request.raft_config
+            && let Some(log_config) = &raft_config.log_config
+        {
+            self.log_sink.configure(log_config);
+        }
 
-    fn create_get_replica_state_response(
-        applied_index: u64,
-        latest_snapshot_size: u64,
-    ) -> out_message::Msg {
-        out_message::Msg::GetReplicaState(GetReplicaStateResponse {
-            applied_index,
-            latest_snapshot_size,
-        })
-    }
+        if let Some(raft_config) = &start_replica_request.raft_config
+            && let Some(metrics_config) = &raft_config.metrics_config
+        {
+            self.driver_config.metrics_report_period = metrics_config.report_period;
+            self.metrics_instant = self.clock.instant();
+        }
 
-    fn create_secure_channel_handshake(
-        sender_replica_id: u64,
-        recipient_replica_id: u64,
-    ) -> SecureChannelHandshake {
-        SecureChannelHandshake {
-            recipient_replica_id,
-            sender_replica_id,
-            encryption: None,
+        if let Some(raft_config) = &start_replica_request.raft_config
+            && let Some(proposal_batch_config) = &raft_config.proposal_batch_config
+        {
+            self.batcher.configure(
+                proposal_batch_config.max_batch_proposals as usize,
+                proposal_batch_config.max_batch_size,
+                proposal_batch_config.max_batch_delay,
+            );
         }
-    }
 
-    fn create_deliver_system_message_request(raft_message: &RaftMessage) -> InMessage {
-        let envelope = InMessage {
-            msg: Some(in_message::Msg::DeliverSystemMessage(
-                DeliverSystemMessage {
-                    recipient_replica_id: raft_message.to,
-                    sender_replica_id: raft_message.from,
-                    message_contents: serialize_raft_message(raft_message).unwrap(),
-                },
-            )),
+        let communication_config = match &start_replica_request.raft_config {
+            Some(raft_config) => Some(CommunicationConfig {
+                handshake_retry_tick: raft_config.handshake_retry_tick,
+                rekey_message_count: raft_config
+                    .rekey_config
+                    .as_ref()
+                    .map_or(0, |rekey_config| rekey_config.max_messages),
+                rekey_tick: raft_config
+                    .rekey_config
+                    .as_ref()
+                    .map_or(0, |rekey_config| rekey_config.max_ticks),
+            }),
+            None => None,
         };
-        envelope
-    }
 
-    fn create_deliver_system_message_response(raft_message: &RaftMessage) -> out_message::Msg {
-        out_message::Msg::DeliverSystemMessage(DeliverSystemMessage {
-            recipient_replica_id: raft_message.to,
-            sender_replica_id: raft_message.from,
-            message_contents: serialize_raft_message(raft_message).unwrap(),
-        })
-    }
+        self.communication.init(
+            self.id,
+            self.logger.new(o!("type" => "communication")),
+            communication_config,
+        );
 
-    fn create_send_messages_matcher(
-        expected: Vec<out_message::Msg>,
-    ) -> impl Fn(&Vec<OutMessage>) -> bool {
-        move |envelopes: &Vec<OutMessage>| {
-            let actual: Vec<out_message::Msg> = envelopes
-                .iter()
-                .map(|m| m.msg.clone().unwrap())
-                .filter(|m| {
-                    if let out_message::Msg::Log(_) = m {
-                        return false;
-                    };
-                    true
-                })
-                .collect();
-            expected.iter().all(|e| actual.contains(e)) && expected.len() == actual.len()
+        if let Some(raft_config) = &start_replica_request.raft_config
+            && let Some(attestation_policy) = &raft_config.attestation_policy
+        {
+            self.communication
+                .update_attestation_policy(attestation_policy);
         }
-    }
 
-    fn wrap_deliver_snapshot_request_out(request: DeliverSnapshotRequest) -> out_message::Msg {
-        out_message::Msg::DeliverSnapshotRequest(request)
+        self.driver_state = DriverState::Started;
+
+        self.stash_message(out_message::Msg::StartReplica(StartReplicaResponse {
+            replica_id: self.id,
+        }));
+
+        Ok(())
     }
 
-    fn wrap_deliver_snapshot_request_in(request: DeliverSnapshotRequest) -> InMessage {
-        InMessage {
-            msg: Some(in_message::Msg::DeliverSnapshotRequest(request)),
+    // Restores the cluster secret from the sealed storage. Otherwise the replica
+    // that bootstraps a new cluster generates it and the other replicas request
+    // it from their peers. The replica restarting from its persisted state must
+    // restore the secret along with it since the recovered entries are applied
+    // before the peers are reached.
+    fn initialize_cluster_secret(
+        &mut self,
+        start_replica_request: &StartReplicaRequest,
+    ) -> Result<(), PalError> {
+        let sealed_secret = self
+            .sealed_storage
+            .as_ref()
+            .and_then(|sealed_storage| sealed_storage.get(CLUSTER_SECRET_NAME));
+        if let Some(sealed_secret) = sealed_secret {
+            let cluster_secret = ClusterSecret::from_bytes(&sealed_secret).map_err(|e| {
+                error!(self.logger, "Failed to restore cluster secret: {:?}", e);
+                PalError::Internal
+            })?;
+            self.set_cluster_secret(cluster_secret);
+        } else if start_replica_request.recovery_state.is_some() {
+            error!(
+                self.logger,
+                "Cluster secret must be restored along with the replica state"
+            );
+            return Err(PalError::InvalidOperation);
+        } else if start_replica_request.is_leader || start_replica_request.is_ephemeral {
+            let cluster_secret = ClusterSecret::generate();
+            self.seal_cluster_secret(&cluster_secret)?;
+            self.set_cluster_secret(cluster_secret);
+        } else {
+            self.communication.request_cluster_secret();
         }
+        Ok(())
     }
 
-    fn create_deliver_snapshot_request(
-        recipient_id: u64,
-        sender_id: u64,
-        delivery_id: u64,
-    ) -> DeliverSnapshotRequest {
-        DeliverSnapshotRequest {
-            recipient_replica_id: recipient_id,
-            sender_replica_id: sender_id,
-            delivery_id,
-            payload_contents: vec![4, 5, 6, 7, 8, 9].into(),
+    // Restores the key the persisted updates are authenticated with from the
+    // sealed storage, otherwise generates it for the new replica. The key is kept
+    // out of reach of the host, hence the replica state can only be persisted
+    // with the sealed storage.
+    fn initialize_journal_key(&self, recovering: bool) -> Result<Vec<u8>, PalError> {
+        let Some(sealed_storage) = &self.sealed_storage else {
+            error!(
+                self.logger,
+                "Replica state can only be persisted with the sealed storage"
+            );
+            return Err(PalError::InvalidOperation);
+        };
+        if let Some(journal_key) = sealed_storage.get(JOURNAL_KEY_NAME) {
+            return Ok(journal_key);
+        }
+        if recovering {
+            error!(
+                self.logger,
+                "Journal key must be restored along with the replica state"
+            );
+            return Err(PalError::InvalidOperation);
         }
+        let journal_key = generate_journal_key();
+        sealed_storage.put(JOURNAL_KEY_NAME, &journal_key)?;
+        Ok(journal_key)
     }
 
-    fn wrap_deliver_snapshot_response_out(response: DeliverSnapshotResponse) -> out_message::Msg {
-        out_message::Msg::DeliverSnapshotResponse(response)
+    // Encrypts the seed replicated along with the entry under the key derived from
+    // the cluster secret, if shared. Otherwise the seed is replicated in plaintext.
+    fn encrypt_random_seed(&self, random_seed: Bytes) -> Result<Bytes, PalError> {
+        let Some(key) = self.core.borrow().cluster_key(RANDOM_SEED_KEY_CONTEXT) else {
+            return Ok(random_seed);
+        };
+        encrypt_seed(&key, &random_seed).map_err(|e| {
+            error!(self.logger, "Failed to encrypt random seed: {}", e);
+            PalError::Internal
+        })
     }
 
-    fn wrap_deliver_snapshot_response_in(response: DeliverSnapshotResponse) -> InMessage {
-        InMessage {
-            msg: Some(in_message::Msg::DeliverSnapshotResponse(response)),
+    // Decrypts the seed replicated along with the entry.
+    fn decrypt_random_seed(&self, random_seed: &Bytes) -> Result<Vec<u8>, PalError> {
+        if random_seed.is_empty() {
+            return Ok(Vec::new());
         }
+        let Some(key) = self.core.borrow().cluster_key(RANDOM_SEED_KEY_CONTEXT) else {
+            return Ok(random_seed.to_vec());
+        };
+        decrypt_seed(&key, random_seed).map_err(|e| {
+            error!(self.logger, "Failed to decrypt random seed: {}", e);
+            // Entry that can't be applied consistently must lead to termination.
+            PalError::Raft
+        })
     }
 
-    fn create_deliver_snapshot_response(
-        recipient_id: u64,
-        sender_id: u64,
-        delivery_id: u64,
-    ) -> DeliverSnapshotResponse {
-        DeliverSnapshotResponse {
-            recipient_replica_id: recipient_id,
-            sender_replica_id: sender_id,
-            delivery_id,
-            payload_contents: vec![6, 7, 8, 9].into(),
-        }
+    fn set_cluster_secret(&mut self, cluster_secret: ClusterSecret) {
+        self.communication
+            .set_cluster_secret(cluster_secret.as_bytes().to_vec());
+        self.mut_core().set_cluster_secret(cluster_secret);
     }
 
-    fn wrap_deliver_snapshot_failure_in(failure: DeliverSnapshotFailure) -> InMessage {
-        InMessage {
-            msg: Some(in_message::Msg::DeliverSnapshotFailure(failure)),
+    // Keeps the cluster secret in the sealed storage so that the replica
+    // restarted from its persisted state can restore it.
+    fn seal_cluster_secret(&self, cluster_secret: &ClusterSecret) -> Result<(), PalError> {
+        match &self.sealed_storage {
+            Some(sealed_storage) => {
+                sealed_storage.put(CLUSTER_SECRET_NAME, cluster_secret.as_bytes())
+            }
+            None => Ok(()),
         }
     }
 
-    fn create_deliver_snapshot_failure(sender_id: u64, delivery_id: u64) -> DeliverSnapshotFailure {
-        DeliverSnapshotFailure {
-            sender_replica_id: sender_id,
-            delivery_id,
+    // Adopts the cluster secret once received from a peer.
+    fn accept_cluster_secret(&mut self) -> Result<(), PalError> {
+        if !self.driver_config.share_cluster_secret {
+            return Ok(());
+        }
+        let Some(received_secret) = self.communication.take_cluster_secret() else {
+            return Ok(());
+        };
+        match ClusterSecret::from_bytes(&received_secret) {
+            Ok(cluster_secret) => {
+                info!(self.logger, "Received cluster secret");
+                self.seal_cluster_secret(&cluster_secret)?;
+                self.set_cluster_secret(cluster_secret);
+            }
+            Err(e) => {
+                warn!(self.logger, "Rejected cluster secret: {:?}", e);
+                self.communication.request_cluster_secret();
+            }
         }
+        Ok(())
     }
 
-    const DELIVERY_1: u64 = 1;
-    const DELIVERY_2: u64 = 2;
-
-    struct MockHostBuilder {
-        mock_host: MockHost,
+    // Indicates if the replica must not process the Raft messages and snapshots
+    // as it doesn't hold the cluster secret the actor state is protected with.
+    fn awaits_cluster_secret(&self) -> bool {
+        self.driver_config.share_cluster_secret && !self.core.borrow().has_cluster_secret()
     }
 
-    impl MockHostBuilder {
-        fn new() -> MockHostBuilder {
-            MockHostBuilder {
-                mock_host: MockHost::new(),
-            }
+    fn process_stop_node(
+        &mut self,
+        _stop_replica_request: &StopReplicaRequest,
+    ) -> Result<(), PalError> {
+        if let DriverState::Stopped = self.driver_state {
+            return Ok(());
         }
 
-        fn expect_public_signing_key(
-            &mut self,
-            public_signing_key: Vec<u8>,
-        ) -> &mut MockHostBuilder {
-            self.mock_host
-                .expect_public_signing_key()
-                .return_once(move || public_signing_key);
-            self
+        if !self.is_witness {
+            self.actor.on_shutdown();
         }
 
-        fn expect_send_messages(
-            &mut self,
-            sent_messages: Vec<out_message::Msg>,
-        ) -> &mut MockHostBuilder {
-            self.mock_host
-                .expect_send_messages()
-                .withf(create_send_messages_matcher(sent_messages))
-                .return_const(());
+        self.driver_state = DriverState::Stopped;
 
-            self
-        }
+        self.stash_message(out_message::Msg::StopReplica(StopReplicaResponse {}));
 
-        fn take(&mut self) -> MockHost {
-            mem::take(&mut self.mock_host)
-        }
+        Ok(())
     }
 
-    struct RaftBuilder {
-        mock_store: MockStore,
-        mock_raft: MockRaft<MockStore>,
-    }
+    fn process_change_cluster(
+        &mut self,
+        change_cluster_request: &ChangeClusterRequest,
+    ) -> Result<(), PalError> {
+        self.check_driver_started()?;
+        self.check_non_ephemeral()?;
 
-    impl RaftBuilder {
-        fn new() -> RaftBuilder {
-            RaftBuilder {
-                mock_store: MockStore::new(),
-                mock_raft: MockRaft::new(),
+        let change_status = match ChangeClusterType::try_from(change_cluster_request.change_type) {
+            Ok(ChangeClusterType::ChangeTypeAddReplica) => self.make_raft_config_change_proposal(
+                change_cluster_request.replica_id,
+                RaftConfigChangeType::AddNode,
+            )?,
+            Ok(ChangeClusterType::ChangeTypeRemoveReplica) => self
+                .make_raft_config_change_proposal(
+                    change_cluster_request.replica_id,
+                    RaftConfigChangeType::RemoveNode,
+                )?,
+            Ok(ChangeClusterType::ChangeTypeAddLearner) => self.make_raft_config_change_proposal(
+                change_cluster_request.replica_id,
+                RaftConfigChangeType::AddLearnerNode,
+            )?,
+            Ok(ChangeClusterType::ChangeTypePromoteLearner) => {
+                self.make_raft_learner_promotion_proposal(change_cluster_request.replica_id)?
             }
-        }
+            Ok(ChangeClusterType::ChangeTypeDemoteReplica) => {
+                self.make_raft_voter_demotion_proposal(change_cluster_request.replica_id)?
+            }
+            _ => {
+                warn!(self.logger, "Rejecting cluster change command: unknown");
 
-        fn expect_leader(mut self, leader: bool) -> RaftBuilder {
-            self.mock_raft.expect_leader().return_const(leader);
+                ChangeClusterStatus::ChangeStatusRejected
+            }
+        };
 
-            self
-        }
+        self.stash_message(out_message::Msg::ChangeCluster(ChangeClusterResponse {
+            change_id: change_cluster_request.change_id,
+            change_status: change_status.into(),
+        }));
 
-        fn expect_init(
-            mut self,
-            handler: impl Fn(u64, &raft::Config, Bytes, bool, MockStore, &Logger) -> Result<(), RaftError>
-                + 'static,
-        ) -> RaftBuilder {
-            self.mock_raft.expect_init().return_once_st(handler);
-            self.mock_raft.expect_initialized().returning(|| true);
+        Ok(())
+    }
 
-            self
-        }
+    fn process_reconfigure_cluster(
+        &mut self,
+        reconfigure_cluster_request: &ReconfigureClusterRequest,
+    ) -> Result<(), PalError> {
+        self.check_driver_started()?;
+        self.check_non_ephemeral()?;
 
-        fn expect_should_snapshot(mut self, should_snapshot: bool) -> RaftBuilder {
-            self.mock_store
-                .expect_should_snapshot()
-                .return_const(should_snapshot);
-            self
-        }
+        let add_replica_ids = &reconfigure_cluster_request.add_replica_ids;
+        let remove_replica_ids = &reconfigure_cluster_request.remove_replica_ids;
+        let change_status = if (add_replica_ids.is_empty() && remove_replica_ids.is_empty())
+            || add_replica_ids
+                .iter()
+                .any(|replica_id| remove_replica_ids.contains(replica_id))
+        {
+            warn!(
+                self.logger,
+                "Rejecting cluster reconfiguration: conflicting or empty"
+            );
 
-        fn expect_latest_snapshot_size(mut self, latest_snapshot_size: u64) -> RaftBuilder {
-            self.mock_store
-                .expect_latest_snapshot_size()
-                .return_const(latest_snapshot_size);
-            self
-        }
+            ChangeClusterStatus::ChangeStatusRejected
+        } else {
+            let changes = add_replica_ids
+                .iter()
+                .map(|replica_id| (*replica_id, RaftConfigChangeType::AddNode))
+                .chain(
+                    remove_replica_ids
+                        .iter()
+                        .map(|replica_id| (*replica_id, RaftConfigChangeType::RemoveNode)),
+                )
+                .collect();
 
-        fn expect_apply_snapshot(
-            mut self,
-            snapshot: RaftSnapshot,
-            handler: impl Fn(RaftSnapshot) -> Result<(), RaftError> + 'static,
-        ) -> RaftBuilder {
-            self.mock_store
-                .expect_apply_snapshot()
-                .with(eq(snapshot))
-                .return_once_st(handler);
-            self
-        }
+            debug!(self.logger, "Making Raft joint config change proposal");
 
-        fn expect_append_entries(
-            mut self,
-            entries: Vec<RaftEntry>,
-            handler: impl Fn(&[RaftEntry]) -> Result<(), RaftError> + 'static,
-        ) -> RaftBuilder {
-            self.mock_store
-                .expect_append_entries()
-                .with(eq(entries))
-                .return_once_st(handler);
-            self
-        }
+            match self
+                .raft
+                .make_config_change_v2_proposal(create_raft_config_change_v2(changes))
+            {
+                Ok(_) => ChangeClusterStatus::ChangeStatusPending,
+                Err(RaftError::ProposalDropped) => {
+                    warn!(self.logger, "Dropping Raft joint config change proposal");
 
-        fn expect_create_snapshot(
-            mut self,
-            applied_index: u64,
-            config_state: RaftConfigState,
-            snapshot_data: Bytes,
-            result: Result<(), RaftError>,
-        ) -> RaftBuilder {
-            self.mock_store
-                .expect_create_snapshot()
-                .with(eq(applied_index), eq(config_state), eq(snapshot_data))
-                .return_once_st(|_, _, _| result);
-            self
-        }
+                    ChangeClusterStatus::ChangeStatusRejected
+                }
+                Err(e) => {
+                    error!(self.logger, "Raft experienced unrecoverable error: {}", e);
 
-        fn expect_make_tick(mut self) -> RaftBuilder {
-            self.mock_raft.expect_make_tick().once().return_const(());
-            self
-        }
+                    // Unrecoverable Raft errors must lead to termination.
+                    return Err(PalError::Raft);
+                }
+            }
+        };
 
-        fn expect_make_step(
-            mut self,
-            raft_message: &RaftMessage,
-            result: Result<(), RaftError>,
-        ) -> RaftBuilder {
-            self.mock_raft
-                .expect_make_step()
-                .with(eq(raft_message.clone()))
-                .return_once_st(move |_| result);
-            self
-        }
+        self.stash_message(out_message::Msg::ChangeCluster(ChangeClusterResponse {
+            change_id: reconfigure_cluster_request.change_id,
+            change_status: change_status.into(),
+        }));
 
-        fn expect_has_ready(mut self, has_ready: bool) -> RaftBuilder {
-            self.mock_raft
-                .expect_has_ready()
-                .once()
-                .return_const(has_ready);
-            self
-        }
+        Ok(())
+    }
 
-        fn expect_state(mut self, raft_state: &RaftState) -> RaftBuilder {
-            self.mock_raft
-                .expect_state()
-                .return_const(raft_state.clone());
-            self
-        }
+    fn process_transfer_leadership(
+        &mut self,
+        transfer_leadership_request: &TransferLeadershipRequest,
+    ) -> Result<(), PalError> {
+        self.check_driver_started()?;
+        self.check_non_ephemeral()?;
 
-        fn expect_state_once(mut self, raft_state: &RaftState) -> RaftBuilder {
-            self.mock_raft
-                .expect_state()
-                .once()
-                .return_const(raft_state.clone());
-            self
+        let target_replica_id = transfer_leadership_request.target_replica_id;
+        if !self.check_raft_leadership()
+            || self.leadership_transfer.is_some()
+            || target_replica_id == self.id
+            || !self
+                .raft_progress
+                .config_state
+                .voters
+                .contains(&target_replica_id)
+        {
+            warn!(
+                self.logger,
+                "Rejecting leadership transfer to replica {}", target_replica_id
+            );
+
+            self.stash_transfer_leadership_response(
+                transfer_leadership_request.transfer_id,
+                TransferLeadershipStatus::TransferStatusRejected,
+            );
+            return Ok(());
         }
 
-        fn expect_make_proposal(
-            mut self,
-            proposal: Entry,
-            handler: impl Fn(Bytes) -> Result<(), RaftError> + 'static,
-        ) -> RaftBuilder {
-            self.mock_raft
-                .expect_make_proposal()
-                .with(eq(Bytes::from(proposal.encode_to_vec())))
-                .returning_st(handler);
+        info!(
+            self.logger,
+            "Transferring leadership to replica {}", target_replica_id
+        );
 
-            self
-        }
+        self.raft.transfer_leadership(target_replica_id);
+        // Raft aborts the transfer after the election timeout.
+        self.leadership_transfer = Some(LeadershipTransfer {
+            transfer_id: transfer_leadership_request.transfer_id,
+            target_replica_id,
+            deadline: self.clock.instant()
+                + self.driver_config.election_tick * self.driver_config.tick_period,
+        });
 
-        fn expect_make_config_change_proposal(
-            mut self,
-            config_change: RaftConfigChange,
-            handler: impl Fn(RaftConfigChange) -> Result<(), RaftError> + 'static,
-        ) -> RaftBuilder {
-            self.mock_raft
-                .expect_make_config_change_proposal()
-                .with(eq(config_change))
-                .returning_st(handler);
+        Ok(())
+    }
 
-            self
-        }
+    fn check_leadership_transfer(&mut self) {
+        let Some(leadership_transfer) = &self.leadership_transfer else {
+            return;
+        };
 
-        fn expect_ready(mut self, ready: &RaftReady) -> RaftBuilder {
-            let ready = ready.clone();
-            self.mock_raft
-                .expect_get_ready()
-                .once()
-                .return_once(move || ready);
-            self
-        }
+        let transfer_status = if !self.check_raft_leadership() {
+            info!(
+                self.logger,
+                "Transferred leadership to replica {}", leadership_transfer.target_replica_id
+            );
 
-        fn expect_apply_config_change(
-            mut self,
-            config_change: &RaftConfigChange,
-            result: Result<RaftConfigState, RaftError>,
-        ) -> RaftBuilder {
-            let config_change = config_change.clone();
-            self.mock_raft
-                .expect_apply_config_change()
-                .with(eq(config_change))
-                .once()
-                .return_once_st(move |_| result);
-            self
-        }
+            TransferLeadershipStatus::TransferStatusCompleted
+        } else if self.clock.instant() >= leadership_transfer.deadline {
+            warn!(
+                self.logger,
+                "Failed to transfer leadership to replica {}",
+                leadership_transfer.target_replica_id
+            );
 
-        fn expect_advance_ready(
-            mut self,
-            ready_number: u64,
-            light_ready: RaftLightReady,
-        ) -> RaftBuilder {
-            self.mock_raft
-                .expect_advance_ready()
-                .withf_st(move |ready| ready.number() == ready_number)
-                .return_const(light_ready);
-            self
-        }
+            TransferLeadershipStatus::TransferStatusFailed
+        } else {
+            return;
+        };
 
-        fn expect_advance_apply(mut self) -> RaftBuilder {
-            self.mock_raft.expect_advance_apply().return_const(());
-            self
-        }
+        let transfer_id = leadership_transfer.transfer_id;
+        self.leadership_transfer = None;
+        self.stash_transfer_leadership_response(transfer_id, transfer_status);
+    }
 
-        fn expect_report_snapshot(
-            mut self,
-            replica_id: u64,
-            status: RaftSnapshotStatus,
-        ) -> RaftBuilder {
-            self.mock_raft
-                .expect_report_snapshot()
-                .with(eq(replica_id), eq(status))
-                .once()
-                .return_const(());
-            self
+    fn process_update_actor_config(
+        &mut self,
+        update_actor_config_request: UpdateActorConfigRequest,
+    ) -> Result<(), PalError> {
+        self.check_driver_started()?;
+
+        let update = ActorConfigUpdate {
+            replica_id: self.id,
+            update_id: update_actor_config_request.update_id,
+            actor_config: update_actor_config_request.actor_config,
+            attestation_policy: update_actor_config_request.attestation_policy,
+        };
+
+        // For ephemeral replica, apply the update immediately since it is not replicated.
+        if self.is_ephemeral {
+            self.apply_actor_config(update, true);
+            return Ok(());
         }
 
-        fn take(&mut self) -> (MockStore, MockRaft<MockStore>) {
-            (
-                mem::take(&mut self.mock_store),
-                mem::take(&mut self.mock_raft),
-            )
+        let update_id = update.update_id;
+        let entry = Entry {
+            actor_config_update: Some(update),
+            ..Default::default()
+        };
+        if self.is_witness
+            || !self.check_raft_leadership()
+            || !self.make_raft_proposal(entry.encode_to_vec().into())?
+        {
+            warn!(self.logger, "Rejecting actor config update #{}", update_id);
+
+            self.stash_update_actor_config_response(
+                update_id,
+                UpdateActorConfigStatus::UpdateConfigStatusRejected,
+            );
         }
+
+        Ok(())
     }
 
-    struct SnapshotBuilder {
-        mock_snapshot_sender: MockSnapshotSender,
-        mock_snapshot_receiver: MockSnapshotReceiver,
-    }
-
-    impl SnapshotBuilder {
-        fn new() -> SnapshotBuilder {
-            SnapshotBuilder {
-                mock_snapshot_sender: MockSnapshotSender::new(),
-                mock_snapshot_receiver: MockSnapshotReceiver::new(),
-            }
+    fn apply_actor_config(&mut self, update: ActorConfigUpdate, owned: bool) {
+        if let Some(attestation_policy) = &update.attestation_policy {
+            info!(
+                self.logger,
+                "Applying attestation policy update #{}", update.update_id
+            );
+            self.apply_attestation_policy(attestation_policy.clone());
         }
 
-        fn expect_init(mut self, replica_id: u64) -> SnapshotBuilder {
-            let (_, _, raft_config) = create_default_parameters();
-            self.mock_snapshot_sender
-                .expect_init()
-                .with(always(), eq(replica_id), eq(raft_config.snapshot_config))
-                .return_const(());
-
-            self.mock_snapshot_receiver
-                .expect_init()
-                .with(always(), eq(replica_id))
-                .return_const(());
+        // Actor refusing the update leaves its configuration unchanged on all replicas.
+        let apply_result = if update.attestation_policy.is_some() && update.actor_config.is_empty()
+        {
+            Ok(())
+        } else {
+            self.actor.on_apply_config(update.actor_config)
+        };
+        let update_status = match apply_result {
+            Ok(()) => UpdateActorConfigStatus::UpdateConfigStatusApplied,
+            Err(e) => {
+                warn!(
+                    self.logger,
+                    "Failed to apply actor config update #{}: {}", update.update_id, e
+                );
+                UpdateActorConfigStatus::UpdateConfigStatusFailed
+            }
+        };
 
-            self
+        if owned {
+            self.stash_update_actor_config_response(update.update_id, update_status);
         }
+    }
 
-        fn expect_receiver_set_instant(mut self) -> SnapshotBuilder {
-            self.mock_snapshot_receiver
-                .expect_set_instant()
-                .return_const(());
+    fn stash_update_actor_config_response(
+        &mut self,
+        update_id: u64,
+        update_status: UpdateActorConfigStatus,
+    ) {
+        self.stash_message(out_message::Msg::UpdateActorConfig(
+            UpdateActorConfigResponse {
+                update_id,
+                update_status: update_status.into(),
+            },
+        ));
+    }
 
-            self
-        }
+    fn stash_transfer_leadership_response(
+        &mut self,
+        transfer_id: u64,
+        transfer_status: TransferLeadershipStatus,
+    ) {
+        self.stash_message(out_message::Msg::TransferLeadership(
+            TransferLeadershipResponse {
+                transfer_id,
+                transfer_status: transfer_status.into(),
+            },
+        ));
+    }
 
-        fn expect_receiver_try_complete(
-            mut self,
-            result: Option<Result<(u64, RaftSnapshot), SnapshotError>>,
-        ) -> SnapshotBuilder {
-            self.mock_snapshot_receiver
-                .expect_try_complete()
-                .once()
-                .return_once(|| result);
+    fn process_check_cluster(
+        &mut self,
+        _check_cluster_request: &CheckClusterRequest,
+    ) -> Result<(), PalError> {
+        self.check_driver_started()?;
+        self.check_non_ephemeral()?;
 
-            self
-        }
+        self.reset_leader_state();
 
-        fn expect_receiver_process_request(
-            mut self,
-            request: DeliverSnapshotRequest,
-            response: DeliverSnapshotResponse,
-        ) -> SnapshotBuilder {
-            self.mock_snapshot_receiver
-                .expect_process_request()
-                .with(eq(request))
-                .once()
-                .return_const(response);
+        Ok(())
+    }
 
-            self
-        }
+    fn process_deliver_system_message(
+        &mut self,
+        deliver_system_message: DeliverSystemMessage,
+    ) -> Result<(), PalError> {
+        self.check_driver_started()?;
+        self.check_non_ephemeral()?;
 
-        fn expect_receiver_reset(mut self) -> SnapshotBuilder {
-            self.mock_snapshot_receiver.expect_reset().return_const(());
+        let message =
+            self.communication
+                .process_in_message(in_message::Msg::DeliverSystemMessage(
+                    deliver_system_message,
+                ))?;
 
-            self
+        if message.is_none() {
+            return Ok(());
         }
-
-        fn expect_sender_set_instant(mut self) -> SnapshotBuilder {
-            self.mock_snapshot_sender
-                .expect_set_instant()
-                .return_const(());
-
-            self
+        if self.awaits_cluster_secret() {
+            debug!(
+                self.logger,
+                "Dropping message until cluster secret is received"
+            );
+            return Ok(());
         }
 
-        fn expect_sender_process_response(
-            mut self,
-            sender_id: u64,
-            delivery_id: u64,
-            response: Result<DeliverSnapshotResponse, SnapshotError>,
-        ) -> SnapshotBuilder {
-            self.mock_snapshot_sender
-                .expect_process_response()
-                .with(eq(sender_id), eq(delivery_id), eq(response))
-                .once()
-                .return_const(());
-            self
+        let message = message.unwrap();
+        match message {
+            in_message::Msg::DeliverSystemMessage(m) => self.make_raft_step(
+                m.sender_replica_id,
+                m.recipient_replica_id,
+                m.message_contents,
+            ),
+            _ => {
+                warn!(self.logger, "Unexpected message type {:?}", message);
+                Err(PalError::Internal)
+            }
         }
+    }
 
-        fn expect_sender_next_request(
-            mut self,
-            request: Option<DeliverSnapshotRequest>,
-        ) -> SnapshotBuilder {
-            self.mock_snapshot_sender
-                .expect_next_request()
-                .once()
-                .return_once(|| request);
-
-            self
+    fn update_snapshot_cluster_change(&mut self) {
+        if self.driver_state != DriverState::Started {
+            return;
         }
 
-        fn expect_sender_try_complete(
-            mut self,
-            result: Option<(u64, RaftSnapshotStatus)>,
-        ) -> SnapshotBuilder {
-            self.mock_snapshot_sender
-                .expect_try_complete()
-                .once()
-                .return_once(move || result);
+        // Notify snapshot processor of the latest state of the cluster.
+        let snapshot_updates = self.snapshot.process_cluster_change(
+            self.raft_state.leader_replica_id,
+            self.raft_state.leader_term,
+            &self.raft_state.committed_cluster_members(),
+        );
 
-            self
+        // Notify raft if any of the snapshot transfers has been cancelled.
+        for (replica_id, snapshot_status) in snapshot_updates {
+            self.raft.report_snapshot(replica_id, snapshot_status);
         }
+    }
 
-        fn expect_sender_start(
-            mut self,
-            recipient_id: u64,
-            snapshot: RaftSnapshot,
-        ) -> SnapshotBuilder {
-            self.mock_snapshot_sender
-                .expect_start()
-                .with(eq(recipient_id), eq(snapshot))
-                .once()
-                .return_const(());
+    fn process_deliver_snapshot_request(
+        &mut self,
+        deliver_snapshot_request: DeliverSnapshotRequest,
+    ) -> Result<(), PalError> {
+        self.check_non_ephemeral()?;
+        let message =
+            self.communication
+                .process_in_message(in_message::Msg::DeliverSnapshotRequest(
+                    deliver_snapshot_request,
+                ))?;
 
-            self
+        if message.is_none() {
+            return Ok(());
+        }
+        if self.awaits_cluster_secret() {
+            debug!(
+                self.logger,
+                "Dropping snapshot until cluster secret is received"
+            );
+            return Ok(());
         }
 
-        fn take(mut self) -> (MockSnapshotSender, MockSnapshotReceiver) {
-            (
-                mem::take(&mut self.mock_snapshot_sender),
-                mem::take(&mut self.mock_snapshot_receiver),
-            )
+        let message = message.unwrap();
+        match message {
+            in_message::Msg::DeliverSnapshotRequest(m) => {
+                self.share_snapshot_session_secret(m.sender_replica_id);
+                let deliver_snapshot_response = match self.snapshot.mut_processor() {
+                    SnapshotProcessorRole::Sender(sender) => {
+                        warn!(
+                            self.logger,
+                            "Node is in snapshot sending mode, unexpected deliver snapshot request"
+                        );
+                        sender.process_unexpected_request(m)
+                    }
+                    SnapshotProcessorRole::Receiver(receiver) => receiver.process_request(m),
+                };
+                self.stash_system_message(
+                    MessageClass::Snapshot,
+                    out_message::Msg::DeliverSnapshotResponse(deliver_snapshot_response),
+                );
+                Ok(())
+            }
+            _ => {
+                warn!(self.logger, "Unexpected message type {:?}", message);
+                Err(PalError::Internal)
+            }
         }
     }
 
-    struct CommunicationBuilder {
-        mock_communication_module: MockCommunicationModule,
-    }
+    fn process_deliver_snapshot_response(
+        &mut self,
+        deliver_snapshot_response: DeliverSnapshotResponse,
+    ) -> Result<(), PalError> {
+        self.check_non_ephemeral()?;
+        let message =
+            self.communication
+                .process_in_message(in_message::Msg::DeliverSnapshotResponse(
+                    deliver_snapshot_response,
+                ))?;
 
-    impl CommunicationBuilder {
-        fn new() -> CommunicationBuilder {
-            CommunicationBuilder {
-                mock_communication_module: MockCommunicationModule::new(),
-            }
+        if message.is_none() {
+            return Ok(());
         }
 
-        fn expect_init(mut self, replica_id: u64) -> CommunicationBuilder {
-            self.mock_communication_module
-                .expect_init()
-                .with(eq(replica_id), always(), always())
-                .once()
-                .return_const(());
-
-            self
+        let message = message.unwrap();
+        match message {
+            in_message::Msg::DeliverSnapshotResponse(m) => {
+                match self.snapshot.mut_processor() {
+                    SnapshotProcessorRole::Sender(sender) => {
+                        sender.process_response(m.sender_replica_id, m.delivery_id, Ok(m))
+                    }
+                    SnapshotProcessorRole::Receiver(_) => {
+                        warn!(
+                            self.logger,
+                            "Node is snapshot receiving mode, unexpected deliver snapshot response"
+                        );
+                    }
+                }
+                Ok(())
+            }
+            _ => {
+                warn!(self.logger, "Unexpected message type {:?}", message);
+                Err(PalError::Internal)
+            }
         }
+    }
 
-        fn expect_make_tick(mut self) -> CommunicationBuilder {
-            self.mock_communication_module
-                .expect_make_tick()
-                .once()
-                .return_const(());
-            self
+    fn process_deliver_snapshot_failure(
+        &mut self,
+        deliver_snapshot_failure: DeliverSnapshotFailure,
+    ) -> Result<(), PalError> {
+        self.check_non_ephemeral()?;
+        match self.snapshot.mut_processor() {
+            SnapshotProcessorRole::Sender(sender) => sender.process_response(
+                deliver_snapshot_failure.sender_replica_id,
+                deliver_snapshot_failure.delivery_id,
+                Err(SnapshotError::FailedDelivery),
+            ),
+            SnapshotProcessorRole::Receiver(_) => {
+                warn!(
+                    self.logger,
+                    "Node is snapshot receiving mode, unexpected deliver snapshot failure"
+                );
+            }
         }
 
-        fn expect_process_out_message(
-            mut self,
-            message: out_message::Msg,
-            result: Result<(), PalError>,
-        ) -> CommunicationBuilder {
-            self.mock_communication_module
-                .expect_process_out_message()
-                .with(eq(message))
-                .return_once_st(|_| result);
+        Ok(())
+    }
 
-            self
+    fn process_snapshot_progress(&mut self) {
+        if self.driver_state != DriverState::Started {
+            return;
         }
 
-        fn expect_process_in_message(
-            mut self,
-            message: in_message::Msg,
-            result: Result<Option<in_message::Msg>, PalError>,
-        ) -> CommunicationBuilder {
-            self.mock_communication_module
-                .expect_process_in_message()
-                .with(eq(message))
-                .return_once_st(|_| result);
+        match self.snapshot.mut_processor() {
+            SnapshotProcessorRole::Sender(sender) => {
+                if let Some((replica_id, snapshot_status)) = sender.try_complete() {
+                    self.raft.report_snapshot(replica_id, snapshot_status);
+                }
+            }
+            SnapshotProcessorRole::Receiver(receiver) => {
+                if let Some(snapshot_result) = receiver.try_complete() {
+                    match snapshot_result {
+                        Ok((sender_replica_id, snapshot)) => {
+                            let mut snapshot_message = create_raft_message(
+                                sender_replica_id,
+                                self.id,
+                                RaftMessageType::MsgSnapshot,
+                            );
+                            snapshot_message.set_snapshot(snapshot);
+                            if let Err(e) = self.raft.make_step(snapshot_message) {
+                                error!(self.logger, "Raft experienced unrecoverable error: {}", e);
+                            }
+                        }
+                        Err(_) => {
+                            warn!(
+                                self.logger,
+                                "Snapshot has been received but failed validation"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
 
-            self
+    // Passes the secret of the attested session with the peer replica to the
+    // snapshot processor to derive the keys of the snapshot transfers from.
+    fn share_snapshot_session_secret(&mut self, replica_id: u64) {
+        if !self.driver_config.encrypt_snapshots {
+            return;
         }
 
-        fn expect_take_out_messages(mut self, messages: Vec<OutMessage>) -> CommunicationBuilder {
-            self.mock_communication_module
-                .expect_take_out_messages()
-                .once()
-                .return_const(messages);
+        if let Some(session_secret) = self
+            .communication
+            .export_secret(replica_id, SESSION_SECRET_CONTEXT)
+        {
+            self.snapshot
+                .set_session_secret(replica_id, session_secret.into());
+        }
+    }
 
-            self
+    fn process_snapshot_sending(&mut self) {
+        let snapshot_messages = mem::take(&mut self.snapshots);
+        for snapshot_message in &snapshot_messages {
+            self.share_snapshot_session_secret(snapshot_message.to);
         }
 
-        fn expect_process_cluster_change(mut self, replicas: Vec<u64>) -> CommunicationBuilder {
-            self.mock_communication_module
-                .expect_process_cluster_change()
-                .with(eq(replicas))
-                .once()
-                .return_const(());
-            self
+        let mut out_messages: Vec<out_message::Msg> = Vec::new();
+        match self.snapshot.mut_processor() {
+            SnapshotProcessorRole::Sender(sender) => {
+                for snapshot_message in snapshot_messages {
+                    sender.start(snapshot_message.to, snapshot_message.snapshot.unwrap());
+                }
+
+                while let Some(request) = sender.next_request() {
+                    out_messages.push(out_message::Msg::DeliverSnapshotRequest(request));
+                }
+            }
+            SnapshotProcessorRole::Receiver(_) => {
+                if !snapshot_messages.is_empty() {
+                    warn!(
+                        self.logger,
+                        "Unexpected snapshot sending while playing receiver role"
+                    );
+                    for snapshot_message in snapshot_messages {
+                        self.raft
+                            .report_snapshot(snapshot_message.to, RaftSnapshotStatus::Failure);
+                    }
+                }
+            }
         }
 
-        fn take(mut self) -> MockCommunicationModule {
-            mem::take(&mut self.mock_communication_module)
+        for out_message in out_messages {
+            self.stash_system_message(MessageClass::Snapshot, out_message);
         }
     }
 
-    struct DriverBuilder {
-        mock_actor: MockActor,
+    fn enqueue_app_message(&mut self, deliver_app_message: DeliverAppMessage) {
+        if self.lame_duck.is_some() {
+            warn!(
+                self.logger,
+                "Rejecting app message #{}: preparing to shut down",
+                deliver_app_message.correlation_id
+            );
+            // Let the consumer retry against the replica that takes over.
+            self.fail_app_message(
+                deliver_app_message.correlation_id,
+                deliver_app_message.route,
+                StatusCode::Unavailable,
+            );
+            return;
+        }
+
+        if let Some(shed_message) = self.mailbox.push(deliver_app_message) {
+            warn!(
+                self.logger,
+                "Shedding app message #{}: mailbox is full", shed_message.correlation_id
+            );
+            self.fail_app_message(
+                shed_message.correlation_id,
+                shed_message.route,
+                StatusCode::ResourceExhausted,
+            );
+        }
     }
 
-    impl DriverBuilder {
-        fn new() -> DriverBuilder {
-            DriverBuilder {
-                mock_actor: MockActor::new(),
-            }
+    fn dequeue_app_message(&mut self) -> Option<DeliverAppMessage> {
+        // Keep messages in the mailbox while too many proposals are in flight so that
+        // the actor does not produce more work than the cluster can replicate.
+        if !self.is_ephemeral
+            && self.driver_config.max_pending_proposals > 0
+            && self.raft_progress.pending_proposals >= self.driver_config.max_pending_proposals
+        {
+            return None;
         }
 
-        fn expect_on_init(
-            &mut self,
-            init_handler: impl Fn(Box<dyn ActorContext>) -> Result<(), ActorError> + 'static,
-        ) -> &mut DriverBuilder {
-            self.mock_actor
-                .expect_on_init()
-                .return_once_st(init_handler);
+        self.mailbox.pop()
+    }
 
-            self
+    fn process_deliver_app_message(
+        &mut self,
+        deliver_app_message: Option<DeliverAppMessage>,
+    ) -> Result<(), PalError> {
+        self.check_driver_started()?;
+
+        // The bytes the command draws are derived from the seed replicated along
+        // with the entry the command proposes, rather than drawn locally.
+        if self.driver_config.replicated_randomness
+            && !self.is_ephemeral
+            && deliver_app_message.is_some()
+        {
+            let command_seed = RandomSource::generate_seed();
+            self.mut_core().random().seed_command(&command_seed);
+            self.command_seed = Some(command_seed);
         }
 
-        fn expect_on_shutdown(&mut self) -> &mut DriverBuilder {
-            self.mock_actor.expect_on_shutdown().once().return_const(());
+        // Route of the actor the command is delivered to, in case its proposals
+        // must be rejected.
+        let route = deliver_app_message
+            .as_ref()
+            .map(|m| m.route.clone())
+            .unwrap_or_default();
+        let message_outcome = self
+            .interceptors
+            .process_command(
+                &mut self.actor,
+                deliver_app_message.map(|m| ActorCommand {
+                    correlation_id: m.correlation_id,
+                    header: m.message_header,
+                    payload: m.message_payload,
+                    route: m.route,
+                    status_code: 0,
+                }),
+            )
+            .map_err(|e| {
+                error!(self.logger, "Failed to process actor command: {}", e);
 
-            self
-        }
+                // Failure to process actor command must lead to termination.
+                PalError::Actor
+            });
+        self.mut_core().random().unseed();
 
-        fn expect_on_process_command(
-            &mut self,
-            command: Option<ActorCommand>,
-            result: Result<CommandOutcome, ActorError>,
-        ) -> &mut DriverBuilder {
-            self.mock_actor
-                .expect_on_process_command()
-                .with(eq(command))
-                .returning(move |_| result.clone());
+        let result =
+            message_outcome.and_then(|outcome| self.process_command_outcome(outcome, route));
+        self.command_seed = None;
+        result
+    }
 
-            self
+    fn process_command_outcome(
+        &mut self,
+        message_outcome: CommandOutcome,
+        route: String,
+    ) -> Result<(), PalError> {
+        if !message_outcome.events.is_empty() && self.check_state_size_exceeded() {
+            // Messages of the actor presume that its proposals are accepted, hence only
+            // the rejection is sent out.
+            self.reject_actor_events(message_outcome.events, route);
+            return Ok(());
         }
 
-        fn expect_on_load_snapshot(
-            &mut self,
-            snapshot: Bytes,
-            result: Result<(), ActorError>,
-        ) -> &mut DriverBuilder {
-            self.mock_actor
-                .expect_on_load_snapshot()
-                .with(eq(snapshot))
-                .return_once(|_| result);
-            self
+        for actor_message in message_outcome.commands {
+            self.stash_message(out_message::Msg::DeliverAppMessage(DeliverAppMessage {
+                correlation_id: actor_message.correlation_id,
+                message_header: actor_message.header,
+                message_payload: actor_message.payload,
+                route: actor_message.route,
+                status_code: actor_message.status_code,
+            }));
         }
 
-        fn expect_on_save_snapshot(
-            &mut self,
-            result: Result<Bytes, ActorError>,
-        ) -> &mut DriverBuilder {
-            self.mock_actor
-                .expect_on_save_snapshot()
-                .return_once(|| result);
-            self
+        if !message_outcome.events.is_empty() {
+            self.process_actor_events(message_outcome.events)?;
         }
 
-        fn expect_on_apply_event(
-            &mut self,
-            context: ActorEventContext,
-            event: ActorEvent,
-            result: Result<EventOutcome, ActorError>,
-        ) -> &mut DriverBuilder {
-            self.mock_actor
-                .expect_on_apply_event()
-                .with(eq(context), eq(event))
-                .return_once(|_, _| result);
-
-            self
+        if let Some(correlation_id) = message_outcome.pending {
+            self.pending_commands.insert(correlation_id);
         }
 
-        fn take(
-            &mut self,
-            mut raft_builder: RaftBuilder,
-            snapshot_builder: SnapshotBuilder,
-            communication_builder: CommunicationBuilder,
-        ) -> Driver<
-            MockRaft<MockStore>,
-            MockStore,
-            DefaultSnapshotProcessor,
-            MockActor,
-            MockCommunicationModule,
-        > {
-            let (mock_store, mut mock_raft) = raft_builder.take();
-            let mock_actor = mem::take(&mut self.mock_actor);
-            let (mock_snapshot_sender, mock_snapshot_receiver) = snapshot_builder.take();
-            let mock_communication_module = communication_builder.take();
+        if let Some(read_command) = message_outcome.read {
+            if self.is_ephemeral {
+                // Ephemeral replica state is not replicated, hence it is always up to date.
+                self.process_read_command(read_command)?;
+            } else if self.check_follower_read() {
+                // Follower state is within the staleness bounds, no need to reach the leader.
+                self.process_read_command(read_command)?;
+            } else {
+                let context = self.reads.start(read_command);
+                self.raft.make_read_index(context);
+            }
+        }
 
-            mock_raft.expect_mut_store().return_var(mock_store);
+        Ok(())
+    }
 
-            Driver::new(
-                mock_raft,
-                Box::new(|_, _| MockStore::new()),
-                DefaultSnapshotProcessor::new(
-                    Box::new(mock_snapshot_sender),
-                    Box::new(mock_snapshot_receiver),
-                ),
-                mock_actor,
-                mock_communication_module,
-            )
+    fn reject_actor_events(&mut self, actor_events: Vec<ActorEvent>, route: String) {
+        let state_size = self.core.borrow().state_size();
+        // Events produced by the same command typically share the correlation id.
+        let mut correlation_ids: Vec<u64> = actor_events
+            .iter()
+            .map(|actor_event| actor_event.correlation_id)
+            .collect();
+        correlation_ids.dedup();
+        for correlation_id in correlation_ids {
+            warn!(
+                self.logger,
+                "Rejecting proposal #{}: actor state size {} exceeds limit {}",
+                correlation_id,
+                state_size,
+                self.driver_config.max_state_size
+            );
+            // Let the consumer know that the proposal has been rejected.
+            self.fail_app_message(correlation_id, route.clone(), StatusCode::ResourceExhausted);
         }
     }
 
-    #[test]
-    fn test_driver_start_node_request() {
-        let (node_id, instant, raft_config) = create_default_parameters();
-        let init_snapshot = Bytes::from(vec![2, 3, 4]);
-
-        let mut mock_host = MockHostBuilder::new()
-            .expect_public_signing_key(vec![])
-            .expect_send_messages(vec![create_start_replica_response(node_id)])
-            .take();
-
-        let exp_raft_config = raft_config.clone();
+    fn process_actor_events(&mut self, actor_events: Vec<ActorEvent>) -> Result<(), PalError> {
+        if self.is_ephemeral {
+            // For ephemeral replica, apply the events immediately since they are not replicated.
+            for actor_event in actor_events {
+                let event_outcome = self
+                    .interceptors
+                    .apply_event(
+                        &mut self.actor,
+                        ActorEventContext {
+                            index: 0,
+                            owned: true,
+                        },
+                        actor_event,
+                    )
+                    .map_err(|e| {
+                        error!(self.logger, "Failed to apply event to actor state: {}", e);
+                        // Failure to apply event to actor state must lead to termination.
+                        PalError::Actor
+                    })?;
+
+                for actor_command in event_outcome.commands {
+                    self.stash_message(out_message::Msg::DeliverAppMessage(DeliverAppMessage {
+                        correlation_id: actor_command.correlation_id,
+                        message_header: actor_command.header,
+                        message_payload: actor_command.payload,
+                        route: actor_command.route,
+                        status_code: actor_command.status_code,
+                    }));
+                }
+            }
+            return Ok(());
+        }
+
+        let mut entries: Vec<Entry> = actor_events
+            .into_iter()
+            .map(|actor_event| {
+                create_entry(
+                    EntryId {
+                        entry_id: actor_event.correlation_id,
+                        replica_id: self.id,
+                    },
+                    actor_event.contents,
+                )
+            })
+            .collect();
+        if self.driver_config.replicated_randomness {
+            // The first entry carries the seed the command has drawn from.
+            let mut command_seed = self.command_seed.take();
+            for entry in &mut entries {
+                let random_seed = command_seed
+                    .take()
+                    .unwrap_or_else(RandomSource::generate_seed);
+                entry.random_seed = self.encrypt_random_seed(random_seed)?;
+            }
+        }
+
+        if self.batcher.enabled() {
+            // Batch is taken as a whole, hence the events end up in the same entry.
+            let instant = self.clock.instant();
+            for entry in entries {
+                self.batcher.push(entry, instant);
+            }
+        } else if entries.len() == 1 {
+            let entry = entries.pop().unwrap();
+            let correlation_ids = entry.entry_id.iter().map(|id| id.entry_id).collect();
+            self.mut_core()
+                .append_proposal(entry.encode_to_vec().into(), correlation_ids);
+        } else {
+            // Events are applied in the order they were produced at the same index.
+            let correlation_ids = entries
+                .iter()
+                .filter_map(|entry| entry.entry_id.as_ref())
+                .map(|entry_id| entry_id.entry_id)
+                .collect();
+            let entry = Entry {
+                batched_entries: entries,
+                ..Default::default()
+            };
+            self.mut_core()
+                .append_proposal(entry.encode_to_vec().into(), correlation_ids);
+        }
+
+        Ok(())
+    }
+
+    fn check_state_size_exceeded(&self) -> bool {
+        self.driver_config.max_state_size > 0
+            && self.core.borrow().state_size() > self.driver_config.max_state_size
+    }
+
+    fn process_completed_commands(&mut self) {
+        for actor_command in self.mut_core().take_completed_commands() {
+            if !self.pending_commands.remove(&actor_command.correlation_id) {
+                warn!(
+                    self.logger,
+                    "Dropping response to #{} command: not pending", actor_command.correlation_id
+                );
+                continue;
+            }
+
+            self.stash_message(out_message::Msg::DeliverAppMessage(DeliverAppMessage {
+                correlation_id: actor_command.correlation_id,
+                message_header: actor_command.header,
+                message_payload: actor_command.payload,
+                route: actor_command.route,
+                status_code: actor_command.status_code,
+            }));
+        }
+    }
+
+    fn process_sent_app_messages(&mut self) {
+        for sent_app_message in self.mut_core().take_sent_app_messages() {
+            self.stash_message(out_message::Msg::SendAppMessage(sent_app_message));
+        }
+    }
+
+    fn process_confirmed_reads(&mut self) -> Result<(), PalError> {
+        for read_command in self.reads.take_ready(self.raft_progress.applied_index) {
+            self.process_read_command(read_command)?;
+        }
+
+        Ok(())
+    }
+
+    fn process_read_command(&mut self, read_command: ActorCommand) -> Result<(), PalError> {
+        let route = read_command.route.clone();
+        self.mut_core().set_read_confirmed(true);
+        let read_outcome = self
+            .interceptors
+            .process_command(&mut self.actor, Some(read_command));
+        self.mut_core().set_read_confirmed(false);
+
+        let mut read_outcome = read_outcome.map_err(|e| {
+            error!(self.logger, "Failed to process actor query: {}", e);
+
+            // Failure to process actor command must lead to termination.
+            PalError::Actor
+        })?;
+
+        if read_outcome.read.take().is_some() {
+            warn!(self.logger, "Ignoring query requested by confirmed query");
+        }
+
+        self.process_command_outcome(read_outcome, route)
+    }
+
+    fn process_get_replica_state(
+        &mut self,
+        _get_replica_state_request: &GetReplicaStateRequest,
+    ) -> Result<(), PalError> {
+        self.check_driver_started()?;
+
+        if self.is_ephemeral {
+            self.stash_message(out_message::Msg::GetReplicaState(GetReplicaStateResponse {
+                mailbox_size: self.mailbox.len() as u64,
+                shed_message_count: self.mailbox.shed_count(),
+                ..Default::default()
+            }));
+        } else {
+            let latest_snapshot_size = self.raft.mut_store().latest_snapshot_size();
+            self.stash_message(out_message::Msg::GetReplicaState(GetReplicaStateResponse {
+                applied_index: self.raft_progress.applied_index,
+                latest_snapshot_size,
+                mailbox_size: self.mailbox.len() as u64,
+                shed_message_count: self.mailbox.shed_count(),
+            }));
+        }
+
+        Ok(())
+    }
+
+    fn process_get_replica_status(
+        &mut self,
+        _get_replica_status_request: &GetReplicaStatusRequest,
+    ) -> Result<(), PalError> {
+        self.check_driver_started()?;
+
+        let actor_health = if self.is_witness {
+            ActorHealth::Unspecified
+        } else if self.restoring_actor {
+            ActorHealth::Restoring
+        } else if self.skipped_entry_count > 0 {
+            ActorHealth::Degraded
+        } else {
+            ActorHealth::Healthy
+        };
+
+        let mut response = GetReplicaStatusResponse {
+            replica_id: self.id,
+            is_witness: self.is_witness,
+            actor_health: actor_health.into(),
+            skipped_entry_count: self.skipped_entry_count,
+            ..Default::default()
+        };
+
+        // Ephemeral replica is not part of any cluster.
+        if !self.is_ephemeral {
+            let raft_state = self.raft.state();
+            let config_state = &self.raft_progress.config_state;
+            let role = if raft_state.leader_replica_id == self.id {
+                ReplicaRole::Leader
+            } else if config_state.voters.contains(&self.id)
+                || config_state.voters_outgoing.contains(&self.id)
+            {
+                ReplicaRole::Follower
+            } else if config_state.learners.contains(&self.id) {
+                ReplicaRole::Learner
+            } else {
+                ReplicaRole::Unspecified
+            };
+
+            response.role = role.into();
+            response.term = raft_state.leader_term;
+            response.leader_replica_id = raft_state.leader_replica_id;
+            response.committed_index = self.raft_progress.committed_index;
+            response.applied_index = self.raft_progress.applied_index;
+            response.voter_ids = config_state.voters.clone();
+            response.learner_ids = config_state.learners.clone();
+            response.snapshot_status = Some(get_replica_status_response::SnapshotStatus {
+                loaded_index: self.loaded_snapshot_index,
+                latest_size: self.raft.mut_store().latest_snapshot_size(),
+                sending_to_replica_ids: self
+                    .raft
+                    .followers_progress()
+                    .into_iter()
+                    .filter(|progress| progress.state == ReplicationState::Snapshot)
+                    .map(|progress| progress.replica_id)
+                    .collect(),
+            });
+        }
+
+        self.stash_message(out_message::Msg::GetReplicaStatus(response));
+
+        Ok(())
+    }
+
+    fn process_health_check(
+        &mut self,
+        health_check_request: &HealthCheckRequest,
+    ) -> Result<(), PalError> {
+        let not_ready_reason = self.check_readiness(health_check_request.max_apply_lag);
+        self.stash_message(out_message::Msg::HealthCheck(HealthCheckResponse {
+            live: self.driver_state != DriverState::Stopped,
+            ready: not_ready_reason == NotReadyReason::Unspecified,
+            not_ready_reason: not_ready_reason.into(),
+        }));
+
+        Ok(())
+    }
+
+    // Checks if the replica is able to serve the client proposals, returns the
+    // reason if it is not.
+    fn check_readiness(&self, max_apply_lag: u64) -> NotReadyReason {
+        if self.driver_state != DriverState::Started {
+            return NotReadyReason::NotStarted;
+        }
+        if self.is_witness {
+            return NotReadyReason::Witness;
+        }
+        if self.lame_duck.is_some() {
+            return NotReadyReason::ShuttingDown;
+        }
+        if self.is_ephemeral {
+            return NotReadyReason::Unspecified;
+        }
+
+        let applied_index = self.raft_progress.applied_index;
+        if self.restoring_actor || applied_index < self.replayed_index {
+            return NotReadyReason::Replaying;
+        }
+        if self.raft.state().leader_replica_id == 0 {
+            return NotReadyReason::NoLeader;
+        }
+        if self
+            .raft_progress
+            .committed_index
+            .saturating_sub(applied_index)
+            > max_apply_lag
+        {
+            return NotReadyReason::Lagging;
+        }
+
+        NotReadyReason::Unspecified
+    }
+
+    fn import_actor_snapshot(
+        &mut self,
+        imported_snapshot: ExportedSnapshot,
+    ) -> Result<(), PalError> {
+        info!(
+            self.logger,
+            "Importing snapshot at index {} and term {}",
+            imported_snapshot.index,
+            imported_snapshot.term
+        );
+
+        // Only the replicas holding the secret of the exporting cluster can import
+        // the snapshot, hence the host can't fabricate or alter it.
+        let Some(key) = self
+            .core
+            .borrow()
+            .cluster_key(EXPORTED_SNAPSHOT_KEY_CONTEXT)
+        else {
+            error!(
+                self.logger,
+                "Importing snapshot requires the secret of the exporting cluster"
+            );
+            return Err(PalError::InvalidOperation);
+        };
+        let snapshot = self.journal.import(imported_snapshot, &key).map_err(|e| {
+            error!(self.logger, "Failed to import snapshot: {}", e);
+
+            // Failure to import snapshot must lead to termination.
+            PalError::Raft
+        })?;
+
+        let snapshot = self.load_attestation_policy(snapshot)?;
+        self.load_actor_snapshot(snapshot)
+    }
+
+    fn process_export_snapshot(
+        &mut self,
+        _export_snapshot_request: &ExportSnapshotRequest,
+    ) -> Result<(), PalError> {
+        self.check_driver_started()?;
+        self.check_non_ephemeral()?;
+
+        // Witness replica snapshots carry no actor state to export.
+        let mut exported_snapshot = None;
+        if !self.is_witness {
+            let Some(key) = self
+                .core
+                .borrow()
+                .cluster_key(EXPORTED_SNAPSHOT_KEY_CONTEXT)
+            else {
+                error!(
+                    self.logger,
+                    "Exporting snapshot requires the cluster secret"
+                );
+                return Err(PalError::InvalidOperation);
+            };
+            let snapshot = self.raft.mut_store().latest_snapshot();
+            exported_snapshot = Some(self.journal.export(&snapshot, &key).map_err(|e| {
+                error!(self.logger, "Failed to export snapshot: {}", e);
+
+                // Failure to export snapshot must lead to termination.
+                PalError::Internal
+            })?);
+        }
+
+        self.stash_message(out_message::Msg::ExportSnapshot(ExportSnapshotResponse {
+            exported_snapshot,
+        }));
+
+        Ok(())
+    }
+
+    fn process_get_replication_status(
+        &mut self,
+        _get_replication_status_request: &GetReplicationStatusRequest,
+    ) -> Result<(), PalError> {
+        self.check_driver_started()?;
+        self.check_non_ephemeral()?;
+
+        let committed_index = self.raft_progress.committed_index;
+        let instant = self.clock.instant();
+        let followers = self
+            .raft
+            .followers_progress()
+            .into_iter()
+            .map(|progress| FollowerStatus {
+                replica_id: progress.replica_id,
+                state: progress.state.into(),
+                matched_index: progress.matched_index,
+                next_index: progress.next_index,
+                lag: committed_index.saturating_sub(progress.matched_index),
+                consecutive_rejections: self.probe_backoff.rejections(progress.replica_id),
+                backoff_remaining: self.probe_backoff.remaining(progress.replica_id, instant),
+                latency: self.flow_control.latency(progress.replica_id).unwrap_or(0),
+            })
+            .collect();
+
+        self.stash_message(out_message::Msg::GetReplicationStatus(
+            GetReplicationStatusResponse {
+                committed_index,
+                followers,
+            },
+        ));
+
+        Ok(())
+    }
+
+    fn process_secure_channel_handshake(
+        &mut self,
+        secure_channel_handshake: SecureChannelHandshake,
+    ) -> Result<(), PalError> {
+        self.check_driver_started()?;
+
+        self.communication
+            .process_in_message(in_message::Msg::SecureChannelHandshake(
+                secure_channel_handshake,
+            ))?;
+        self.accept_cluster_secret()
+    }
+
+    fn process_actor_raft_proposals(&mut self) -> Result<(), PalError> {
+        let proposals = self.mut_core().take_outputs();
+
+        // Proposals are counted by the events as they are applied one by one.
+        for (proposal, correlation_ids) in proposals {
+            self.queue_raft_proposal(proposal, correlation_ids);
+        }
+
+        // Batched proposals are counted individually as they are applied one by one.
+        let batch_correlation_ids = self.batcher.correlation_ids();
+        if let Some(batch) = self.batcher.take_ready(self.clock.instant()) {
+            self.queue_raft_proposal(batch, batch_correlation_ids);
+        }
+
+        self.make_queued_raft_proposals()
+    }
+
+    // Queued proposals count as pending, so that the mailbox holds back further
+    // messages while they wait for a leader.
+    fn queue_raft_proposal(&mut self, proposal: Bytes, correlation_ids: Vec<u64>) {
+        self.raft_progress.pending_proposals += correlation_ids.len() as u64;
+        self.queued_proposals.push_back((proposal, correlation_ids));
+    }
+
+    fn make_queued_raft_proposals(&mut self) -> Result<(), PalError> {
+        // Raft drops the proposals while there is no leader to forward them to,
+        // keep them queued in order until one is known.
+        if self.raft_state.leader_replica_id == 0 {
+            if !self.queued_proposals.is_empty() {
+                debug!(
+                    self.logger,
+                    "Queueing {} Raft proposals until a leader is known",
+                    self.queued_proposals.len()
+                );
+            }
+            return Ok(());
+        }
+
+        while let Some((proposal, correlation_ids)) = self.queued_proposals.pop_front() {
+            if self.make_raft_proposal(proposal)? {
+                self.proposed_events.extend(correlation_ids);
+            } else {
+                self.raft_progress.pending_proposals = self
+                    .raft_progress
+                    .pending_proposals
+                    .saturating_sub(correlation_ids.len() as u64);
+                for correlation_id in correlation_ids {
+                    self.fail_app_message(correlation_id, String::new(), StatusCode::Unavailable);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn propose_due_timers(&mut self) -> Result<(), PalError> {
+        if !self.check_raft_leadership() {
+            return Ok(());
+        }
+
+        let instant = self.clock.instant();
+        let fired_timers = self.mut_core().timers().take_due(instant);
+        if fired_timers.is_empty() {
+            return Ok(());
+        }
+
+        let mut entry = Entry {
+            entry_id: None,
+            entry_contents: Bytes::new(),
+            batched_entries: Vec::new(),
+            fired_timers,
+            random_seed: Bytes::new(),
+            skipped_index: 0,
+            schema_version_report: None,
+            activated_schema_version: 0,
+            actor_config_update: None,
+            skipped_positions: Vec::new(),
+            proposer_applied_index: 0,
+        };
+        if self.driver_config.replicated_randomness {
+            entry.random_seed = self.encrypt_random_seed(RandomSource::generate_seed())?;
+        }
+        if !self.make_raft_proposal(entry.encode_to_vec().into())? {
+            // Dropped timers are proposed again in the next cycle.
+            self.mut_core().timers().reset_proposed();
+        }
+
+        Ok(())
+    }
+
+    fn propose_schema_upgrade(&mut self) -> Result<(), PalError> {
+        // Proposals are dropped until there is a leader to forward them to.
+        if self.raft_state.leader_replica_id == 0 {
+            return Ok(());
+        }
+
+        let report = self.mut_core().schema().take_report(self.id);
+        if let Some(report) = report {
+            let entry = Entry {
+                schema_version_report: Some(report),
+                ..Default::default()
+            };
+            if !self.make_raft_proposal(entry.encode_to_vec().into())? {
+                self.mut_core().schema().reset_proposed();
+            }
+        }
+
+        if !self.check_raft_leadership() {
+            return Ok(());
+        }
+
+        let config_state = &self.raft_progress.config_state;
+        let replica_ids: Vec<u64> = config_state
+            .voters
+            .iter()
+            .chain(config_state.learners.iter())
+            .copied()
+            .collect();
+        let activation = self.mut_core().schema().take_activation(&replica_ids);
+        if let Some(schema_version) = activation {
+            let entry = Entry {
+                activated_schema_version: schema_version,
+                ..Default::default()
+            };
+            if !self.make_raft_proposal(entry.encode_to_vec().into())? {
+                self.mut_core().schema().reset_proposed();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn process_ephemeral_timers(&mut self) -> Result<(), PalError> {
+        // Ephemeral replica state is not replicated, hence timers fire immediately.
+        let instant = self.clock.instant();
+        let fired_timers = self.mut_core().timers().take_due(instant);
+        for fired_timer in fired_timers {
+            self.apply_fired_timer(0, fired_timer)?;
+        }
+
+        Ok(())
+    }
+
+    fn process_state_machine(&mut self) -> Result<(), PalError> {
+        if self.raft.initialized() {
+            // Advance Raft internal state.
+            self.advance_raft()?;
+
+            // Answer queries whose read index has been applied.
+            self.update_context_progress();
+            self.process_commit_watermark()?;
+            self.process_confirmed_reads()?;
+
+            // Fire the timers whose deadline has passed through the replicated log.
+            self.propose_due_timers()?;
+
+            // Advance the rolling upgrade of the snapshot schema.
+            self.propose_schema_upgrade()?;
+
+            // Limit appends in flight to the slow peers.
+            self.adjust_flow_control();
+
+            // Maybe create a snashot of the actor to reduce the size of the log.
+            self.maybe_create_raft_snapshot()?;
+
+            // If the leader state has changed send it out for observability.
+            self.stash_leader_state();
+
+            // Replicate skipping the entries the previous leaders have not.
+            self.propose_tombstones()?;
+
+            // Make the proposals queued until a leader is known.
+            self.make_queued_raft_proposals()?;
+
+            // Report outcome of the leadership transfer if it has concluded.
+            self.check_leadership_transfer();
+
+            // Hand over leadership if the witness replica has been elected.
+            self.maybe_hand_over_leadership();
+
+            // Remove replicas that have stopped responding.
+            self.maybe_evict_dead_replica()?;
+
+            // Acknowledge shutdown preparation once the replica has drained.
+            self.check_lame_duck()?;
+        }
+
+        Ok(())
+    }
+
+    fn process_prepare_shutdown(
+        &mut self,
+        _prepare_shutdown_request: &PrepareShutdownRequest,
+    ) -> Result<(), PalError> {
+        self.check_driver_started()?;
+
+        if self.lame_duck.is_some() {
+            // Shutdown preparation is already in progress or complete.
+            return Ok(());
+        }
+
+        info!(self.logger, "Entering lame-duck mode");
+
+        if self.is_ephemeral {
+            // Ephemeral replica has no log to drain or leadership to hand over.
+            self.lame_duck = Some(LameDuck {
+                deadline: self.clock.instant(),
+                acknowledged: true,
+            });
+            self.stash_message(out_message::Msg::PrepareShutdown(PrepareShutdownResponse {
+                applied_index: 0,
+            }));
+            return Ok(());
+        }
+
+        self.lame_duck = Some(LameDuck {
+            deadline: self.clock.instant()
+                + self.driver_config.election_tick * self.driver_config.tick_period,
+            acknowledged: false,
+        });
+
+        Ok(())
+    }
+
+    fn check_lame_duck(&mut self) -> Result<(), PalError> {
+        let Some(lame_duck) = &self.lame_duck else {
+            return Ok(());
+        };
+        if lame_duck.acknowledged {
+            return Ok(());
+        }
+
+        // Leadership must be handed over unless no other voter can take it over in time.
+        if self.check_raft_leadership() && self.clock.instant() < lame_duck.deadline {
+            return Ok(());
+        }
+
+        // Accepted application messages must be processed and applied.
+        if !self.mailbox.is_empty()
+            || !self.batcher.is_empty()
+            || self.raft_progress.pending_proposals > 0
+            || self.raft_progress.applied_index < self.raft_progress.committed_index
+        {
+            return Ok(());
+        }
+
+        // Capture the applied state in the final snapshot so that the replica
+        // restarts without replaying the log.
+        let applied_index = self.raft_progress.applied_index;
+        let snapshot_index = get_metadata(&self.raft.mut_store().latest_snapshot()).index;
+        if applied_index > snapshot_index {
+            self.create_raft_snapshot()?;
+        }
+
+        info!(
+            self.logger,
+            "Ready to shut down at applied index {}", applied_index
+        );
+
+        if let Some(lame_duck) = &mut self.lame_duck {
+            lame_duck.acknowledged = true;
+        }
+        self.stash_message(out_message::Msg::PrepareShutdown(PrepareShutdownResponse {
+            applied_index,
+        }));
+
+        Ok(())
+    }
+
+    fn process_commit_watermark(&mut self) -> Result<(), PalError> {
+        let committed_index = self.raft_progress.committed_index;
+        if !self.mut_core().take_commit_watermark(committed_index) {
+            return Ok(());
+        }
+
+        let event_outcome = self
+            .actor
+            .on_commit_watermark(committed_index)
+            .map_err(|e| {
+                error!(
+                    self.logger,
+                    "Failed to process commit watermark by actor: {}", e
+                );
+                // Failure to process commit watermark must lead to termination.
+                PalError::Actor
+            })?;
+
+        for actor_command in event_outcome.commands {
+            self.stash_message(out_message::Msg::DeliverAppMessage(DeliverAppMessage {
+                correlation_id: actor_command.correlation_id,
+                message_header: actor_command.header,
+                message_payload: actor_command.payload,
+                route: actor_command.route,
+                status_code: actor_command.status_code,
+            }));
+        }
+
+        Ok(())
+    }
+
+    fn maybe_evict_dead_replica(&mut self) -> Result<(), PalError> {
+        // Evict one replica at a time, only once the previous change has been applied.
+        if !self.failure_detector.enabled()
+            || !self.check_raft_leadership()
+            || self.raft_state.has_pending_change
+            || self.leadership_transfer.is_some()
+        {
+            return Ok(());
+        }
+
+        let config_state = &self.raft_progress.config_state;
+        let can_remove_voter = config_state.voters.len() > self.driver_config.min_voters as usize;
+        let peer_ids = config_state
+            .voters
+            .iter()
+            .filter(|_| can_remove_voter)
+            .chain(config_state.learners.iter())
+            .copied()
+            .filter(|peer_id| *peer_id != self.id);
+        let Some(dead_replica_id) = self
+            .failure_detector
+            .find_dead(peer_ids, self.clock.instant())
+        else {
+            return Ok(());
+        };
+
+        warn!(
+            self.logger,
+            "Evicting replica {}: not responding", dead_replica_id
+        );
+
+        self.make_raft_config_change_proposal(dead_replica_id, RaftConfigChangeType::RemoveNode)?;
+        // Wait for the full timeout again if the proposal has been dropped.
+        self.failure_detector.reset(self.clock.instant());
+
+        Ok(())
+    }
+
+    fn maybe_hand_over_leadership(&mut self) {
+        if self.lame_duck.is_none()
+            || self.leadership_transfer.is_some()
+            || !self.check_raft_leadership()
+        {
+            return;
+        }
+
+        // Replica in lame-duck mode is about to stop, hand over leadership to a
+        // voter that has caught up with the log.
+        let Some(target_replica_id) = self
+            .raft_progress
+            .config_state
+            .voters
+            .iter()
+            .copied()
+            .find(|voter_id| *voter_id != self.id && self.raft.replica_caught_up(*voter_id))
+        else {
+            return;
+        };
+
+        debug!(
+            self.logger,
+            "Handing over leadership to replica {}", target_replica_id
+        );
+
+        // Raft ignores the request while the transfer to the same replica is in
+        // progress and aborts the transfer after the election timeout, in which
+        // case it is requested again.
+        self.raft.transfer_leadership(target_replica_id);
+    }
+
+    fn take_out_messages(&mut self) -> Vec<OutMessage> {
+        // Take messages to be sent out in the order of their priority.
+        self.messages.take()
+    }
+
+    fn set_message_budgets(&mut self, message_priority_config: &MessagePriorityConfig) {
+        self.system_messages.set_budget(
+            MessageClass::Replication,
+            message_priority_config.replication_budget,
+        );
+        self.system_messages.set_budget(
+            MessageClass::Snapshot,
+            message_priority_config.snapshot_budget,
+        );
+        self.messages.set_budget(
+            MessageClass::Application,
+            message_priority_config.application_budget,
+        );
+    }
+
+    fn flush_system_messages(&mut self) -> Result<(), PalError> {
+        // Pass prioritized messages within budgets to the communication module, the rest
+        // will be passed on the next invocation.
+        for system_message in self.system_messages.take() {
+            self.communication.process_out_message(system_message)?;
+        }
+
+        Ok(())
+    }
+
+    fn stash_metrics_report(&mut self) {
+        let instant = self.clock.instant();
+        if self.driver_config.metrics_report_period == 0
+            || instant
+                < self
+                    .metrics_instant
+                    .saturating_add(self.driver_config.metrics_report_period)
+        {
+            return;
+        }
+        self.metrics_instant = instant;
+
+        let metrics = self.core.borrow().metrics();
+        if !self.is_ephemeral {
+            let raft_state = self.raft.state();
+            let applied_index = self.raft_progress.applied_index;
+            let committed_index = self.raft_progress.committed_index;
+            metrics.set_gauge(RAFT_TERM, raft_state.leader_term as i64);
+            metrics.set_gauge(RAFT_LEADER_ID, raft_state.leader_replica_id as i64);
+            metrics.set_gauge(RAFT_COMMIT_INDEX, committed_index as i64);
+            metrics.set_gauge(RAFT_APPLIED_INDEX, applied_index as i64);
+            metrics.set_gauge(
+                RAFT_APPLY_LAG,
+                committed_index.saturating_sub(applied_index) as i64,
+            );
+
+            let transfer_progress = match self.snapshot.mut_processor() {
+                SnapshotProcessorRole::Sender(sender) => sender.transfer_progress(),
+                SnapshotProcessorRole::Receiver(receiver) => receiver.transfer_progress(),
+            };
+            metrics.set_gauge(
+                SNAPSHOT_TRANSFER_PROGRESS,
+                transfer_progress.unwrap_or(100) as i64,
+            );
+        }
+        self.stash_message(out_message::Msg::MetricsReport(metrics.report()));
+    }
+
+    fn stash_sealed_secrets(&mut self) {
+        let Some(sealed_storage) = self.sealed_storage.clone() else {
+            return;
+        };
+        for sealed_secret in sealed_storage.take_sealed() {
+            self.stash_message(out_message::Msg::PersistSealedSecret(sealed_secret));
+        }
+    }
+
+    fn stash_log_entries(&mut self) {
+        let entries = self.logger_output.take_entries();
+        for log_message in self.log_sink.process(self.clock.instant(), entries) {
+            self.stash_message(out_message::Msg::Log(log_message));
+        }
+    }
+
+    fn stash_comms_module_entries(&mut self) {
+        for message in self.communication.take_out_messages() {
+            let message_class = match &message.msg {
+                Some(msg) => MessageClass::from_out_message(msg),
+                None => MessageClass::Control,
+            };
+            self.messages.push(message_class, message);
+        }
+    }
+
+    fn stash_message(&mut self, message: out_message::Msg) {
+        self.messages.push(
+            MessageClass::from_out_message(&message),
+            OutMessage { msg: Some(message) },
+        );
+    }
+
+    fn stash_system_message(&mut self, message_class: MessageClass, message: out_message::Msg) {
+        self.system_messages.push(message_class, message);
+    }
+
+    fn stash_snapshot(&mut self, snapshot_message: RaftMessage) {
+        self.snapshots.push(snapshot_message);
+    }
+}
+
+impl<
+        R: Raft<S = S>,
+        S: Store + RaftStorage,
+        P: SnapshotProcessor,
+        A: Actor,
+        C: CommunicationModule,
+    > Application for Driver<R, S, P, A, C>
+{
+    /// Handles messages received from the trusted host.
+    fn receive_message(
+        &mut self,
+        host: &mut impl Host,
+        instant: u64,
+        opt_message: Option<InMessage>,
+    ) -> Result<(), PalError> {
+        // Update state of the context that will remain unchanged while messages are
+        // dispatched for processing.
+        self.preset_state_machine(instant, host.wall_time(), host.signed_wall_time());
+
+        // Dispatch incoming message for processing.
+        if let Some(deserialized_message) = opt_message {
+            match deserialized_message.msg {
+                None => {
+                    warn!(self.logger, "Rejecting message: unknown");
+                    // Ignore unknown message.
+                    return Ok(());
+                }
+                Some(mut message) => {
+                    match message {
+                        in_message::Msg::StartReplica(ref mut start_node_request) => {
+                            self.process_start_node(start_node_request, host.public_signing_key())
+                        }
+                        in_message::Msg::StopReplica(ref stop_node_request) => {
+                            self.process_stop_node(stop_node_request)
+                        }
+                        in_message::Msg::ChangeCluster(ref change_cluster_request) => {
+                            self.process_change_cluster(change_cluster_request)
+                        }
+                        in_message::Msg::CheckCluster(ref check_cluster_request) => {
+                            self.process_check_cluster(check_cluster_request)
+                        }
+                        in_message::Msg::TransferLeadership(ref transfer_leadership_request) => {
+                            self.process_transfer_leadership(transfer_leadership_request)
+                        }
+                        in_message::Msg::ReconfigureCluster(ref reconfigure_cluster_request) => {
+                            self.process_reconfigure_cluster(reconfigure_cluster_request)
+                        }
+                        in_message::Msg::PrepareShutdown(ref prepare_shutdown_request) => {
+                            self.process_prepare_shutdown(prepare_shutdown_request)
+                        }
+                        in_message::Msg::GetReplicationStatus(
+                            ref get_replication_status_request,
+                        ) => self.process_get_replication_status(get_replication_status_request),
+                        in_message::Msg::ExportSnapshot(ref export_snapshot_request) => {
+                            self.process_export_snapshot(export_snapshot_request)
+                        }
+                        in_message::Msg::UpdateActorConfig(update_actor_config_request) => {
+                            self.process_update_actor_config(update_actor_config_request)
+                        }
+                        in_message::Msg::DeliverSystemMessage(deliver_system_message) => {
+                            self.process_deliver_system_message(deliver_system_message)
+                        }
+                        in_message::Msg::DeliverSnapshotRequest(deliver_snapshot_request) => {
+                            self.process_deliver_snapshot_request(deliver_snapshot_request)
+                        }
+                        in_message::Msg::DeliverSnapshotResponse(deliver_snapshot_response) => {
+                            self.process_deliver_snapshot_response(deliver_snapshot_response)
+                        }
+                        in_message::Msg::DeliverSnapshotFailure(deliver_snapshot_failure) => {
+                            self.process_deliver_snapshot_failure(deliver_snapshot_failure)
+                        }
+                        in_message::Msg::GetReplicaState(ref get_replica_state_request) => {
+                            self.process_get_replica_state(get_replica_state_request)
+                        }
+                        in_message::Msg::GetReplicaStatus(ref get_replica_status_request) => {
+                            self.process_get_replica_status(get_replica_status_request)
+                        }
+                        in_message::Msg::HealthCheck(ref health_check_request) => {
+                            self.process_health_check(health_check_request)
+                        }
+                        in_message::Msg::SecureChannelHandshake(secure_channel_handshake) => {
+                            self.process_secure_channel_handshake(secure_channel_handshake)
+                        }
+                        in_message::Msg::DeliverAppMessage(deliver_app_message) => {
+                            // App messages are queued in the mailbox and passed to the
+                            // actor one per invocation.
+                            if self.driver_state == DriverState::Started && !self.is_witness {
+                                self.enqueue_app_message(deliver_app_message);
+                            }
+                            Ok(())
+                        }
+                    }?;
+                }
+            };
+        }
+        if self.driver_state == DriverState::Started {
+            // Witness replica has no actor to pass application messages to.
+            if !self.is_witness {
+                let deliver_app_message_opt = self.dequeue_app_message();
+                self.process_deliver_app_message(deliver_app_message_opt)?;
+            }
+            if self.is_ephemeral {
+                self.process_ephemeral_timers()?;
+            }
+            self.communication.make_tick();
+        }
+
+        if !self.is_ephemeral {
+            // Processes outputs from the actor to make raft proposals.
+            self.process_actor_raft_proposals()?;
+
+            // Process snapshot transfer completion or failures.
+            self.process_snapshot_progress();
+
+            // Advance the Raft and collect results messages.
+            self.process_state_machine()?;
+
+            // Initiate if needed snapshot sending.
+            self.process_snapshot_sending();
+
+            // Pass messages to peers to the communication module in the order of priority.
+            self.flush_system_messages()?;
+        }
+
+        // Send out responses to the pending commands the actor has completed.
+        self.process_completed_commands();
+
+        // Send out messages the actor has sent on its own initiative.
+        self.process_sent_app_messages();
+
+        if self.driver_state == DriverState::Started {
+            self.stash_metrics_report();
+        }
+
+        // Sealed secrets must be persisted before the messages that depend on them
+        // are delivered.
+        self.stash_sealed_secrets();
+        self.stash_log_entries();
+        self.stash_comms_module_entries();
+
+        // Send messages to Raft peers and consumers through the trusted host.
+        host.send_messages(self.take_out_messages());
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    extern crate mockall;
+    extern crate spin;
+
+    use crate::{
+        clock::HostClock,
+        consensus::{RaftLightReady, RaftReady},
+        mock::{MockSnapshotReceiver, MockSnapshotSender},
+        model::{CommandOutcome, EventOutcome},
+        recovery::StateJournal,
+        snapshot::DefaultSnapshotProcessor,
+        util::raft::{
+            create_empty_raft_entry, create_entry_id, create_raft_config_state, create_raft_entry,
+            create_raft_message, create_raft_snapshot, create_raft_snapshot_metadata,
+            serialize_config_change,
+        },
+    };
+
+    use self::mockall::predicate::{always, eq};
+    use self::mockall::Sequence;
+    use super::*;
+    use mock::{MockActor, MockCommunicationModule, MockHost, MockRaft, MockStore};
+    use model::ActorError;
+    use raft::eraftpb::{
+        ConfChange as RaftConfigChange, ConfChangeV2 as RaftConfigChangeV2,
+        EntryType as RaftEntryType, MessageType as RaftMessageType,
+    };
+    use tcp_proto::runtime::endpoint::metric;
+    use tcp_proto::runtime::endpoint::raft_config::{
+        ActorErrorPolicy, FlowControlConfig, MailboxConfig, ShedPolicy, SnapshotConfig,
+    };
+
+    const REPLICA_1: u64 = 1;
+    const REPLICA_2: u64 = 2;
+    const REPLICA_3: u64 = 3;
+
+    fn create_default_parameters() -> (u64, u64, RaftConfig) {
+        let node_id = REPLICA_1;
+        let instant = 100;
+
+        let raft_config = RaftConfig {
+            tick_period: 100,
+            election_tick: 20,
+            heartbeat_tick: 2,
+            max_size_per_msg: 0,
+            snapshot_config: Some(SnapshotConfig {
+                snapshot_count: 10,
+                chunk_size: 20,
+                max_pending_chunks: 2,
+                delta_transfer: false,
+                max_delivery_retries: 0,
+                encrypt_payloads: false,
+            }),
+            handshake_retry_tick: 1,
+            message_priority_config: None,
+            mailbox_config: None,
+            flow_control_config: Some(FlowControlConfig {
+                max_committed_size_per_ready: 1 << 20,
+                max_uncommitted_size: 1 << 22,
+                ..Default::default()
+            }),
+            persist_replica_state: false,
+            follower_read_config: None,
+            pre_vote: true,
+            check_quorum: true,
+            lease_based_reads: false,
+            max_clock_drift_ppm: 0,
+            proposal_batch_config: None,
+            eviction_config: None,
+            min_election_tick: 20,
+            max_election_tick: 40,
+            compaction_config: None,
+            replicated_randomness: false,
+            actor_error_policy: ActorErrorPolicy::Unspecified.into(),
+            max_wall_clock_skew: 0,
+            attestation_policy: None,
+            rekey_config: None,
+            log_config: None,
+            metrics_config: None,
+            share_cluster_secret: false,
+        };
+
+        (node_id, instant, raft_config)
+    }
+
+    fn create_default_raft_state(node_id: u64) -> RaftState {
+        RaftState {
+            leader_replica_id: node_id,
+            leader_term: 1,
+            committed_cluster_config: vec![node_id],
+            committed_cluster_learners: vec![],
+            committed_cluster_outgoing_config: vec![],
+            has_pending_change: false,
+        }
+    }
+
+    fn create_raft_state(leader_replica_id: u64, committed_cluster_config: Vec<u64>) -> RaftState {
+        RaftState {
+            leader_replica_id,
+            leader_term: 1,
+            committed_cluster_config,
+            committed_cluster_learners: vec![],
+            committed_cluster_outgoing_config: vec![],
+            has_pending_change: false,
+        }
+    }
+
+    fn create_start_replica_request(
+        raft_config: RaftConfig,
+        leader: bool,
+        replica_id_hint: u64,
+        app_config: Bytes,
+    ) -> InMessage {
+        let envelope = InMessage {
+            msg: Some(in_message::Msg::StartReplica(StartReplicaRequest {
+                is_leader: leader,
+                replica_id_hint,
+                raft_config: Some(raft_config),
+                app_config: app_config,
+                attestation_config: None,
+                is_ephemeral: false,
+                recovery_state: None,
+                is_witness: false,
+                imported_snapshot: None,
+                sealed_secrets: vec![],
+            })),
+        };
+        envelope
+    }
+
+    fn create_start_replica_response(replica_id: u64) -> out_message::Msg {
+        out_message::Msg::StartReplica(StartReplicaResponse { replica_id })
+    }
+
+    fn create_stop_replica_request() -> InMessage {
+        let envelope = InMessage {
+            msg: Some(in_message::Msg::StopReplica(StopReplicaRequest {})),
+        };
+        envelope
+    }
+
+    fn create_stop_replica_response() -> out_message::Msg {
+        out_message::Msg::StopReplica(StopReplicaResponse {})
+    }
+
+    fn create_in_deliver_app_message(correlation_id: u64, message_header: Bytes) -> InMessage {
+        let envelope = InMessage {
+            msg: Some(in_message::Msg::DeliverAppMessage(DeliverAppMessage {
+                correlation_id,
+                message_header,
+                message_payload: Bytes::new(),
+                route: String::new(),
+                status_code: 0,
+            })),
+        };
+        envelope
+    }
+
+    fn create_out_deliver_app_message(
+        correlation_id: u64,
+        message_header: Bytes,
+    ) -> out_message::Msg {
+        out_message::Msg::DeliverAppMessage(DeliverAppMessage {
+            correlation_id,
+            message_header,
+            message_payload: Bytes::new(),
+            route: String::new(),
+            status_code: 0,
+        })
+    }
+
+    fn create_change_cluster_request(replica_id: u64, change_type: ChangeClusterType) -> InMessage {
+        let envelope = InMessage {
+            msg: Some(in_message::Msg::ChangeCluster(ChangeClusterRequest {
+                change_id: 1,
+                replica_id,
+                change_type: change_type.into(),
+            })),
+        };
+        envelope
+    }
+
+    fn create_change_cluster_response(change_status: ChangeClusterStatus) -> out_message::Msg {
+        out_message::Msg::ChangeCluster(ChangeClusterResponse {
+            change_id: 1,
+            change_status: change_status.into(),
+        })
+    }
+
+    fn create_reconfigure_cluster_request(
+        add_replica_ids: Vec<u64>,
+        remove_replica_ids: Vec<u64>,
+    ) -> InMessage {
+        InMessage {
+            msg: Some(in_message::Msg::ReconfigureCluster(
+                ReconfigureClusterRequest {
+                    change_id: 1,
+                    add_replica_ids,
+                    remove_replica_ids,
+                },
+            )),
+        }
+    }
+
+    fn create_transfer_leadership_request(target_replica_id: u64) -> InMessage {
+        InMessage {
+            msg: Some(in_message::Msg::TransferLeadership(
+                TransferLeadershipRequest {
+                    transfer_id: 1,
+                    target_replica_id,
+                },
+            )),
+        }
+    }
+
+    fn create_transfer_leadership_response(
+        transfer_status: TransferLeadershipStatus,
+    ) -> out_message::Msg {
+        out_message::Msg::TransferLeadership(TransferLeadershipResponse {
+            transfer_id: 1,
+            transfer_status: transfer_status.into(),
+        })
+    }
+
+    fn create_check_cluster_request() -> InMessage {
+        let envelope = InMessage {
+            msg: Some(in_message::Msg::CheckCluster(CheckClusterRequest {})),
+        };
+        envelope
+    }
+
+    fn create_check_cluster_response(raft_state: &RaftState) -> out_message::Msg {
+        out_message::Msg::CheckCluster(CheckClusterResponse {
+            leader_replica_id: raft_state.leader_replica_id,
+            leader_term: raft_state.leader_term,
+            cluster_replica_ids: raft_state.committed_cluster_config.clone(),
+            has_pending_changes: raft_state.has_pending_change,
+            cluster_learner_ids: raft_state.committed_cluster_learners.clone(),
+            outgoing_replica_ids: raft_state.committed_cluster_outgoing_config.clone(),
+        })
+    }
+
+    fn create_get_replica_state_request() -> InMessage {
+        let envelope = InMessage {
+            msg: Some(in_message::Msg::GetReplicaState(GetReplicaStateRequest {})),
+        };
+        envelope
+    }
+
+    fn create_get_replica_state_response(
+        applied_index: u64,
+        latest_snapshot_size: u64,
+    ) -> out_message::Msg {
+        out_message::Msg::GetReplicaState(GetReplicaStateResponse {
+            applied_index,
+            latest_snapshot_size,
+            mailbox_size: 0,
+            shed_message_count: 0,
+        })
+    }
+
+    fn create_secure_channel_handshake(
+        sender_replica_id: u64,
+        recipient_replica_id: u64,
+    ) -> SecureChannelHandshake {
+        SecureChannelHandshake {
+            recipient_replica_id,
+            sender_replica_id,
+            encryption: None,
+        }
+    }
+
+    fn create_deliver_system_message_request(raft_message: &RaftMessage) -> InMessage {
+        let envelope = InMessage {
+            msg: Some(in_message::Msg::DeliverSystemMessage(
+                DeliverSystemMessage {
+                    recipient_replica_id: raft_message.to,
+                    sender_replica_id: raft_message.from,
+                    message_contents: serialize_raft_message(raft_message).unwrap(),
+                },
+            )),
+        };
+        envelope
+    }
+
+    fn create_deliver_system_message_response(raft_message: &RaftMessage) -> out_message::Msg {
+        out_message::Msg::DeliverSystemMessage(DeliverSystemMessage {
+            recipient_replica_id: raft_message.to,
+            sender_replica_id: raft_message.from,
+            message_contents: serialize_raft_message(raft_message).unwrap(),
+        })
+    }
+
+    fn create_send_messages_matcher(
+        expected: Vec<out_message::Msg>,
+    ) -> impl Fn(&Vec<OutMessage>) -> bool {
+        move |envelopes: &Vec<OutMessage>| {
+            let actual: Vec<out_message::Msg> = envelopes
+                .iter()
+                .map(|m| m.msg.clone().unwrap())
+                .filter(|m| {
+                    if let out_message::Msg::Log(_) = m {
+                        return false;
+                    };
+                    true
+                })
+                .collect();
+            expected.iter().all(|e| actual.contains(e)) && expected.len() == actual.len()
+        }
+    }
+
+    fn wrap_deliver_snapshot_request_out(request: DeliverSnapshotRequest) -> out_message::Msg {
+        out_message::Msg::DeliverSnapshotRequest(request)
+    }
+
+    fn wrap_deliver_snapshot_request_in(request: DeliverSnapshotRequest) -> InMessage {
+        InMessage {
+            msg: Some(in_message::Msg::DeliverSnapshotRequest(request)),
+        }
+    }
+
+    fn create_deliver_snapshot_request(
+        recipient_id: u64,
+        sender_id: u64,
+        delivery_id: u64,
+    ) -> DeliverSnapshotRequest {
+        DeliverSnapshotRequest {
+            recipient_replica_id: recipient_id,
+            sender_replica_id: sender_id,
+            delivery_id,
+            payload_contents: vec![4, 5, 6, 7, 8, 9].into(),
+        }
+    }
+
+    fn wrap_deliver_snapshot_response_out(response: DeliverSnapshotResponse) -> out_message::Msg {
+        out_message::Msg::DeliverSnapshotResponse(response)
+    }
+
+    fn wrap_deliver_snapshot_response_in(response: DeliverSnapshotResponse) -> InMessage {
+        InMessage {
+            msg: Some(in_message::Msg::DeliverSnapshotResponse(response)),
+        }
+    }
+
+    fn create_deliver_snapshot_response(
+        recipient_id: u64,
+        sender_id: u64,
+        delivery_id: u64,
+    ) -> DeliverSnapshotResponse {
+        DeliverSnapshotResponse {
+            recipient_replica_id: recipient_id,
+            sender_replica_id: sender_id,
+            delivery_id,
+            payload_contents: vec![6, 7, 8, 9].into(),
+        }
+    }
+
+    fn wrap_deliver_snapshot_failure_in(failure: DeliverSnapshotFailure) -> InMessage {
+        InMessage {
+            msg: Some(in_message::Msg::DeliverSnapshotFailure(failure)),
+        }
+    }
+
+    fn create_deliver_snapshot_failure(sender_id: u64, delivery_id: u64) -> DeliverSnapshotFailure {
+        DeliverSnapshotFailure {
+            sender_replica_id: sender_id,
+            delivery_id,
+        }
+    }
+
+    const DELIVERY_1: u64 = 1;
+    const DELIVERY_2: u64 = 2;
+
+    struct MockHostBuilder {
+        mock_host: MockHost,
+    }
+
+    impl MockHostBuilder {
+        fn new() -> MockHostBuilder {
+            MockHostBuilder {
+                mock_host: MockHost::new(),
+            }
+        }
+
+        fn expect_public_signing_key(
+            &mut self,
+            public_signing_key: Vec<u8>,
+        ) -> &mut MockHostBuilder {
+            self.mock_host
+                .expect_public_signing_key()
+                .return_once(move || public_signing_key);
+            self
+        }
+
+        fn expect_send_messages(
+            &mut self,
+            sent_messages: Vec<out_message::Msg>,
+        ) -> &mut MockHostBuilder {
+            self.mock_host
+                .expect_send_messages()
+                .withf(create_send_messages_matcher(sent_messages))
+                .return_const(());
+
+            self
+        }
+
+        fn take(&mut self) -> MockHost {
+            mem::take(&mut self.mock_host)
+        }
+    }
+
+    struct RaftBuilder {
+        mock_store: MockStore,
+        mock_raft: MockRaft<MockStore>,
+    }
+
+    impl RaftBuilder {
+        fn new() -> RaftBuilder {
+            RaftBuilder {
+                mock_store: MockStore::new(),
+                mock_raft: MockRaft::new(),
+            }
+        }
+
+        fn expect_leader(mut self, leader: bool) -> RaftBuilder {
+            self.mock_raft.expect_leader().return_const(leader);
+
+            self
+        }
+
+        fn expect_init(
+            mut self,
+            handler: impl Fn(u64, &raft::Config, Bytes, bool, MockStore, &Logger) -> Result<(), RaftError>
+                + 'static,
+        ) -> RaftBuilder {
+            self.mock_raft.expect_init().return_once_st(handler);
+            self.mock_raft.expect_initialized().returning(|| true);
+
+            self
+        }
+
+        fn expect_should_snapshot(mut self, should_snapshot: bool) -> RaftBuilder {
+            self.mock_store
+                .expect_should_snapshot()
+                .return_const(should_snapshot);
+            self
+        }
+
+        fn expect_latest_snapshot_size(mut self, latest_snapshot_size: u64) -> RaftBuilder {
+            self.mock_store
+                .expect_latest_snapshot_size()
+                .return_const(latest_snapshot_size);
+            self
+        }
+
+        fn expect_apply_snapshot(
+            mut self,
+            snapshot: RaftSnapshot,
+            handler: impl Fn(RaftSnapshot) -> Result<(), RaftError> + 'static,
+        ) -> RaftBuilder {
+            self.mock_store
+                .expect_apply_snapshot()
+                .with(eq(snapshot))
+                .return_once_st(handler);
+            self
+        }
+
+        fn expect_append_entries(
+            mut self,
+            entries: Vec<RaftEntry>,
+            handler: impl Fn(Vec<RaftEntry>) -> Result<(), RaftError> + 'static,
+        ) -> RaftBuilder {
+            self.mock_store
+                .expect_append_entries()
+                .with(eq(entries))
+                .return_once_st(handler);
+            self
+        }
+
+        fn expect_create_snapshot(
+            mut self,
+            applied_index: u64,
+            config_state: RaftConfigState,
+            snapshot_data: Bytes,
+            result: Result<(), RaftError>,
+        ) -> RaftBuilder {
+            self.mock_store
+                .expect_create_snapshot()
+                .with(eq(applied_index), eq(config_state), eq(snapshot_data))
+                .return_once_st(|_, _, _| result);
+            self
+        }
+
+        fn expect_make_tick(mut self) -> RaftBuilder {
+            self.mock_raft.expect_make_tick().once().return_const(());
+            self
+        }
+
+        fn expect_make_step(
+            mut self,
+            raft_message: &RaftMessage,
+            result: Result<(), RaftError>,
+        ) -> RaftBuilder {
+            self.mock_raft
+                .expect_make_step()
+                .with(eq(raft_message.clone()))
+                .return_once_st(move |_| result);
+            self
+        }
+
+        fn expect_has_ready(mut self, has_ready: bool) -> RaftBuilder {
+            self.mock_raft
+                .expect_has_ready()
+                .once()
+                .return_const(has_ready);
+            self
+        }
+
+        fn expect_state(mut self, raft_state: &RaftState) -> RaftBuilder {
+            self.mock_raft
+                .expect_state()
+                .return_const(raft_state.clone());
+            self
+        }
+
+        fn expect_state_once(mut self, raft_state: &RaftState) -> RaftBuilder {
+            self.mock_raft
+                .expect_state()
+                .once()
+                .return_const(raft_state.clone());
+            self
+        }
+
+        fn expect_make_proposal(
+            mut self,
+            proposal: Entry,
+            handler: impl Fn(Bytes) -> Result<(), RaftError> + 'static,
+        ) -> RaftBuilder {
+            self.mock_raft
+                .expect_make_proposal()
+                .with(eq(Bytes::from(proposal.encode_to_vec())))
+                .returning_st(handler);
+
+            self
+        }
+
+        fn expect_make_config_change_proposal(
+            mut self,
+            config_change: RaftConfigChange,
+            handler: impl Fn(RaftConfigChange) -> Result<(), RaftError> + 'static,
+        ) -> RaftBuilder {
+            self.mock_raft
+                .expect_make_config_change_proposal()
+                .with(eq(config_change))
+                .returning_st(handler);
+
+            self
+        }
+
+        fn expect_make_config_change_v2_proposal(
+            mut self,
+            config_change: RaftConfigChangeV2,
+            handler: impl Fn(RaftConfigChangeV2) -> Result<(), RaftError> + 'static,
+        ) -> RaftBuilder {
+            self.mock_raft
+                .expect_make_config_change_v2_proposal()
+                .with(eq(config_change))
+                .returning_st(handler);
+
+            self
+        }
+
+        fn expect_ready(mut self, ready: &RaftReady) -> RaftBuilder {
+            let ready = ready.clone();
+            self.mock_raft
+                .expect_get_ready()
+                .once()
+                .return_once(move || ready);
+            self
+        }
+
+        fn expect_committed_index(mut self, committed_index: u64) -> RaftBuilder {
+            self.mock_raft
+                .expect_committed_index()
+                .return_const(committed_index);
+            self
+        }
+
+        fn expect_apply_config_change(
+            mut self,
+            config_change: &RaftConfigChange,
+            result: Result<RaftConfigState, RaftError>,
+        ) -> RaftBuilder {
+            let config_change = config_change.clone();
+            self.mock_raft
+                .expect_apply_config_change()
+                .with(eq(config_change))
+                .once()
+                .return_once_st(move |_| result);
+            self
+        }
+
+        fn expect_advance_ready(
+            mut self,
+            ready_number: u64,
+            light_ready: RaftLightReady,
+        ) -> RaftBuilder {
+            self.mock_raft
+                .expect_advance_ready()
+                .withf_st(move |ready| ready.number() == ready_number)
+                .return_const(light_ready);
+            self
+        }
+
+        fn expect_advance_apply(mut self) -> RaftBuilder {
+            self.mock_raft.expect_advance_apply().return_const(());
+            self
+        }
+
+        fn expect_report_snapshot(
+            mut self,
+            replica_id: u64,
+            status: RaftSnapshotStatus,
+        ) -> RaftBuilder {
+            self.mock_raft
+                .expect_report_snapshot()
+                .with(eq(replica_id), eq(status))
+                .once()
+                .return_const(());
+            self
+        }
+
+        fn take(&mut self) -> (MockStore, MockRaft<MockStore>) {
+            (
+                mem::take(&mut self.mock_store),
+                mem::take(&mut self.mock_raft),
+            )
+        }
+    }
+
+    struct SnapshotBuilder {
+        mock_snapshot_sender: MockSnapshotSender,
+        mock_snapshot_receiver: MockSnapshotReceiver,
+    }
+
+    impl SnapshotBuilder {
+        fn new() -> SnapshotBuilder {
+            SnapshotBuilder {
+                mock_snapshot_sender: MockSnapshotSender::new(),
+                mock_snapshot_receiver: MockSnapshotReceiver::new(),
+            }
+        }
+
+        fn expect_init(mut self, replica_id: u64) -> SnapshotBuilder {
+            let (_, _, raft_config) = create_default_parameters();
+            self.mock_snapshot_sender
+                .expect_init()
+                .with(
+                    always(),
+                    eq(replica_id),
+                    eq(raft_config.snapshot_config.clone()),
+                )
+                .return_const(());
+
+            self.mock_snapshot_receiver
+                .expect_init()
+                .with(always(), eq(replica_id), eq(raft_config.snapshot_config))
+                .return_const(());
+
+            self
+        }
+
+        fn expect_receiver_try_complete(
+            mut self,
+            result: Option<Result<(u64, RaftSnapshot), SnapshotError>>,
+        ) -> SnapshotBuilder {
+            self.mock_snapshot_receiver
+                .expect_try_complete()
+                .once()
+                .return_once(|| result);
+
+            self
+        }
+
+        fn expect_receiver_process_request(
+            mut self,
+            request: DeliverSnapshotRequest,
+            response: DeliverSnapshotResponse,
+        ) -> SnapshotBuilder {
+            self.mock_snapshot_receiver
+                .expect_process_request()
+                .with(eq(request))
+                .once()
+                .return_const(response);
+
+            self
+        }
+
+        fn expect_receiver_reset(mut self) -> SnapshotBuilder {
+            self.mock_snapshot_receiver.expect_reset().return_const(());
+
+            self
+        }
+
+        fn expect_sender_process_response(
+            mut self,
+            sender_id: u64,
+            delivery_id: u64,
+            response: Result<DeliverSnapshotResponse, SnapshotError>,
+        ) -> SnapshotBuilder {
+            self.mock_snapshot_sender
+                .expect_process_response()
+                .with(eq(sender_id), eq(delivery_id), eq(response))
+                .once()
+                .return_const(());
+            self
+        }
+
+        fn expect_sender_next_request(
+            mut self,
+            request: Option<DeliverSnapshotRequest>,
+        ) -> SnapshotBuilder {
+            self.mock_snapshot_sender
+                .expect_next_request()
+                .once()
+                .return_once(|| request);
+
+            self
+        }
+
+        fn expect_sender_try_complete(
+            mut self,
+            result: Option<(u64, RaftSnapshotStatus)>,
+        ) -> SnapshotBuilder {
+            self.mock_snapshot_sender
+                .expect_try_complete()
+                .once()
+                .return_once(move || result);
+
+            self
+        }
+
+        fn expect_sender_start(
+            mut self,
+            recipient_id: u64,
+            snapshot: RaftSnapshot,
+        ) -> SnapshotBuilder {
+            self.mock_snapshot_sender
+                .expect_start()
+                .with(eq(recipient_id), eq(snapshot))
+                .once()
+                .return_const(());
+
+            self
+        }
+
+        fn take(mut self) -> (MockSnapshotSender, MockSnapshotReceiver) {
+            (
+                mem::take(&mut self.mock_snapshot_sender),
+                mem::take(&mut self.mock_snapshot_receiver),
+            )
+        }
+    }
+
+    struct CommunicationBuilder {
+        mock_communication_module: MockCommunicationModule,
+    }
+
+    impl CommunicationBuilder {
+        fn new() -> CommunicationBuilder {
+            CommunicationBuilder {
+                mock_communication_module: MockCommunicationModule::new(),
+            }
+        }
+
+        fn expect_init(mut self, replica_id: u64) -> CommunicationBuilder {
+            self.mock_communication_module
+                .expect_init()
+                .with(eq(replica_id), always(), always())
+                .once()
+                .return_const(());
+
+            self
+        }
+
+        fn expect_update_attestation_policy(
+            mut self,
+            policy: AttestationPolicy,
+        ) -> CommunicationBuilder {
+            self.mock_communication_module
+                .expect_update_attestation_policy()
+                .with(eq(policy))
+                .once()
+                .return_const(());
+            self
+        }
+
+        fn expect_make_tick(mut self) -> CommunicationBuilder {
+            self.mock_communication_module
+                .expect_make_tick()
+                .once()
+                .return_const(());
+            self
+        }
+
+        fn expect_process_out_message(
+            mut self,
+            message: out_message::Msg,
+            result: Result<(), PalError>,
+        ) -> CommunicationBuilder {
+            self.mock_communication_module
+                .expect_process_out_message()
+                .with(eq(message))
+                .return_once_st(|_| result);
+
+            self
+        }
+
+        fn expect_process_in_message(
+            mut self,
+            message: in_message::Msg,
+            result: Result<Option<in_message::Msg>, PalError>,
+        ) -> CommunicationBuilder {
+            self.mock_communication_module
+                .expect_process_in_message()
+                .with(eq(message))
+                .return_once_st(|_| result);
+
+            self
+        }
+
+        fn expect_take_out_messages(mut self, messages: Vec<OutMessage>) -> CommunicationBuilder {
+            self.mock_communication_module
+                .expect_take_out_messages()
+                .once()
+                .return_const(messages);
+
+            self
+        }
+
+        fn expect_request_cluster_secret(mut self) -> CommunicationBuilder {
+            self.mock_communication_module
+                .expect_request_cluster_secret()
+                .once()
+                .return_const(());
+            self
+        }
+
+        fn expect_take_cluster_secret(
+            mut self,
+            cluster_secret: Option<Vec<u8>>,
+        ) -> CommunicationBuilder {
+            self.mock_communication_module
+                .expect_take_cluster_secret()
+                .once()
+                .return_const(cluster_secret);
+            self
+        }
+
+        fn expect_set_cluster_secret(mut self, cluster_secret: Vec<u8>) -> CommunicationBuilder {
+            self.mock_communication_module
+                .expect_set_cluster_secret()
+                .with(eq(cluster_secret))
+                .once()
+                .return_const(());
+            self
+        }
+
+        fn expect_process_cluster_change(mut self, replicas: Vec<u64>) -> CommunicationBuilder {
+            self.mock_communication_module
+                .expect_process_cluster_change()
+                .with(eq(replicas))
+                .once()
+                .return_const(());
+            self
+        }
+
+        fn take(mut self) -> MockCommunicationModule {
+            mem::take(&mut self.mock_communication_module)
+        }
+    }
+
+    struct DriverBuilder {
+        mock_actor: MockActor,
+    }
+
+    impl DriverBuilder {
+        fn new() -> DriverBuilder {
+            DriverBuilder {
+                mock_actor: MockActor::new(),
+            }
+        }
+
+        fn expect_on_init(
+            &mut self,
+            init_handler: impl Fn(Box<dyn ActorContext>) -> Result<(), ActorError> + 'static,
+        ) -> &mut DriverBuilder {
+            self.mock_actor
+                .expect_on_init()
+                .return_once_st(init_handler);
+
+            self
+        }
+
+        fn expect_on_shutdown(&mut self) -> &mut DriverBuilder {
+            self.mock_actor.expect_on_shutdown().once().return_const(());
+
+            self
+        }
+
+        fn expect_on_process_command(
+            &mut self,
+            command: Option<ActorCommand>,
+            result: Result<CommandOutcome, ActorError>,
+        ) -> &mut DriverBuilder {
+            self.mock_actor
+                .expect_on_process_command()
+                .with(eq(command))
+                .returning(move |_| result.clone());
+
+            self
+        }
+
+        fn expect_on_load_snapshot(
+            &mut self,
+            snapshot: Bytes,
+            result: Result<(), ActorError>,
+        ) -> &mut DriverBuilder {
+            self.mock_actor
+                .expect_loads_snapshot_in_chunks()
+                .return_const(false);
+            self.mock_actor
+                .expect_on_load_snapshot()
+                .with(eq(snapshot))
+                .return_once(|_| result);
+            self
+        }
+
+        fn expect_on_load_snapshot_chunks(&mut self, chunks: Vec<Bytes>) -> &mut DriverBuilder {
+            self.mock_actor
+                .expect_loads_snapshot_in_chunks()
+                .return_const(true);
+            let mut sequence = Sequence::new();
+            for chunk in chunks {
+                self.mock_actor
+                    .expect_on_load_snapshot_chunk()
+                    .with(eq(chunk))
+                    .once()
+                    .in_sequence(&mut sequence)
+                    .return_once(|_| Ok(()));
+            }
+            self.mock_actor
+                .expect_on_load_snapshot_complete()
+                .once()
+                .in_sequence(&mut sequence)
+                .return_once(|| Ok(()));
+            self
+        }
+
+        fn expect_on_save_snapshot(
+            &mut self,
+            result: Result<Bytes, ActorError>,
+        ) -> &mut DriverBuilder {
+            self.mock_actor
+                .expect_on_save_snapshot()
+                .return_once(|| result);
+            self
+        }
+
+        fn expect_on_apply_event(
+            &mut self,
+            context: ActorEventContext,
+            event: ActorEvent,
+            result: Result<EventOutcome, ActorError>,
+        ) -> &mut DriverBuilder {
+            self.mock_actor
+                .expect_on_apply_event()
+                .with(eq(context), eq(event))
+                .return_once(|_, _| result);
+
+            self
+        }
+
+        fn take(
+            &mut self,
+            mut raft_builder: RaftBuilder,
+            snapshot_builder: SnapshotBuilder,
+            communication_builder: CommunicationBuilder,
+        ) -> Driver<
+            MockRaft<MockStore>,
+            MockStore,
+            DefaultSnapshotProcessor,
+            MockActor,
+            MockCommunicationModule,
+        > {
+            let (mock_store, mut mock_raft) = raft_builder.take();
+            let mock_actor = mem::take(&mut self.mock_actor);
+            let (mock_snapshot_sender, mock_snapshot_receiver) = snapshot_builder.take();
+            let mock_communication_module = communication_builder.take();
+
+            mock_raft.expect_mut_store().return_var(mock_store);
+
+            Driver::new(
+                mock_raft,
+                Box::new(|_, _| MockStore::new()),
+                DefaultSnapshotProcessor::new(
+                    Box::new(mock_snapshot_sender),
+                    Box::new(mock_snapshot_receiver),
+                ),
+                mock_actor,
+                mock_communication_module,
+                Box::new(StateJournal::new()),
+                Rc::new(HostClock::new()),
+            )
+        }
+    }
+
+    #[test]
+    fn test_driver_start_node_request() {
+        let (node_id, instant, raft_config) = create_default_parameters();
+        let init_snapshot = Bytes::from(vec![2, 3, 4]);
+
+        let mut mock_host = MockHostBuilder::new()
+            .expect_public_signing_key(vec![])
+            .expect_send_messages(vec![create_start_replica_response(node_id)])
+            .take();
+
+        let exp_raft_config = raft_config.clone();
         let exp_init_snapshot = init_snapshot.clone();
         let raft_builder = RaftBuilder::new()
             .expect_leader(false)
-            .expect_init(move |id, config, snapshot, leader, _, _| {
-                assert_eq!(node_id, config.id);
-                assert_eq!(exp_raft_config.election_tick as usize, config.election_tick);
-                assert_eq!(
-                    exp_raft_config.heartbeat_tick as usize,
-                    config.heartbeat_tick
-                );
-                assert_eq!(exp_raft_config.max_size_per_msg, config.max_size_per_msg);
-                assert_eq!(node_id, id);
-                assert_eq!(exp_init_snapshot, snapshot);
-                assert!(leader);
+            .expect_init(move |id, config, snapshot, leader, _, _| {
+                assert_eq!(node_id, config.id);
+                assert_eq!(exp_raft_config.election_tick as usize, config.election_tick);
+                assert_eq!(
+                    exp_raft_config.heartbeat_tick as usize,
+                    config.heartbeat_tick
+                );
+                assert_eq!(exp_raft_config.max_size_per_msg, config.max_size_per_msg);
+                assert_eq!(
+                    exp_raft_config.min_election_tick as usize,
+                    config.min_election_tick
+                );
+                assert_eq!(
+                    exp_raft_config.max_election_tick as usize,
+                    config.max_election_tick
+                );
+                assert_eq!(exp_raft_config.pre_vote, config.pre_vote);
+                assert_eq!(exp_raft_config.check_quorum, config.check_quorum);
+                let exp_flow_control_config = exp_raft_config.flow_control_config.as_ref().unwrap();
+                assert_eq!(
+                    exp_flow_control_config.max_committed_size_per_ready,
+                    config.max_committed_size_per_ready
+                );
+                assert_eq!(
+                    exp_flow_control_config.max_uncommitted_size,
+                    config.max_uncommitted_size
+                );
+                assert_eq!(RaftReadOnlyOption::Safe, config.read_only_option);
+                assert_eq!(node_id, id);
+                assert_eq!(exp_init_snapshot, snapshot);
+                assert!(leader);
+                Ok(())
+            })
+            .expect_has_ready(false)
+            .expect_should_snapshot(false)
+            .expect_state(&create_default_raft_state(node_id));
+
+        let snapshot_builder = SnapshotBuilder::new()
+            .expect_init(node_id)
+            .expect_receiver_try_complete(None);
+
+        let communication_builder = CommunicationBuilder::new()
+            .expect_init(node_id)
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new());
+
+        let mut driver = DriverBuilder::new()
+            .expect_on_init(|_| Ok(()))
+            .expect_on_save_snapshot(Ok(init_snapshot.clone()))
+            .expect_on_process_command(None, Ok(CommandOutcome::with_none()))
+            .take(raft_builder, snapshot_builder, communication_builder);
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant,
+                Some(create_start_replica_request(
+                    raft_config.clone(),
+                    true,
+                    node_id,
+                    Bytes::new()
+                )),
+            )
+        );
+    }
+
+    #[test]
+    fn test_check_lease_based_reads() {
+        let (_, _, mut raft_config) = create_default_parameters();
+        raft_config.election_tick = 20;
+        raft_config.min_election_tick = 23;
+        raft_config.max_clock_drift_ppm = 50_000;
+        assert_eq!(Ok(()), check_lease_based_reads(&raft_config));
+
+        // Minimum election timeout defaults to the election timeout, which
+        // leaves no room for the drift.
+        raft_config.min_election_tick = 0;
+        assert!(check_lease_based_reads(&raft_config).is_err());
+
+        raft_config.min_election_tick = 22;
+        assert!(check_lease_based_reads(&raft_config).is_err());
+
+        raft_config.min_election_tick = 40;
+        raft_config.max_clock_drift_ppm = 0;
+        assert!(check_lease_based_reads(&raft_config).is_err());
+
+        raft_config.max_clock_drift_ppm = MAX_LEASE_CLOCK_DRIFT_PPM + 1;
+        assert!(check_lease_based_reads(&raft_config).is_err());
+
+        raft_config.max_clock_drift_ppm = MAX_LEASE_CLOCK_DRIFT_PPM;
+        assert_eq!(Ok(()), check_lease_based_reads(&raft_config));
+
+        raft_config.check_quorum = false;
+        assert!(check_lease_based_reads(&raft_config).is_err());
+    }
+
+    // Initializes the Raft node of a follower with the given configuration and
+    // returns the native Raft configuration the node has been created with.
+    fn create_native_raft_config(raft_config: RaftConfig) -> raft::Config {
+        let (node_id, _, _) = create_default_parameters();
+        let native_config = Rc::new(RefCell::new(None));
+        let captured_config = native_config.clone();
+        let raft_builder = RaftBuilder::new()
+            .expect_init(move |_, config, _, _, _, _| {
+                *captured_config.borrow_mut() = Some(config.clone());
+                Ok(())
+            })
+            .expect_state(&create_default_raft_state(node_id));
+
+        let mut driver = DriverBuilder::new().take(
+            raft_builder,
+            SnapshotBuilder::new(),
+            CommunicationBuilder::new(),
+        );
+        driver.id = node_id;
+        assert_eq!(
+            Ok(()),
+            driver.initialize_raft_node(&Some(raft_config), Bytes::new(), false, None)
+        );
+
+        native_config.take().unwrap()
+    }
+
+    #[test]
+    fn test_driver_pre_vote() {
+        for pre_vote in [false, true] {
+            let (_, _, mut raft_config) = create_default_parameters();
+            raft_config.pre_vote = pre_vote;
+
+            let config = create_native_raft_config(raft_config);
+            assert_eq!(pre_vote, config.pre_vote);
+            assert!(config.validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_driver_election_tick_bounds() {
+        let (_, _, mut raft_config) = create_default_parameters();
+        raft_config.election_tick = 20;
+
+        // Unset bounds fall back to the Raft defaults.
+        raft_config.min_election_tick = 0;
+        raft_config.max_election_tick = 0;
+        let config = create_native_raft_config(raft_config.clone());
+        assert_eq!(20, config.min_election_tick());
+        assert_eq!(40, config.max_election_tick());
+        assert!(config.validate().is_ok());
+
+        raft_config.min_election_tick = 25;
+        raft_config.max_election_tick = 35;
+        let config = create_native_raft_config(raft_config.clone());
+        assert_eq!(25, config.min_election_tick());
+        assert_eq!(35, config.max_election_tick());
+        assert!(config.validate().is_ok());
+
+        // Range must not start below the election timeout and must not be empty.
+        raft_config.min_election_tick = 10;
+        assert!(create_native_raft_config(raft_config.clone())
+            .validate()
+            .is_err());
+        raft_config.min_election_tick = 35;
+        assert!(create_native_raft_config(raft_config).validate().is_err());
+    }
+
+    #[test]
+    fn test_driver_start_witness_request() {
+        let (node_id, instant, raft_config) = create_default_parameters();
+
+        let mut mock_host = MockHostBuilder::new()
+            .expect_public_signing_key(vec![])
+            .expect_send_messages(vec![create_start_replica_response(node_id)])
+            .take();
+
+        let raft_builder = RaftBuilder::new()
+            .expect_leader(false)
+            .expect_init(move |_, _, snapshot, leader, _, _| {
+                // Witness replica starts without actor state.
+                assert_eq!(Bytes::new(), snapshot);
+                assert!(!leader);
+                Ok(())
+            })
+            .expect_has_ready(false)
+            .expect_should_snapshot(false)
+            .expect_state(&create_default_raft_state(node_id));
+
+        let snapshot_builder = SnapshotBuilder::new()
+            .expect_init(node_id)
+            .expect_receiver_try_complete(None);
+
+        let communication_builder = CommunicationBuilder::new()
+            .expect_init(node_id)
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new());
+
+        // Actor is neither initialized nor asked to process commands.
+        let mut driver =
+            DriverBuilder::new().take(raft_builder, snapshot_builder, communication_builder);
+
+        let mut start_replica_request =
+            create_start_replica_request(raft_config.clone(), false, node_id, Bytes::new());
+        if let Some(in_message::Msg::StartReplica(request)) = &mut start_replica_request.msg {
+            request.is_witness = true;
+        }
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(&mut mock_host, instant, Some(start_replica_request))
+        );
+    }
+
+    #[test]
+    fn test_driver_witness_election() {
+        let (node_id, instant, raft_config) = create_default_parameters();
+        let peer_id = 2;
+
+        let timeout_now_message =
+            create_raft_message(peer_id, node_id, RaftMessageType::MsgTimeoutNow);
+        let request_vote_message =
+            create_raft_message(peer_id, node_id, RaftMessageType::MsgRequestVote);
+
+        let mut mock_host = MockHostBuilder::new()
+            .expect_public_signing_key(vec![])
+            .expect_send_messages(vec![create_start_replica_response(node_id)])
+            .expect_send_messages(vec![])
+            .expect_send_messages(vec![])
+            .take();
+
+        let raft_builder = RaftBuilder::new()
+            .expect_leader(false)
+            .expect_init(|_, config, _, leader, _, _| {
+                // Witness replica never reaches the election timeout.
+                assert!(!leader);
+                assert!(config.min_election_tick >= usize::MAX / 2);
+                assert!(config.max_election_tick > config.min_election_tick);
+                Ok(())
+            })
+            .expect_has_ready(false)
+            .expect_has_ready(false)
+            .expect_has_ready(false)
+            .expect_should_snapshot(false)
+            .expect_state(&create_default_raft_state(node_id))
+            // Witness replica votes, but the leadership transfer is not stepped.
+            .expect_make_step(&request_vote_message, Ok(()));
+
+        let snapshot_builder = SnapshotBuilder::new()
+            .expect_init(node_id)
+            .expect_receiver_try_complete(None)
+            .expect_receiver_try_complete(None)
+            .expect_receiver_try_complete(None);
+
+        let communication_builder = CommunicationBuilder::new()
+            .expect_init(node_id)
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new())
+            .expect_process_in_message(
+                create_deliver_system_message_request(&timeout_now_message)
+                    .msg
+                    .unwrap(),
+                Ok(Some(
+                    create_deliver_system_message_request(&timeout_now_message)
+                        .msg
+                        .unwrap(),
+                )),
+            )
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new())
+            .expect_process_in_message(
+                create_deliver_system_message_request(&request_vote_message)
+                    .msg
+                    .unwrap(),
+                Ok(Some(
+                    create_deliver_system_message_request(&request_vote_message)
+                        .msg
+                        .unwrap(),
+                )),
+            )
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new());
+
+        let mut driver =
+            DriverBuilder::new().take(raft_builder, snapshot_builder, communication_builder);
+
+        let mut start_replica_request =
+            create_start_replica_request(raft_config.clone(), false, node_id, Bytes::new());
+        if let Some(in_message::Msg::StartReplica(request)) = &mut start_replica_request.msg {
+            request.is_witness = true;
+        }
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(&mut mock_host, instant, Some(start_replica_request))
+        );
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 10,
+                Some(create_deliver_system_message_request(&timeout_now_message)),
+            )
+        );
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 20,
+                Some(create_deliver_system_message_request(&request_vote_message)),
+            )
+        );
+    }
+
+    #[test]
+    fn test_driver_stop_node_request() {
+        let (node_id, instant, raft_config) = create_default_parameters();
+        let init_snapshot = Bytes::from(vec![2, 3, 4]);
+
+        let mut mock_host = MockHostBuilder::new()
+            .expect_public_signing_key(vec![])
+            .expect_send_messages(vec![create_start_replica_response(node_id)])
+            .expect_send_messages(vec![create_stop_replica_response()])
+            .take();
+
+        let raft_builder = RaftBuilder::new()
+            .expect_leader(false)
+            .expect_init(|_, _, _, _, _, _| Ok(()))
+            .expect_has_ready(false)
+            .expect_has_ready(false)
+            .expect_should_snapshot(false)
+            .expect_state(&create_default_raft_state(node_id));
+
+        let snapshot_builder = SnapshotBuilder::new()
+            .expect_init(node_id)
+            .expect_receiver_try_complete(None);
+
+        let communication_builder = CommunicationBuilder::new()
+            .expect_init(node_id)
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new())
+            .expect_take_out_messages(Vec::new());
+
+        let mut driver = DriverBuilder::new()
+            .expect_on_init(|_| Ok(()))
+            .expect_on_save_snapshot(Ok(init_snapshot.clone()))
+            .expect_on_process_command(None, Ok(CommandOutcome::with_none()))
+            .expect_on_shutdown()
+            .take(raft_builder, snapshot_builder, communication_builder);
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant,
+                Some(create_start_replica_request(
+                    raft_config.clone(),
+                    true,
+                    node_id,
+                    Bytes::new()
+                )),
+            )
+        );
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 10,
+                Some(create_stop_replica_request()),
+            )
+        );
+    }
+
+    #[test]
+    fn test_driver_deliver_app_message_request() {
+        let (node_id, instant, raft_config) = create_default_parameters();
+        let init_snapshot = Bytes::from(vec![2, 3, 4]);
+        let proposal_contents_1 = Bytes::from(vec![1, 2, 3]);
+        let proposal_contents_2 = Bytes::from(vec![4, 5, 6]);
+        let proposal_result_2 = vec![4, 4, 6];
+        let correlation_id_1 = 1;
+        let correlation_id_2 = 2;
+        let entry_id_1 = create_entry_id(node_id, correlation_id_1);
+
+        let mut mock_host = MockHostBuilder::new()
+            .expect_public_signing_key(vec![])
+            .expect_send_messages(vec![create_start_replica_response(node_id)])
+            .expect_send_messages(vec![])
+            .expect_send_messages(vec![create_out_deliver_app_message(
+                correlation_id_2,
+                proposal_result_2.clone().into(),
+            )])
+            .take();
+
+        let proposal_entry_1 = Entry {
+            entry_id: Some(entry_id_1.clone()),
+            entry_contents: proposal_contents_1.clone().into(),
+            batched_entries: vec![],
+            fired_timers: vec![],
+            random_seed: Bytes::new(),
+            skipped_index: 0,
+            schema_version_report: None,
+            activated_schema_version: 0,
+            actor_config_update: None,
+            skipped_positions: Vec::new(),
+            proposer_applied_index: 0,
+        };
+
+        let raft_builder = RaftBuilder::new()
+            .expect_leader(false)
+            .expect_init(|_, _, _, _, _, _| Ok(()))
+            .expect_has_ready(false)
+            .expect_has_ready(false)
+            .expect_has_ready(false)
+            .expect_make_proposal(proposal_entry_1, |_| Ok(()))
+            .expect_should_snapshot(false)
+            .expect_state(&create_default_raft_state(node_id));
+
+        let snapshot_builder = SnapshotBuilder::new()
+            .expect_init(node_id)
+            .expect_receiver_try_complete(None)
+            .expect_receiver_try_complete(None)
+            .expect_receiver_try_complete(None);
+
+        let communication_builder = CommunicationBuilder::new()
+            .expect_init(node_id)
+            .expect_make_tick()
+            .expect_make_tick()
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new())
+            .expect_take_out_messages(Vec::new())
+            .expect_take_out_messages(Vec::new());
+
+        let mut driver = DriverBuilder::new()
+            .expect_on_init(|_| Ok(()))
+            .expect_on_save_snapshot(Ok(init_snapshot.clone()))
+            .expect_on_process_command(None, Ok(CommandOutcome::with_none()))
+            .expect_on_process_command(
+                Some(ActorCommand {
+                    correlation_id: correlation_id_1,
+                    header: proposal_contents_1.clone(),
+                    payload: Bytes::new(),
+                    route: String::new(),
+                    status_code: 0,
+                }),
+                Ok(CommandOutcome::with_event(ActorEvent {
+                    correlation_id: correlation_id_1,
+                    contents: proposal_contents_1.clone().into(),
+                })),
+            )
+            .expect_on_process_command(
+                Some(ActorCommand {
+                    correlation_id: correlation_id_2,
+                    header: proposal_contents_2.clone(),
+                    payload: Bytes::new(),
+                    route: String::new(),
+                    status_code: 0,
+                }),
+                Ok(CommandOutcome::with_command(ActorCommand {
+                    correlation_id: correlation_id_2,
+                    header: proposal_result_2.clone().into(),
+                    payload: Bytes::new(),
+                    route: String::new(),
+                    status_code: 0,
+                })),
+            )
+            .take(raft_builder, snapshot_builder, communication_builder);
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant,
+                Some(create_start_replica_request(
+                    raft_config.clone(),
+                    true,
+                    node_id,
+                    Bytes::new()
+                )),
+            )
+        );
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 10,
+                Some(create_in_deliver_app_message(
+                    correlation_id_1,
+                    proposal_contents_1.clone().into()
+                )),
+            )
+        );
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 10,
+                Some(create_in_deliver_app_message(
+                    correlation_id_2,
+                    proposal_contents_2.clone().into()
+                )),
+            )
+        );
+    }
+
+    #[test]
+    fn test_driver_queue_proposals_without_leader() {
+        let (node_id, instant, raft_config) = create_default_parameters();
+        let init_snapshot = Bytes::from(vec![2, 3, 4]);
+        let proposal_contents_1 = Bytes::from(vec![1, 2, 3]);
+        let proposal_contents_2 = Bytes::from(vec![4, 5, 6]);
+        let proposal_result_2 = vec![4, 4, 6];
+        let correlation_id_1 = 1;
+        let correlation_id_2 = 2;
+        let entry_id_1 = create_entry_id(node_id, correlation_id_1);
+
+        // No leader is known until the second message has been processed.
+        let leader_replica_id = Rc::new(RefCell::new(0));
+        let leader_raft_state = create_raft_state(node_id, vec![node_id]);
+
+        let mut mock_host = MockHostBuilder::new()
+            .expect_public_signing_key(vec![])
+            .expect_send_messages(vec![create_start_replica_response(node_id)])
+            .expect_send_messages(vec![])
+            .expect_send_messages(vec![
+                create_out_deliver_app_message(correlation_id_2, proposal_result_2.clone().into()),
+                create_check_cluster_response(&leader_raft_state),
+            ])
+            .take();
+
+        let proposal_entry_1 = Entry {
+            entry_id: Some(entry_id_1.clone()),
+            entry_contents: proposal_contents_1.clone().into(),
+            ..Default::default()
+        };
+
+        let proposal_count = Rc::new(RefCell::new(0));
+        let made_proposals = Rc::clone(&proposal_count);
+        let mut raft_builder = RaftBuilder::new()
+            .expect_leader(false)
+            .expect_init(|_, _, _, _, _, _| Ok(()))
+            .expect_has_ready(false)
+            .expect_has_ready(false)
+            .expect_has_ready(false)
+            .expect_make_proposal(proposal_entry_1, move |_| {
+                *made_proposals.borrow_mut() += 1;
+                Ok(())
+            })
+            .expect_should_snapshot(false);
+        let state_leader_replica_id = Rc::clone(&leader_replica_id);
+        raft_builder.mock_raft.expect_state().returning_st(move || {
+            create_raft_state(*state_leader_replica_id.borrow(), vec![node_id])
+        });
+
+        let snapshot_builder = SnapshotBuilder::new()
+            .expect_init(node_id)
+            .expect_receiver_try_complete(None)
+            .expect_receiver_try_complete(None)
+            .expect_receiver_try_complete(None)
+            .expect_receiver_reset()
+            .expect_sender_next_request(None);
+
+        let communication_builder = CommunicationBuilder::new()
+            .expect_init(node_id)
+            .expect_make_tick()
+            .expect_make_tick()
+            .expect_make_tick()
+            .expect_process_cluster_change(vec![node_id])
+            .expect_take_out_messages(Vec::new())
+            .expect_take_out_messages(Vec::new())
+            .expect_take_out_messages(Vec::new());
+
+        let mut driver = DriverBuilder::new()
+            .expect_on_init(|_| Ok(()))
+            .expect_on_save_snapshot(Ok(init_snapshot.clone()))
+            .expect_on_process_command(None, Ok(CommandOutcome::with_none()))
+            .expect_on_process_command(
+                Some(ActorCommand {
+                    correlation_id: correlation_id_1,
+                    header: proposal_contents_1.clone(),
+                    payload: Bytes::new(),
+                    route: String::new(),
+                    status_code: 0,
+                }),
+                Ok(CommandOutcome::with_event(ActorEvent {
+                    correlation_id: correlation_id_1,
+                    contents: proposal_contents_1.clone().into(),
+                })),
+            )
+            .expect_on_process_command(
+                Some(ActorCommand {
+                    correlation_id: correlation_id_2,
+                    header: proposal_contents_2.clone(),
+                    payload: Bytes::new(),
+                    route: String::new(),
+                    status_code: 0,
+                }),
+                Ok(CommandOutcome::with_command(ActorCommand {
+                    correlation_id: correlation_id_2,
+                    header: proposal_result_2.clone().into(),
+                    payload: Bytes::new(),
+                    route: String::new(),
+                    status_code: 0,
+                })),
+            )
+            .take(raft_builder, snapshot_builder, communication_builder);
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant,
+                Some(create_start_replica_request(
+                    raft_config.clone(),
+                    true,
+                    node_id,
+                    Bytes::new()
+                )),
+            )
+        );
+
+        // The proposal is queued instead of being dropped by Raft.
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 10,
+                Some(create_in_deliver_app_message(
+                    correlation_id_1,
+                    proposal_contents_1.clone().into()
+                )),
+            )
+        );
+        assert_eq!(0, *proposal_count.borrow());
+        assert_eq!(1, driver.raft_progress.pending_proposals);
+
+        // Once the leader is known the queued proposal is made.
+        *leader_replica_id.borrow_mut() = node_id;
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 20,
+                Some(create_in_deliver_app_message(
+                    correlation_id_2,
+                    proposal_contents_2.clone().into()
+                )),
+            )
+        );
+        assert_eq!(1, *proposal_count.borrow());
+        assert_eq!(1, driver.raft_progress.pending_proposals);
+        assert!(driver.queued_proposals.is_empty());
+    }
+
+    #[test]
+    fn test_driver_fail_proposals_on_leadership_loss() {
+        let (node_id, instant, raft_config) = create_default_parameters();
+        let init_snapshot = Bytes::from(vec![2, 3, 4]);
+        let proposal_contents_1 = Bytes::from(vec![1, 2, 3]);
+        let proposal_contents_2 = Bytes::from(vec![4, 5, 6]);
+        let proposal_contents_3 = Bytes::from(vec![7, 8, 9]);
+        let correlation_id_1 = 1;
+        let correlation_id_2 = 2;
+        let correlation_id_3 = 3;
+        let entry_id_1 = create_entry_id(node_id, correlation_id_1);
+
+        let raft_state = Rc::new(RefCell::new(create_raft_state(
+            REPLICA_2,
+            vec![node_id, REPLICA_2],
+        )));
+        let mut pending_change_raft_state = create_raft_state(REPLICA_2, vec![node_id, REPLICA_2]);
+        pending_change_raft_state.has_pending_change = true;
+        let mut new_leader_raft_state = create_raft_state(REPLICA_3, vec![node_id, REPLICA_2]);
+        new_leader_raft_state.leader_term = 2;
+
+        let mut mock_host = MockHostBuilder::new()
+            .expect_public_signing_key(vec![])
+            .expect_send_messages(vec![create_start_replica_response(node_id)])
+            .expect_send_messages(vec![])
+            .expect_send_messages(vec![
+                create_out_deliver_app_message(correlation_id_2, proposal_contents_2.clone()),
+                create_check_cluster_response(&pending_change_raft_state),
+            ])
+            .expect_send_messages(vec![
+                create_out_deliver_app_message(correlation_id_3, proposal_contents_3.clone()),
+                out_message::Msg::DeliverAppMessage(DeliverAppMessage {
+                    correlation_id: correlation_id_1,
+                    message_header: Bytes::new(),
+                    message_payload: Bytes::new(),
+                    route: String::new(),
+                    status_code: StatusCode::Unavailable as i32,
+                }),
+                create_check_cluster_response(&new_leader_raft_state),
+            ])
+            .take();
+
+        let proposal_entry_1 = Entry {
+            entry_id: Some(entry_id_1.clone()),
+            entry_contents: proposal_contents_1.clone().into(),
+            ..Default::default()
+        };
+
+        let mut raft_builder = RaftBuilder::new()
+            .expect_leader(false)
+            .expect_init(|_, _, _, _, _, _| Ok(()))
+            .expect_has_ready(false)
+            .expect_has_ready(false)
+            .expect_has_ready(false)
+            .expect_has_ready(false)
+            .expect_make_proposal(proposal_entry_1, |_| Ok(()))
+            .expect_should_snapshot(false);
+        let state_raft_state = Rc::clone(&raft_state);
+        raft_builder
+            .mock_raft
+            .expect_state()
+            .returning_st(move || state_raft_state.borrow().clone());
+
+        let snapshot_builder = SnapshotBuilder::new()
+            .expect_init(node_id)
+            .expect_receiver_try_complete(None)
+            .expect_receiver_try_complete(None)
+            .expect_receiver_try_complete(None)
+            .expect_receiver_try_complete(None)
+            .expect_receiver_reset();
+
+        let communication_builder = CommunicationBuilder::new()
+            .expect_init(node_id)
+            .expect_make_tick()
+            .expect_make_tick()
+            .expect_make_tick()
+            .expect_make_tick()
+            .expect_process_cluster_change(vec![node_id, REPLICA_2])
+            .expect_process_cluster_change(vec![node_id, REPLICA_2])
+            .expect_take_out_messages(Vec::new())
+            .expect_take_out_messages(Vec::new())
+            .expect_take_out_messages(Vec::new())
+            .expect_take_out_messages(Vec::new());
+
+        let mut driver = DriverBuilder::new()
+            .expect_on_init(|_| Ok(()))
+            .expect_on_save_snapshot(Ok(init_snapshot.clone()))
+            .expect_on_process_command(None, Ok(CommandOutcome::with_none()))
+            .expect_on_process_command(
+                Some(ActorCommand {
+                    correlation_id: correlation_id_1,
+                    header: proposal_contents_1.clone(),
+                    payload: Bytes::new(),
+                    route: String::new(),
+                    status_code: 0,
+                }),
+                Ok(CommandOutcome::with_event(ActorEvent {
+                    correlation_id: correlation_id_1,
+                    contents: proposal_contents_1.clone().into(),
+                })),
+            )
+            .expect_on_process_command(
+                Some(ActorCommand {
+                    correlation_id: correlation_id_2,
+                    header: proposal_contents_2.clone(),
+                    payload: Bytes::new(),
+                    route: String::new(),
+                    status_code: 0,
+                }),
+                Ok(CommandOutcome::with_command(ActorCommand {
+                    correlation_id: correlation_id_2,
+                    header: proposal_contents_2.clone(),
+                    payload: Bytes::new(),
+                    route: String::new(),
+                    status_code: 0,
+                })),
+            )
+            .expect_on_process_command(
+                Some(ActorCommand {
+                    correlation_id: correlation_id_3,
+                    header: proposal_contents_3.clone(),
+                    payload: Bytes::new(),
+                    route: String::new(),
+                    status_code: 0,
+                }),
+                Ok(CommandOutcome::with_command(ActorCommand {
+                    correlation_id: correlation_id_3,
+                    header: proposal_contents_3.clone(),
+                    payload: Bytes::new(),
+                    route: String::new(),
+                    status_code: 0,
+                })),
+            )
+            .take(raft_builder, snapshot_builder, communication_builder);
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant,
+                Some(create_start_replica_request(
+                    raft_config.clone(),
+                    true,
+                    node_id,
+                    Bytes::new()
+                )),
+            )
+        );
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 10,
+                Some(create_in_deliver_app_message(
+                    correlation_id_1,
+                    proposal_contents_1.clone()
+                )),
+            )
+        );
+        assert_eq!(1, driver.raft_progress.pending_proposals);
+
+        // Changes of the cluster config keep the proposal pending.
+        *raft_state.borrow_mut() = pending_change_raft_state.clone();
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 20,
+                Some(create_in_deliver_app_message(
+                    correlation_id_2,
+                    proposal_contents_2.clone()
+                )),
+            )
+        );
+        assert_eq!(1, driver.raft_progress.pending_proposals);
+
+        // Once the leader steps down the proposal fails.
+        *raft_state.borrow_mut() = new_leader_raft_state.clone();
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 30,
+                Some(create_in_deliver_app_message(
+                    correlation_id_3,
+                    proposal_contents_3.clone()
+                )),
+            )
+        );
+        assert_eq!(0, driver.raft_progress.pending_proposals);
+        assert!(driver.proposed_events.is_empty());
+        assert!(driver.abandoned_events.contains(&correlation_id_1));
+    }
+
+    #[test]
+    fn test_driver_deliver_app_message_events() {
+        let (node_id, instant, raft_config) = create_default_parameters();
+        let init_snapshot = Bytes::from(vec![2, 3, 4]);
+        let proposal_contents = Bytes::from(vec![1, 2, 3]);
+        let correlation_id = 1;
+        let event_contents_1 = Bytes::from(vec![4, 5]);
+        let event_contents_2 = Bytes::from(vec![6]);
+
+        let mut mock_host = MockHostBuilder::new()
+            .expect_public_signing_key(vec![])
+            .expect_send_messages(vec![create_start_replica_response(node_id)])
+            .expect_send_messages(vec![])
+            .take();
+
+        // Events produced by a single command are proposed as a single entry.
+        let proposal_entry = Entry {
+            batched_entries: vec![
+                create_entry(
+                    create_entry_id(node_id, correlation_id),
+                    event_contents_1.clone(),
+                ),
+                create_entry(
+                    create_entry_id(node_id, correlation_id),
+                    event_contents_2.clone(),
+                ),
+            ],
+            ..Default::default()
+        };
+
+        let raft_builder = RaftBuilder::new()
+            .expect_leader(false)
+            .expect_init(|_, _, _, _, _, _| Ok(()))
+            .expect_has_ready(false)
+            .expect_has_ready(false)
+            .expect_make_proposal(proposal_entry, |_| Ok(()))
+            .expect_should_snapshot(false)
+            .expect_state(&create_default_raft_state(node_id));
+
+        let snapshot_builder = SnapshotBuilder::new()
+            .expect_init(node_id)
+            .expect_receiver_try_complete(None)
+            .expect_receiver_try_complete(None);
+
+        let communication_builder = CommunicationBuilder::new()
+            .expect_init(node_id)
+            .expect_make_tick()
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new())
+            .expect_take_out_messages(Vec::new());
+
+        let mut driver = DriverBuilder::new()
+            .expect_on_init(|_| Ok(()))
+            .expect_on_save_snapshot(Ok(init_snapshot.clone()))
+            .expect_on_process_command(None, Ok(CommandOutcome::with_none()))
+            .expect_on_process_command(
+                Some(ActorCommand {
+                    correlation_id,
+                    header: proposal_contents.clone(),
+                    payload: Bytes::new(),
+                    route: String::new(),
+                    status_code: 0,
+                }),
+                Ok(CommandOutcome::with_events(vec![
+                    ActorEvent::with_bytes(correlation_id, event_contents_1),
+                    ActorEvent::with_bytes(correlation_id, event_contents_2),
+                ])),
+            )
+            .take(raft_builder, snapshot_builder, communication_builder);
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant,
+                Some(create_start_replica_request(
+                    raft_config.clone(),
+                    true,
+                    node_id,
+                    Bytes::new()
+                )),
+            )
+        );
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 10,
+                Some(create_in_deliver_app_message(
+                    correlation_id,
+                    proposal_contents.clone()
+                )),
+            )
+        );
+    }
+
+    #[test]
+    fn test_driver_actor_context() {
+        let (node_id, instant, raft_config) = create_default_parameters();
+        let init_snapshot = Bytes::from(vec![2, 3, 4]);
+        let self_config = vec![1, 2, 3];
+
+        let mut mock_host = MockHostBuilder::new()
+            .expect_public_signing_key(vec![])
+            .expect_send_messages(vec![create_start_replica_response(node_id)])
+            .take();
+
+        let raft_builder = RaftBuilder::new()
+            .expect_leader(false)
+            .expect_init(|_, _, _, _, _, _| Ok(()))
+            .expect_has_ready(false)
+            .expect_should_snapshot(false)
+            .expect_state(&create_default_raft_state(node_id));
+
+        let snapshot_builder = SnapshotBuilder::new()
+            .expect_init(node_id)
+            .expect_receiver_try_complete(None);
+
+        let exp_self_config = self_config.clone();
+
+        let communication_builder = CommunicationBuilder::new()
+            .expect_init(node_id)
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new());
+
+        let mut driver = DriverBuilder::new()
+            .expect_on_init(move |actor_context| {
+                assert_eq!(node_id, actor_context.id());
+                assert_eq!(instant, actor_context.instant());
+                assert_eq!(exp_self_config, actor_context.config());
+                assert!(!actor_context.leader());
+
+                Ok(())
+            })
+            .expect_on_save_snapshot(Ok(init_snapshot.clone()))
+            .expect_on_process_command(None, Ok(CommandOutcome::with_none()))
+            .take(raft_builder, snapshot_builder, communication_builder);
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant,
+                Some(create_start_replica_request(
+                    raft_config.clone(),
+                    true,
+                    node_id,
+                    self_config.into()
+                )),
+            )
+        );
+    }
+
+    #[test]
+    fn test_driver_ephemeral_replica() {
+        let (node_id, instant, _) = create_default_parameters();
+        let self_config = vec![1, 2, 3];
+        let correlation_id = 1;
+        let proposal_contents = Bytes::from(vec![1, 2, 3]);
+        let actor_command_1 = ActorCommand {
+            correlation_id,
+            header: proposal_contents.clone(),
+            payload: Bytes::new(),
+            route: String::new(),
+            status_code: 0,
+        };
+        let actor_event = ActorEvent {
+            correlation_id,
+            contents: proposal_contents.clone(),
+        };
+        let proposal_result = vec![4, 4, 6];
+        let actor_command_2 = ActorCommand {
+            correlation_id,
+            header: proposal_result.clone().into(),
+            payload: Bytes::new(),
+            route: String::new(),
+            status_code: 0,
+        };
+
+        let mut mock_host = MockHostBuilder::new()
+            .expect_public_signing_key(vec![])
+            .expect_send_messages(vec![create_start_replica_response(node_id)])
+            .expect_send_messages(vec![
+                create_out_deliver_app_message(correlation_id, proposal_contents.clone().into()),
+                create_out_deliver_app_message(correlation_id, proposal_result.clone().into()),
+            ])
+            .expect_send_messages(vec![create_stop_replica_response()])
+            .take();
+
+        let raft_builder = RaftBuilder::new().expect_leader(false);
+        let snapshot_builder = SnapshotBuilder::new();
+        let communication_builder = CommunicationBuilder::new()
+            .expect_init(node_id)
+            .expect_make_tick()
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new())
+            .expect_take_out_messages(Vec::new())
+            .expect_take_out_messages(Vec::new());
+
+        let exp_self_config = self_config.clone();
+
+        let mut driver = DriverBuilder::new()
+            .expect_on_init(move |actor_context| {
+                assert_eq!(node_id, actor_context.id());
+                assert_eq!(instant, actor_context.instant());
+                assert_eq!(exp_self_config, actor_context.config());
+                assert!(!actor_context.leader());
+
+                Ok(())
+            })
+            .expect_on_process_command(None, Ok(CommandOutcome::with_none()))
+            .expect_on_process_command(
+                Some(actor_command_1.clone()),
+                Ok(CommandOutcome::with_command_and_event(
+                    actor_command_1.clone(),
+                    actor_event.clone(),
+                )),
+            )
+            .expect_on_apply_event(
+                ActorEventContext {
+                    index: 0,
+                    owned: true,
+                },
+                actor_event.clone(),
+                Ok(EventOutcome::with_command(actor_command_2.clone())),
+            )
+            .expect_on_shutdown()
+            .take(raft_builder, snapshot_builder, communication_builder);
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant,
+                Some(InMessage {
+                    msg: Some(in_message::Msg::StartReplica(StartReplicaRequest {
+                        is_leader: false,
+                        replica_id_hint: node_id,
+                        raft_config: None,
+                        app_config: self_config.into(),
+                        attestation_config: None,
+                        is_ephemeral: true,
+                        recovery_state: None,
+                        is_witness: false,
+                        imported_snapshot: None,
+                        sealed_secrets: vec![],
+                    })),
+                }),
+            )
+        );
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 10,
+                Some(create_in_deliver_app_message(
+                    correlation_id,
+                    proposal_contents.clone().into()
+                )),
+            )
+        );
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 20,
+                Some(create_stop_replica_request()),
+            )
+        );
+    }
+
+    #[test]
+    fn test_driver_pending_command() {
+        let (node_id, instant, _) = create_default_parameters();
+        let correlation_id = 1;
+        let actor_command = ActorCommand {
+            correlation_id,
+            header: Bytes::from(vec![1, 2, 3]),
+            payload: Bytes::new(),
+            route: String::new(),
+            status_code: 0,
+        };
+        let response = ActorCommand {
+            correlation_id,
+            header: Bytes::from(vec![4, 5, 6]),
+            payload: Bytes::new(),
+            route: String::new(),
+            status_code: 0,
+        };
+
+        let mut mock_host = MockHostBuilder::new()
+            .expect_public_signing_key(vec![])
+            .expect_send_messages(vec![create_start_replica_response(node_id)])
+            .expect_send_messages(vec![])
+            .expect_send_messages(vec![create_out_deliver_app_message(
+                correlation_id,
+                response.header.clone(),
+            )])
+            .take();
+
+        let raft_builder = RaftBuilder::new().expect_leader(false);
+        let snapshot_builder = SnapshotBuilder::new();
+        let communication_builder = CommunicationBuilder::new()
+            .expect_init(node_id)
+            .expect_make_tick()
+            .expect_make_tick()
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new())
+            .expect_take_out_messages(Vec::new())
+            .expect_take_out_messages(Vec::new());
+
+        // Actor completes the command whenever it is given a chance to make
+        // progress, completion before the command is pending is dropped.
+        let actor_context: Rc<RefCell<Option<Box<dyn ActorContext>>>> = Rc::new(RefCell::new(None));
+        let init_actor_context = actor_context.clone();
+        let mut driver_builder = DriverBuilder::new();
+        driver_builder
+            .expect_on_init(move |context| {
+                *init_actor_context.borrow_mut() = Some(context);
                 Ok(())
             })
-            .expect_has_ready(false)
-            .expect_should_snapshot(false)
-            .expect_state(&create_default_raft_state(node_id));
+            .expect_on_process_command(
+                Some(actor_command.clone()),
+                Ok(CommandOutcome::with_pending(correlation_id)),
+            );
+        let completed_response = response.clone();
+        driver_builder
+            .mock_actor
+            .expect_on_process_command()
+            .with(eq(None))
+            .returning_st(move |_| {
+                actor_context
+                    .borrow()
+                    .as_ref()
+                    .unwrap()
+                    .complete_command(correlation_id, completed_response.clone());
+                Ok(CommandOutcome::with_none())
+            });
+        let mut driver = driver_builder.take(raft_builder, snapshot_builder, communication_builder);
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant,
+                Some(InMessage {
+                    msg: Some(in_message::Msg::StartReplica(StartReplicaRequest {
+                        is_ephemeral: true,
+                        replica_id_hint: node_id,
+                        ..Default::default()
+                    })),
+                }),
+            )
+        );
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 10,
+                Some(create_in_deliver_app_message(
+                    correlation_id,
+                    actor_command.header.clone()
+                )),
+            )
+        );
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(&mut mock_host, instant + 20, None)
+        );
+    }
+
+    #[test]
+    fn test_driver_send_app_message() {
+        let (node_id, instant, _) = create_default_parameters();
+        let message = Bytes::from(vec![1, 2, 3]);
+
+        let mut mock_host = MockHostBuilder::new()
+            .expect_public_signing_key(vec![])
+            .expect_send_messages(vec![
+                create_start_replica_response(node_id),
+                out_message::Msg::SendAppMessage(SendAppMessage {
+                    destination: String::from("store"),
+                    message_contents: message.clone(),
+                }),
+            ])
+            .take();
+
+        let raft_builder = RaftBuilder::new().expect_leader(false);
+        let snapshot_builder = SnapshotBuilder::new();
+        let communication_builder = CommunicationBuilder::new()
+            .expect_init(node_id)
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new());
+
+        // Message sent by the actor is sent out once the invocation returns.
+        let mut driver = DriverBuilder::new()
+            .expect_on_init(move |context| {
+                context.send_app_message("store", message.clone());
+                Ok(())
+            })
+            .expect_on_process_command(None, Ok(CommandOutcome::with_none()))
+            .take(raft_builder, snapshot_builder, communication_builder);
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant,
+                Some(InMessage {
+                    msg: Some(in_message::Msg::StartReplica(StartReplicaRequest {
+                        is_ephemeral: true,
+                        replica_id_hint: node_id,
+                        ..Default::default()
+                    })),
+                }),
+            )
+        );
+    }
+
+    #[test]
+    fn test_driver_update_actor_config() {
+        let (node_id, instant, _) = create_default_parameters();
+        let actor_config = Bytes::from(vec![1, 2, 3]);
+
+        let mut mock_host = MockHostBuilder::new()
+            .expect_public_signing_key(vec![])
+            .expect_send_messages(vec![create_start_replica_response(node_id)])
+            .expect_send_messages(vec![out_message::Msg::UpdateActorConfig(
+                UpdateActorConfigResponse {
+                    update_id: 1,
+                    update_status: UpdateActorConfigStatus::UpdateConfigStatusApplied.into(),
+                },
+            )])
+            .expect_send_messages(vec![out_message::Msg::UpdateActorConfig(
+                UpdateActorConfigResponse {
+                    update_id: 2,
+                    update_status: UpdateActorConfigStatus::UpdateConfigStatusFailed.into(),
+                },
+            )])
+            .take();
+
+        let raft_builder = RaftBuilder::new().expect_leader(false);
+        let snapshot_builder = SnapshotBuilder::new();
+        let communication_builder = CommunicationBuilder::new()
+            .expect_init(node_id)
+            .expect_make_tick()
+            .expect_make_tick()
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new())
+            .expect_take_out_messages(Vec::new())
+            .expect_take_out_messages(Vec::new());
+
+        // Actor applies the first update and refuses the second one.
+        let mut driver_builder = DriverBuilder::new();
+        driver_builder
+            .expect_on_init(|_| Ok(()))
+            .expect_on_process_command(None, Ok(CommandOutcome::with_none()));
+        let mut update_count = 0;
+        driver_builder
+            .mock_actor
+            .expect_on_apply_config()
+            .with(eq(actor_config.clone()))
+            .times(2)
+            .returning(move |_| {
+                update_count += 1;
+                if update_count == 1 {
+                    Ok(())
+                } else {
+                    Err(ActorError::ConfigLoading)
+                }
+            });
+        let mut driver = driver_builder.take(raft_builder, snapshot_builder, communication_builder);
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant,
+                Some(InMessage {
+                    msg: Some(in_message::Msg::StartReplica(StartReplicaRequest {
+                        is_ephemeral: true,
+                        replica_id_hint: node_id,
+                        ..Default::default()
+                    })),
+                }),
+            )
+        );
+
+        for update_id in 1..=2 {
+            assert_eq!(
+                Ok(()),
+                driver.receive_message(
+                    &mut mock_host,
+                    instant + 10 * update_id,
+                    Some(InMessage {
+                        msg: Some(in_message::Msg::UpdateActorConfig(
+                            UpdateActorConfigRequest {
+                                update_id,
+                                actor_config: actor_config.clone(),
+                                attestation_policy: None,
+                            }
+                        )),
+                    }),
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_driver_update_attestation_policy() {
+        let (node_id, instant, _) = create_default_parameters();
+        let start_policy = AttestationPolicy {
+            allowed_measurements: vec![vec![1; 48]],
+            ..Default::default()
+        };
+        let updated_policy = AttestationPolicy {
+            allowed_measurements: vec![vec![2; 48]],
+            ..Default::default()
+        };
+
+        let mut mock_host = MockHostBuilder::new()
+            .expect_public_signing_key(vec![])
+            .expect_send_messages(vec![create_start_replica_response(node_id)])
+            .expect_send_messages(vec![out_message::Msg::UpdateActorConfig(
+                UpdateActorConfigResponse {
+                    update_id: 1,
+                    update_status: UpdateActorConfigStatus::UpdateConfigStatusApplied.into(),
+                },
+            )])
+            .take();
+
+        let raft_builder = RaftBuilder::new().expect_leader(false);
+        let snapshot_builder = SnapshotBuilder::new();
+        let communication_builder = CommunicationBuilder::new()
+            .expect_init(node_id)
+            .expect_update_attestation_policy(start_policy.clone())
+            .expect_update_attestation_policy(updated_policy.clone())
+            .expect_make_tick()
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new())
+            .expect_take_out_messages(Vec::new());
+
+        // Policy only update is not passed to the actor.
+        let mut driver_builder = DriverBuilder::new();
+        driver_builder
+            .expect_on_init(|_| Ok(()))
+            .expect_on_process_command(None, Ok(CommandOutcome::with_none()));
+        driver_builder.mock_actor.expect_on_apply_config().never();
+        let mut driver = driver_builder.take(raft_builder, snapshot_builder, communication_builder);
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant,
+                Some(InMessage {
+                    msg: Some(in_message::Msg::StartReplica(StartReplicaRequest {
+                        is_ephemeral: true,
+                        replica_id_hint: node_id,
+                        raft_config: Some(RaftConfig {
+                            attestation_policy: Some(start_policy),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    })),
+                }),
+            )
+        );
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 10,
+                Some(InMessage {
+                    msg: Some(in_message::Msg::UpdateActorConfig(
+                        UpdateActorConfigRequest {
+                            update_id: 1,
+                            actor_config: Bytes::new(),
+                            attestation_policy: Some(updated_policy),
+                        }
+                    )),
+                }),
+            )
+        );
+    }
 
-        let snapshot_builder = SnapshotBuilder::new()
-            .expect_init(node_id)
-            .expect_receiver_set_instant()
-            .expect_receiver_try_complete(None);
+    #[test]
+    fn test_driver_state_size_backpressure() {
+        let (node_id, instant, _) = create_default_parameters();
+        let actor_command_1 = ActorCommand {
+            correlation_id: 1,
+            header: Bytes::from(vec![1]),
+            payload: Bytes::new(),
+            route: String::new(),
+            status_code: 0,
+        };
+        let actor_command_2 = ActorCommand {
+            correlation_id: 2,
+            header: Bytes::from(vec![2]),
+            payload: Bytes::new(),
+            route: "ledger".to_string(),
+            status_code: 0,
+        };
+        let actor_event_1 = ActorEvent::with_bytes(1, Bytes::from(vec![3]));
+        let response_1 = ActorCommand {
+            correlation_id: 1,
+            header: Bytes::from(vec![4]),
+            payload: Bytes::new(),
+            route: String::new(),
+            status_code: 0,
+        };
+
+        let mut mock_host = MockHostBuilder::new()
+            .expect_public_signing_key(vec![])
+            .expect_send_messages(vec![create_start_replica_response(node_id)])
+            .expect_send_messages(vec![create_out_deliver_app_message(
+                1,
+                response_1.header.clone(),
+            )])
+            .expect_send_messages(vec![out_message::Msg::DeliverAppMessage(
+                DeliverAppMessage {
+                    correlation_id: 2,
+                    message_header: Bytes::new(),
+                    message_payload: Bytes::new(),
+                    route: actor_command_2.route.clone(),
+                    status_code: StatusCode::ResourceExhausted as i32,
+                },
+            )])
+            .take();
 
+        let raft_builder = RaftBuilder::new().expect_leader(false);
+        let snapshot_builder = SnapshotBuilder::new();
         let communication_builder = CommunicationBuilder::new()
             .expect_init(node_id)
             .expect_make_tick()
+            .expect_make_tick()
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new())
+            .expect_take_out_messages(Vec::new())
             .expect_take_out_messages(Vec::new());
 
-        let mut driver = DriverBuilder::new()
-            .expect_on_init(|_| Ok(()))
-            .expect_on_save_snapshot(Ok(init_snapshot.clone()))
+        // Actor reports the state beyond the limit once the first event is
+        // applied, hence the proposal of the second command is rejected and
+        // the response the actor has produced along with it is not sent out.
+        let actor_context: Rc<RefCell<Option<Box<dyn ActorContext>>>> = Rc::new(RefCell::new(None));
+        let init_actor_context = actor_context.clone();
+        let mut driver_builder = DriverBuilder::new();
+        driver_builder
+            .expect_on_init(move |context| {
+                *init_actor_context.borrow_mut() = Some(context);
+                Ok(())
+            })
             .expect_on_process_command(None, Ok(CommandOutcome::with_none()))
-            .take(raft_builder, snapshot_builder, communication_builder);
+            .expect_on_process_command(
+                Some(actor_command_1.clone()),
+                Ok(CommandOutcome::with_event(actor_event_1.clone())),
+            )
+            .expect_on_process_command(
+                Some(actor_command_2.clone()),
+                Ok(CommandOutcome::with_command_and_event(
+                    ActorCommand {
+                        correlation_id: 2,
+                        header: Bytes::from(vec![6]),
+                        payload: Bytes::new(),
+                        route: actor_command_2.route.clone(),
+                        status_code: 0,
+                    },
+                    ActorEvent::with_bytes(2, Bytes::from(vec![5])),
+                )),
+            );
+        let applied_response = response_1.clone();
+        driver_builder
+            .mock_actor
+            .expect_on_apply_event()
+            .with(
+                eq(ActorEventContext {
+                    index: 0,
+                    owned: true,
+                }),
+                eq(actor_event_1),
+            )
+            .return_once_st(move |_, _| {
+                actor_context
+                    .borrow()
+                    .as_ref()
+                    .unwrap()
+                    .report_state_size(200);
+                Ok(EventOutcome::with_command(applied_response))
+            });
+        let mut driver = driver_builder.take(raft_builder, snapshot_builder, communication_builder);
 
         assert_eq!(
             Ok(()),
             driver.receive_message(
                 &mut mock_host,
                 instant,
-                Some(create_start_replica_request(
-                    raft_config.clone(),
-                    true,
-                    node_id,
-                    Bytes::new()
+                Some(InMessage {
+                    msg: Some(in_message::Msg::StartReplica(StartReplicaRequest {
+                        is_ephemeral: true,
+                        replica_id_hint: node_id,
+                        raft_config: Some(RaftConfig {
+                            mailbox_config: Some(MailboxConfig {
+                                max_state_size: 100,
+                                ..Default::default()
+                            }),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    })),
+                }),
+            )
+        );
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 10,
+                Some(create_in_deliver_app_message(
+                    1,
+                    actor_command_1.header.clone()
                 )),
             )
         );
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 20,
+                Some(InMessage {
+                    msg: Some(in_message::Msg::DeliverAppMessage(DeliverAppMessage {
+                        correlation_id: 2,
+                        message_header: actor_command_2.header.clone(),
+                        message_payload: Bytes::new(),
+                        route: actor_command_2.route.clone(),
+                        status_code: 0,
+                    })),
+                }),
+            )
+        );
+    }
+
+    fn create_routed_app_message(correlation_id: u64, route: &str) -> DeliverAppMessage {
+        DeliverAppMessage {
+            correlation_id,
+            message_header: Bytes::from(vec![correlation_id as u8]),
+            message_payload: Bytes::new(),
+            route: route.to_string(),
+            status_code: 0,
+        }
+    }
+
+    fn create_rejected_app_message(
+        correlation_id: u64,
+        route: &str,
+        status_code: StatusCode,
+    ) -> OutMessage {
+        OutMessage {
+            msg: Some(out_message::Msg::DeliverAppMessage(DeliverAppMessage {
+                correlation_id,
+                message_header: Bytes::new(),
+                message_payload: Bytes::new(),
+                route: route.to_string(),
+                status_code: status_code as i32,
+            })),
+        }
     }
 
     #[test]
-    fn test_driver_stop_node_request() {
+    fn test_driver_mailbox_shed_reply() {
+        for (shed_policy, shed_correlation_id) in
+            [(ShedPolicy::RejectNewest, 3), (ShedPolicy::DropOldest, 1)]
+        {
+            let mut driver = DriverBuilder::new().take(
+                RaftBuilder::new(),
+                SnapshotBuilder::new(),
+                CommunicationBuilder::new(),
+            );
+            driver.mailbox.configure(2, shed_policy);
+
+            for correlation_id in 1..=3 {
+                driver.enqueue_app_message(create_routed_app_message(correlation_id, "ledger"));
+            }
+
+            // The consumer is told that the shed message has been rejected.
+            assert_eq!(
+                driver.messages.take(),
+                vec![create_rejected_app_message(
+                    shed_correlation_id,
+                    "ledger",
+                    StatusCode::ResourceExhausted
+                )]
+            );
+            assert_eq!(driver.mailbox.len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_driver_lame_duck_reply() {
+        let mut driver = DriverBuilder::new().take(
+            RaftBuilder::new(),
+            SnapshotBuilder::new(),
+            CommunicationBuilder::new(),
+        );
+        driver.lame_duck = Some(LameDuck {
+            deadline: 0,
+            acknowledged: false,
+        });
+
+        driver.enqueue_app_message(create_routed_app_message(1, "ledger"));
+
+        // The consumer is told to retry the message against another replica.
+        assert_eq!(
+            driver.messages.take(),
+            vec![create_rejected_app_message(
+                1,
+                "ledger",
+                StatusCode::Unavailable
+            )]
+        );
+        assert!(driver.mailbox.is_empty());
+    }
+
+    #[test]
+    fn test_driver_change_cluster_request() {
         let (node_id, instant, raft_config) = create_default_parameters();
         let init_snapshot = Bytes::from(vec![2, 3, 4]);
+        let peer_id = 2;
 
         let mut mock_host = MockHostBuilder::new()
             .expect_public_signing_key(vec![])
             .expect_send_messages(vec![create_start_replica_response(node_id)])
-            .expect_send_messages(vec![create_stop_replica_response()])
+            .expect_send_messages(vec![create_change_cluster_response(
+                ChangeClusterStatus::ChangeStatusPending,
+            )])
             .take();
 
         let raft_builder = RaftBuilder::new()
@@ -2233,16 +7043,21 @@ mod test {
             .expect_has_ready(false)
             .expect_has_ready(false)
             .expect_should_snapshot(false)
-            .expect_state(&create_default_raft_state(node_id));
+            .expect_state(&create_default_raft_state(node_id))
+            .expect_make_config_change_proposal(
+                create_raft_config_change(peer_id, RaftConfigChangeType::AddNode),
+                |_| Ok(()),
+            );
 
         let snapshot_builder = SnapshotBuilder::new()
             .expect_init(node_id)
-            .expect_receiver_set_instant()
+            .expect_receiver_try_complete(None)
             .expect_receiver_try_complete(None);
 
         let communication_builder = CommunicationBuilder::new()
             .expect_init(node_id)
             .expect_make_tick()
+            .expect_make_tick()
             .expect_take_out_messages(Vec::new())
             .expect_take_out_messages(Vec::new());
 
@@ -2250,7 +7065,6 @@ mod test {
             .expect_on_init(|_| Ok(()))
             .expect_on_save_snapshot(Ok(init_snapshot.clone()))
             .expect_on_process_command(None, Ok(CommandOutcome::with_none()))
-            .expect_on_shutdown()
             .take(raft_builder, snapshot_builder, communication_builder);
 
         assert_eq!(
@@ -2272,50 +7086,46 @@ mod test {
             driver.receive_message(
                 &mut mock_host,
                 instant + 10,
-                Some(create_stop_replica_request()),
+                Some(create_change_cluster_request(
+                    peer_id,
+                    ChangeClusterType::ChangeTypeAddReplica
+                )),
             )
         );
     }
 
     #[test]
-    fn test_driver_deliver_app_message_request() {
+    fn test_driver_change_cluster_learner_request() {
         let (node_id, instant, raft_config) = create_default_parameters();
         let init_snapshot = Bytes::from(vec![2, 3, 4]);
-        let proposal_contents_1 = Bytes::from(vec![1, 2, 3]);
-        let proposal_contents_2 = Bytes::from(vec![4, 5, 6]);
-        let proposal_result_2 = vec![4, 4, 6];
-        let correlation_id_1 = 1;
-        let correlation_id_2 = 2;
-        let entry_id_1 = create_entry_id(node_id, correlation_id_1);
+        let peer_id = 2;
 
         let mut mock_host = MockHostBuilder::new()
             .expect_public_signing_key(vec![])
             .expect_send_messages(vec![create_start_replica_response(node_id)])
-            .expect_send_messages(vec![])
-            .expect_send_messages(vec![create_out_deliver_app_message(
-                correlation_id_2,
-                proposal_result_2.clone().into(),
+            .expect_send_messages(vec![create_change_cluster_response(
+                ChangeClusterStatus::ChangeStatusPending,
+            )])
+            .expect_send_messages(vec![create_change_cluster_response(
+                ChangeClusterStatus::ChangeStatusRejected,
             )])
             .take();
 
-        let proposal_entry_1 = Entry {
-            entry_id: Some(entry_id_1.clone()),
-            entry_contents: proposal_contents_1.clone().into(),
-        };
-
         let raft_builder = RaftBuilder::new()
             .expect_leader(false)
             .expect_init(|_, _, _, _, _, _| Ok(()))
             .expect_has_ready(false)
             .expect_has_ready(false)
             .expect_has_ready(false)
-            .expect_make_proposal(proposal_entry_1, |_| Ok(()))
             .expect_should_snapshot(false)
-            .expect_state(&create_default_raft_state(node_id));
+            .expect_state(&create_default_raft_state(node_id))
+            .expect_make_config_change_proposal(
+                create_raft_config_change(peer_id, RaftConfigChangeType::AddLearnerNode),
+                |_| Ok(()),
+            );
 
         let snapshot_builder = SnapshotBuilder::new()
             .expect_init(node_id)
-            .expect_receiver_set_instant()
             .expect_receiver_try_complete(None)
             .expect_receiver_try_complete(None)
             .expect_receiver_try_complete(None);
@@ -2333,29 +7143,6 @@ mod test {
             .expect_on_init(|_| Ok(()))
             .expect_on_save_snapshot(Ok(init_snapshot.clone()))
             .expect_on_process_command(None, Ok(CommandOutcome::with_none()))
-            .expect_on_process_command(
-                Some(ActorCommand {
-                    correlation_id: correlation_id_1,
-                    header: proposal_contents_1.clone(),
-                    payload: Bytes::new(),
-                }),
-                Ok(CommandOutcome::with_event(ActorEvent {
-                    correlation_id: correlation_id_1,
-                    contents: proposal_contents_1.clone().into(),
-                })),
-            )
-            .expect_on_process_command(
-                Some(ActorCommand {
-                    correlation_id: correlation_id_2,
-                    header: proposal_contents_2.clone(),
-                    payload: Bytes::new(),
-                }),
-                Ok(CommandOutcome::with_command(ActorCommand {
-                    correlation_id: correlation_id_2,
-                    header: proposal_result_2.clone().into(),
-                    payload: Bytes::new(),
-                })),
-            )
             .take(raft_builder, snapshot_builder, communication_builder);
 
         assert_eq!(
@@ -2377,65 +7164,64 @@ mod test {
             driver.receive_message(
                 &mut mock_host,
                 instant + 10,
-                Some(create_in_deliver_app_message(
-                    correlation_id_1,
-                    proposal_contents_1.clone().into()
+                Some(create_change_cluster_request(
+                    peer_id,
+                    ChangeClusterType::ChangeTypeAddLearner
                 )),
             )
         );
 
+        // Learner addition is not committed yet hence promotion must be rejected.
         assert_eq!(
             Ok(()),
             driver.receive_message(
                 &mut mock_host,
-                instant + 10,
-                Some(create_in_deliver_app_message(
-                    correlation_id_2,
-                    proposal_contents_2.clone().into()
+                instant + 20,
+                Some(create_change_cluster_request(
+                    peer_id,
+                    ChangeClusterType::ChangeTypePromoteLearner
                 )),
             )
         );
     }
 
     #[test]
-    fn test_driver_actor_context() {
+    fn test_driver_transfer_leadership_request_rejected() {
         let (node_id, instant, raft_config) = create_default_parameters();
         let init_snapshot = Bytes::from(vec![2, 3, 4]);
-        let self_config = vec![1, 2, 3];
+        let peer_id = 2;
 
         let mut mock_host = MockHostBuilder::new()
             .expect_public_signing_key(vec![])
             .expect_send_messages(vec![create_start_replica_response(node_id)])
+            .expect_send_messages(vec![create_transfer_leadership_response(
+                TransferLeadershipStatus::TransferStatusRejected,
+            )])
             .take();
 
+        // Transfer must be rejected since the replica is not the leader.
         let raft_builder = RaftBuilder::new()
             .expect_leader(false)
             .expect_init(|_, _, _, _, _, _| Ok(()))
             .expect_has_ready(false)
+            .expect_has_ready(false)
             .expect_should_snapshot(false)
             .expect_state(&create_default_raft_state(node_id));
 
         let snapshot_builder = SnapshotBuilder::new()
             .expect_init(node_id)
-            .expect_receiver_set_instant()
+            .expect_receiver_try_complete(None)
             .expect_receiver_try_complete(None);
 
-        let exp_self_config = self_config.clone();
-
         let communication_builder = CommunicationBuilder::new()
             .expect_init(node_id)
             .expect_make_tick()
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new())
             .expect_take_out_messages(Vec::new());
 
         let mut driver = DriverBuilder::new()
-            .expect_on_init(move |actor_context| {
-                assert_eq!(node_id, actor_context.id());
-                assert_eq!(instant, actor_context.instant());
-                assert_eq!(exp_self_config, actor_context.config());
-                assert!(!actor_context.leader());
-
-                Ok(())
-            })
+            .expect_on_init(|_| Ok(()))
             .expect_on_save_snapshot(Ok(init_snapshot.clone()))
             .expect_on_process_command(None, Ok(CommandOutcome::with_none()))
             .take(raft_builder, snapshot_builder, communication_builder);
@@ -2449,82 +7235,73 @@ mod test {
                     raft_config.clone(),
                     true,
                     node_id,
-                    self_config.into()
+                    Bytes::new()
                 )),
             )
         );
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 10,
+                Some(create_transfer_leadership_request(peer_id)),
+            )
+        );
     }
 
     #[test]
-    fn test_driver_ephemeral_replica() {
-        let (node_id, instant, _) = create_default_parameters();
-        let self_config = vec![1, 2, 3];
-        let correlation_id = 1;
-        let proposal_contents = Bytes::from(vec![1, 2, 3]);
-        let actor_command_1 = ActorCommand {
-            correlation_id,
-            header: proposal_contents.clone(),
-            payload: Bytes::new(),
-        };
-        let actor_event = ActorEvent {
-            correlation_id,
-            contents: proposal_contents.clone(),
-        };
-        let proposal_result = vec![4, 4, 6];
-        let actor_command_2 = ActorCommand {
-            correlation_id,
-            header: proposal_result.clone().into(),
-            payload: Bytes::new(),
-        };
+    fn test_driver_reconfigure_cluster_request() {
+        let (node_id, instant, raft_config) = create_default_parameters();
+        let init_snapshot = Bytes::from(vec![2, 3, 4]);
+        let peer_id = 2;
 
         let mut mock_host = MockHostBuilder::new()
             .expect_public_signing_key(vec![])
             .expect_send_messages(vec![create_start_replica_response(node_id)])
-            .expect_send_messages(vec![
-                create_out_deliver_app_message(correlation_id, proposal_contents.clone().into()),
-                create_out_deliver_app_message(correlation_id, proposal_result.clone().into()),
-            ])
-            .expect_send_messages(vec![create_stop_replica_response()])
+            .expect_send_messages(vec![create_change_cluster_response(
+                ChangeClusterStatus::ChangeStatusPending,
+            )])
+            .expect_send_messages(vec![create_change_cluster_response(
+                ChangeClusterStatus::ChangeStatusRejected,
+            )])
             .take();
 
-        let raft_builder = RaftBuilder::new().expect_leader(false);
-        let snapshot_builder = SnapshotBuilder::new();
+        let raft_builder = RaftBuilder::new()
+            .expect_leader(false)
+            .expect_init(|_, _, _, _, _, _| Ok(()))
+            .expect_has_ready(false)
+            .expect_has_ready(false)
+            .expect_has_ready(false)
+            .expect_should_snapshot(false)
+            .expect_state(&create_default_raft_state(node_id))
+            .expect_make_config_change_v2_proposal(
+                create_raft_config_change_v2(vec![
+                    (peer_id, RaftConfigChangeType::AddNode),
+                    (peer_id + 1, RaftConfigChangeType::AddNode),
+                ]),
+                |_| Ok(()),
+            );
+
+        let snapshot_builder = SnapshotBuilder::new()
+            .expect_init(node_id)
+            .expect_receiver_try_complete(None)
+            .expect_receiver_try_complete(None)
+            .expect_receiver_try_complete(None);
+
         let communication_builder = CommunicationBuilder::new()
             .expect_init(node_id)
             .expect_make_tick()
             .expect_make_tick()
+            .expect_make_tick()
             .expect_take_out_messages(Vec::new())
             .expect_take_out_messages(Vec::new())
             .expect_take_out_messages(Vec::new());
 
-        let exp_self_config = self_config.clone();
-
         let mut driver = DriverBuilder::new()
-            .expect_on_init(move |actor_context| {
-                assert_eq!(node_id, actor_context.id());
-                assert_eq!(instant, actor_context.instant());
-                assert_eq!(exp_self_config, actor_context.config());
-                assert!(!actor_context.leader());
-
-                Ok(())
-            })
+            .expect_on_init(|_| Ok(()))
+            .expect_on_save_snapshot(Ok(init_snapshot.clone()))
             .expect_on_process_command(None, Ok(CommandOutcome::with_none()))
-            .expect_on_process_command(
-                Some(actor_command_1.clone()),
-                Ok(CommandOutcome::with_command_and_event(
-                    actor_command_1.clone(),
-                    actor_event.clone(),
-                )),
-            )
-            .expect_on_apply_event(
-                ActorEventContext {
-                    index: 0,
-                    owned: true,
-                },
-                actor_event.clone(),
-                Ok(EventOutcome::with_command(actor_command_2.clone())),
-            )
-            .expect_on_shutdown()
             .take(raft_builder, snapshot_builder, communication_builder);
 
         assert_eq!(
@@ -2532,16 +7309,12 @@ mod test {
             driver.receive_message(
                 &mut mock_host,
                 instant,
-                Some(InMessage {
-                    msg: Some(in_message::Msg::StartReplica(StartReplicaRequest {
-                        is_leader: false,
-                        replica_id_hint: node_id,
-                        raft_config: None,
-                        app_config: self_config.into(),
-                        attestation_config: None,
-                        is_ephemeral: true,
-                    })),
-                }),
+                Some(create_start_replica_request(
+                    raft_config.clone(),
+                    true,
+                    node_id,
+                    Bytes::new()
+                )),
             )
         );
 
@@ -2550,35 +7323,39 @@ mod test {
             driver.receive_message(
                 &mut mock_host,
                 instant + 10,
-                Some(create_in_deliver_app_message(
-                    correlation_id,
-                    proposal_contents.clone().into()
+                Some(create_reconfigure_cluster_request(
+                    vec![peer_id, peer_id + 1],
+                    vec![]
                 )),
             )
         );
 
+        // Replica cannot be both added and removed.
         assert_eq!(
             Ok(()),
             driver.receive_message(
                 &mut mock_host,
                 instant + 20,
-                Some(create_stop_replica_request()),
+                Some(create_reconfigure_cluster_request(
+                    vec![peer_id],
+                    vec![peer_id]
+                )),
             )
         );
     }
 
     #[test]
-    fn test_driver_change_cluster_request() {
+    fn test_driver_check_cluster_request() {
         let (node_id, instant, raft_config) = create_default_parameters();
         let init_snapshot = Bytes::from(vec![2, 3, 4]);
         let peer_id = 2;
 
+        let raft_state = create_default_raft_state(node_id);
+
         let mut mock_host = MockHostBuilder::new()
             .expect_public_signing_key(vec![])
             .expect_send_messages(vec![create_start_replica_response(node_id)])
-            .expect_send_messages(vec![create_change_cluster_response(
-                ChangeClusterStatus::ChangeStatusPending,
-            )])
+            .expect_send_messages(vec![create_check_cluster_response(&raft_state)])
             .take();
 
         let raft_builder = RaftBuilder::new()
@@ -2587,7 +7364,7 @@ mod test {
             .expect_has_ready(false)
             .expect_has_ready(false)
             .expect_should_snapshot(false)
-            .expect_state(&create_default_raft_state(node_id))
+            .expect_state(&raft_state)
             .expect_make_config_change_proposal(
                 create_raft_config_change(peer_id, RaftConfigChangeType::AddNode),
                 |_| Ok(()),
@@ -2595,15 +7372,17 @@ mod test {
 
         let snapshot_builder = SnapshotBuilder::new()
             .expect_init(node_id)
-            .expect_receiver_set_instant()
             .expect_receiver_try_complete(None)
-            .expect_receiver_try_complete(None);
+            .expect_receiver_try_complete(None)
+            .expect_receiver_reset()
+            .expect_sender_next_request(None);
 
         let communication_builder = CommunicationBuilder::new()
             .expect_init(node_id)
             .expect_make_tick()
-            .expect_make_tick()
             .expect_take_out_messages(Vec::new())
+            .expect_process_cluster_change(vec![node_id])
+            .expect_make_tick()
             .expect_take_out_messages(Vec::new());
 
         let mut driver = DriverBuilder::new()
@@ -2631,54 +7410,219 @@ mod test {
             driver.receive_message(
                 &mut mock_host,
                 instant + 10,
-                Some(create_change_cluster_request(
-                    peer_id,
-                    ChangeClusterType::ChangeTypeAddReplica
-                )),
+                Some(create_check_cluster_request()),
             )
         );
     }
 
     #[test]
-    fn test_driver_check_cluster_request() {
+    fn test_driver_raft_ready() {
         let (node_id, instant, raft_config) = create_default_parameters();
         let init_snapshot = Bytes::from(vec![2, 3, 4]);
         let peer_id = 2;
 
-        let raft_state = create_default_raft_state(node_id);
+        let raft_state = RaftState::default();
+
+        let message_a = create_raft_message(node_id, peer_id, RaftMessageType::MsgBeat);
+        let messages = vec![message_a.clone()];
+
+        let message_b = create_raft_message(node_id, peer_id, RaftMessageType::MsgAppend);
+        let persisted_messages: Vec<RaftMessage> = vec![message_b.clone()];
+
+        let entries = vec![create_empty_raft_entry(3, 2)];
+
+        let proposal_result = vec![4, 5, 6];
+        let entry_id = create_entry_id(node_id, 1);
+        let entry = create_entry(entry_id.clone(), proposal_result.clone().into());
+        let committed_normal_entry = create_raft_entry(
+            2,
+            2,
+            RaftEntryType::EntryNormal,
+            entry.encode_to_vec().into(),
+        );
+
+        let config_change = create_raft_config_change(peer_id, RaftConfigChangeType::AddNode);
+        let config_state = create_raft_config_state(vec![node_id, peer_id]);
+        let committed_config_entry = create_raft_entry(
+            3,
+            2,
+            RaftEntryType::EntryConfChange,
+            serialize_config_change(&config_change).unwrap(),
+        );
+        let committed_entries = vec![
+            committed_normal_entry.clone(),
+            committed_config_entry.clone(),
+        ];
+
+        let snapshot = create_raft_snapshot(
+            create_raft_snapshot_metadata(1, 1, create_raft_config_state(vec![node_id, peer_id])),
+            vec![1, 2, 3].into(),
+        );
+
+        let ready = RaftReady::new(
+            messages,
+            persisted_messages,
+            entries.clone(),
+            committed_entries,
+            None,
+            snapshot.clone(),
+            1,
+        );
+
+        let light_ready = RaftLightReady::default();
 
         let mut mock_host = MockHostBuilder::new()
             .expect_public_signing_key(vec![])
             .expect_send_messages(vec![create_start_replica_response(node_id)])
-            .expect_send_messages(vec![create_check_cluster_response(&raft_state)])
+            .expect_send_messages(vec![
+                out_message::Msg::SecureChannelHandshake(create_secure_channel_handshake(
+                    node_id, peer_id,
+                )),
+                create_out_deliver_app_message(entry_id.entry_id, proposal_result.clone().into()),
+            ])
             .take();
 
         let raft_builder = RaftBuilder::new()
             .expect_leader(false)
             .expect_init(|_, _, _, _, _, _| Ok(()))
+            .expect_should_snapshot(false)
+            .expect_state(&raft_state)
             .expect_has_ready(false)
-            .expect_has_ready(false)
+            .expect_has_ready(true)
+            .expect_ready(&ready)
+            .expect_committed_index(3)
+            .expect_advance_ready(ready.number(), light_ready)
+            .expect_advance_apply()
+            .expect_apply_snapshot(snapshot.clone(), |_| Ok(()))
+            .expect_append_entries(entries, |_| Ok(()))
+            .expect_apply_config_change(&config_change, Ok(config_state.clone()));
+
+        let snapshot_builder = SnapshotBuilder::new()
+            .expect_init(node_id)
+            .expect_receiver_try_complete(None)
+            .expect_receiver_try_complete(None);
+
+        let communication_builder = CommunicationBuilder::new()
+            .expect_init(node_id)
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new())
+            .expect_process_out_message(create_deliver_system_message_response(&message_a), Ok(()))
+            .expect_process_out_message(create_deliver_system_message_response(&message_b), Ok(()))
+            .expect_make_tick()
+            .expect_take_out_messages(vec![OutMessage {
+                msg: Some(out_message::Msg::SecureChannelHandshake(
+                    create_secure_channel_handshake(node_id, peer_id),
+                )),
+            }]);
+
+        let mut driver = DriverBuilder::new()
+            .expect_on_init(|_| Ok(()))
+            .expect_on_save_snapshot(Ok(init_snapshot.clone()))
+            .expect_on_load_snapshot(snapshot.data.into(), Ok(()))
+            .expect_on_process_command(None, Ok(CommandOutcome::with_none()))
+            .expect_on_apply_event(
+                ActorEventContext {
+                    index: committed_normal_entry.index,
+                    owned: true,
+                },
+                ActorEvent {
+                    correlation_id: entry_id.entry_id,
+                    contents: entry.entry_contents.into(),
+                },
+                Ok(EventOutcome::with_command(ActorCommand {
+                    correlation_id: entry_id.entry_id,
+                    header: proposal_result.into(),
+                    payload: Bytes::new(),
+                    route: String::new(),
+                    status_code: 0,
+                })),
+            )
+            .take(raft_builder, snapshot_builder, communication_builder);
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant,
+                Some(create_start_replica_request(
+                    raft_config.clone(),
+                    false,
+                    node_id,
+                    Bytes::new()
+                )),
+            )
+        );
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(&mut mock_host, instant + 10, None)
+        );
+    }
+
+    #[test]
+    fn test_driver_skip_failed_entry() {
+        let (node_id, instant, mut raft_config) = create_default_parameters();
+        raft_config.set_actor_error_policy(ActorErrorPolicy::SkipEntry);
+        let init_snapshot = Bytes::from(vec![2, 3, 4]);
+
+        let raft_state = RaftState::default();
+
+        let entry_id = create_entry_id(node_id, 1);
+        let entry = create_entry(entry_id.clone(), vec![4, 5, 6].into());
+        let committed_normal_entry = create_raft_entry(
+            2,
+            2,
+            RaftEntryType::EntryNormal,
+            entry.encode_to_vec().into(),
+        );
+
+        let ready = RaftReady::new(
+            vec![],
+            vec![],
+            vec![],
+            vec![committed_normal_entry.clone()],
+            None,
+            RaftSnapshot::default(),
+            1,
+        );
+
+        // Proposer of the failed entry is answered with internal error and the
+        // replica keeps running.
+        let mut mock_host = MockHostBuilder::new()
+            .expect_public_signing_key(vec![])
+            .expect_send_messages(vec![create_start_replica_response(node_id)])
+            .expect_send_messages(vec![out_message::Msg::DeliverAppMessage(
+                DeliverAppMessage {
+                    correlation_id: entry_id.entry_id,
+                    message_header: Bytes::new(),
+                    message_payload: Bytes::new(),
+                    route: String::new(),
+                    status_code: StatusCode::Internal as i32,
+                },
+            )])
+            .take();
+
+        let raft_builder = RaftBuilder::new()
+            .expect_leader(false)
+            .expect_init(|_, _, _, _, _, _| Ok(()))
             .expect_should_snapshot(false)
             .expect_state(&raft_state)
-            .expect_make_config_change_proposal(
-                create_raft_config_change(peer_id, RaftConfigChangeType::AddNode),
-                |_| Ok(()),
-            );
+            .expect_has_ready(false)
+            .expect_has_ready(true)
+            .expect_ready(&ready)
+            .expect_committed_index(2)
+            .expect_advance_ready(ready.number(), RaftLightReady::default())
+            .expect_advance_apply();
 
         let snapshot_builder = SnapshotBuilder::new()
             .expect_init(node_id)
-            .expect_receiver_set_instant()
             .expect_receiver_try_complete(None)
-            .expect_receiver_try_complete(None)
-            .expect_receiver_reset()
-            .expect_sender_set_instant()
-            .expect_sender_next_request(None);
+            .expect_receiver_try_complete(None);
 
         let communication_builder = CommunicationBuilder::new()
             .expect_init(node_id)
             .expect_make_tick()
             .expect_take_out_messages(Vec::new())
-            .expect_process_cluster_change(vec![node_id])
             .expect_make_tick()
             .expect_take_out_messages(Vec::new());
 
@@ -2686,6 +7630,17 @@ mod test {
             .expect_on_init(|_| Ok(()))
             .expect_on_save_snapshot(Ok(init_snapshot.clone()))
             .expect_on_process_command(None, Ok(CommandOutcome::with_none()))
+            .expect_on_apply_event(
+                ActorEventContext {
+                    index: committed_normal_entry.index,
+                    owned: true,
+                },
+                ActorEvent {
+                    correlation_id: entry_id.entry_id,
+                    contents: entry.entry_contents.into(),
+                },
+                Err(ActorError::Internal),
+            )
             .take(raft_builder, snapshot_builder, communication_builder);
 
         assert_eq!(
@@ -2695,7 +7650,7 @@ mod test {
                 instant,
                 Some(create_start_replica_request(
                     raft_config.clone(),
-                    true,
+                    false,
                     node_id,
                     Bytes::new()
                 )),
@@ -2704,79 +7659,54 @@ mod test {
 
         assert_eq!(
             Ok(()),
-            driver.receive_message(
-                &mut mock_host,
-                instant + 10,
-                Some(create_check_cluster_request()),
-            )
+            driver.receive_message(&mut mock_host, instant + 10, None)
+        );
+        assert_eq!(
+            Some(&vec![0]),
+            driver.skipped_entries.get(&committed_normal_entry.index)
         );
     }
 
     #[test]
-    fn test_driver_raft_ready() {
-        let (node_id, instant, raft_config) = create_default_parameters();
+    fn test_driver_halt_on_unsettled_skipped_entry() {
+        let (node_id, instant, mut raft_config) = create_default_parameters();
+        raft_config.set_actor_error_policy(ActorErrorPolicy::SkipEntry);
         let init_snapshot = Bytes::from(vec![2, 3, 4]);
-        let peer_id = 2;
 
         let raft_state = RaftState::default();
 
-        let message_a = create_raft_message(node_id, peer_id, RaftMessageType::MsgBeat);
-        let messages = vec![message_a.clone()];
-
-        let message_b = create_raft_message(node_id, peer_id, RaftMessageType::MsgAppend);
-        let persisted_messages: Vec<RaftMessage> = vec![message_b.clone()];
-
-        let entries = vec![create_empty_raft_entry(3, 2)];
-
-        let proposal_result = vec![4, 5, 6];
-        let entry_id = create_entry_id(node_id, 1);
-        let entry = create_entry(entry_id.clone(), proposal_result.clone().into());
-        let committed_normal_entry = create_raft_entry(
+        let failed_entry_id = create_entry_id(REPLICA_2, 1);
+        let failed_entry = create_entry(failed_entry_id.clone(), vec![4, 5, 6].into());
+        let committed_failed_entry = create_raft_entry(
             2,
             2,
             RaftEntryType::EntryNormal,
-            entry.encode_to_vec().into(),
+            failed_entry.encode_to_vec().into(),
         );
-
-        let config_change = create_raft_config_change(peer_id, RaftConfigChangeType::AddNode);
-        let config_state = create_raft_config_state(vec![node_id, peer_id]);
-        let committed_config_entry = create_raft_entry(
+        // Leader has proposed the entry after applying the failed entry without
+        // replicating the tombstone for it.
+        let mut next_entry = create_entry(create_entry_id(REPLICA_2, 2), vec![7, 8].into());
+        next_entry.proposer_applied_index = committed_failed_entry.index;
+        let committed_next_entry = create_raft_entry(
             3,
             2,
-            RaftEntryType::EntryConfChange,
-            serialize_config_change(&config_change).unwrap(),
-        );
-        let committed_entries = vec![
-            committed_normal_entry.clone(),
-            committed_config_entry.clone(),
-        ];
-
-        let snapshot = create_raft_snapshot(
-            create_raft_snapshot_metadata(1, 1, create_raft_config_state(vec![node_id, peer_id])),
-            vec![1, 2, 3].into(),
+            RaftEntryType::EntryNormal,
+            next_entry.encode_to_vec().into(),
         );
 
         let ready = RaftReady::new(
-            messages,
-            persisted_messages,
-            entries.clone(),
-            committed_entries,
+            vec![],
+            vec![],
+            vec![],
+            vec![committed_failed_entry.clone(), committed_next_entry],
             None,
-            snapshot.clone(),
+            RaftSnapshot::default(),
             1,
         );
 
-        let light_ready = RaftLightReady::default();
-
         let mut mock_host = MockHostBuilder::new()
             .expect_public_signing_key(vec![])
             .expect_send_messages(vec![create_start_replica_response(node_id)])
-            .expect_send_messages(vec![
-                out_message::Msg::SecureChannelHandshake(create_secure_channel_handshake(
-                    node_id, peer_id,
-                )),
-                create_out_deliver_app_message(entry_id.entry_id, proposal_result.clone().into()),
-            ])
             .take();
 
         let raft_builder = RaftBuilder::new()
@@ -2787,15 +7717,12 @@ mod test {
             .expect_has_ready(false)
             .expect_has_ready(true)
             .expect_ready(&ready)
-            .expect_advance_ready(ready.number(), light_ready)
-            .expect_advance_apply()
-            .expect_apply_snapshot(snapshot.clone(), |_| Ok(()))
-            .expect_append_entries(entries, |_| Ok(()))
-            .expect_apply_config_change(&config_change, Ok(config_state.clone()));
+            .expect_committed_index(3)
+            .expect_advance_ready(ready.number(), RaftLightReady::default())
+            .expect_advance_apply();
 
         let snapshot_builder = SnapshotBuilder::new()
             .expect_init(node_id)
-            .expect_receiver_set_instant()
             .expect_receiver_try_complete(None)
             .expect_receiver_try_complete(None);
 
@@ -2803,34 +7730,25 @@ mod test {
             .expect_init(node_id)
             .expect_make_tick()
             .expect_take_out_messages(Vec::new())
-            .expect_process_out_message(create_deliver_system_message_response(&message_a), Ok(()))
-            .expect_process_out_message(create_deliver_system_message_response(&message_b), Ok(()))
             .expect_make_tick()
-            .expect_take_out_messages(vec![OutMessage {
-                msg: Some(out_message::Msg::SecureChannelHandshake(
-                    create_secure_channel_handshake(node_id, peer_id),
-                )),
-            }]);
+            .expect_take_out_messages(Vec::new());
 
+        // Only the failed entry reaches the actor, the replica halts before
+        // applying the entry that settles it.
         let mut driver = DriverBuilder::new()
             .expect_on_init(|_| Ok(()))
             .expect_on_save_snapshot(Ok(init_snapshot.clone()))
-            .expect_on_load_snapshot(snapshot.data.into(), Ok(()))
             .expect_on_process_command(None, Ok(CommandOutcome::with_none()))
             .expect_on_apply_event(
                 ActorEventContext {
-                    index: committed_normal_entry.index,
-                    owned: true,
+                    index: committed_failed_entry.index,
+                    owned: false,
                 },
                 ActorEvent {
-                    correlation_id: entry_id.entry_id,
-                    contents: entry.entry_contents.into(),
+                    correlation_id: failed_entry_id.entry_id,
+                    contents: failed_entry.entry_contents.into(),
                 },
-                Ok(EventOutcome::with_command(ActorCommand {
-                    correlation_id: entry_id.entry_id,
-                    header: proposal_result.into(),
-                    payload: Bytes::new(),
-                })),
+                Err(ActorError::Internal),
             )
             .take(raft_builder, snapshot_builder, communication_builder);
 
@@ -2849,7 +7767,7 @@ mod test {
         );
 
         assert_eq!(
-            Ok(()),
+            Err(PalError::Actor),
             driver.receive_message(&mut mock_host, instant + 10, None)
         );
     }
@@ -2878,7 +7796,6 @@ mod test {
 
         let snapshot_builder = SnapshotBuilder::new()
             .expect_init(node_id)
-            .expect_receiver_set_instant()
             .expect_receiver_try_complete(None)
             .expect_receiver_try_complete(None);
 
@@ -2946,7 +7863,6 @@ mod test {
 
         let snapshot_builder = SnapshotBuilder::new()
             .expect_init(node_id)
-            .expect_receiver_set_instant()
             .expect_receiver_try_complete(None)
             .expect_receiver_try_complete(None)
             .expect_receiver_try_complete(None);
@@ -3017,6 +7933,276 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_driver_await_cluster_secret() {
+        let (node_id, instant, mut raft_config) = create_default_parameters();
+        raft_config.share_cluster_secret = true;
+        let init_snapshot = Bytes::from(vec![2, 3, 4]);
+        let peer_id = 2;
+        let cluster_secret = vec![7; 32];
+
+        let raft_state = create_default_raft_state(node_id);
+
+        let message_a = create_raft_message(peer_id, node_id, RaftMessageType::MsgBeat);
+        let message_b = create_raft_message(peer_id, node_id, RaftMessageType::MsgHeartbeat);
+
+        let handshake_message = create_secure_channel_handshake(peer_id, node_id);
+
+        let mut mock_host = MockHostBuilder::new()
+            .expect_public_signing_key(vec![])
+            .expect_send_messages(vec![create_start_replica_response(node_id)])
+            .expect_send_messages(vec![])
+            .expect_send_messages(vec![])
+            .expect_send_messages(vec![])
+            .take();
+
+        // Only the message received after the cluster secret is stepped.
+        let raft_builder = RaftBuilder::new()
+            .expect_leader(false)
+            .expect_init(|_, _, _, _, _, _| Ok(()))
+            .expect_has_ready(false)
+            .expect_has_ready(false)
+            .expect_has_ready(false)
+            .expect_has_ready(false)
+            .expect_should_snapshot(false)
+            .expect_state(&raft_state)
+            .expect_make_step(&message_b, Ok(()));
+
+        let snapshot_builder = SnapshotBuilder::new()
+            .expect_init(node_id)
+            .expect_receiver_try_complete(None)
+            .expect_receiver_try_complete(None)
+            .expect_receiver_try_complete(None)
+            .expect_receiver_try_complete(None);
+
+        let communication_builder = CommunicationBuilder::new()
+            .expect_request_cluster_secret()
+            .expect_init(node_id)
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new())
+            .expect_process_in_message(
+                create_deliver_system_message_request(&message_a)
+                    .msg
+                    .unwrap(),
+                Ok(Some(
+                    create_deliver_system_message_request(&message_a)
+                        .msg
+                        .unwrap(),
+                )),
+            )
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new())
+            .expect_process_in_message(
+                in_message::Msg::SecureChannelHandshake(handshake_message.clone()),
+                Ok(None),
+            )
+            .expect_take_cluster_secret(Some(cluster_secret.clone()))
+            .expect_set_cluster_secret(cluster_secret)
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new())
+            .expect_process_in_message(
+                create_deliver_system_message_request(&message_b)
+                    .msg
+                    .unwrap(),
+                Ok(Some(
+                    create_deliver_system_message_request(&message_b)
+                        .msg
+                        .unwrap(),
+                )),
+            )
+            .expect_make_tick()
+            .expect_take_out_messages(Vec::new());
+
+        let mut driver = DriverBuilder::new()
+            .expect_on_init(|_| Ok(()))
+            .expect_on_save_snapshot(Ok(init_snapshot.clone()))
+            .expect_on_process_command(None, Ok(CommandOutcome::with_none()))
+            .take(raft_builder, snapshot_builder, communication_builder);
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant,
+                Some(create_start_replica_request(
+                    raft_config.clone(),
+                    false,
+                    node_id,
+                    Bytes::new()
+                )),
+            )
+        );
+
+        // Message is dropped until the replica receives the cluster secret.
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 10,
+                Some(create_deliver_system_message_request(&message_a)),
+            )
+        );
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 20,
+                Some(InMessage {
+                    msg: Some(in_message::Msg::SecureChannelHandshake(
+                        handshake_message.clone()
+                    ))
+                }),
+            )
+        );
+
+        assert_eq!(
+            Ok(()),
+            driver.receive_message(
+                &mut mock_host,
+                instant + 30,
+                Some(create_deliver_system_message_request(&message_b)),
+            )
+        );
+    }
+
+    #[test]
+    fn test_driver_install_snapshot_in_chunks() {
+        let mut snapshot = create_raft_snapshot(
+            create_raft_snapshot_metadata(5, 2, create_raft_config_state(vec![REPLICA_1])),
+            vec![1, 2, 3, 4, 5].into(),
+        );
+
+        let raft_builder = RaftBuilder::new().expect_apply_snapshot(snapshot.clone(), |_| Ok(()));
+
+        let mut driver = DriverBuilder::new()
+            .expect_on_load_snapshot_chunks(vec![
+                Bytes::from(vec![1, 2]),
+                Bytes::from(vec![3, 4]),
+                Bytes::from(vec![5]),
+            ])
+            .take(
+                raft_builder,
+                SnapshotBuilder::new(),
+                CommunicationBuilder::new(),
+            );
+        driver.driver_config.snapshot_chunk_size = 2;
+
+        // Snapshot is passed to the actor piece by piece and completed once.
+        assert_eq!(driver.restore_raft_snapshot(&mut snapshot), Ok(()));
+        assert_eq!(driver.raft_progress.applied_index, 5);
+    }
+
+    #[test]
+    fn test_driver_commit_watermark() {
+        let raft_builder = RaftBuilder::new().expect_committed_index(7);
+
+        let mut driver_builder = DriverBuilder::new();
+        driver_builder
+            .mock_actor
+            .expect_on_commit_watermark()
+            .with(eq(7))
+            .once()
+            .return_once(|_| Ok(EventOutcome::with_none()));
+        let mut driver = driver_builder.take(
+            raft_builder,
+            SnapshotBuilder::new(),
+            CommunicationBuilder::new(),
+        );
+
+        // Commit index is taken from the Raft log.
+        driver.collect_committed_index();
+        assert_eq!(driver.raft_progress.committed_index, 7);
+
+        // Watermark beyond the committed index has not been reached.
+        driver.mut_core().subscribe_commit_watermark(9);
+        assert_eq!(driver.process_commit_watermark(), Ok(()));
+
+        // Lowest subscribed index is reached and the subscription fires once.
+        driver.mut_core().subscribe_commit_watermark(5);
+        assert_eq!(driver.process_commit_watermark(), Ok(()));
+        assert_eq!(driver.process_commit_watermark(), Ok(()));
+    }
+
+    #[test]
+    fn test_driver_follower_read() {
+        let raft_builder = RaftBuilder::new()
+            .expect_leader(false)
+            .expect_committed_index(10);
+
+        let mut driver = DriverBuilder::new().take(
+            raft_builder,
+            SnapshotBuilder::new(),
+            CommunicationBuilder::new(),
+        );
+        driver.driver_config.follower_read_config = FollowerReadConfig {
+            enabled: true,
+            max_index_lag: 2,
+            max_leader_silence: 100,
+        };
+        driver.clock.observe_host_instant(50);
+        driver.raft_progress.leader_contact_instant = Some(0);
+        driver.raft_progress.applied_index = 7;
+        driver.collect_committed_index();
+
+        // Actor sees the applied index and the commit index of the Raft log.
+        driver.update_context_progress();
+        assert_eq!(driver.core.borrow().applied_index(), 7);
+        assert_eq!(driver.core.borrow().committed_index(), 10);
+
+        // Applied state lags the commit index by more than allowed.
+        assert!(!driver.check_follower_read());
+
+        driver.raft_progress.applied_index = 8;
+        assert!(driver.check_follower_read());
+
+        // Leader has not been heard from for too long.
+        driver.clock.observe_host_instant(101);
+        assert!(!driver.check_follower_read());
+    }
+
+    #[test]
+    fn test_driver_metrics_report() {
+        let (node_id, _, raft_config) = create_default_parameters();
+        let raft_builder = RaftBuilder::new()
+            .expect_state(&create_default_raft_state(node_id))
+            .expect_committed_index(10);
+        let mut snapshot_builder = SnapshotBuilder::new().expect_init(node_id);
+        snapshot_builder
+            .mock_snapshot_receiver
+            .expect_transfer_progress()
+            .return_const(Some(40));
+
+        let mut driver =
+            DriverBuilder::new().take(raft_builder, snapshot_builder, CommunicationBuilder::new());
+        driver
+            .snapshot
+            .init(driver.logger.clone(), node_id, &raft_config.snapshot_config);
+        driver.driver_config.metrics_report_period = 10;
+        driver.clock.observe_host_instant(10);
+        driver.raft_progress.applied_index = 7;
+        driver.collect_committed_index();
+
+        driver.stash_metrics_report();
+
+        // Apply lag is measured against the commit index of the Raft log.
+        let report = driver.core.borrow().metrics().report();
+        let gauge = |name: &str| {
+            report
+                .metrics
+                .iter()
+                .find(|metric| metric.name == name)
+                .and_then(|metric| metric.value.clone())
+        };
+        assert_eq!(gauge(RAFT_COMMIT_INDEX), Some(metric::Value::Gauge(10)));
+        assert_eq!(gauge(RAFT_APPLIED_INDEX), Some(metric::Value::Gauge(7)));
+        assert_eq!(gauge(RAFT_APPLY_LAG), Some(metric::Value::Gauge(3)));
+        assert_eq!(
+            gauge(SNAPSHOT_TRANSFER_PROGRESS),
+            Some(metric::Value::Gauge(40))
+        );
+    }
+
     #[test]
     fn test_driver_trigger_snapshot() {
         let (node_id, instant, raft_config) = create_default_parameters();
@@ -3070,6 +8256,7 @@ mod test {
             .expect_has_ready(true)
             .expect_has_ready(false)
             .expect_ready(&ready)
+            .expect_committed_index(2)
             .expect_should_snapshot(false)
             .expect_should_snapshot(true)
             .expect_create_snapshot(
@@ -3085,7 +8272,6 @@ mod test {
 
         let snapshot_builder = SnapshotBuilder::new()
             .expect_init(node_id)
-            .expect_receiver_set_instant()
             .expect_receiver_try_complete(None)
             .expect_receiver_try_complete(None)
             .expect_receiver_try_complete(None);
@@ -3116,6 +8302,8 @@ mod test {
                     correlation_id: entry_id.entry_id,
                     header: proposal_result.into(),
                     payload: Bytes::new(),
+                    route: String::new(),
+                    status_code: 0,
                 })),
             )
             .expect_on_save_snapshot(Ok(snapshot.clone()))
@@ -3196,7 +8384,6 @@ mod test {
 
         let snapshot_builder = SnapshotBuilder::new()
             .expect_init(node_id)
-            .expect_receiver_set_instant()
             .expect_receiver_try_complete(None)
             .expect_receiver_try_complete(None)
             .expect_receiver_try_complete(Some(Ok((REPLICA_2, snapshot.clone()))))
@@ -3336,6 +8523,7 @@ mod test {
             .expect_has_ready(false)
             .expect_has_ready(false)
             .expect_ready(&ready)
+            .expect_committed_index(0)
             .expect_advance_ready(ready.number(), light_ready)
             .expect_advance_apply()
             .expect_should_snapshot(false)
@@ -3348,10 +8536,8 @@ mod test {
 
         let snapshot_builder = SnapshotBuilder::new()
             .expect_init(node_id)
-            .expect_receiver_set_instant()
             .expect_receiver_try_complete(None)
             .expect_receiver_reset()
-            .expect_sender_set_instant()
             .expect_sender_next_request(Some(deliver_snapshot_request.clone()))
             .expect_sender_next_request(None)
             .expect_sender_next_request(None)
@@ -12,8 +12,26 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use aes_gcm_siv::{
+    aead::{Aead, KeyInit},
+    Aes256GcmSiv, Nonce,
+};
+use alloc::vec;
 use alloc::vec::Vec;
+use anyhow::anyhow;
+use hkdf::Hkdf;
 use oak_proto_rust::oak::crypto::v1::SessionKeys;
+use rand::{rngs::OsRng, RngCore};
+use sha2::Sha256;
+
+// Size of the secrets exported from the session.
+const EXPORTED_SECRET_SIZE: usize = 32;
+
+// Size of the nonce prepended to each encrypted message.
+const NONCE_SIZE: usize = 12;
+
+// Context the session keys are derived with when the session is rekeyed.
+const REKEY_CONTEXT: &[u8] = b"TCP session rekey";
 
 // Encryptor trait responsible for encrypting/decrypting messages between TCP
 // replicas after handshake has successfully completed.
@@ -21,28 +39,196 @@ pub trait Encryptor {
     fn encrypt(&self, plaintext: &[u8]) -> anyhow::Result<Vec<u8>>;
 
     fn decrypt(&self, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>>;
+
+    // Exports secret bound to the session and the given context. Both peers
+    // export the same secret for the same context.
+    fn export_secret(&self, context: &[u8]) -> anyhow::Result<Vec<u8>>;
+
+    // Replaces the session keys with the keys derived from them. The replaced
+    // keys cannot be recovered from the new ones, both peers must rekey to
+    // continue the communication.
+    fn rekey(&mut self) -> anyhow::Result<()>;
 }
 
-// Default implementation for Encryptor trait.
+// Default implementation for Encryptor trait. Messages are encrypted with
+// AES-256-GCM-SIV under the session keys established by the attested
+// handshake, the request key protects the messages sent and the response key
+// the messages received. Each message is prefixed with a random nonce.
 // TODO: Use Oak's default noise encryptor implementation once it is ready.
 pub struct DefaultEncryptor {
-    _session_keys: SessionKeys,
+    session_keys: SessionKeys,
 }
 
 impl DefaultEncryptor {
     pub fn new(session_keys: SessionKeys) -> Self {
-        Self {
-            _session_keys: session_keys,
-        }
+        Self { session_keys }
     }
 }
 
 impl Encryptor for DefaultEncryptor {
     fn encrypt(&self, plaintext: &[u8]) -> anyhow::Result<Vec<u8>> {
-        Ok(plaintext.to_vec())
+        let mut nonce = [0; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = Aes256GcmSiv::new_from_slice(&self.session_keys.request_key)
+            .map_err(|e| anyhow!("Invalid request key: {}", e))?
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|e| anyhow!("Failed to encrypt message: {}", e))?;
+
+        let mut message = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+        message.extend_from_slice(&nonce);
+        message.extend_from_slice(&ciphertext);
+        Ok(message)
     }
 
     fn decrypt(&self, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
-        Ok(ciphertext.to_vec())
+        if ciphertext.len() < NONCE_SIZE {
+            return Err(anyhow!("Encrypted message is too short"));
+        }
+        let (nonce, ciphertext) = ciphertext.split_at(NONCE_SIZE);
+        Aes256GcmSiv::new_from_slice(&self.session_keys.response_key)
+            .map_err(|e| anyhow!("Invalid response key: {}", e))?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|e| anyhow!("Failed to decrypt message: {}", e))
+    }
+
+    fn export_secret(&self, context: &[u8]) -> anyhow::Result<Vec<u8>> {
+        // Request key of one peer is the response key of the other, order the
+        // keys so that both peers derive the same secret.
+        let (first_key, second_key) =
+            if self.session_keys.request_key <= self.session_keys.response_key {
+                (
+                    &self.session_keys.request_key,
+                    &self.session_keys.response_key,
+                )
+            } else {
+                (
+                    &self.session_keys.response_key,
+                    &self.session_keys.request_key,
+                )
+            };
+        let mut input_key = Vec::with_capacity(first_key.len() + second_key.len());
+        input_key.extend_from_slice(first_key);
+        input_key.extend_from_slice(second_key);
+
+        let mut secret = vec![0; EXPORTED_SECRET_SIZE];
+        Hkdf::<Sha256>::new(None, &input_key)
+            .expand(context, &mut secret)
+            .map_err(|e| anyhow!("Failed to export secret: {}", e))?;
+        Ok(secret)
+    }
+
+    fn rekey(&mut self) -> anyhow::Result<()> {
+        // Each key is derived separately so that the request key of one peer
+        // remains the response key of the other. The secret exported from the
+        // channel binds the derived keys to both keys of the attested session.
+        let channel_secret = self.export_secret(REKEY_CONTEXT)?;
+        self.session_keys.request_key =
+            derive_next_key(&channel_secret, &self.session_keys.request_key)?;
+        self.session_keys.response_key =
+            derive_next_key(&channel_secret, &self.session_keys.response_key)?;
+        Ok(())
+    }
+}
+
+fn derive_next_key(channel_secret: &[u8], key: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut next_key = vec![0; key.len()];
+    Hkdf::<Sha256>::new(Some(channel_secret), key)
+        .expand(REKEY_CONTEXT, &mut next_key)
+        .map_err(|e| anyhow!("Failed to derive session key: {}", e))?;
+    Ok(next_key)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::encryptor::*;
+
+    fn create_session_keys(request_key: &[u8], response_key: &[u8]) -> SessionKeys {
+        SessionKeys {
+            request_key: request_key.to_vec(),
+            response_key: response_key.to_vec(),
+        }
+    }
+
+    const KEY_A: &[u8; 32] = b"key_a___________________________";
+    const KEY_B: &[u8; 32] = b"key_b___________________________";
+    const KEY_C: &[u8; 32] = b"key_c___________________________";
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let initiator = DefaultEncryptor::new(create_session_keys(KEY_A, KEY_B));
+        let recipient = DefaultEncryptor::new(create_session_keys(KEY_B, KEY_A));
+        let other = DefaultEncryptor::new(create_session_keys(KEY_C, KEY_A));
+
+        let ciphertext = initiator.encrypt(b"message").unwrap();
+        assert!(!ciphertext.windows(7).any(|window| window == b"message"));
+        assert_eq!(recipient.decrypt(&ciphertext).unwrap(), b"message".to_vec());
+        // Random nonces make repeated messages indistinguishable.
+        assert_ne!(initiator.encrypt(b"message").unwrap(), ciphertext);
+
+        // Messages are only accepted from the peer holding the session keys
+        // and only if they have not been modified.
+        assert!(initiator.decrypt(&ciphertext).is_err());
+        assert!(recipient
+            .decrypt(&other.encrypt(b"message").unwrap())
+            .is_err());
+        let mut tampered = ciphertext.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(recipient.decrypt(&tampered).is_err());
+        assert!(recipient.decrypt(&ciphertext[..NONCE_SIZE - 1]).is_err());
+    }
+
+    #[test]
+    fn test_export_secret() {
+        let initiator = DefaultEncryptor::new(create_session_keys(KEY_A, KEY_B));
+        let recipient = DefaultEncryptor::new(create_session_keys(KEY_B, KEY_A));
+        let other = DefaultEncryptor::new(create_session_keys(KEY_A, KEY_C));
+
+        let secret = initiator.export_secret(b"context").unwrap();
+        assert_eq!(secret.len(), EXPORTED_SECRET_SIZE);
+        assert_eq!(recipient.export_secret(b"context").unwrap(), secret);
+        assert_ne!(initiator.export_secret(b"other").unwrap(), secret);
+        assert_ne!(other.export_secret(b"context").unwrap(), secret);
+    }
+
+    #[test]
+    fn test_rekey() {
+        let mut initiator = DefaultEncryptor::new(create_session_keys(KEY_A, KEY_B));
+        let mut recipient = DefaultEncryptor::new(create_session_keys(KEY_B, KEY_A));
+        let mut other = DefaultEncryptor::new(create_session_keys(KEY_A, KEY_C));
+        let secret = initiator.export_secret(b"context").unwrap();
+        let ciphertext = initiator.encrypt(b"message").unwrap();
+
+        // Peers that have both rekeyed share the new keys.
+        initiator.rekey().unwrap();
+        assert_ne!(initiator.export_secret(b"context").unwrap(), secret);
+        assert_ne!(initiator.session_keys.request_key, KEY_A.to_vec());
+        assert!(recipient
+            .decrypt(&initiator.encrypt(b"message").unwrap())
+            .is_err());
+        recipient.rekey().unwrap();
+        assert_eq!(
+            initiator.session_keys.request_key,
+            recipient.session_keys.response_key
+        );
+        assert_eq!(
+            recipient.export_secret(b"context").unwrap(),
+            initiator.export_secret(b"context").unwrap()
+        );
+        assert_eq!(
+            recipient
+                .decrypt(&initiator.encrypt(b"message").unwrap())
+                .unwrap(),
+            b"message".to_vec()
+        );
+        // Messages encrypted with the replaced keys are no longer accepted.
+        assert!(recipient.decrypt(&ciphertext).is_err());
+
+        // Keys derived from a channel with a different peer key differ even
+        // though the request key is the same.
+        other.rekey().unwrap();
+        assert_ne!(
+            other.session_keys.request_key,
+            initiator.session_keys.request_key
+        );
     }
 }
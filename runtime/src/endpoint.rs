@@ -0,0 +1,142 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module runs the endpoint service outside of the enclave, e.g. for local
+//! development and integration testing where the replicas run as ordinary
+//! processes. The driver is single threaded and cannot be moved between threads,
+//! hence the service is owned by a dedicated thread and the requests are passed
+//! to it through the [EndpointHandle]. The handle can be shared with the worker
+//! threads of a gRPC server, e.g. the one in the tcp_grpc_host crate that
+//! implements the `EndpointService` defined in endpoint.proto by forwarding the
+//! requests to the handle.
+
+use micro_rpc::{Status, StatusCode};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use tcp_proto::runtime::endpoint::{
+    EndpointService, ReceiveMessageRequest, ReceiveMessageResponse,
+};
+
+type Request = (
+    ReceiveMessageRequest,
+    Sender<Result<ReceiveMessageResponse, Status>>,
+);
+
+/// Handle to the endpoint service running on its own thread. Cloned handles
+/// pass the requests to the same service.
+#[derive(Clone)]
+pub struct EndpointHandle {
+    requests: Sender<Request>,
+}
+
+impl EndpointHandle {
+    /// Passes the request to the service and waits for the response. Fails if
+    /// the service thread has terminated, e.g. because the application has
+    /// encountered an unrecoverable error.
+    pub fn receive_message(
+        &self,
+        request: ReceiveMessageRequest,
+    ) -> Result<ReceiveMessageResponse, Status> {
+        let (response_sender, response_receiver) = channel();
+        self.requests
+            .send((request, response_sender))
+            .map_err(|_| Self::terminated())?;
+        response_receiver.recv().map_err(|_| Self::terminated())?
+    }
+
+    fn terminated() -> Status {
+        Status::new_with_message(StatusCode::Unavailable, "Endpoint service has terminated")
+    }
+}
+
+/// Starts the thread that creates the endpoint service, e.g.
+/// [crate::service::ApplicationService], and serves the requests passed through
+/// the returned handle. The thread terminates once all handles are dropped.
+pub fn spawn_endpoint<S, F>(create_service: F) -> (EndpointHandle, JoinHandle<()>)
+where
+    S: EndpointService,
+    F: FnOnce() -> S + Send + 'static,
+{
+    let (requests, receiver) = channel();
+    let join_handle = thread::spawn(move || serve(create_service(), receiver));
+    (EndpointHandle { requests }, join_handle)
+}
+
+fn serve<S: EndpointService>(mut service: S, requests: Receiver<Request>) {
+    for (request, response_sender) in requests {
+        // The request is abandoned if the caller has stopped waiting for the response.
+        let _ = response_sender.send(service.receive_message(request));
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::endpoint::*;
+    use alloc::vec::Vec;
+    use tcp_proto::runtime::endpoint::OutMessage;
+
+    // Responds with as many messages as the instant of the request, panics on the
+    // instant of zero.
+    struct FakeService {}
+
+    impl EndpointService for FakeService {
+        fn receive_message(
+            &mut self,
+            request: ReceiveMessageRequest,
+        ) -> Result<ReceiveMessageResponse, Status> {
+            assert!(request.instant > 0);
+            Ok(ReceiveMessageResponse {
+                messages: (0..request.instant)
+                    .map(|_| OutMessage::default())
+                    .collect(),
+                ..Default::default()
+            })
+        }
+    }
+
+    fn create_request(instant: u64) -> ReceiveMessageRequest {
+        ReceiveMessageRequest {
+            instant,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_endpoint() {
+        let (handle, join_handle) = spawn_endpoint(|| FakeService {});
+
+        let other_handle = handle.clone();
+        let responses: Vec<usize> = thread::spawn(move || {
+            (1..4)
+                .map(|instant| {
+                    other_handle
+                        .receive_message(create_request(instant))
+                        .unwrap()
+                        .messages
+                        .len()
+                })
+                .collect()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(responses, [1, 2, 3]);
+
+        // Service that has terminated is unavailable.
+        assert_eq!(
+            handle.receive_message(create_request(0)).unwrap_err().code,
+            StatusCode::Unavailable
+        );
+        assert!(join_handle.join().is_err());
+    }
+}
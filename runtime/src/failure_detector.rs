@@ -0,0 +1,108 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use hashbrown::HashMap;
+
+/// Tracks when the leader last heard from each of the peers. A peer that
+/// has not been heard from within the timeout is considered dead. Peers that
+/// have never been heard from are measured from the instant tracking started.
+pub struct FailureDetector {
+    timeout: u64,
+    // Instant since which the peers are tracked.
+    start_instant: u64,
+    contact_instants: HashMap<u64, u64>,
+}
+
+impl FailureDetector {
+    /// Creates failure detector with detection disabled.
+    pub fn new() -> FailureDetector {
+        FailureDetector {
+            timeout: 0,
+            start_instant: 0,
+            contact_instants: HashMap::new(),
+        }
+    }
+
+    /// Sets time since the last contact after which a peer is considered dead.
+    /// Zero timeout disables detection.
+    pub fn configure(&mut self, timeout: u64) {
+        self.timeout = timeout;
+    }
+
+    /// Checks if the dead peers are detected.
+    pub fn enabled(&self) -> bool {
+        self.timeout != 0
+    }
+
+    /// Records that a message has been received from the peer.
+    pub fn observe(&mut self, peer_id: u64, instant: u64) {
+        if self.enabled() {
+            self.contact_instants.insert(peer_id, instant);
+        }
+    }
+
+    /// Finds the first of the given peers that is considered dead.
+    pub fn find_dead(&self, peer_ids: impl Iterator<Item = u64>, instant: u64) -> Option<u64> {
+        if !self.enabled() {
+            return None;
+        }
+
+        peer_ids.into_iter().find(|peer_id| {
+            let contact_instant = self
+                .contact_instants
+                .get(peer_id)
+                .copied()
+                .unwrap_or(self.start_instant)
+                .max(self.start_instant);
+            instant.saturating_sub(contact_instant) >= self.timeout
+        })
+    }
+
+    /// Forgets all observations and starts tracking from the given instant,
+    /// must be called when the leadership or the cluster changes.
+    pub fn reset(&mut self, instant: u64) {
+        self.start_instant = instant;
+        self.contact_instants.clear();
+    }
+}
+
+impl Default for FailureDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::failure_detector::FailureDetector;
+
+    #[test]
+    fn test_find_dead() {
+        let mut failure_detector = FailureDetector::new();
+        failure_detector.observe(2, 0);
+        assert_eq!(failure_detector.find_dead([2, 3].into_iter(), 1000), None);
+
+        failure_detector.configure(100);
+        failure_detector.reset(50);
+        failure_detector.observe(2, 60);
+        assert_eq!(failure_detector.find_dead([2, 3].into_iter(), 149), None);
+        // Peer 3 has not been heard from since tracking started.
+        assert_eq!(failure_detector.find_dead([2, 3].into_iter(), 150), Some(3));
+        failure_detector.observe(3, 150);
+        assert_eq!(failure_detector.find_dead([3, 2].into_iter(), 160), Some(2));
+
+        failure_detector.reset(200);
+        assert_eq!(failure_detector.find_dead([2, 3].into_iter(), 250), None);
+    }
+}
@@ -0,0 +1,209 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+
+struct PeerFlow {
+    // Instant of the oldest append message that has not been acknowledged yet.
+    append_instant: Option<u64>,
+    // Smoothed latency of acknowledging append messages.
+    latency: u64,
+    // Maximum number of append messages in flight currently set for the peer.
+    max_inflight_msgs: usize,
+}
+
+/// Tracks replication latency of the peers and derives the number of append
+/// messages that can be in flight to each of them. Slow peers get a smaller
+/// window so that they cannot monopolize the outbound bandwidth.
+pub struct FlowControl {
+    max_inflight_msgs: usize,
+    slow_peer_max_inflight_msgs: usize,
+    slow_peer_latency: u64,
+    peers: HashMap<u64, PeerFlow>,
+}
+
+impl FlowControl {
+    /// Creates flow control with latency based adjustment disabled.
+    pub fn new() -> FlowControl {
+        FlowControl {
+            max_inflight_msgs: 0,
+            slow_peer_max_inflight_msgs: 0,
+            slow_peer_latency: 0,
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Sets windows for regular and slow peers along with the latency above
+    /// which a peer is considered slow. Zero latency disables adjustment.
+    pub fn configure(
+        &mut self,
+        max_inflight_msgs: usize,
+        slow_peer_max_inflight_msgs: usize,
+        slow_peer_latency: u64,
+    ) {
+        self.max_inflight_msgs = max_inflight_msgs;
+        self.slow_peer_max_inflight_msgs = slow_peer_max_inflight_msgs.min(max_inflight_msgs);
+        self.slow_peer_latency = slow_peer_latency;
+    }
+
+    /// Checks if windows are adjusted based on the observed latency.
+    pub fn enabled(&self) -> bool {
+        self.slow_peer_latency != 0 && self.slow_peer_max_inflight_msgs != 0
+    }
+
+    /// Records that an append message has been sent to the peer.
+    pub fn observe_append(&mut self, peer_id: u64, instant: u64) {
+        if !self.enabled() {
+            return;
+        }
+
+        let max_inflight_msgs = self.max_inflight_msgs;
+        let peer = self.peers.entry(peer_id).or_insert_with(|| PeerFlow {
+            append_instant: None,
+            latency: 0,
+            max_inflight_msgs,
+        });
+        if peer.append_instant.is_none() {
+            peer.append_instant = Some(instant);
+        }
+    }
+
+    /// Records that the peer has acknowledged append messages.
+    pub fn observe_append_response(&mut self, peer_id: u64, instant: u64) {
+        if let Some(peer) = self.peers.get_mut(&peer_id)
+            && let Some(append_instant) = peer.append_instant.take()
+        {
+            let sample = instant.saturating_sub(append_instant);
+            peer.latency = (peer.latency + sample) / 2;
+        }
+    }
+
+    /// Gets smoothed replication latency of the peer.
+    pub fn latency(&self, peer_id: u64) -> Option<u64> {
+        self.peers.get(&peer_id).map(|peer| peer.latency)
+    }
+
+    /// Takes peers whose window must change along with the new window. Appends
+    /// that remain unacknowledged for too long mark the peer as slow as well.
+    pub fn take_adjustments(&mut self, instant: u64) -> Vec<(u64, usize)> {
+        let mut adjustments = Vec::new();
+        if !self.enabled() {
+            return adjustments;
+        }
+
+        for (peer_id, peer) in self.peers.iter_mut() {
+            let pending = peer
+                .append_instant
+                .map_or(0, |append_instant| instant.saturating_sub(append_instant));
+            let max_inflight_msgs = if peer.latency.max(pending) > self.slow_peer_latency {
+                self.slow_peer_max_inflight_msgs
+            } else {
+                self.max_inflight_msgs
+            };
+
+            if peer.max_inflight_msgs != max_inflight_msgs {
+                peer.max_inflight_msgs = max_inflight_msgs;
+                adjustments.push((*peer_id, max_inflight_msgs));
+            }
+        }
+
+        adjustments
+    }
+
+    /// Forgets all observations, must be called when the leadership changes.
+    /// Takes peers whose window has been shrunk along with the window to
+    /// restore, since Raft keeps the windows across the leadership changes.
+    pub fn reset(&mut self) -> Vec<(u64, usize)> {
+        let max_inflight_msgs = self.max_inflight_msgs;
+        self.peers
+            .drain()
+            .filter(|(_, peer)| peer.max_inflight_msgs != max_inflight_msgs)
+            .map(|(peer_id, _)| (peer_id, max_inflight_msgs))
+            .collect()
+    }
+}
+
+impl Default for FlowControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::flow_control::FlowControl;
+    use alloc::vec;
+
+    #[test]
+    fn test_disabled() {
+        let mut flow_control = FlowControl::new();
+        flow_control.configure(256, 16, 0);
+
+        flow_control.observe_append(2, 0);
+        flow_control.observe_append_response(2, 1000);
+
+        assert!(flow_control.take_adjustments(1000).is_empty());
+        assert_eq!(flow_control.latency(2), None);
+    }
+
+    #[test]
+    fn test_adjust_slow_peer() {
+        let mut flow_control = FlowControl::new();
+        flow_control.configure(256, 16, 100);
+
+        flow_control.observe_append(2, 0);
+        flow_control.observe_append(3, 0);
+        flow_control.observe_append_response(2, 10);
+
+        // Peer 3 has not acknowledged appends for too long.
+        assert_eq!(flow_control.take_adjustments(200), vec![(3, 16)]);
+        assert!(flow_control.take_adjustments(300).is_empty());
+
+        flow_control.observe_append_response(3, 300);
+        flow_control.observe_append(3, 400);
+        flow_control.observe_append_response(3, 410);
+        flow_control.observe_append(3, 500);
+        flow_control.observe_append_response(3, 510);
+        assert_eq!(flow_control.latency(3), Some(45));
+
+        // Peer 3 has recovered.
+        assert_eq!(flow_control.take_adjustments(600), vec![(3, 256)]);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut flow_control = FlowControl::new();
+        flow_control.configure(256, 16, 100);
+
+        flow_control.observe_append(2, 0);
+        flow_control.observe_append(3, 0);
+        flow_control.observe_append_response(2, 10);
+        assert_eq!(flow_control.take_adjustments(200), vec![(3, 16)]);
+
+        // Window of the slow peer is restored and its pending append is forgotten.
+        assert_eq!(flow_control.reset(), vec![(3, 256)]);
+        assert_eq!(flow_control.latency(2), None);
+        assert_eq!(flow_control.latency(3), None);
+        assert!(flow_control.reset().is_empty());
+
+        // Appends sent before the reset don't count towards the latency.
+        flow_control.observe_append_response(3, 1000);
+        assert_eq!(flow_control.latency(3), None);
+        flow_control.observe_append(3, 1000);
+        flow_control.observe_append_response(3, 1010);
+        assert_eq!(flow_control.latency(3), Some(5));
+        assert!(flow_control.take_adjustments(1100).is_empty());
+    }
+}
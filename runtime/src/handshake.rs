@@ -57,6 +57,10 @@ pub trait HandshakeSessionProvider {
         role: Role,
         logger: Logger,
     ) -> Box<dyn HandshakeSession>;
+
+    /// Replaces the policy the attestation evidence of the peers is verified
+    /// against by the sessions returned from now on.
+    fn update_attestation_policy(&self, policy: &AttestationPolicy);
 }
 
 /// Responsible for establishing a handshake between two raft replicas.
@@ -106,23 +110,37 @@ impl HandshakeSessionProvider for DefaultHandshakeSessionProvider {
         role: Role,
         logger: Logger,
     ) -> Box<dyn HandshakeSession> {
+        // The attestation evidence binds the public key the session keys are
+        // agreed on with.
         match role {
-            Role::Initiator => Box::new(ClientHandshakeSession::new(
-                logger,
-                self_replica_id,
-                peer_replica_id,
-                self.attestation_provider.get_client_attestation(),
-                self.oak_handshaker_factory.get_client_oak_handshaker(),
-            )),
-            Role::Recipient => Box::new(ServerHandshakeSession::new(
-                logger,
-                self_replica_id,
-                peer_replica_id,
-                self.attestation_provider.get_server_attestation(),
-                self.oak_handshaker_factory.get_server_oak_handshaker(),
-            )),
+            Role::Initiator => {
+                let oak_handshaker = self.oak_handshaker_factory.get_client_oak_handshaker();
+                Box::new(ClientHandshakeSession::new(
+                    logger,
+                    self_replica_id,
+                    peer_replica_id,
+                    self.attestation_provider
+                        .get_client_attestation(&oak_handshaker.public_key()),
+                    oak_handshaker,
+                ))
+            }
+            Role::Recipient => {
+                let oak_handshaker = self.oak_handshaker_factory.get_server_oak_handshaker();
+                Box::new(ServerHandshakeSession::new(
+                    logger,
+                    self_replica_id,
+                    peer_replica_id,
+                    self.attestation_provider
+                        .get_server_attestation(&oak_handshaker.public_key()),
+                    oak_handshaker,
+                ))
+            }
         }
     }
+
+    fn update_attestation_policy(&self, policy: &AttestationPolicy) {
+        self.attestation_provider.update_policy(policy)
+    }
 }
 
 #[derive(PartialEq)]
@@ -655,11 +673,13 @@ mod test {
     extern crate mockall;
 
     use self::mockall::predicate::eq;
+    use crate::attestation::DefaultAttestationProvider;
     use crate::handshake::{
         ClientHandshakeSession, DefaultHandshakeSessionProvider, HandshakeSession,
         HandshakeSessionProvider, Role, ServerHandshakeSession,
     };
     use crate::logger::log::create_logger;
+    use crate::oak_handshaker::DefaultOakHandshakerFactory;
     use alloc::vec;
     use anyhow::{anyhow, Result};
     use core::mem;
@@ -685,6 +705,9 @@ mod test {
         *,
     };
 
+    // Public key of the key pair the mock handshakers generate for the session.
+    const SESSION_PUBLIC_KEY: &[u8] = b"session_public_key";
+
     fn create_attest_request(
         sender_replica_id: u64,
         recipient_replica_id: u64,
@@ -762,7 +785,8 @@ mod test {
         ) -> AttestationProviderBuilder {
             self.mock_attestation_provider
                 .expect_get_client_attestation()
-                .return_once(move || Box::new(mock_attestation));
+                .with(eq(SESSION_PUBLIC_KEY))
+                .return_once(move |_| Box::new(mock_attestation));
             self
         }
 
@@ -772,7 +796,8 @@ mod test {
         ) -> AttestationProviderBuilder {
             self.mock_attestation_provider
                 .expect_get_server_attestation()
-                .return_once(move || Box::new(mock_attestation));
+                .with(eq(SESSION_PUBLIC_KEY))
+                .return_once(move |_| Box::new(mock_attestation));
             self
         }
 
@@ -939,8 +964,12 @@ mod test {
 
     impl OakClientHandshakerBuilder {
         fn new() -> OakClientHandshakerBuilder {
+            let mut mock_oak_client_handshaker = MockOakClientHandshaker::new();
+            mock_oak_client_handshaker
+                .expect_public_key()
+                .return_const(SESSION_PUBLIC_KEY.to_vec());
             OakClientHandshakerBuilder {
-                mock_oak_client_handshaker: MockOakClientHandshaker::new(),
+                mock_oak_client_handshaker,
             }
         }
 
@@ -998,8 +1027,12 @@ mod test {
 
     impl OakServerHandshakerBuilder {
         fn new() -> OakServerHandshakerBuilder {
+            let mut mock_oak_server_handshaker = MockOakServerHandshaker::new();
+            mock_oak_server_handshaker
+                .expect_public_key()
+                .return_const(SESSION_PUBLIC_KEY.to_vec());
             OakServerHandshakerBuilder {
-                mock_oak_server_handshaker: MockOakServerHandshaker::new(),
+                mock_oak_server_handshaker,
             }
         }
 
@@ -1661,4 +1694,40 @@ mod test {
                 .is_err()
         );
     }
+
+    #[test]
+    fn test_default_session_keys_agree() {
+        let client_provider = DefaultHandshakeSessionProvider::new(
+            Box::new(DefaultAttestationProvider {}),
+            Box::new(DefaultOakHandshakerFactory {}),
+        );
+        let server_provider = DefaultHandshakeSessionProvider::new(
+            Box::new(DefaultAttestationProvider {}),
+            Box::new(DefaultOakHandshakerFactory {}),
+        );
+        let mut client = client_provider.get(1, 2, Role::Initiator, create_logger());
+        let mut server = server_provider.get(2, 1, Role::Recipient, create_logger());
+
+        while !client.is_completed() || !server.is_completed() {
+            if let Some(message) = client.take_out_message().unwrap() {
+                server.process_message(&message).unwrap();
+            }
+            if let Some(message) = server.take_out_message().unwrap() {
+                client.process_message(&message).unwrap();
+            }
+        }
+
+        // Both sides derive the same keys from the session public keys bound
+        // to the exchanged evidence.
+        let client_encryptor = client.get_encryptor().unwrap();
+        let server_encryptor = server.get_encryptor().unwrap();
+        assert_eq!(
+            client_encryptor.export_secret(b"context").unwrap(),
+            server_encryptor.export_secret(b"context").unwrap()
+        );
+        assert_ne!(
+            client_encryptor.export_secret(b"context").unwrap(),
+            vec![0; 32]
+        );
+    }
 }
@@ -30,25 +30,52 @@ extern crate oak_restricted_kernel_sdk;
 extern crate oak_session;
 extern crate prost;
 extern crate raft;
+extern crate rand;
+extern crate sha2;
 extern crate slog;
 extern crate tcp_proto;
 
 pub mod attestation;
+pub mod backoff;
+pub mod batcher;
+pub mod clock;
 pub mod communication;
+pub mod compaction;
 pub mod consensus;
 pub mod driver;
 pub mod encryptor;
+#[cfg(feature = "std")]
+pub mod endpoint;
+pub mod failure_detector;
+pub mod flow_control;
 pub mod handshake;
 pub mod logger;
+pub mod mailbox;
+pub mod metrics;
 #[cfg(feature = "std")]
 pub mod mock;
 pub mod model;
+pub mod multi_raft;
 pub mod oak_handshaker;
 pub mod platform;
+pub mod priority;
+pub mod random;
+pub mod read_index;
+pub mod recovery;
+pub mod router;
+pub mod sealed;
+pub mod secret;
+#[cfg(not(feature = "std"))]
+pub mod server;
 pub mod service;
 pub mod session;
+pub mod sev_snp;
 pub mod snapshot;
 pub mod storage;
+pub mod tdx;
+pub mod timer;
+pub mod typed;
+pub mod upgrade;
 pub mod util;
 
 #[cfg(not(feature = "std"))]
@@ -12,18 +12,134 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::platform::LogSink;
 use alloc::boxed::Box;
 use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
 use core::result::Result;
 use slog::{o, Drain, Logger, Serializer, KV};
-use tcp_proto::runtime::endpoint::{LogMessage, LogSeverity};
+use tcp_proto::runtime::endpoint::{raft_config::LogConfig, LogMessage, LogSeverity};
 
 pub trait DrainOutput {
     fn take_entries(&mut self) -> Vec<LogMessage>;
 }
 
+/// Rewrites the log messages before they leave the trusted application, e.g.
+/// masks the values that must not be revealed to the untrusted launcher.
+pub trait LogRedactor {
+    fn redact(&self, message: &mut String);
+}
+
+impl<F: Fn(&mut String)> LogRedactor for F {
+    fn redact(&self, message: &mut String) {
+        self(message)
+    }
+}
+
+/// Implementation of `LogSink` that drops the messages below the minimum
+/// severity and the messages above the rate limit, and passes the remaining
+/// messages through the redactors in the order they have been added.
+pub struct DefaultLogSink {
+    min_severity: LogSeverity,
+    max_messages: u64,
+    period: u64,
+    redactors: Vec<Box<dyn LogRedactor>>,
+    // Instant the current period has started at.
+    period_instant: u64,
+    period_messages: u64,
+    dropped_messages: u64,
+}
+
+impl DefaultLogSink {
+    /// Creates sink that passes all messages as is.
+    pub fn new() -> DefaultLogSink {
+        DefaultLogSink {
+            min_severity: LogSeverity::Unspecified,
+            max_messages: 0,
+            period: 0,
+            redactors: Vec::new(),
+            period_instant: 0,
+            period_messages: 0,
+            dropped_messages: 0,
+        }
+    }
+
+    /// Adds the redactor the messages pass through after the redactors added
+    /// before.
+    pub fn with_redactor(mut self, redactor: Box<dyn LogRedactor>) -> Self {
+        self.redactors.push(redactor);
+        self
+    }
+
+    fn start_period(&mut self, instant: u64, messages: &mut Vec<LogMessage>) {
+        if self.dropped_messages > 0 {
+            messages.push(LogMessage {
+                severity: LogSeverity::Warning.into(),
+                message: format!("Dropped {} log messages", self.dropped_messages),
+            });
+        }
+        self.period_instant = instant;
+        self.period_messages = 0;
+        self.dropped_messages = 0;
+    }
+}
+
+impl Default for DefaultLogSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogSink for DefaultLogSink {
+    fn configure(&mut self, config: &LogConfig) {
+        self.min_severity = config.min_severity();
+        self.max_messages = config.max_messages;
+        self.period = config.period;
+    }
+
+    fn process(&mut self, instant: u64, messages: Vec<LogMessage>) -> Vec<LogMessage> {
+        let mut passed = Vec::new();
+        if self.max_messages > 0 && instant >= self.period_instant.saturating_add(self.period) {
+            self.start_period(instant, &mut passed);
+        }
+
+        for mut message in messages {
+            if severity_rank(message.severity()) < severity_rank(self.min_severity) {
+                continue;
+            }
+            if self.max_messages > 0 {
+                if self.period_messages >= self.max_messages {
+                    self.dropped_messages += 1;
+                    continue;
+                }
+                self.period_messages += 1;
+            }
+            for redactor in &self.redactors {
+                redactor.redact(&mut message.message);
+            }
+            passed.push(message);
+        }
+
+        passed
+    }
+}
+
+// Orders the severities from the least to the most severe. Unspecified
+// severity ranks the lowest, hence as the minimum severity it passes all
+// messages.
+fn severity_rank(severity: LogSeverity) -> u8 {
+    match severity {
+        LogSeverity::Unspecified => 0,
+        LogSeverity::Trace => 1,
+        LogSeverity::Debug => 2,
+        LogSeverity::Info => 3,
+        LogSeverity::Warning => 4,
+        LogSeverity::Error => 5,
+        LogSeverity::Critical => 6,
+    }
+}
+
 struct ValueSerializer {
     output: String,
 }
@@ -228,3 +344,76 @@ pub mod log {
         Logger::root(Discard, o!())
     }
 }
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::logger::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    fn create_message(severity: LogSeverity, message: &str) -> LogMessage {
+        LogMessage {
+            severity: severity.into(),
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_default_log_sink() {
+        let mut sink = DefaultLogSink::new().with_redactor(Box::new(|message: &mut String| {
+            *message = message.replace("secret", "***")
+        }));
+
+        // Messages pass as is, apart from redaction, until the sink is configured.
+        assert_eq!(
+            sink.process(
+                0,
+                vec![
+                    create_message(LogSeverity::Trace, "a"),
+                    create_message(LogSeverity::Info, "key secret")
+                ]
+            ),
+            vec![
+                create_message(LogSeverity::Trace, "a"),
+                create_message(LogSeverity::Info, "key ***")
+            ]
+        );
+
+        sink.configure(&LogConfig {
+            min_severity: LogSeverity::Info.into(),
+            max_messages: 2,
+            period: 100,
+        });
+
+        // Messages below the minimum severity are dropped without counting
+        // towards the rate limit.
+        assert_eq!(
+            sink.process(
+                100,
+                vec![
+                    create_message(LogSeverity::Debug, "b"),
+                    create_message(LogSeverity::Warning, "c"),
+                    create_message(LogSeverity::Critical, "d"),
+                    create_message(LogSeverity::Error, "e"),
+                ]
+            ),
+            vec![
+                create_message(LogSeverity::Warning, "c"),
+                create_message(LogSeverity::Critical, "d")
+            ]
+        );
+        assert!(sink
+            .process(150, vec![create_message(LogSeverity::Error, "f")])
+            .is_empty());
+
+        // Number of dropped messages is reported once the next period starts.
+        assert_eq!(
+            sink.process(200, vec![create_message(LogSeverity::Info, "g")]),
+            vec![
+                create_message(LogSeverity::Warning, "Dropped 2 log messages"),
+                create_message(LogSeverity::Info, "g")
+            ]
+        );
+        assert!(sink.process(300, vec![]).is_empty());
+    }
+}
@@ -0,0 +1,127 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::collections::VecDeque;
+use tcp_proto::runtime::endpoint::raft_config::ShedPolicy;
+
+/// Bounded queue of incoming messages. When the mailbox is full a message is
+/// shed according to the configured policy.
+pub struct Mailbox<T> {
+    messages: VecDeque<T>,
+    capacity: usize,
+    policy: ShedPolicy,
+    shed_count: u64,
+}
+
+impl<T> Mailbox<T> {
+    /// Creates an unbounded mailbox.
+    pub fn new() -> Mailbox<T> {
+        Mailbox {
+            messages: VecDeque::new(),
+            capacity: 0,
+            policy: ShedPolicy::RejectNewest,
+            shed_count: 0,
+        }
+    }
+
+    /// Sets maximum number of messages in the mailbox and shedding policy.
+    /// Zero capacity means no limit. Messages in excess of the new capacity
+    /// are kept until they are popped.
+    pub fn configure(&mut self, capacity: usize, policy: ShedPolicy) {
+        self.capacity = capacity;
+        self.policy = match policy {
+            ShedPolicy::Unspecified => ShedPolicy::RejectNewest,
+            _ => policy,
+        };
+    }
+
+    /// Appends message to the mailbox. Returns the message that has been shed
+    /// if the mailbox is full.
+    pub fn push(&mut self, message: T) -> Option<T> {
+        if self.capacity == 0 || self.messages.len() < self.capacity {
+            self.messages.push_back(message);
+            return None;
+        }
+
+        self.shed_count += 1;
+        match self.policy {
+            ShedPolicy::DropOldest => {
+                let shed = self.messages.pop_front();
+                self.messages.push_back(message);
+                shed
+            }
+            _ => Some(message),
+        }
+    }
+
+    /// Removes the oldest message from the mailbox.
+    pub fn pop(&mut self) -> Option<T> {
+        self.messages.pop_front()
+    }
+
+    /// Gets number of messages in the mailbox.
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Checks if the mailbox has no messages.
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Gets total number of messages shed by the mailbox.
+    pub fn shed_count(&self) -> u64 {
+        self.shed_count
+    }
+}
+
+impl<T> Default for Mailbox<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::mailbox::Mailbox;
+    use tcp_proto::runtime::endpoint::raft_config::ShedPolicy;
+
+    #[test]
+    fn test_reject_newest() {
+        let mut mailbox = Mailbox::new();
+        mailbox.configure(2, ShedPolicy::Unspecified);
+
+        assert_eq!(mailbox.push(1), None);
+        assert_eq!(mailbox.push(2), None);
+        assert_eq!(mailbox.push(3), Some(3));
+        assert_eq!(mailbox.shed_count(), 1);
+        assert_eq!(mailbox.pop(), Some(1));
+        assert_eq!(mailbox.pop(), Some(2));
+        assert_eq!(mailbox.pop(), None);
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        let mut mailbox = Mailbox::new();
+        mailbox.configure(2, ShedPolicy::DropOldest);
+
+        assert_eq!(mailbox.push(1), None);
+        assert_eq!(mailbox.push(2), None);
+        assert_eq!(mailbox.push(3), Some(1));
+        assert_eq!(mailbox.shed_count(), 1);
+        assert_eq!(mailbox.len(), 2);
+        assert_eq!(mailbox.pop(), Some(2));
+        assert_eq!(mailbox.pop(), Some(3));
+    }
+}
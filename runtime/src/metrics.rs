@@ -0,0 +1,199 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use tcp_proto::runtime::endpoint::{metric, Metric, MetricsReport};
+
+/// Bounds of the histogram buckets used unless the histogram is registered with
+/// its own bounds, covering the latencies in milliseconds and the sizes.
+pub const DEFAULT_HISTOGRAM_BOUNDS: [u64; 12] =
+    [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000, 2000, 5000];
+
+/// Names of the metrics reported by the driver.
+pub const RAFT_TERM: &str = "raft.term";
+pub const RAFT_LEADER_ID: &str = "raft.leader_id";
+pub const RAFT_COMMIT_INDEX: &str = "raft.commit_index";
+pub const RAFT_APPLIED_INDEX: &str = "raft.applied_index";
+pub const RAFT_APPLY_LAG: &str = "raft.apply_lag";
+pub const SNAPSHOT_CREATED: &str = "snapshot.created";
+pub const SNAPSHOT_LOADED: &str = "snapshot.loaded";
+/// Percentage of the chunks of the snapshot transfers in progress that have
+/// been delivered, 100 if no snapshot is being transferred.
+pub const SNAPSHOT_TRANSFER_PROGRESS: &str = "snapshot.transfer_progress";
+
+enum MetricValue {
+    Counter(u64),
+    Gauge(i64),
+    Histogram(metric::Histogram),
+}
+
+/// Registry of the counters, gauges and histograms of the replica. The registry
+/// is shared by the driver and the actor, see
+/// [crate::model::ActorContext::metrics], and its snapshot is periodically
+/// reported to the untrusted launcher. Metrics are created on first use, using a
+/// name for a metric of a different kind replaces the metric.
+pub struct MetricsRegistry {
+    metrics: RefCell<BTreeMap<String, MetricValue>>,
+}
+
+impl MetricsRegistry {
+    pub fn new() -> MetricsRegistry {
+        MetricsRegistry {
+            metrics: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// Adds the delta to the counter.
+    pub fn increment_counter(&self, name: &str, delta: u64) {
+        let mut metrics = self.metrics.borrow_mut();
+        match metrics.get_mut(name) {
+            Some(MetricValue::Counter(value)) => *value = value.saturating_add(delta),
+            _ => {
+                metrics.insert(name.to_string(), MetricValue::Counter(delta));
+            }
+        }
+    }
+
+    /// Sets the current value of the gauge.
+    pub fn set_gauge(&self, name: &str, value: i64) {
+        self.metrics
+            .borrow_mut()
+            .insert(name.to_string(), MetricValue::Gauge(value));
+    }
+
+    /// Creates the histogram with the given bucket bounds in increasing order,
+    /// replacing the values observed so far.
+    pub fn register_histogram(&self, name: &str, bounds: Vec<u64>) {
+        let counts = vec![0; bounds.len() + 1];
+        self.metrics.borrow_mut().insert(
+            name.to_string(),
+            MetricValue::Histogram(metric::Histogram {
+                bounds,
+                counts,
+                sum: 0,
+            }),
+        );
+    }
+
+    /// Adds the value to the histogram, the histogram that has not been
+    /// registered is created with the default bounds.
+    pub fn observe(&self, name: &str, value: u64) {
+        if !matches!(
+            self.metrics.borrow().get(name),
+            Some(MetricValue::Histogram(_))
+        ) {
+            self.register_histogram(name, DEFAULT_HISTOGRAM_BOUNDS.to_vec());
+        }
+
+        let mut metrics = self.metrics.borrow_mut();
+        if let Some(MetricValue::Histogram(histogram)) = metrics.get_mut(name) {
+            let bucket = histogram.bounds.partition_point(|bound| *bound < value);
+            histogram.counts[bucket] += 1;
+            histogram.sum = histogram.sum.saturating_add(value);
+        }
+    }
+
+    /// Creates the report with the current values of all metrics ordered by
+    /// name.
+    pub fn report(&self) -> MetricsReport {
+        MetricsReport {
+            metrics: self
+                .metrics
+                .borrow()
+                .iter()
+                .map(|(name, value)| Metric {
+                    name: name.clone(),
+                    value: Some(match value {
+                        MetricValue::Counter(value) => metric::Value::Counter(*value),
+                        MetricValue::Gauge(value) => metric::Value::Gauge(*value),
+                        MetricValue::Histogram(histogram) => {
+                            metric::Value::Histogram(histogram.clone())
+                        }
+                    }),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Default for MetricsRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::metrics::*;
+
+    fn find_value(report: &MetricsReport, name: &str) -> Option<metric::Value> {
+        report
+            .metrics
+            .iter()
+            .find(|metric| metric.name == name)
+            .and_then(|metric| metric.value.clone())
+    }
+
+    #[test]
+    fn test_metrics_registry() {
+        let registry = MetricsRegistry::new();
+        assert!(registry.report().metrics.is_empty());
+
+        registry.increment_counter("a", 2);
+        registry.increment_counter("a", 3);
+        registry.set_gauge("b", 7);
+        registry.set_gauge("b", -1);
+        registry.register_histogram("c", vec![10, 100]);
+        for value in [5, 10, 11, 1000] {
+            registry.observe("c", value);
+        }
+        registry.observe("d", 3);
+
+        let report = registry.report();
+        assert_eq!(
+            report
+                .metrics
+                .iter()
+                .map(|metric| metric.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["a", "b", "c", "d"]
+        );
+        assert_eq!(find_value(&report, "a"), Some(metric::Value::Counter(5)));
+        assert_eq!(find_value(&report, "b"), Some(metric::Value::Gauge(-1)));
+        assert_eq!(
+            find_value(&report, "c"),
+            Some(metric::Value::Histogram(metric::Histogram {
+                bounds: vec![10, 100],
+                counts: vec![2, 1, 1],
+                sum: 1026,
+            }))
+        );
+        let Some(metric::Value::Histogram(histogram)) = find_value(&report, "d") else {
+            panic!("Histogram expected");
+        };
+        assert_eq!(histogram.bounds, DEFAULT_HISTOGRAM_BOUNDS.to_vec());
+        assert_eq!(histogram.counts[2], 1);
+
+        // Metric of a different kind replaces the metric.
+        registry.increment_counter("b", 1);
+        assert_eq!(
+            find_value(&registry.report(), "b"),
+            Some(metric::Value::Counter(1))
+        );
+    }
+}
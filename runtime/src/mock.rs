@@ -19,6 +19,7 @@ extern crate tcp_proto;
 
 use self::mockall::mock;
 use alloc::boxed::Box;
+use alloc::rc::Rc;
 use alloc::vec::Vec;
 use attestation::{Attestation, AttestationProvider, ClientAttestation, ServerAttestation};
 use communication::{CommunicationConfig, CommunicationModule};
@@ -26,6 +27,7 @@ use consensus;
 use consensus::{Raft, RaftLightReady, RaftReady, Store};
 use encryptor::Encryptor;
 use handshake::{HandshakeSession, HandshakeSessionProvider, Role};
+use metrics::MetricsRegistry;
 use model::{
     Actor, ActorCommand, ActorContext, ActorError, ActorEvent, ActorEventContext, CommandOutcome,
     EventOutcome,
@@ -44,11 +46,12 @@ use oak_proto_rust::oak::{
 use platform::{Host, PalError};
 use prost::bytes::Bytes;
 use raft::{
-    eraftpb::ConfChange as RaftConfigChange, eraftpb::ConfState as RaftConfigState,
-    eraftpb::Entry as RaftEntry, eraftpb::HardState as RaftHardState,
-    eraftpb::Message as RaftMessage, eraftpb::Snapshot as RaftSnapshot, Config as RaftConfig,
-    Error as RaftError, GetEntriesContext as RaftGetEntriesContext,
-    SnapshotStatus as RaftSnapshotStatus, Storage as RaftStorage,
+    eraftpb::ConfChange as RaftConfigChange, eraftpb::ConfChangeV2 as RaftConfigChangeV2,
+    eraftpb::ConfState as RaftConfigState, eraftpb::Entry as RaftEntry,
+    eraftpb::HardState as RaftHardState, eraftpb::Message as RaftMessage,
+    eraftpb::Snapshot as RaftSnapshot, Config as RaftConfig, Error as RaftError,
+    GetEntriesContext as RaftGetEntriesContext, SnapshotStatus as RaftSnapshotStatus,
+    Storage as RaftStorage,
 };
 use session::{OakClientSession, OakServerSession, OakSession};
 use slog::Logger;
@@ -56,8 +59,8 @@ use snapshot::{
     SnapshotError, SnapshotReceiver, SnapshotReceiverImpl, SnapshotSender, SnapshotSenderImpl,
 };
 use tcp_proto::runtime::endpoint::{
-    in_message, out_message, raft_config::SnapshotConfig, DeliverSnapshotRequest,
-    DeliverSnapshotResponse, OutMessage, SecureChannelHandshake,
+    in_message, out_message, raft_config::SnapshotConfig, AttestationPolicy,
+    DeliverSnapshotRequest, DeliverSnapshotResponse, OutMessage, SecureChannelHandshake,
 };
 
 mock! {
@@ -73,9 +76,19 @@ mock! {
 
         fn on_load_snapshot(&mut self, snapshot: Bytes) -> Result<(), ActorError>;
 
+        fn loads_snapshot_in_chunks(&self) -> bool;
+
+        fn on_load_snapshot_chunk(&mut self, chunk: Bytes) -> Result<(), ActorError>;
+
+        fn on_load_snapshot_complete(&mut self) -> Result<(), ActorError>;
+
         fn on_process_command(&mut self, command: Option<ActorCommand>) -> Result<CommandOutcome, ActorError>;
 
         fn on_apply_event(&mut self, context: ActorEventContext, event: ActorEvent) -> Result<EventOutcome, ActorError>;
+
+        fn on_commit_watermark(&mut self, committed_index: u64) -> Result<EventOutcome, ActorError>;
+
+        fn on_apply_config(&mut self, config: Bytes) -> Result<(), ActorError>;
     }
 }
 
@@ -89,9 +102,37 @@ mock! {
 
         fn instant(&self) -> u64;
 
+        fn wall_time(&self) -> Option<u64>;
+
         fn config(&self) -> Bytes;
 
         fn leader(&self) -> bool;
+
+        fn read_confirmed(&self) -> bool;
+
+        fn applied_index(&self) -> u64;
+
+        fn leader_commit_hint(&self) -> u64;
+
+        fn subscribe_commit_watermark(&self, index: u64);
+
+        fn complete_command(&self, correlation_id: u64, response: ActorCommand);
+
+        fn set_timer(&self, timer_id: u64, deadline: u64);
+
+        fn cancel_timer(&self, timer_id: u64);
+
+        fn report_state_size(&self, state_size: u64);
+
+        fn fill_random(&self, dest: &mut [u8]);
+
+        fn send_app_message(&self, destination: &str, message: Bytes);
+
+        fn snapshot_schema_version(&self) -> u32;
+
+        fn metrics(&self) -> Rc<MetricsRegistry>;
+
+        fn cluster_key(&self, context: &[u8]) -> Option<Vec<u8>>;
     }
 }
 
@@ -113,7 +154,7 @@ mock! {
     impl Store for Store {
         fn set_hard_state(&mut self, state: RaftHardState);
 
-        fn append_entries(&mut self, entries: &[RaftEntry]) -> Result<(), RaftError>;
+        fn append_entries(&mut self, entries: Vec<RaftEntry>) -> Result<(), RaftError>;
 
         fn apply_snapshot(&mut self, snapshot: RaftSnapshot) -> Result<(), RaftError>;
 
@@ -126,7 +167,11 @@ mock! {
             snapshot_data: Bytes,
         ) -> Result<(), RaftError>;
 
+        fn log_size(&self, high_index: u64) -> u64;
+
         fn latest_snapshot_size(&self) -> u64;
+
+        fn latest_snapshot(&self) -> RaftSnapshot;
     }
 }
 
@@ -188,10 +233,19 @@ mock! {
             config_change: RaftConfigChange,
         ) -> Result<(), RaftError>;
 
+        fn make_config_change_v2_proposal(
+            &mut self,
+            config_change: RaftConfigChangeV2,
+        ) -> Result<(), RaftError>;
+
+        fn make_read_index(&mut self, context: Bytes);
+
         fn make_tick(&mut self);
 
         fn apply_config_change(&mut self, config_change: &RaftConfigChange) -> Result<RaftConfigState, RaftError>;
 
+        fn apply_config_change_v2(&mut self, config_change: &RaftConfigChangeV2) -> Result<RaftConfigState, RaftError>;
+
         fn has_ready(&self) -> bool;
 
         fn get_ready(&mut self) -> RaftReady;
@@ -201,6 +255,16 @@ mock! {
         fn advance_apply(&mut self);
 
         fn report_snapshot(&mut self, replica_id: u64, status: RaftSnapshotStatus);
+
+        fn adjust_max_inflight_msgs(&mut self, replica_id: u64, max_inflight_msgs: usize);
+
+        fn replica_caught_up(&self, replica_id: u64) -> bool;
+
+        fn committed_index(&self) -> u64;
+
+        fn followers_progress(&self) -> Vec<consensus::FollowerProgress>;
+
+        fn transfer_leadership(&mut self, target_replica_id: u64);
     }
 }
 
@@ -209,17 +273,19 @@ mock! {
     }
 
     impl SnapshotReceiverImpl for SnapshotReceiver {
-        fn init(&mut self, logger: Logger, replica_id: u64);
-
-        fn set_instant(&mut self, instant: u64);
+        fn init(&mut self, logger: Logger, replica_id: u64, snapshot_config: &Option<SnapshotConfig>);
 
         fn reset(&mut self);
+
+        fn set_session_secret(&mut self, replica_id: u64, session_secret: Bytes);
     }
 
     impl SnapshotReceiver for SnapshotReceiver {
         fn process_request(&mut self, request: DeliverSnapshotRequest) -> DeliverSnapshotResponse;
 
         fn try_complete(&mut self) -> Option<Result<(u64, RaftSnapshot), SnapshotError>>;
+
+        fn transfer_progress(&self) -> Option<u64>;
     }
 }
 
@@ -230,9 +296,9 @@ mock! {
     impl SnapshotSenderImpl for SnapshotSender {
         fn init(&mut self, logger: Logger, replica_id: u64, snapshot_config: &Option<SnapshotConfig>);
 
-        fn set_instant(&mut self, instant: u64);
-
         fn reset(&mut self) -> Vec<(u64, RaftSnapshotStatus)>;
+
+        fn set_session_secret(&mut self, replica_id: u64, session_secret: Bytes);
     }
 
     impl SnapshotSender for SnapshotSender {
@@ -250,6 +316,8 @@ mock! {
         fn process_unexpected_request(&mut self, request: DeliverSnapshotRequest) -> DeliverSnapshotResponse;
 
         fn try_complete(&mut self) -> Option<(u64, RaftSnapshotStatus)>;
+
+        fn transfer_progress(&self) -> Option<u64>;
     }
 }
 
@@ -272,6 +340,16 @@ mock! {
         fn process_cluster_change(&mut self, new_replica_ids: &[u64]);
 
         fn make_tick(&mut self);
+
+        fn export_secret(&self, peer_replica_id: u64, context: &[u8]) -> Option<Vec<u8>>;
+
+        fn update_attestation_policy(&mut self, policy: &AttestationPolicy);
+
+        fn set_cluster_secret(&mut self, cluster_secret: Vec<u8>);
+
+        fn request_cluster_secret(&mut self);
+
+        fn take_cluster_secret(&mut self) -> Option<Vec<u8>>;
     }
 }
 
@@ -287,6 +365,8 @@ mock! {
             role: Role,
             logger: Logger,
         ) -> Box<dyn HandshakeSession>;
+
+        fn update_attestation_policy(&self, policy: &AttestationPolicy);
     }
 }
 
@@ -310,9 +390,11 @@ mock! {
     }
 
     impl AttestationProvider for AttestationProvider {
-        fn get_client_attestation(&self) -> Box<dyn ClientAttestation>;
+        fn get_client_attestation(&self, session_public_key: &[u8]) -> Box<dyn ClientAttestation>;
+
+        fn get_server_attestation(&self, session_public_key: &[u8]) -> Box<dyn ServerAttestation>;
 
-        fn get_server_attestation(&self) -> Box<dyn ServerAttestation>;
+        fn update_policy(&self, policy: &AttestationPolicy);
     }
 }
 
@@ -362,6 +444,8 @@ mock! {
     }
 
     impl OakHandshaker<HandshakeResponse, HandshakeRequest> for OakClientHandshaker {
+        fn public_key(&self) -> Vec<u8>;
+
         fn init(&mut self, peer_static_public_key: Vec<u8>);
 
         fn derive_session_keys(self: Box<Self>) -> Option<SessionKeys>;
@@ -379,6 +463,8 @@ mock! {
     }
 
     impl OakHandshaker<HandshakeRequest, HandshakeResponse> for OakServerHandshaker {
+        fn public_key(&self) -> Vec<u8>;
+
         fn init(&mut self, peer_static_public_key: Vec<u8>);
 
         fn derive_session_keys(self: Box<Self>) -> Option<SessionKeys>;
@@ -399,6 +485,10 @@ mock! {
         fn encrypt(&self, plaintext: &[u8]) -> anyhow::Result<Vec<u8>>;
 
         fn decrypt(&self, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>>;
+
+        fn export_secret(&self, context: &[u8]) -> anyhow::Result<Vec<u8>>;
+
+        fn rekey(&mut self) -> anyhow::Result<()>;
     }
 }
 
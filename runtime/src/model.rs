@@ -12,8 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::metrics::MetricsRegistry;
 use crate::StdError;
 use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 use core::fmt;
@@ -68,6 +71,14 @@ pub trait ActorContext {
     /// clock time or time since the trusted application start.
     fn instant(&self) -> u64;
 
+    /// Gets the wall clock time in milliseconds since the Unix epoch as read
+    /// from the trusted host, none if the host has no trusted wall clock or the
+    /// reading has been rejected for going backwards or drifting away from the
+    /// monotonic clock. The reading differs between replicas, hence it must be
+    /// replicated through the proposed events rather than read while applying
+    /// them.
+    fn wall_time(&self) -> Option<u64>;
+
     /// Gets serialized configuration that stays immutable through the lifetime of
     /// the trusted application.
     fn config(&self) -> Bytes;
@@ -75,6 +86,96 @@ pub trait ActorContext {
     /// Checks if the underlying consensus module is currently executing under leader
     /// role.
     fn leader(&self) -> bool;
+
+    /// Checks if the command being processed is a query confirmed through Raft
+    /// ReadIndex, in which case the actor state reflects all events committed
+    /// before the query has been received. If follower reads are enabled the
+    /// query may instead be confirmed by a follower, in which case the actor
+    /// state is only within the configured staleness bounds.
+    fn read_confirmed(&self) -> bool;
+
+    /// Gets the index of the last committed event applied to the actor state.
+    fn applied_index(&self) -> u64;
+
+    /// Gets the latest commit index reported by the leader. Together with the
+    /// applied index it bounds how stale the actor state on a follower is.
+    fn leader_commit_hint(&self) -> u64;
+
+    /// Subscribes to the commit watermark reaching the given index. Once the
+    /// entry at the index is committed i.e. durable across a quorum, the actor
+    /// is notified through `on_commit_watermark`. Subscriptions fire once and
+    /// only the lowest subscribed index is tracked, the actor must subscribe
+    /// again for the indices it is still waiting for.
+    fn subscribe_commit_watermark(&self, index: u64);
+
+    /// Completes the command previously left pending through the command
+    /// outcome with the given response. The response is sent out once the
+    /// current actor invocation returns, responses to the commands that are
+    /// not pending are dropped.
+    fn complete_command(&self, correlation_id: u64, response: ActorCommand);
+
+    /// Schedules the timer with the given id to fire once the clock of the
+    /// leader reaches the deadline, replacing the timer with the same id if
+    /// any. The leader fires timers through the replicated log and the actor
+    /// is notified through `on_timer` on every replica, hence timers survive
+    /// the leadership changes. Timers must only be scheduled while applying
+    /// events, handling timers or loading snapshots so that all replicas track
+    /// the same timers, the deadline must be derived from the replicated state
+    /// e.g. from the instant captured in the event. Timers are not captured in
+    /// snapshots, the actor must schedule them again when loading a snapshot.
+    fn set_timer(&self, timer_id: u64, deadline: u64);
+
+    /// Cancels the timer with the given id if it has not fired yet. Must only
+    /// be called under the same conditions as `set_timer`.
+    fn cancel_timer(&self, timer_id: u64);
+
+    /// Reports the current size (in bytes) of the actor state. Once the size
+    /// exceeds the configured limit new proposals of the actor are rejected
+    /// until the actor reports a smaller size.
+    fn report_state_size(&self, state_size: u64);
+
+    /// Fills the buffer with random bytes. If the replica is configured with
+    /// replicated randomness the bytes are derived from the seed replicated
+    /// along with the entry, hence all replicas applying the entry or handling
+    /// the timers it fires draw the same bytes, and the bytes drawn by the
+    /// leader processing a command are derived from the seed replicated along
+    /// with the first event the command proposes. Otherwise the bytes are
+    /// local to the replica.
+    ///
+    /// The bytes are visible to the host: the seeds are persisted in the log
+    /// by the untrusted launcher and are only encrypted if the cluster secret
+    /// is shared. The bytes must not be used as key material, use
+    /// [ActorContext::cluster_key] or the operating system instead.
+    fn fill_random(&self, dest: &mut [u8]);
+
+    /// Sends the message to the given destination e.g. another actor or
+    /// service, as understood by the untrusted launcher. The message is sent
+    /// out once the current actor invocation returns. Messages sent while
+    /// applying events are sent by every replica, the actor may check for
+    /// leadership to send a single copy.
+    fn send_app_message(&self, destination: &str, message: Bytes);
+
+    /// Gets the snapshot schema version the actor must save its snapshots in.
+    /// The version is agreed upon by the cluster during the rolling upgrade,
+    /// hence it may be lower than the version supported by the actor until all
+    /// replicas have been upgraded.
+    fn snapshot_schema_version(&self) -> u32;
+
+    /// Gets the metrics registry shared with the driver. The metrics recorded
+    /// by the actor are reported to the untrusted launcher along with the
+    /// metrics of the driver, the actor may prefix their names to tell them
+    /// apart.
+    fn metrics(&self) -> Rc<MetricsRegistry>;
+
+    /// Derives the key for the given context from the secret shared by the
+    /// replicas of the cluster, all replicas derive the same key for the same
+    /// context while neither the untrusted launcher nor the enclaves outside
+    /// of the cluster can. The actor protects the secrets it replicates with
+    /// the keys, e.g. the key material carried by the events. Returns None
+    /// unless the replica is configured to share the cluster secret, see
+    /// `RaftConfig.share_cluster_secret`. Events and snapshots are passed to
+    /// the actor only once the replica holds the secret.
+    fn cluster_key(&self, context: &[u8]) -> Option<Vec<u8>>;
 }
 
 /// Represents an application level command sent to or from an actor. Command is split
@@ -91,6 +192,15 @@ pub struct ActorCommand {
 
     /// Serialized and encrypted payload of the application command.
     pub payload: Bytes,
+
+    /// Route of the actor that handles or produced the command if several
+    /// actors share the Raft group, empty for the single actor.
+    pub route: String,
+
+    /// Status code (as defined by micro_rpc::StatusCode) the command has been
+    /// rejected with on behalf of the actor, zero for the commands produced by
+    /// the actor.
+    pub status_code: i32,
 }
 
 impl ActorCommand {
@@ -100,6 +210,8 @@ impl ActorCommand {
             correlation_id,
             header: header.encode_to_vec().into(),
             payload: Bytes::new(),
+            route: String::new(),
+            status_code: 0,
         }
     }
 
@@ -112,6 +224,8 @@ impl ActorCommand {
             correlation_id,
             header: header.encode_to_vec().into(),
             payload,
+            route: String::new(),
+            status_code: 0,
         }
     }
 }
@@ -154,14 +268,24 @@ pub struct ActorEventContext {
 }
 
 /// Represents an outcome of application command processing, which may result
-/// in a number of application commands requested to be sent out and an event
-/// requested to be replicated.
+/// in a number of application commands requested to be sent out and events
+/// requested to be replicated, or in a query requested to be processed again
+/// once it can be answered without appending to the replicated log, or in the
+/// response deferred until the actor completes the command.
 #[derive(Default, PartialEq, Debug, Clone)]
 pub struct CommandOutcome {
     /// Application messages that are requested to be sent out.
     pub commands: Vec<ActorCommand>,
-    /// Event that is requested to be replicated.
-    pub event: Option<ActorEvent>,
+    /// Events that are requested to be replicated. Events are appended to the
+    /// replicated log atomically as a single entry, i.e. either all of them are
+    /// committed or none of them, and are applied in the given order.
+    pub events: Vec<ActorEvent>,
+    /// Query command that is requested to be processed again once Raft confirms
+    /// through ReadIndex that the actor state is up to date.
+    pub read: Option<ActorCommand>,
+    /// Correlation id of the command whose response is deferred until the
+    /// actor completes it through the context.
+    pub pending: Option<u64>,
 }
 
 impl CommandOutcome {
@@ -174,7 +298,9 @@ impl CommandOutcome {
     pub fn with_command(command: ActorCommand) -> CommandOutcome {
         CommandOutcome {
             commands: vec![command],
-            event: None,
+            events: vec![],
+            read: None,
+            pending: None,
         }
     }
 
@@ -182,7 +308,9 @@ impl CommandOutcome {
     pub fn with_commands(commands: Vec<ActorCommand>) -> CommandOutcome {
         CommandOutcome {
             commands,
-            event: None,
+            events: vec![],
+            read: None,
+            pending: None,
         }
     }
 
@@ -190,7 +318,19 @@ impl CommandOutcome {
     pub fn with_event(event: ActorEvent) -> CommandOutcome {
         CommandOutcome {
             commands: vec![],
-            event: Some(event),
+            events: vec![event],
+            read: None,
+            pending: None,
+        }
+    }
+
+    /// Creates an outcome with multiple events to be replicated atomically.
+    pub fn with_events(events: Vec<ActorEvent>) -> CommandOutcome {
+        CommandOutcome {
+            commands: vec![],
+            events,
+            read: None,
+            pending: None,
         }
     }
 
@@ -198,7 +338,32 @@ impl CommandOutcome {
     pub fn with_command_and_event(command: ActorCommand, event: ActorEvent) -> CommandOutcome {
         CommandOutcome {
             commands: vec![command],
-            event: Some(event),
+            events: vec![event],
+            read: None,
+            pending: None,
+        }
+    }
+
+    /// Creates an outcome with a query command to be processed again once its
+    /// results are guaranteed to be linearizable.
+    pub fn with_read(command: ActorCommand) -> CommandOutcome {
+        CommandOutcome {
+            commands: vec![],
+            events: vec![],
+            read: Some(command),
+            pending: None,
+        }
+    }
+
+    /// Creates an outcome with the response to the command deferred until the
+    /// actor completes the command with the given correlation id, e.g. once
+    /// the asynchronous work the response depends on is done.
+    pub fn with_pending(correlation_id: u64) -> CommandOutcome {
+        CommandOutcome {
+            commands: vec![],
+            events: vec![],
+            read: None,
+            pending: Some(correlation_id),
         }
     }
 }
@@ -230,6 +395,14 @@ impl EventOutcome {
     }
 }
 
+/// Receives the actor state snapshot piece by piece, so that the actor does not
+/// need to hold the whole serialized state at once.
+pub trait SnapshotWriter {
+    /// Appends the next piece of the snapshot. If error is returned the snapshot
+    /// creation must be aborted.
+    fn write(&mut self, chunk: &[u8]) -> Result<(), ActorError>;
+}
+
 /// Represents a stateful actor backed by replicated state machine.
 pub trait Actor {
     /// Handles actor initialization. If error is returned the actor is considered
@@ -244,10 +417,71 @@ pub trait Actor {
     /// is considered is unknown state and is destroyed.
     fn on_save_snapshot(&mut self) -> Result<Bytes, ActorError>;
 
+    /// Handles creation of the actor state snapshot by writing it out piece by
+    /// piece, so that the serialized state is not held twice in memory. By default
+    /// writes out the snapshot created by `on_save_snapshot`. If error is returned
+    /// the actor is considered is unknown state and is destroyed.
+    fn on_save_snapshot_chunked(
+        &mut self,
+        writer: &mut dyn SnapshotWriter,
+    ) -> Result<(), ActorError> {
+        let snapshot = self.on_save_snapshot()?;
+        writer.write(&snapshot)
+    }
+
     /// Handles restoration of the actor state from snapshot. If error is returned the actor
     /// is considered is unknown state and is destroyed.
     fn on_load_snapshot(&mut self, snapshot: Bytes) -> Result<(), ActorError>;
 
+    /// Checks if the actor restores its state from the snapshot piece by piece
+    /// through `on_load_snapshot_chunk` and `on_load_snapshot_complete` instead
+    /// of `on_load_snapshot`.
+    fn loads_snapshot_in_chunks(&self) -> bool {
+        false
+    }
+
+    /// Handles the next piece of the snapshot being restored. Pieces are passed
+    /// in order and split the snapshot at arbitrary boundaries. If error is
+    /// returned the actor is considered is unknown state and is destroyed.
+    fn on_load_snapshot_chunk(&mut self, _chunk: Bytes) -> Result<(), ActorError> {
+        Err(ActorError::SnapshotLoading)
+    }
+
+    /// Handles completion of the snapshot restoration once all pieces have been
+    /// passed to the actor. If error is returned the actor is considered is
+    /// unknown state and is destroyed.
+    fn on_load_snapshot_complete(&mut self) -> Result<(), ActorError> {
+        Err(ActorError::SnapshotLoading)
+    }
+
+    /// Gets the highest snapshot schema version the actor supports. The actor
+    /// must be able to save snapshots in any version up to this one, as the
+    /// cluster moves to the newer version only once all replicas support it.
+    fn snapshot_schema_version(&self) -> u32 {
+        0
+    }
+
+    /// Handles migration of the snapshot saved in the older schema version to
+    /// the version supported by the actor, before the snapshot is loaded. If
+    /// error is returned the actor is considered is unknown state and is
+    /// destroyed.
+    fn on_migrate_snapshot(
+        &mut self,
+        _old_version: u32,
+        _snapshot: Bytes,
+    ) -> Result<Bytes, ActorError> {
+        Err(ActorError::SnapshotLoading)
+    }
+
+    /// Handles the actor configuration update replicated through the log, hence
+    /// all replicas apply the same updates at the same index. Updates are not
+    /// captured in snapshots by the runtime, the actor must capture the applied
+    /// configuration in its own snapshot. If error is returned the update is
+    /// refused and the actor must leave its configuration unchanged.
+    fn on_apply_config(&mut self, _config: Bytes) -> Result<(), ActorError> {
+        Err(ActorError::ConfigLoading)
+    }
+
     /// Handles processing of a command by the actor. If not none the command represents
     /// an intent of a consumer (e.g. request to update actor state). If none it
     /// represents time advancement or tick. The command or tick processing logic may
@@ -261,10 +495,239 @@ pub trait Actor {
 
     /// Handles committed events by applying them to the actor state. Event represents
     /// a state transition of the actor and may result in messages being sent to the
-    /// consumer (e.g. response to the command that generated this event).
+    /// consumer (e.g. response to the command that generated this event). If error
+    /// is returned the runtime recovers according to the configured actor error
+    /// policy, which by default halts the replica.
     fn on_apply_event(
         &mut self,
         context: ActorEventContext,
         event: ActorEvent,
     ) -> Result<EventOutcome, ActorError>;
+
+    /// Handles advancement of the commit watermark past the index the actor has
+    /// subscribed to through the context. Allows to release responses deferred
+    /// until the corresponding events are durable across a quorum.
+    fn on_commit_watermark(&mut self, _committed_index: u64) -> Result<EventOutcome, ActorError> {
+        Ok(EventOutcome::with_none())
+    }
+
+    /// Handles the timer scheduled through the context firing. Fired timers are
+    /// applied as committed entries, hence all replicas handle the same timer
+    /// at the same index.
+    fn on_timer(
+        &mut self,
+        _context: ActorEventContext,
+        _timer_id: u64,
+    ) -> Result<EventOutcome, ActorError> {
+        Ok(EventOutcome::with_none())
+    }
+}
+
+/// Handler the interceptor passes the command to, either the next interceptor
+/// in the chain or the actor.
+pub type CommandHandler<'a> =
+    dyn FnMut(Option<ActorCommand>) -> Result<CommandOutcome, ActorError> + 'a;
+
+/// Handler the interceptor passes the event to, either the next interceptor
+/// in the chain or the actor.
+pub type EventHandler<'a> =
+    dyn FnMut(ActorEventContext, ActorEvent) -> Result<EventOutcome, ActorError> + 'a;
+
+/// Wraps command processing and event application of the actor to implement
+/// cross-cutting concerns such as logging, metrics, payload validation or
+/// encryption once for all actors. Interceptor may alter the command or event
+/// before passing it on, alter the outcome or handle the command itself
+/// without passing it on. Interceptors intercepting events must behave
+/// deterministically as events are applied on every replica.
+pub trait ActorInterceptor {
+    /// Intercepts the command on its way to `Actor::on_process_command`.
+    fn intercept_command(
+        &mut self,
+        command: Option<ActorCommand>,
+        next: &mut CommandHandler,
+    ) -> Result<CommandOutcome, ActorError> {
+        next(command)
+    }
+
+    /// Intercepts the event on its way to `Actor::on_apply_event`.
+    fn intercept_event(
+        &mut self,
+        context: ActorEventContext,
+        event: ActorEvent,
+        next: &mut EventHandler,
+    ) -> Result<EventOutcome, ActorError> {
+        next(context, event)
+    }
+}
+
+/// Chain of interceptors the commands and events pass through in the order
+/// the interceptors have been added before reaching the actor.
+#[derive(Default)]
+pub struct InterceptorChain {
+    interceptors: Vec<Box<dyn ActorInterceptor>>,
+}
+
+impl InterceptorChain {
+    pub fn new() -> InterceptorChain {
+        InterceptorChain {
+            interceptors: Vec::new(),
+        }
+    }
+
+    /// Adds the interceptor to the end of the chain, closest to the actor.
+    pub fn add(&mut self, interceptor: Box<dyn ActorInterceptor>) {
+        self.interceptors.push(interceptor);
+    }
+
+    /// Passes the command through the chain to the actor.
+    pub fn process_command(
+        &mut self,
+        actor: &mut dyn Actor,
+        command: Option<ActorCommand>,
+    ) -> Result<CommandOutcome, ActorError> {
+        process_command_through(&mut self.interceptors, actor, command)
+    }
+
+    /// Passes the event through the chain to the actor.
+    pub fn apply_event(
+        &mut self,
+        actor: &mut dyn Actor,
+        context: ActorEventContext,
+        event: ActorEvent,
+    ) -> Result<EventOutcome, ActorError> {
+        apply_event_through(&mut self.interceptors, actor, context, event)
+    }
+}
+
+fn process_command_through(
+    interceptors: &mut [Box<dyn ActorInterceptor>],
+    actor: &mut dyn Actor,
+    command: Option<ActorCommand>,
+) -> Result<CommandOutcome, ActorError> {
+    match interceptors.split_first_mut() {
+        None => actor.on_process_command(command),
+        Some((interceptor, rest)) => interceptor.intercept_command(command, &mut |command| {
+            process_command_through(rest, actor, command)
+        }),
+    }
+}
+
+fn apply_event_through(
+    interceptors: &mut [Box<dyn ActorInterceptor>],
+    actor: &mut dyn Actor,
+    context: ActorEventContext,
+    event: ActorEvent,
+) -> Result<EventOutcome, ActorError> {
+    match interceptors.split_first_mut() {
+        None => actor.on_apply_event(context, event),
+        Some((interceptor, rest)) => {
+            interceptor.intercept_event(context, event, &mut |context, event| {
+                apply_event_through(rest, actor, context, event)
+            })
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::mock::MockActor;
+    use crate::model::*;
+    use alloc::rc::Rc;
+    use alloc::vec;
+    use core::cell::RefCell;
+    use mockall::predicate::eq;
+
+    // Appends its tag to the command header and records the order of calls.
+    struct TaggingInterceptor {
+        tag: u8,
+        calls: Rc<RefCell<Vec<u8>>>,
+    }
+
+    impl ActorInterceptor for TaggingInterceptor {
+        fn intercept_command(
+            &mut self,
+            command: Option<ActorCommand>,
+            next: &mut CommandHandler,
+        ) -> Result<CommandOutcome, ActorError> {
+            self.calls.borrow_mut().push(self.tag);
+            next(command.map(|mut command| {
+                let mut header = command.header.to_vec();
+                header.push(self.tag);
+                command.header = header.into();
+                command
+            }))
+        }
+    }
+
+    // Handles all events without passing them on.
+    struct DroppingInterceptor {}
+
+    impl ActorInterceptor for DroppingInterceptor {
+        fn intercept_event(
+            &mut self,
+            _context: ActorEventContext,
+            _event: ActorEvent,
+            _next: &mut EventHandler,
+        ) -> Result<EventOutcome, ActorError> {
+            Ok(EventOutcome::with_none())
+        }
+    }
+
+    fn create_command(header: Vec<u8>) -> ActorCommand {
+        ActorCommand {
+            correlation_id: 1,
+            header: header.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_interceptor_chain() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut chain = InterceptorChain::new();
+        chain.add(Box::new(TaggingInterceptor {
+            tag: 1,
+            calls: calls.clone(),
+        }));
+        chain.add(Box::new(TaggingInterceptor {
+            tag: 2,
+            calls: calls.clone(),
+        }));
+
+        let mut actor = MockActor::new();
+        actor
+            .expect_on_process_command()
+            .with(eq(Some(create_command(vec![0, 1, 2]))))
+            .return_once(|_| Ok(CommandOutcome::with_none()));
+        actor
+            .expect_on_apply_event()
+            .return_once(|_, _| Ok(EventOutcome::with_command(create_command(vec![3]))));
+
+        // Command passes through the interceptors in the order they were added.
+        assert_eq!(
+            chain.process_command(&mut actor, Some(create_command(vec![0]))),
+            Ok(CommandOutcome::with_none())
+        );
+        assert_eq!(*calls.borrow(), vec![1, 2]);
+
+        let context = ActorEventContext {
+            index: 1,
+            owned: false,
+        };
+        assert_eq!(
+            chain
+                .apply_event(&mut actor, context.clone(), ActorEvent::default())
+                .unwrap()
+                .commands,
+            vec![create_command(vec![3])]
+        );
+
+        // Event handled by the interceptor does not reach the actor.
+        chain.add(Box::new(DroppingInterceptor {}));
+        assert!(chain
+            .apply_event(&mut actor, context, ActorEvent::default())
+            .unwrap()
+            .commands
+            .is_empty());
+    }
 }
@@ -0,0 +1,420 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::platform::{Application, Host, PalError};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use tcp_proto::runtime::endpoint::*;
+
+/// Represents a trusted host of several independent Raft groups. Messages
+/// sent to the host are tagged with the group they originate from.
+pub trait GroupHost {
+    /// Sends messages through the communication channel that connects the trusted
+    /// application to the untrusted launcher.
+    fn send_group_messages(&mut self, messages: Vec<GroupOutMessage>);
+
+    /// Gets serialized public key used for signing by the trusted application.
+    fn public_signing_key(&self) -> Vec<u8>;
+
+    /// Gets the trusted wall clock time shared by all groups, see [Host::wall_time].
+    fn wall_time(&self) -> Option<u64> {
+        None
+    }
+
+    /// Gets the signed wall clock time shared by all groups, see
+    /// [Host::signed_wall_time].
+    fn signed_wall_time(&self) -> Option<SignedTimestamp> {
+        None
+    }
+}
+
+// Presents the group host as a host of a single group to the group driver.
+struct GroupScopedHost<'a, H: GroupHost> {
+    host: &'a mut H,
+    group_id: u64,
+}
+
+impl<'a, H: GroupHost> Host for GroupScopedHost<'a, H> {
+    fn send_messages(&mut self, messages: Vec<OutMessage>) {
+        if messages.is_empty() {
+            return;
+        }
+
+        self.host.send_group_messages(
+            messages
+                .into_iter()
+                .map(|message| GroupOutMessage {
+                    group_id: self.group_id,
+                    message: Some(message),
+                })
+                .collect(),
+        );
+    }
+
+    fn public_signing_key(&self) -> Vec<u8> {
+        self.host.public_signing_key()
+    }
+
+    fn wall_time(&self) -> Option<u64> {
+        self.host.wall_time()
+    }
+
+    fn signed_wall_time(&self) -> Option<SignedTimestamp> {
+        self.host.signed_wall_time()
+    }
+}
+
+/// Hosts several independent Raft groups within a single trusted application.
+/// Each group is served by its own driver with its own actor instance, created
+/// when the group receives StartReplicaRequest and dropped once it receives
+/// StopReplicaRequest. Incoming messages are routed by group id, every other
+/// group is advanced in round robin order so that ticks and ready state are
+/// processed for all groups.
+pub struct MultiRaftDriver<D: Application> {
+    factory: Box<dyn FnMut(u64) -> D>,
+    groups: BTreeMap<u64, D>,
+    // Maximum number of groups advanced without a message in one slice, zero
+    // if all groups must be advanced.
+    max_idle_groups: usize,
+    // Group id to continue advancing idle groups from.
+    cursor: u64,
+}
+
+impl<D: Application> MultiRaftDriver<D> {
+    /// Creates multi group driver that uses the factory to create the driver for
+    /// the given group id.
+    pub fn new(factory: Box<dyn FnMut(u64) -> D>) -> Self {
+        MultiRaftDriver {
+            factory,
+            groups: BTreeMap::new(),
+            max_idle_groups: 0,
+            cursor: 0,
+        }
+    }
+
+    /// Limits the number of groups advanced without a message in one slice to
+    /// bound the amount of work done in a single slice.
+    pub fn with_max_idle_groups(mut self, max_idle_groups: usize) -> Self {
+        self.max_idle_groups = max_idle_groups;
+        self
+    }
+
+    /// Gets ids of the hosted groups.
+    pub fn group_ids(&self) -> Vec<u64> {
+        self.groups.keys().copied().collect()
+    }
+
+    /// Receives message addressed to one of the groups and advances other
+    /// groups. Messages addressed to unknown groups are ignored unless they
+    /// start a replica.
+    ///
+    /// # Returns
+    ///
+    /// Error if any of the groups encountered an unrecoverable error, a success
+    /// otherwise.
+    pub fn receive_message(
+        &mut self,
+        host: &mut impl GroupHost,
+        instant: u64,
+        opt_message: Option<GroupInMessage>,
+    ) -> Result<(), PalError> {
+        let mut advanced_group_id = None;
+        if let Some(GroupInMessage {
+            group_id,
+            message: Some(message),
+        }) = opt_message
+        {
+            advanced_group_id = Some(group_id);
+            self.deliver_message(host, instant, group_id, message)?;
+        }
+
+        self.advance_idle_groups(host, instant, advanced_group_id)
+    }
+
+    fn deliver_message(
+        &mut self,
+        host: &mut impl GroupHost,
+        instant: u64,
+        group_id: u64,
+        message: InMessage,
+    ) -> Result<(), PalError> {
+        let stop = match message.msg {
+            Some(in_message::Msg::StartReplica(_)) => {
+                if !self.groups.contains_key(&group_id) {
+                    self.groups.insert(group_id, (self.factory)(group_id));
+                }
+                false
+            }
+            Some(in_message::Msg::StopReplica(_)) => true,
+            _ => false,
+        };
+
+        let Some(driver) = self.groups.get_mut(&group_id) else {
+            return Ok(());
+        };
+
+        driver.receive_message(
+            &mut GroupScopedHost { host, group_id },
+            instant,
+            Some(message),
+        )?;
+
+        if stop {
+            self.groups.remove(&group_id);
+        }
+
+        Ok(())
+    }
+
+    fn advance_idle_groups(
+        &mut self,
+        host: &mut impl GroupHost,
+        instant: u64,
+        advanced_group_id: Option<u64>,
+    ) -> Result<(), PalError> {
+        let idle_group_ids: Vec<u64> = self
+            .groups
+            .range(self.cursor..)
+            .chain(self.groups.range(..self.cursor))
+            .map(|(group_id, _)| *group_id)
+            .filter(|group_id| Some(*group_id) != advanced_group_id)
+            .collect();
+
+        let budget = if self.max_idle_groups == 0 {
+            idle_group_ids.len()
+        } else {
+            self.max_idle_groups
+        };
+
+        for group_id in idle_group_ids.into_iter().take(budget) {
+            // Group with the highest possible id ends the round, the next round
+            // starts from the lowest id.
+            self.cursor = group_id.checked_add(1).unwrap_or(0);
+            let driver = self.groups.get_mut(&group_id).unwrap();
+            driver.receive_message(&mut GroupScopedHost { host, group_id }, instant, None)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::multi_raft::*;
+    use alloc::rc::Rc;
+    use alloc::vec;
+    use core::cell::RefCell;
+
+    // Records received messages and responds to every message it receives.
+    struct FakeApplication {
+        group_id: u64,
+        received: Rc<RefCell<Vec<(u64, bool)>>>,
+    }
+
+    impl Application for FakeApplication {
+        fn receive_message(
+            &mut self,
+            host: &mut impl Host,
+            _instant: u64,
+            opt_message: Option<InMessage>,
+        ) -> Result<(), PalError> {
+            self.received
+                .borrow_mut()
+                .push((self.group_id, opt_message.is_some()));
+            if opt_message.is_some() {
+                host.send_messages(vec![OutMessage {
+                    msg: Some(out_message::Msg::StopReplica(StopReplicaResponse::default())),
+                }]);
+            }
+            Ok(())
+        }
+    }
+
+    // Records the wall clock time observed by every message it receives.
+    struct TimeRecordingApplication {
+        observed: Rc<RefCell<Vec<(Option<u64>, Option<SignedTimestamp>)>>>,
+    }
+
+    impl Application for TimeRecordingApplication {
+        fn receive_message(
+            &mut self,
+            host: &mut impl Host,
+            _instant: u64,
+            _opt_message: Option<InMessage>,
+        ) -> Result<(), PalError> {
+            self.observed
+                .borrow_mut()
+                .push((host.wall_time(), host.signed_wall_time()));
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct FakeGroupHost {
+        messages: Vec<GroupOutMessage>,
+        wall_time: Option<u64>,
+        signed_wall_time: Option<SignedTimestamp>,
+    }
+
+    impl GroupHost for FakeGroupHost {
+        fn send_group_messages(&mut self, mut messages: Vec<GroupOutMessage>) {
+            self.messages.append(&mut messages);
+        }
+
+        fn public_signing_key(&self) -> Vec<u8> {
+            Vec::new()
+        }
+
+        fn wall_time(&self) -> Option<u64> {
+            self.wall_time
+        }
+
+        fn signed_wall_time(&self) -> Option<SignedTimestamp> {
+            self.signed_wall_time.clone()
+        }
+    }
+
+    fn create_group_message(group_id: u64, msg: in_message::Msg) -> Option<GroupInMessage> {
+        Some(GroupInMessage {
+            group_id,
+            message: Some(InMessage { msg: Some(msg) }),
+        })
+    }
+
+    fn create_start_message(group_id: u64) -> Option<GroupInMessage> {
+        create_group_message(
+            group_id,
+            in_message::Msg::StartReplica(StartReplicaRequest::default()),
+        )
+    }
+
+    #[test]
+    fn test_multi_raft_driver() {
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let factory_received = received.clone();
+        let mut driver = MultiRaftDriver::new(Box::new(move |group_id| FakeApplication {
+            group_id,
+            received: factory_received.clone(),
+        }))
+        .with_max_idle_groups(1);
+        let mut host = FakeGroupHost::default();
+
+        // Messages for unknown groups are ignored.
+        driver
+            .receive_message(
+                &mut host,
+                0,
+                create_group_message(
+                    1,
+                    in_message::Msg::StopReplica(StopReplicaRequest::default()),
+                ),
+            )
+            .unwrap();
+        assert!(driver.group_ids().is_empty());
+
+        for group_id in [1, 2, 3] {
+            driver
+                .receive_message(&mut host, 0, create_start_message(group_id))
+                .unwrap();
+        }
+        assert_eq!(driver.group_ids(), vec![1, 2, 3]);
+        assert_eq!(
+            host.messages
+                .iter()
+                .map(|message| message.group_id)
+                .collect::<Vec<u64>>(),
+            vec![1, 2, 3]
+        );
+
+        // Idle groups are advanced in round robin order within the budget.
+        received.borrow_mut().clear();
+        driver.receive_message(&mut host, 1, None).unwrap();
+        driver.receive_message(&mut host, 2, None).unwrap();
+        driver.receive_message(&mut host, 3, None).unwrap();
+        assert_eq!(*received.borrow(), vec![(3, false), (1, false), (2, false)]);
+
+        // Stopped group is dropped.
+        driver
+            .receive_message(
+                &mut host,
+                4,
+                create_group_message(
+                    2,
+                    in_message::Msg::StopReplica(StopReplicaRequest::default()),
+                ),
+            )
+            .unwrap();
+        assert_eq!(driver.group_ids(), vec![1, 3]);
+    }
+
+    #[test]
+    fn test_multi_raft_driver_highest_group_id() {
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let factory_received = received.clone();
+        let mut driver = MultiRaftDriver::new(Box::new(move |group_id| FakeApplication {
+            group_id,
+            received: factory_received.clone(),
+        }))
+        .with_max_idle_groups(1);
+        let mut host = FakeGroupHost::default();
+
+        for group_id in [1, u64::MAX] {
+            driver
+                .receive_message(&mut host, 0, create_start_message(group_id))
+                .unwrap();
+        }
+
+        // Cursor wraps around past the highest group id.
+        received.borrow_mut().clear();
+        driver.receive_message(&mut host, 1, None).unwrap();
+        driver.receive_message(&mut host, 2, None).unwrap();
+        driver.receive_message(&mut host, 3, None).unwrap();
+        assert_eq!(
+            *received.borrow(),
+            vec![(u64::MAX, false), (1, false), (u64::MAX, false)]
+        );
+    }
+
+    #[test]
+    fn test_multi_raft_driver_wall_time() {
+        let observed = Rc::new(RefCell::new(Vec::new()));
+        let factory_observed = observed.clone();
+        let mut driver = MultiRaftDriver::new(Box::new(move |_| TimeRecordingApplication {
+            observed: factory_observed.clone(),
+        }));
+        let signed_wall_time = SignedTimestamp {
+            wall_time: 1000,
+            radius: 10,
+            signature: vec![1, 2, 3],
+            nonce: vec![4, 5],
+        };
+        let mut host = FakeGroupHost {
+            wall_time: Some(2000),
+            signed_wall_time: Some(signed_wall_time.clone()),
+            ..Default::default()
+        };
+
+        // Both the addressed and the idle groups observe the time of the host.
+        for group_id in [1, 2] {
+            driver
+                .receive_message(&mut host, 0, create_start_message(group_id))
+                .unwrap();
+        }
+        assert_eq!(
+            *observed.borrow(),
+            vec![(Some(2000), Some(signed_wall_time)); 3]
+        );
+    }
+}
@@ -13,20 +13,30 @@
 // limitations under the License.
 
 use alloc::boxed::Box;
-use alloc::{vec, vec::Vec};
+use alloc::vec;
+use alloc::vec::Vec;
+use hkdf::Hkdf;
 use oak_proto_rust::oak::{
     crypto::v1::SessionKeys,
     session::v1::{HandshakeRequest, HandshakeResponse},
 };
-use oak_session::config::HandshakerConfig;
-use oak_session::handshake::{ClientHandshaker, HandshakeType, ServerHandshaker};
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
 
 pub trait OakClientHandshaker = OakHandshaker<HandshakeResponse, HandshakeRequest>;
 pub trait OakServerHandshaker = OakHandshaker<HandshakeRequest, HandshakeResponse>;
 
+// Context the session keys are derived for, followed by the public keys of the
+// client and the server in the key derivation info.
+const SESSION_KEYS_CONTEXT: &[u8] = b"TCP session keys";
+
+// Size of the session key protecting the messages sent in one direction.
+const SESSION_KEY_SIZE: usize = 32;
+
 // Factory class for creating instances of `OakClientHandshaker` and `OakServerHandshaker`
-// traits which are used for initiating crypto key exchange using Noise protocol implementation
-// provided by the Oak restricted kernel SDK.
+// traits which are used for agreeing on the session keys between 2 raft replicas once
+// they have attested to one another.
 pub trait OakHandshakerFactory {
     // Returns OakClientHandshaker, responsible for initiating crypto key exchange
     // between 2 raft replicas using Noise protocol.
@@ -41,6 +51,11 @@ pub trait OakHandshakerFactory {
 // response. `SessionKeys` can be retrieved once key exchange has successfully
 // completed after an initial exchange of messages.
 pub trait OakHandshaker<I, O> {
+    // Returns the public key of the key pair generated for this session. The
+    // attestation evidence binds the public key, such that only the attested
+    // peer holding the private key can agree on the session keys.
+    fn public_key(&self) -> Vec<u8>;
+    // Initializes the handshaker with the public key the peer evidence binds.
     fn init(&mut self, peer_static_public_key: Vec<u8>);
     fn put_incoming_message(&mut self, incoming_message: &I) -> anyhow::Result<Option<()>>;
     fn get_outgoing_message(&mut self) -> anyhow::Result<Option<O>>;
@@ -60,29 +75,79 @@ impl OakHandshakerFactory for DefaultOakHandshakerFactory {
     }
 }
 
-// Default implementation of `OakClientHandshaker`.
+// Key pair generated inside the enclave for a single session. The session keys
+// are derived with HKDF-SHA256 from the X25519 shared secret of the key pairs of
+// both sides, one key per direction.
+struct SessionKeyAgreement {
+    private_key: StaticSecret,
+    peer_public_key: Option<Vec<u8>>,
+}
+
+impl SessionKeyAgreement {
+    fn generate() -> Self {
+        Self {
+            private_key: StaticSecret::random_from_rng(OsRng),
+            peer_public_key: None,
+        }
+    }
+
+    fn public_key(&self) -> Vec<u8> {
+        PublicKey::from(&self.private_key).as_bytes().to_vec()
+    }
+
+    // Returns the keys protecting the messages sent by the client and by the
+    // server respectively, none if the peer public key is missing or invalid.
+    fn derive_keys(&self, client_public_key: &[u8], server_public_key: &[u8]) -> Option<Vec<u8>> {
+        let peer_public_key: [u8; 32] =
+            self.peer_public_key.as_ref()?.as_slice().try_into().ok()?;
+        let shared_secret = self
+            .private_key
+            .diffie_hellman(&PublicKey::from(peer_public_key));
+        // Rejects the low order points that would make the shared secret
+        // independent of the private key.
+        if !shared_secret.was_contributory() {
+            return None;
+        }
+
+        let mut info = SESSION_KEYS_CONTEXT.to_vec();
+        info.extend_from_slice(client_public_key);
+        info.extend_from_slice(server_public_key);
+        let mut keys = vec![0; 2 * SESSION_KEY_SIZE];
+        Hkdf::<Sha256>::new(None, shared_secret.as_bytes())
+            .expand(&info, &mut keys)
+            .ok()?;
+        Some(keys)
+    }
+}
+
+// Default implementation of `OakClientHandshaker`. The session keys are agreed on
+// through the public keys exchanged in the attestation evidence, hence the
+// handshake messages carry no data.
 pub struct DefaultOakClientHandshaker {
-    inner: Option<ClientHandshaker>,
+    key_agreement: SessionKeyAgreement,
 }
 
 impl DefaultOakClientHandshaker {
     pub fn new() -> Self {
-        Self { inner: None }
+        Self {
+            key_agreement: SessionKeyAgreement::generate(),
+        }
+    }
+}
+
+impl Default for DefaultOakClientHandshaker {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl OakHandshaker<HandshakeResponse, HandshakeRequest> for DefaultOakClientHandshaker {
-    // TODO: Delegate to `inner` once the implementation is complete on Oak side.
+    fn public_key(&self) -> Vec<u8> {
+        self.key_agreement.public_key()
+    }
+
     fn init(&mut self, peer_static_public_key: Vec<u8>) {
-        let config = HandshakerConfig {
-            // TODO: review the parameters below.
-            handshake_type: HandshakeType::NoiseNN,
-            self_static_private_key: None,
-            peer_static_public_key: Some(peer_static_public_key),
-            peer_attestation_binding_public_key: None,
-        };
-        // TODO: handle error to create ClientHandshaker instead of unwrap().
-        self.inner = Some(ClientHandshaker::create(&config).unwrap());
+        self.key_agreement.peer_public_key = Some(peer_static_public_key);
     }
 
     fn get_outgoing_message(&mut self) -> anyhow::Result<Option<HandshakeRequest>> {
@@ -100,34 +165,45 @@ impl OakHandshaker<HandshakeResponse, HandshakeRequest> for DefaultOakClientHand
     }
 
     fn derive_session_keys(self: Box<Self>) -> Option<SessionKeys> {
+        let public_key = self.public_key();
+        let peer_public_key = self.key_agreement.peer_public_key.clone()?;
+        let mut keys = self
+            .key_agreement
+            .derive_keys(&public_key, &peer_public_key)?;
+        let response_key = keys.split_off(SESSION_KEY_SIZE);
         Some(SessionKeys {
-            request_key: vec![],
-            response_key: vec![],
+            request_key: keys,
+            response_key,
         })
     }
 }
 
-// Default implementation of `OakServerHandshaker`.
-pub struct DefaultOakServerHandshaker<'a> {
-    inner: Option<ServerHandshaker<'a>>,
+// Default implementation of `OakServerHandshaker`, see `DefaultOakClientHandshaker`.
+pub struct DefaultOakServerHandshaker {
+    key_agreement: SessionKeyAgreement,
 }
 
-impl<'a> DefaultOakServerHandshaker<'a> {
+impl DefaultOakServerHandshaker {
     pub fn new() -> Self {
-        Self { inner: None }
+        Self {
+            key_agreement: SessionKeyAgreement::generate(),
+        }
+    }
+}
+
+impl Default for DefaultOakServerHandshaker {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-impl<'a> OakHandshaker<HandshakeRequest, HandshakeResponse> for DefaultOakServerHandshaker<'a> {
-    // TODO: Delegate to `inner` once the implementation is complete on Oak side.
+impl OakHandshaker<HandshakeRequest, HandshakeResponse> for DefaultOakServerHandshaker {
+    fn public_key(&self) -> Vec<u8> {
+        self.key_agreement.public_key()
+    }
+
     fn init(&mut self, peer_static_public_key: Vec<u8>) {
-        let config = HandshakerConfig {
-            handshake_type: HandshakeType::NoiseNN,
-            self_static_private_key: None,
-            peer_static_public_key: Some(peer_static_public_key),
-            peer_attestation_binding_public_key: None,
-        };
-        self.inner = Some(ServerHandshaker::new(&config));
+        self.key_agreement.peer_public_key = Some(peer_static_public_key);
     }
 
     fn get_outgoing_message(&mut self) -> anyhow::Result<Option<HandshakeResponse>> {
@@ -144,10 +220,62 @@ impl<'a> OakHandshaker<HandshakeRequest, HandshakeResponse> for DefaultOakServer
         Ok(Some(()))
     }
 
+    // The server sends with the key the client receives with and vice versa.
     fn derive_session_keys(self: Box<Self>) -> Option<SessionKeys> {
+        let public_key = self.public_key();
+        let peer_public_key = self.key_agreement.peer_public_key.clone()?;
+        let mut keys = self
+            .key_agreement
+            .derive_keys(&peer_public_key, &public_key)?;
+        let request_key = keys.split_off(SESSION_KEY_SIZE);
         Some(SessionKeys {
-            request_key: vec![],
-            response_key: vec![],
+            request_key,
+            response_key: keys,
         })
     }
 }
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::oak_handshaker::*;
+
+    #[test]
+    fn test_session_keys() {
+        let mut client = Box::new(DefaultOakClientHandshaker::new());
+        let mut server = Box::new(DefaultOakServerHandshaker::new());
+        client.init(server.public_key());
+        server.init(client.public_key());
+
+        let client_keys = client.derive_session_keys().unwrap();
+        let server_keys = server.derive_session_keys().unwrap();
+        assert_eq!(client_keys.request_key.len(), SESSION_KEY_SIZE);
+        assert_ne!(client_keys.request_key, client_keys.response_key);
+        assert_eq!(client_keys.request_key, server_keys.response_key);
+        assert_eq!(client_keys.response_key, server_keys.request_key);
+    }
+
+    #[test]
+    fn test_session_keys_bound_to_peer() {
+        // Peer presenting the public key of another session can't agree on
+        // the same keys without its private key.
+        let mut client = Box::new(DefaultOakClientHandshaker::new());
+        let mut server = Box::new(DefaultOakServerHandshaker::new());
+        let mut rogue = Box::new(DefaultOakServerHandshaker::new());
+        client.init(server.public_key());
+        server.init(client.public_key());
+        rogue.init(client.public_key());
+
+        let client_keys = client.derive_session_keys().unwrap();
+        let rogue_keys = rogue.derive_session_keys().unwrap();
+        assert_ne!(client_keys.request_key, rogue_keys.response_key);
+        assert!(server.derive_session_keys().is_some());
+
+        // Invalid and low order public keys are rejected.
+        let mut client = Box::new(DefaultOakClientHandshaker::new());
+        client.init(vec![1, 2, 3]);
+        assert!(client.derive_session_keys().is_none());
+        let mut client = Box::new(DefaultOakClientHandshaker::new());
+        client.init(vec![0; 32]);
+        assert!(client.derive_session_keys().is_none());
+    }
+}
@@ -32,12 +32,28 @@
 //!
 //! [Host] trait must be implemented by a concrete trusted host to expose its capabilities
 //! to the trusted application.
+//!
+//! The time signals, i.e. the instants provided by the untrusted launcher and the
+//! wall clock time read by the trusted host, are not used by the trusted application
+//! directly. They are observed by a [crate::clock::Clock] that can be replaced in
+//! tests and are validated before they are passed to the actor. The wall clock time
+//! read by the trusted host can be replaced by the time signed by a time authority
+//! the trusted application is configured with.
+//!
+//! [LoopbackHostCluster] wires a number of trusted applications together in one
+//! process, so that the replicated applications can be tested end to end without
+//! any transport.
 
 use crate::StdError;
+use alloc::collections::{BTreeMap, VecDeque};
 use alloc::vec::Vec;
 use core::fmt;
+use core::mem;
 use core::result::Result;
-use tcp_proto::runtime::endpoint::{InMessage, OutMessage};
+use tcp_proto::runtime::endpoint::{
+    in_message, out_message, raft_config::LogConfig, InMessage, LogMessage, OutMessage,
+    SignedTimestamp,
+};
 
 // Unrecoverable errors that lead to program termination.
 #[derive(Debug, PartialEq)]
@@ -95,6 +111,72 @@ pub trait Host {
     /// has low collision chance. Hash of the public signing key is an identity
     /// mechanism that is compliant with these requirements.
     fn public_signing_key(&self) -> Vec<u8>;
+
+    /// Gets the wall clock time in milliseconds since the Unix epoch read from
+    /// a source the trusted host attests to, e.g. a secure timestamp counter
+    /// calibrated by the platform. Unlike the instants provided by the untrusted
+    /// launcher the wall clock time can be interpreted as absolute time.
+    ///
+    /// # Note
+    ///
+    /// Hosts without a trusted wall clock return none, which is the default.
+    fn wall_time(&self) -> Option<u64> {
+        None
+    }
+
+    /// Gets the wall clock time signed by the time authority and passed along
+    /// with the message from the untrusted launcher. The signature is verified
+    /// by the clock that trusts the time authority, see
+    /// [crate::clock::AuthorityClock].
+    ///
+    /// # Note
+    ///
+    /// Hosts that do not pass the signed time return none, which is the default.
+    fn signed_wall_time(&self) -> Option<SignedTimestamp> {
+        None
+    }
+}
+
+/// Stores small secrets of the trusted application, e.g. the private keys of the
+/// ledger, through the trusted host. The secrets are sealed to the identity of the
+/// enclave, i.e. encrypted with a key only an enclave with the same identity can
+/// derive, hence the untrusted launcher can persist but cannot read them. Secrets
+/// persisted before the restart are unsealed when the trusted application is
+/// started again, so that single replica deployments keep their secrets.
+///
+/// The storage is shared with the actors when they are created, see
+/// [crate::sealed::HostSealedStorage].
+pub trait SealedStorage {
+    /// Gets the secret stored under the given name.
+    fn get(&self, name: &str) -> Option<Vec<u8>>;
+
+    /// Stores the secret under the given name replacing the previous one. The
+    /// secret is sealed and passed to the untrusted launcher to persist along
+    /// with the outgoing messages.
+    fn put(&self, name: &str, secret: &[u8]) -> Result<(), PalError>;
+}
+
+/// Processes the log messages of the trusted application before they are passed
+/// to the untrusted launcher. The untrusted launcher must not learn the secrets of
+/// the trusted application, hence the sink is the place to redact the messages,
+/// besides dropping the messages the replica is not configured to pass and the
+/// messages that would flood the launcher.
+///
+/// The default sink is [crate::logger::DefaultLogSink].
+pub trait LogSink {
+    /// Applies the log configuration the replica has been started with.
+    fn configure(&mut self, config: &LogConfig);
+
+    /// Processes the messages logged since the last call, returns the messages
+    /// to pass to the untrusted launcher.
+    ///
+    /// # Arguments
+    ///
+    /// * `instant` - A measurement of a monotonically nondecreasing clock provided
+    /// by the untrusted launcher, see [Application::receive_message].
+    /// * `messages` - A potentially empty set of messages in the order they have
+    /// been logged.
+    fn process(&mut self, instant: u64, messages: Vec<LogMessage>) -> Vec<LogMessage>;
 }
 
 /// Represents a trusted application running inside a trusted host. The trusted
@@ -131,3 +213,307 @@ pub trait Application {
         opt_message: Option<InMessage>,
     ) -> Result<(), PalError>;
 }
+
+/// Implementation of `Host` for the trusted applications in the [LoopbackHostCluster].
+pub struct LoopbackHost {
+    public_signing_key: Vec<u8>,
+    messages: Vec<OutMessage>,
+}
+
+impl Host for LoopbackHost {
+    fn send_messages(&mut self, mut messages: Vec<OutMessage>) {
+        self.messages.append(&mut messages)
+    }
+
+    fn public_signing_key(&self) -> Vec<u8> {
+        self.public_signing_key.clone()
+    }
+}
+
+struct LoopbackNode<T: Application> {
+    application: T,
+    host: LoopbackHost,
+    messages_in: VecDeque<InMessage>,
+}
+
+/// Wires the trusted applications, e.g. the drivers of the replicas, together
+/// through an in-memory message bus. On every step the clock advances by the
+/// configured period, each application receives the messages addressed to it and
+/// the messages it sends to its peers are delivered on the next step. Messages to
+/// the replicas that are not in the cluster are dropped as if the network lost
+/// them. Remaining messages, e.g. the responses to the commands, are kept for the
+/// test to take.
+pub struct LoopbackHostCluster<T: Application> {
+    instant: u64,
+    step: u64,
+    nodes: BTreeMap<u64, LoopbackNode<T>>,
+    // Messages that are not addressed to the peers along with the sender id.
+    pulled_messages: Vec<(u64, OutMessage)>,
+}
+
+impl<T: Application> LoopbackHostCluster<T> {
+    /// Creates empty cluster that advances the clock by the given number of
+    /// milliseconds on every step.
+    pub fn new(step: u64) -> LoopbackHostCluster<T> {
+        LoopbackHostCluster {
+            instant: 0,
+            step,
+            nodes: BTreeMap::new(),
+            pulled_messages: Vec::new(),
+        }
+    }
+
+    /// Gets the instant the applications have last received.
+    pub fn instant(&self) -> u64 {
+        self.instant
+    }
+
+    /// Gets the ids of the applications in the cluster.
+    pub fn node_ids(&self) -> Vec<u64> {
+        self.nodes.keys().copied().collect()
+    }
+
+    /// Adds the application with the given id, which is expected to be the
+    /// replica id the application is started with.
+    pub fn add_node(&mut self, id: u64, public_signing_key: Vec<u8>, application: T) {
+        self.nodes.insert(
+            id,
+            LoopbackNode {
+                application,
+                host: LoopbackHost {
+                    public_signing_key,
+                    messages: Vec::new(),
+                },
+                messages_in: VecDeque::new(),
+            },
+        );
+    }
+
+    /// Removes the application with the given id, the messages in flight to
+    /// the application are dropped.
+    pub fn remove_node(&mut self, id: u64) -> Option<T> {
+        self.nodes.remove(&id).map(|node| node.application)
+    }
+
+    /// Queues the message to be received by the application on the next step.
+    pub fn send_message(&mut self, id: u64, message: InMessage) {
+        if let Some(node) = self.nodes.get_mut(&id) {
+            node.messages_in.push_back(message);
+        }
+    }
+
+    /// Advances the clock and lets every application receive its messages, the
+    /// applications without messages receive an empty message. Fails if any of
+    /// the applications has encountered an unrecoverable error.
+    pub fn advance(&mut self) -> Result<(), PalError> {
+        self.instant += self.step;
+
+        let mut messages_out = Vec::new();
+        for (id, node) in &mut self.nodes {
+            if node.messages_in.is_empty() {
+                node.application
+                    .receive_message(&mut node.host, self.instant, None)?;
+            }
+            while let Some(message) = node.messages_in.pop_front() {
+                node.application
+                    .receive_message(&mut node.host, self.instant, Some(message))?;
+            }
+            messages_out.extend(
+                mem::take(&mut node.host.messages)
+                    .into_iter()
+                    .map(|message| (*id, message)),
+            );
+        }
+
+        for (id, message) in messages_out {
+            match route_message(message) {
+                Ok((recipient_id, message)) => self.send_message(recipient_id, message),
+                Err(message) => self.pulled_messages.push((id, message)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Advances the cluster until any of the kept messages matches the condition
+    /// or the number of steps is exhausted, and takes the matching messages.
+    pub fn advance_until(
+        &mut self,
+        max_steps: usize,
+        condition: &mut impl FnMut(u64, &OutMessage) -> bool,
+    ) -> Result<Vec<(u64, OutMessage)>, PalError> {
+        for _ in 0..max_steps {
+            self.advance()?;
+            let messages = self.take_messages(condition);
+            if !messages.is_empty() {
+                return Ok(messages);
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    /// Takes the kept messages that match the filter along with their sender ids.
+    pub fn take_messages(
+        &mut self,
+        filter: &mut impl FnMut(u64, &OutMessage) -> bool,
+    ) -> Vec<(u64, OutMessage)> {
+        let (taken, kept) = mem::take(&mut self.pulled_messages)
+            .into_iter()
+            .partition(|(id, message)| filter(*id, message));
+        self.pulled_messages = kept;
+        taken
+    }
+}
+
+// Converts the message to the peer into the message the peer receives, other
+// messages are returned as is.
+fn route_message(message: OutMessage) -> Result<(u64, InMessage), OutMessage> {
+    let (recipient_id, msg) = match message.msg {
+        Some(out_message::Msg::DeliverSystemMessage(msg)) => (
+            msg.recipient_replica_id,
+            in_message::Msg::DeliverSystemMessage(msg),
+        ),
+        Some(out_message::Msg::DeliverSnapshotRequest(msg)) => (
+            msg.recipient_replica_id,
+            in_message::Msg::DeliverSnapshotRequest(msg),
+        ),
+        Some(out_message::Msg::DeliverSnapshotResponse(msg)) => (
+            msg.recipient_replica_id,
+            in_message::Msg::DeliverSnapshotResponse(msg),
+        ),
+        Some(out_message::Msg::SecureChannelHandshake(msg)) => (
+            msg.recipient_replica_id,
+            in_message::Msg::SecureChannelHandshake(msg),
+        ),
+        msg => return Err(OutMessage { msg }),
+    };
+    Ok((recipient_id, InMessage { msg: Some(msg) }))
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::platform::*;
+    use alloc::vec;
+    use core::convert::TryInto;
+    use prost::bytes::Bytes;
+    use tcp_proto::runtime::endpoint::{DeliverAppMessage, DeliverSystemMessage};
+
+    // Passes the counter received from the test or a peer on to the next peer
+    // until it drops to zero, then reports back to the test.
+    struct RingApplication {
+        id: u64,
+        peer_ids: Vec<u64>,
+    }
+
+    impl RingApplication {
+        fn forward(&self, host: &mut impl Host, counter: u64) {
+            let message = if counter == 0 {
+                out_message::Msg::DeliverAppMessage(DeliverAppMessage {
+                    correlation_id: self.id,
+                    ..Default::default()
+                })
+            } else {
+                let index = (self.peer_ids.iter().position(|id| *id == self.id).unwrap() + 1)
+                    % self.peer_ids.len();
+                out_message::Msg::DeliverSystemMessage(DeliverSystemMessage {
+                    recipient_replica_id: self.peer_ids[index],
+                    sender_replica_id: self.id,
+                    message_contents: Bytes::from((counter - 1).to_le_bytes().to_vec()),
+                })
+            };
+            host.send_messages(vec![OutMessage { msg: Some(message) }]);
+        }
+    }
+
+    impl Application for RingApplication {
+        fn receive_message(
+            &mut self,
+            host: &mut impl Host,
+            _instant: u64,
+            opt_message: Option<InMessage>,
+        ) -> Result<(), PalError> {
+            match opt_message.and_then(|message| message.msg) {
+                Some(in_message::Msg::DeliverAppMessage(message)) => {
+                    self.forward(host, message.correlation_id)
+                }
+                Some(in_message::Msg::DeliverSystemMessage(message)) => {
+                    let counter = u64::from_le_bytes(
+                        message.message_contents[..]
+                            .try_into()
+                            .map_err(|_| PalError::Internal)?,
+                    );
+                    self.forward(host, counter)
+                }
+                Some(_) => return Err(PalError::InvalidOperation),
+                None => {}
+            }
+            Ok(())
+        }
+    }
+
+    fn create_cluster(ids: &[u64]) -> LoopbackHostCluster<RingApplication> {
+        let mut cluster = LoopbackHostCluster::new(10);
+        for id in ids {
+            cluster.add_node(
+                *id,
+                vec![*id as u8],
+                RingApplication {
+                    id: *id,
+                    peer_ids: ids.to_vec(),
+                },
+            );
+        }
+        cluster
+    }
+
+    fn start_counter(cluster: &mut LoopbackHostCluster<RingApplication>, id: u64, counter: u64) {
+        cluster.send_message(
+            id,
+            InMessage {
+                msg: Some(in_message::Msg::DeliverAppMessage(DeliverAppMessage {
+                    correlation_id: counter,
+                    ..Default::default()
+                })),
+            },
+        );
+    }
+
+    #[test]
+    fn test_loopback_host_cluster() {
+        let mut cluster = create_cluster(&[1, 2, 3]);
+        assert_eq!(cluster.node_ids(), vec![1, 2, 3]);
+
+        // Counter goes around the ring once a step.
+        start_counter(&mut cluster, 1, 4);
+        let messages = cluster
+            .advance_until(10, &mut |_, message| {
+                matches!(message.msg, Some(out_message::Msg::DeliverAppMessage(_)))
+            })
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].0, 2);
+        assert_eq!(cluster.instant(), 50);
+
+        // Messages to the removed node are dropped.
+        assert!(cluster.remove_node(3).is_some());
+        start_counter(&mut cluster, 2, 4);
+        cluster.advance().unwrap();
+        cluster.advance().unwrap();
+        assert!(cluster.take_messages(&mut |_, _| true).is_empty());
+        assert!(cluster
+            .advance_until(5, &mut |_, _| true)
+            .unwrap()
+            .is_empty());
+
+        // Unrecoverable error of the application is surfaced.
+        cluster.send_message(1, InMessage::default());
+        assert!(cluster.advance().is_ok());
+        cluster.send_message(
+            1,
+            InMessage {
+                msg: Some(in_message::Msg::StopReplica(Default::default())),
+            },
+        );
+        assert_eq!(cluster.advance(), Err(PalError::InvalidOperation));
+    }
+}
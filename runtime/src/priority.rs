@@ -0,0 +1,150 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::array;
+use raft::eraftpb::MessageType as RaftMessageType;
+use tcp_proto::runtime::endpoint::*;
+
+const MESSAGE_CLASS_COUNT: usize = 4;
+
+/// Class of an outgoing message. Classes are declared in the order of their
+/// priority, messages of a higher priority class are emitted first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MessageClass {
+    /// Elections, heartbeats, handshakes and replica management messages.
+    Control = 0,
+    /// Raft log replication messages.
+    Replication = 1,
+    /// Snapshot transfer messages.
+    Snapshot = 2,
+    /// Messages produced by the actor.
+    Application = 3,
+}
+
+impl MessageClass {
+    /// Classifies Raft message by its type.
+    pub fn from_raft_message_type(message_type: RaftMessageType) -> MessageClass {
+        match message_type {
+            RaftMessageType::MsgAppend | RaftMessageType::MsgAppendResponse => {
+                MessageClass::Replication
+            }
+            RaftMessageType::MsgSnapshot => MessageClass::Snapshot,
+            _ => MessageClass::Control,
+        }
+    }
+
+    /// Classifies outgoing message by its type. Note that the contents of the
+    /// system messages may be encrypted and therefore all of them are considered
+    /// to be replication messages.
+    pub fn from_out_message(message: &out_message::Msg) -> MessageClass {
+        match message {
+            out_message::Msg::DeliverSystemMessage(_) => MessageClass::Replication,
+            out_message::Msg::DeliverSnapshotRequest(_)
+            | out_message::Msg::DeliverSnapshotResponse(_) => MessageClass::Snapshot,
+            out_message::Msg::DeliverAppMessage(_) => MessageClass::Application,
+            _ => MessageClass::Control,
+        }
+    }
+}
+
+/// Queue of outgoing messages that emits messages in the order of their class
+/// priority. Number of messages of a given class emitted at once is limited by
+/// the class budget, remaining messages are deferred until the next take.
+pub struct MessageQueue<T> {
+    queues: [VecDeque<T>; MESSAGE_CLASS_COUNT],
+    budgets: [u32; MESSAGE_CLASS_COUNT],
+}
+
+impl<T> MessageQueue<T> {
+    /// Creates a queue with unlimited budgets for all classes.
+    pub fn new() -> MessageQueue<T> {
+        MessageQueue {
+            queues: array::from_fn(|_| VecDeque::new()),
+            budgets: [0; MESSAGE_CLASS_COUNT],
+        }
+    }
+
+    /// Sets maximum number of messages of the given class emitted at once.
+    /// Zero budget means no limit. Control messages are never limited.
+    pub fn set_budget(&mut self, class: MessageClass, budget: u32) {
+        if class != MessageClass::Control {
+            self.budgets[class as usize] = budget;
+        }
+    }
+
+    /// Appends message of the given class to the queue.
+    pub fn push(&mut self, class: MessageClass, message: T) {
+        self.queues[class as usize].push_back(message);
+    }
+
+    /// Takes messages in the order of their class priority within class budgets.
+    pub fn take(&mut self) -> Vec<T> {
+        let mut messages = Vec::new();
+        for (queue, budget) in self.queues.iter_mut().zip(self.budgets.iter()) {
+            let count = if *budget == 0 {
+                queue.len()
+            } else {
+                queue.len().min(*budget as usize)
+            };
+            messages.extend(queue.drain(..count));
+        }
+        messages
+    }
+
+    /// Gets number of messages of the given class waiting to be emitted.
+    pub fn pending(&self, class: MessageClass) -> usize {
+        self.queues[class as usize].len()
+    }
+}
+
+impl<T> Default for MessageQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::priority::{MessageClass, MessageQueue};
+    use alloc::vec;
+
+    #[test]
+    fn test_take_in_priority_order() {
+        let mut queue = MessageQueue::new();
+        queue.push(MessageClass::Application, 4);
+        queue.push(MessageClass::Snapshot, 3);
+        queue.push(MessageClass::Replication, 2);
+        queue.push(MessageClass::Control, 1);
+
+        assert_eq!(queue.take(), vec![1, 2, 3, 4]);
+        assert!(queue.take().is_empty());
+    }
+
+    #[test]
+    fn test_take_within_budget() {
+        let mut queue = MessageQueue::new();
+        queue.set_budget(MessageClass::Control, 1);
+        queue.set_budget(MessageClass::Snapshot, 1);
+        queue.push(MessageClass::Snapshot, 3);
+        queue.push(MessageClass::Snapshot, 4);
+        queue.push(MessageClass::Control, 1);
+        queue.push(MessageClass::Control, 2);
+
+        assert_eq!(queue.take(), vec![1, 2, 3]);
+        assert_eq!(queue.pending(MessageClass::Snapshot), 1);
+        assert_eq!(queue.take(), vec![4]);
+    }
+}
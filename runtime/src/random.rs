@@ -0,0 +1,211 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use aes_gcm_siv::{
+    aead::{Aead, KeyInit},
+    Aes256GcmSiv, Nonce,
+};
+use alloc::vec;
+use alloc::vec::Vec;
+use anyhow::{anyhow, Result};
+use prost::bytes::Bytes;
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+/// Size of the seeds attached to the proposed entries.
+pub const RANDOM_SEED_SIZE: usize = 32;
+
+/// Context the key the seeds are encrypted with is derived from the cluster
+/// secret with.
+pub const RANDOM_SEED_KEY_CONTEXT: &[u8] = b"random seed";
+
+const BLOCK_SIZE: usize = 32;
+
+const NONCE_SIZE: usize = 12;
+
+// Separates the stream drawn while processing a command from the stream drawn
+// while applying the entry the seed is replicated with.
+const COMMAND_STREAM_CONTEXT: &[u8] = b"command";
+
+/// Source of the random bytes drawn by the actor. While seeded the bytes are
+/// derived from the seed, each block of the stream being the SHA-256 digest of
+/// the seed and the block counter, hence the replicas seeded with the same
+/// seed draw the same bytes. Otherwise the bytes are drawn from the operating
+/// system.
+pub struct RandomSource {
+    seed: Vec<u8>,
+    counter: u64,
+    block: [u8; BLOCK_SIZE],
+    // Number of bytes of the current block that have been drawn.
+    offset: usize,
+}
+
+impl RandomSource {
+    pub fn new() -> RandomSource {
+        RandomSource {
+            seed: Vec::new(),
+            counter: 0,
+            block: [0; BLOCK_SIZE],
+            offset: BLOCK_SIZE,
+        }
+    }
+
+    /// Generates a fresh seed to be replicated along with the entry.
+    pub fn generate_seed() -> Bytes {
+        let mut seed = vec![0; RANDOM_SEED_SIZE];
+        OsRng.fill_bytes(&mut seed);
+        seed.into()
+    }
+
+    /// Starts the stream derived from the given seed, empty seed makes the
+    /// source draw from the operating system.
+    pub fn seed(&mut self, seed: &[u8]) {
+        self.seed = seed.to_vec();
+        self.counter = 0;
+        self.offset = BLOCK_SIZE;
+    }
+
+    /// Starts the stream drawn while processing a command, derived from the
+    /// seed replicated along with the first entry the command proposes.
+    pub fn seed_command(&mut self, seed: &[u8]) {
+        let command_seed = Sha256::new()
+            .chain_update(COMMAND_STREAM_CONTEXT)
+            .chain_update(seed)
+            .finalize();
+        self.seed(&command_seed);
+    }
+
+    /// Makes the source draw from the operating system.
+    pub fn unseed(&mut self) {
+        self.seed(&[]);
+    }
+
+    /// Checks if the bytes are derived from the seed.
+    pub fn seeded(&self) -> bool {
+        !self.seed.is_empty()
+    }
+
+    /// Fills the buffer with the next random bytes.
+    pub fn fill(&mut self, dest: &mut [u8]) {
+        if !self.seeded() {
+            OsRng.fill_bytes(dest);
+            return;
+        }
+
+        for byte in dest.iter_mut() {
+            if self.offset == BLOCK_SIZE {
+                let digest = Sha256::new()
+                    .chain_update(&self.seed)
+                    .chain_update(self.counter.to_le_bytes())
+                    .finalize();
+                self.block.copy_from_slice(&digest);
+                self.counter += 1;
+                self.offset = 0;
+            }
+            *byte = self.block[self.offset];
+            self.offset += 1;
+        }
+    }
+}
+
+/// Encrypts the seed before it is replicated, so that the log persisted by the
+/// untrusted launcher does not reveal the bytes the actor draws.
+pub fn encrypt_seed(key: &[u8], seed: &[u8]) -> Result<Bytes> {
+    let mut nonce = [0; NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = Aes256GcmSiv::new_from_slice(key)
+        .map_err(|e| anyhow!("Invalid seed key: {}", e))?
+        .encrypt(Nonce::from_slice(&nonce), seed)
+        .map_err(|e| anyhow!("Failed to encrypt seed: {}", e))?;
+    let mut encrypted_seed = nonce.to_vec();
+    encrypted_seed.extend_from_slice(&ciphertext);
+    Ok(encrypted_seed.into())
+}
+
+/// Decrypts the seed encrypted by [encrypt_seed].
+pub fn decrypt_seed(key: &[u8], encrypted_seed: &[u8]) -> Result<Vec<u8>> {
+    if encrypted_seed.len() < NONCE_SIZE {
+        return Err(anyhow!("Encrypted seed is too short"));
+    }
+    let (nonce, ciphertext) = encrypted_seed.split_at(NONCE_SIZE);
+    Aes256GcmSiv::new_from_slice(key)
+        .map_err(|e| anyhow!("Invalid seed key: {}", e))?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|e| anyhow!("Failed to decrypt seed: {}", e))
+}
+
+impl Default for RandomSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::random::*;
+
+    #[test]
+    fn test_random_source() {
+        let seed = RandomSource::generate_seed();
+        assert_eq!(seed.len(), RANDOM_SEED_SIZE);
+
+        // Sources with the same seed draw the same bytes regardless of how the
+        // draws are split.
+        let mut source_1 = RandomSource::new();
+        source_1.seed(&seed);
+        let mut bytes_1 = [0; 48];
+        source_1.fill(&mut bytes_1[..20]);
+        source_1.fill(&mut bytes_1[20..]);
+
+        let mut source_2 = RandomSource::new();
+        source_2.seed(&seed);
+        let mut bytes_2 = [0; 48];
+        source_2.fill(&mut bytes_2);
+        assert_eq!(bytes_1, bytes_2);
+
+        // Reseeding restarts the stream.
+        source_2.seed(&seed);
+        let mut bytes_3 = [0; 48];
+        source_2.fill(&mut bytes_3);
+        assert_eq!(bytes_1, bytes_3);
+
+        // Different seed derives different bytes.
+        source_2.seed(&[1, 2, 3]);
+        source_2.fill(&mut bytes_3);
+        assert_ne!(bytes_1, bytes_3);
+
+        // Command stream differs from the stream of the entry.
+        source_2.seed_command(&seed);
+        source_2.fill(&mut bytes_3);
+        assert_ne!(bytes_1, bytes_3);
+
+        source_2.unseed();
+        assert!(!source_2.seeded());
+        source_2.fill(&mut bytes_3);
+    }
+
+    #[test]
+    fn test_encrypt_seed() {
+        let key = [1; 32];
+        let seed = RandomSource::generate_seed();
+        let encrypted_seed = encrypt_seed(&key, &seed).unwrap();
+
+        assert!(!encrypted_seed
+            .windows(seed.len())
+            .any(|window| window == &seed[..]));
+        assert_eq!(decrypt_seed(&key, &encrypted_seed).unwrap(), seed.to_vec());
+        assert!(decrypt_seed(&[2; 32], &encrypted_seed).is_err());
+        assert!(decrypt_seed(&key, &encrypted_seed[..4]).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::ActorCommand;
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+use prost::bytes::Bytes;
+
+/// Tracks read-only queries through the Raft ReadIndex protocol. A query is
+/// started with a unique context passed to Raft, confirmed once Raft reports
+/// the read index for that context and becomes ready once the read index has
+/// been applied to the actor.
+pub struct ReadIndexQueue {
+    next_read_id: u64,
+    // Queries waiting for Raft to confirm the read index, keyed by read id.
+    pending: HashMap<u64, ActorCommand>,
+    // Queries with confirmed read index waiting for it to be applied.
+    confirmed: Vec<(u64, ActorCommand)>,
+}
+
+impl ReadIndexQueue {
+    pub fn new() -> ReadIndexQueue {
+        ReadIndexQueue {
+            next_read_id: 1,
+            pending: HashMap::new(),
+            confirmed: Vec::new(),
+        }
+    }
+
+    /// Registers the query and returns the context to request read index with.
+    pub fn start(&mut self, command: ActorCommand) -> Bytes {
+        let read_id = self.next_read_id;
+        self.next_read_id += 1;
+        self.pending.insert(read_id, command);
+
+        Bytes::copy_from_slice(&read_id.to_le_bytes())
+    }
+
+    /// Records the read index reported by Raft for the given context. Contexts
+    /// of the queries that have been dropped are ignored.
+    pub fn confirm(&mut self, context: &[u8], index: u64) {
+        let Ok(read_id) = <[u8; 8]>::try_from(context) else {
+            return;
+        };
+
+        if let Some(command) = self.pending.remove(&u64::from_le_bytes(read_id)) {
+            self.confirmed.push((index, command));
+        }
+    }
+
+    /// Takes queries whose read index does not exceed the applied index, in
+    /// the order they have been confirmed.
+    pub fn take_ready(&mut self, applied_index: u64) -> Vec<ActorCommand> {
+        let mut ready = Vec::new();
+        let mut i = 0;
+        while i < self.confirmed.len() {
+            if self.confirmed[i].0 <= applied_index {
+                ready.push(self.confirmed.remove(i).1);
+            } else {
+                i += 1;
+            }
+        }
+
+        ready
+    }
+
+    /// Takes all queries to fail them. Queries started before the leadership
+    /// change may never be confirmed.
+    pub fn reset(&mut self) -> Vec<ActorCommand> {
+        let mut dropped: Vec<ActorCommand> =
+            self.pending.drain().map(|(_, command)| command).collect();
+        dropped.extend(self.confirmed.drain(..).map(|(_, command)| command));
+        dropped
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::model::ActorCommand;
+    use crate::read_index::*;
+    use alloc::vec;
+
+    fn create_command(correlation_id: u64) -> ActorCommand {
+        ActorCommand {
+            correlation_id,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_read_index_queue() {
+        let mut queue = ReadIndexQueue::new();
+
+        let context_1 = queue.start(create_command(1));
+        let context_2 = queue.start(create_command(2));
+        let context_3 = queue.start(create_command(3));
+        assert_ne!(context_1, context_2);
+
+        queue.confirm(&context_2, 7);
+        queue.confirm(&context_1, 5);
+        // Unknown and malformed contexts are ignored.
+        queue.confirm(&context_1, 5);
+        queue.confirm(&[1, 2], 5);
+
+        assert_eq!(queue.take_ready(4), vec![]);
+        assert_eq!(queue.take_ready(5), vec![create_command(1)]);
+        assert_eq!(queue.take_ready(9), vec![create_command(2)]);
+
+        assert_eq!(queue.reset(), vec![create_command(3)]);
+        queue.confirm(&context_3, 1);
+        assert_eq!(queue.take_ready(9), vec![]);
+    }
+}
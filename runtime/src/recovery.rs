@@ -0,0 +1,741 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::consensus::PersistentStore;
+use crate::encryptor::Encryptor;
+use crate::StdError;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+use hmac::{Hmac, Mac};
+use prost::{bytes::Bytes, Message};
+use raft::eraftpb::{Entry as RaftEntry, HardState as RaftHardState, Snapshot as RaftSnapshot};
+use rand::{rngs::OsRng, RngCore};
+use sha2::Sha256;
+use tcp_proto::runtime::endpoint::{ExportedSnapshot, PersistReplicaState, ReplicaRecoveryState};
+
+use crate::util::raft::get_metadata;
+
+/// Context the key the exported snapshots are authenticated with is derived
+/// from the cluster secret with.
+pub const EXPORTED_SNAPSHOT_KEY_CONTEXT: &[u8] = b"exported snapshot";
+
+/// Name the key the persisted updates are authenticated with is kept under in
+/// the sealed storage of the replica.
+pub const JOURNAL_KEY_NAME: &str = "tcp.journal_key";
+
+/// Size of the key the persisted updates are authenticated with.
+pub const JOURNAL_KEY_SIZE: usize = 32;
+
+/// Enumerates errors possible while recovering the replica state.
+#[derive(Debug, PartialEq)]
+pub enum RecoveryError {
+    /// The persisted updates do not start with a checkpoint.
+    MissingCheckpoint,
+    /// The persisted updates are missing, reordered or end below the expected
+    /// counter.
+    Counter,
+    /// The persisted update doesn't match its digest or the key the updates
+    /// are authenticated with is missing.
+    Integrity,
+    /// The persisted updates have been produced by a different replica.
+    ReplicaMismatch,
+    /// The persisted update cannot be decoded or is inconsistent.
+    Corrupted,
+    /// The update contents cannot be encrypted or decrypted.
+    Encryption,
+    /// The replica state is not persisted, hence there is no authenticated
+    /// chain of updates to recover from.
+    Disabled,
+}
+
+impl StdError for RecoveryError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        None
+    }
+}
+
+impl fmt::Display for RecoveryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RecoveryError::MissingCheckpoint => write!(f, "Recovery state has no checkpoint"),
+            RecoveryError::Counter => write!(f, "Recovery state counter is invalid"),
+            RecoveryError::Integrity => write!(f, "Recovery state digest is invalid"),
+            RecoveryError::ReplicaMismatch => write!(f, "Recovery state replica id mismatch"),
+            RecoveryError::Corrupted => write!(f, "Recovery state is corrupted"),
+            RecoveryError::Encryption => write!(f, "Recovery state encryption failed"),
+            RecoveryError::Disabled => write!(f, "Recovery state persistence is disabled"),
+        }
+    }
+}
+
+/// Replica state restored from the persisted updates.
+#[derive(Debug, Default)]
+pub struct RecoveredState {
+    pub hard_state: RaftHardState,
+    pub snapshot: RaftSnapshot,
+    pub entries: Vec<RaftEntry>,
+    // Counter and digest of the last update to continue the chain from.
+    counter: u64,
+    digest: Bytes,
+}
+
+/// Produces the chain of replica state updates for the host to persist.
+/// Contents of the updates are encrypted if the journal has an encryptor.
+/// The digests are HMAC-SHA256 over the encrypted contents keyed with the key
+/// the replica keeps in its sealed storage, hence the host can neither forge
+/// nor modify the updates.
+///
+/// The journal doesn't protect against the rollback of the whole chain: the
+/// host that retains an earlier chain along with the sealed secrets it has
+/// been produced with can restart the replica from it. The minimum counter the
+/// last update is checked against is supplied by the host as well and only
+/// detects the trailing updates the host has failed to persist.
+pub struct StateJournal {
+    enabled: bool,
+    replica_id: u64,
+    key: Vec<u8>,
+    counter: u64,
+    digest: Bytes,
+    encryptor: Option<Box<dyn Encryptor>>,
+}
+
+impl StateJournal {
+    /// Creates journal with persistence disabled.
+    pub fn new() -> StateJournal {
+        StateJournal {
+            enabled: false,
+            replica_id: 0,
+            key: Vec::new(),
+            counter: 0,
+            digest: Bytes::new(),
+            encryptor: None,
+        }
+    }
+
+    /// Creates journal with persistence disabled that encrypts the contents of
+    /// the updates. The encryptor must be able to decrypt the contents after the
+    /// replica restart.
+    pub fn with_encryptor(encryptor: Box<dyn Encryptor>) -> StateJournal {
+        StateJournal {
+            encryptor: Some(encryptor),
+            ..StateJournal::new()
+        }
+    }
+
+    fn seal(&self, contents: Vec<u8>) -> Result<Bytes, RecoveryError> {
+        match &self.encryptor {
+            Some(encryptor) => encryptor
+                .encrypt(&contents)
+                .map(Bytes::from)
+                .map_err(|_| RecoveryError::Encryption),
+            None => Ok(contents.into()),
+        }
+    }
+}
+
+impl PersistentStore for StateJournal {
+    fn configure(&mut self, enabled: bool, replica_id: u64, key: Vec<u8>) {
+        self.enabled = enabled;
+        self.replica_id = replica_id;
+        self.key = key;
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn recover(
+        &mut self,
+        recovery_state: ReplicaRecoveryState,
+    ) -> Result<RecoveredState, RecoveryError> {
+        // Replica state is only restored from the chain authenticated with the
+        // key the replica persists its own updates with.
+        if !self.enabled {
+            return Err(RecoveryError::Disabled);
+        }
+
+        let recovered_state = recover_state(
+            self.replica_id,
+            &self.key,
+            recovery_state,
+            self.encryptor.as_deref(),
+        )?;
+
+        // Continue the chain of updates after the recovered state.
+        self.counter = recovered_state.counter;
+        self.digest = recovered_state.digest.clone();
+
+        Ok(recovered_state)
+    }
+
+    fn record(
+        &mut self,
+        checkpoint: bool,
+        hard_state: Option<&RaftHardState>,
+        snapshot: Option<&RaftSnapshot>,
+        entries: &[RaftEntry],
+    ) -> Result<PersistReplicaState, RecoveryError> {
+        let mut update = PersistReplicaState {
+            counter: self.counter + 1,
+            checkpoint,
+            replica_id: self.replica_id,
+            hard_state: match hard_state {
+                Some(hard_state) => self.seal(hard_state.encode_to_vec())?,
+                None => Bytes::new(),
+            },
+            snapshot: match snapshot {
+                Some(snapshot) => self.seal(snapshot.encode_to_vec())?,
+                None => Bytes::new(),
+            },
+            entries: entries
+                .iter()
+                .map(|entry| self.seal(entry.encode_to_vec()))
+                .collect::<Result<_, _>>()?,
+            digest: Bytes::new(),
+        };
+        update.digest = create_update_mac(&self.key, &self.digest, &update)?
+            .finalize()
+            .into_bytes()
+            .to_vec()
+            .into();
+        self.counter = update.counter;
+        self.digest = update.digest.clone();
+
+        Ok(update)
+    }
+
+    fn export(
+        &self,
+        snapshot: &RaftSnapshot,
+        key: &[u8],
+    ) -> Result<ExportedSnapshot, RecoveryError> {
+        let metadata = get_metadata(snapshot);
+        let mut exported_snapshot = ExportedSnapshot {
+            index: metadata.index,
+            term: metadata.term,
+            // Snapshot is exported along with its metadata so that the position
+            // can be checked against it on import.
+            contents: self.seal(snapshot.encode_to_vec())?,
+            digest: Bytes::new(),
+        };
+        exported_snapshot.digest = create_snapshot_mac(key, &exported_snapshot)?
+            .finalize()
+            .into_bytes()
+            .to_vec()
+            .into();
+
+        Ok(exported_snapshot)
+    }
+
+    fn import(
+        &self,
+        exported_snapshot: ExportedSnapshot,
+        key: &[u8],
+    ) -> Result<Bytes, RecoveryError> {
+        create_snapshot_mac(key, &exported_snapshot)?
+            .verify_slice(&exported_snapshot.digest)
+            .map_err(|_| RecoveryError::Integrity)?;
+
+        let snapshot = RaftSnapshot::decode(unseal(
+            self.encryptor.as_deref(),
+            exported_snapshot.contents,
+        )?)
+        .map_err(|_| RecoveryError::Corrupted)?;
+        let metadata = get_metadata(&snapshot);
+        if metadata.index != exported_snapshot.index || metadata.term != exported_snapshot.term {
+            return Err(RecoveryError::Corrupted);
+        }
+
+        Ok(snapshot.data)
+    }
+}
+
+/// Generates the key the persisted updates of a new replica are authenticated
+/// with.
+pub fn generate_journal_key() -> Vec<u8> {
+    let mut key = alloc::vec![0; JOURNAL_KEY_SIZE];
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+// Decrypts the contents of the persisted update if the encryptor is given.
+fn unseal(encryptor: Option<&dyn Encryptor>, contents: Bytes) -> Result<Bytes, RecoveryError> {
+    match encryptor {
+        Some(encryptor) => encryptor
+            .decrypt(&contents)
+            .map(Bytes::from)
+            .map_err(|_| RecoveryError::Encryption),
+        None => Ok(contents),
+    }
+}
+
+// Creates the MAC of the update contents chained with the digest of the
+// previous update unless the update is a checkpoint, keyed with the key only
+// the replica can unseal.
+fn create_update_mac(
+    key: &[u8],
+    previous_digest: &Bytes,
+    update: &PersistReplicaState,
+) -> Result<Hmac<Sha256>, RecoveryError> {
+    // HMAC accepts the empty key, which the host could compute the MAC with.
+    if key.is_empty() {
+        return Err(RecoveryError::Integrity);
+    }
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).map_err(|_| RecoveryError::Integrity)?;
+    if !update.checkpoint {
+        mac.update(previous_digest);
+    }
+    mac.update(&update.counter.to_le_bytes());
+    mac.update(&[update.checkpoint as u8]);
+    mac.update(&update.replica_id.to_le_bytes());
+    for contents in [&update.hard_state, &update.snapshot]
+        .into_iter()
+        .chain(update.entries.iter())
+    {
+        // Length prefix keeps boundaries between the fields unambiguous.
+        mac.update(&(contents.len() as u64).to_le_bytes());
+        mac.update(contents);
+    }
+    Ok(mac)
+}
+
+// Creates the MAC of the exported snapshot position and contents, keyed with
+// the key only the replicas of the cluster can derive.
+fn create_snapshot_mac(
+    key: &[u8],
+    exported_snapshot: &ExportedSnapshot,
+) -> Result<Hmac<Sha256>, RecoveryError> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).map_err(|_| RecoveryError::Integrity)?;
+    mac.update(&exported_snapshot.index.to_le_bytes());
+    mac.update(&exported_snapshot.term.to_le_bytes());
+    mac.update(&exported_snapshot.contents);
+    Ok(mac)
+}
+
+/// Verifies the chain of persisted updates and folds it into the replica
+/// state. Updates must start with a checkpoint, have consecutive counters
+/// and digests matching the MAC under the given key, be produced by the given
+/// replica and end with a counter no lower than the expected minimum. The
+/// contents of the updates are decrypted with the encryptor if given.
+pub fn recover_state(
+    replica_id: u64,
+    key: &[u8],
+    recovery_state: ReplicaRecoveryState,
+    encryptor: Option<&dyn Encryptor>,
+) -> Result<RecoveredState, RecoveryError> {
+    let mut recovered_state = RecoveredState::default();
+
+    for (position, update) in recovery_state.updates.into_iter().enumerate() {
+        if position == 0 {
+            if !update.checkpoint {
+                return Err(RecoveryError::MissingCheckpoint);
+            }
+        } else if update.counter != recovered_state.counter + 1 {
+            return Err(RecoveryError::Counter);
+        }
+        if update.replica_id != replica_id {
+            return Err(RecoveryError::ReplicaMismatch);
+        }
+        create_update_mac(key, &recovered_state.digest, &update)?
+            .verify_slice(&update.digest)
+            .map_err(|_| RecoveryError::Integrity)?;
+
+        if !update.hard_state.is_empty() {
+            recovered_state.hard_state =
+                RaftHardState::decode(unseal(encryptor, update.hard_state)?)
+                    .map_err(|_| RecoveryError::Corrupted)?;
+        }
+
+        if !update.snapshot.is_empty() {
+            let snapshot = RaftSnapshot::decode(unseal(encryptor, update.snapshot)?)
+                .map_err(|_| RecoveryError::Corrupted)?;
+            let snapshot_index = get_metadata(&snapshot).index;
+            recovered_state
+                .entries
+                .retain(|entry| entry.index > snapshot_index);
+            recovered_state.snapshot = snapshot;
+        }
+
+        for entry in update.entries {
+            let entry = RaftEntry::decode(unseal(encryptor, entry)?)
+                .map_err(|_| RecoveryError::Corrupted)?;
+            // Appended entry replaces all entries starting from its index and must
+            // follow the snapshot or the preceding entries without gaps.
+            let first_index = get_metadata(&recovered_state.snapshot).index + 1;
+            if entry.index < first_index {
+                return Err(RecoveryError::Corrupted);
+            }
+            recovered_state
+                .entries
+                .truncate((entry.index - first_index) as usize);
+            if recovered_state.entries.len() as u64 != entry.index - first_index {
+                return Err(RecoveryError::Corrupted);
+            }
+            recovered_state.entries.push(entry);
+        }
+
+        recovered_state.counter = update.counter;
+        recovered_state.digest = update.digest;
+    }
+
+    if recovered_state.counter == 0 {
+        return Err(RecoveryError::MissingCheckpoint);
+    }
+    if recovered_state.counter < recovery_state.min_counter {
+        return Err(RecoveryError::Counter);
+    }
+    // Committed entries must be covered by the snapshot or the log.
+    let snapshot_index = get_metadata(&recovered_state.snapshot).index;
+    let last_index = recovered_state
+        .entries
+        .last()
+        .map_or(snapshot_index, |entry| entry.index);
+    if recovered_state.hard_state.commit < snapshot_index
+        || recovered_state.hard_state.commit > last_index
+    {
+        return Err(RecoveryError::Corrupted);
+    }
+
+    Ok(recovered_state)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::recovery::*;
+    use crate::util::raft::{
+        create_empty_raft_entry, create_raft_config_state, create_raft_snapshot,
+        create_raft_snapshot_metadata,
+    };
+    use alloc::vec;
+
+    const JOURNAL_KEY: [u8; JOURNAL_KEY_SIZE] = [3; JOURNAL_KEY_SIZE];
+
+    // Reversible transformation standing in for the encryption.
+    struct FakeEncryptor {
+        key: u8,
+    }
+
+    impl Encryptor for FakeEncryptor {
+        fn encrypt(&self, plaintext: &[u8]) -> anyhow::Result<Vec<u8>> {
+            Ok(plaintext.iter().map(|byte| byte ^ self.key).collect())
+        }
+
+        fn decrypt(&self, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
+            self.encrypt(ciphertext)
+        }
+
+        fn export_secret(&self, context: &[u8]) -> anyhow::Result<Vec<u8>> {
+            self.encrypt(context)
+        }
+
+        fn rekey(&mut self) -> anyhow::Result<()> {
+            self.key = self.key.wrapping_add(1);
+            Ok(())
+        }
+    }
+
+    fn create_hard_state(term: u64, commit: u64) -> RaftHardState {
+        RaftHardState {
+            term,
+            vote: 1,
+            commit,
+        }
+    }
+
+    fn create_snapshot(index: u64, term: u64) -> RaftSnapshot {
+        create_raft_snapshot(
+            create_raft_snapshot_metadata(index, term, create_raft_config_state(vec![1])),
+            Bytes::from(vec![index as u8; 4]),
+        )
+    }
+
+    fn record_updates(journal: &mut StateJournal) -> Vec<PersistReplicaState> {
+        vec![
+            journal
+                .record(
+                    true,
+                    Some(&create_hard_state(1, 1)),
+                    Some(&create_snapshot(1, 1)),
+                    &[],
+                )
+                .unwrap(),
+            journal
+                .record(
+                    false,
+                    Some(&create_hard_state(2, 3)),
+                    None,
+                    &[
+                        create_empty_raft_entry(2, 1),
+                        create_empty_raft_entry(3, 1),
+                        create_empty_raft_entry(4, 1),
+                    ],
+                )
+                .unwrap(),
+            // Overwrites the uncommitted entry.
+            journal
+                .record(false, None, None, &[create_empty_raft_entry(4, 2)])
+                .unwrap(),
+        ]
+    }
+
+    #[test]
+    fn test_recover_state() {
+        let mut journal = StateJournal::new();
+        journal.configure(true, 1, JOURNAL_KEY.to_vec());
+        let updates = record_updates(&mut journal);
+
+        // The chain continues after the recovered state.
+        let mut resumed_journal = StateJournal::new();
+        resumed_journal.configure(true, 1, JOURNAL_KEY.to_vec());
+        let recovered_state = resumed_journal
+            .recover(ReplicaRecoveryState {
+                updates: updates.clone(),
+                min_counter: 3,
+            })
+            .unwrap();
+
+        assert_eq!(recovered_state.hard_state, create_hard_state(2, 3));
+        assert_eq!(recovered_state.snapshot, create_snapshot(1, 1));
+        assert_eq!(
+            recovered_state.entries,
+            vec![
+                create_empty_raft_entry(2, 1),
+                create_empty_raft_entry(3, 1),
+                create_empty_raft_entry(4, 2),
+            ]
+        );
+        assert_eq!(
+            resumed_journal.record(false, Some(&create_hard_state(2, 4)), None, &[]),
+            journal.record(false, Some(&create_hard_state(2, 4)), None, &[])
+        );
+    }
+
+    #[test]
+    fn test_recover_encrypted_state() {
+        let mut journal = StateJournal::with_encryptor(Box::new(FakeEncryptor { key: 7 }));
+        journal.configure(true, 1, JOURNAL_KEY.to_vec());
+        let updates = record_updates(&mut journal);
+        assert_ne!(
+            updates[1].hard_state,
+            Bytes::from(create_hard_state(2, 3).encode_to_vec())
+        );
+
+        let mut resumed_journal = StateJournal::with_encryptor(Box::new(FakeEncryptor { key: 7 }));
+        resumed_journal.configure(true, 1, JOURNAL_KEY.to_vec());
+        let recovered_state = resumed_journal
+            .recover(ReplicaRecoveryState {
+                updates: updates.clone(),
+                min_counter: 3,
+            })
+            .unwrap();
+        assert_eq!(recovered_state.hard_state, create_hard_state(2, 3));
+        assert_eq!(recovered_state.snapshot, create_snapshot(1, 1));
+        assert_eq!(recovered_state.entries.len(), 3);
+
+        // Updates cannot be recovered without the matching encryptor.
+        assert!(recover_state(
+            1,
+            &JOURNAL_KEY,
+            ReplicaRecoveryState {
+                updates: updates.clone(),
+                min_counter: 3,
+            },
+            None,
+        )
+        .is_err());
+        assert_eq!(
+            recover_state(
+                1,
+                &JOURNAL_KEY,
+                ReplicaRecoveryState {
+                    updates,
+                    min_counter: 3,
+                },
+                Some(&FakeEncryptor { key: 9 }),
+            )
+            .err(),
+            Some(RecoveryError::Corrupted)
+        );
+    }
+
+    #[test]
+    fn test_recover_state_failures() {
+        let mut journal = StateJournal::new();
+        journal.configure(true, 1, JOURNAL_KEY.to_vec());
+        let updates = record_updates(&mut journal);
+
+        let recover = |updates: Vec<PersistReplicaState>, min_counter: u64| {
+            recover_state(
+                1,
+                &JOURNAL_KEY,
+                ReplicaRecoveryState {
+                    updates,
+                    min_counter,
+                },
+                None,
+            )
+            .err()
+        };
+
+        assert_eq!(recover(vec![], 0), Some(RecoveryError::MissingCheckpoint));
+        assert_eq!(
+            recover(updates[1..].to_vec(), 0),
+            Some(RecoveryError::MissingCheckpoint)
+        );
+        assert_eq!(
+            recover(vec![updates[0].clone(), updates[2].clone()], 0),
+            Some(RecoveryError::Counter)
+        );
+        // Dropped trailing update is detected through the minimum counter.
+        assert_eq!(
+            recover(updates[..2].to_vec(), 3),
+            Some(RecoveryError::Counter)
+        );
+
+        let mut tampered_updates = updates.clone();
+        tampered_updates[1].hard_state = create_hard_state(3, 3).encode_to_vec().into();
+        assert_eq!(recover(tampered_updates, 0), Some(RecoveryError::Integrity));
+
+        assert_eq!(
+            recover_state(
+                2,
+                &JOURNAL_KEY,
+                ReplicaRecoveryState {
+                    updates,
+                    min_counter: 0,
+                },
+                None,
+            )
+            .err(),
+            Some(RecoveryError::ReplicaMismatch)
+        );
+    }
+
+    #[test]
+    fn test_recover_state_disabled() {
+        let mut journal = StateJournal::new();
+        journal.configure(true, 1, JOURNAL_KEY.to_vec());
+        let updates = record_updates(&mut journal);
+
+        // Valid chain isn't replayed unless the replica persists its state.
+        let mut resumed_journal = StateJournal::new();
+        resumed_journal.configure(false, 1, JOURNAL_KEY.to_vec());
+        assert_eq!(
+            resumed_journal
+                .recover(ReplicaRecoveryState {
+                    updates,
+                    min_counter: 3,
+                })
+                .err(),
+            Some(RecoveryError::Disabled)
+        );
+    }
+
+    #[test]
+    fn test_recover_forged_state() {
+        let mut journal = StateJournal::new();
+        journal.configure(true, 1, JOURNAL_KEY.to_vec());
+        let updates = record_updates(&mut journal);
+
+        // The host recomputes the digests of the modified chain without the key.
+        let mut forging_journal = StateJournal::new();
+        forging_journal.configure(true, 1, [4; JOURNAL_KEY_SIZE].to_vec());
+        let forged_updates = vec![forging_journal
+            .record(
+                true,
+                Some(&create_hard_state(5, 1)),
+                Some(&create_snapshot(1, 1)),
+                &[],
+            )
+            .unwrap()];
+        let recover = |updates: Vec<PersistReplicaState>, key: &[u8]| {
+            recover_state(
+                1,
+                key,
+                ReplicaRecoveryState {
+                    updates,
+                    min_counter: 0,
+                },
+                None,
+            )
+            .err()
+        };
+        assert_eq!(
+            recover(forged_updates, &JOURNAL_KEY),
+            Some(RecoveryError::Integrity)
+        );
+
+        // Updates can't be authenticated without the key.
+        assert_eq!(recover(updates, &[]), Some(RecoveryError::Integrity));
+        let mut unkeyed_journal = StateJournal::new();
+        unkeyed_journal.configure(true, 1, Vec::new());
+        assert_eq!(
+            unkeyed_journal.record(true, None, None, &[]),
+            Err(RecoveryError::Integrity)
+        );
+    }
+
+    #[test]
+    fn test_export_import_snapshot() {
+        let key = [1; 32];
+        let journal = StateJournal::with_encryptor(Box::new(FakeEncryptor { key: 7 }));
+        let exported_snapshot = journal.export(&create_snapshot(5, 2), &key).unwrap();
+        assert_eq!((exported_snapshot.index, exported_snapshot.term), (5, 2));
+        assert_ne!(
+            exported_snapshot.contents,
+            Bytes::from(create_snapshot(5, 2).encode_to_vec())
+        );
+
+        // Snapshot is imported by a replica of the restarted cluster.
+        let restarted_journal = StateJournal::with_encryptor(Box::new(FakeEncryptor { key: 7 }));
+        assert_eq!(
+            restarted_journal.import(exported_snapshot.clone(), &key),
+            Ok(Bytes::from(vec![5; 4]))
+        );
+
+        // The host can't recompute the MAC without the key.
+        assert_eq!(
+            restarted_journal.import(exported_snapshot.clone(), &[2; 32]),
+            Err(RecoveryError::Integrity)
+        );
+
+        let mut tampered_snapshot = exported_snapshot.clone();
+        tampered_snapshot.term = 3;
+        assert_eq!(
+            restarted_journal.import(tampered_snapshot, &key),
+            Err(RecoveryError::Integrity)
+        );
+    }
+
+    #[test]
+    fn test_import_snapshot_position_mismatch() {
+        let key = [1; 32];
+        let journal = StateJournal::new();
+
+        // Position doesn't match the metadata of the snapshot despite the valid MAC.
+        let mut exported_snapshot = journal.export(&create_snapshot(5, 2), &key).unwrap();
+        exported_snapshot.index = 7;
+        exported_snapshot.digest = create_snapshot_mac(&key, &exported_snapshot)
+            .unwrap()
+            .finalize()
+            .into_bytes()
+            .to_vec()
+            .into();
+        assert_eq!(
+            journal.import(exported_snapshot, &key),
+            Err(RecoveryError::Corrupted)
+        );
+    }
+}
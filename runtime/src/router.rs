@@ -0,0 +1,786 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::metrics::MetricsRegistry;
+use crate::model::{
+    Actor, ActorCommand, ActorContext, ActorError, ActorEvent, ActorEventContext, CommandOutcome,
+    EventOutcome, SnapshotWriter,
+};
+use crate::snapshot::{split_chunks, SnapshotBuffer};
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use micro_rpc::StatusCode;
+use prost::{bytes::Bytes, Message};
+use slog::{error, warn, Logger};
+use tcp_proto::runtime::endpoint::{routed_snapshot::Section, RoutedEvent, RoutedSnapshot};
+
+// Number of low bits of the timer id available to each of the actors, the
+// high bits identify the actor that has scheduled the timer.
+const TIMER_ID_BITS: u32 = 56;
+const TIMER_ID_MASK: u64 = (1 << TIMER_ID_BITS) - 1;
+// Maximum number of actors the high bits of the timer id can identify.
+const MAX_ACTORS: usize = 1 << (u64::BITS - TIMER_ID_BITS);
+
+// Size of the pieces the snapshot sections are passed in to the actors that
+// restore their state piece by piece.
+const SECTION_CHUNK_SIZE: u64 = 1024 * 1024;
+
+// Presents the shared context to one of the actors, tagging the commands
+// with the route of the actor and scoping its timers.
+struct RoutedContext {
+    context: Rc<dyn ActorContext>,
+    route: String,
+    ordinal: u64,
+    // State sizes reported by each of the actors, the shared context is
+    // reported the total.
+    state_sizes: Rc<RefCell<Vec<u64>>>,
+}
+
+impl ActorContext for RoutedContext {
+    fn logger(&self) -> &Logger {
+        self.context.logger()
+    }
+
+    fn id(&self) -> u64 {
+        self.context.id()
+    }
+
+    fn instant(&self) -> u64 {
+        self.context.instant()
+    }
+
+    fn wall_time(&self) -> Option<u64> {
+        self.context.wall_time()
+    }
+
+    fn config(&self) -> Bytes {
+        self.context.config()
+    }
+
+    fn leader(&self) -> bool {
+        self.context.leader()
+    }
+
+    fn read_confirmed(&self) -> bool {
+        self.context.read_confirmed()
+    }
+
+    fn applied_index(&self) -> u64 {
+        self.context.applied_index()
+    }
+
+    fn leader_commit_hint(&self) -> u64 {
+        self.context.leader_commit_hint()
+    }
+
+    fn subscribe_commit_watermark(&self, index: u64) {
+        self.context.subscribe_commit_watermark(index)
+    }
+
+    fn complete_command(&self, correlation_id: u64, mut response: ActorCommand) {
+        response.route = self.route.clone();
+        self.context.complete_command(correlation_id, response)
+    }
+
+    fn set_timer(&self, timer_id: u64, deadline: u64) {
+        self.context
+            .set_timer(scope_timer_id(self.ordinal, timer_id), deadline)
+    }
+
+    fn cancel_timer(&self, timer_id: u64) {
+        self.context
+            .cancel_timer(scope_timer_id(self.ordinal, timer_id))
+    }
+
+    fn report_state_size(&self, state_size: u64) {
+        let mut state_sizes = self.state_sizes.borrow_mut();
+        state_sizes[self.ordinal as usize] = state_size;
+        self.context.report_state_size(state_sizes.iter().sum())
+    }
+
+    fn fill_random(&self, dest: &mut [u8]) {
+        self.context.fill_random(dest)
+    }
+
+    fn send_app_message(&self, destination: &str, message: Bytes) {
+        self.context.send_app_message(destination, message)
+    }
+
+    fn snapshot_schema_version(&self) -> u32 {
+        self.context.snapshot_schema_version()
+    }
+
+    fn metrics(&self) -> Rc<MetricsRegistry> {
+        self.context.metrics()
+    }
+
+    fn cluster_key(&self, context: &[u8]) -> Option<Vec<u8>> {
+        self.context.cluster_key(context)
+    }
+}
+
+fn scope_timer_id(ordinal: u64, timer_id: u64) -> u64 {
+    (ordinal << TIMER_ID_BITS) | (timer_id & TIMER_ID_MASK)
+}
+
+/// Hosts several actors within a single Raft group so that related services
+/// share the replicated log without merging their code. Commands are routed
+/// by the route they carry, events are tagged with the route of the actor
+/// that produced them and the snapshot holds a section for each actor. All
+/// replicas must register the same actors under the same routes.
+///
+/// Each actor sees the shared context, hence the actors share the application
+/// config and the commit watermark. Config updates are applied by every actor,
+/// an update refused by one of the actors remains applied by the actors that
+/// precede it in the order of the routes. Timer ids of each actor are limited
+/// to 56 bits and at most 256 actors can be registered.
+///
+/// Commands with unknown route are answered with the NotFound status. The
+/// snapshot schema version supported by the router is the lowest version
+/// supported by the actors, each actor migrates its own section.
+pub struct ActorRouter {
+    actors: BTreeMap<String, Box<dyn Actor>>,
+    context: Option<Rc<dyn ActorContext>>,
+    // Outcomes that could not be merged into a single outcome, returned one by
+    // one when the actors are given a chance to make progress.
+    deferred_outcomes: VecDeque<CommandOutcome>,
+}
+
+impl ActorRouter {
+    pub fn new() -> Self {
+        ActorRouter {
+            actors: BTreeMap::new(),
+            context: None,
+            deferred_outcomes: VecDeque::new(),
+        }
+    }
+
+    /// Registers the actor to handle the commands with the given route.
+    pub fn with_actor(mut self, route: &str, actor: Box<dyn Actor>) -> Self {
+        self.actors.insert(route.to_string(), actor);
+        self
+    }
+
+    /// Gets routes of the registered actors.
+    pub fn routes(&self) -> Vec<String> {
+        self.actors.keys().cloned().collect()
+    }
+
+    fn logger(&self) -> &Logger {
+        self.context
+            .as_ref()
+            .expect("Context is initialized")
+            .logger()
+    }
+
+    fn get_actor(&mut self, route: &str) -> Result<&mut Box<dyn Actor>, ActorError> {
+        self.actors.get_mut(route).ok_or(ActorError::Internal)
+    }
+
+    fn route_commands(route: &str, commands: &mut [ActorCommand]) {
+        for command in commands {
+            command.route = route.to_string();
+        }
+    }
+
+    fn route_command_outcome(route: &str, outcome: &mut CommandOutcome) {
+        Self::route_commands(route, &mut outcome.commands);
+        // Query is routed to the same actor once it is processed again.
+        if let Some(read) = &mut outcome.read {
+            read.route = route.to_string();
+        }
+        for event in &mut outcome.events {
+            event.contents = RoutedEvent {
+                route: route.to_string(),
+                contents: event.contents.clone(),
+            }
+            .encode_to_vec()
+            .into();
+        }
+    }
+
+    // Merges the outcomes of several actors, parts that cannot be merged are
+    // deferred until the next invocation.
+    fn merge_outcomes(&mut self, outcomes: Vec<CommandOutcome>) -> CommandOutcome {
+        let mut merged = self.deferred_outcomes.pop_front().unwrap_or_default();
+        for mut outcome in outcomes {
+            merged.commands.append(&mut outcome.commands);
+            if outcome.events.is_empty() && outcome.read.is_none() && outcome.pending.is_none() {
+                continue;
+            }
+            if merged.events.is_empty() && merged.read.is_none() && merged.pending.is_none() {
+                merged.events = outcome.events;
+                merged.read = outcome.read;
+                merged.pending = outcome.pending;
+            } else {
+                self.deferred_outcomes.push_back(outcome);
+            }
+        }
+        merged
+    }
+}
+
+impl Default for ActorRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Actor for ActorRouter {
+    fn on_init(&mut self, context: Box<dyn ActorContext>) -> Result<(), ActorError> {
+        let context: Rc<dyn ActorContext> = Rc::from(context);
+        if self.actors.len() > MAX_ACTORS {
+            error!(
+                context.logger(),
+                "Routing {} actors, at most {} are supported",
+                self.actors.len(),
+                MAX_ACTORS
+            );
+            return Err(ActorError::Internal);
+        }
+
+        let state_sizes = Rc::new(RefCell::new(vec![0; self.actors.len()]));
+        for (ordinal, (route, actor)) in self.actors.iter_mut().enumerate() {
+            actor.on_init(Box::new(RoutedContext {
+                context: context.clone(),
+                route: route.clone(),
+                ordinal: ordinal as u64,
+                state_sizes: state_sizes.clone(),
+            }))?;
+        }
+        self.context = Some(context);
+        self.deferred_outcomes.clear();
+
+        Ok(())
+    }
+
+    fn on_shutdown(&mut self) {
+        for actor in self.actors.values_mut() {
+            actor.on_shutdown();
+        }
+    }
+
+    fn on_save_snapshot(&mut self) -> Result<Bytes, ActorError> {
+        let mut snapshot = RoutedSnapshot::default();
+        for (route, actor) in &mut self.actors {
+            snapshot.sections.push(Section {
+                route: route.clone(),
+                contents: actor.on_save_snapshot()?,
+            });
+        }
+
+        Ok(snapshot.encode_to_vec().into())
+    }
+
+    fn on_save_snapshot_chunked(
+        &mut self,
+        writer: &mut dyn SnapshotWriter,
+    ) -> Result<(), ActorError> {
+        // Sections are written out one by one, each encoded as an element of the
+        // repeated sections field, hence only one of them is held at a time.
+        for (route, actor) in &mut self.actors {
+            let mut section_buffer = SnapshotBuffer::new();
+            actor.on_save_snapshot_chunked(&mut section_buffer)?;
+            let section = Section {
+                route: route.clone(),
+                contents: section_buffer.into_bytes(),
+            };
+            let mut encoded_section = Vec::new();
+            prost::encoding::message::encode(1, &section, &mut encoded_section);
+            writer.write(&encoded_section)?;
+        }
+
+        Ok(())
+    }
+
+    fn on_load_snapshot(&mut self, snapshot: Bytes) -> Result<(), ActorError> {
+        let snapshot = RoutedSnapshot::decode(snapshot).map_err(|_| ActorError::SnapshotLoading)?;
+        if snapshot.sections.len() != self.actors.len() {
+            return Err(ActorError::SnapshotLoading);
+        }
+
+        // Snapshot is saved in the version the cluster has agreed upon, which may
+        // be lower than the version supported by some of the actors.
+        let schema_version = self
+            .context
+            .as_ref()
+            .map_or(0, |context| context.snapshot_schema_version());
+        for section in snapshot.sections {
+            let actor = self
+                .actors
+                .get_mut(&section.route)
+                .ok_or(ActorError::SnapshotLoading)?;
+            let mut contents = section.contents;
+            if schema_version < actor.snapshot_schema_version() {
+                contents = actor.on_migrate_snapshot(schema_version, contents)?;
+            }
+            if actor.loads_snapshot_in_chunks() {
+                for chunk in split_chunks(&contents, SECTION_CHUNK_SIZE) {
+                    actor.on_load_snapshot_chunk(chunk)?;
+                }
+                actor.on_load_snapshot_complete()?;
+            } else {
+                actor.on_load_snapshot(contents)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn snapshot_schema_version(&self) -> u32 {
+        self.actors
+            .values()
+            .map(|actor| actor.snapshot_schema_version())
+            .min()
+            .unwrap_or(0)
+    }
+
+    fn on_migrate_snapshot(
+        &mut self,
+        _old_version: u32,
+        snapshot: Bytes,
+    ) -> Result<Bytes, ActorError> {
+        // Sections are migrated by the actors while loading, as the actors may
+        // support different versions.
+        Ok(snapshot)
+    }
+
+    fn on_apply_config(&mut self, config: Bytes) -> Result<(), ActorError> {
+        // Config is shared, hence every actor applies the update.
+        for actor in self.actors.values_mut() {
+            actor.on_apply_config(config.clone())?;
+        }
+
+        Ok(())
+    }
+
+    fn on_process_command(
+        &mut self,
+        command: Option<ActorCommand>,
+    ) -> Result<CommandOutcome, ActorError> {
+        let Some(command) = command else {
+            // Every actor is given a chance to make progress.
+            let mut outcomes = Vec::with_capacity(self.actors.len());
+            for (route, actor) in &mut self.actors {
+                let mut outcome = actor.on_process_command(None)?;
+                Self::route_command_outcome(route, &mut outcome);
+                outcomes.push(outcome);
+            }
+            return Ok(self.merge_outcomes(outcomes));
+        };
+
+        let route = command.route.clone();
+        let Some(actor) = self.actors.get_mut(&route) else {
+            warn!(
+                self.logger(),
+                "Rejecting #{} command: unknown route {}", command.correlation_id, route
+            );
+            return Ok(CommandOutcome::with_command(ActorCommand {
+                correlation_id: command.correlation_id,
+                header: Bytes::new(),
+                payload: Bytes::new(),
+                route,
+                status_code: StatusCode::NotFound as i32,
+            }));
+        };
+
+        let mut outcome = actor.on_process_command(Some(command))?;
+        Self::route_command_outcome(&route, &mut outcome);
+
+        Ok(outcome)
+    }
+
+    fn on_apply_event(
+        &mut self,
+        context: ActorEventContext,
+        event: ActorEvent,
+    ) -> Result<EventOutcome, ActorError> {
+        let routed_event = RoutedEvent::decode(event.contents).map_err(|_| ActorError::Internal)?;
+
+        let mut outcome = self.get_actor(&routed_event.route)?.on_apply_event(
+            context,
+            ActorEvent::with_bytes(event.correlation_id, routed_event.contents),
+        )?;
+        Self::route_commands(&routed_event.route, &mut outcome.commands);
+
+        Ok(outcome)
+    }
+
+    fn on_commit_watermark(&mut self, committed_index: u64) -> Result<EventOutcome, ActorError> {
+        // Watermark subscriptions are shared, hence every actor is notified.
+        let mut commands = Vec::new();
+        for (route, actor) in &mut self.actors {
+            let mut outcome = actor.on_commit_watermark(committed_index)?;
+            Self::route_commands(route, &mut outcome.commands);
+            commands.append(&mut outcome.commands);
+        }
+
+        Ok(EventOutcome::with_commands(commands))
+    }
+
+    fn on_timer(
+        &mut self,
+        context: ActorEventContext,
+        timer_id: u64,
+    ) -> Result<EventOutcome, ActorError> {
+        let ordinal = (timer_id >> TIMER_ID_BITS) as usize;
+        let Some((route, actor)) = self.actors.iter_mut().nth(ordinal) else {
+            return Err(ActorError::Internal);
+        };
+
+        let mut outcome = actor.on_timer(context, timer_id & TIMER_ID_MASK)?;
+        Self::route_commands(route, &mut outcome.commands);
+
+        Ok(outcome)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::logger::log::create_logger;
+    use crate::mock::{MockActor, MockActorContext};
+    use crate::router::*;
+    use alloc::format;
+    use alloc::vec;
+    use mockall::mock;
+    use mockall::predicate::eq;
+
+    mock! {
+        ChunkedActor {}
+
+        impl Actor for ChunkedActor {
+            fn on_init(&mut self, context: Box<dyn ActorContext>) -> Result<(), ActorError>;
+
+            fn on_shutdown(&mut self);
+
+            fn on_save_snapshot(&mut self) -> Result<Bytes, ActorError>;
+
+            fn on_load_snapshot(&mut self, snapshot: Bytes) -> Result<(), ActorError>;
+
+            fn loads_snapshot_in_chunks(&self) -> bool;
+
+            fn on_load_snapshot_chunk(&mut self, chunk: Bytes) -> Result<(), ActorError>;
+
+            fn on_load_snapshot_complete(&mut self) -> Result<(), ActorError>;
+
+            fn snapshot_schema_version(&self) -> u32;
+
+            fn on_migrate_snapshot(&mut self, old_version: u32, snapshot: Bytes) -> Result<Bytes, ActorError>;
+
+            fn on_process_command(&mut self, command: Option<ActorCommand>) -> Result<CommandOutcome, ActorError>;
+
+            fn on_apply_event(&mut self, context: ActorEventContext, event: ActorEvent) -> Result<EventOutcome, ActorError>;
+        }
+    }
+
+    const LEDGER: &str = "ledger";
+    const AUDIT: &str = "audit";
+
+    fn create_command(route: &str, correlation_id: u64) -> ActorCommand {
+        ActorCommand {
+            correlation_id,
+            header: Bytes::from(vec![correlation_id as u8]),
+            payload: Bytes::new(),
+            route: route.to_string(),
+            status_code: 0,
+        }
+    }
+
+    fn create_router(ledger_actor: MockActor, audit_actor: MockActor) -> ActorRouter {
+        let mut router = ActorRouter::new()
+            .with_actor(LEDGER, Box::new(ledger_actor))
+            .with_actor(AUDIT, Box::new(audit_actor));
+        let mut context = MockActorContext::new();
+        context.expect_logger().return_const(create_logger());
+        context.expect_snapshot_schema_version().return_const(0u32);
+        router.on_init(Box::new(context)).unwrap();
+        router
+    }
+
+    fn create_actor() -> MockActor {
+        let mut actor = MockActor::new();
+        actor.expect_on_init().returning(|_| Ok(()));
+        actor
+    }
+
+    #[test]
+    fn test_actor_router_commands_and_events() {
+        let mut ledger_actor = create_actor();
+        ledger_actor
+            .expect_on_process_command()
+            .with(eq(Some(create_command(LEDGER, 1))))
+            .return_once(|_| {
+                Ok(CommandOutcome::with_event(ActorEvent::with_bytes(
+                    1,
+                    Bytes::from(vec![1]),
+                )))
+            });
+        ledger_actor
+            .expect_on_apply_event()
+            .with(
+                eq(ActorEventContext {
+                    index: 5,
+                    owned: true,
+                }),
+                eq(ActorEvent::with_bytes(1, Bytes::from(vec![1]))),
+            )
+            .return_once(|_, _| Ok(EventOutcome::with_command(create_command("", 1))));
+        let audit_actor = create_actor();
+        let mut router = create_router(ledger_actor, audit_actor);
+        assert_eq!(router.routes(), vec![AUDIT.to_string(), LEDGER.to_string()]);
+
+        // Event is tagged with the route of the actor that produced it.
+        let outcome = router
+            .on_process_command(Some(create_command(LEDGER, 1)))
+            .unwrap();
+        let event = outcome.events[0].clone();
+        assert_eq!(
+            RoutedEvent::decode(event.contents.clone()).unwrap(),
+            RoutedEvent {
+                route: LEDGER.to_string(),
+                contents: Bytes::from(vec![1]),
+            }
+        );
+
+        let outcome = router
+            .on_apply_event(
+                ActorEventContext {
+                    index: 5,
+                    owned: true,
+                },
+                event,
+            )
+            .unwrap();
+        assert_eq!(outcome.commands, vec![create_command(LEDGER, 1)]);
+
+        // Commands with unknown route are rejected.
+        assert_eq!(
+            router.on_process_command(Some(create_command("unknown", 2))),
+            Ok(CommandOutcome::with_command(ActorCommand {
+                correlation_id: 2,
+                header: Bytes::new(),
+                payload: Bytes::new(),
+                route: "unknown".to_string(),
+                status_code: StatusCode::NotFound as i32,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_actor_router_max_actors() {
+        let mut router = ActorRouter::new();
+        for ordinal in 0..=MAX_ACTORS {
+            router = router.with_actor(&format!("{:03}", ordinal), Box::new(MockActor::new()));
+        }
+        let mut context = MockActorContext::new();
+        context.expect_logger().return_const(create_logger());
+
+        // Timer ids of the last actor would not fit into the timer id.
+        assert_eq!(router.on_init(Box::new(context)), Err(ActorError::Internal));
+    }
+
+    #[test]
+    fn test_actor_router_apply_config() {
+        let mut ledger_actor = create_actor();
+        ledger_actor
+            .expect_on_apply_config()
+            .with(eq(Bytes::from(vec![1])))
+            .return_once(|_| Ok(()));
+        let mut audit_actor = create_actor();
+        audit_actor
+            .expect_on_apply_config()
+            .with(eq(Bytes::from(vec![1])))
+            .return_once(|_| Ok(()));
+        ledger_actor
+            .expect_on_apply_config()
+            .with(eq(Bytes::from(vec![2])))
+            .return_once(|_| Ok(()));
+        audit_actor
+            .expect_on_apply_config()
+            .with(eq(Bytes::from(vec![2])))
+            .return_once(|_| Err(ActorError::ConfigLoading));
+        let mut router = create_router(ledger_actor, audit_actor);
+
+        assert_eq!(router.on_apply_config(Bytes::from(vec![1])), Ok(()));
+        assert_eq!(
+            router.on_apply_config(Bytes::from(vec![2])),
+            Err(ActorError::ConfigLoading)
+        );
+    }
+
+    #[test]
+    fn test_actor_router_progress() {
+        let mut ledger_actor = create_actor();
+        ledger_actor
+            .expect_on_process_command()
+            .with(eq(None))
+            .returning(|_| {
+                Ok(CommandOutcome::with_event(ActorEvent::with_bytes(
+                    2,
+                    Bytes::new(),
+                )))
+            });
+        let mut audit_actor = create_actor();
+        audit_actor
+            .expect_on_process_command()
+            .with(eq(None))
+            .returning(|_| Ok(CommandOutcome::with_read(create_command("", 3))));
+        let mut router = create_router(ledger_actor, audit_actor);
+
+        // Outcomes that cannot be merged are returned in the next invocation.
+        let outcome = router.on_process_command(None).unwrap();
+        assert_eq!(outcome.read, Some(create_command(AUDIT, 3)));
+        assert!(outcome.events.is_empty());
+        let outcome = router.on_process_command(None).unwrap();
+        assert_eq!(outcome.events[0].correlation_id, 2);
+    }
+
+    #[test]
+    fn test_actor_router_snapshot() {
+        let mut ledger_actor = create_actor();
+        ledger_actor
+            .expect_on_save_snapshot()
+            .return_once(|| Ok(Bytes::from(vec![1])));
+        ledger_actor
+            .expect_loads_snapshot_in_chunks()
+            .return_const(false);
+        ledger_actor
+            .expect_on_load_snapshot()
+            .with(eq(Bytes::from(vec![1])))
+            .return_once(|_| Ok(()));
+        let mut audit_actor = create_actor();
+        audit_actor
+            .expect_on_save_snapshot()
+            .return_once(|| Ok(Bytes::from(vec![2])));
+        audit_actor
+            .expect_loads_snapshot_in_chunks()
+            .return_const(false);
+        audit_actor
+            .expect_on_load_snapshot()
+            .with(eq(Bytes::from(vec![2])))
+            .return_once(|_| Ok(()));
+        let mut router = create_router(ledger_actor, audit_actor);
+
+        let snapshot = router.on_save_snapshot().unwrap();
+        assert_eq!(
+            RoutedSnapshot::decode(snapshot.clone())
+                .unwrap()
+                .sections
+                .len(),
+            2
+        );
+        assert_eq!(router.on_load_snapshot(snapshot), Ok(()));
+
+        // Snapshot must have a section for each of the actors.
+        assert_eq!(
+            router.on_load_snapshot(RoutedSnapshot::default().encode_to_vec().into()),
+            Err(ActorError::SnapshotLoading)
+        );
+    }
+
+    #[test]
+    fn test_actor_router_snapshot_chunked() {
+        let mut ledger_actor = create_actor();
+        ledger_actor
+            .expect_on_save_snapshot()
+            .times(2)
+            .returning(|| Ok(Bytes::from(vec![1])));
+        let mut audit_actor = create_actor();
+        audit_actor
+            .expect_on_save_snapshot()
+            .times(2)
+            .returning(|| Ok(Bytes::from(vec![2; 3])));
+        let mut router = create_router(ledger_actor, audit_actor);
+
+        // Sections written out one by one decode the same as the whole snapshot.
+        let mut buffer = SnapshotBuffer::new();
+        assert_eq!(router.on_save_snapshot_chunked(&mut buffer), Ok(()));
+        assert_eq!(
+            RoutedSnapshot::decode(buffer.into_bytes()).unwrap(),
+            RoutedSnapshot::decode(router.on_save_snapshot().unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_actor_router_snapshot_migration() {
+        let mut ledger_actor = MockChunkedActor::new();
+        ledger_actor.expect_on_init().returning(|_| Ok(()));
+        ledger_actor
+            .expect_snapshot_schema_version()
+            .return_const(2u32);
+        ledger_actor
+            .expect_on_migrate_snapshot()
+            .with(eq(1), eq(Bytes::from(vec![1])))
+            .return_once(|_, _| Ok(Bytes::from(vec![1, 1])));
+        ledger_actor
+            .expect_loads_snapshot_in_chunks()
+            .return_const(true);
+        ledger_actor
+            .expect_on_load_snapshot_chunk()
+            .with(eq(Bytes::from(vec![1, 1])))
+            .return_once(|_| Ok(()));
+        ledger_actor
+            .expect_on_load_snapshot_complete()
+            .return_once(|| Ok(()));
+        let mut audit_actor = MockChunkedActor::new();
+        audit_actor.expect_on_init().returning(|_| Ok(()));
+        audit_actor
+            .expect_snapshot_schema_version()
+            .return_const(1u32);
+        audit_actor
+            .expect_loads_snapshot_in_chunks()
+            .return_const(false);
+        audit_actor
+            .expect_on_load_snapshot()
+            .with(eq(Bytes::from(vec![2])))
+            .return_once(|_| Ok(()));
+        let mut router = ActorRouter::new()
+            .with_actor(LEDGER, Box::new(ledger_actor))
+            .with_actor(AUDIT, Box::new(audit_actor));
+        let mut context = MockActorContext::new();
+        context.expect_logger().return_const(create_logger());
+        context.expect_snapshot_schema_version().return_const(1u32);
+        router.on_init(Box::new(context)).unwrap();
+
+        // Router supports the version all of the actors support.
+        assert_eq!(router.snapshot_schema_version(), 1);
+        assert_eq!(
+            router.on_migrate_snapshot(0, Bytes::from(vec![3])),
+            Ok(Bytes::from(vec![3]))
+        );
+
+        // Only the sections older than the version of the actor are migrated.
+        let snapshot = RoutedSnapshot {
+            sections: vec![
+                Section {
+                    route: AUDIT.to_string(),
+                    contents: Bytes::from(vec![2]),
+                },
+                Section {
+                    route: LEDGER.to_string(),
+                    contents: Bytes::from(vec![1]),
+                },
+            ],
+        };
+        assert_eq!(
+            router.on_load_snapshot(snapshot.encode_to_vec().into()),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,441 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::encryptor::Encryptor;
+use crate::platform::{PalError, SealedStorage};
+use aes_gcm_siv::{
+    aead::{Aead, KeyInit, Payload as AeadPayload},
+    Aes256GcmSiv, Nonce,
+};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use anyhow::{anyhow, Result};
+use core::cell::RefCell;
+use core::mem;
+use hkdf::Hkdf;
+use rand::{rngs::OsRng, RngCore};
+use sha2::Sha256;
+use tcp_proto::runtime::endpoint::SealedSecret;
+
+// Context the sealing key is derived with from the key of the platform.
+const SEALING_CONTEXT: &[u8] = b"TCP sealing";
+
+// Size of the security version and the nonce prepended to the sealed contents.
+const SECURITY_VERSION_SIZE: usize = 4;
+const NONCE_SIZE: usize = 12;
+
+// Size of the keys derived from the key of the platform.
+const KEY_SIZE: usize = 32;
+
+/// Derives keys bound to the identity of the enclave from the platform, e.g.
+/// the keys the AMD secure processor derives from the measurement of the guest
+/// and the given guest security version number through the guest message
+/// interface. Only an enclave with the same identity derives the same keys.
+pub trait SealingKeyDeriver {
+    /// Derives the key for the given security version. The platform refuses to
+    /// derive the keys for the versions above the version of the running
+    /// enclave.
+    fn derive_key(&self, security_version: u32) -> Result<Vec<u8>>;
+}
+
+/// Implementation of `Encryptor` that seals the contents with AES-256-GCM-SIV
+/// under the key derived from the platform for the security version of the
+/// enclave. The sealed contents carry the security version, so that the
+/// enclave unseals the contents sealed by its previous versions while the
+/// contents sealed by a newer version are rejected, i.e. rolling back the
+/// enclave to a version with known vulnerabilities doesn't expose the secrets
+/// sealed since.
+pub struct SealingEncryptor {
+    deriver: Box<dyn SealingKeyDeriver>,
+    security_version: u32,
+}
+
+impl SealingEncryptor {
+    pub fn new(deriver: Box<dyn SealingKeyDeriver>, security_version: u32) -> SealingEncryptor {
+        SealingEncryptor {
+            deriver,
+            security_version,
+        }
+    }
+
+    fn derive_key(&self, security_version: u32, context: &[u8]) -> Result<Vec<u8>> {
+        let platform_key = self.deriver.derive_key(security_version)?;
+        let mut key = vec![0; KEY_SIZE];
+        Hkdf::<Sha256>::new(None, &platform_key)
+            .expand(context, &mut key)
+            .map_err(|e| anyhow!("Failed to derive sealing key: {}", e))?;
+        Ok(key)
+    }
+}
+
+impl Encryptor for SealingEncryptor {
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let security_version = self.security_version.to_le_bytes();
+        let mut nonce = [0; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext =
+            Aes256GcmSiv::new_from_slice(&self.derive_key(self.security_version, SEALING_CONTEXT)?)
+                .map_err(|e| anyhow!("Invalid sealing key: {}", e))?
+                .encrypt(
+                    Nonce::from_slice(&nonce),
+                    AeadPayload {
+                        msg: plaintext,
+                        aad: &security_version,
+                    },
+                )
+                .map_err(|e| anyhow!("Failed to seal: {}", e))?;
+
+        let mut sealed = Vec::with_capacity(SECURITY_VERSION_SIZE + NONCE_SIZE + ciphertext.len());
+        sealed.extend_from_slice(&security_version);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let (security_version, rest) = ciphertext
+            .split_first_chunk::<SECURITY_VERSION_SIZE>()
+            .ok_or_else(|| anyhow!("Sealed contents are too short"))?;
+        if rest.len() < NONCE_SIZE {
+            return Err(anyhow!("Sealed contents are too short"));
+        }
+        let (nonce, ciphertext) = rest.split_at(NONCE_SIZE);
+        let version = u32::from_le_bytes(*security_version);
+        if version > self.security_version {
+            return Err(anyhow!(
+                "Contents sealed by security version {} above {}",
+                version,
+                self.security_version
+            ));
+        }
+
+        Aes256GcmSiv::new_from_slice(&self.derive_key(version, SEALING_CONTEXT)?)
+            .map_err(|e| anyhow!("Invalid sealing key: {}", e))?
+            .decrypt(
+                Nonce::from_slice(nonce),
+                AeadPayload {
+                    msg: ciphertext,
+                    aad: security_version,
+                },
+            )
+            .map_err(|e| anyhow!("Failed to unseal: {}", e))
+    }
+
+    // Exports secret bound to the identity and the security version of the
+    // enclave, e.g. to authenticate the state the enclave persists through the
+    // host.
+    fn export_secret(&self, context: &[u8]) -> Result<Vec<u8>> {
+        let mut info = Vec::with_capacity(SEALING_CONTEXT.len() + context.len());
+        info.extend_from_slice(SEALING_CONTEXT);
+        info.extend_from_slice(context);
+        self.derive_key(self.security_version, &info)
+    }
+
+    fn rekey(&mut self) -> Result<()> {
+        Err(anyhow!("Sealing key cannot be replaced"))
+    }
+}
+
+/// Implementation of `SealedStorage` that passes the sealed secrets to the
+/// untrusted launcher through the driver. The secrets are sealed with the given
+/// encryptor, which must encrypt with a key derived from the identity of the
+/// enclave, see [SealingEncryptor].
+///
+/// Each secret is sealed along with a counter incremented every time the secret
+/// is replaced. When the untrusted launcher passes several versions of the same
+/// secret after the restart, e.g. because it failed to remove the replaced one,
+/// the version with the highest counter is loaded.
+///
+/// The same storage must be shared with the actors and given to the driver, see
+/// [crate::driver::Driver::with_sealed_storage].
+pub struct HostSealedStorage {
+    sealer: Box<dyn Encryptor>,
+    secrets: RefCell<BTreeMap<String, (u64, Vec<u8>)>>,
+    // Sealed secrets that are yet to be passed to the untrusted launcher.
+    pending: RefCell<Vec<SealedSecret>>,
+}
+
+impl HostSealedStorage {
+    pub fn new(sealer: Box<dyn Encryptor>) -> HostSealedStorage {
+        HostSealedStorage {
+            sealer,
+            secrets: RefCell::new(BTreeMap::new()),
+            pending: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Unseals the secrets persisted before the restart, fails if any of them
+    /// cannot be unsealed, e.g. because it has been sealed by an enclave with a
+    /// different identity or a newer security version or tampered with, or if
+    /// different secrets have been sealed under the same name and counter.
+    pub fn load(&self, sealed_secrets: &[SealedSecret]) -> Result<(), PalError> {
+        let mut secrets: BTreeMap<String, (u64, Vec<u8>)> = BTreeMap::new();
+        for sealed_secret in sealed_secrets {
+            let contents = self
+                .sealer
+                .decrypt(&sealed_secret.sealed_contents)
+                .map_err(|_| PalError::Internal)?;
+            // Sealed contents carry the name so that the secrets persisted under
+            // different names cannot be swapped.
+            let (counter, secret) =
+                split_contents(&contents, &sealed_secret.name).ok_or(PalError::Internal)?;
+            match secrets.get(&sealed_secret.name) {
+                Some((loaded_counter, _)) if *loaded_counter > counter => {}
+                Some((loaded_counter, loaded_secret))
+                    if *loaded_counter == counter && loaded_secret != secret =>
+                {
+                    return Err(PalError::Internal);
+                }
+                _ => {
+                    secrets.insert(sealed_secret.name.clone(), (counter, secret.to_vec()));
+                }
+            }
+        }
+
+        *self.secrets.borrow_mut() = secrets;
+        Ok(())
+    }
+
+    /// Gets the number of times the secret stored under the given name has been
+    /// replaced, zero if no secret has been stored.
+    pub fn counter(&self, name: &str) -> u64 {
+        self.secrets
+            .borrow()
+            .get(name)
+            .map_or(0, |(counter, _)| *counter)
+    }
+
+    /// Takes the sealed secrets the untrusted launcher must persist.
+    pub fn take_sealed(&self) -> Vec<SealedSecret> {
+        mem::take(&mut self.pending.borrow_mut())
+    }
+}
+
+impl SealedStorage for HostSealedStorage {
+    fn get(&self, name: &str) -> Option<Vec<u8>> {
+        self.secrets
+            .borrow()
+            .get(name)
+            .map(|(_, secret)| secret.clone())
+    }
+
+    fn put(&self, name: &str, secret: &[u8]) -> Result<(), PalError> {
+        let counter = self.counter(name) + 1;
+        let sealed_contents = self
+            .sealer
+            .encrypt(&join_contents(name, counter, secret))
+            .map_err(|_| PalError::Internal)?;
+
+        self.secrets
+            .borrow_mut()
+            .insert(name.to_string(), (counter, secret.to_vec()));
+        self.pending.borrow_mut().push(SealedSecret {
+            name: name.to_string(),
+            sealed_contents,
+        });
+        Ok(())
+    }
+}
+
+// Prefixes the secret with its name, the length of the name and the counter.
+fn join_contents(name: &str, counter: u64, secret: &[u8]) -> Vec<u8> {
+    let mut contents = Vec::with_capacity(12 + name.len() + secret.len());
+    contents.extend_from_slice(&(name.len() as u32).to_le_bytes());
+    contents.extend_from_slice(name.as_bytes());
+    contents.extend_from_slice(&counter.to_le_bytes());
+    contents.extend_from_slice(secret);
+    contents
+}
+
+// Splits the counter and the secret from the contents if they are prefixed
+// with the name.
+fn split_contents<'a>(contents: &'a [u8], name: &str) -> Option<(u64, &'a [u8])> {
+    let (length, rest) = contents.split_first_chunk::<4>()?;
+    let length = u32::from_le_bytes(*length) as usize;
+    if rest.len() < length || &rest[..length] != name.as_bytes() {
+        return None;
+    }
+    let (counter, secret) = rest[length..].split_first_chunk::<8>()?;
+    Some((u64::from_le_bytes(*counter), secret))
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::sealed::*;
+    use alloc::vec;
+
+    // Reversible transformation standing in for the sealing.
+    struct FakeSealer {
+        key: u8,
+    }
+
+    impl Encryptor for FakeSealer {
+        fn encrypt(&self, plaintext: &[u8]) -> anyhow::Result<Vec<u8>> {
+            Ok(plaintext.iter().map(|byte| byte ^ self.key).collect())
+        }
+
+        fn decrypt(&self, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
+            self.encrypt(ciphertext)
+        }
+
+        fn export_secret(&self, context: &[u8]) -> anyhow::Result<Vec<u8>> {
+            self.encrypt(context)
+        }
+
+        fn rekey(&mut self) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Derives the keys from the identity, refuses to derive the keys for the
+    // versions above the given one.
+    struct FakeKeyDeriver {
+        identity: u8,
+        security_version: u32,
+    }
+
+    impl SealingKeyDeriver for FakeKeyDeriver {
+        fn derive_key(&self, security_version: u32) -> anyhow::Result<Vec<u8>> {
+            if security_version > self.security_version {
+                return Err(anyhow!("Security version is above the current one"));
+            }
+            let mut key = vec![self.identity; 32];
+            key[..4].copy_from_slice(&security_version.to_le_bytes());
+            Ok(key)
+        }
+    }
+
+    fn create_sealing_encryptor(identity: u8, security_version: u32) -> SealingEncryptor {
+        SealingEncryptor::new(
+            Box::new(FakeKeyDeriver {
+                identity,
+                security_version,
+            }),
+            security_version,
+        )
+    }
+
+    #[test]
+    fn test_sealing_encryptor() {
+        let encryptor = create_sealing_encryptor(1, 2);
+        let sealed = encryptor.encrypt(b"secret").unwrap();
+        assert!(!sealed.windows(6).any(|window| window == b"secret"));
+        assert_eq!(encryptor.decrypt(&sealed).unwrap(), b"secret".to_vec());
+
+        // Enclave with a different identity cannot unseal.
+        assert!(create_sealing_encryptor(2, 2).decrypt(&sealed).is_err());
+
+        // Newer version unseals the contents sealed by the previous versions,
+        // older version cannot unseal the contents sealed by the newer ones.
+        assert_eq!(
+            create_sealing_encryptor(1, 3).decrypt(&sealed).unwrap(),
+            b"secret".to_vec()
+        );
+        assert!(create_sealing_encryptor(1, 1).decrypt(&sealed).is_err());
+
+        // Security version cannot be altered.
+        let mut downgraded = sealed.clone();
+        downgraded[..SECURITY_VERSION_SIZE].copy_from_slice(&1u32.to_le_bytes());
+        assert!(encryptor.decrypt(&downgraded).is_err());
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(encryptor.decrypt(&tampered).is_err());
+        assert!(encryptor.decrypt(&sealed[..SECURITY_VERSION_SIZE]).is_err());
+
+        // Exported secrets are bound to the identity and the context.
+        let secret = encryptor.export_secret(b"context").unwrap();
+        assert_eq!(secret, encryptor.export_secret(b"context").unwrap());
+        assert_ne!(secret, encryptor.export_secret(b"other").unwrap());
+        assert_ne!(
+            secret,
+            create_sealing_encryptor(2, 2)
+                .export_secret(b"context")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sealed_storage() {
+        let storage = HostSealedStorage::new(Box::new(FakeSealer { key: 7 }));
+        assert_eq!(storage.get("signing_key"), None);
+
+        storage.put("signing_key", &[1, 2, 3]).unwrap();
+        storage.put("encryption_key", &[4, 5]).unwrap();
+        assert_eq!(storage.get("signing_key"), Some(vec![1, 2, 3]));
+
+        // Secrets are sealed before they are passed to the launcher.
+        let sealed_secrets = storage.take_sealed();
+        assert_eq!(sealed_secrets.len(), 2);
+        assert_eq!(sealed_secrets[0].name, "signing_key");
+        assert_ne!(
+            sealed_secrets[0].sealed_contents,
+            join_contents("signing_key", 1, &[1, 2, 3])
+        );
+        assert!(storage.take_sealed().is_empty());
+
+        // Secrets are unsealed after the restart.
+        let restarted = HostSealedStorage::new(Box::new(FakeSealer { key: 7 }));
+        restarted.load(&sealed_secrets).unwrap();
+        assert_eq!(restarted.get("signing_key"), Some(vec![1, 2, 3]));
+        assert_eq!(restarted.get("encryption_key"), Some(vec![4, 5]));
+
+        // Secrets sealed by a different enclave or swapped cannot be unsealed.
+        let other = HostSealedStorage::new(Box::new(FakeSealer { key: 8 }));
+        assert_eq!(other.load(&sealed_secrets), Err(PalError::Internal));
+        let swapped = vec![SealedSecret {
+            name: "encryption_key".to_string(),
+            sealed_contents: sealed_secrets[0].sealed_contents.clone(),
+        }];
+        assert_eq!(restarted.load(&swapped), Err(PalError::Internal));
+    }
+
+    #[test]
+    fn test_sealed_storage_counter() {
+        let storage = HostSealedStorage::new(Box::new(create_sealing_encryptor(1, 1)));
+        storage.put("signing_key", &[1]).unwrap();
+        let sealed_1 = storage.take_sealed();
+        storage.put("signing_key", &[2]).unwrap();
+        let sealed_2 = storage.take_sealed();
+        assert_eq!(storage.counter("signing_key"), 2);
+        assert_eq!(storage.counter("encryption_key"), 0);
+
+        // The latest version of the secret is loaded regardless of the order.
+        let restarted = HostSealedStorage::new(Box::new(create_sealing_encryptor(1, 1)));
+        restarted
+            .load(&[sealed_2[0].clone(), sealed_1[0].clone()])
+            .unwrap();
+        assert_eq!(restarted.get("signing_key"), Some(vec![2]));
+        assert_eq!(restarted.counter("signing_key"), 2);
+        restarted
+            .load(&[sealed_1[0].clone(), sealed_2[0].clone()])
+            .unwrap();
+        assert_eq!(restarted.get("signing_key"), Some(vec![2]));
+
+        // Replacing the secret after the restart continues the counter.
+        restarted.put("signing_key", &[3]).unwrap();
+        assert_eq!(restarted.take_sealed().len(), 1);
+        assert_eq!(restarted.counter("signing_key"), 3);
+
+        // Different secrets sealed with the same counter are rejected.
+        let other = HostSealedStorage::new(Box::new(create_sealing_encryptor(1, 1)));
+        other.put("signing_key", &[4]).unwrap();
+        assert_eq!(
+            restarted.load(&[sealed_1[0].clone(), other.take_sealed()[0].clone()]),
+            Err(PalError::Internal)
+        );
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use anyhow::{anyhow, Result};
+use hkdf::Hkdf;
+use rand::{rngs::OsRng, RngCore};
+use sha2::Sha256;
+
+/// Name the cluster secret is kept under in the sealed storage of the replica.
+pub const CLUSTER_SECRET_NAME: &str = "tcp.cluster_secret";
+
+// Size of the cluster secret and of the keys derived from it.
+const SECRET_SIZE: usize = 32;
+
+// Prefix of the info the keys are derived with, separates them from the other
+// uses of the secret.
+const CLUSTER_KEY_CONTEXT: &[u8] = b"TCP cluster key";
+
+/// Secret shared by the replicas of the cluster over the attested channels
+/// only, hence neither the untrusted launcher nor the enclaves outside of the
+/// cluster learn it. The actor derives the keys protecting the secrets it
+/// replicates from it, see [crate::model::ActorContext::cluster_key].
+#[derive(Clone, PartialEq)]
+pub struct ClusterSecret {
+    secret: Vec<u8>,
+}
+
+impl ClusterSecret {
+    /// Generates the secret of a new cluster.
+    pub fn generate() -> ClusterSecret {
+        let mut secret = vec![0; SECRET_SIZE];
+        OsRng.fill_bytes(&mut secret);
+        ClusterSecret { secret }
+    }
+
+    /// Restores the secret received from a peer or unsealed after restart.
+    pub fn from_bytes(secret: &[u8]) -> Result<ClusterSecret> {
+        if secret.len() != SECRET_SIZE {
+            return Err(anyhow!(
+                "Cluster secret must be {} bytes but is {}",
+                SECRET_SIZE,
+                secret.len()
+            ));
+        }
+        Ok(ClusterSecret {
+            secret: secret.to_vec(),
+        })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.secret
+    }
+
+    /// Derives the key for the given context, all replicas derive the same key
+    /// for the same context.
+    pub fn derive_key(&self, context: &[u8]) -> Vec<u8> {
+        let mut info = CLUSTER_KEY_CONTEXT.to_vec();
+        info.extend_from_slice(context);
+        let mut key = vec![0; SECRET_SIZE];
+        Hkdf::<Sha256>::new(None, &self.secret)
+            .expand(&info, &mut key)
+            .expect("Key size is valid");
+        key
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::secret::*;
+
+    #[test]
+    fn test_derive_key() {
+        let secret = ClusterSecret::generate();
+        let restored = ClusterSecret::from_bytes(secret.as_bytes()).unwrap();
+
+        assert_eq!(secret.derive_key(b"a"), restored.derive_key(b"a"));
+        assert_ne!(secret.derive_key(b"a"), secret.derive_key(b"b"));
+        assert_ne!(
+            secret.derive_key(b"a"),
+            ClusterSecret::generate().derive_key(b"a")
+        );
+    }
+
+    #[test]
+    fn test_from_bytes_invalid_size() {
+        assert!(ClusterSecret::from_bytes(&[1, 2, 3]).is_err());
+    }
+}
@@ -0,0 +1,67 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::Actor;
+use crate::service::ApplicationService;
+use alloc::boxed::Box;
+use oak_restricted_kernel_sdk::{
+    channel::{start_blocking_server, FileDescriptorChannel},
+    utils::{log, samplestore::StaticSampleStore},
+};
+use tcp_proto::runtime::endpoint::EndpointServiceServer;
+
+/// Number of invocation samples kept by the server.
+const INVOCATION_SAMPLE_COUNT: usize = 1000;
+
+/// Exposes the endpoint service of the given application over the micro_rpc
+/// transport of the restricted kernel communication channel. Requests from the
+/// untrusted launcher are dispatched to the driver until the enclave is
+/// terminated, hence this function never returns.
+pub fn run_blocking_server<A: Actor>(service: ApplicationService<A>) -> ! {
+    // Only log warnings and errors to reduce the risk of accidentally leaking execution
+    // information through debug logs.
+    log::set_max_level(log::LevelFilter::Warn);
+
+    let mut invocation_stats = StaticSampleStore::<INVOCATION_SAMPLE_COUNT>::new().unwrap();
+    let server = EndpointServiceServer::new(service);
+    start_blocking_server(
+        Box::<FileDescriptorChannel>::default(),
+        server,
+        &mut invocation_stats,
+    )
+    .expect("Server encountered an unrecoverable error");
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::mock::MockActor;
+    use crate::server::*;
+    use tcp_proto::runtime::endpoint::{EndpointServiceClient, ReceiveMessageRequest};
+
+    #[test]
+    fn test_server_dispatches_to_service() {
+        // Server run by `run_blocking_server` decodes the requests of the launcher
+        // and dispatches them to the application service.
+        let server = EndpointServiceServer::new(ApplicationService::new(MockActor::new()));
+        let mut client = EndpointServiceClient::new(server);
+
+        let response = client
+            .receive_message(&ReceiveMessageRequest {
+                instant: 10,
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(response.is_ok());
+    }
+}
@@ -17,33 +17,40 @@ extern crate micro_rpc;
 extern crate prost;
 extern crate tcp_proto;
 
-use crate::attestation::DefaultAttestationProvider;
+use crate::attestation::{AttestationProvider, DefaultAttestationProvider};
+use crate::clock::{Clock, HostClock};
 use crate::communication::DefaultCommunicationModule;
 use crate::handshake::DefaultHandshakeSessionProvider;
 use crate::model::Actor;
 use crate::oak_handshaker::DefaultOakHandshakerFactory;
 use crate::platform::{Application, Host};
+use crate::recovery::StateJournal;
+use crate::sealed::HostSealedStorage;
 use crate::snapshot::{DefaultSnapshotReceiver, DefaultSnapshotSender};
 use crate::{
     consensus::RaftSimple, driver::Driver, snapshot::DefaultSnapshotProcessor,
     storage::MemoryStorage,
 };
 use alloc::boxed::Box;
+use alloc::rc::Rc;
 use alloc::vec::Vec;
 use core::mem;
 use service::micro_rpc::Status;
 use tcp_proto::runtime::endpoint::{
-    EndpointService, OutMessage, ReceiveMessageRequest, ReceiveMessageResponse,
+    EndpointService, OutMessage, ReceiveMessageRequest, ReceiveMessageResponse, SignedTimestamp,
 };
 
 struct ApplicationHost {
     messages: Vec<OutMessage>,
+    // Signed wall clock time passed along with the message.
+    signed_wall_time: Option<SignedTimestamp>,
 }
 
 impl ApplicationHost {
-    fn new() -> ApplicationHost {
+    fn new(signed_wall_time: Option<SignedTimestamp>) -> ApplicationHost {
         ApplicationHost {
             messages: Vec::new(),
+            signed_wall_time,
         }
     }
 
@@ -60,6 +67,10 @@ impl Host for ApplicationHost {
     fn public_signing_key(&self) -> Vec<u8> {
         Vec::new()
     }
+
+    fn signed_wall_time(&self) -> Option<SignedTimestamp> {
+        self.signed_wall_time.clone()
+    }
 }
 
 pub struct ApplicationService<A: Actor> {
@@ -70,10 +81,31 @@ pub struct ApplicationService<A: Actor> {
         A,
         DefaultCommunicationModule,
     >,
+    clock: Rc<dyn Clock>,
 }
 
 impl<A: Actor> ApplicationService<A> {
     pub fn new(actor: A) -> ApplicationService<A> {
+        Self::with_clock(actor, Rc::new(HostClock::new()))
+    }
+
+    /// Creates application service that measures time with the given clock. The
+    /// clock can be shared with the actor components that need to measure time.
+    /// Replicas that must not trust the wall clock time of the host are given the
+    /// clock that trusts the time authority instead, see
+    /// [crate::clock::AuthorityClock].
+    pub fn with_clock(actor: A, clock: Rc<dyn Clock>) -> ApplicationService<A> {
+        Self::with_attestation_provider(actor, clock, Box::new(DefaultAttestationProvider {}))
+    }
+
+    /// Creates application service that attests the replicas to one another with
+    /// the given provider, e.g. the SEV-SNP or TDX provider when the trusted host
+    /// runs in an AMD SEV-SNP guest or an Intel TDX trust domain respectively.
+    pub fn with_attestation_provider(
+        actor: A,
+        clock: Rc<dyn Clock>,
+        attestation_provider: Box<dyn AttestationProvider>,
+    ) -> ApplicationService<A> {
         ApplicationService {
             driver: Driver::new(
                 RaftSimple::new(),
@@ -84,10 +116,24 @@ impl<A: Actor> ApplicationService<A> {
                 ),
                 actor,
                 DefaultCommunicationModule::new(Box::new(DefaultHandshakeSessionProvider::new(
-                    Box::new(DefaultAttestationProvider {}),
+                    attestation_provider,
                     Box::new(DefaultOakHandshakerFactory {}),
                 ))),
+                Box::new(StateJournal::new()),
+                clock.clone(),
             ),
+            clock,
+        }
+    }
+
+    /// Persists the secrets of the replica through the untrusted launcher sealed
+    /// to the identity and the security version of the enclave, see
+    /// [crate::sealed::SealingEncryptor]. The same storage can be shared with the
+    /// actor to keep its own secrets.
+    pub fn with_sealed_storage(self, sealed_storage: Rc<HostSealedStorage>) -> Self {
+        ApplicationService {
+            driver: self.driver.with_sealed_storage(sealed_storage),
+            clock: self.clock,
         }
     }
 }
@@ -97,7 +143,7 @@ impl<A: Actor> EndpointService for ApplicationService<A> {
         &mut self,
         request: ReceiveMessageRequest,
     ) -> Result<ReceiveMessageResponse, Status> {
-        let mut host = ApplicationHost::new();
+        let mut host = ApplicationHost::new(request.signed_wall_time);
 
         let Ok(()) = self
             .driver
@@ -109,6 +155,7 @@ impl<A: Actor> EndpointService for ApplicationService<A> {
 
         let response = ReceiveMessageResponse {
             messages: host.take_messages(),
+            time_nonce: self.clock.time_nonce().unwrap_or_default(),
         };
 
         Ok(response)
@@ -0,0 +1,938 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::attestation::{
+    allows, AttestationProvider, ClientAttestation, RootLayerAttestationProvider,
+    RootLayerReportGenerator, RootLayerVerifier, ServerAttestation, VerifiedPeerCache,
+};
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use anyhow::{anyhow, ensure, Result};
+use core::cell::RefCell;
+use oak_proto_rust::oak::attestation::v1::TeePlatform;
+use p384::ecdsa::{signature::Verifier, Signature as EcdsaSignature, VerifyingKey};
+use rsa::{pkcs1::DecodeRsaPublicKey, pss, RsaPublicKey};
+use sha2::{Digest, Sha256, Sha384};
+use tcp_proto::runtime::endpoint::AttestationPolicy;
+use x509_cert::{
+    der::{oid::ObjectIdentifier, Decode, Encode},
+    Certificate,
+};
+
+/// Size of the attestation report as defined by the AMD SEV-SNP firmware ABI.
+pub const SNP_REPORT_SIZE: usize = 0x4A0;
+/// Size of the data the guest binds to the attestation report.
+pub const SNP_REPORT_DATA_SIZE: usize = 64;
+/// Size of the launch measurement of the guest.
+pub const SNP_MEASUREMENT_SIZE: usize = 48;
+
+// Offsets of the report fields as defined by the AMD SEV-SNP firmware ABI.
+const POLICY_OFFSET: usize = 0x08;
+const SIGNATURE_ALGORITHM_OFFSET: usize = 0x34;
+const REPORT_DATA_OFFSET: usize = 0x50;
+const MEASUREMENT_OFFSET: usize = 0x90;
+const ID_KEY_DIGEST_OFFSET: usize = 0xE0;
+const ID_KEY_DIGEST_SIZE: usize = 48;
+const REPORTED_TCB_OFFSET: usize = 0x180;
+const SIGNATURE_OFFSET: usize = 0x2A0;
+const SIGNATURE_SIZE: usize = 0x200;
+
+// Signature algorithm of the reports, ECDSA P-384 with SHA-384. The signature
+// components R and S are stored in little endian in fields of 72 bytes, only
+// the first 48 bytes of which are significant.
+const SIGNATURE_ALGORITHM_ECDSA_P384_SHA384: u32 = 1;
+const SIGNATURE_COMPONENT_SIZE: usize = 72;
+const SIGNATURE_SCALAR_SIZE: usize = 48;
+
+// Signature algorithm of the ARK, ASK and VCEK certificates, RSASSA-PSS.
+const RSASSA_PSS_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.10");
+
+// Guest policy bit that allows the hypervisor to debug the guest.
+const POLICY_DEBUG: u64 = 1 << 19;
+
+/// Security version numbers of the components of the trusted computing base
+/// the report has been produced by.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SnpTcbVersion {
+    pub boot_loader: u8,
+    pub tee: u8,
+    pub snp: u8,
+    pub microcode: u8,
+}
+
+impl SnpTcbVersion {
+    /// Decodes the version from its representation in the report.
+    pub fn from_u64(tcb: u64) -> SnpTcbVersion {
+        let bytes = tcb.to_le_bytes();
+        SnpTcbVersion {
+            boot_loader: bytes[0],
+            tee: bytes[1],
+            snp: bytes[6],
+            microcode: bytes[7],
+        }
+    }
+
+    /// Encodes the version into its representation in the report.
+    pub fn to_u64(&self) -> u64 {
+        u64::from_le_bytes([
+            self.boot_loader,
+            self.tee,
+            0,
+            0,
+            0,
+            0,
+            self.snp,
+            self.microcode,
+        ])
+    }
+
+    /// Checks if none of the components is below the minimum version.
+    pub fn meets(&self, minimum: &SnpTcbVersion) -> bool {
+        self.boot_loader >= minimum.boot_loader
+            && self.tee >= minimum.tee
+            && self.snp >= minimum.snp
+            && self.microcode >= minimum.microcode
+    }
+}
+
+/// Attestation report produced by the AMD secure processor for the guest.
+pub struct SnpReport {
+    raw: Vec<u8>,
+}
+
+impl SnpReport {
+    /// Parses the report, only the size of the report is checked.
+    pub fn parse(raw: &[u8]) -> Result<SnpReport> {
+        ensure!(
+            raw.len() == SNP_REPORT_SIZE,
+            "SEV-SNP report has unexpected size {}",
+            raw.len()
+        );
+        Ok(SnpReport { raw: raw.to_vec() })
+    }
+
+    /// Gets the guest policy the guest has been launched with.
+    pub fn policy(&self) -> u64 {
+        self.read_u64(POLICY_OFFSET)
+    }
+
+    /// Gets the algorithm the report is signed with.
+    pub fn signature_algorithm(&self) -> u32 {
+        let mut bytes = [0; 4];
+        bytes
+            .copy_from_slice(&self.raw[SIGNATURE_ALGORITHM_OFFSET..SIGNATURE_ALGORITHM_OFFSET + 4]);
+        u32::from_le_bytes(bytes)
+    }
+
+    /// Checks if the guest policy allows the hypervisor to debug the guest.
+    pub fn debug(&self) -> bool {
+        self.policy() & POLICY_DEBUG != 0
+    }
+
+    /// Gets the version of the trusted computing base the report has been
+    /// produced by.
+    pub fn reported_tcb(&self) -> SnpTcbVersion {
+        SnpTcbVersion::from_u64(self.read_u64(REPORTED_TCB_OFFSET))
+    }
+
+    /// Gets the data the guest has bound to the report.
+    pub fn report_data(&self) -> &[u8] {
+        &self.raw[REPORT_DATA_OFFSET..REPORT_DATA_OFFSET + SNP_REPORT_DATA_SIZE]
+    }
+
+    /// Gets the launch measurement of the guest.
+    pub fn measurement(&self) -> &[u8] {
+        &self.raw[MEASUREMENT_OFFSET..MEASUREMENT_OFFSET + SNP_MEASUREMENT_SIZE]
+    }
+
+    /// Gets the digest of the key the ID block of the guest has been signed
+    /// with, all zeros if the guest has been launched without the ID block.
+    pub fn id_key_digest(&self) -> &[u8] {
+        &self.raw[ID_KEY_DIGEST_OFFSET..ID_KEY_DIGEST_OFFSET + ID_KEY_DIGEST_SIZE]
+    }
+
+    /// Gets the part of the report that is covered by the signature.
+    pub fn signed_bytes(&self) -> &[u8] {
+        &self.raw[..SIGNATURE_OFFSET]
+    }
+
+    /// Gets the signature of the report made with the chip endorsement key.
+    pub fn signature(&self) -> &[u8] {
+        &self.raw[SIGNATURE_OFFSET..SIGNATURE_OFFSET + SIGNATURE_SIZE]
+    }
+
+    fn read_u64(&self, offset: usize) -> u64 {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&self.raw[offset..offset + 8]);
+        u64::from_le_bytes(bytes)
+    }
+}
+
+/// Creates the report data that binds the public key to the report, i.e. the
+/// SHA-256 digest of the public key followed by zeros.
+pub fn create_report_data(public_key: &[u8]) -> [u8; SNP_REPORT_DATA_SIZE] {
+    let mut report_data = [0; SNP_REPORT_DATA_SIZE];
+    report_data[..32].copy_from_slice(&Sha256::digest(public_key));
+    report_data
+}
+
+/// Requests attestation reports from the AMD secure processor, e.g. through
+/// the guest message interface exposed by the trusted host kernel.
+pub trait SnpReportGenerator {
+    /// Gets the raw report with the given data bound to it.
+    fn get_report(&self, report_data: &[u8; SNP_REPORT_DATA_SIZE]) -> Result<Vec<u8>>;
+    /// Gets the DER encoded certificate of the versioned chip endorsement key
+    /// (VCEK) the reports are signed with, e.g. as returned along with the
+    /// report by the extended guest request.
+    fn get_vcek_certificate(&self) -> Result<Vec<u8>>;
+}
+
+/// Verifies that the report has been signed by the chip endorsement key of
+/// a genuine AMD processor.
+pub trait SnpSignatureVerifier {
+    /// Verifies the certificate of the chip endorsement key up to the AMD
+    /// root key.
+    fn verify_certificate(&self, vcek_certificate: &[u8]) -> Result<()>;
+    /// Verifies that the report is signed with the key of the verified
+    /// certificate.
+    fn verify_signature(&self, report: &SnpReport, vcek_certificate: &[u8]) -> Result<()>;
+}
+
+/// Implementation of `SnpSignatureVerifier` that checks the certificate chain
+/// of the chip endorsement key up to the AMD root key (ARK) through the AMD
+/// SEV key (ASK) of the product line the chips belong to, e.g. Milan or Genoa.
+/// The certificates are signed with RSASSA-PSS and SHA-384, the reports with
+/// ECDSA P-384 and SHA-384.
+pub struct SnpCertificateChainVerifier {
+    ask: Certificate,
+}
+
+impl SnpCertificateChainVerifier {
+    /// Creates the verifier from the DER encoded ARK and ASK certificates as
+    /// published by AMD. The ARK must be self-signed and must sign the ASK.
+    pub fn new(
+        ark_certificate: &[u8],
+        ask_certificate: &[u8],
+    ) -> Result<SnpCertificateChainVerifier> {
+        let ark = parse_certificate(ark_certificate)?;
+        let ask = parse_certificate(ask_certificate)?;
+        verify_certificate_signature(&ark, &ark)?;
+        verify_certificate_signature(&ask, &ark)?;
+        Ok(SnpCertificateChainVerifier { ask })
+    }
+}
+
+impl SnpSignatureVerifier for SnpCertificateChainVerifier {
+    fn verify_certificate(&self, vcek_certificate: &[u8]) -> Result<()> {
+        verify_certificate_signature(&parse_certificate(vcek_certificate)?, &self.ask)
+    }
+
+    fn verify_signature(&self, report: &SnpReport, vcek_certificate: &[u8]) -> Result<()> {
+        ensure!(
+            report.signature_algorithm() == SIGNATURE_ALGORITHM_ECDSA_P384_SHA384,
+            "SEV-SNP report has unsupported signature algorithm {}",
+            report.signature_algorithm()
+        );
+        let vcek = parse_certificate(vcek_certificate)?;
+        let verifying_key = VerifyingKey::from_sec1_bytes(
+            vcek.tbs_certificate
+                .subject_public_key_info
+                .subject_public_key
+                .raw_bytes(),
+        )
+        .map_err(|err| anyhow!("VCEK has invalid public key: {}", err))?;
+
+        let signature = report.signature();
+        let r = read_signature_scalar(&signature[..SIGNATURE_COMPONENT_SIZE])?;
+        let s = read_signature_scalar(
+            &signature[SIGNATURE_COMPONENT_SIZE..2 * SIGNATURE_COMPONENT_SIZE],
+        )?;
+        let signature = EcdsaSignature::from_scalars(r, s)
+            .map_err(|err| anyhow!("SEV-SNP report has invalid signature: {}", err))?;
+        verifying_key
+            .verify(report.signed_bytes(), &signature)
+            .map_err(|_| anyhow!("SEV-SNP report signature verification failed"))
+    }
+}
+
+fn parse_certificate(certificate: &[u8]) -> Result<Certificate> {
+    Certificate::from_der(certificate)
+        .map_err(|err| anyhow!("Failed to parse certificate: {}", err))
+}
+
+// Verifies that the certificate is issued and signed by the issuer with
+// RSASSA-PSS and SHA-384.
+fn verify_certificate_signature(certificate: &Certificate, issuer: &Certificate) -> Result<()> {
+    ensure!(
+        certificate.tbs_certificate.issuer == issuer.tbs_certificate.subject,
+        "Certificate {} is not issued by {}",
+        certificate.tbs_certificate.subject,
+        issuer.tbs_certificate.subject
+    );
+    ensure!(
+        certificate.signature_algorithm.oid == RSASSA_PSS_OID,
+        "Certificate {} has unsupported signature algorithm {}",
+        certificate.tbs_certificate.subject,
+        certificate.signature_algorithm.oid
+    );
+    // The AMD keys are identified as RSASSA-PSS keys rather than RSA keys,
+    // hence the key is decoded from the PKCS#1 encoding directly.
+    let issuer_key = RsaPublicKey::from_pkcs1_der(
+        issuer
+            .tbs_certificate
+            .subject_public_key_info
+            .subject_public_key
+            .raw_bytes(),
+    )
+    .map_err(|err| anyhow!("Issuer has invalid public key: {}", err))?;
+    let signature = pss::Signature::try_from(certificate.signature.raw_bytes())
+        .map_err(|err| anyhow!("Certificate has invalid signature: {}", err))?;
+    let signed_bytes = certificate
+        .tbs_certificate
+        .to_der()
+        .map_err(|err| anyhow!("Failed to encode certificate: {}", err))?;
+    pss::VerifyingKey::<Sha384>::new(issuer_key)
+        .verify(&signed_bytes, &signature)
+        .map_err(|_| {
+            anyhow!(
+                "Certificate {} signature verification failed",
+                certificate.tbs_certificate.subject
+            )
+        })
+}
+
+// Converts the little endian signature component into the big endian scalar.
+fn read_signature_scalar(component: &[u8]) -> Result<p384::FieldBytes> {
+    ensure!(
+        component[SIGNATURE_SCALAR_SIZE..]
+            .iter()
+            .all(|byte| *byte == 0),
+        "SEV-SNP report has invalid signature"
+    );
+    let mut scalar = p384::FieldBytes::default();
+    scalar.copy_from_slice(&component[..SIGNATURE_SCALAR_SIZE]);
+    scalar.reverse();
+    Ok(scalar)
+}
+
+/// Values the reports of the peers are expected to carry.
+#[derive(Debug, Default, Clone)]
+pub struct SnpReferenceValues {
+    /// Launch measurements the guests are allowed to run with. Empty list
+    /// allows no guest.
+    pub measurements: Vec<Vec<u8>>,
+    /// If true the guests launched with any measurement are accepted.
+    pub allow_any_measurement: bool,
+    /// Digests of the ID keys the guests are allowed to be signed with. Empty
+    /// list allows any ID key.
+    pub signers: Vec<Vec<u8>>,
+    /// Minimum version of the trusted computing base.
+    pub min_tcb: SnpTcbVersion,
+    /// If true the guests that can be debugged by the hypervisor are accepted.
+    pub allow_debug: bool,
+}
+
+impl SnpReferenceValues {
+    /// Creates the reference values the attestation policy translates to.
+    pub fn from_policy(policy: &AttestationPolicy) -> SnpReferenceValues {
+        SnpReferenceValues {
+            measurements: policy.allowed_measurements.clone(),
+            allow_any_measurement: policy.allow_any_measurement,
+            signers: policy.allowed_signers.clone(),
+            min_tcb: SnpTcbVersion::from_u64(policy.min_snp_tcb_version),
+            allow_debug: policy.allow_debug,
+        }
+    }
+}
+
+/// Verifies the attestation reports of the peers against the reference values.
+pub struct SnpVerifier {
+    reference_values: RefCell<SnpReferenceValues>,
+    signature_verifier: Box<dyn SnpSignatureVerifier>,
+}
+
+impl SnpVerifier {
+    pub fn new(
+        reference_values: SnpReferenceValues,
+        signature_verifier: Box<dyn SnpSignatureVerifier>,
+    ) -> SnpVerifier {
+        SnpVerifier {
+            reference_values: RefCell::new(reference_values),
+            signature_verifier,
+        }
+    }
+
+    /// Verifies the certificate of the key the raw report is signed with, the
+    /// raw report and checks that it binds the given public key.
+    pub fn verify(
+        &self,
+        raw_report: &[u8],
+        vcek_certificate: &[u8],
+        public_key: &[u8],
+    ) -> Result<SnpReport> {
+        self.signature_verifier
+            .verify_certificate(vcek_certificate)?;
+        self.verify_signed_report(raw_report, vcek_certificate, public_key)
+    }
+
+    fn verify_signed_report(
+        &self,
+        raw_report: &[u8],
+        vcek_certificate: &[u8],
+        public_key: &[u8],
+    ) -> Result<SnpReport> {
+        let report = SnpReport::parse(raw_report)?;
+        self.signature_verifier
+            .verify_signature(&report, vcek_certificate)?;
+
+        let reference_values = self.reference_values.borrow();
+        ensure!(
+            allows(
+                &reference_values.measurements,
+                reference_values.allow_any_measurement,
+                report.measurement()
+            ),
+            "SEV-SNP report has unexpected measurement"
+        );
+        ensure!(
+            allows(
+                &reference_values.signers,
+                reference_values.signers.is_empty(),
+                report.id_key_digest()
+            ),
+            "SEV-SNP report has unexpected ID key digest"
+        );
+        ensure!(
+            report.reported_tcb().meets(&reference_values.min_tcb),
+            "SEV-SNP report has TCB {:?} below minimum {:?}",
+            report.reported_tcb(),
+            reference_values.min_tcb
+        );
+        ensure!(
+            reference_values.allow_debug || !report.debug(),
+            "SEV-SNP report allows debugging"
+        );
+        ensure!(
+            report.report_data() == create_report_data(public_key),
+            "SEV-SNP report is not bound to the public key"
+        );
+
+        Ok(report)
+    }
+}
+
+impl RootLayerVerifier for SnpVerifier {
+    fn platform(&self) -> TeePlatform {
+        TeePlatform::AmdSevSnp
+    }
+
+    fn verify_tee_certificate(&self, tee_certificate: &[u8]) -> Result<()> {
+        self.signature_verifier.verify_certificate(tee_certificate)
+    }
+
+    fn verify_report(
+        &self,
+        report: &[u8],
+        tee_certificate: &[u8],
+        public_key: &[u8],
+    ) -> Result<()> {
+        self.verify_signed_report(report, tee_certificate, public_key)
+            .map(|_| ())
+    }
+
+    fn update_policy(&self, policy: &AttestationPolicy) {
+        *self.reference_values.borrow_mut() = SnpReferenceValues::from_policy(policy);
+    }
+}
+
+// Requests the reports that bind the session public keys.
+struct SnpRootLayerReportGenerator {
+    generator: Rc<dyn SnpReportGenerator>,
+}
+
+impl RootLayerReportGenerator for SnpRootLayerReportGenerator {
+    fn get_report(&self, public_key: &[u8]) -> Result<Vec<u8>> {
+        self.generator.get_report(&create_report_data(public_key))
+    }
+
+    fn get_tee_certificate(&self) -> Result<Vec<u8>> {
+        self.generator.get_vcek_certificate()
+    }
+}
+
+/// Implementation of `AttestationProvider` for the replicas running in AMD
+/// SEV-SNP guests. Each replica presents a fresh report that binds its session
+/// public key and verifies the report of its peer against the reference
+/// values.
+pub struct SevSnpAttestationProvider {
+    inner: RootLayerAttestationProvider,
+}
+
+impl SevSnpAttestationProvider {
+    /// Creates the provider, a report is requested for every session as the
+    /// session public keys are generated for every session.
+    pub fn new(
+        generator: Rc<dyn SnpReportGenerator>,
+        verifier: SnpVerifier,
+    ) -> SevSnpAttestationProvider {
+        SevSnpAttestationProvider {
+            inner: RootLayerAttestationProvider::new(
+                Rc::new(SnpRootLayerReportGenerator { generator }),
+                Rc::new(verifier),
+            ),
+        }
+    }
+
+    /// Skips the verification of the peer certificates found in the cache,
+    /// see [VerifiedPeerCache].
+    pub fn with_cache(self, cache: Rc<VerifiedPeerCache>) -> SevSnpAttestationProvider {
+        SevSnpAttestationProvider {
+            inner: self.inner.with_cache(cache),
+        }
+    }
+}
+
+impl AttestationProvider for SevSnpAttestationProvider {
+    fn get_client_attestation(&self, session_public_key: &[u8]) -> Box<dyn ClientAttestation> {
+        self.inner.get_client_attestation(session_public_key)
+    }
+
+    fn get_server_attestation(&self, session_public_key: &[u8]) -> Box<dyn ServerAttestation> {
+        self.inner.get_server_attestation(session_public_key)
+    }
+
+    fn update_policy(&self, policy: &AttestationPolicy) {
+        self.inner.update_policy(policy)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::clock::ManualClock;
+    use crate::sev_snp::*;
+    use alloc::vec;
+    use core::cell::Cell;
+    use core::str::FromStr;
+    use core::time::Duration;
+    use p384::ecdsa::{signature::Signer, SigningKey};
+    use rand::rngs::OsRng;
+    use rsa::RsaPrivateKey;
+    use x509_cert::{
+        builder::{Builder, CertificateBuilder, Profile},
+        name::Name,
+        serial_number::SerialNumber,
+        spki::SubjectPublicKeyInfoOwned,
+        time::Validity,
+    };
+
+    const MEASUREMENT: [u8; SNP_MEASUREMENT_SIZE] = [7; SNP_MEASUREMENT_SIZE];
+
+    const TCB: SnpTcbVersion = SnpTcbVersion {
+        boot_loader: 3,
+        tee: 0,
+        snp: 14,
+        microcode: 209,
+    };
+
+    const VCEK_CERTIFICATE: &[u8] = b"vcek";
+
+    // Produces unsigned reports with the given fields.
+    struct FakeReportGenerator {
+        measurement: [u8; SNP_MEASUREMENT_SIZE],
+        tcb: SnpTcbVersion,
+        policy: u64,
+    }
+
+    impl SnpReportGenerator for FakeReportGenerator {
+        fn get_report(&self, report_data: &[u8; SNP_REPORT_DATA_SIZE]) -> Result<Vec<u8>> {
+            let mut report = vec![0; SNP_REPORT_SIZE];
+            report[POLICY_OFFSET..POLICY_OFFSET + 8].copy_from_slice(&self.policy.to_le_bytes());
+            report[SIGNATURE_ALGORITHM_OFFSET..SIGNATURE_ALGORITHM_OFFSET + 4]
+                .copy_from_slice(&SIGNATURE_ALGORITHM_ECDSA_P384_SHA384.to_le_bytes());
+            report[REPORT_DATA_OFFSET..REPORT_DATA_OFFSET + SNP_REPORT_DATA_SIZE]
+                .copy_from_slice(report_data);
+            report[MEASUREMENT_OFFSET..MEASUREMENT_OFFSET + SNP_MEASUREMENT_SIZE]
+                .copy_from_slice(&self.measurement);
+            report[REPORTED_TCB_OFFSET..REPORTED_TCB_OFFSET + 8]
+                .copy_from_slice(&self.tcb.to_u64().to_le_bytes());
+            Ok(report)
+        }
+
+        fn get_vcek_certificate(&self) -> Result<Vec<u8>> {
+            Ok(VCEK_CERTIFICATE.to_vec())
+        }
+    }
+
+    struct FakeSignatureVerifier {}
+
+    impl SnpSignatureVerifier for FakeSignatureVerifier {
+        fn verify_certificate(&self, vcek_certificate: &[u8]) -> Result<()> {
+            ensure!(vcek_certificate == VCEK_CERTIFICATE, "Invalid certificate");
+            Ok(())
+        }
+
+        fn verify_signature(&self, _report: &SnpReport, _vcek_certificate: &[u8]) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    // Counts the certificates and the reports that have been verified.
+    struct CountingSignatureVerifier {
+        certificate_count: Rc<Cell<u32>>,
+        signature_count: Rc<Cell<u32>>,
+    }
+
+    impl SnpSignatureVerifier for CountingSignatureVerifier {
+        fn verify_certificate(&self, _vcek_certificate: &[u8]) -> Result<()> {
+            self.certificate_count.set(self.certificate_count.get() + 1);
+            Ok(())
+        }
+
+        fn verify_signature(&self, _report: &SnpReport, _vcek_certificate: &[u8]) -> Result<()> {
+            self.signature_count.set(self.signature_count.get() + 1);
+            Ok(())
+        }
+    }
+
+    fn create_verifier() -> SnpVerifier {
+        SnpVerifier::new(
+            SnpReferenceValues {
+                measurements: vec![MEASUREMENT.to_vec()],
+                allow_any_measurement: false,
+                signers: vec![],
+                min_tcb: TCB,
+                allow_debug: false,
+            },
+            Box::new(FakeSignatureVerifier {}),
+        )
+    }
+
+    fn create_provider(generator: FakeReportGenerator) -> SevSnpAttestationProvider {
+        SevSnpAttestationProvider::new(Rc::new(generator), create_verifier())
+    }
+
+    fn create_generator() -> FakeReportGenerator {
+        FakeReportGenerator {
+            measurement: MEASUREMENT,
+            tcb: TCB,
+            policy: 0,
+        }
+    }
+
+    fn create_certificate(
+        profile: Profile,
+        subject: &str,
+        subject_public_key_info: SubjectPublicKeyInfoOwned,
+        signer: &pss::BlindedSigningKey<Sha384>,
+    ) -> Vec<u8> {
+        CertificateBuilder::new(
+            profile,
+            SerialNumber::from(1u32),
+            Validity::from_now(Duration::from_secs(3600)).unwrap(),
+            Name::from_str(subject).unwrap(),
+            subject_public_key_info,
+            signer,
+        )
+        .unwrap()
+        .build_with_rng::<pss::Signature>(&mut OsRng)
+        .unwrap()
+        .to_der()
+        .unwrap()
+    }
+
+    fn create_rsa_key() -> pss::BlindedSigningKey<Sha384> {
+        pss::BlindedSigningKey::new(RsaPrivateKey::new(&mut OsRng, 1024).unwrap())
+    }
+
+    fn create_vcek_certificate(
+        vcek: &SigningKey,
+        signer: &pss::BlindedSigningKey<Sha384>,
+    ) -> Vec<u8> {
+        create_certificate(
+            Profile::Leaf {
+                issuer: Name::from_str("CN=SEV-Milan").unwrap(),
+                enable_key_agreement: false,
+                enable_key_encipherment: false,
+            },
+            "CN=SEV-VCEK",
+            SubjectPublicKeyInfoOwned::from_key(*vcek.verifying_key()).unwrap(),
+            signer,
+        )
+    }
+
+    // Signs the report with the chip endorsement key the way the AMD secure
+    // processor does.
+    fn sign_report(report: &mut [u8], vcek: &SigningKey) {
+        let signature: EcdsaSignature = vcek.sign(&report[..SIGNATURE_OFFSET]);
+        let (r, s) = signature.split_bytes();
+        for (offset, scalar) in [(0, r), (SIGNATURE_COMPONENT_SIZE, s)] {
+            let component = &mut report[SIGNATURE_OFFSET + offset..];
+            component[..SIGNATURE_SCALAR_SIZE].copy_from_slice(&scalar);
+            component[..SIGNATURE_SCALAR_SIZE].reverse();
+        }
+    }
+
+    #[test]
+    fn test_snp_report() {
+        let raw_report = create_generator()
+            .get_report(&create_report_data(&[1, 2, 3]))
+            .unwrap();
+        let report = SnpReport::parse(&raw_report).unwrap();
+        assert_eq!(report.measurement(), &MEASUREMENT);
+        assert_eq!(report.reported_tcb(), TCB);
+        assert!(!report.debug());
+        assert_eq!(report.signed_bytes().len(), SIGNATURE_OFFSET);
+        assert_eq!(report.signature().len(), SIGNATURE_SIZE);
+        assert!(SnpReport::parse(&raw_report[1..]).is_err());
+
+        let verifier = create_verifier();
+        assert!(verifier
+            .verify(&raw_report, VCEK_CERTIFICATE, &[1, 2, 3])
+            .is_ok());
+
+        // Report must bind the public key.
+        assert!(verifier
+            .verify(&raw_report, VCEK_CERTIFICATE, &[1, 2])
+            .is_err());
+
+        // Certificate of the key the report is signed with must be valid.
+        assert!(verifier.verify(&raw_report, b"other", &[1, 2, 3]).is_err());
+
+        // Report must carry the expected measurement.
+        let mut generator = create_generator();
+        generator.measurement = [8; SNP_MEASUREMENT_SIZE];
+        let raw_report = generator.get_report(&create_report_data(&[1])).unwrap();
+        assert!(verifier
+            .verify(&raw_report, VCEK_CERTIFICATE, &[1])
+            .is_err());
+
+        // Report must not come from an outdated TCB.
+        let mut generator = create_generator();
+        generator.tcb.snp = 13;
+        let raw_report = generator.get_report(&create_report_data(&[1])).unwrap();
+        assert!(verifier
+            .verify(&raw_report, VCEK_CERTIFICATE, &[1])
+            .is_err());
+
+        // Report must not come from a guest that can be debugged.
+        let mut generator = create_generator();
+        generator.policy = POLICY_DEBUG;
+        let raw_report = generator.get_report(&create_report_data(&[1])).unwrap();
+        assert!(verifier
+            .verify(&raw_report, VCEK_CERTIFICATE, &[1])
+            .is_err());
+
+        // Policy update replaces the reference values.
+        verifier.update_policy(&AttestationPolicy {
+            allowed_signers: vec![vec![0; ID_KEY_DIGEST_SIZE]],
+            min_snp_tcb_version: TCB.to_u64(),
+            allow_debug: true,
+            allow_any_measurement: true,
+            ..Default::default()
+        });
+        assert!(verifier.verify(&raw_report, VCEK_CERTIFICATE, &[1]).is_ok());
+
+        // Empty policy allows no guest.
+        verifier.update_policy(&AttestationPolicy {
+            allow_debug: true,
+            ..Default::default()
+        });
+        assert!(verifier
+            .verify(&raw_report, VCEK_CERTIFICATE, &[1])
+            .is_err());
+        verifier.update_policy(&AttestationPolicy {
+            allowed_signers: vec![vec![1; ID_KEY_DIGEST_SIZE]],
+            ..Default::default()
+        });
+        assert!(verifier
+            .verify(&raw_report, VCEK_CERTIFICATE, &[1])
+            .is_err());
+    }
+
+    #[test]
+    fn test_snp_certificate_chain_verifier() {
+        let ark = create_rsa_key();
+        let ask = create_rsa_key();
+        let vcek = SigningKey::random(&mut OsRng);
+        let ark_certificate = create_certificate(
+            Profile::Root,
+            "CN=ARK-Milan",
+            SubjectPublicKeyInfoOwned::from_key(ark.as_ref().to_public_key()).unwrap(),
+            &ark,
+        );
+        let ask_certificate = create_certificate(
+            Profile::SubCA {
+                issuer: Name::from_str("CN=ARK-Milan").unwrap(),
+                path_len_constraint: Some(0),
+            },
+            "CN=SEV-Milan",
+            SubjectPublicKeyInfoOwned::from_key(ask.as_ref().to_public_key()).unwrap(),
+            &ark,
+        );
+        let vcek_certificate = create_vcek_certificate(&vcek, &ask);
+
+        // ARK must be self-signed and must sign the ASK.
+        assert!(SnpCertificateChainVerifier::new(&ask_certificate, &ask_certificate).is_err());
+        assert!(SnpCertificateChainVerifier::new(&ark_certificate, b"invalid").is_err());
+        let chain_verifier =
+            SnpCertificateChainVerifier::new(&ark_certificate, &ask_certificate).unwrap();
+
+        // VCEK must be signed by the ASK.
+        assert!(chain_verifier.verify_certificate(&vcek_certificate).is_ok());
+        let rogue_vcek_certificate = create_vcek_certificate(&vcek, &create_rsa_key());
+        assert!(chain_verifier
+            .verify_certificate(&rogue_vcek_certificate)
+            .is_err());
+        assert!(chain_verifier.verify_certificate(&ask_certificate).is_err());
+
+        // Report must be signed with the VCEK.
+        let verifier = SnpVerifier::new(
+            SnpReferenceValues {
+                measurements: vec![MEASUREMENT.to_vec()],
+                min_tcb: TCB,
+                ..Default::default()
+            },
+            Box::new(chain_verifier),
+        );
+        let mut raw_report = create_generator()
+            .get_report(&create_report_data(&[1]))
+            .unwrap();
+        sign_report(&mut raw_report, &vcek);
+        assert!(verifier
+            .verify(&raw_report, &vcek_certificate, &[1])
+            .is_ok());
+        assert!(verifier
+            .verify(&raw_report, &rogue_vcek_certificate, &[1])
+            .is_err());
+
+        let mut tampered_report = raw_report.clone();
+        tampered_report[REPORT_DATA_OFFSET] ^= 1;
+        assert!(verifier
+            .verify(&tampered_report, &vcek_certificate, &[1])
+            .is_err());
+
+        let mut other_report = raw_report.clone();
+        sign_report(&mut other_report, &SigningKey::random(&mut OsRng));
+        assert!(verifier
+            .verify(&other_report, &vcek_certificate, &[1])
+            .is_err());
+    }
+
+    #[test]
+    fn test_sev_snp_attestation() {
+        let client_provider = create_provider(create_generator());
+        let server_provider = create_provider(create_generator());
+
+        let mut client = client_provider.get_client_attestation(&[1]);
+        let mut server = server_provider.get_server_attestation(&[2]);
+
+        let request = client.get_outgoing_message().unwrap().unwrap();
+        assert_eq!(server.put_incoming_message(&request).unwrap(), Some(()));
+        let response = server.get_outgoing_message().unwrap().unwrap();
+        assert_eq!(client.put_incoming_message(&response).unwrap(), Some(()));
+
+        assert_eq!(
+            client
+                .get_attestation_results()
+                .unwrap()
+                .encryption_public_key,
+            vec![2]
+        );
+        assert_eq!(
+            server
+                .get_attestation_results()
+                .unwrap()
+                .encryption_public_key,
+            vec![1]
+        );
+
+        // Report presented with a session public key it doesn't bind is
+        // rejected.
+        let mut server = server_provider.get_server_attestation(&[2]);
+        let mut replayed_request = request.clone();
+        replayed_request.endorsed_evidence[0]
+            .evidence
+            .as_mut()
+            .unwrap()
+            .root_layer
+            .as_mut()
+            .unwrap()
+            .eca_public_key = vec![3];
+        assert!(server.put_incoming_message(&replayed_request).is_err());
+        assert!(server.get_attestation_results().is_none());
+
+        // Peer with unexpected measurement is rejected.
+        let mut generator = create_generator();
+        generator.measurement = [8; SNP_MEASUREMENT_SIZE];
+        let rogue_provider = create_provider(generator);
+        let mut rogue = rogue_provider.get_client_attestation(&[3]);
+        let mut server = server_provider.get_server_attestation(&[2]);
+        let request = rogue.get_outgoing_message().unwrap().unwrap();
+        assert!(server.put_incoming_message(&request).is_err());
+        assert!(server.get_attestation_results().is_none());
+    }
+
+    #[test]
+    fn test_sev_snp_attestation_cache() {
+        let clock = Rc::new(ManualClock::new(0));
+        let cache = Rc::new(VerifiedPeerCache::new(clock.clone(), 1000));
+        let certificate_count = Rc::new(Cell::new(0));
+        let signature_count = Rc::new(Cell::new(0));
+        let client_provider = create_provider(create_generator());
+        let server_provider = SevSnpAttestationProvider::new(
+            Rc::new(create_generator()),
+            SnpVerifier::new(
+                SnpReferenceValues {
+                    measurements: vec![MEASUREMENT.to_vec()],
+                    ..Default::default()
+                },
+                Box::new(CountingSignatureVerifier {
+                    certificate_count: certificate_count.clone(),
+                    signature_count: signature_count.clone(),
+                }),
+            ),
+        )
+        .with_cache(cache.clone());
+
+        let attest = |session_public_key: &[u8]| {
+            let mut client = client_provider.get_client_attestation(session_public_key);
+            let mut server = server_provider.get_server_attestation(&[2]);
+            let request = client.get_outgoing_message().unwrap().unwrap();
+            server.put_incoming_message(&request).unwrap()
+        };
+
+        // Certificate of the reconnecting peer is verified once, while the
+        // report bound to every session is verified every time.
+        assert_eq!(attest(&[1]), Some(()));
+        assert_eq!(attest(&[3]), Some(()));
+        assert_eq!(certificate_count.get(), 1);
+        assert_eq!(signature_count.get(), 2);
+
+        // Certificate is verified again once the entry expires.
+        clock.advance(1000);
+        assert_eq!(attest(&[1]), Some(()));
+        assert_eq!(certificate_count.get(), 2);
+
+        // Policy update invalidates the cache.
+        server_provider.update_policy(&AttestationPolicy {
+            allowed_measurements: vec![MEASUREMENT.to_vec()],
+            ..Default::default()
+        });
+        assert!(cache.is_empty());
+        assert_eq!(attest(&[1]), Some(()));
+        assert_eq!(certificate_count.get(), 3);
+        assert_eq!(signature_count.get(), 4);
+    }
+}
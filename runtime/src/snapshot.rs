@@ -13,20 +13,28 @@
 // limitations under the License.
 
 use crate::logger::log::create_logger;
+use crate::model::{ActorError, SnapshotWriter};
 use crate::StdError;
+use aes_gcm_siv::{
+    aead::{Aead, KeyInit, Payload as AeadPayload},
+    Aes256GcmSiv, Nonce,
+};
 use alloc::boxed::Box;
+use alloc::rc::Rc;
 use alloc::vec::Vec;
-use core::convert::TryInto;
 use core::option::Option;
-use core::{cmp, fmt};
-use hashbrown::HashMap;
+use core::{cmp, fmt, mem};
+use hashbrown::{HashMap, HashSet};
+use hkdf::Hkdf;
 use prost::{
     bytes::{BufMut, Bytes, BytesMut},
     Message,
 };
+use sha2::{Digest, Sha256};
 use tcp_proto::runtime::endpoint::{
     deliver_snapshot_request, deliver_snapshot_response, raft_config::SnapshotConfig,
-    DeliverSnapshotRequest, DeliverSnapshotResponse, DeliverSnapshotStatus,
+    AttestationPolicy, ChunkManifest, DeliverSnapshotRequest, DeliverSnapshotResponse,
+    DeliverSnapshotStatus, EncryptedSnapshotPayload,
 };
 
 use raft::{
@@ -41,6 +49,8 @@ pub enum SnapshotError {
     /// Failed to deliver part of the snapshot.
     FailedDelivery,
     Corrupted,
+    /// Failed to encrypt or decrypt part of the snapshot.
+    FailedEncryption,
 }
 
 impl StdError for SnapshotError {
@@ -54,10 +64,18 @@ impl fmt::Display for SnapshotError {
         match *self {
             SnapshotError::FailedDelivery => write!(f, "Failed to deliver snapshot"),
             SnapshotError::Corrupted => write!(f, "Snapshot is corrupted"),
+            SnapshotError::FailedEncryption => write!(f, "Failed to encrypt snapshot"),
         }
     }
 }
 
+/// Context of the secret exported from the attested session with the peer
+/// replica to derive the keys of the snapshot transfers from.
+pub const SESSION_SECRET_CONTEXT: &[u8] = b"tcp snapshot transfer";
+
+// Size of the key encrypting payloads of a single snapshot transfer.
+const TRANSFER_KEY_SIZE: usize = 32;
+
 /// Represents snapshot processor that plays sender or receiver role depending
 /// on the state of Raft.
 pub trait SnapshotProcessor {
@@ -104,7 +122,16 @@ pub trait SnapshotProcessor {
     /// # Returns
     ///
     /// Snapshot processor in its current role. Must not be retained.
-    fn mut_processor(&mut self, instant: u64) -> SnapshotProcessorRole<'_>;
+    fn mut_processor(&mut self) -> SnapshotProcessorRole<'_>;
+
+    /// Sets the secret exported from the attested session with given replica.
+    ///
+    /// # Note
+    ///
+    /// If the snapshot payloads are encrypted, the keys of the transfers to and
+    /// from the replica are derived from its latest secret. Transfers with
+    /// replicas without secret fail.
+    fn set_session_secret(&mut self, replica_id: u64, session_secret: Bytes);
 }
 
 /// Enumerates snapshot processor roles.
@@ -127,6 +154,8 @@ pub trait SnapshotSender {
     /// # Note
     ///
     /// Sender will internally serialize and split snapshot into several chunks.
+    /// Concurrent transfers of the same snapshot to several replicas share the
+    /// chunks.
     fn start(&mut self, receiver_id: u64, snapshot: RaftSnapshot);
 
     /// Attempts to fetch next request to send.
@@ -177,6 +206,14 @@ pub trait SnapshotSender {
     /// the replica and status of the snapshot otherwise. Tuple containing id and
     /// snapshot status that must be reported to Raft.
     fn try_complete(&mut self) -> Option<(u64, RaftSnapshotStatus)>;
+
+    /// Reports the progress of the transfers.
+    ///
+    /// # Returns
+    ///
+    /// Nothing if no snapshot is being sent. Percentage of the chunks
+    /// acknowledged by the least advanced receiver otherwise.
+    fn transfer_progress(&self) -> Option<u64>;
 }
 
 /// Represents snapshot receiver.
@@ -201,6 +238,14 @@ pub trait SnapshotReceiver {
     /// Nothing if not all chunks have been received. Error if fully assembled snapshot
     /// failed to pass checksum validation. Sender replica id and snapshot otherwise.
     fn try_complete(&mut self) -> Option<Result<(u64, RaftSnapshot), SnapshotError>>;
+
+    /// Reports the progress of the transfer.
+    ///
+    /// # Returns
+    ///
+    /// Nothing if no snapshot is being received. Percentage of the chunks
+    /// received so far otherwise.
+    fn transfer_progress(&self) -> Option<u64>;
 }
 
 /// Enumerates the state the replica is currently in.
@@ -215,17 +260,17 @@ enum ReplicaState {
 pub trait SnapshotSenderImpl: SnapshotSender {
     fn init(&mut self, logger: Logger, replica_id: u64, snapshot_config: &Option<SnapshotConfig>);
 
-    fn set_instant(&mut self, instant: u64);
-
     fn reset(&mut self) -> Vec<(u64, RaftSnapshotStatus)>;
+
+    fn set_session_secret(&mut self, replica_id: u64, session_secret: Bytes);
 }
 
 pub trait SnapshotReceiverImpl: SnapshotReceiver {
-    fn init(&mut self, logger: Logger, replica_id: u64);
-
-    fn set_instant(&mut self, instant: u64);
+    fn init(&mut self, logger: Logger, replica_id: u64, snapshot_config: &Option<SnapshotConfig>);
 
     fn reset(&mut self);
+
+    fn set_session_secret(&mut self, replica_id: u64, session_secret: Bytes);
 }
 
 pub struct DefaultSnapshotProcessor {
@@ -260,7 +305,8 @@ impl SnapshotProcessor for DefaultSnapshotProcessor {
         self.replica_id = replica_id;
         self.sender
             .init(logger.clone(), self.replica_id, snapshot_config);
-        self.receiver.init(logger.clone(), self.replica_id);
+        self.receiver
+            .init(logger.clone(), self.replica_id, snapshot_config);
         // Always start as a follower.
         self.state = ReplicaState::Follower;
     }
@@ -318,21 +364,120 @@ impl SnapshotProcessor for DefaultSnapshotProcessor {
         cancelled_snapshots
     }
 
-    fn mut_processor(&mut self, instant: u64) -> SnapshotProcessorRole<'_> {
+    fn mut_processor(&mut self) -> SnapshotProcessorRole<'_> {
         match self.state {
-            ReplicaState::Follower => {
-                self.receiver.set_instant(instant);
-                SnapshotProcessorRole::Receiver(&mut *self.receiver)
-            }
-            ReplicaState::Leader => {
-                self.sender.set_instant(instant);
-                SnapshotProcessorRole::Sender(&mut *self.sender)
-            }
+            ReplicaState::Follower => SnapshotProcessorRole::Receiver(&mut *self.receiver),
+            ReplicaState::Leader => SnapshotProcessorRole::Sender(&mut *self.sender),
             ReplicaState::Unknown => {
                 panic!("Snapshot processor is not initialized");
             }
         }
     }
+
+    fn set_session_secret(&mut self, replica_id: u64, session_secret: Bytes) {
+        // Secrets are kept by both roles as the role may change at any time.
+        self.sender
+            .set_session_secret(replica_id, session_secret.clone());
+        self.receiver.set_session_secret(replica_id, session_secret);
+    }
+}
+
+/// Accumulates the snapshot written out by the actor in a single buffer. The
+/// snapshot sender slices chunks out of the buffer as they are sent, without
+/// copying them.
+pub struct SnapshotBuffer {
+    contents: BytesMut,
+}
+
+impl SnapshotBuffer {
+    pub fn new() -> SnapshotBuffer {
+        SnapshotBuffer {
+            contents: BytesMut::new(),
+        }
+    }
+
+    /// Takes the accumulated snapshot.
+    pub fn into_bytes(self) -> Bytes {
+        self.contents.freeze()
+    }
+}
+
+impl Default for SnapshotBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SnapshotWriter for SnapshotBuffer {
+    fn write(&mut self, chunk: &[u8]) -> Result<(), ActorError> {
+        self.contents.extend_from_slice(chunk);
+        Ok(())
+    }
+}
+
+// Prefix of the snapshots that carry the attestation policy the cluster has
+// agreed upon, followed by the size of the encoded policy.
+const ATTESTATION_POLICY_MAGIC: &[u8; 4] = b"TCPA";
+const ATTESTATION_POLICY_HEADER_SIZE: usize = 8;
+
+/// Writes out the header carrying the attestation policy in effect at the
+/// snapshot that follows it.
+pub fn write_attestation_policy_header(
+    writer: &mut dyn SnapshotWriter,
+    policy: &AttestationPolicy,
+) -> Result<(), ActorError> {
+    let encoded_policy = policy.encode_to_vec();
+    writer.write(ATTESTATION_POLICY_MAGIC)?;
+    writer.write(&(encoded_policy.len() as u32).to_le_bytes())?;
+    writer.write(&encoded_policy)
+}
+
+/// Splits the snapshot into the attestation policy it carries and the rest of
+/// the snapshot. Snapshots without the header carry no policy.
+pub fn split_attestation_policy(
+    snapshot: Bytes,
+) -> Result<(Option<AttestationPolicy>, Bytes), SnapshotError> {
+    if !snapshot.starts_with(ATTESTATION_POLICY_MAGIC) {
+        return Ok((None, snapshot));
+    }
+
+    if snapshot.len() < ATTESTATION_POLICY_HEADER_SIZE {
+        return Err(SnapshotError::Corrupted);
+    }
+    let mut size = [0; 4];
+    size.copy_from_slice(&snapshot[ATTESTATION_POLICY_MAGIC.len()..ATTESTATION_POLICY_HEADER_SIZE]);
+    let policy_end = ATTESTATION_POLICY_HEADER_SIZE + u32::from_le_bytes(size) as usize;
+    if snapshot.len() < policy_end {
+        return Err(SnapshotError::Corrupted);
+    }
+    let policy =
+        AttestationPolicy::decode(snapshot.slice(ATTESTATION_POLICY_HEADER_SIZE..policy_end))
+            .map_err(|_| SnapshotError::Corrupted)?;
+    Ok((Some(policy), snapshot.slice(policy_end..)))
+}
+
+/// Produces the contents of the snapshot being sent on demand, one chunk at a
+/// time as the chunks are sent. Transfers of the same snapshot share the
+/// source and hold on to the chunks only while they are in flight.
+pub trait SnapshotSource {
+    /// Gets the total size of the snapshot.
+    fn size(&self) -> u64;
+
+    /// Produces the chunk of the given size starting at the given offset, the
+    /// chunk is cut short at the end of the snapshot.
+    fn read_chunk(&self, offset: u64, chunk_size: u64) -> Bytes;
+}
+
+impl SnapshotSource for Bytes {
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+
+    fn read_chunk(&self, offset: u64, chunk_size: u64) -> Bytes {
+        let chunk_start = cmp::min(offset, self.size());
+        let chunk_end = cmp::min(offset.saturating_add(chunk_size), self.size());
+        self.slice(chunk_start as usize..chunk_end as usize)
+    }
 }
 
 /// Calculates the number of chunks needed to transmit a snapshot.
@@ -344,16 +489,113 @@ fn chunk_count(snapshot_size: u64, chunk_size: u64) -> u64 {
     }
 }
 
+/// Calculates the digest identifying the chunk by its contents.
+fn chunk_digest(chunk_contents: &[u8]) -> Bytes {
+    Bytes::copy_from_slice(&Sha256::digest(chunk_contents))
+}
+
+/// Derives the key encrypting payloads of the snapshot transfer from the
+/// secret shared by the sender and the receiver.
+fn derive_transfer_key(
+    session_secret: &[u8],
+    sender_id: u64,
+    receiver_id: u64,
+    snapshot_id: u32,
+) -> Bytes {
+    let mut info = Vec::with_capacity(20);
+    info.extend_from_slice(&sender_id.to_le_bytes());
+    info.extend_from_slice(&receiver_id.to_le_bytes());
+    info.extend_from_slice(&snapshot_id.to_le_bytes());
+
+    let mut transfer_key = [0; TRANSFER_KEY_SIZE];
+    Hkdf::<Sha256>::new(None, session_secret)
+        .expand(&info, &mut transfer_key)
+        .expect("Transfer key size is valid");
+    Bytes::copy_from_slice(&transfer_key)
+}
+
+/// Encrypts the payload of the delivery, delivery ids are never reused by the
+/// sender hence they serve as nonces.
+fn encrypt_payload(
+    transfer_key: &[u8],
+    delivery_id: u64,
+    payload: &[u8],
+) -> Result<Vec<u8>, SnapshotError> {
+    let delivery_id = delivery_id.to_le_bytes();
+    let mut nonce = [0; 12];
+    nonce[..8].copy_from_slice(&delivery_id);
+    Aes256GcmSiv::new_from_slice(transfer_key)
+        .map_err(|_| SnapshotError::FailedEncryption)?
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            AeadPayload {
+                msg: payload,
+                aad: &delivery_id,
+            },
+        )
+        .map_err(|_| SnapshotError::FailedEncryption)
+}
+
+/// Decrypts the payload of the delivery encrypted by `encrypt_payload`.
+fn decrypt_payload(
+    transfer_key: &[u8],
+    delivery_id: u64,
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, SnapshotError> {
+    let delivery_id = delivery_id.to_le_bytes();
+    let mut nonce = [0; 12];
+    nonce[..8].copy_from_slice(&delivery_id);
+    Aes256GcmSiv::new_from_slice(transfer_key)
+        .map_err(|_| SnapshotError::FailedEncryption)?
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            AeadPayload {
+                msg: ciphertext,
+                aad: &delivery_id,
+            },
+        )
+        .map_err(|_| SnapshotError::FailedEncryption)
+}
+
+/// Splits snapshot into chunks of the given size.
+pub(crate) fn split_chunks(
+    snapshot_data: &Bytes,
+    chunk_size: u64,
+) -> impl Iterator<Item = Bytes> + '_ {
+    let chunk_count = chunk_count(snapshot_data.len() as u64, chunk_size);
+    (0..chunk_count).map(move |chunk_index| {
+        let chunk_start = (chunk_index * chunk_size) as usize;
+        let chunk_end = cmp::min(snapshot_data.len(), chunk_start + chunk_size as usize);
+        snapshot_data.slice(chunk_start..chunk_end)
+    })
+}
+
 struct SnapshotSenderState {
     logger: Logger,
     snapshot_id: u32,
     snapshot_metadata: RaftSnapshotMetadata,
-    snapshot_data: Bytes,
+    // Produces the chunks as they are sent, none once the transfer completes.
+    snapshot_source: Option<Rc<dyn SnapshotSource>>,
+    snapshot_size: u64,
     chunk_size: u64,
     chunk_count: u64,
     next_chunk_index: u32,
     sent_chunk_count: u64,
     pending_chunks: HashMap<u64, u32>,
+    // Digests of the chunks sent with the header for delta transfer.
+    chunk_digests: Option<Vec<Bytes>>,
+    // Indicates that chunks are held back until the receiver responds to the
+    // header with the chunks it already has.
+    awaiting_header: bool,
+    // Chunks beyond the acknowledged ones the receiver already has.
+    reused_chunks: HashSet<u32>,
+    // Indicates that the transfer must be resumed with the next header after
+    // a delivery failure.
+    resuming: bool,
+    // Number of times the transfer can still be resumed.
+    remaining_retries: u32,
+    // Key encrypting the payloads if the payloads are encrypted.
+    transfer_key: Option<Bytes>,
     status: Option<RaftSnapshotStatus>,
 }
 
@@ -362,32 +604,98 @@ impl SnapshotSenderState {
         logger: Logger,
         snapshot_id: u32,
         snapshot: RaftSnapshot,
-        chunk_size: u64,
+        config: SnapshotSenderConfig,
     ) -> SnapshotSenderState {
-        let snapshot_metadata = snapshot.metadata.unwrap();
-        let snapshot_data: Bytes = snapshot.data.into();
-        let snapshot_size = snapshot_data.len() as u64;
+        let snapshot_source: Rc<dyn SnapshotSource> = Rc::new(Bytes::from(snapshot.data));
+        // Digests are produced one chunk at a time, the chunks themselves are
+        // produced again once they are sent.
+        let chunk_digests = config.delta_transfer.then(|| {
+            let snapshot_size = snapshot_source.size();
+            (0..chunk_count(snapshot_size, config.chunk_size))
+                .map(|chunk_index| {
+                    chunk_digest(
+                        &snapshot_source
+                            .read_chunk(chunk_index * config.chunk_size, config.chunk_size),
+                    )
+                })
+                .collect()
+        });
+
+        Self::with_contents(
+            logger,
+            snapshot_id,
+            snapshot.metadata.unwrap(),
+            snapshot_source,
+            chunk_digests,
+            config,
+        )
+    }
+
+    // Creates state for the transfer of the same snapshot as the given one to
+    // another replica. Snapshot source and digests are shared between transfers.
+    fn share(&self, logger: Logger, snapshot_id: u32, config: SnapshotSenderConfig) -> Self {
+        Self::with_contents(
+            logger,
+            snapshot_id,
+            self.snapshot_metadata.clone(),
+            self.snapshot_source.clone().unwrap(),
+            self.chunk_digests.clone(),
+            config,
+        )
+    }
+
+    fn with_contents(
+        logger: Logger,
+        snapshot_id: u32,
+        snapshot_metadata: RaftSnapshotMetadata,
+        snapshot_source: Rc<dyn SnapshotSource>,
+        chunk_digests: Option<Vec<Bytes>>,
+        config: SnapshotSenderConfig,
+    ) -> SnapshotSenderState {
+        let snapshot_size = snapshot_source.size();
+        let chunk_size = config.chunk_size;
 
         SnapshotSenderState {
             logger,
             snapshot_id,
             snapshot_metadata,
-            snapshot_data,
+            snapshot_source: Some(snapshot_source),
+            snapshot_size,
             chunk_size,
             chunk_count: chunk_count(snapshot_size, chunk_size),
             next_chunk_index: 0,
             sent_chunk_count: 0,
             pending_chunks: HashMap::new(),
+            chunk_digests,
+            awaiting_header: false,
+            reused_chunks: HashSet::new(),
+            resuming: false,
+            remaining_retries: config.max_delivery_retries,
+            transfer_key: None,
             status: None,
         }
     }
 
+    // Checks if the snapshot with given metadata is still being sent, so that
+    // its contents can be shared.
+    fn sends(&self, snapshot_metadata: &RaftSnapshotMetadata) -> bool {
+        self.status.is_none()
+            && self.snapshot_metadata.index == snapshot_metadata.index
+            && self.snapshot_metadata.term == snapshot_metadata.term
+    }
+
     fn progress(&self) -> f64 {
         if self.status.is_some() {
             // The snapshot transfer has reached terminal state,
             // both success and failure are considered completion.
             return 1.0;
         }
+        if self.awaiting_header || (self.resuming && !self.pending_chunks.is_empty()) {
+            // No chunks can be sent until the receiver reports the chunks it
+            // already has, or until the chunks in flight are resolved before
+            // resuming, consider the transfer saturated.
+            return 1.0;
+        }
         (self.sent_chunk_count as f64 + self.pending_chunks.len() as f64) / self.chunk_count as f64
     }
 
@@ -395,24 +703,33 @@ impl SnapshotSenderState {
         self.pending_chunks.len() as u32
     }
 
+    // Percentage of the chunks acknowledged by the receiver.
+    fn acknowledged_percentage(&self) -> u64 {
+        self.sent_chunk_count * 100 / self.chunk_count
+    }
+
     fn next_chunk(&mut self, delivery_id: u64) -> Option<Bytes> {
         if self.status.is_some() {
             // The snapshot transfer has reached terminal state,
             // no new chunks will be sent.
             return None;
         }
+        let snapshot_source = self.snapshot_source.as_ref()?;
+
+        // Resumed transfer starts with the header again.
+        if self.resuming {
+            self.next_chunk_index = 0;
+        }
 
         // Register chunk as pending.
         self.pending_chunks
             .insert(delivery_id, self.next_chunk_index);
 
-        // Compute the next chunk contents.
-        let next_chunk_start = self.next_chunk_index as usize * self.chunk_size as usize;
-        let next_chunk_end = cmp::min(
-            self.snapshot_data.len(),
-            next_chunk_start + self.chunk_size as usize,
+        // Produce the next chunk contents.
+        let next_chunk = snapshot_source.read_chunk(
+            u64::from(self.next_chunk_index) * self.chunk_size,
+            self.chunk_size,
         );
-        let next_chunk = self.snapshot_data.slice(next_chunk_start..next_chunk_end);
 
         // Assemble request payload.
         let mut payload = deliver_snapshot_request::Payload {
@@ -424,11 +741,18 @@ impl SnapshotSenderState {
             // Send header for the new snapshot transfer.
             payload.it = Some(deliver_snapshot_request::payload::It::Header(
                 deliver_snapshot_request::payload::Header {
-                    snapshot_size: self.snapshot_data.len() as u64,
+                    snapshot_size: self.snapshot_size,
                     snapshot_metadata: self.snapshot_metadata.encode_to_vec().into(),
                     chunk_contents: next_chunk,
+                    chunk_manifest: self.chunk_digests.as_ref().map(|chunk_digests| {
+                        ChunkManifest {
+                            chunk_digests: chunk_digests.clone(),
+                        }
+                    }),
+                    resume: self.resuming,
                 },
-            ))
+            ));
+            self.awaiting_header = self.chunk_digests.is_some() || self.resuming;
         } else {
             // Send next chunk of the existing snapshot transfer.
             payload.it = Some(deliver_snapshot_request::payload::It::Chunk(
@@ -449,8 +773,65 @@ impl SnapshotSenderState {
 
         // Advance index of the next to be sent chunk.
         self.next_chunk_index += 1;
+        self.skip_reused_chunks();
 
-        Some(payload.encode_to_vec().into())
+        let Some(transfer_key) = &self.transfer_key else {
+            return Some(payload.encode_to_vec().into());
+        };
+        match encrypt_payload(transfer_key, delivery_id, &payload.encode_to_vec()) {
+            Ok(ciphertext) => Some(
+                EncryptedSnapshotPayload {
+                    snapshot_id: self.snapshot_id,
+                    ciphertext: ciphertext.into(),
+                }
+                .encode_to_vec()
+                .into(),
+            ),
+            Err(e) => {
+                warn!(self.logger, "Aborting transfer: {}", e);
+                self.complete_with(RaftSnapshotStatus::Failure);
+                None
+            }
+        }
+    }
+
+    fn skip_reused_chunks(&mut self) {
+        while self.reused_chunks.contains(&self.next_chunk_index) {
+            self.next_chunk_index += 1;
+        }
+    }
+
+    // Continues the transfer after the chunks the receiver already has, as
+    // reported in response to the header.
+    fn acknowledge_chunks(&mut self, acknowledged_chunk_count: u32, reused_chunk_indices: &[u32]) {
+        self.awaiting_header = false;
+        self.resuming = false;
+
+        // The header carries the first chunk, hence it is always acknowledged.
+        let acknowledged_chunk_count = cmp::min(
+            cmp::max(acknowledged_chunk_count, 1) as u64,
+            self.chunk_count,
+        ) as u32;
+        self.reused_chunks = reused_chunk_indices
+            .iter()
+            .copied()
+            .filter(|chunk_index| {
+                *chunk_index >= acknowledged_chunk_count
+                    && u64::from(*chunk_index) < self.chunk_count
+            })
+            .collect();
+        self.sent_chunk_count = acknowledged_chunk_count as u64 + self.reused_chunks.len() as u64;
+
+        debug!(
+            self.logger,
+            "Continuing transfer: acknowledged {}, reused {} out of {} chunks",
+            acknowledged_chunk_count,
+            self.reused_chunks.len(),
+            self.chunk_count
+        );
+
+        self.next_chunk_index = acknowledged_chunk_count;
+        self.skip_reused_chunks();
     }
 
     fn process_response(
@@ -487,6 +868,12 @@ impl SnapshotSenderState {
                             )
                         {
                             self.sent_chunk_count += 1;
+                            if payload.chunk_index == 0 && self.awaiting_header {
+                                self.acknowledge_chunks(
+                                    payload.acknowledged_chunk_count,
+                                    &payload.reused_chunk_indices,
+                                );
+                            }
                             true
                         } else {
                             warn!(self.logger, "Receiver rejected delivery request");
@@ -495,6 +882,22 @@ impl SnapshotSenderState {
                     }
                 }
             }
+            Err(error) if self.resuming || self.remaining_retries > 0 => {
+                // Resume the transfer from the last acknowledged chunk once the
+                // chunks in flight are resolved. Failures of the chunks in flight
+                // are covered by the same retry.
+                warn!(
+                    self.logger,
+                    "Resuming transfer after delivery failure: {}", error
+                );
+                self.pending_chunks.remove(&delivery_id);
+                if !self.resuming {
+                    self.remaining_retries -= 1;
+                }
+                self.awaiting_header = false;
+                self.resuming = true;
+                true
+            }
             Err(error) => {
                 warn!(self.logger, "Rejecting delivery response: {}", error);
                 false
@@ -504,7 +907,7 @@ impl SnapshotSenderState {
         if !success {
             // The snapshot delivery has failed and we need to abort
             // snapshot transfer.
-            self.status = Some(RaftSnapshotStatus::Failure);
+            self.complete_with(RaftSnapshotStatus::Failure);
         }
     }
 
@@ -518,8 +921,8 @@ impl SnapshotSenderState {
 
     fn complete_with(&mut self, status: RaftSnapshotStatus) {
         self.status = Some(status);
-        // Free up the memory used by the snapshot.
-        self.snapshot_data = Bytes::new();
+        // Release the source so that the snapshot is not held past the transfer.
+        self.snapshot_source = None;
     }
 }
 
@@ -527,16 +930,20 @@ impl SnapshotSenderState {
 pub struct SnapshotSenderConfig {
     pub chunk_size: u64,
     pub max_pending_chunks: u32,
+    pub delta_transfer: bool,
+    pub max_delivery_retries: u32,
+    pub encrypt_payloads: bool,
 }
 
 pub struct DefaultSnapshotSender {
     logger: Logger,
     config: SnapshotSenderConfig,
     replica_id: u64,
-    instant: u64,
     next_snapshot_id: u32,
     next_delivery_id: u64,
     receivers: HashMap<u64, SnapshotSenderState>,
+    // Secrets of the attested sessions with the receivers by receiver id.
+    session_secrets: HashMap<u64, Bytes>,
 }
 
 impl DefaultSnapshotSender {
@@ -547,12 +954,15 @@ impl DefaultSnapshotSender {
                 // System defaults.
                 chunk_size: 1024 * 1024,
                 max_pending_chunks: 2,
+                delta_transfer: false,
+                max_delivery_retries: 0,
+                encrypt_payloads: false,
             },
             replica_id: 0,
-            instant: 0,
             next_snapshot_id: 1,
             next_delivery_id: 1,
             receivers: HashMap::new(),
+            session_secrets: HashMap::new(),
         }
     }
 }
@@ -563,14 +973,13 @@ impl SnapshotSenderImpl for DefaultSnapshotSender {
         self.replica_id = replica_id;
         if let Some(snapshot_config) = snapshot_config {
             self.config.chunk_size = snapshot_config.chunk_size;
-            self.config.max_pending_chunks = snapshot_config.max_pending_chunks
+            self.config.max_pending_chunks = snapshot_config.max_pending_chunks;
+            self.config.delta_transfer = snapshot_config.delta_transfer;
+            self.config.max_delivery_retries = snapshot_config.max_delivery_retries;
+            self.config.encrypt_payloads = snapshot_config.encrypt_payloads;
         }
     }
 
-    fn set_instant(&mut self, instant: u64) {
-        self.instant = instant;
-    }
-
     fn reset(&mut self) -> Vec<(u64, RaftSnapshotStatus)> {
         // All uncompleted snapshot transfers are considered failed.
         let mut cancellations: Vec<(u64, RaftSnapshotStatus)> =
@@ -585,6 +994,10 @@ impl SnapshotSenderImpl for DefaultSnapshotSender {
 
         cancellations
     }
+
+    fn set_session_secret(&mut self, replica_id: u64, session_secret: Bytes) {
+        self.session_secrets.insert(replica_id, session_secret);
+    }
 }
 
 impl SnapshotSender for DefaultSnapshotSender {
@@ -596,17 +1009,56 @@ impl SnapshotSender for DefaultSnapshotSender {
             snapshot.data.len()
         );
 
-        // Note that we rely on Raft protocol to initiate transfers.
-        // Hence we silently override any progress for the existing transfer.
-        self.receivers.insert(
-            receiver_id,
-            SnapshotSenderState::new(
+        // Lagging replicas are typically sent the same snapshot, in which
+        // case the transfers share the chunks of the first one.
+        let shared_state = snapshot.metadata.as_ref().and_then(|snapshot_metadata| {
+            self.receivers
+                .iter()
+                .find(|(id, state)| **id != receiver_id && state.sends(snapshot_metadata))
+                .map(|(_, state)| state)
+        });
+        let mut sender_state = match shared_state {
+            Some(shared_state) => {
+                debug!(
+                    self.logger,
+                    "Sharing snapshot chunks with transfer: snapshot id {}",
+                    shared_state.snapshot_id
+                );
+                shared_state.share(self.logger.clone(), self.next_snapshot_id, self.config)
+            }
+            None => SnapshotSenderState::new(
                 self.logger.clone(),
                 self.next_snapshot_id,
                 snapshot,
-                self.config.chunk_size,
+                self.config,
             ),
-        );
+        };
+
+        if self.config.encrypt_payloads {
+            match self.session_secrets.get(&receiver_id) {
+                Some(session_secret) => {
+                    sender_state.transfer_key = Some(derive_transfer_key(
+                        session_secret,
+                        self.replica_id,
+                        receiver_id,
+                        self.next_snapshot_id,
+                    ));
+                }
+                None => {
+                    // The transfer is retried by Raft once the session with the
+                    // receiver is established.
+                    warn!(
+                        self.logger,
+                        "Aborting transfer: no session with receiver {}", receiver_id
+                    );
+                    sender_state.complete_with(RaftSnapshotStatus::Failure);
+                }
+            }
+        }
+
+        // Note that we rely on Raft protocol to initiate transfers.
+        // Hence we silently override any progress for the existing transfer.
+        self.receivers.insert(receiver_id, sender_state);
         self.next_snapshot_id += 1;
     }
 
@@ -722,6 +1174,21 @@ impl SnapshotSender for DefaultSnapshotSender {
 
         result
     }
+
+    fn transfer_progress(&self) -> Option<u64> {
+        self.receivers
+            .values()
+            .filter(|sender_state| sender_state.status.is_none())
+            .map(|sender_state| sender_state.acknowledged_percentage())
+            .min()
+    }
+}
+
+// Last snapshot received through delta transfer, its chunks can be reused by
+// the next transfer.
+struct BaseSnapshot {
+    chunk_size: u64,
+    snapshot_data: Bytes,
 }
 
 struct ReceiverState {
@@ -732,7 +1199,15 @@ struct ReceiverState {
     snapshot_metadata: Bytes,
     chunk_size: u64,
     chunk_count: u64,
+    // Consecutive chunks received starting from the first one, appended to the
+    // snapshot as soon as they arrive.
+    snapshot_data: BytesMut,
+    assembled_chunk_count: u64,
+    // Chunks received ahead of the consecutive ones, held only until the gap
+    // before them is filled.
     chunks: HashMap<u64, Bytes>,
+    // Digests of all chunks if the snapshot is received through delta transfer.
+    chunk_digests: Vec<Bytes>,
 }
 
 impl ReceiverState {
@@ -743,10 +1218,11 @@ impl ReceiverState {
         snapshot_size: u64,
         snapshot_metadata: Bytes,
         first_chunk: Bytes,
+        chunk_manifest: Option<ChunkManifest>,
     ) -> ReceiverState {
         let chunk_size = first_chunk.len() as u64;
-        let mut chunks = HashMap::new();
-        chunks.insert(0, first_chunk);
+        let mut snapshot_data = BytesMut::new();
+        snapshot_data.put(first_chunk);
         ReceiverState {
             logger,
             sender_id,
@@ -755,10 +1231,74 @@ impl ReceiverState {
             snapshot_metadata,
             chunk_size,
             chunk_count: chunk_count(snapshot_size, chunk_size),
-            chunks,
+            snapshot_data,
+            assembled_chunk_count: 1,
+            chunks: HashMap::new(),
+            chunk_digests: chunk_manifest.map_or(Vec::new(), |manifest| manifest.chunk_digests),
         }
     }
 
+    // Percentage of the chunks received, whether assembled or held ahead of the
+    // consecutive ones.
+    fn received_percentage(&self) -> u64 {
+        (self.assembled_chunk_count + self.chunks.len() as u64) * 100 / self.chunk_count
+    }
+
+    // Appends the chunks that follow the consecutive ones to the snapshot.
+    fn assemble_chunks(&mut self) {
+        while let Some(chunk) = self.chunks.remove(&self.assembled_chunk_count) {
+            self.snapshot_data.put(chunk);
+            self.assembled_chunk_count += 1;
+        }
+    }
+
+    fn delta_transfer(&self) -> bool {
+        !self.chunk_digests.is_empty()
+    }
+
+    // Checks that the manifest describes all chunks including the first one.
+    fn verify_manifest(&self) -> bool {
+        if !self.delta_transfer() {
+            return true;
+        }
+
+        self.chunk_digests.len() as u64 == self.chunk_count
+            && chunk_digest(&self.snapshot_data[..self.chunk_size as usize])
+                == self.chunk_digests[0]
+    }
+
+    // Takes the chunks with matching digests from the base snapshot regardless
+    // of their position in it.
+    fn reuse_chunks(&mut self, base: &BaseSnapshot) {
+        if !self.delta_transfer() {
+            return;
+        }
+
+        let base_chunks: HashMap<Bytes, Bytes> = split_chunks(&base.snapshot_data, base.chunk_size)
+            .map(|chunk| (chunk_digest(&chunk), chunk))
+            .collect();
+
+        for chunk_index in 1..self.chunk_count {
+            if let Some(chunk) = base_chunks.get(&self.chunk_digests[chunk_index as usize]) {
+                self.chunks.insert(chunk_index, chunk.clone());
+            }
+        }
+        self.assemble_chunks();
+    }
+
+    // Gets the number of consecutive chunks received starting from the first one
+    // along with the indices of the other chunks received beyond them.
+    fn received_chunks(&self) -> (u32, Vec<u32>) {
+        let mut received_chunk_indices: Vec<u32> = self
+            .chunks
+            .keys()
+            .map(|chunk_index| *chunk_index as u32)
+            .collect();
+        received_chunk_indices.sort();
+
+        (self.assembled_chunk_count as u32, received_chunk_indices)
+    }
+
     fn accept_chunk(&mut self, sender_id: u64, index: u64, chunk_contents: Bytes) -> bool {
         if sender_id != self.sender_id {
             return false;
@@ -769,15 +1309,22 @@ impl ReceiverState {
             || (index < self.chunk_count - 1 && chunk_size != self.chunk_size)
             || (index == self.chunk_count - 1
                 && chunk_size != self.snapshot_size - (self.chunk_count - 1) * self.chunk_size)
+            || (self.delta_transfer()
+                && chunk_digest(&chunk_contents) != self.chunk_digests[index as usize])
         {
             return false;
         }
-        self.chunks.insert(index, chunk_contents);
+        // Chunks already appended to the snapshot are delivered again only if
+        // the response has been lost, hence they are acknowledged again.
+        if index >= self.assembled_chunk_count {
+            self.chunks.insert(index, chunk_contents);
+            self.assemble_chunks();
+        }
         true
     }
 
     fn try_complete(&mut self) -> Option<Result<(u64, RaftSnapshot), SnapshotError>> {
-        if self.chunks.len() as u64 != self.chunk_count {
+        if self.assembled_chunk_count != self.chunk_count {
             return None;
         }
         // Decode snapshot metadata.
@@ -785,11 +1332,8 @@ impl ReceiverState {
         if snapshot_metadata.is_err() {
             return Some(Err(SnapshotError::Corrupted));
         }
-        // Copy snapshot data. Will try to remove the copy once Raft switch to use Bytes.
-        let mut snapshot_data = BytesMut::with_capacity(self.snapshot_size.try_into().unwrap());
-        for c in 0..self.chunk_count {
-            snapshot_data.put(self.chunks.remove(&c).unwrap());
-        }
+        // Snapshot has been assembled as the chunks arrived.
+        let snapshot_data = mem::take(&mut self.snapshot_data);
 
         let snapshot = RaftSnapshot {
             data: snapshot_data.into(),
@@ -811,8 +1355,11 @@ impl ReceiverState {
 pub struct DefaultSnapshotReceiver {
     logger: Logger,
     replica_id: u64,
-    instant: u64,
+    encrypt_payloads: bool,
     state: Option<ReceiverState>,
+    base: Option<BaseSnapshot>,
+    // Secrets of the attested sessions with the senders by sender id.
+    session_secrets: HashMap<u64, Bytes>,
 }
 
 impl DefaultSnapshotReceiver {
@@ -820,25 +1367,57 @@ impl DefaultSnapshotReceiver {
         DefaultSnapshotReceiver {
             logger: create_logger(),
             replica_id: 0,
-            instant: 0,
+            encrypt_payloads: false,
             state: None,
+            base: None,
+            session_secrets: HashMap::new(),
         }
     }
+
+    // Obtains the serialized payload of the request, decrypting it if the
+    // payloads are encrypted.
+    fn payload_contents(&self, request: &DeliverSnapshotRequest) -> Result<Bytes, SnapshotError> {
+        if !self.encrypt_payloads {
+            return Ok(request.payload_contents.clone());
+        }
+
+        let encrypted_payload = EncryptedSnapshotPayload::decode(request.payload_contents.clone())
+            .map_err(|_| SnapshotError::Corrupted)?;
+        let session_secret = self
+            .session_secrets
+            .get(&request.sender_replica_id)
+            .ok_or(SnapshotError::FailedEncryption)?;
+        let transfer_key = derive_transfer_key(
+            session_secret,
+            request.sender_replica_id,
+            self.replica_id,
+            encrypted_payload.snapshot_id,
+        );
+        decrypt_payload(
+            &transfer_key,
+            request.delivery_id,
+            &encrypted_payload.ciphertext,
+        )
+        .map(Bytes::from)
+    }
 }
 
 impl SnapshotReceiverImpl for DefaultSnapshotReceiver {
-    fn init(&mut self, logger: Logger, replica_id: u64) {
+    fn init(&mut self, logger: Logger, replica_id: u64, snapshot_config: &Option<SnapshotConfig>) {
         self.logger = logger;
         self.replica_id = replica_id;
-    }
-
-    fn set_instant(&mut self, instant: u64) {
-        self.instant = instant;
+        if let Some(snapshot_config) = snapshot_config {
+            self.encrypt_payloads = snapshot_config.encrypt_payloads;
+        }
     }
 
     fn reset(&mut self) {
         self.state = None;
     }
+
+    fn set_session_secret(&mut self, replica_id: u64, session_secret: Bytes) {
+        self.session_secrets.insert(replica_id, session_secret);
+    }
 }
 
 impl SnapshotReceiver for DefaultSnapshotReceiver {
@@ -855,34 +1434,79 @@ impl SnapshotReceiver for DefaultSnapshotReceiver {
             ..Default::default()
         };
 
-        match deliver_snapshot_request::Payload::decode(request.payload_contents) {
+        let payload_result = self
+            .payload_contents(&request)
+            .and_then(|payload_contents| {
+                deliver_snapshot_request::Payload::decode(payload_contents)
+                    .map_err(|_| SnapshotError::Corrupted)
+            });
+        match payload_result {
             Ok(payload) => {
                 match payload.it {
                     None => {}
                     Some(deliver_snapshot_request::payload::It::Header(header)) => {
-                        info!(self.logger,
-                            "Starting snapshot receiving: sender {}, snapshot id {}, snapshot size {}",
-                        request.sender_replica_id,
-                        payload.snapshot_id,
-                        header.snapshot_size);
-
-                        // Received new header, must reset any progress as there can only
-                        // be one snapshot at a time.
-                        self.reset();
-                        // Initiate new snapshot.
-                        self.state = Some(ReceiverState::new(
-                            self.logger.clone(),
-                            request.sender_replica_id,
-                            payload.snapshot_id,
-                            header.snapshot_size,
-                            header.snapshot_metadata.clone(),
-                            header.chunk_contents,
-                        ));
                         // Respond to the snapshot sender.
                         response_payload.snapshot_id = payload.snapshot_id;
                         response_payload.chunk_index = 0;
-                        response_payload.status =
-                            DeliverSnapshotStatus::SnapshotStatusAccepted.into();
+
+                        let resumed = header.resume
+                            && self.state.as_ref().is_some_and(|state| {
+                                state.sender_id == request.sender_replica_id
+                                    && state.snapshot_id == payload.snapshot_id
+                            });
+                        if resumed {
+                            info!(
+                                self.logger,
+                                "Resuming snapshot receiving: sender {}, snapshot id {}",
+                                request.sender_replica_id,
+                                payload.snapshot_id
+                            );
+
+                            response_payload.status =
+                                DeliverSnapshotStatus::SnapshotStatusAccepted.into();
+                        } else {
+                            info!(self.logger,
+                                "Starting snapshot receiving: sender {}, snapshot id {}, snapshot size {}",
+                            request.sender_replica_id,
+                            payload.snapshot_id,
+                            header.snapshot_size);
+
+                            // Received new header, must reset any progress as there can only
+                            // be one snapshot at a time.
+                            self.reset();
+                            // Initiate new snapshot.
+                            let mut state = ReceiverState::new(
+                                self.logger.clone(),
+                                request.sender_replica_id,
+                                payload.snapshot_id,
+                                header.snapshot_size,
+                                header.snapshot_metadata.clone(),
+                                header.chunk_contents,
+                                header.chunk_manifest,
+                            );
+                            if state.verify_manifest() {
+                                if let Some(base) = &self.base {
+                                    state.reuse_chunks(base);
+                                }
+                                self.state = Some(state);
+                                response_payload.status =
+                                    DeliverSnapshotStatus::SnapshotStatusAccepted.into();
+                            } else {
+                                warn!(self.logger, "Rejecting payload: invalid chunk manifest");
+                                response_payload.status =
+                                    DeliverSnapshotStatus::SnapshotStatusRejected.into();
+                            }
+                        }
+
+                        // Report the chunks the sender can skip.
+                        if let Some(state) = &self.state
+                            && (header.resume || state.delta_transfer())
+                        {
+                            (
+                                response_payload.acknowledged_chunk_count,
+                                response_payload.reused_chunk_indices,
+                            ) = state.received_chunks();
+                        }
                     }
                     Some(deliver_snapshot_request::payload::It::Chunk(chunk)) => {
                         response_payload.snapshot_id = payload.snapshot_id;
@@ -930,12 +1554,26 @@ impl SnapshotReceiver for DefaultSnapshotReceiver {
 
     fn try_complete(&mut self) -> Option<Result<(u64, RaftSnapshot), SnapshotError>> {
         let result = self.state.as_mut().map(|s| s.try_complete()).flatten();
+        // Keep snapshot received through delta transfer to reuse its chunks.
+        if let Some(Ok((_, snapshot))) = &result
+            && let Some(state) = &self.state
+            && state.delta_transfer()
+        {
+            self.base = Some(BaseSnapshot {
+                chunk_size: state.chunk_size,
+                snapshot_data: snapshot.data.clone().into(),
+            });
+        }
         // Reset state if snapshot has been succefully received.
         if result.is_some() {
             self.reset()
         }
         result
     }
+
+    fn transfer_progress(&self) -> Option<u64> {
+        self.state.as_ref().map(|state| state.received_percentage())
+    }
 }
 
 #[cfg(all(test, feature = "std"))]
@@ -945,6 +1583,7 @@ mod test {
     use self::mockall::predicate::{always, eq};
     use super::*;
     use alloc::vec;
+    use core::cell::RefCell;
     use core::matches;
     use mock::{MockSnapshotReceiver, MockSnapshotSender};
 
@@ -984,14 +1623,7 @@ mod test {
     fn expect_receiver_init(mock_receiver: &mut MockSnapshotReceiver, replica_id: u64) {
         mock_receiver
             .expect_init()
-            .with(always(), eq(replica_id))
-            .return_const(());
-    }
-
-    fn expect_sender_set_instant(mock_sender: &mut MockSnapshotSender, instant: u64) {
-        mock_sender
-            .expect_set_instant()
-            .with(eq(instant))
+            .with(always(), eq(replica_id), always())
             .return_const(());
     }
 
@@ -1002,40 +1634,66 @@ mod test {
         mock_sender.expect_reset().return_const(cancellations);
     }
 
-    fn expect_receiver_set_instant(mock_receiver: &mut MockSnapshotReceiver, instant: u64) {
-        mock_receiver
-            .expect_set_instant()
-            .with(eq(instant))
-            .return_const(());
-    }
-
     fn expect_receiver_reset(mock_receiver: &mut MockSnapshotReceiver) {
         mock_receiver.expect_reset().return_const(());
     }
 
+    #[test]
+    fn test_attestation_policy_header() {
+        let policy = AttestationPolicy {
+            allowed_measurements: vec![vec![1; 48]],
+            allow_debug: true,
+            ..Default::default()
+        };
+        let mut snapshot_buffer = SnapshotBuffer::new();
+        write_attestation_policy_header(&mut snapshot_buffer, &policy).unwrap();
+        snapshot_buffer.write(&[1, 2, 3]).unwrap();
+        assert_eq!(
+            split_attestation_policy(snapshot_buffer.into_bytes()),
+            Ok((Some(policy), Bytes::from_static(&[1, 2, 3])))
+        );
+
+        // Snapshots without the header carry no policy.
+        assert_eq!(
+            split_attestation_policy(Bytes::from_static(&[1, 2, 3])),
+            Ok((None, Bytes::from_static(&[1, 2, 3])))
+        );
+
+        // Truncated header is rejected.
+        let mut snapshot_buffer = SnapshotBuffer::new();
+        write_attestation_policy_header(&mut snapshot_buffer, &AttestationPolicy::default())
+            .unwrap();
+        snapshot_buffer.write(&[1]).unwrap();
+        let mut truncated = snapshot_buffer.into_bytes().to_vec();
+        truncated[ATTESTATION_POLICY_MAGIC.len()] = 2;
+        assert_eq!(
+            split_attestation_policy(truncated.into()),
+            Err(SnapshotError::Corrupted)
+        );
+    }
+
     #[test]
     fn test_snapshot_processor_starts_receiver() {
-        let (instant, replica_id) = (10, REPLICA_1);
+        let replica_id = REPLICA_1;
 
         let mut mock_sender = Box::new(MockSnapshotSender::new());
         expect_sender_init(&mut mock_sender, replica_id);
 
         let mut mock_receiver = Box::new(MockSnapshotReceiver::new());
         expect_receiver_init(&mut mock_receiver, replica_id);
-        expect_receiver_set_instant(&mut mock_receiver, instant);
 
         let mut snapshot_processor =
             create_and_init_processor(replica_id, mock_sender, mock_receiver);
 
         assert!(matches!(
-            snapshot_processor.mut_processor(instant),
+            snapshot_processor.mut_processor(),
             SnapshotProcessorRole::Receiver(_)
         ));
     }
 
     #[test]
     fn test_snapshot_processor_cluster_change_remains_follower_leader_changes() {
-        let (instant, replica_id) = (10, REPLICA_1);
+        let replica_id = REPLICA_1;
 
         let mut mock_sender = Box::new(MockSnapshotSender::new());
         expect_sender_init(&mut mock_sender, replica_id);
@@ -1043,7 +1701,6 @@ mod test {
         let mut mock_receiver = Box::new(MockSnapshotReceiver::new());
         expect_receiver_init(&mut mock_receiver, replica_id);
         expect_receiver_reset(&mut mock_receiver);
-        expect_receiver_set_instant(&mut mock_receiver, instant);
 
         let mut snapshot_processor =
             create_and_init_processor(replica_id, mock_sender, mock_receiver);
@@ -1058,14 +1715,14 @@ mod test {
         );
 
         assert!(matches!(
-            snapshot_processor.mut_processor(instant),
+            snapshot_processor.mut_processor(),
             SnapshotProcessorRole::Receiver(_)
         ));
     }
 
     #[test]
     fn test_snapshot_processor_cluster_change_remains_follower_leader_term_changes() {
-        let (instant, replica_id) = (10, REPLICA_1);
+        let replica_id = REPLICA_1;
 
         let mut mock_sender = Box::new(MockSnapshotSender::new());
         expect_sender_init(&mut mock_sender, replica_id);
@@ -1073,7 +1730,6 @@ mod test {
         let mut mock_receiver = Box::new(MockSnapshotReceiver::new());
         expect_receiver_init(&mut mock_receiver, replica_id);
         expect_receiver_reset(&mut mock_receiver);
-        expect_receiver_set_instant(&mut mock_receiver, instant);
 
         let mut snapshot_processor =
             create_and_init_processor(replica_id, mock_sender, mock_receiver);
@@ -1088,18 +1744,17 @@ mod test {
         );
 
         assert!(matches!(
-            snapshot_processor.mut_processor(instant),
+            snapshot_processor.mut_processor(),
             SnapshotProcessorRole::Receiver(_)
         ));
     }
 
     #[test]
     fn test_snapshot_processor_cluster_change_becomes_leader() {
-        let (instant, replica_id) = (10, REPLICA_1);
+        let replica_id = REPLICA_1;
 
         let mut mock_sender = Box::new(MockSnapshotSender::new());
         expect_sender_init(&mut mock_sender, replica_id);
-        expect_sender_set_instant(&mut mock_sender, instant);
 
         let mut mock_receiver = Box::new(MockSnapshotReceiver::new());
         expect_receiver_init(&mut mock_receiver, replica_id);
@@ -1114,14 +1769,14 @@ mod test {
         );
 
         assert!(matches!(
-            snapshot_processor.mut_processor(instant),
+            snapshot_processor.mut_processor(),
             SnapshotProcessorRole::Sender(_)
         ));
     }
 
     #[test]
     fn test_snapshot_processor_cluster_change_becomes_follower() {
-        let (instant, replica_id) = (10, REPLICA_1);
+        let replica_id = REPLICA_1;
 
         let cancellations = vec![(1, RaftSnapshotStatus::Failure)];
 
@@ -1132,7 +1787,6 @@ mod test {
         let mut mock_receiver = Box::new(MockSnapshotReceiver::new());
         expect_receiver_init(&mut mock_receiver, replica_id);
         expect_receiver_reset(&mut mock_receiver);
-        expect_receiver_set_instant(&mut mock_receiver, instant);
 
         let mut snapshot_processor =
             create_and_init_processor(replica_id, mock_sender, mock_receiver);
@@ -1155,21 +1809,20 @@ mod test {
         );
 
         assert!(matches!(
-            snapshot_processor.mut_processor(instant),
+            snapshot_processor.mut_processor(),
             SnapshotProcessorRole::Receiver(_)
         ));
     }
 
     #[test]
     fn test_snapshot_processor_cluster_change_remains_leader() {
-        let (instant, replica_id) = (10, REPLICA_1);
+        let replica_id = REPLICA_1;
 
         let cancellations = vec![(1, RaftSnapshotStatus::Failure)];
 
         let mut mock_sender = Box::new(MockSnapshotSender::new());
         expect_sender_init(&mut mock_sender, replica_id);
         expect_sender_reset(&mut mock_sender, cancellations.clone());
-        expect_sender_set_instant(&mut mock_sender, instant);
 
         let mut mock_receiver = Box::new(MockSnapshotReceiver::new());
         expect_receiver_init(&mut mock_receiver, replica_id);
@@ -1196,7 +1849,7 @@ mod test {
         );
 
         assert!(matches!(
-            snapshot_processor.mut_processor(instant),
+            snapshot_processor.mut_processor(),
             SnapshotProcessorRole::Sender(_)
         ));
     }
@@ -1223,6 +1876,8 @@ mod test {
             snapshot_size,
             snapshot_metadata,
             chunk_contents,
+            chunk_manifest: None,
+            resume: false,
         };
 
         let payload = deliver_snapshot_request::Payload {
@@ -1294,7 +1949,9 @@ mod test {
             deliver_snapshot_response::Payload {
                 snapshot_id,
                 chunk_index,
-                status: DeliverSnapshotStatus::SnapshotStatusAccepted.into()
+                status: DeliverSnapshotStatus::SnapshotStatusAccepted.into(),
+                reused_chunk_indices: vec![],
+                acknowledged_chunk_count: 0,
             }
         );
     }
@@ -1313,13 +1970,16 @@ mod test {
             deliver_snapshot_response::Payload {
                 snapshot_id,
                 chunk_index,
-                status: DeliverSnapshotStatus::SnapshotStatusRejected.into()
+                status: DeliverSnapshotStatus::SnapshotStatusRejected.into(),
+                reused_chunk_indices: vec![],
+                acknowledged_chunk_count: 0,
             }
         );
     }
 
     const DELIVERY_1: u64 = 1;
     const DELIVERY_2: u64 = 2;
+    const DELIVERY_3: u64 = 3;
 
     const SNAPSHOT_1: u32 = 1;
     const SNAPSHOT_2: u32 = 2;
@@ -1356,6 +2016,7 @@ mod test {
         let metadata = default_snapshot_metadata();
 
         let data = Bytes::from(vec![1, 2, 3, 4, 5]);
+        assert_eq!(receiver.transfer_progress(), None);
 
         assert_deliver_snapshot_accepted(
             receiver.process_request(create_deliver_snapshot_request_header(
@@ -1374,6 +2035,7 @@ mod test {
         let complete_result = receiver.try_complete();
 
         assert!(complete_result.is_none());
+        assert_eq!(receiver.transfer_progress(), Some(50));
 
         assert_deliver_snapshot_accepted(
             receiver.process_request(create_deliver_snapshot_request_chunk(
@@ -1388,6 +2050,95 @@ mod test {
             1,
         );
 
+        assert_snapshot_success(receiver.try_complete(), REPLICA_1, data, metadata);
+        assert_eq!(receiver.transfer_progress(), None);
+    }
+
+    #[test]
+    fn test_snapshot_receiver_assembles_chunks_as_they_arrive() {
+        let mut receiver = DefaultSnapshotReceiver::new();
+
+        let metadata = default_snapshot_metadata();
+
+        let data = Bytes::from(vec![1, 2, 3, 4, 5, 6, 7]);
+
+        assert_deliver_snapshot_accepted(
+            receiver.process_request(create_deliver_snapshot_request_header(
+                REPLICA_1,
+                SNAPSHOT_1,
+                DELIVERY_1,
+                data.len() as u64,
+                metadata.encode_to_vec().into(),
+                data.slice(0..2),
+            )),
+            SNAPSHOT_1,
+            DELIVERY_1,
+            0,
+        );
+
+        // Chunk received ahead of the consecutive ones is held apart.
+        assert_deliver_snapshot_accepted(
+            receiver.process_request(create_deliver_snapshot_request_chunk(
+                REPLICA_1,
+                SNAPSHOT_1,
+                DELIVERY_1,
+                2,
+                data.slice(4..6),
+            )),
+            SNAPSHOT_1,
+            DELIVERY_1,
+            2,
+        );
+        let state = receiver.state.as_ref().unwrap();
+        assert_eq!(state.snapshot_data, data.slice(0..2));
+        assert_eq!(state.received_chunks(), (1, vec![2]));
+
+        // Chunk filling the gap is appended along with the chunks after it.
+        assert_deliver_snapshot_accepted(
+            receiver.process_request(create_deliver_snapshot_request_chunk(
+                REPLICA_1,
+                SNAPSHOT_1,
+                DELIVERY_1,
+                1,
+                data.slice(2..4),
+            )),
+            SNAPSHOT_1,
+            DELIVERY_1,
+            1,
+        );
+        let state = receiver.state.as_ref().unwrap();
+        assert_eq!(state.snapshot_data, data.slice(0..6));
+        assert!(state.chunks.is_empty());
+        assert_eq!(state.received_chunks(), (3, vec![]));
+        assert!(receiver.try_complete().is_none());
+
+        // Chunk delivered again is acknowledged without being appended twice.
+        assert_deliver_snapshot_accepted(
+            receiver.process_request(create_deliver_snapshot_request_chunk(
+                REPLICA_1,
+                SNAPSHOT_1,
+                DELIVERY_1,
+                1,
+                data.slice(2..4),
+            )),
+            SNAPSHOT_1,
+            DELIVERY_1,
+            1,
+        );
+
+        assert_deliver_snapshot_accepted(
+            receiver.process_request(create_deliver_snapshot_request_chunk(
+                REPLICA_1,
+                SNAPSHOT_1,
+                DELIVERY_1,
+                3,
+                data.slice(6..7),
+            )),
+            SNAPSHOT_1,
+            DELIVERY_1,
+            3,
+        );
+
         assert_snapshot_success(receiver.try_complete(), REPLICA_1, data, metadata);
     }
 
@@ -1621,6 +2372,8 @@ mod test {
             snapshot_id,
             chunk_index,
             status: status.into(),
+            reused_chunk_indices: vec![],
+            acknowledged_chunk_count: 0,
         };
 
         DeliverSnapshotResponse {
@@ -1636,6 +2389,9 @@ mod test {
             snapshot_count: 1000,
             chunk_size: 3,
             max_pending_chunks: 1,
+            delta_transfer: false,
+            max_delivery_retries: 0,
+            encrypt_payloads: false,
         }
     }
 
@@ -1647,6 +2403,84 @@ mod test {
         sender
     }
 
+    #[test]
+    fn test_snapshot_buffer() {
+        let mut snapshot_buffer = SnapshotBuffer::new();
+        snapshot_buffer.write(&[1, 2, 3]).unwrap();
+        snapshot_buffer.write(&[]).unwrap();
+        snapshot_buffer.write(&[4, 5]).unwrap();
+
+        assert_eq!(
+            snapshot_buffer.into_bytes(),
+            Bytes::from(vec![1, 2, 3, 4, 5])
+        );
+    }
+
+    // Records the offsets of the chunks produced by the source.
+    struct RecordingSnapshotSource {
+        snapshot_data: Bytes,
+        read_offsets: RefCell<Vec<u64>>,
+    }
+
+    impl SnapshotSource for RecordingSnapshotSource {
+        fn size(&self) -> u64 {
+            self.snapshot_data.size()
+        }
+
+        fn read_chunk(&self, offset: u64, chunk_size: u64) -> Bytes {
+            self.read_offsets.borrow_mut().push(offset);
+            self.snapshot_data.read_chunk(offset, chunk_size)
+        }
+    }
+
+    #[test]
+    fn test_snapshot_source_read_chunk() {
+        let snapshot_data = Bytes::from(vec![1, 2, 3, 4, 5]);
+
+        assert_eq!(snapshot_data.size(), 5);
+        assert_eq!(snapshot_data.read_chunk(0, 2), Bytes::from(vec![1, 2]));
+        assert_eq!(snapshot_data.read_chunk(4, 2), Bytes::from(vec![5]));
+        assert_eq!(snapshot_data.read_chunk(6, 2), Bytes::new());
+        assert_eq!(
+            snapshot_data.read_chunk(2, u64::MAX),
+            Bytes::from(vec![3, 4, 5])
+        );
+    }
+
+    #[test]
+    fn test_snapshot_sender_reads_chunks_on_demand() {
+        let snapshot_source = Rc::new(RecordingSnapshotSource {
+            snapshot_data: Bytes::from(vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]),
+            read_offsets: RefCell::new(Vec::new()),
+        });
+        let mut sender_state = SnapshotSenderState::with_contents(
+            create_logger(),
+            1,
+            default_snapshot_metadata(),
+            snapshot_source.clone(),
+            None,
+            SnapshotSenderConfig {
+                chunk_size: 4,
+                max_pending_chunks: 2,
+                delta_transfer: false,
+                max_delivery_retries: 0,
+                encrypt_payloads: false,
+            },
+        );
+
+        // Nothing is produced until the chunks are sent.
+        assert!(snapshot_source.read_offsets.borrow().is_empty());
+
+        assert!(sender_state.next_chunk(1).is_some());
+        assert!(sender_state.next_chunk(2).is_some());
+        assert_eq!(*snapshot_source.read_offsets.borrow(), vec![0, 4]);
+
+        // Source is released once the transfer completes.
+        sender_state.complete_with(RaftSnapshotStatus::Failure);
+        assert_eq!(Rc::strong_count(&snapshot_source), 1);
+        assert!(sender_state.next_chunk(3).is_none());
+    }
+
     #[test]
     fn test_snapshot_sender_reset_cancellations() {
         let mut sender = create_sender();
@@ -1743,6 +2577,7 @@ mod test {
         );
 
         assert_eq!(sender.try_complete(), None);
+        assert_eq!(sender.transfer_progress(), Some(0));
 
         assert_eq!(
             sender.next_request(),
@@ -1772,6 +2607,7 @@ mod test {
                 DeliverSnapshotStatus::SnapshotStatusAccepted,
             )),
         );
+        assert_eq!(sender.transfer_progress(), Some(50));
 
         assert_eq!(
             sender.next_request(),
@@ -1803,6 +2639,7 @@ mod test {
             sender.try_complete(),
             Some((REPLICA_1, RaftSnapshotStatus::Finish))
         );
+        assert_eq!(sender.transfer_progress(), None);
     }
 
     #[test]
@@ -2040,6 +2877,9 @@ mod test {
                     snapshot_count: 1000,
                     chunk_size: chunk_size as u64,
                     max_pending_chunks: max_pending_chunks as u32,
+                    delta_transfer: false,
+                    max_delivery_retries: 0,
+                    encrypt_payloads: false,
                 });
                 let mut sender = create_sender();
                 sender.init(create_logger(), REPLICA_0, &config);
@@ -2104,4 +2944,324 @@ mod test {
             }
         }
     }
+
+    // Transfers the snapshot to the receiver and returns the indices of the
+    // chunks sent.
+    fn transfer_snapshot(
+        sender: &mut DefaultSnapshotSender,
+        receiver: &mut DefaultSnapshotReceiver,
+        snapshot: RaftSnapshot,
+    ) -> (Vec<u32>, RaftSnapshot) {
+        sender.start(REPLICA_1, snapshot);
+
+        let mut sent_chunk_indices = Vec::new();
+        loop {
+            while let Some(request) = sender.next_request() {
+                let delivery_id = request.delivery_id;
+                let payload =
+                    deliver_snapshot_request::Payload::decode(request.payload_contents.clone())
+                        .unwrap();
+                sent_chunk_indices.push(match payload.it {
+                    Some(deliver_snapshot_request::payload::It::Chunk(chunk)) => chunk.chunk_index,
+                    _ => CHUNK_0,
+                });
+                let response = receiver.process_request(request);
+                sender.process_response(REPLICA_1, delivery_id, Ok(response));
+            }
+
+            if let Some(result) = sender.try_complete() {
+                assert_eq!(result, (REPLICA_1, RaftSnapshotStatus::Finish));
+                break;
+            }
+        }
+
+        let (sender_id, snapshot) = receiver.try_complete().unwrap().unwrap();
+        assert_eq!(sender_id, REPLICA_0);
+        (sent_chunk_indices, snapshot)
+    }
+
+    #[test]
+    fn test_snapshot_sender_receiver_fan_out() {
+        let mut sender = create_sender();
+        sender.init(
+            create_logger(),
+            REPLICA_0,
+            &Some(SnapshotConfig {
+                max_pending_chunks: 2,
+                ..default_snapshot_config()
+            }),
+        );
+        let mut receivers = HashMap::new();
+        for replica_id in [REPLICA_1, REPLICA_2] {
+            let mut receiver = DefaultSnapshotReceiver::new();
+            receiver.init(create_logger(), replica_id, &None);
+            receivers.insert(replica_id, receiver);
+        }
+        let metadata = default_snapshot_metadata();
+        let data = Bytes::from(vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+
+        // Both transfers of the same snapshot share its chunks.
+        sender.start(
+            REPLICA_1,
+            create_raft_snapshot(metadata.clone(), data.clone()),
+        );
+        sender.start(
+            REPLICA_2,
+            create_raft_snapshot(metadata.clone(), data.clone()),
+        );
+        assert!(Rc::ptr_eq(
+            sender.receivers[&REPLICA_1]
+                .snapshot_source
+                .as_ref()
+                .unwrap(),
+            sender.receivers[&REPLICA_2]
+                .snapshot_source
+                .as_ref()
+                .unwrap()
+        ));
+
+        // Chunks are sent to both replicas concurrently.
+        let mut recipient_replica_ids = Vec::new();
+        let mut completed_replica_ids = HashSet::new();
+        while completed_replica_ids.len() < 2 {
+            let mut requests = Vec::new();
+            while let Some(request) = sender.next_request() {
+                requests.push(request);
+            }
+            for request in requests {
+                let recipient_replica_id = request.recipient_replica_id;
+                let delivery_id = request.delivery_id;
+                recipient_replica_ids.push(recipient_replica_id);
+                let response = receivers
+                    .get_mut(&recipient_replica_id)
+                    .unwrap()
+                    .process_request(request);
+                sender.process_response(recipient_replica_id, delivery_id, Ok(response));
+            }
+            while let Some((replica_id, status)) = sender.try_complete() {
+                assert_eq!(status, RaftSnapshotStatus::Finish);
+                completed_replica_ids.insert(replica_id);
+            }
+        }
+        let mut first_recipient_replica_ids = recipient_replica_ids[0..2].to_vec();
+        first_recipient_replica_ids.sort();
+        assert_eq!(first_recipient_replica_ids, vec![REPLICA_1, REPLICA_2]);
+        assert_eq!(recipient_replica_ids.len(), 8);
+
+        for receiver in receivers.values_mut() {
+            let (sender_id, snapshot) = receiver.try_complete().unwrap().unwrap();
+            assert_eq!(sender_id, REPLICA_0);
+            assert_eq!(snapshot.data, data);
+        }
+    }
+
+    #[test]
+    fn test_snapshot_sender_receiver_encrypted_transfer() {
+        let snapshot_config = Some(SnapshotConfig {
+            max_pending_chunks: 2,
+            encrypt_payloads: true,
+            ..default_snapshot_config()
+        });
+        let session_secret = Bytes::from(vec![7; 32]);
+        let mut sender = create_sender();
+        sender.init(create_logger(), REPLICA_0, &snapshot_config);
+        sender.set_session_secret(REPLICA_1, session_secret.clone());
+        let mut receiver = DefaultSnapshotReceiver::new();
+        receiver.init(create_logger(), REPLICA_1, &snapshot_config);
+        receiver.set_session_secret(REPLICA_0, session_secret.clone());
+        let metadata = default_snapshot_metadata();
+        let data = Bytes::from(vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+
+        let (sent_chunk_indices, snapshot) = transfer_snapshot(
+            &mut sender,
+            &mut receiver,
+            create_raft_snapshot(metadata.clone(), data.clone()),
+        );
+        assert_eq!(sent_chunk_indices, vec![0, 1, 2, 3]);
+        assert_eq!(snapshot.data, data);
+
+        // Payloads are not readable without the key of the transfer.
+        sender.start(
+            REPLICA_1,
+            create_raft_snapshot(metadata.clone(), data.clone()),
+        );
+        let request = sender.next_request().unwrap();
+        let encrypted_payload =
+            EncryptedSnapshotPayload::decode(request.payload_contents.clone()).unwrap();
+        assert_eq!(encrypted_payload.snapshot_id, SNAPSHOT_2);
+        let mut other_receiver = DefaultSnapshotReceiver::new();
+        other_receiver.init(create_logger(), REPLICA_1, &snapshot_config);
+        other_receiver.set_session_secret(REPLICA_0, Bytes::from(vec![8; 32]));
+        let response = other_receiver.process_request(request);
+        let response_payload =
+            deliver_snapshot_response::Payload::decode(response.payload_contents).unwrap();
+        assert_eq!(
+            response_payload.status(),
+            DeliverSnapshotStatus::SnapshotStatusRejected
+        );
+
+        // Transfers to replicas without established session fail.
+        sender.start(REPLICA_2, create_raft_snapshot(metadata, data));
+        assert_eq!(
+            sender.try_complete(),
+            Some((REPLICA_2, RaftSnapshotStatus::Failure))
+        );
+    }
+
+    #[test]
+    fn test_snapshot_sender_receiver_delta_transfer() {
+        let mut sender = create_sender();
+        sender.init(
+            create_logger(),
+            REPLICA_0,
+            &Some(SnapshotConfig {
+                delta_transfer: true,
+                max_pending_chunks: 2,
+                ..default_snapshot_config()
+            }),
+        );
+        let mut receiver = DefaultSnapshotReceiver::new();
+        receiver.init(create_logger(), REPLICA_1, &None);
+        let metadata = default_snapshot_metadata();
+
+        // The first snapshot is transferred in full.
+        let data_1 = Bytes::from(vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        let (sent_chunk_indices, snapshot) = transfer_snapshot(
+            &mut sender,
+            &mut receiver,
+            create_raft_snapshot(metadata.clone(), data_1.clone()),
+        );
+        assert_eq!(sent_chunk_indices, vec![0, 1, 2, 3]);
+        assert_eq!(snapshot.data, data_1);
+
+        // Only the changed chunks of the next snapshot are transferred, the
+        // unchanged chunks are reused even if they have moved.
+        let data_2 = Bytes::from(vec![1, 2, 3, 7, 8, 9, 0, 0, 0, 4, 5]);
+        let (sent_chunk_indices, snapshot) = transfer_snapshot(
+            &mut sender,
+            &mut receiver,
+            create_raft_snapshot(metadata.clone(), data_2.clone()),
+        );
+        assert_eq!(sent_chunk_indices, vec![0, 2, 3]);
+        assert_eq!(snapshot.data, data_2);
+        assert_eq!(snapshot.metadata, Some(metadata));
+    }
+
+    #[test]
+    fn test_snapshot_sender_receiver_resume_transfer() {
+        let mut sender = create_sender();
+        sender.init(
+            create_logger(),
+            REPLICA_0,
+            &Some(SnapshotConfig {
+                max_pending_chunks: 2,
+                max_delivery_retries: 1,
+                ..default_snapshot_config()
+            }),
+        );
+        let mut receiver = DefaultSnapshotReceiver::new();
+        receiver.init(create_logger(), REPLICA_1, &None);
+        let metadata = default_snapshot_metadata();
+        let data = Bytes::from(vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+
+        sender.start(
+            REPLICA_1,
+            create_raft_snapshot(metadata.clone(), data.clone()),
+        );
+
+        // The header is delivered, the response to the next chunk is lost
+        // although the receiver has got the chunk.
+        let header_request = sender.next_request().unwrap();
+        let chunk_request = sender.next_request().unwrap();
+        let response = receiver.process_request(header_request);
+        sender.process_response(REPLICA_1, DELIVERY_1, Ok(response));
+        receiver.process_request(chunk_request);
+        sender.process_response(REPLICA_1, DELIVERY_2, Err(SnapshotError::FailedDelivery));
+        assert_eq!(sender.try_complete(), None);
+
+        // The transfer is resumed with the header and continues after the
+        // chunks acknowledged by the receiver.
+        let resume_request = sender.next_request().unwrap();
+        match deliver_snapshot_request::Payload::decode(resume_request.payload_contents.clone())
+            .unwrap()
+            .it
+        {
+            Some(deliver_snapshot_request::payload::It::Header(header)) => assert!(header.resume),
+            _ => panic!("Expected resuming header"),
+        }
+        let response = receiver.process_request(resume_request);
+        sender.process_response(REPLICA_1, DELIVERY_3, Ok(response));
+
+        let mut sent_chunk_indices = Vec::new();
+        while let Some(request) = sender.next_request() {
+            let delivery_id = request.delivery_id;
+            if let Some(deliver_snapshot_request::payload::It::Chunk(chunk)) =
+                deliver_snapshot_request::Payload::decode(request.payload_contents.clone())
+                    .unwrap()
+                    .it
+            {
+                sent_chunk_indices.push(chunk.chunk_index);
+            }
+            let response = receiver.process_request(request);
+            sender.process_response(REPLICA_1, delivery_id, Ok(response));
+        }
+        assert_eq!(sent_chunk_indices, vec![2, 3]);
+        assert_eq!(
+            sender.try_complete(),
+            Some((REPLICA_1, RaftSnapshotStatus::Finish))
+        );
+
+        let (sender_id, snapshot) = receiver.try_complete().unwrap().unwrap();
+        assert_eq!(sender_id, REPLICA_0);
+        assert_eq!(snapshot.data, data);
+        assert_eq!(snapshot.metadata, Some(metadata));
+    }
+
+    #[test]
+    fn test_snapshot_receiver_rejects_chunk_not_matching_manifest() {
+        let mut receiver = DefaultSnapshotReceiver::new();
+        receiver.init(create_logger(), REPLICA_1, &None);
+
+        let data = Bytes::from(vec![1, 2, 3, 4, 5, 6]);
+        let header = deliver_snapshot_request::payload::Header {
+            snapshot_size: data.len() as u64,
+            snapshot_metadata: default_snapshot_metadata().encode_to_vec().into(),
+            chunk_contents: data.slice(0..3),
+            chunk_manifest: Some(ChunkManifest {
+                chunk_digests: vec![chunk_digest(&data[0..3]), chunk_digest(&data[3..6])],
+            }),
+            resume: false,
+        };
+        let payload = deliver_snapshot_request::Payload {
+            snapshot_id: SNAPSHOT_1,
+            it: Some(deliver_snapshot_request::payload::It::Header(header)),
+        };
+        let response = receiver.process_request(DeliverSnapshotRequest {
+            recipient_replica_id: REPLICA_1,
+            sender_replica_id: REPLICA_0,
+            delivery_id: DELIVERY_1,
+            payload_contents: payload.encode_to_vec().into(),
+        });
+        let response_payload =
+            deliver_snapshot_response::Payload::decode(response.payload_contents).unwrap();
+        assert_eq!(
+            response_payload.status(),
+            DeliverSnapshotStatus::SnapshotStatusAccepted
+        );
+        assert_eq!(response_payload.acknowledged_chunk_count, 1);
+
+        assert_deliver_snapshot_rejected(
+            receiver.process_request(create_deliver_snapshot_request_chunk(
+                REPLICA_0,
+                SNAPSHOT_1,
+                DELIVERY_2,
+                CHUNK_1,
+                Bytes::from(vec![4, 5, 7]),
+            )),
+            SNAPSHOT_1,
+            DELIVERY_2,
+            CHUNK_1,
+        );
+        assert!(receiver.try_complete().is_none());
+    }
 }
@@ -23,9 +23,9 @@ use crate::{
 };
 use alloc::vec;
 use alloc::vec::Vec;
-use core::{cell::RefCell, cmp, result::Result};
+use core::{borrow::Borrow, cell::RefCell, cmp, result::Result};
 use hashbrown::HashMap;
-use prost::bytes::Bytes;
+use prost::{bytes::Bytes, Message};
 use raft::{
     eraftpb::ConfState as RaftConfigState, eraftpb::Entry as RaftEntry,
     eraftpb::HardState as RaftHardState, eraftpb::Snapshot as RaftSnapshot, util::limit_size,
@@ -42,10 +42,18 @@ fn statisfies_snapshot_request(
     snapshot_index >= request_index && config_state_contains_node(snapshot_config_state, peer_id)
 }
 
+fn entries_size<E: Borrow<RaftEntry>>(entries: impl Iterator<Item = E>) -> u64 {
+    entries
+        .map(|entry| entry.borrow().encoded_len() as u64)
+        .sum()
+}
+
 struct MemoryStorageCore {
     logger: Logger,
     state: RaftHardState,
     entries: Vec<RaftEntry>,
+    // Total encoded size of the entries.
+    entries_size: u64,
     max_snapshot_diff: u64,
     snapshot: RaftSnapshot,
     snapshot_peer_requests: HashMap<u64, u64>,
@@ -58,6 +66,7 @@ impl MemoryStorageCore {
             max_snapshot_diff,
             state: RaftHardState::default(),
             entries: Vec::new(),
+            entries_size: 0,
             snapshot: create_raft_snapshot(
                 create_raft_snapshot_metadata(0, 0, create_raft_config_state(vec![])),
                 Bytes::new(),
@@ -96,15 +105,31 @@ impl MemoryStorageCore {
         self.state = state;
     }
 
-    fn append_entries(&mut self, entries: &[RaftEntry]) -> Result<(), RaftError> {
-        debug!(self.logger, "Append, entries: {:?}", entries);
+    // Entries past the given index are typically few, hence their size is
+    // subtracted from the total rather than summing up the rest.
+    fn entries_size(&self, high_index: u64) -> u64 {
+        let offset = high_index
+            .saturating_add(1)
+            .saturating_sub(self.first_entry_index())
+            .min(self.entries.len() as u64);
+        self.entries_size - entries_size(self.entries[offset as usize..].iter())
+    }
 
+    fn append_entries(&mut self, mut entries: Vec<RaftEntry>) -> Result<(), RaftError> {
         if entries.is_empty() {
             return Ok(());
         }
 
         let first_append_index = entries[0].index;
 
+        // Only log the range of entries to avoid formatting their payloads.
+        debug!(
+            self.logger,
+            "Append, first index: {}, count: {}",
+            first_append_index,
+            entries.len()
+        );
+
         // Check that new entries do not overwrite previsouly compacted entries.
         if self.first_entry_index() > first_append_index {
             panic!(
@@ -125,9 +150,10 @@ impl MemoryStorageCore {
 
         // Remove all overwritten entries.
         let overwritten_entries = first_append_index - self.first_entry_index();
-        self.entries.drain(overwritten_entries as usize..);
+        self.entries_size -= entries_size(self.entries.drain(overwritten_entries as usize..));
         // Append new entries.
-        self.entries.extend_from_slice(entries);
+        self.entries_size += entries_size(entries.iter());
+        self.entries.append(&mut entries);
 
         Ok(())
     }
@@ -151,7 +177,7 @@ impl MemoryStorageCore {
 
         if let Some(entry) = self.entries.first() {
             let offset = compact_index - entry.index;
-            self.entries.drain(..offset as usize);
+            self.entries_size -= entries_size(self.entries.drain(..offset as usize));
         }
         Ok(())
     }
@@ -170,6 +196,7 @@ impl MemoryStorageCore {
         self.state.term = cmp::max(self.state.term, snapshot_metadata.term);
 
         self.entries.clear();
+        self.entries_size = 0;
         self.set_snapshot(snapshot);
 
         Ok(())
@@ -363,7 +390,7 @@ impl Store for MemoryStorage {
         self.core.borrow_mut().set_hard_state(state);
     }
 
-    fn append_entries(&mut self, entries: &[RaftEntry]) -> Result<(), RaftError> {
+    fn append_entries(&mut self, entries: Vec<RaftEntry>) -> Result<(), RaftError> {
         self.core.borrow_mut().append_entries(entries)
     }
 
@@ -388,9 +415,17 @@ impl Store for MemoryStorage {
             .should_snapshot(applied_index, config_state)
     }
 
+    fn log_size(&self, high_index: u64) -> u64 {
+        self.core.borrow().entries_size(high_index)
+    }
+
     fn latest_snapshot_size(&self) -> u64 {
         self.core.borrow().snapshot.data.len() as u64
     }
+
+    fn latest_snapshot(&self) -> RaftSnapshot {
+        self.core.borrow().snapshot.clone()
+    }
 }
 
 impl Storage for MemoryStorage {
@@ -432,13 +467,16 @@ mod test {
     use crate::{
         logger::log::create_logger,
         util::raft::{
-            create_empty_raft_entry, create_raft_config_state, create_raft_snapshot,
-            create_raft_snapshot_metadata, message_size,
+            create_empty_raft_entry, create_raft_config_state, create_raft_entry,
+            create_raft_snapshot, create_raft_snapshot_metadata, message_size,
         },
     };
 
     use super::*;
-    use raft::{eraftpb::Entry as RaftEntry, GetEntriesContext};
+    use raft::{
+        eraftpb::{Entry as RaftEntry, EntryType as RaftEntryType},
+        GetEntriesContext,
+    };
 
     fn create_snapshot(snapshot_index: u64, snapshot_term: u64, voters: &[u64]) -> RaftSnapshot {
         create_raft_snapshot(
@@ -463,7 +501,7 @@ mod test {
         let snapshot = create_snapshot(snapshot_index, snapshot_term, voters);
 
         storage.apply_snapshot(snapshot).unwrap();
-        storage.append_entries(entries.as_ref()).unwrap();
+        storage.append_entries(entries.clone()).unwrap();
 
         storage
     }
@@ -623,12 +661,38 @@ mod test {
         assert_eq!(Ok(5), storage.last_index());
 
         storage
-            .append_entries(&[create_empty_raft_entry(6, 5)])
+            .append_entries(vec![create_empty_raft_entry(6, 5)])
             .unwrap();
 
         assert_eq!(Ok(6), storage.last_index());
     }
 
+    #[test]
+    fn test_storage_append_entries_moves_payloads() {
+        let voters = vec![1];
+        let mut storage = create_storage(2, 2, 1, &vec![], &voters);
+
+        let entries = vec![
+            create_raft_entry(3, 3, RaftEntryType::EntryNormal, Bytes::from(vec![1, 2, 3])),
+            create_raft_entry(4, 3, RaftEntryType::EntryNormal, Bytes::from(vec![4, 5, 6])),
+        ];
+        let payloads: Vec<*const u8> = entries.iter().map(|entry| entry.data.as_ptr()).collect();
+
+        storage.append_entries(entries).unwrap();
+
+        // Stored entries hold the appended payload buffers rather than their copies.
+        let storage_core = storage.core.borrow();
+        assert_eq!(
+            storage_core
+                .entries
+                .iter()
+                .map(|entry| entry.data.as_ptr())
+                .collect::<Vec<_>>(),
+            payloads
+        );
+        assert_eq!(storage_core.entries[1].data, vec![4, 5, 6]);
+    }
+
     #[test]
     fn test_storage_first_index() {
         let entries = vec![
@@ -769,7 +833,7 @@ mod test {
             let mut storage = create_storage(2, 2, 1, &entries, &voters);
 
             let result =
-                panic::catch_unwind(AssertUnwindSafe(|| storage.append_entries(&append_entries)));
+                panic::catch_unwind(AssertUnwindSafe(|| storage.append_entries(append_entries)));
 
             if let Some(result_entries) = result_entries {
                 assert_eq!(
@@ -860,6 +924,50 @@ mod test {
         assert_eq!(storage.latest_snapshot_size(), snapshot_size);
     }
 
+    #[test]
+    fn test_storage_log_size() {
+        let entries = vec![
+            create_empty_raft_entry(3, 3),
+            create_empty_raft_entry(4, 4),
+            create_empty_raft_entry(5, 5),
+        ];
+
+        let voters = vec![1];
+
+        let mut storage = create_storage(2, 2, 1, &entries, &voters);
+
+        let entry_size = |index: usize| u64::from(message_size(&entries[index]));
+        assert_eq!(
+            storage.log_size(u64::MAX),
+            entry_size(0) + entry_size(1) + entry_size(2)
+        );
+
+        // Only the entries up to the given index are accounted for.
+        assert_eq!(storage.log_size(2), 0);
+        assert_eq!(storage.log_size(4), entry_size(0) + entry_size(1));
+
+        // Overwritten entries are no longer accounted for.
+        let overwriting_entry = create_empty_raft_entry(5, 6);
+        storage
+            .append_entries(vec![overwriting_entry.clone()])
+            .unwrap();
+        assert_eq!(
+            storage.log_size(u64::MAX),
+            entry_size(0) + entry_size(1) + u64::from(message_size(&overwriting_entry))
+        );
+
+        storage.compact_entries(5).unwrap();
+        assert_eq!(
+            storage.log_size(u64::MAX),
+            u64::from(message_size(&overwriting_entry))
+        );
+
+        storage
+            .apply_snapshot(create_snapshot(6, 6, &voters))
+            .unwrap();
+        assert_eq!(storage.log_size(u64::MAX), 0);
+    }
+
     #[test]
     fn test_storage_latest_snapshot_size_no_previous_snapshot() {
         let entries = vec![
@@ -0,0 +1,970 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::attestation::{
+    allows, AttestationProvider, ClientAttestation, RootLayerAttestationProvider,
+    RootLayerReportGenerator, RootLayerVerifier, ServerAttestation, VerifiedPeerCache,
+};
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use anyhow::{anyhow, ensure, Result};
+use core::cell::RefCell;
+use oak_proto_rust::oak::attestation::v1::TeePlatform;
+use p256::ecdsa::{signature::Verifier, Signature as EcdsaSignature, VerifyingKey};
+use sha2::{Digest, Sha256};
+use tcp_proto::runtime::endpoint::AttestationPolicy;
+use x509_cert::{
+    der::{oid::ObjectIdentifier, Decode, Encode},
+    Certificate,
+};
+
+/// Size of the data the trust domain binds to the quote.
+pub const TDX_REPORT_DATA_SIZE: usize = 64;
+/// Size of the measurement registers of the trust domain.
+pub const TDX_MEASUREMENT_SIZE: usize = 48;
+/// Number of the runtime measurement registers of the trust domain.
+pub const TDX_RTMR_COUNT: usize = 4;
+/// Size of the security version number of the TDX module.
+pub const TDX_TCB_SVN_SIZE: usize = 16;
+
+// Layout of the version 4 quote as defined by the Intel TDX DCAP quote format.
+const QUOTE_VERSION: u16 = 4;
+const TEE_TYPE_TDX: u32 = 0x81;
+const HEADER_SIZE: usize = 48;
+const TEE_TCB_SVN_OFFSET: usize = HEADER_SIZE;
+const TD_ATTRIBUTES_OFFSET: usize = HEADER_SIZE + 120;
+const MRTD_OFFSET: usize = HEADER_SIZE + 136;
+const MROWNER_OFFSET: usize = HEADER_SIZE + 232;
+const RTMR_OFFSET: usize = HEADER_SIZE + 328;
+const REPORT_DATA_OFFSET: usize = HEADER_SIZE + 520;
+const SIGNATURE_DATA_SIZE_OFFSET: usize = HEADER_SIZE + 584;
+const SIGNATURE_DATA_OFFSET: usize = SIGNATURE_DATA_SIZE_OFFSET + 4;
+
+// Layout of the ECDSA signature data of the version 4 quote.
+const ECDSA_SIGNATURE_SIZE: usize = 64;
+const ECDSA_PUBLIC_KEY_SIZE: usize = 64;
+const CERTIFICATION_DATA_QE_REPORT: u16 = 6;
+const CERTIFICATION_DATA_PCK_CERTIFICATE_CHAIN: u16 = 5;
+const QE_REPORT_SIZE: usize = 384;
+const QE_REPORT_MRSIGNER_OFFSET: usize = 128;
+const QE_REPORT_DATA_OFFSET: usize = 320;
+
+// Identity of the key Intel signs the quoting enclave with.
+const INTEL_QE_MRSIGNER: [u8; 32] = [
+    0x8c, 0x4f, 0x57, 0x75, 0xd7, 0x96, 0x50, 0x3e, 0x96, 0x13, 0x7f, 0x77, 0xc6, 0x8a, 0x82, 0x9a,
+    0x00, 0x56, 0xac, 0x8d, 0xed, 0x70, 0x14, 0x0b, 0x08, 0x1b, 0x09, 0x44, 0x90, 0xc5, 0x7b, 0xff,
+];
+
+// Object identifier of the ECDSA with SHA-256 signature algorithm the Intel
+// certificates are signed with.
+const ECDSA_WITH_SHA256_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2");
+
+// Trust domain attribute that allows the host to debug the trust domain.
+const TD_ATTRIBUTES_DEBUG: u64 = 1;
+
+/// Quote produced by the quoting enclave for the trust domain.
+pub struct TdxQuote {
+    raw: Vec<u8>,
+}
+
+impl TdxQuote {
+    /// Parses the quote, only the layout of the quote is checked.
+    pub fn parse(raw: &[u8]) -> Result<TdxQuote> {
+        ensure!(
+            raw.len() >= SIGNATURE_DATA_OFFSET,
+            "TDX quote is too short {}",
+            raw.len()
+        );
+        let version = u16::from_le_bytes([raw[0], raw[1]]);
+        ensure!(
+            version == QUOTE_VERSION,
+            "TDX quote has unsupported version {}",
+            version
+        );
+        let tee_type = u32::from_le_bytes([raw[4], raw[5], raw[6], raw[7]]);
+        ensure!(
+            tee_type == TEE_TYPE_TDX,
+            "TDX quote has unexpected TEE type {}",
+            tee_type
+        );
+
+        let quote = TdxQuote { raw: raw.to_vec() };
+        let signature_data_size = quote.read_u32(SIGNATURE_DATA_SIZE_OFFSET) as usize;
+        ensure!(
+            raw.len() == SIGNATURE_DATA_OFFSET + signature_data_size,
+            "TDX quote has unexpected signature data size {}",
+            signature_data_size
+        );
+        Ok(quote)
+    }
+
+    /// Gets the security version number of the TDX module.
+    pub fn tee_tcb_svn(&self) -> &[u8] {
+        &self.raw[TEE_TCB_SVN_OFFSET..TEE_TCB_SVN_OFFSET + TDX_TCB_SVN_SIZE]
+    }
+
+    /// Gets the attributes the trust domain has been launched with.
+    pub fn td_attributes(&self) -> u64 {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&self.raw[TD_ATTRIBUTES_OFFSET..TD_ATTRIBUTES_OFFSET + 8]);
+        u64::from_le_bytes(bytes)
+    }
+
+    /// Checks if the trust domain can be debugged by the host.
+    pub fn debug(&self) -> bool {
+        self.td_attributes() & TD_ATTRIBUTES_DEBUG != 0
+    }
+
+    /// Gets the build time measurement of the trust domain.
+    pub fn mrtd(&self) -> &[u8] {
+        &self.raw[MRTD_OFFSET..MRTD_OFFSET + TDX_MEASUREMENT_SIZE]
+    }
+
+    /// Gets the identity of the owner of the trust domain.
+    pub fn mrowner(&self) -> &[u8] {
+        &self.raw[MROWNER_OFFSET..MROWNER_OFFSET + TDX_MEASUREMENT_SIZE]
+    }
+
+    /// Gets the runtime measurement register with the given index.
+    pub fn rtmr(&self, index: usize) -> &[u8] {
+        let offset = RTMR_OFFSET + index * TDX_MEASUREMENT_SIZE;
+        &self.raw[offset..offset + TDX_MEASUREMENT_SIZE]
+    }
+
+    /// Gets the data the trust domain has bound to the quote.
+    pub fn report_data(&self) -> &[u8] {
+        &self.raw[REPORT_DATA_OFFSET..REPORT_DATA_OFFSET + TDX_REPORT_DATA_SIZE]
+    }
+
+    /// Gets the part of the quote that is covered by the signature.
+    pub fn signed_bytes(&self) -> &[u8] {
+        &self.raw[..SIGNATURE_DATA_SIZE_OFFSET]
+    }
+
+    /// Gets the signature data, i.e. the signature made with the attestation
+    /// key along with the certification data of the key.
+    pub fn signature_data(&self) -> &[u8] {
+        &self.raw[SIGNATURE_DATA_OFFSET..]
+    }
+
+    fn read_u32(&self, offset: usize) -> u32 {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&self.raw[offset..offset + 4]);
+        u32::from_le_bytes(bytes)
+    }
+}
+
+/// Creates the report data that binds the public key to the quote, i.e. the
+/// SHA-256 digest of the public key followed by zeros.
+pub fn create_report_data(public_key: &[u8]) -> [u8; TDX_REPORT_DATA_SIZE] {
+    let mut report_data = [0; TDX_REPORT_DATA_SIZE];
+    report_data[..32].copy_from_slice(&Sha256::digest(public_key));
+    report_data
+}
+
+/// Requests quotes for the trust domain, e.g. through the TDX guest device
+/// exposed by the trusted host kernel and the quoting enclave on the host.
+pub trait TdxQuoteGenerator {
+    /// Gets the raw quote with the given data bound to it.
+    fn get_quote(&self, report_data: &[u8; TDX_REPORT_DATA_SIZE]) -> Result<Vec<u8>>;
+}
+
+/// Verifies that the quote has been signed by the attestation key of the
+/// quoting enclave running on a genuine Intel platform, e.g. by checking the
+/// certification data of the key up to the Intel root key.
+pub trait TdxSignatureVerifier {
+    fn verify_signature(&self, quote: &TdxQuote) -> Result<()>;
+}
+
+/// Implementation of `TdxSignatureVerifier` that checks the ECDSA signature
+/// data of the quote:
+///   * the PCK certificate chain from the certification data must end with the
+///     pinned Intel SGX root CA certificate;
+///   * the report of the quoting enclave must be signed with the PCK key, must
+///     come from the quoting enclave signed by Intel and must bind the
+///     attestation key;
+///   * the quote must be signed with the attestation key.
+///
+/// The TCB status of the platform and the revocation of the PCK certificates
+/// are published by Intel as separate collateral and are not checked.
+pub struct TdxCertificateChainVerifier {
+    root: Certificate,
+}
+
+impl TdxCertificateChainVerifier {
+    /// Creates the verifier from the DER encoded Intel SGX root CA certificate,
+    /// the certificate must be self-signed.
+    pub fn new(root_certificate: &[u8]) -> Result<TdxCertificateChainVerifier> {
+        let root = Certificate::from_der(root_certificate)
+            .map_err(|err| anyhow!("Failed to parse certificate: {}", err))?;
+        verify_certificate_signature(&root, &root)?;
+        Ok(TdxCertificateChainVerifier { root })
+    }
+}
+
+impl TdxSignatureVerifier for TdxCertificateChainVerifier {
+    fn verify_signature(&self, quote: &TdxQuote) -> Result<()> {
+        let signature_data = QuoteSignatureData::parse(quote.signature_data())?;
+
+        let certificates = Certificate::load_pem_chain(signature_data.pck_certificate_chain)
+            .map_err(|err| anyhow!("Failed to parse PCK certificate chain: {}", err))?;
+        ensure!(
+            certificates.last() == Some(&self.root),
+            "PCK certificate chain doesn't end with the root certificate"
+        );
+        for pair in certificates.windows(2) {
+            verify_certificate_signature(&pair[0], &pair[1])?;
+        }
+
+        let pck_key = VerifyingKey::from_sec1_bytes(
+            certificates[0]
+                .tbs_certificate
+                .subject_public_key_info
+                .subject_public_key
+                .raw_bytes(),
+        )
+        .map_err(|err| anyhow!("PCK certificate has invalid public key: {}", err))?;
+        pck_key
+            .verify(
+                signature_data.qe_report,
+                &parse_signature(signature_data.qe_report_signature)?,
+            )
+            .map_err(|_| anyhow!("TDX quoting enclave report signature verification failed"))?;
+        ensure!(
+            signature_data.qe_report[QE_REPORT_MRSIGNER_OFFSET..QE_REPORT_MRSIGNER_OFFSET + 32]
+                == INTEL_QE_MRSIGNER,
+            "TDX quoting enclave is not signed by Intel"
+        );
+
+        // Quoting enclave binds the attestation key and its authentication data
+        // to its report.
+        let mut expected_report_data = [0; TDX_REPORT_DATA_SIZE];
+        expected_report_data[..32].copy_from_slice(
+            &Sha256::new()
+                .chain_update(signature_data.attestation_key)
+                .chain_update(signature_data.qe_authentication_data)
+                .finalize(),
+        );
+        ensure!(
+            signature_data.qe_report
+                [QE_REPORT_DATA_OFFSET..QE_REPORT_DATA_OFFSET + TDX_REPORT_DATA_SIZE]
+                == expected_report_data,
+            "TDX quoting enclave report doesn't bind the attestation key"
+        );
+
+        let mut attestation_key = Vec::with_capacity(ECDSA_PUBLIC_KEY_SIZE + 1);
+        attestation_key.push(0x04);
+        attestation_key.extend_from_slice(signature_data.attestation_key);
+        VerifyingKey::from_sec1_bytes(&attestation_key)
+            .map_err(|err| anyhow!("TDX quote has invalid attestation key: {}", err))?
+            .verify(
+                quote.signed_bytes(),
+                &parse_signature(signature_data.quote_signature)?,
+            )
+            .map_err(|_| anyhow!("TDX quote signature verification failed"))
+    }
+}
+
+// Signature data of the quote, see the Intel TDX DCAP quote format.
+struct QuoteSignatureData<'a> {
+    quote_signature: &'a [u8],
+    attestation_key: &'a [u8],
+    qe_report: &'a [u8],
+    qe_report_signature: &'a [u8],
+    qe_authentication_data: &'a [u8],
+    pck_certificate_chain: &'a [u8],
+}
+
+impl<'a> QuoteSignatureData<'a> {
+    fn parse(data: &'a [u8]) -> Result<QuoteSignatureData<'a>> {
+        let (quote_signature, data) = split(data, ECDSA_SIGNATURE_SIZE)?;
+        let (attestation_key, data) = split(data, ECDSA_PUBLIC_KEY_SIZE)?;
+        let data = parse_certification_data(data, CERTIFICATION_DATA_QE_REPORT)?;
+        let (qe_report, data) = split(data, QE_REPORT_SIZE)?;
+        let (qe_report_signature, data) = split(data, ECDSA_SIGNATURE_SIZE)?;
+        let (qe_authentication_data_size, data) = split(data, 2)?;
+        let (qe_authentication_data, data) = split(
+            data,
+            u16::from_le_bytes([
+                qe_authentication_data_size[0],
+                qe_authentication_data_size[1],
+            ]) as usize,
+        )?;
+        let pck_certificate_chain =
+            parse_certification_data(data, CERTIFICATION_DATA_PCK_CERTIFICATE_CHAIN)?;
+
+        Ok(QuoteSignatureData {
+            quote_signature,
+            attestation_key,
+            qe_report,
+            qe_report_signature,
+            qe_authentication_data,
+            // Chain is padded with zeros by some quoting enclaves.
+            pck_certificate_chain: trim_zeros(pck_certificate_chain),
+        })
+    }
+}
+
+// Parses the certification data of the expected type, i.e. the type and the
+// size of the data followed by the data.
+fn parse_certification_data(data: &[u8], expected_type: u16) -> Result<&[u8]> {
+    let (header, data) = split(data, 6)?;
+    let certification_data_type = u16::from_le_bytes([header[0], header[1]]);
+    ensure!(
+        certification_data_type == expected_type,
+        "TDX quote has unsupported certification data type {}",
+        certification_data_type
+    );
+    let size = u32::from_le_bytes([header[2], header[3], header[4], header[5]]) as usize;
+    split(data, size).map(|(certification_data, _)| certification_data)
+}
+
+fn split(data: &[u8], size: usize) -> Result<(&[u8], &[u8])> {
+    ensure!(data.len() >= size, "TDX quote signature data is too short");
+    Ok(data.split_at(size))
+}
+
+fn trim_zeros(data: &[u8]) -> &[u8] {
+    let size = data
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .map_or(0, |i| i + 1);
+    &data[..size]
+}
+
+// Parses the ECDSA signature made of the big endian r and s scalars.
+fn parse_signature(signature: &[u8]) -> Result<EcdsaSignature> {
+    EcdsaSignature::from_slice(signature)
+        .map_err(|err| anyhow!("TDX quote has invalid signature: {}", err))
+}
+
+// Verifies that the certificate is issued and signed by the issuer with ECDSA
+// and SHA-256.
+fn verify_certificate_signature(certificate: &Certificate, issuer: &Certificate) -> Result<()> {
+    ensure!(
+        certificate.tbs_certificate.issuer == issuer.tbs_certificate.subject,
+        "Certificate {} is not issued by {}",
+        certificate.tbs_certificate.subject,
+        issuer.tbs_certificate.subject
+    );
+    ensure!(
+        certificate.signature_algorithm.oid == ECDSA_WITH_SHA256_OID,
+        "Certificate {} has unsupported signature algorithm {}",
+        certificate.tbs_certificate.subject,
+        certificate.signature_algorithm.oid
+    );
+    let issuer_key = VerifyingKey::from_sec1_bytes(
+        issuer
+            .tbs_certificate
+            .subject_public_key_info
+            .subject_public_key
+            .raw_bytes(),
+    )
+    .map_err(|err| anyhow!("Issuer has invalid public key: {}", err))?;
+    let signature = EcdsaSignature::from_der(certificate.signature.raw_bytes())
+        .map_err(|err| anyhow!("Certificate has invalid signature: {}", err))?;
+    let signed_bytes = certificate
+        .tbs_certificate
+        .to_der()
+        .map_err(|err| anyhow!("Failed to encode certificate: {}", err))?;
+    issuer_key.verify(&signed_bytes, &signature).map_err(|_| {
+        anyhow!(
+            "Certificate {} signature verification failed",
+            certificate.tbs_certificate.subject
+        )
+    })
+}
+
+/// Values the quotes of the peers are expected to carry.
+#[derive(Debug, Default, Clone)]
+pub struct TdxReferenceValues {
+    /// Build time measurements the trust domains are allowed to run with.
+    /// Empty list allows no trust domain.
+    pub mrtds: Vec<Vec<u8>>,
+    /// If true the trust domains with any build time measurement are
+    /// accepted.
+    pub allow_any_mrtd: bool,
+    /// Owner identities the trust domains are allowed to have. Empty list
+    /// allows any owner.
+    pub mrowners: Vec<Vec<u8>>,
+    /// Expected runtime measurement registers, none matches any value.
+    pub rtmrs: [Option<Vec<u8>>; TDX_RTMR_COUNT],
+    /// Minimum security version number of the TDX module, each component is
+    /// compared separately.
+    pub min_tee_tcb_svn: Vec<u8>,
+    /// If true the trust domains that can be debugged by the host are accepted.
+    pub allow_debug: bool,
+}
+
+impl TdxReferenceValues {
+    /// Replaces the values covered by the attestation policy, the expected
+    /// runtime measurement registers are left unchanged.
+    pub fn update_policy(&mut self, policy: &AttestationPolicy) {
+        self.mrtds = policy.allowed_measurements.clone();
+        self.allow_any_mrtd = policy.allow_any_measurement;
+        self.mrowners = policy.allowed_signers.clone();
+        self.min_tee_tcb_svn = policy.min_tdx_tee_tcb_svn.clone();
+        self.allow_debug = policy.allow_debug;
+    }
+}
+
+/// Verifies the quotes of the peers against the reference values.
+pub struct TdxVerifier {
+    reference_values: RefCell<TdxReferenceValues>,
+    signature_verifier: Box<dyn TdxSignatureVerifier>,
+}
+
+impl TdxVerifier {
+    pub fn new(
+        reference_values: TdxReferenceValues,
+        signature_verifier: Box<dyn TdxSignatureVerifier>,
+    ) -> TdxVerifier {
+        TdxVerifier {
+            reference_values: RefCell::new(reference_values),
+            signature_verifier,
+        }
+    }
+
+    /// Verifies the raw quote and checks that it binds the given public key.
+    pub fn verify(&self, raw_quote: &[u8], public_key: &[u8]) -> Result<TdxQuote> {
+        let quote = TdxQuote::parse(raw_quote)?;
+        self.signature_verifier.verify_signature(&quote)?;
+
+        let reference_values = self.reference_values.borrow();
+        ensure!(
+            allows(
+                &reference_values.mrtds,
+                reference_values.allow_any_mrtd,
+                quote.mrtd()
+            ),
+            "TDX quote has unexpected MRTD"
+        );
+        ensure!(
+            allows(
+                &reference_values.mrowners,
+                reference_values.mrowners.is_empty(),
+                quote.mrowner()
+            ),
+            "TDX quote has unexpected MROWNER"
+        );
+        for (index, rtmr) in reference_values.rtmrs.iter().enumerate() {
+            if let Some(rtmr) = rtmr {
+                ensure!(
+                    quote.rtmr(index) == rtmr.as_slice(),
+                    "TDX quote has unexpected RTMR{}",
+                    index
+                );
+            }
+        }
+        ensure!(
+            quote
+                .tee_tcb_svn()
+                .iter()
+                .zip(reference_values.min_tee_tcb_svn.iter())
+                .all(|(svn, min_svn)| svn >= min_svn),
+            "TDX quote has TEE TCB SVN {:?} below minimum {:?}",
+            quote.tee_tcb_svn(),
+            reference_values.min_tee_tcb_svn
+        );
+        ensure!(
+            reference_values.allow_debug || !quote.debug(),
+            "TDX quote allows debugging"
+        );
+        ensure!(
+            quote.report_data() == create_report_data(public_key),
+            "TDX quote is not bound to the public key"
+        );
+
+        Ok(quote)
+    }
+}
+
+impl RootLayerVerifier for TdxVerifier {
+    fn platform(&self) -> TeePlatform {
+        TeePlatform::IntelTdx
+    }
+
+    // The certification data of the attestation key is carried by the quote
+    // and verified along with it.
+    fn verify_tee_certificate(&self, tee_certificate: &[u8]) -> Result<()> {
+        ensure!(
+            tee_certificate.is_empty(),
+            "TDX quote has unexpected certificate"
+        );
+        Ok(())
+    }
+
+    fn verify_report(
+        &self,
+        report: &[u8],
+        _tee_certificate: &[u8],
+        public_key: &[u8],
+    ) -> Result<()> {
+        self.verify(report, public_key).map(|_| ())
+    }
+
+    fn update_policy(&self, policy: &AttestationPolicy) {
+        self.reference_values.borrow_mut().update_policy(policy)
+    }
+}
+
+// Requests the quotes that bind the session public keys.
+struct TdxRootLayerReportGenerator {
+    generator: Rc<dyn TdxQuoteGenerator>,
+}
+
+impl RootLayerReportGenerator for TdxRootLayerReportGenerator {
+    fn get_report(&self, public_key: &[u8]) -> Result<Vec<u8>> {
+        self.generator.get_quote(&create_report_data(public_key))
+    }
+
+    fn get_tee_certificate(&self) -> Result<Vec<u8>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Implementation of `AttestationProvider` for the replicas running in Intel
+/// TDX trust domains. Each replica presents a fresh quote that binds its
+/// session public key and verifies the quote of its peer against the
+/// reference values.
+pub struct TdxAttestationProvider {
+    inner: RootLayerAttestationProvider,
+}
+
+impl TdxAttestationProvider {
+    /// Creates the provider, a quote is requested for every session as the
+    /// session public keys are generated for every session.
+    pub fn new(
+        generator: Rc<dyn TdxQuoteGenerator>,
+        verifier: TdxVerifier,
+    ) -> TdxAttestationProvider {
+        TdxAttestationProvider {
+            inner: RootLayerAttestationProvider::new(
+                Rc::new(TdxRootLayerReportGenerator { generator }),
+                Rc::new(verifier),
+            ),
+        }
+    }
+
+    /// Skips the verification of the peer certificates found in the cache,
+    /// see [VerifiedPeerCache].
+    pub fn with_cache(self, cache: Rc<VerifiedPeerCache>) -> TdxAttestationProvider {
+        TdxAttestationProvider {
+            inner: self.inner.with_cache(cache),
+        }
+    }
+}
+
+impl AttestationProvider for TdxAttestationProvider {
+    fn get_client_attestation(&self, session_public_key: &[u8]) -> Box<dyn ClientAttestation> {
+        self.inner.get_client_attestation(session_public_key)
+    }
+
+    fn get_server_attestation(&self, session_public_key: &[u8]) -> Box<dyn ServerAttestation> {
+        self.inner.get_server_attestation(session_public_key)
+    }
+
+    fn update_policy(&self, policy: &AttestationPolicy) {
+        self.inner.update_policy(policy)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::tdx::*;
+    use alloc::vec;
+    use core::str::FromStr;
+    use core::time::Duration;
+    use p256::ecdsa::{signature::Signer, DerSignature, SigningKey};
+    use rand::rngs::OsRng;
+    use x509_cert::{
+        builder::{Builder, CertificateBuilder, Profile},
+        der::{pem::LineEnding, EncodePem},
+        name::Name,
+        serial_number::SerialNumber,
+        spki::SubjectPublicKeyInfoOwned,
+        time::Validity,
+    };
+
+    const MRTD: [u8; TDX_MEASUREMENT_SIZE] = [7; TDX_MEASUREMENT_SIZE];
+
+    const RTMR: [u8; TDX_MEASUREMENT_SIZE] = [9; TDX_MEASUREMENT_SIZE];
+
+    // Produces quotes with the given fields and a fake signature.
+    struct FakeQuoteGenerator {
+        mrtd: [u8; TDX_MEASUREMENT_SIZE],
+        tee_tcb_svn: [u8; TDX_TCB_SVN_SIZE],
+        td_attributes: u64,
+    }
+
+    impl TdxQuoteGenerator for FakeQuoteGenerator {
+        fn get_quote(&self, report_data: &[u8; TDX_REPORT_DATA_SIZE]) -> Result<Vec<u8>> {
+            let signature_data = [1, 2, 3, 4];
+            let mut quote = vec![0; SIGNATURE_DATA_OFFSET];
+            quote[0..2].copy_from_slice(&QUOTE_VERSION.to_le_bytes());
+            quote[4..8].copy_from_slice(&TEE_TYPE_TDX.to_le_bytes());
+            quote[TEE_TCB_SVN_OFFSET..TEE_TCB_SVN_OFFSET + TDX_TCB_SVN_SIZE]
+                .copy_from_slice(&self.tee_tcb_svn);
+            quote[TD_ATTRIBUTES_OFFSET..TD_ATTRIBUTES_OFFSET + 8]
+                .copy_from_slice(&self.td_attributes.to_le_bytes());
+            quote[MRTD_OFFSET..MRTD_OFFSET + TDX_MEASUREMENT_SIZE].copy_from_slice(&self.mrtd);
+            quote[RTMR_OFFSET..RTMR_OFFSET + TDX_MEASUREMENT_SIZE].copy_from_slice(&RTMR);
+            quote[REPORT_DATA_OFFSET..REPORT_DATA_OFFSET + TDX_REPORT_DATA_SIZE]
+                .copy_from_slice(report_data);
+            quote[SIGNATURE_DATA_SIZE_OFFSET..SIGNATURE_DATA_OFFSET]
+                .copy_from_slice(&(signature_data.len() as u32).to_le_bytes());
+            quote.extend_from_slice(&signature_data);
+            Ok(quote)
+        }
+    }
+
+    struct FakeSignatureVerifier {}
+
+    impl TdxSignatureVerifier for FakeSignatureVerifier {
+        fn verify_signature(&self, quote: &TdxQuote) -> Result<()> {
+            ensure!(quote.signature_data() == [1, 2, 3, 4], "Invalid signature");
+            Ok(())
+        }
+    }
+
+    // Intel SGX root CA, platform CA and PCK keys along with their
+    // certificates.
+    struct PckCertificateChain {
+        root_certificate: Certificate,
+        ca_certificate: Certificate,
+        pck: SigningKey,
+        pck_certificate: Certificate,
+    }
+
+    impl PckCertificateChain {
+        fn generate(subject_prefix: &str) -> PckCertificateChain {
+            let root = SigningKey::random(&mut OsRng);
+            let ca = SigningKey::random(&mut OsRng);
+            let pck = SigningKey::random(&mut OsRng);
+            let root_name = format!("CN={} Root CA", subject_prefix);
+            let ca_name = format!("CN={} Platform CA", subject_prefix);
+            let root_certificate = create_certificate(Profile::Root, &root_name, &root, &root);
+            let ca_certificate = create_certificate(
+                Profile::SubCA {
+                    issuer: Name::from_str(&root_name).unwrap(),
+                    path_len_constraint: Some(0),
+                },
+                &ca_name,
+                &ca,
+                &root,
+            );
+            let pck_certificate = create_certificate(
+                Profile::Leaf {
+                    issuer: Name::from_str(&ca_name).unwrap(),
+                    enable_key_agreement: false,
+                    enable_key_encipherment: false,
+                },
+                &format!("CN={} PCK Certificate", subject_prefix),
+                &pck,
+                &ca,
+            );
+            PckCertificateChain {
+                root_certificate,
+                ca_certificate,
+                pck,
+                pck_certificate,
+            }
+        }
+
+        fn to_pem(&self) -> Vec<u8> {
+            [
+                &self.pck_certificate,
+                &self.ca_certificate,
+                &self.root_certificate,
+            ]
+            .iter()
+            .flat_map(|certificate| certificate.to_pem(LineEnding::LF).unwrap().into_bytes())
+            .collect()
+        }
+    }
+
+    fn create_certificate(
+        profile: Profile,
+        subject: &str,
+        subject_key: &SigningKey,
+        signer: &SigningKey,
+    ) -> Certificate {
+        CertificateBuilder::new(
+            profile,
+            SerialNumber::from(1u32),
+            Validity::from_now(Duration::from_secs(3600)).unwrap(),
+            Name::from_str(subject).unwrap(),
+            SubjectPublicKeyInfoOwned::from_key(*subject_key.verifying_key()).unwrap(),
+            signer,
+        )
+        .unwrap()
+        .build::<DerSignature>()
+        .unwrap()
+    }
+
+    // Appends the signature data to the quote the way the quoting enclave
+    // does, the report of the quoting enclave is signed with the PCK key.
+    fn sign_quote(
+        quote: &mut Vec<u8>,
+        chain: &PckCertificateChain,
+        qe_mrsigner: &[u8; 32],
+        qe_authentication_data: &[u8],
+    ) {
+        let attestation_key = SigningKey::random(&mut OsRng);
+        let encoded_attestation_key = attestation_key
+            .verifying_key()
+            .to_encoded_point(false)
+            .as_bytes()[1..]
+            .to_vec();
+
+        let mut qe_report = vec![0; QE_REPORT_SIZE];
+        qe_report[QE_REPORT_MRSIGNER_OFFSET..QE_REPORT_MRSIGNER_OFFSET + 32]
+            .copy_from_slice(qe_mrsigner);
+        qe_report[QE_REPORT_DATA_OFFSET..QE_REPORT_DATA_OFFSET + 32].copy_from_slice(
+            &Sha256::new()
+                .chain_update(&encoded_attestation_key)
+                .chain_update(qe_authentication_data)
+                .finalize(),
+        );
+        let qe_report_signature: EcdsaSignature = chain.pck.sign(&qe_report);
+
+        let mut pck_certificate_chain = chain.to_pem();
+        pck_certificate_chain.push(0);
+        let mut qe_certification_data = qe_report;
+        qe_certification_data.extend_from_slice(&qe_report_signature.to_bytes());
+        qe_certification_data
+            .extend_from_slice(&(qe_authentication_data.len() as u16).to_le_bytes());
+        qe_certification_data.extend_from_slice(qe_authentication_data);
+        qe_certification_data
+            .extend_from_slice(&CERTIFICATION_DATA_PCK_CERTIFICATE_CHAIN.to_le_bytes());
+        qe_certification_data
+            .extend_from_slice(&(pck_certificate_chain.len() as u32).to_le_bytes());
+        qe_certification_data.extend_from_slice(&pck_certificate_chain);
+
+        quote.truncate(SIGNATURE_DATA_SIZE_OFFSET);
+        let quote_signature: EcdsaSignature = attestation_key.sign(quote);
+        let mut signature_data = quote_signature.to_bytes().to_vec();
+        signature_data.extend_from_slice(&encoded_attestation_key);
+        signature_data.extend_from_slice(&CERTIFICATION_DATA_QE_REPORT.to_le_bytes());
+        signature_data.extend_from_slice(&(qe_certification_data.len() as u32).to_le_bytes());
+        signature_data.extend_from_slice(&qe_certification_data);
+
+        quote.extend_from_slice(&(signature_data.len() as u32).to_le_bytes());
+        quote.extend_from_slice(&signature_data);
+    }
+
+    fn create_generator() -> FakeQuoteGenerator {
+        FakeQuoteGenerator {
+            mrtd: MRTD,
+            tee_tcb_svn: [2; TDX_TCB_SVN_SIZE],
+            td_attributes: 0,
+        }
+    }
+
+    fn create_verifier() -> TdxVerifier {
+        TdxVerifier::new(
+            TdxReferenceValues {
+                mrtds: vec![MRTD.to_vec()],
+                allow_any_mrtd: false,
+                mrowners: vec![],
+                rtmrs: [Some(RTMR.to_vec()), None, None, None],
+                min_tee_tcb_svn: vec![2; TDX_TCB_SVN_SIZE],
+                allow_debug: false,
+            },
+            Box::new(FakeSignatureVerifier {}),
+        )
+    }
+
+    #[test]
+    fn test_tdx_quote() {
+        let raw_quote = create_generator()
+            .get_quote(&create_report_data(&[1, 2, 3]))
+            .unwrap();
+        let quote = TdxQuote::parse(&raw_quote).unwrap();
+        assert_eq!(quote.mrtd(), &MRTD);
+        assert_eq!(quote.rtmr(0), &RTMR);
+        assert!(!quote.debug());
+        assert_eq!(quote.signed_bytes().len(), SIGNATURE_DATA_SIZE_OFFSET);
+        assert!(TdxQuote::parse(&raw_quote[..raw_quote.len() - 1]).is_err());
+
+        let verifier = create_verifier();
+        assert!(verifier.verify(&raw_quote, &[1, 2, 3]).is_ok());
+
+        // Quote must bind the public key.
+        assert!(verifier.verify(&raw_quote, &[1, 2]).is_err());
+
+        // Quote must carry the expected measurement.
+        let mut generator = create_generator();
+        generator.mrtd = [8; TDX_MEASUREMENT_SIZE];
+        let raw_quote = generator.get_quote(&create_report_data(&[1])).unwrap();
+        assert!(verifier.verify(&raw_quote, &[1]).is_err());
+
+        // Quote must not come from an outdated TDX module.
+        let mut generator = create_generator();
+        generator.tee_tcb_svn[3] = 1;
+        let raw_quote = generator.get_quote(&create_report_data(&[1])).unwrap();
+        assert!(verifier.verify(&raw_quote, &[1]).is_err());
+
+        // Quote must not come from a trust domain that can be debugged.
+        let mut generator = create_generator();
+        generator.td_attributes = TD_ATTRIBUTES_DEBUG;
+        let raw_quote = generator.get_quote(&create_report_data(&[1])).unwrap();
+        assert!(verifier.verify(&raw_quote, &[1]).is_err());
+
+        // Policy update replaces the allowed values but keeps the expected RTMRs.
+        verifier.update_policy(&AttestationPolicy {
+            allowed_measurements: vec![[8; TDX_MEASUREMENT_SIZE].to_vec()],
+            allow_debug: true,
+            ..Default::default()
+        });
+        let mut generator = create_generator();
+        generator.mrtd = [8; TDX_MEASUREMENT_SIZE];
+        generator.td_attributes = TD_ATTRIBUTES_DEBUG;
+        let raw_quote = generator.get_quote(&create_report_data(&[1])).unwrap();
+        assert!(verifier.verify(&raw_quote, &[1]).is_ok());
+        let raw_quote = create_generator()
+            .get_quote(&create_report_data(&[1]))
+            .unwrap();
+        assert!(verifier.verify(&raw_quote, &[1]).is_err());
+        assert_eq!(
+            verifier.reference_values.borrow().rtmrs[0],
+            Some(RTMR.to_vec())
+        );
+
+        // Empty policy allows no trust domain unless any measurement is
+        // explicitly allowed.
+        verifier.update_policy(&AttestationPolicy::default());
+        assert!(verifier.verify(&raw_quote, &[1]).is_err());
+        verifier.update_policy(&AttestationPolicy {
+            allow_any_measurement: true,
+            ..Default::default()
+        });
+        assert!(verifier.verify(&raw_quote, &[1]).is_ok());
+    }
+
+    #[test]
+    fn test_tdx_certificate_chain_verifier() {
+        let chain = PckCertificateChain::generate("Intel SGX");
+        let root_certificate = chain.root_certificate.to_der().unwrap();
+        assert!(TdxCertificateChainVerifier::new(&chain.ca_certificate.to_der().unwrap()).is_err());
+        assert!(TdxCertificateChainVerifier::new(b"invalid").is_err());
+        let verifier = TdxVerifier::new(
+            TdxReferenceValues {
+                mrtds: vec![MRTD.to_vec()],
+                ..Default::default()
+            },
+            Box::new(TdxCertificateChainVerifier::new(&root_certificate).unwrap()),
+        );
+        let create_quote = || {
+            create_generator()
+                .get_quote(&create_report_data(&[1]))
+                .unwrap()
+        };
+
+        // Quote must be signed with the attestation key certified by the
+        // quoting enclave.
+        let mut raw_quote = create_quote();
+        sign_quote(&mut raw_quote, &chain, &INTEL_QE_MRSIGNER, b"auth");
+        assert!(verifier.verify(&raw_quote, &[1]).is_ok());
+
+        let mut tampered_quote = raw_quote.clone();
+        tampered_quote[MRTD_OFFSET + 1] ^= 1;
+        assert!(verifier.verify(&tampered_quote, &[1]).is_err());
+        let mut tampered_quote = raw_quote.clone();
+        tampered_quote[SIGNATURE_DATA_OFFSET + ECDSA_SIGNATURE_SIZE] ^= 1;
+        assert!(verifier.verify(&tampered_quote, &[1]).is_err());
+
+        // Authentication data must be bound by the quoting enclave report.
+        let mut tampered_quote = raw_quote.clone();
+        let offset = SIGNATURE_DATA_OFFSET
+            + tampered_quote[SIGNATURE_DATA_OFFSET..]
+                .windows(4)
+                .position(|window| window == b"auth")
+                .unwrap();
+        tampered_quote[offset] ^= 1;
+        assert!(verifier.verify(&tampered_quote, &[1]).is_err());
+
+        // Quote with fake signature data is rejected.
+        assert!(verifier.verify(&create_quote(), &[1]).is_err());
+
+        // Chain of the PCK certificate must end with the pinned root.
+        let mut raw_quote = create_quote();
+        sign_quote(
+            &mut raw_quote,
+            &PckCertificateChain::generate("Intel SGX"),
+            &INTEL_QE_MRSIGNER,
+            b"auth",
+        );
+        assert!(verifier.verify(&raw_quote, &[1]).is_err());
+
+        // Certificates in the chain must be signed by their issuers.
+        let mut rogue_chain = PckCertificateChain::generate("Intel SGX");
+        rogue_chain.root_certificate = chain.root_certificate.clone();
+        let mut raw_quote = create_quote();
+        sign_quote(&mut raw_quote, &rogue_chain, &INTEL_QE_MRSIGNER, b"auth");
+        assert!(verifier.verify(&raw_quote, &[1]).is_err());
+
+        // Quoting enclave must be signed by Intel.
+        let mut raw_quote = create_quote();
+        sign_quote(&mut raw_quote, &chain, &[1; 32], b"auth");
+        assert!(verifier.verify(&raw_quote, &[1]).is_err());
+    }
+
+    #[test]
+    fn test_tdx_attestation() {
+        let client_provider =
+            TdxAttestationProvider::new(Rc::new(create_generator()), create_verifier());
+        let server_provider =
+            TdxAttestationProvider::new(Rc::new(create_generator()), create_verifier());
+
+        let mut client = client_provider.get_client_attestation(&[1]);
+        let mut server = server_provider.get_server_attestation(&[2]);
+
+        let request = client.get_outgoing_message().unwrap().unwrap();
+        assert_eq!(server.put_incoming_message(&request).unwrap(), Some(()));
+        let response = server.get_outgoing_message().unwrap().unwrap();
+        assert_eq!(client.put_incoming_message(&response).unwrap(), Some(()));
+
+        assert_eq!(
+            client
+                .get_attestation_results()
+                .unwrap()
+                .encryption_public_key,
+            vec![2]
+        );
+        assert_eq!(
+            server
+                .get_attestation_results()
+                .unwrap()
+                .encryption_public_key,
+            vec![1]
+        );
+
+        // Quote presented with a session public key it doesn't bind is
+        // rejected.
+        let mut server = server_provider.get_server_attestation(&[2]);
+        let mut replayed_request = request.clone();
+        replayed_request.endorsed_evidence[0]
+            .evidence
+            .as_mut()
+            .unwrap()
+            .root_layer
+            .as_mut()
+            .unwrap()
+            .eca_public_key = vec![3];
+        assert!(server.put_incoming_message(&replayed_request).is_err());
+        assert!(server.get_attestation_results().is_none());
+    }
+}
@@ -0,0 +1,160 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use tcp_proto::runtime::endpoint::FiredTimer;
+
+/// Tracks timers scheduled by the actor. Timers are scheduled and fired while
+/// committed entries are applied, hence all replicas track the same timers.
+/// The leader proposes to fire each of the due timers once, the proposals are
+/// forgotten once the leadership changes since they may have been dropped.
+pub struct TimerQueue {
+    // Deadline of each of the scheduled timers keyed by timer id.
+    deadlines: BTreeMap<u64, u64>,
+    // Timers proposed to be fired that have not been fired yet.
+    proposed: BTreeSet<u64>,
+}
+
+impl TimerQueue {
+    pub fn new() -> TimerQueue {
+        TimerQueue {
+            deadlines: BTreeMap::new(),
+            proposed: BTreeSet::new(),
+        }
+    }
+
+    /// Schedules the timer, replacing the timer with the same id if any.
+    pub fn set(&mut self, timer_id: u64, deadline: u64) {
+        self.deadlines.insert(timer_id, deadline);
+        self.proposed.remove(&timer_id);
+    }
+
+    /// Cancels the timer if it has not fired yet.
+    pub fn cancel(&mut self, timer_id: u64) {
+        self.deadlines.remove(&timer_id);
+        self.proposed.remove(&timer_id);
+    }
+
+    /// Gets number of the scheduled timers.
+    pub fn len(&self) -> usize {
+        self.deadlines.len()
+    }
+
+    /// Checks if there are no scheduled timers.
+    pub fn is_empty(&self) -> bool {
+        self.deadlines.is_empty()
+    }
+
+    /// Takes timers whose deadline has passed and that have not been proposed
+    /// yet, ordered by the deadline. Taken timers are considered proposed.
+    pub fn take_due(&mut self, instant: u64) -> Vec<FiredTimer> {
+        let mut due: Vec<FiredTimer> = self
+            .deadlines
+            .iter()
+            .filter(|(timer_id, deadline)| {
+                **deadline <= instant && !self.proposed.contains(*timer_id)
+            })
+            .map(|(timer_id, deadline)| FiredTimer {
+                timer_id: *timer_id,
+                deadline: *deadline,
+            })
+            .collect();
+        due.sort_by_key(|timer| (timer.deadline, timer.timer_id));
+
+        for timer in &due {
+            self.proposed.insert(timer.timer_id);
+        }
+
+        due
+    }
+
+    /// Fires the timer if it is still scheduled with the given deadline.
+    /// Returns false if the timer has been cancelled or rescheduled.
+    pub fn fire(&mut self, timer: &FiredTimer) -> bool {
+        if self.deadlines.get(&timer.timer_id) != Some(&timer.deadline) {
+            return false;
+        }
+
+        self.cancel(timer.timer_id);
+        true
+    }
+
+    /// Forgets the proposals so that the due timers are proposed again, must
+    /// be called when the leadership changes or proposals are dropped. Returns
+    /// the number of forgotten proposals.
+    pub fn reset_proposed(&mut self) -> usize {
+        let forgotten = self.proposed.len();
+        self.proposed.clear();
+        forgotten
+    }
+
+    /// Drops all timers, must be called when the actor state is replaced.
+    pub fn clear(&mut self) {
+        self.deadlines.clear();
+        self.proposed.clear();
+    }
+}
+
+impl Default for TimerQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::timer::*;
+    use alloc::vec;
+
+    fn create_fired_timer(timer_id: u64, deadline: u64) -> FiredTimer {
+        FiredTimer { timer_id, deadline }
+    }
+
+    #[test]
+    fn test_timer_queue() {
+        let mut timers = TimerQueue::new();
+        timers.set(1, 30);
+        timers.set(2, 10);
+        timers.set(3, 50);
+        assert_eq!(timers.len(), 3);
+
+        // Due timers are taken once in order of the deadline.
+        assert_eq!(timers.take_due(5), vec![]);
+        assert_eq!(
+            timers.take_due(30),
+            vec![create_fired_timer(2, 10), create_fired_timer(1, 30)]
+        );
+        assert_eq!(timers.take_due(40), vec![]);
+
+        // Dropped proposals are taken again once forgotten.
+        assert_eq!(timers.reset_proposed(), 2);
+        assert_eq!(timers.take_due(40).len(), 2);
+
+        assert!(timers.fire(&create_fired_timer(2, 10)));
+        assert!(!timers.fire(&create_fired_timer(2, 10)));
+
+        // Rescheduled timer ignores the firing with the previous deadline.
+        timers.set(1, 60);
+        assert!(!timers.fire(&create_fired_timer(1, 30)));
+        assert_eq!(timers.take_due(55), vec![create_fired_timer(3, 50)]);
+
+        timers.cancel(3);
+        assert!(!timers.fire(&create_fired_timer(3, 50)));
+        assert_eq!(timers.len(), 1);
+
+        timers.clear();
+        assert!(timers.is_empty());
+    }
+}
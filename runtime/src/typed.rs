@@ -0,0 +1,320 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::{
+    Actor, ActorCommand, ActorContext, ActorError, ActorEvent, ActorEventContext, CommandOutcome,
+    EventOutcome,
+};
+use alloc::boxed::Box;
+use micro_rpc::{Status, StatusCode};
+use prost::{bytes::Bytes, Message};
+
+/// Represents an outcome of typed request handling.
+#[derive(PartialEq, Debug, Clone)]
+pub enum RequestOutcome<Resp, Event> {
+    /// Response to be sent out right away.
+    Response(Resp),
+    /// Event to be replicated, the response is produced once the event is
+    /// applied by the replica that has proposed it.
+    Event(Event),
+    /// Request to be handled again once Raft confirms through ReadIndex that
+    /// the actor state is up to date.
+    Read,
+}
+
+/// Represents an actor that operates on prost messages rather than on raw
+/// commands and events. The requests are decoded from the command header and
+/// the responses are encoded into the command header, the correlation id of
+/// the request is carried over to the response. Requests rejected with status
+/// are answered with the response the status maps to.
+///
+/// Typed actor is passed to the runtime wrapped into TypedActorAdapter.
+pub trait TypedActor {
+    /// Request carried in the command header.
+    type Request: Message + Default;
+    /// Response carried in the command header.
+    type Response: Message + Default;
+    /// Event replicated through the log.
+    type Event: Message + Default;
+    /// Snapshot of the actor state.
+    type Snapshot: Message + Default;
+
+    /// Handles actor initialization. If error is returned the actor is considered
+    /// in unknown state and is destroyed.
+    fn on_init(&mut self, context: Box<dyn ActorContext>) -> Result<(), ActorError>;
+
+    /// Handles actor shutdown. After this method call completes the actor
+    /// is destroyed.
+    fn on_shutdown(&mut self) {}
+
+    /// Handles creation of the actor state snapshot. If error is returned the actor
+    /// is considered is unknown state and is destroyed.
+    fn on_save_snapshot(&mut self) -> Result<Self::Snapshot, ActorError>;
+
+    /// Handles restoration of the actor state from snapshot. If error is returned the actor
+    /// is considered is unknown state and is destroyed.
+    fn on_load_snapshot(&mut self, snapshot: Self::Snapshot) -> Result<(), ActorError>;
+
+    /// Handles the request either by responding right away or by proposing an
+    /// event for replication. If status is returned the request is rejected.
+    fn on_request(
+        &mut self,
+        request: Self::Request,
+    ) -> Result<RequestOutcome<Self::Response, Self::Event>, Status>;
+
+    /// Handles committed event by applying it to the actor state. The response
+    /// is sent out only by the replica that has proposed the event.
+    fn on_event(
+        &mut self,
+        context: ActorEventContext,
+        event: Self::Event,
+    ) -> Result<Self::Response, ActorError>;
+
+    /// Maps the status the request has been rejected with to the response.
+    fn on_status(&mut self, status: Status) -> Self::Response;
+}
+
+/// Adapts the typed actor to the actor the runtime operates on.
+pub struct TypedActorAdapter<T: TypedActor> {
+    actor: T,
+}
+
+impl<T: TypedActor> TypedActorAdapter<T> {
+    pub fn new(actor: T) -> TypedActorAdapter<T> {
+        TypedActorAdapter { actor }
+    }
+
+    /// Gets the adapted typed actor.
+    pub fn actor(&self) -> &T {
+        &self.actor
+    }
+
+    /// Gets the adapted typed actor for modification.
+    pub fn mut_actor(&mut self) -> &mut T {
+        &mut self.actor
+    }
+}
+
+impl<T: TypedActor> Actor for TypedActorAdapter<T> {
+    fn on_init(&mut self, context: Box<dyn ActorContext>) -> Result<(), ActorError> {
+        self.actor.on_init(context)
+    }
+
+    fn on_shutdown(&mut self) {
+        self.actor.on_shutdown()
+    }
+
+    fn on_save_snapshot(&mut self) -> Result<Bytes, ActorError> {
+        let snapshot = self.actor.on_save_snapshot()?;
+        Ok(snapshot.encode_to_vec().into())
+    }
+
+    fn on_load_snapshot(&mut self, snapshot: Bytes) -> Result<(), ActorError> {
+        let snapshot = T::Snapshot::decode(snapshot).map_err(|_| ActorError::SnapshotLoading)?;
+        self.actor.on_load_snapshot(snapshot)
+    }
+
+    fn on_process_command(
+        &mut self,
+        command: Option<ActorCommand>,
+    ) -> Result<CommandOutcome, ActorError> {
+        let Some(command) = command else {
+            return Ok(CommandOutcome::with_none());
+        };
+
+        let outcome = T::Request::decode(command.header.clone())
+            .map_err(|_| {
+                Status::new_with_message(StatusCode::InvalidArgument, "Request cannot be parsed")
+            })
+            .and_then(|request| self.actor.on_request(request));
+
+        let response = match outcome {
+            Ok(RequestOutcome::Response(response)) => response,
+            Ok(RequestOutcome::Event(event)) => {
+                return Ok(CommandOutcome::with_event(ActorEvent::with_proto(
+                    command.correlation_id,
+                    &event,
+                )))
+            }
+            Ok(RequestOutcome::Read) => return Ok(CommandOutcome::with_read(command)),
+            Err(status) => self.actor.on_status(status),
+        };
+
+        Ok(CommandOutcome::with_command(ActorCommand::with_header(
+            command.correlation_id,
+            &response,
+        )))
+    }
+
+    fn on_apply_event(
+        &mut self,
+        context: ActorEventContext,
+        event: ActorEvent,
+    ) -> Result<EventOutcome, ActorError> {
+        // Events are only ever proposed by the actor itself, hence the event
+        // that cannot be decoded indicates corrupted state.
+        let contents = T::Event::decode(event.contents).map_err(|_| ActorError::Internal)?;
+        let owned = context.owned;
+
+        let response = self.actor.on_event(context, contents)?;
+        if owned {
+            return Ok(EventOutcome::with_command(ActorCommand::with_header(
+                event.correlation_id,
+                &response,
+            )));
+        }
+
+        Ok(EventOutcome::with_none())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::mock::MockActorContext;
+    use crate::model::*;
+    use crate::typed::*;
+    use alloc::format;
+    use alloc::string::String;
+    use alloc::vec;
+
+    // Sums up the replicated values, requests above the limit are rejected and
+    // zero requests are served from the local state.
+    struct SumActor {
+        sum: u64,
+    }
+
+    impl TypedActor for SumActor {
+        type Request = u64;
+        type Response = String;
+        type Event = u64;
+        type Snapshot = u64;
+
+        fn on_init(&mut self, _context: Box<dyn ActorContext>) -> Result<(), ActorError> {
+            Ok(())
+        }
+
+        fn on_save_snapshot(&mut self) -> Result<u64, ActorError> {
+            Ok(self.sum)
+        }
+
+        fn on_load_snapshot(&mut self, snapshot: u64) -> Result<(), ActorError> {
+            self.sum = snapshot;
+            Ok(())
+        }
+
+        fn on_request(&mut self, request: u64) -> Result<RequestOutcome<String, u64>, Status> {
+            match request {
+                0 => Ok(RequestOutcome::Response(format!("sum {}", self.sum))),
+                1..=100 => Ok(RequestOutcome::Event(request)),
+                _ => Err(Status::new_with_message(
+                    StatusCode::OutOfRange,
+                    "Value is too large",
+                )),
+            }
+        }
+
+        fn on_event(
+            &mut self,
+            _context: ActorEventContext,
+            event: u64,
+        ) -> Result<String, ActorError> {
+            self.sum += event;
+            Ok(format!("sum {}", self.sum))
+        }
+
+        fn on_status(&mut self, status: Status) -> String {
+            format!("error {}", status.code as i32)
+        }
+    }
+
+    fn create_command<M: Message>(correlation_id: u64, header: &M) -> ActorCommand {
+        ActorCommand::with_header(correlation_id, header)
+    }
+
+    #[test]
+    fn test_typed_actor() {
+        let mut actor = TypedActorAdapter::new(SumActor { sum: 0 });
+        assert_eq!(actor.on_init(Box::new(MockActorContext::new())), Ok(()));
+
+        // Request is decoded and the proposed event is encoded.
+        assert_eq!(
+            actor.on_process_command(Some(create_command(1, &5u64))),
+            Ok(CommandOutcome::with_event(ActorEvent::with_proto(1, &5u64)))
+        );
+
+        let outcome = actor
+            .on_apply_event(
+                ActorEventContext {
+                    index: 1,
+                    owned: true,
+                },
+                ActorEvent::with_proto(1, &5u64),
+            )
+            .unwrap();
+        assert_eq!(
+            outcome.commands,
+            vec![create_command(1, &String::from("sum 5"))]
+        );
+
+        // Response is sent out only by the replica that has proposed the event.
+        let outcome = actor
+            .on_apply_event(
+                ActorEventContext {
+                    index: 2,
+                    owned: false,
+                },
+                ActorEvent::with_proto(2, &3u64),
+            )
+            .unwrap();
+        assert!(outcome.commands.is_empty());
+
+        assert_eq!(
+            actor.on_process_command(Some(create_command(3, &0u64))),
+            Ok(CommandOutcome::with_command(create_command(
+                3,
+                &String::from("sum 8")
+            )))
+        );
+
+        // Rejected request is answered with the response the status maps to.
+        assert_eq!(
+            actor.on_process_command(Some(create_command(4, &200u64))),
+            Ok(CommandOutcome::with_command(create_command(
+                4,
+                &format!("error {}", StatusCode::OutOfRange as i32)
+            )))
+        );
+        assert_eq!(
+            actor.on_process_command(Some(ActorCommand {
+                correlation_id: 5,
+                header: Bytes::from(vec![0xff]),
+                ..Default::default()
+            })),
+            Ok(CommandOutcome::with_command(create_command(
+                5,
+                &format!("error {}", StatusCode::InvalidArgument as i32)
+            )))
+        );
+
+        // Snapshot is encoded and decoded.
+        let snapshot = actor.on_save_snapshot().unwrap();
+        let mut restored = TypedActorAdapter::new(SumActor { sum: 0 });
+        assert_eq!(restored.on_load_snapshot(snapshot), Ok(()));
+        assert_eq!(restored.actor().sum, 8);
+        assert_eq!(
+            restored.on_load_snapshot(Bytes::from(vec![0xff])),
+            Err(ActorError::SnapshotLoading)
+        );
+    }
+}
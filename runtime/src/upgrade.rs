@@ -0,0 +1,234 @@
+// Copyright 2024 The Trusted Computations Platform Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::{ActorError, SnapshotWriter};
+use alloc::collections::BTreeMap;
+use prost::bytes::Bytes;
+use tcp_proto::runtime::endpoint::SchemaVersionReport;
+
+// Prefix of the snapshots that carry the schema version of the actor state.
+const SCHEMA_VERSION_MAGIC: &[u8; 4] = b"TCPV";
+const SCHEMA_VERSION_HEADER_SIZE: usize = 8;
+
+/// Writes out the header carrying the schema version the snapshot that
+/// follows it is saved in.
+pub fn write_schema_version_header(
+    writer: &mut dyn SnapshotWriter,
+    schema_version: u32,
+) -> Result<(), ActorError> {
+    writer.write(SCHEMA_VERSION_MAGIC)?;
+    writer.write(&schema_version.to_le_bytes())
+}
+
+/// Splits the snapshot into the schema version it has been saved in and the
+/// snapshot of the actor state. Snapshots without the header are considered
+/// saved in the schema version zero.
+pub fn split_schema_version(snapshot: Bytes) -> (u32, Bytes) {
+    if snapshot.len() < SCHEMA_VERSION_HEADER_SIZE || !snapshot.starts_with(SCHEMA_VERSION_MAGIC) {
+        return (0, snapshot);
+    }
+
+    let mut version = [0; 4];
+    version.copy_from_slice(&snapshot[SCHEMA_VERSION_MAGIC.len()..SCHEMA_VERSION_HEADER_SIZE]);
+    (
+        u32::from_le_bytes(version),
+        snapshot.slice(SCHEMA_VERSION_HEADER_SIZE..),
+    )
+}
+
+/// Tracks the rolling upgrade of the actor snapshot schema. Each replica
+/// reports the highest schema version its actor supports through the
+/// replicated log, once all replicas of the cluster support a newer version
+/// the leader activates it through the replicated log. Snapshots are saved in
+/// the active version, hence the replicas that are yet to be upgraded can load
+/// the snapshots saved by the upgraded ones.
+pub struct SchemaUpgrade {
+    // Highest schema version supported by this replica.
+    supported_version: u32,
+    // Schema version the snapshots are saved in.
+    active_version: u32,
+    // Highest schema version supported by each replica as reported through
+    // the replicated log.
+    reported_versions: BTreeMap<u64, u32>,
+    // Indicates that this replica has proposed its report.
+    report_proposed: bool,
+    // Schema version the leader has proposed to activate.
+    proposed_version: u32,
+}
+
+impl SchemaUpgrade {
+    pub fn new() -> SchemaUpgrade {
+        SchemaUpgrade {
+            supported_version: 0,
+            active_version: 0,
+            reported_versions: BTreeMap::new(),
+            report_proposed: false,
+            proposed_version: 0,
+        }
+    }
+
+    /// Sets the highest schema version supported by this replica.
+    pub fn configure(&mut self, supported_version: u32) {
+        self.supported_version = supported_version;
+    }
+
+    /// Gets the highest schema version supported by this replica.
+    pub fn supported_version(&self) -> u32 {
+        self.supported_version
+    }
+
+    /// Gets the schema version the snapshots must be saved in.
+    pub fn active_version(&self) -> u32 {
+        self.active_version
+    }
+
+    /// Resets the active version to the one of the loaded snapshot. Reports
+    /// made before the snapshot are dropped, hence the replicas report again.
+    pub fn load(&mut self, active_version: u32) {
+        self.active_version = active_version;
+        self.reported_versions.clear();
+        self.reset_proposed();
+    }
+
+    /// Records the replicated report of the replica.
+    pub fn record_report(&mut self, report: &SchemaVersionReport) {
+        self.reported_versions
+            .insert(report.replica_id, report.schema_version);
+    }
+
+    /// Activates the replicated schema version, returns false if this replica
+    /// does not support it.
+    pub fn activate(&mut self, schema_version: u32) -> bool {
+        if schema_version > self.supported_version {
+            return false;
+        }
+
+        self.active_version = self.active_version.max(schema_version);
+        self.proposed_version = 0;
+        true
+    }
+
+    /// Takes the report of this replica if it supports a newer version than
+    /// the active one and it has not been replicated or proposed yet.
+    pub fn take_report(&mut self, replica_id: u64) -> Option<SchemaVersionReport> {
+        if self.report_proposed
+            || self.supported_version <= self.active_version
+            || self.reported_versions.get(&replica_id) == Some(&self.supported_version)
+        {
+            return None;
+        }
+
+        self.report_proposed = true;
+        Some(SchemaVersionReport {
+            replica_id,
+            schema_version: self.supported_version,
+        })
+    }
+
+    /// Takes the newer schema version supported by all given replicas if it
+    /// has not been proposed to be activated yet.
+    pub fn take_activation(&mut self, replica_ids: &[u64]) -> Option<u32> {
+        let mut version = u32::MAX;
+        for replica_id in replica_ids {
+            version = version.min(*self.reported_versions.get(replica_id)?);
+        }
+
+        if replica_ids.is_empty()
+            || version <= self.active_version
+            || version == self.proposed_version
+        {
+            return None;
+        }
+
+        self.proposed_version = version;
+        Some(version)
+    }
+
+    /// Forgets the proposals so that they are made again, must be called when
+    /// the leadership changes or proposals are dropped.
+    pub fn reset_proposed(&mut self) {
+        self.report_proposed = false;
+        self.proposed_version = 0;
+    }
+}
+
+impl Default for SchemaUpgrade {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::snapshot::SnapshotBuffer;
+    use crate::upgrade::*;
+
+    fn create_report(replica_id: u64, schema_version: u32) -> SchemaVersionReport {
+        SchemaVersionReport {
+            replica_id,
+            schema_version,
+        }
+    }
+
+    #[test]
+    fn test_schema_version_header() {
+        let mut snapshot_buffer = SnapshotBuffer::new();
+        write_schema_version_header(&mut snapshot_buffer, 3).unwrap();
+        snapshot_buffer.write(&[1, 2, 3]).unwrap();
+
+        assert_eq!(
+            split_schema_version(snapshot_buffer.into_bytes()),
+            (3, Bytes::from_static(&[1, 2, 3]))
+        );
+        assert_eq!(
+            split_schema_version(Bytes::from_static(&[1, 2, 3])),
+            (0, Bytes::from_static(&[1, 2, 3]))
+        );
+    }
+
+    #[test]
+    fn test_schema_upgrade() {
+        let mut upgrade = SchemaUpgrade::new();
+        upgrade.configure(2);
+
+        // Replica reports once until the report is replicated.
+        assert_eq!(upgrade.take_report(1), Some(create_report(1, 2)));
+        assert_eq!(upgrade.take_report(1), None);
+        upgrade.reset_proposed();
+        assert_eq!(upgrade.take_report(1), Some(create_report(1, 2)));
+        upgrade.record_report(&create_report(1, 2));
+        assert_eq!(upgrade.take_report(1), None);
+
+        // Version is activated once all replicas support it.
+        upgrade.record_report(&create_report(2, 1));
+        assert_eq!(upgrade.take_activation(&[1, 2, 3]), None);
+        upgrade.record_report(&create_report(3, 2));
+        assert_eq!(upgrade.take_activation(&[1, 2, 3]), Some(1));
+        assert_eq!(upgrade.take_activation(&[1, 2, 3]), None);
+        assert!(upgrade.activate(1));
+        assert_eq!(upgrade.active_version(), 1);
+
+        upgrade.record_report(&create_report(2, 2));
+        assert_eq!(upgrade.take_activation(&[1, 2, 3]), Some(2));
+        assert!(upgrade.activate(2));
+        assert_eq!(upgrade.active_version(), 2);
+        assert!(!upgrade.activate(3));
+
+        // Loaded snapshot resets the reports.
+        upgrade.load(1);
+        assert_eq!(upgrade.active_version(), 1);
+        assert_eq!(upgrade.take_activation(&[1, 2, 3]), None);
+        assert_eq!(upgrade.take_report(1), Some(create_report(1, 2)));
+    }
+}
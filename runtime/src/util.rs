@@ -17,10 +17,11 @@ use alloc::vec::Vec;
 use core::fmt;
 use core::result::Result;
 use raft::eraftpb::{
-    ConfChange as RaftConfigChange, ConfChangeType as RaftConfigChangeType,
-    ConfState as RaftConfigState, Entry as RaftEntry, EntryType as RaftEntryType,
-    Message as RaftMessage, MessageType as RaftMessageType, Snapshot as RaftSnapshot,
-    SnapshotMetadata as RaftSnapshotMetadata,
+    ConfChange as RaftConfigChange, ConfChangeSingle as RaftConfigChangeSingle,
+    ConfChangeTransition as RaftConfigChangeTransition, ConfChangeType as RaftConfigChangeType,
+    ConfChangeV2 as RaftConfigChangeV2, ConfState as RaftConfigState, Entry as RaftEntry,
+    EntryType as RaftEntryType, Message as RaftMessage, MessageType as RaftMessageType,
+    Snapshot as RaftSnapshot, SnapshotMetadata as RaftSnapshotMetadata,
 };
 use tcp_proto::runtime::endpoint::{Entry, EntryId};
 
@@ -66,9 +67,9 @@ pub mod raft {
     }
 
     pub fn deserialize_config_change(
-        change_contents: &Vec<u8>,
+        change_contents: &[u8],
     ) -> Result<RaftConfigChange, UtilError> {
-        RaftConfigChange::decode(change_contents.as_ref()).map_err(|_e| UtilError::Decoding)
+        RaftConfigChange::decode(change_contents).map_err(|_e| UtilError::Decoding)
     }
 
     pub fn create_raft_config_change(
@@ -82,6 +83,31 @@ pub mod raft {
         }
     }
 
+    pub fn deserialize_config_change_v2(
+        change_contents: &[u8],
+    ) -> Result<RaftConfigChangeV2, UtilError> {
+        RaftConfigChangeV2::decode(change_contents).map_err(|_e| UtilError::Decoding)
+    }
+
+    /// Creates config change that applies all given changes atomically. Multiple
+    /// changes go through joint consensus, which Raft leaves automatically once
+    /// the joint configuration is committed.
+    pub fn create_raft_config_change_v2(
+        changes: Vec<(u64, RaftConfigChangeType)>,
+    ) -> RaftConfigChangeV2 {
+        RaftConfigChangeV2 {
+            transition: RaftConfigChangeTransition::Auto.into(),
+            changes: changes
+                .into_iter()
+                .map(|(node_id, change_type)| RaftConfigChangeSingle {
+                    change_type: change_type.into(),
+                    node_id,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
     pub fn get_metadata(snapshot: &RaftSnapshot) -> &RaftSnapshotMetadata {
         snapshot.metadata.as_ref().unwrap()
     }
@@ -163,6 +189,15 @@ pub mod raft {
         Entry {
             entry_id: Some(entry_id),
             entry_contents,
+            batched_entries: Vec::new(),
+            fired_timers: Vec::new(),
+            random_seed: Bytes::new(),
+            skipped_index: 0,
+            schema_version_report: None,
+            activated_schema_version: 0,
+            actor_config_update: None,
+            skipped_positions: Vec::new(),
+            proposer_applied_index: 0,
         }
     }
 